@@ -18,10 +18,17 @@ use arrow_arith::arity::binary;
 use arrow_array::{Int32Array, RecordBatch, StringArray};
 use arrow_schema::{DataType, Field, Schema};
 use arrow_udf::function;
+use arrow_udf::types::{serde_json, Decimal};
 use arrow_udf_js::Runtime as JsRuntime;
 use arrow_udf_python::Runtime as PythonRuntime;
 use arrow_udf_wasm::Runtime as WasmRuntime;
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
+
+/// The number of rows in each input chunk.
+const CHUNK_SIZE: usize = 1024;
+
+/// The path of the WASM binary built from `arrow-udf-example`.
+const WASM_PATH: &str = "../target/wasm32-wasip1/release/arrow_udf_example.wasm";
 
 fn bench_eval_gcd(c: &mut Criterion) {
     #[function("gcd(int, int) -> int")]
@@ -56,32 +63,32 @@ def gcd(a: int, b: int) -> int:
             Field::new("b", DataType::Int32, true),
         ])),
         vec![
-            Arc::new(Int32Array::from_iter(0..1024)),
-            Arc::new(Int32Array::from_iter((0..2048).step_by(2))),
+            Arc::new(Int32Array::from_iter(0..CHUNK_SIZE as i32)),
+            Arc::new(Int32Array::from_iter((0..2 * CHUNK_SIZE as i32).step_by(2))),
         ],
     )
     .unwrap();
 
-    c.bench_function("gcd/native", |bencher| {
-        let a = Int32Array::from_iter(0..1024);
-        let b = Int32Array::from_iter((0..2048).step_by(2));
+    let mut group = group(c, "gcd", CHUNK_SIZE);
+
+    group.bench_function("native", |bencher| {
+        let a = Int32Array::from_iter(0..CHUNK_SIZE as i32);
+        let b = Int32Array::from_iter((0..2 * CHUNK_SIZE as i32).step_by(2));
         bencher.iter(|| {
             let _: Int32Array = binary(&a, &b, gcd).unwrap();
         })
     });
 
-    c.bench_function("gcd/rust", |bencher| {
+    group.bench_function("rust", |bencher| {
         bencher.iter(|| gcd_int32_int32_int32_eval(&input).unwrap())
     });
 
-    c.bench_function("gcd/wasm", |bencher| {
-        let filepath = "../target/wasm32-wasip1/release/arrow_udf_example.wasm";
-        let binary = std::fs::read(filepath).unwrap();
-        let rt = WasmRuntime::new(&binary).unwrap();
+    group.bench_function("wasm", |bencher| {
+        let rt = wasm_runtime();
         bencher.iter(|| rt.call("gcd(int32,int32)->int32", &input).unwrap())
     });
 
-    c.bench_function("gcd/js", |bencher| {
+    group.bench_function("js", |bencher| {
         let mut rt = JsRuntime::new().unwrap();
         rt.add_function(
             "gcd",
@@ -93,7 +100,7 @@ def gcd(a: int, b: int) -> int:
         bencher.iter(|| rt.call("gcd", &input).unwrap())
     });
 
-    c.bench_function("gcd/python", |bencher| {
+    group.bench_function("python", |bencher| {
         let mut rt = PythonRuntime::new().unwrap();
         rt.add_function(
             "gcd",
@@ -106,6 +113,134 @@ def gcd(a: int, b: int) -> int:
     });
 }
 
+fn bench_eval_length(c: &mut Criterion) {
+    #[function("length(varchar) -> int")]
+    fn length(s: &str) -> i32 {
+        s.len() as i32
+    }
+
+    let js_code = r#"
+    export function length(s) {
+        return s.length;
+    }
+    "#;
+
+    let python_code = r#"
+def length(s: str) -> int:
+    return len(s)
+"#;
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)])),
+        vec![Arc::new(StringArray::from_iter_values(
+            (0..CHUNK_SIZE).map(|i| format!("hello, world {i}")),
+        ))],
+    )
+    .unwrap();
+
+    let mut group = group(c, "length", CHUNK_SIZE);
+
+    group.bench_function("rust", |bencher| {
+        bencher.iter(|| length_string_int32_eval(&input).unwrap())
+    });
+
+    group.bench_function("wasm", |bencher| {
+        let rt = wasm_runtime();
+        bencher.iter(|| rt.call("length(string)->int32", &input).unwrap())
+    });
+
+    group.bench_function("js", |bencher| {
+        let mut rt = JsRuntime::new().unwrap();
+        rt.add_function(
+            "length",
+            DataType::Int32,
+            arrow_udf_js::CallMode::ReturnNullOnNullInput,
+            js_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("length", &input).unwrap())
+    });
+
+    group.bench_function("python", |bencher| {
+        let mut rt = PythonRuntime::new().unwrap();
+        rt.add_function(
+            "length",
+            DataType::Int32,
+            arrow_udf_python::CallMode::ReturnNullOnNullInput,
+            python_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("length", &input).unwrap())
+    });
+}
+
+fn bench_eval_json(c: &mut Criterion) {
+    #[function("jsonb_access(json, int) -> json")]
+    fn jsonb_access(json: serde_json::Value, index: i32) -> Option<serde_json::Value> {
+        json.get(index as usize).cloned()
+    }
+
+    let js_code = r#"
+    export function jsonb_access(array, i) {
+        return array[i];
+    }
+    "#;
+
+    let python_code = r#"
+def jsonb_access(array, i):
+    return array[i]
+"#;
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            json_field("array"),
+            Field::new("i", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(StringArray::from_iter_values(
+                (0..CHUNK_SIZE).map(|i| format!(r#"[{i}, "{i}", {{"key": {i}}}]"#)),
+            )),
+            Arc::new(Int32Array::from_iter((0..CHUNK_SIZE as i32).map(|i| i % 3))),
+        ],
+    )
+    .unwrap();
+
+    let mut group = group(c, "json", CHUNK_SIZE);
+
+    group.bench_function("rust", |bencher| {
+        bencher.iter(|| jsonb_access_json_int32_json_eval(&input).unwrap())
+    });
+
+    group.bench_function("wasm", |bencher| {
+        let rt = wasm_runtime();
+        bencher.iter(|| rt.call("jsonb_access(json,int32)->json", &input).unwrap())
+    });
+
+    group.bench_function("js", |bencher| {
+        let mut rt = JsRuntime::new().unwrap();
+        rt.add_function(
+            "jsonb_access",
+            json_field("json"),
+            arrow_udf_js::CallMode::ReturnNullOnNullInput,
+            js_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("jsonb_access", &input).unwrap())
+    });
+
+    group.bench_function("python", |bencher| {
+        let mut rt = PythonRuntime::new().unwrap();
+        rt.add_function(
+            "jsonb_access",
+            json_field("json"),
+            arrow_udf_python::CallMode::ReturnNullOnNullInput,
+            python_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("jsonb_access", &input).unwrap())
+    });
+}
+
 fn bench_eval_range(c: &mut Criterion) {
     let js_code = r#"
     export function* range(n) {
@@ -121,12 +256,14 @@ def range1(n: int):
         yield i
 "#;
 
-    let a = Int32Array::from(vec![16 * 1024]);
-    c.bench_function("range/native", |bencher| {
+    let a = Int32Array::from(vec![16 * CHUNK_SIZE as i32]);
+    let mut group = group(c, "range", 16 * CHUNK_SIZE);
+
+    group.bench_function("native", |bencher| {
         bencher.iter(|| {
             for i in 0..16 {
-                let _ = Int32Array::from_iter((0..1024).map(|_| 0));
-                let _ = Int32Array::from_iter(i * 1024..(i + 1) * 1024);
+                let _ = Int32Array::from_iter((0..CHUNK_SIZE).map(|_| 0));
+                let _ = Int32Array::from_iter(i * CHUNK_SIZE as i32..(i + 1) * CHUNK_SIZE as i32);
             }
         })
     });
@@ -137,10 +274,8 @@ def range1(n: int):
     )
     .unwrap();
 
-    c.bench_function("range/wasm", |bencher| {
-        let filepath = "../target/wasm32-wasip1/release/arrow_udf_example.wasm";
-        let binary = std::fs::read(filepath).unwrap();
-        let rt = WasmRuntime::new(&binary).unwrap();
+    group.bench_function("wasm", |bencher| {
+        let rt = wasm_runtime();
         bencher.iter(|| {
             rt.call_table_function("range(int32)->>int32", &input)
                 .unwrap()
@@ -148,7 +283,7 @@ def range1(n: int):
         })
     });
 
-    c.bench_function("range/js", |bencher| {
+    group.bench_function("js", |bencher| {
         let mut rt = JsRuntime::new().unwrap();
         rt.add_function(
             "range",
@@ -158,13 +293,13 @@ def range1(n: int):
         )
         .unwrap();
         bencher.iter(|| {
-            rt.call_table_function("range", &input, 1024)
+            rt.call_table_function("range", &input, CHUNK_SIZE)
                 .unwrap()
                 .for_each(|_| {})
         })
     });
 
-    c.bench_function("range/python", |bencher| {
+    group.bench_function("python", |bencher| {
         let mut rt = PythonRuntime::new().unwrap();
         rt.add_function(
             "range1",
@@ -174,7 +309,7 @@ def range1(n: int):
         )
         .unwrap();
         bencher.iter(|| {
-            rt.call_table_function("range1", &input, 1024)
+            rt.call_table_function("range1", &input, CHUNK_SIZE)
                 .unwrap()
                 .for_each(|_| {})
         })
@@ -200,15 +335,17 @@ def decimal_(a):
 
     let input = RecordBatch::try_new(
         Arc::new(Schema::new(vec![decimal_field("a")])),
-        vec![Arc::new(StringArray::from(vec!["0.0"; 1024]))],
+        vec![Arc::new(StringArray::from(vec!["0.0"; CHUNK_SIZE]))],
     )
     .unwrap();
 
-    c.bench_function("decimal/rust", |bencher| {
+    let mut group = group(c, "decimal", CHUNK_SIZE);
+
+    group.bench_function("rust", |bencher| {
         bencher.iter(|| decimal_decimal_decimal_eval(&input).unwrap())
     });
 
-    c.bench_function("decimal/js", |bencher| {
+    group.bench_function("js", |bencher| {
         let mut rt = JsRuntime::new().unwrap();
         rt.add_function(
             "decimal",
@@ -220,7 +357,7 @@ def decimal_(a):
         bencher.iter(|| rt.call("decimal", &input).unwrap())
     });
 
-    c.bench_function("decimal/python", |bencher| {
+    group.bench_function("python", |bencher| {
         let mut rt = PythonRuntime::new().unwrap();
         rt.add_function(
             "decimal_",
@@ -233,14 +370,85 @@ def decimal_(a):
     });
 }
 
+fn bench_eval_decimal_add(c: &mut Criterion) {
+    #[function("decimal_add(decimal, decimal) -> decimal")]
+    fn decimal_add(a: Decimal, b: Decimal) -> Decimal {
+        a + b
+    }
+
+    let js_code = r#"
+    export function decimal_add(a, b) {
+        return a + b;
+    }
+    "#;
+
+    let python_code = r#"
+def decimal_add(a, b):
+    return a + b
+"#;
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![decimal_field("a"), decimal_field("b")])),
+        vec![
+            Arc::new(StringArray::from_iter_values(
+                (0..CHUNK_SIZE).map(|i| format!("{i}.0001")),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                (0..CHUNK_SIZE).map(|i| format!("-{i}.25")),
+            )),
+        ],
+    )
+    .unwrap();
+
+    let mut group = group(c, "decimal_add", CHUNK_SIZE);
+
+    group.bench_function("rust", |bencher| {
+        bencher.iter(|| decimal_add_decimal_decimal_decimal_eval(&input).unwrap())
+    });
+
+    group.bench_function("wasm", |bencher| {
+        let rt = wasm_runtime();
+        bencher.iter(|| {
+            rt.call("decimal_add(decimal,decimal)->decimal", &input)
+                .unwrap()
+        })
+    });
+
+    group.bench_function("js", |bencher| {
+        let mut rt = JsRuntime::new().unwrap();
+        rt.add_function(
+            "decimal_add",
+            decimal_field("decimal_add"),
+            arrow_udf_js::CallMode::ReturnNullOnNullInput,
+            js_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("decimal_add", &input).unwrap())
+    });
+
+    group.bench_function("python", |bencher| {
+        let mut rt = PythonRuntime::new().unwrap();
+        rt.add_function(
+            "decimal_add",
+            decimal_field("decimal_add"),
+            arrow_udf_python::CallMode::ReturnNullOnNullInput,
+            python_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("decimal_add", &input).unwrap())
+    });
+}
+
 fn bench_eval_sum(c: &mut Criterion) {
     let input = RecordBatch::try_new(
         Arc::new(Schema::new(vec![Field::new("v", DataType::Int32, true)])),
-        vec![Arc::new(Int32Array::from_iter(0..1024))],
+        vec![Arc::new(Int32Array::from_iter(0..CHUNK_SIZE as i32))],
     )
     .unwrap();
 
-    c.bench_function("sum/js", |bencher| {
+    let mut group = group(c, "sum", CHUNK_SIZE);
+
+    group.bench_function("js", |bencher| {
         let mut rt = JsRuntime::new().unwrap();
         rt.add_aggregate(
             "sum",
@@ -264,7 +472,7 @@ fn bench_eval_sum(c: &mut Criterion) {
         bencher.iter(|| rt.accumulate("sum", &state, &input).unwrap())
     });
 
-    c.bench_function("sum/python", |bencher| {
+    group.bench_function("python", |bencher| {
         let mut rt = PythonRuntime::new().unwrap();
         rt.add_aggregate(
             "sum",
@@ -288,15 +496,75 @@ def retract(state, value):
     });
 }
 
+fn bench_eval_alloc(c: &mut Criterion) {
+    let python_code = r#"
+def alloc(n: int) -> int:
+    return len([str(i) for i in range(n)])
+"#;
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![16; CHUNK_SIZE]))],
+    )
+    .unwrap();
+
+    let mut group = group(c, "alloc", CHUNK_SIZE);
+
+    // compare with `alloc/python_accounted` in the `memory` benchmark
+    group.bench_function("python", |bencher| {
+        let mut rt = PythonRuntime::new().unwrap();
+        rt.add_function(
+            "alloc",
+            DataType::Int32,
+            arrow_udf_python::CallMode::ReturnNullOnNullInput,
+            python_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("alloc", &input).unwrap())
+    });
+}
+
 criterion_group!(
     benches,
     bench_eval_gcd,
+    bench_eval_length,
+    bench_eval_json,
     bench_eval_range,
     bench_eval_decimal,
-    bench_eval_sum
+    bench_eval_decimal_add,
+    bench_eval_sum,
+    bench_eval_alloc
 );
 criterion_main!(benches);
 
+/// Creates a benchmark group that reports throughput in rows per second.
+fn group<'a>(
+    c: &'a mut Criterion,
+    name: &str,
+    rows: usize,
+) -> BenchmarkGroup<'a, criterion::measurement::WallTime> {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(rows as u64));
+    group
+}
+
+/// Loads the WASM runtime from the example binary.
+fn wasm_runtime() -> WasmRuntime {
+    let binary = std::fs::read(WASM_PATH).unwrap_or_else(|e| {
+        panic!(
+            "failed to read {WASM_PATH}: {e}\n\
+             hint: run `cargo build --release -p arrow-udf-example --target wasm32-wasip1` first"
+        )
+    });
+    WasmRuntime::new(&binary).unwrap()
+}
+
+/// Returns a field with JSON type.
+fn json_field(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, true)
+        .with_metadata([("ARROW:extension:name".into(), "arrowudf.json".into())].into())
+}
+
 /// Returns a field with decimal type.
 fn decimal_field(name: &str) -> Field {
     Field::new(name, DataType::Utf8, true)
@@ -0,0 +1,62 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The overhead of memory accounting in Python, compared with `alloc/python` in `bench`.
+//!
+//! The allocators of Python can only be wrapped before it is initialized,
+//! so this runs in its own process.
+
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_python::Runtime as PythonRuntime;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// The number of rows in each input chunk.
+const CHUNK_SIZE: usize = 1024;
+
+fn bench_eval_alloc(c: &mut Criterion) {
+    let python_code = r#"
+def alloc(n: int) -> int:
+    return len([str(i) for i in range(n)])
+"#;
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![16; CHUNK_SIZE]))],
+    )
+    .unwrap();
+
+    let mut group = c.benchmark_group("alloc");
+    group.throughput(Throughput::Elements(CHUNK_SIZE as u64));
+
+    group.bench_function("python_accounted", |bencher| {
+        let mut rt = PythonRuntime::builder()
+            .memory_accounting(true)
+            .build()
+            .unwrap();
+        rt.add_function(
+            "alloc",
+            DataType::Int32,
+            arrow_udf_python::CallMode::ReturnNullOnNullInput,
+            python_code,
+        )
+        .unwrap();
+        bencher.iter(|| rt.call("alloc", &input).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_eval_alloc);
+criterion_main!(benches);
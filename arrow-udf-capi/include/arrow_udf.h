@@ -0,0 +1,125 @@
+#ifndef ARROW_UDF_H
+#define ARROW_UDF_H
+
+/* This file is generated by cbindgen. Do not edit it by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Arrow C data interface, see https://arrow.apache.org/docs/format/CDataInterface.html
+#ifndef ARROW_C_DATA_INTERFACE
+#define ARROW_C_DATA_INTERFACE
+
+#define ARROW_FLAG_DICTIONARY_ORDERED 1
+#define ARROW_FLAG_NULLABLE 2
+#define ARROW_FLAG_MAP_KEYS_SORTED 4
+
+struct ArrowSchema {
+  const char* format;
+  const char* name;
+  const char* metadata;
+  int64_t flags;
+  int64_t n_children;
+  struct ArrowSchema** children;
+  struct ArrowSchema* dictionary;
+  void (*release)(struct ArrowSchema*);
+  void* private_data;
+};
+
+struct ArrowArray {
+  int64_t length;
+  int64_t null_count;
+  int64_t offset;
+  int64_t n_buffers;
+  int64_t n_children;
+  const void** buffers;
+  struct ArrowArray** children;
+  struct ArrowArray* dictionary;
+  void (*release)(struct ArrowArray*);
+  void* private_data;
+};
+
+#endif  // ARROW_C_DATA_INTERFACE
+
+// Arrow C stream interface, see https://arrow.apache.org/docs/format/CStreamInterface.html
+#ifndef ARROW_C_STREAM_INTERFACE
+#define ARROW_C_STREAM_INTERFACE
+
+struct ArrowArrayStream {
+  int (*get_schema)(struct ArrowArrayStream*, struct ArrowSchema* out);
+  int (*get_next)(struct ArrowArrayStream*, struct ArrowArray* out);
+  const char* (*get_last_error)(struct ArrowArrayStream*);
+  void (*release)(struct ArrowArrayStream*);
+  void* private_data;
+};
+
+#endif  // ARROW_C_STREAM_INTERFACE
+
+
+// The function is called normally when some of its arguments are null.
+#define ARROW_UDF_CALLED_ON_NULL_INPUT 0
+
+// The function returns null whenever any of its arguments are null.
+#define ARROW_UDF_RETURN_NULL_ON_NULL_INPUT 1
+
+// A UDF runtime for one language.
+typedef struct ArrowUdfRuntime ArrowUdfRuntime;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the message of the last error on this thread, or null if there is no error.
+//
+// The string is valid until the next call to a function of this library on the same thread.
+const char *arrow_udf_last_error(void);
+
+// Creates a runtime for the language: `python`, `javascript` or `wasm`.
+//
+// `binary` is only used for WebAssembly and can be null for other languages.
+// Returns null on error.
+struct ArrowUdfRuntime *arrow_udf_runtime_new(const char *language,
+                                              const uint8_t *binary,
+                                              size_t binary_len);
+
+// Frees a runtime. Does nothing if `runtime` is null.
+void arrow_udf_runtime_free(struct ArrowUdfRuntime *runtime);
+
+// Adds a function to the runtime.
+//
+// `return_field` is a schema of the return field, which is not released by this function.
+// `mode` is one of `ARROW_UDF_CALLED_ON_NULL_INPUT` and `ARROW_UDF_RETURN_NULL_ON_NULL_INPUT`.
+// Returns 0 on success, -1 on error.
+int arrow_udf_runtime_add_function(struct ArrowUdfRuntime *runtime,
+                                   const char *name,
+                                   const struct ArrowSchema *return_field,
+                                   int mode,
+                                   const char *code);
+
+// Calls a scalar function on each batch of the input stream.
+//
+// The input stream is consumed and released.
+// On success, `output` is set to a stream with one output batch for each input batch.
+// Returns 0 on success, -1 on error.
+int arrow_udf_runtime_call(const struct ArrowUdfRuntime *runtime,
+                           const char *name,
+                           struct ArrowArrayStream *input,
+                           struct ArrowArrayStream *output);
+
+// Calls a table function on all rows of the input stream.
+//
+// The first column of output batches is the index of the input row in the whole stream.
+// The input stream is consumed and released.
+// On success, `output` is set to a stream of output batches with up to `chunk_size` rows.
+// Returns 0 on success, -1 on error.
+int arrow_udf_runtime_call_table_function(const struct ArrowUdfRuntime *runtime,
+                                          const char *name,
+                                          struct ArrowArrayStream *input,
+                                          size_t chunk_size,
+                                          struct ArrowArrayStream *output);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ARROW_UDF_H */
@@ -0,0 +1,285 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+// All functions taking raw pointers are unsafe by the nature of a C API,
+// and their safety requirements are documented in the header.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use arrow_array::ffi::FFI_ArrowSchema;
+use arrow_array::ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream};
+use arrow_array::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::{unify_outputs, CallMode, UdfRuntime};
+
+/// The function is called normally when some of its arguments are null.
+pub const ARROW_UDF_CALLED_ON_NULL_INPUT: c_int = 0;
+/// The function returns null whenever any of its arguments are null.
+pub const ARROW_UDF_RETURN_NULL_ON_NULL_INPUT: c_int = 1;
+
+/// A UDF runtime for one language.
+pub struct ArrowUdfRuntime {
+    runtime: Box<dyn UdfRuntime>,
+    /// Return fields of added functions, used when a table function returns no rows.
+    return_fields: HashMap<String, Field>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Returns the message of the last error on this thread, or null if there is no error.
+///
+/// The string is valid until the next call to a function of this library on the same thread.
+#[no_mangle]
+pub extern "C" fn arrow_udf_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// Creates a runtime for the language: `python`, `javascript` or `wasm`.
+///
+/// `binary` is only used for WebAssembly and can be null for other languages.
+/// Returns null on error.
+#[no_mangle]
+pub unsafe extern "C" fn arrow_udf_runtime_new(
+    language: *const c_char,
+    binary: *const u8,
+    binary_len: usize,
+) -> *mut ArrowUdfRuntime {
+    let result = guard(|| {
+        let language = str_arg(language, "language")?;
+        let binary = match binary.is_null() {
+            true => &[][..],
+            false => std::slice::from_raw_parts(binary, binary_len),
+        };
+        Ok(ArrowUdfRuntime {
+            runtime: new_runtime(language, binary)?,
+            return_fields: HashMap::new(),
+        })
+    });
+    match result {
+        Some(runtime) => Box::into_raw(Box::new(runtime)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Frees a runtime. Does nothing if `runtime` is null.
+#[no_mangle]
+pub unsafe extern "C" fn arrow_udf_runtime_free(runtime: *mut ArrowUdfRuntime) {
+    if !runtime.is_null() {
+        drop(Box::from_raw(runtime));
+    }
+}
+
+/// Adds a function to the runtime.
+///
+/// `return_field` is a schema of the return field, which is not released by this function.
+/// `mode` is one of `ARROW_UDF_CALLED_ON_NULL_INPUT` and `ARROW_UDF_RETURN_NULL_ON_NULL_INPUT`.
+/// Returns 0 on success, -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn arrow_udf_runtime_add_function(
+    runtime: *mut ArrowUdfRuntime,
+    name: *const c_char,
+    return_field: *const FFI_ArrowSchema,
+    mode: c_int,
+    code: *const c_char,
+) -> c_int {
+    status(guard(|| {
+        let runtime = runtime.as_mut().context("runtime is null")?;
+        let name = str_arg(name, "name")?;
+        let return_field = return_field.as_ref().context("return field is null")?;
+        let return_field = Field::try_from(return_field)?;
+        let mode = match mode {
+            ARROW_UDF_CALLED_ON_NULL_INPUT => CallMode::CalledOnNullInput,
+            ARROW_UDF_RETURN_NULL_ON_NULL_INPUT => CallMode::ReturnNullOnNullInput,
+            _ => bail!("invalid call mode: {mode}"),
+        };
+        let code = str_arg(code, "code")?;
+        (runtime.runtime).add_function(name, return_field.clone(), mode, code)?;
+        runtime.return_fields.insert(name.to_string(), return_field);
+        Ok(())
+    }))
+}
+
+/// Calls a scalar function on each batch of the input stream.
+///
+/// The input stream is consumed and released.
+/// On success, `output` is set to a stream with one output batch for each input batch.
+/// Returns 0 on success, -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn arrow_udf_runtime_call(
+    runtime: *const ArrowUdfRuntime,
+    name: *const c_char,
+    input: *mut FFI_ArrowArrayStream,
+    output: *mut FFI_ArrowArrayStream,
+) -> c_int {
+    status(guard(|| {
+        // read the input first to release it on all errors
+        let input = read_stream(input)?;
+        let runtime = runtime.as_ref().context("runtime is null")?;
+        let name = str_arg(name, "name")?;
+        ensure_not_null(output, "output")?;
+
+        let mut outputs = vec![];
+        for batch in &input.batches {
+            outputs.push(runtime.runtime.call(name, batch)?);
+        }
+        // some runtimes add an error column only to batches where some rows fail
+        let outputs = unify_outputs(outputs)?;
+        let schema = match outputs.first() {
+            Some(batch) => batch.schema(),
+            // call on an empty batch to get the output schema
+            None => (runtime.runtime)
+                .call(name, &RecordBatch::new_empty(input.schema))?
+                .schema(),
+        };
+        write_stream(output, schema, outputs);
+        Ok(())
+    }))
+}
+
+/// Calls a table function on all rows of the input stream.
+///
+/// The first column of output batches is the index of the input row in the whole stream.
+/// The input stream is consumed and released.
+/// On success, `output` is set to a stream of output batches with up to `chunk_size` rows.
+/// Returns 0 on success, -1 on error.
+#[no_mangle]
+pub unsafe extern "C" fn arrow_udf_runtime_call_table_function(
+    runtime: *const ArrowUdfRuntime,
+    name: *const c_char,
+    input: *mut FFI_ArrowArrayStream,
+    chunk_size: usize,
+    output: *mut FFI_ArrowArrayStream,
+) -> c_int {
+    status(guard(|| {
+        // read the input first to release it on all errors
+        let input = read_stream(input)?;
+        let runtime = runtime.as_ref().context("runtime is null")?;
+        let name = str_arg(name, "name")?;
+        ensure_not_null(output, "output")?;
+        // row indexes are relative to the input batch, so call on the whole stream at once
+        let batch = arrow_select::concat::concat_batches(&input.schema, &input.batches)?;
+
+        let outputs = (runtime.runtime)
+            .call_table_function(name, &batch, chunk_size)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = unify_outputs(outputs)?;
+        let schema = match outputs.first() {
+            Some(batch) => batch.schema(),
+            None => {
+                let return_field = (runtime.return_fields.get(name))
+                    .with_context(|| format!("unknown output schema of function: {name}"))?;
+                Arc::new(Schema::new(vec![
+                    Field::new("row", DataType::Int32, true),
+                    return_field.clone(),
+                ]))
+            }
+        };
+        write_stream(output, schema, outputs);
+        Ok(())
+    }))
+}
+
+/// Create a new runtime for the language.
+///
+/// `binary` is only used for WebAssembly.
+#[allow(unused_variables)]
+fn new_runtime(language: &str, binary: &[u8]) -> Result<Box<dyn UdfRuntime>> {
+    match language {
+        #[cfg(feature = "python")]
+        "python" => Ok(Box::new(arrow_udf_python::Runtime::new()?)),
+        #[cfg(feature = "javascript")]
+        "javascript" => Ok(Box::new(arrow_udf_js::Runtime::new()?)),
+        #[cfg(feature = "wasm")]
+        "wasm" => Ok(Box::new(arrow_udf_wasm::Runtime::new(binary)?)),
+        _ => bail!("unknown or disabled language: {language}"),
+    }
+}
+
+/// All batches of an input stream.
+struct Input {
+    schema: Arc<Schema>,
+    batches: Vec<RecordBatch>,
+}
+
+/// Read all batches of a stream, taking its ownership and releasing it.
+unsafe fn read_stream(stream: *mut FFI_ArrowArrayStream) -> Result<Input> {
+    ensure_not_null(stream, "input")?;
+    let reader = ArrowArrayStreamReader::from_raw(stream)?;
+    let schema = reader.schema();
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    Ok(Input { schema, batches })
+}
+
+unsafe fn write_stream(
+    stream: *mut FFI_ArrowArrayStream,
+    schema: Arc<Schema>,
+    batches: Vec<RecordBatch>,
+) {
+    let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+    std::ptr::write(stream, FFI_ArrowArrayStream::new(Box::new(reader)));
+}
+
+fn ensure_not_null<T>(ptr: *const T, name: &str) -> Result<()> {
+    if ptr.is_null() {
+        bail!("{name} is null");
+    }
+    Ok(())
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str> {
+    ensure_not_null(ptr, name)?;
+    CStr::from_ptr(ptr)
+        .to_str()
+        .with_context(|| format!("{name} is not valid UTF-8"))
+}
+
+/// Run the function, catching panics and recording errors as the last error.
+fn guard<T>(f: impl FnOnce() -> Result<T>) -> Option<T> {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|e| {
+        let message = (e.downcast_ref::<&str>().copied())
+            .or_else(|| e.downcast_ref::<String>().map(|s| s.as_str()))
+            .unwrap_or("unknown panic");
+        Err(anyhow!("panicked: {message}"))
+    });
+    LAST_ERROR.with(|last| match result {
+        Ok(value) => {
+            *last.borrow_mut() = None;
+            Some(value)
+        }
+        Err(e) => {
+            let message = CString::new(format!("{e:#}").replace('\0', " ")).unwrap();
+            *last.borrow_mut() = Some(message);
+            None
+        }
+    })
+}
+
+fn status(result: Option<()>) -> c_int {
+    match result {
+        Some(()) => 0,
+        None => -1,
+    }
+}
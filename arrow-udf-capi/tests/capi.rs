@@ -0,0 +1,291 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use arrow_array::ffi::FFI_ArrowSchema;
+use arrow_array::ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream};
+use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use arrow_udf_capi::*;
+use expect_test::{expect, Expect};
+
+#[test]
+fn test_call() {
+    unsafe {
+        let runtime = new_runtime("javascript");
+        add_function(
+            runtime,
+            "gcd",
+            ARROW_UDF_RETURN_NULL_ON_NULL_INPUT,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        );
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Int32, true),
+            Field::new("y", DataType::Int32, true),
+        ]));
+        let batch = |x: Vec<Option<i32>>, y: Vec<Option<i32>>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(x)), Arc::new(Int32Array::from(y))],
+            )
+            .unwrap()
+        };
+        let mut input = to_stream(
+            schema.clone(),
+            vec![
+                batch(vec![Some(25), None], vec![Some(15), Some(1)]),
+                batch(vec![Some(6)], vec![Some(4)]),
+            ],
+        );
+        let mut output = FFI_ArrowArrayStream::empty();
+        let name = CString::new("gcd").unwrap();
+        let ret = arrow_udf_runtime_call(runtime, name.as_ptr(), &mut input, &mut output);
+        assert_eq!(ret, 0);
+        let outputs = from_stream(output);
+        assert_eq!(outputs.len(), 2);
+        check(
+            &outputs,
+            expect![[r#"
+            +-----+
+            | gcd |
+            +-----+
+            | 5   |
+            |     |
+            | 2   |
+            +-----+"#]],
+        );
+
+        // the output schema is known even if the input is empty
+        let mut input = to_stream(schema, vec![]);
+        let mut output = FFI_ArrowArrayStream::empty();
+        let ret = arrow_udf_runtime_call(runtime, name.as_ptr(), &mut input, &mut output);
+        assert_eq!(ret, 0);
+        let reader = ArrowArrayStreamReader::try_new(output).unwrap();
+        assert_eq!(
+            arrow_array::RecordBatchReader::schema(&reader)
+                .field(0)
+                .name(),
+            "gcd"
+        );
+
+        arrow_udf_runtime_free(runtime);
+    }
+}
+
+#[test]
+fn test_call_table_function() {
+    unsafe {
+        let runtime = new_runtime("javascript");
+        add_function(
+            runtime,
+            "range",
+            ARROW_UDF_RETURN_NULL_ON_NULL_INPUT,
+            r#"
+            export function* range(n) {
+                for (let i = 0; i < n; i++) {
+                    yield i;
+                }
+            }
+            "#,
+        );
+
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)]));
+        let batch = |x: Vec<Option<i32>>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(x))]).unwrap()
+        };
+        let mut input = to_stream(
+            schema.clone(),
+            vec![batch(vec![Some(1), None]), batch(vec![Some(2)])],
+        );
+        let mut output = FFI_ArrowArrayStream::empty();
+        let name = CString::new("range").unwrap();
+        let ret = arrow_udf_runtime_call_table_function(
+            runtime,
+            name.as_ptr(),
+            &mut input,
+            2,
+            &mut output,
+        );
+        assert_eq!(ret, 0);
+        // row indexes refer to the whole input stream
+        check(
+            &from_stream(output),
+            expect![[r#"
+            +-----+-------+
+            | row | range |
+            +-----+-------+
+            | 0   | 0     |
+            | 2   | 0     |
+            | 2   | 1     |
+            +-----+-------+"#]],
+        );
+
+        arrow_udf_runtime_free(runtime);
+    }
+}
+
+#[test]
+fn test_call_row_errors() {
+    unsafe {
+        let runtime = new_runtime("python");
+        add_function(
+            runtime,
+            "div",
+            ARROW_UDF_RETURN_NULL_ON_NULL_INPUT,
+            "def div(x):\n    return 6 // x",
+        );
+
+        // only the second output batch has an error column
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)]));
+        let batch = |x: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(x))]).unwrap()
+        };
+        let mut input = to_stream(schema.clone(), vec![batch(vec![1]), batch(vec![2, 0])]);
+        let mut output = FFI_ArrowArrayStream::empty();
+        let name = CString::new("div").unwrap();
+        let ret = arrow_udf_runtime_call(runtime, name.as_ptr(), &mut input, &mut output);
+        assert_eq!(ret, 0, "{}", last_error());
+        check(
+            &from_stream(output),
+            expect![[r#"
+            +-----+-------------------------------------------------------+
+            | div | error                                                 |
+            +-----+-------------------------------------------------------+
+            | 6   |                                                       |
+            | 3   |                                                       |
+            |     | ZeroDivisionError: integer division or modulo by zero |
+            +-----+-------------------------------------------------------+"#]],
+        );
+
+        arrow_udf_runtime_free(runtime);
+    }
+}
+
+#[test]
+fn test_errors() {
+    unsafe {
+        let language = CString::new("cobol").unwrap();
+        let runtime = arrow_udf_runtime_new(language.as_ptr(), std::ptr::null(), 0);
+        assert!(runtime.is_null());
+        assert_eq!(last_error(), "unknown or disabled language: cobol");
+
+        let runtime = new_runtime("javascript");
+        assert!(arrow_udf_last_error().is_null());
+
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)]));
+        let mut input = to_stream(schema, vec![]);
+        let mut output = FFI_ArrowArrayStream::empty();
+        let name = CString::new("unknown").unwrap();
+        let ret = arrow_udf_runtime_call(runtime, name.as_ptr(), &mut input, &mut output);
+        assert_eq!(ret, -1);
+        assert_eq!(last_error(), "function not found: unknown");
+
+        let field = FFI_ArrowSchema::try_from(Field::new("f", DataType::Int32, true)).unwrap();
+        let code = CString::new("").unwrap();
+        let ret = arrow_udf_runtime_add_function(runtime, name.as_ptr(), &field, 2, code.as_ptr());
+        assert_eq!(ret, -1);
+        assert_eq!(last_error(), "invalid call mode: 2");
+
+        // the input is released even if the call fails before reading it
+        let released = Arc::new(AtomicBool::new(false));
+        let reader = DropFlag {
+            schema: Arc::new(Schema::empty()),
+            released: released.clone(),
+        };
+        let mut input = FFI_ArrowArrayStream::new(Box::new(reader));
+        let ret = arrow_udf_runtime_call(runtime, name.as_ptr(), &mut input, std::ptr::null_mut());
+        assert_eq!(ret, -1);
+        assert_eq!(last_error(), "output is null");
+        assert!(released.load(Ordering::Relaxed));
+
+        arrow_udf_runtime_free(runtime);
+    }
+}
+
+unsafe fn new_runtime(language: &str) -> *mut ArrowUdfRuntime {
+    let language = CString::new(language).unwrap();
+    let runtime = arrow_udf_runtime_new(language.as_ptr(), std::ptr::null(), 0);
+    assert!(!runtime.is_null());
+    runtime
+}
+
+unsafe fn add_function(runtime: *mut ArrowUdfRuntime, name: &str, mode: i32, code: &str) {
+    let field = FFI_ArrowSchema::try_from(Field::new(name, DataType::Int32, true)).unwrap();
+    let name = CString::new(name).unwrap();
+    let code = CString::new(code).unwrap();
+    let ret = arrow_udf_runtime_add_function(runtime, name.as_ptr(), &field, mode, code.as_ptr());
+    assert_eq!(ret, 0, "{}", last_error());
+}
+
+unsafe fn last_error() -> String {
+    CStr::from_ptr(arrow_udf_last_error())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// An empty stream that records whether it is released.
+struct DropFlag {
+    schema: Arc<Schema>,
+    released: Arc<AtomicBool>,
+}
+
+impl Iterator for DropFlag {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        None
+    }
+}
+
+impl RecordBatchReader for DropFlag {
+    fn schema(&self) -> Arc<Schema> {
+        self.schema.clone()
+    }
+}
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.released.store(true, Ordering::Relaxed);
+    }
+}
+
+fn to_stream(schema: Arc<Schema>, batches: Vec<RecordBatch>) -> FFI_ArrowArrayStream {
+    let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+    FFI_ArrowArrayStream::new(Box::new(reader))
+}
+
+fn from_stream(stream: FFI_ArrowArrayStream) -> Vec<RecordBatch> {
+    let reader = ArrowArrayStreamReader::try_new(stream).unwrap();
+    reader.collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
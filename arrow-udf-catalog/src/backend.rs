@@ -0,0 +1,123 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::FunctionDefinition;
+
+/// A storage for function definitions.
+pub trait Backend: Send {
+    /// Load all function definitions.
+    fn load(&self) -> Result<Vec<FunctionDefinition>>;
+
+    /// Save a function definition, replacing the existing one with the same name.
+    fn save(&mut self, def: &FunctionDefinition) -> Result<()>;
+
+    /// Remove a function definition by name.
+    fn remove(&mut self, name: &str) -> Result<()>;
+}
+
+/// A backend that keeps function definitions in memory.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    functions: BTreeMap<String, FunctionDefinition>,
+}
+
+impl Backend for MemoryBackend {
+    fn load(&self) -> Result<Vec<FunctionDefinition>> {
+        Ok(self.functions.values().cloned().collect())
+    }
+
+    fn save(&mut self, def: &FunctionDefinition) -> Result<()> {
+        self.functions.insert(def.name.clone(), def.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<()> {
+        self.functions.remove(name);
+        Ok(())
+    }
+}
+
+/// A backend that stores all function definitions in a JSON file.
+///
+/// The file is rewritten atomically on every change.
+#[derive(Debug)]
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    /// Create a backend with the given file path. The file is created on the first change.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, FunctionDefinition>> {
+        let content = match std::fs::read(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.path.display()))
+            }
+        };
+        let functions: Vec<FunctionDefinition> = serde_json::from_slice(&content)
+            .with_context(|| format!("failed to parse {}", self.path.display()))?;
+        Ok(functions
+            .into_iter()
+            .map(|def| (def.name.clone(), def))
+            .collect())
+    }
+
+    fn write(&self, functions: &BTreeMap<String, FunctionDefinition>) -> Result<()> {
+        let content = serde_json::to_vec_pretty(&functions.values().collect::<Vec<_>>())?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, content)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+impl Backend for FileBackend {
+    fn load(&self) -> Result<Vec<FunctionDefinition>> {
+        Ok(self.read()?.into_values().collect())
+    }
+
+    fn save(&mut self, def: &FunctionDefinition) -> Result<()> {
+        let mut functions = self.read()?;
+        functions.insert(def.name.clone(), def.clone());
+        self.write(&functions)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<()> {
+        let mut functions = self.read()?;
+        if functions.remove(name).is_some() {
+            self.write(&functions)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Context, Result};
+use arrow_array::RecordBatch;
+use arrow_schema::Field;
+use arrow_udf_core::{check_arguments, Error, RecordBatchIter, UdfRuntime};
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
+use serde::{Deserialize, Serialize};
+
+pub use self::backend::{Backend, FileBackend, MemoryBackend};
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteBackend;
+
+mod backend;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+/// The language of a user defined function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Python,
+    JavaScript,
+    Wasm,
+}
+
+impl std::fmt::Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Language::Python => write!(f, "python"),
+            Language::JavaScript => write!(f, "javascript"),
+            Language::Wasm => write!(f, "wasm"),
+        }
+    }
+}
+
+/// The definition of a user defined function stored in the catalog.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    /// The name of the function.
+    ///
+    /// For WebAssembly, this is the name of the module.
+    /// The functions defined by the module are registered with their own names.
+    pub name: String,
+    /// The language of the function.
+    pub language: Language,
+    /// The source code for Python and JavaScript, or the module binary for WebAssembly.
+    #[serde(with = "base64_bytes")]
+    pub body: Vec<u8>,
+    /// The fields of the arguments. Empty if not checked.
+    pub arg_fields: Vec<Field>,
+    /// The field of the return value. Not used for WebAssembly.
+    pub return_field: Option<Field>,
+    /// Whether the function will be called when some of its arguments are null.
+    pub mode: CallMode,
+    /// Whether the function returns the same result for the same arguments.
+    #[serde(default)]
+    pub volatility: Volatility,
+    /// Whether the function can be evaluated in parallel.
+    #[serde(default)]
+    pub parallel_safety: ParallelSafety,
+}
+
+impl FunctionDefinition {
+    /// Create a definition of a Python function.
+    pub fn python(name: &str, return_field: Field, code: &str) -> Self {
+        Self::source(name, Language::Python, return_field, code)
+    }
+
+    /// Create a definition of a JavaScript function.
+    pub fn javascript(name: &str, return_field: Field, code: &str) -> Self {
+        Self::source(name, Language::JavaScript, return_field, code)
+    }
+
+    /// Create a definition of a WebAssembly module.
+    pub fn wasm(name: &str, binary: &[u8]) -> Self {
+        Self {
+            name: name.to_string(),
+            language: Language::Wasm,
+            body: binary.to_vec(),
+            arg_fields: vec![],
+            return_field: None,
+            mode: CallMode::default(),
+            volatility: Volatility::default(),
+            parallel_safety: ParallelSafety::default(),
+        }
+    }
+
+    fn source(name: &str, language: Language, return_field: Field, code: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            language,
+            body: code.as_bytes().to_vec(),
+            arg_fields: vec![],
+            return_field: Some(return_field),
+            mode: CallMode::default(),
+            volatility: Volatility::default(),
+            parallel_safety: ParallelSafety::default(),
+        }
+    }
+
+    /// Set the argument fields, which are checked against the input on every call.
+    pub fn with_args(mut self, arg_fields: Vec<Field>) -> Self {
+        self.arg_fields = arg_fields;
+        self
+    }
+
+    /// Set the call mode.
+    pub fn with_mode(mut self, mode: CallMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the volatility.
+    pub fn with_volatility(mut self, volatility: Volatility) -> Self {
+        self.volatility = volatility;
+        self
+    }
+
+    /// Set the parallel safety.
+    pub fn with_parallel_safety(mut self, parallel_safety: ParallelSafety) -> Self {
+        self.parallel_safety = parallel_safety;
+        self
+    }
+
+    /// Returns the properties of the function.
+    pub fn properties(&self) -> FunctionProperties {
+        FunctionProperties {
+            mode: self.mode,
+            volatility: self.volatility,
+            parallel_safety: self.parallel_safety,
+        }
+    }
+
+    /// Return the source code of a Python or JavaScript function.
+    fn code(&self) -> Result<&str> {
+        std::str::from_utf8(&self.body).context("source code is not valid UTF-8")
+    }
+
+    fn return_field(&self) -> Result<Field> {
+        self.return_field.clone().context("return field is missing")
+    }
+}
+
+/// A catalog of user defined functions, persisted in a [`Backend`].
+pub struct Catalog<B> {
+    backend: B,
+    functions: BTreeMap<String, FunctionDefinition>,
+}
+
+impl<B: Backend> Catalog<B> {
+    /// Open a catalog and load all function definitions from the backend.
+    pub fn open(backend: B) -> Result<Self> {
+        let functions = backend
+            .load()
+            .context("failed to load catalog")?
+            .into_iter()
+            .map(|def| (def.name.clone(), def))
+            .collect();
+        Ok(Self { backend, functions })
+    }
+
+    /// Add a new function definition and persist it.
+    pub fn add_function(&mut self, def: FunctionDefinition) -> Result<()> {
+        if self.functions.contains_key(&def.name) {
+            bail!("function already exists: {}", def.name);
+        }
+        self.backend
+            .save(&def)
+            .with_context(|| format!("failed to save function {}", def.name))?;
+        self.functions.insert(def.name.clone(), def);
+        Ok(())
+    }
+
+    /// Remove a function definition and return it.
+    pub fn remove_function(&mut self, name: &str) -> Result<FunctionDefinition> {
+        if !self.functions.contains_key(name) {
+            bail!("function not found: {name}");
+        }
+        self.backend
+            .remove(name)
+            .with_context(|| format!("failed to remove function {name}"))?;
+        Ok(self.functions.remove(name).unwrap())
+    }
+
+    /// Get a function definition by name.
+    pub fn get_function(&self, name: &str) -> Option<&FunctionDefinition> {
+        self.functions.get(name)
+    }
+
+    /// Return all function definitions ordered by name.
+    pub fn functions(&self) -> impl Iterator<Item = &FunctionDefinition> {
+        self.functions.values()
+    }
+
+    /// Returns a reference to the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Build runtimes with all functions in the catalog.
+    pub fn build_runtimes(&self) -> Result<Runtimes> {
+        let mut builder = RuntimesBuilder::default();
+        for def in self.functions.values() {
+            builder
+                .add(def)
+                .with_context(|| format!("failed to build function {}", def.name))?;
+        }
+        Ok(builder.build())
+    }
+}
+
+/// A set of runtimes built from a [`Catalog`].
+pub struct Runtimes {
+    runtimes: Vec<Box<dyn UdfRuntime>>,
+    /// Function name -> (index of runtime, argument fields).
+    functions: HashMap<String, (usize, Vec<Field>)>,
+}
+
+impl Runtimes {
+    /// Call a scalar function.
+    pub fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        self.runtime_for(name, input)?.call(name, input)
+    }
+
+    /// Call a table function.
+    pub fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> arrow_udf_core::Result<RecordBatchIter<'a>> {
+        self.runtime_for(name, input)?
+            .call_table_function(name, input, chunk_size)
+    }
+
+    /// Return the names of all functions that can be called.
+    pub fn list_functions(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.functions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Find the runtime of a function and check the input against its arguments.
+    fn runtime_for(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+    ) -> arrow_udf_core::Result<&dyn UdfRuntime> {
+        let (index, arg_fields) =
+            (self.functions.get(name)).ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        if !arg_fields.is_empty() {
+            let arg_types: Vec<_> = arg_fields.iter().map(|f| f.data_type().clone()).collect();
+            check_arguments(name, &arg_types, input.schema().fields())?;
+        }
+        Ok(self.runtimes[*index].as_ref())
+    }
+}
+
+#[derive(Default)]
+struct RuntimesBuilder {
+    runtimes: Vec<Box<dyn UdfRuntime>>,
+    functions: HashMap<String, (usize, Vec<Field>)>,
+    /// The index of the shared Python runtime.
+    python: Option<usize>,
+    /// The index of the shared JavaScript runtime.
+    javascript: Option<usize>,
+}
+
+impl RuntimesBuilder {
+    fn add(&mut self, def: &FunctionDefinition) -> Result<()> {
+        let index = match def.language {
+            Language::Python | Language::JavaScript => {
+                let index = self.shared(def.language)?;
+                let code = def.code()?;
+                self.runtimes[index].add_function_with_properties(
+                    &def.name,
+                    def.return_field()?,
+                    def.properties(),
+                    code,
+                )?;
+                index
+            }
+            Language::Wasm => {
+                let runtime = new_runtime(def.language, &def.body)?;
+                let index = self.runtimes.len();
+                for name in runtime.list_functions() {
+                    self.register(name, index, vec![])?;
+                }
+                self.runtimes.push(runtime);
+                return Ok(());
+            }
+        };
+        self.register(def.name.clone(), index, def.arg_fields.clone())
+    }
+
+    /// Returns the index of the runtime shared by all functions in the language.
+    fn shared(&mut self, language: Language) -> Result<usize> {
+        let slot = match language {
+            Language::Python => &mut self.python,
+            Language::JavaScript => &mut self.javascript,
+            Language::Wasm => unreachable!("WebAssembly runtimes are not shared"),
+        };
+        if let Some(index) = *slot {
+            return Ok(index);
+        }
+        let index = self.runtimes.len();
+        self.runtimes.push(new_runtime(language, &[])?);
+        *slot = Some(index);
+        Ok(index)
+    }
+
+    fn register(&mut self, name: String, index: usize, arg_fields: Vec<Field>) -> Result<()> {
+        if self.functions.contains_key(&name) {
+            bail!("duplicate function: {name}");
+        }
+        self.functions.insert(name, (index, arg_fields));
+        Ok(())
+    }
+
+    fn build(self) -> Runtimes {
+        Runtimes {
+            runtimes: self.runtimes,
+            functions: self.functions,
+        }
+    }
+}
+
+/// Create a new runtime for the language.
+///
+/// `binary` is only used for WebAssembly.
+#[allow(unused_variables)]
+fn new_runtime(language: Language, binary: &[u8]) -> Result<Box<dyn UdfRuntime>> {
+    match language {
+        #[cfg(feature = "python")]
+        Language::Python => Ok(Box::new(arrow_udf_python::Runtime::new()?)),
+        #[cfg(feature = "javascript")]
+        Language::JavaScript => Ok(Box::new(arrow_udf_js::Runtime::new()?)),
+        #[cfg(feature = "wasm")]
+        Language::Wasm => Ok(Box::new(arrow_udf_wasm::Runtime::new(binary)?)),
+        #[allow(unreachable_patterns)]
+        _ => bail!("{language} runtime is not enabled"),
+    }
+}
+
+/// Serialize bytes as a base64 string.
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        STANDARD.decode(s).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+
+use crate::{Backend, FunctionDefinition};
+
+/// A backend that stores function definitions in a SQLite database.
+pub struct SqliteBackend {
+    conn: Connection,
+}
+
+impl SqliteBackend {
+    /// Open a database file, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open(path).context("failed to open database")?;
+        Self::with_connection(conn)
+    }
+
+    /// Open an in-memory database.
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory().context("failed to open database")?;
+        Self::with_connection(conn)
+    }
+
+    /// Create a backend from an existing connection.
+    pub fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS udf_functions (
+                name TEXT PRIMARY KEY,
+                definition TEXT NOT NULL
+            )",
+            (),
+        )
+        .context("failed to create table")?;
+        Ok(Self { conn })
+    }
+}
+
+impl Backend for SqliteBackend {
+    fn load(&self) -> Result<Vec<FunctionDefinition>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT definition FROM udf_functions ORDER BY name")?;
+        let rows = stmt.query_map((), |row| row.get::<_, String>(0))?;
+        let mut functions = vec![];
+        for row in rows {
+            functions.push(serde_json::from_str(&row?).context("failed to parse definition")?);
+        }
+        Ok(functions)
+    }
+
+    fn save(&mut self, def: &FunctionDefinition) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO udf_functions (name, definition) VALUES (?1, ?2)",
+            (&def.name, serde_json::to_string(def)?),
+        )?;
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM udf_functions WHERE name = ?1", (name,))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_catalog::*;
+use expect_test::{expect, Expect};
+
+const BINARY_PATH: &str = "../target/wasm32-wasip1/release/arrow_udf_example.wasm";
+
+#[test]
+fn test_file_backend_rebuild() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("functions.json");
+
+    let mut catalog = Catalog::open(FileBackend::new(&path)).unwrap();
+    catalog.add_function(python_neg()).unwrap();
+    catalog.add_function(javascript_add()).unwrap();
+    catalog
+        .add_function(FunctionDefinition::wasm(
+            "example",
+            &std::fs::read(BINARY_PATH).expect("failed to load wasm binary"),
+        ))
+        .unwrap();
+    drop(catalog);
+
+    // reopen the catalog and rebuild runtimes
+    let catalog = Catalog::open(FileBackend::new(&path)).unwrap();
+    assert_eq!(
+        catalog.functions().map(|f| &f.name).collect::<Vec<_>>(),
+        ["add", "example", "neg"]
+    );
+    let runtimes = catalog.build_runtimes().unwrap();
+    let functions = runtimes.list_functions();
+    assert!(functions.contains(&"neg".to_string()));
+    assert!(functions.contains(&"add".to_string()));
+    assert!(functions.contains(&"gcd(int32,int32)->int32".to_string()));
+
+    let input = int32_input(&[&[Some(25), None]]);
+    let neg = runtimes.call("neg", &input).unwrap();
+    let input = int32_input(&[&[Some(25), None], &[Some(15), Some(1)]]);
+    let add = runtimes.call("add", &input).unwrap();
+    let gcd = runtimes.call("gcd(int32,int32)->int32", &input).unwrap();
+    check(
+        &[neg, add, gcd],
+        expect![[r#"
+        +-----+-----+-----+
+        | neg | add | gcd |
+        +-----+-----+-----+
+        | -25 | 40  | 5   |
+        |     |     |     |
+        +-----+-----+-----+"#]],
+    );
+}
+
+#[test]
+fn test_remove_function() {
+    let mut catalog = Catalog::open(MemoryBackend::default()).unwrap();
+    catalog.add_function(python_neg()).unwrap();
+    catalog.add_function(python_neg()).unwrap_err();
+
+    let def = catalog.remove_function("neg").unwrap();
+    assert_eq!(def, python_neg());
+    catalog.remove_function("neg").unwrap_err();
+    assert!(catalog.backend().load().unwrap().is_empty());
+    assert!(catalog
+        .build_runtimes()
+        .unwrap()
+        .list_functions()
+        .is_empty());
+}
+
+#[test]
+fn test_check_arguments() {
+    let mut catalog = Catalog::open(MemoryBackend::default()).unwrap();
+    catalog.add_function(python_neg()).unwrap();
+    let runtimes = catalog.build_runtimes().unwrap();
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("x", DataType::Utf8, true)])),
+        vec![Arc::new(arrow_array::StringArray::from(vec!["a"]))],
+    )
+    .unwrap();
+    let err = runtimes.call("neg", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: argument 1 (x) of function neg: expected Int32, got Utf8"
+    );
+    let err = runtimes.call("unknown", &input).unwrap_err();
+    assert!(matches!(err, arrow_udf_core::Error::FunctionNotFound(_)));
+}
+
+#[test]
+fn test_invalid_function() {
+    let mut catalog = Catalog::open(MemoryBackend::default()).unwrap();
+    catalog
+        .add_function(FunctionDefinition::javascript(
+            "broken",
+            Field::new("broken", DataType::Int32, true),
+            "export function broken( {",
+        ))
+        .unwrap();
+    let err = catalog.build_runtimes().err().unwrap();
+    assert_eq!(err.to_string(), "failed to build function broken");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_backend() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("functions.db");
+
+    let mut catalog = Catalog::open(SqliteBackend::open(&path).unwrap()).unwrap();
+    catalog.add_function(python_neg()).unwrap();
+    catalog.add_function(javascript_add()).unwrap();
+    catalog.remove_function("add").unwrap();
+    drop(catalog);
+
+    let catalog = Catalog::open(SqliteBackend::open(&path).unwrap()).unwrap();
+    assert_eq!(catalog.get_function("neg"), Some(&python_neg()));
+    assert_eq!(catalog.get_function("add"), None);
+}
+
+fn python_neg() -> FunctionDefinition {
+    FunctionDefinition::python(
+        "neg",
+        Field::new("neg", DataType::Int32, true),
+        r#"
+def neg(x):
+    return -x
+"#,
+    )
+    .with_args(vec![Field::new("x", DataType::Int32, true)])
+    .with_mode(CallMode::ReturnNullOnNullInput)
+}
+
+fn javascript_add() -> FunctionDefinition {
+    FunctionDefinition::javascript(
+        "add",
+        Field::new("add", DataType::Int32, true),
+        r#"
+        export function add(a, b) {
+            return a + b;
+        }
+        "#,
+    )
+    .with_mode(CallMode::ReturnNullOnNullInput)
+    .with_volatility(Volatility::Immutable)
+    .with_parallel_safety(ParallelSafety::Safe)
+}
+
+/// Build a batch of int32 columns from the given values.
+fn int32_input(columns: &[&[Option<i32>]]) -> RecordBatch {
+    let fields = (0..columns.len())
+        .map(|i| Field::new(format!("c{i}"), DataType::Int32, true))
+        .collect::<Vec<_>>();
+    let arrays = columns
+        .iter()
+        .map(|c| Arc::new(Int32Array::from(c.to_vec())) as _)
+        .collect();
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).unwrap()
+}
+
+/// Compare the output columns of single-column batches with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(
+            actual
+                .iter()
+                .map(|b| b.schema().field(0).clone())
+                .collect::<Vec<_>>(),
+        )),
+        actual.iter().map(|b| b.column(0).clone()).collect(),
+    )
+    .unwrap();
+    expect.assert_eq(&pretty_format_batches(&[batch]).unwrap().to_string());
+}
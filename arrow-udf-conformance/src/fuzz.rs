@@ -0,0 +1,359 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Random arrays to fuzz the conversions of runtimes.
+//!
+//! The arrays are generated from [`Unstructured`] data, so they can be driven by
+//! a fuzzer like `cargo fuzz` as well as by seeded random bytes in ordinary tests.
+
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::sync::Arc;
+
+use arbitrary::{Arbitrary, Unstructured};
+use arrow_array::types::*;
+use arrow_array::*;
+use arrow_buffer::{IntervalMonthDayNano, NullBuffer, OffsetBuffer};
+use arrow_schema::{DataType, Field, FieldRef, IntervalUnit, TimeUnit};
+
+use crate::{fixtures, format_array, run_fixture, Adapter, Fixture, Outcome, DECIMAL, JSON};
+
+/// The maximum number of values in a generated array, or in a list value.
+const MAX_LEN: usize = 8;
+
+/// The number of seconds in 100 years, which bounds the generated dates and times.
+const CENTURY_SECS: i64 = 100 * 365 * 24 * 3600;
+
+/// Generate an array of random values for the field.
+///
+/// Values are bounded to the ranges that languages can represent, e.g. dates within 100 years of the epoch.
+/// Types that are not supported by the generator get arrays of nulls.
+pub fn arbitrary_array(u: &mut Unstructured, field: &Field) -> arbitrary::Result<ArrayRef> {
+    let len = u.int_in_range(0..=MAX_LEN)?;
+    array(u, field, len)
+}
+
+fn array(u: &mut Unstructured, field: &Field, len: usize) -> arbitrary::Result<ArrayRef> {
+    let nullable = field.is_nullable();
+    let extension = field
+        .metadata()
+        .get("ARROW:extension:name")
+        .map(|s| s.as_str());
+    Ok(match field.data_type() {
+        DataType::Null => Arc::new(NullArray::new(len)),
+        DataType::Boolean => Arc::new(BooleanArray::from(values(
+            u,
+            len,
+            nullable,
+            bool::arbitrary,
+        )?)),
+        DataType::Int8 => primitive::<Int8Type>(u, len, nullable, i8::arbitrary)?,
+        DataType::Int16 => primitive::<Int16Type>(u, len, nullable, i16::arbitrary)?,
+        DataType::Int32 => primitive::<Int32Type>(u, len, nullable, i32::arbitrary)?,
+        DataType::Int64 => primitive::<Int64Type>(u, len, nullable, i64::arbitrary)?,
+        DataType::UInt8 => primitive::<UInt8Type>(u, len, nullable, u8::arbitrary)?,
+        DataType::UInt16 => primitive::<UInt16Type>(u, len, nullable, u16::arbitrary)?,
+        DataType::UInt32 => primitive::<UInt32Type>(u, len, nullable, u32::arbitrary)?,
+        DataType::UInt64 => primitive::<UInt64Type>(u, len, nullable, u64::arbitrary)?,
+        DataType::Float32 => primitive::<Float32Type>(u, len, nullable, |u| Ok(float(u)? as f32))?,
+        DataType::Float64 => primitive::<Float64Type>(u, len, nullable, float)?,
+        DataType::Decimal128(precision, scale) => {
+            let max = 10i128.pow(*precision as u32) - 1;
+            let values = values(u, len, nullable, |u| u.int_in_range(-max..=max))?;
+            let array = Decimal128Array::from(values);
+            Arc::new(array.with_precision_and_scale(*precision, *scale).unwrap())
+        }
+        DataType::Date32 => {
+            let days = (CENTURY_SECS / 86400) as i32;
+            primitive::<Date32Type>(u, len, nullable, |u| u.int_in_range(-days..=days))?
+        }
+        DataType::Time64(TimeUnit::Microsecond) => {
+            primitive::<Time64MicrosecondType>(u, len, nullable, |u| {
+                u.int_in_range(0..=86_400_000_000 - 1)
+            })?
+        }
+        DataType::Time64(TimeUnit::Nanosecond) => {
+            primitive::<Time64NanosecondType>(u, len, nullable, |u| {
+                u.int_in_range(0..=86_400_000_000_000 - 1)
+            })?
+        }
+        DataType::Timestamp(unit, tz) => {
+            let per_sec = match unit {
+                TimeUnit::Second => 1,
+                TimeUnit::Millisecond => 1_000,
+                TimeUnit::Microsecond => 1_000_000,
+                TimeUnit::Nanosecond => 1_000_000_000,
+            };
+            let max = CENTURY_SECS * per_sec;
+            let values = values(u, len, nullable, |u| u.int_in_range(-max..=max))?;
+            let tz = tz.clone();
+            match unit {
+                TimeUnit::Second => {
+                    Arc::new(TimestampSecondArray::from(values).with_timezone_opt(tz))
+                }
+                TimeUnit::Millisecond => {
+                    Arc::new(TimestampMillisecondArray::from(values).with_timezone_opt(tz))
+                }
+                TimeUnit::Microsecond => {
+                    Arc::new(TimestampMicrosecondArray::from(values).with_timezone_opt(tz))
+                }
+                TimeUnit::Nanosecond => {
+                    Arc::new(TimestampNanosecondArray::from(values).with_timezone_opt(tz))
+                }
+            }
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let values = values(u, len, nullable, |u| {
+                Ok(IntervalMonthDayNano::new(
+                    u.int_in_range(-1200..=1200)?,
+                    u.int_in_range(-36500..=36500)?,
+                    u.int_in_range(-86_400_000_000_000..=86_400_000_000_000)?,
+                ))
+            })?;
+            Arc::new(IntervalMonthDayNanoArray::from(values))
+        }
+        DataType::Utf8 | DataType::LargeUtf8 => {
+            let values = values(u, len, nullable, |u| match extension {
+                Some(JSON) => json(u, 2),
+                Some(DECIMAL) => decimal(u),
+                _ => Ok(<&str>::arbitrary(u)?.to_string()),
+            })?;
+            match field.data_type() {
+                DataType::Utf8 => Arc::new(StringArray::from(values)),
+                _ => Arc::new(LargeStringArray::from(values)),
+            }
+        }
+        DataType::Binary => {
+            let values = values(u, len, nullable, <&[u8]>::arbitrary)?;
+            Arc::new(BinaryArray::from(values))
+        }
+        DataType::LargeBinary => {
+            let values = values(u, len, nullable, <&[u8]>::arbitrary)?;
+            Arc::new(LargeBinaryArray::from(values))
+        }
+        DataType::List(child) => {
+            let (offsets, nulls, values) = list(u, child, len, nullable)?;
+            let offsets = OffsetBuffer::new(offsets.into_iter().map(|o| o as i32).collect());
+            Arc::new(ListArray::new(child.clone(), offsets, values, nulls))
+        }
+        DataType::LargeList(child) => {
+            let (offsets, nulls, values) = list(u, child, len, nullable)?;
+            let offsets = OffsetBuffer::new(offsets.into_iter().map(|o| o as i64).collect());
+            Arc::new(LargeListArray::new(child.clone(), offsets, values, nulls))
+        }
+        DataType::Struct(fields) => {
+            let columns = (fields.iter())
+                .map(|f| array(u, f, len))
+                .collect::<arbitrary::Result<Vec<_>>>()?;
+            let nulls = null_buffer(u, len, nullable)?;
+            Arc::new(StructArray::new(fields.clone(), columns, nulls))
+        }
+        data_type => new_null_array(data_type, len),
+    })
+}
+
+/// Generate `len` values, some of which are null if nullable.
+fn values<'a, T>(
+    u: &mut Unstructured<'a>,
+    len: usize,
+    nullable: bool,
+    mut f: impl FnMut(&mut Unstructured<'a>) -> arbitrary::Result<T>,
+) -> arbitrary::Result<Vec<Option<T>>> {
+    let mut values = Vec::with_capacity(len);
+    for _ in 0..len {
+        if nullable && u.ratio(1, 8)? {
+            values.push(None);
+        } else {
+            values.push(Some(f(u)?));
+        }
+    }
+    Ok(values)
+}
+
+fn primitive<'a, T: ArrowPrimitiveType>(
+    u: &mut Unstructured<'a>,
+    len: usize,
+    nullable: bool,
+    f: impl FnMut(&mut Unstructured<'a>) -> arbitrary::Result<T::Native>,
+) -> arbitrary::Result<ArrayRef> {
+    let values = values(u, len, nullable, f)?;
+    Ok(Arc::new(values.into_iter().collect::<PrimitiveArray<T>>()))
+}
+
+fn null_buffer(
+    u: &mut Unstructured,
+    len: usize,
+    nullable: bool,
+) -> arbitrary::Result<Option<NullBuffer>> {
+    let valid = values(u, len, nullable, |_| Ok(()))?;
+    Ok(nullable.then(|| valid.iter().map(|v| v.is_some()).collect()))
+}
+
+/// Generate the offsets, nulls and values of a list array.
+fn list(
+    u: &mut Unstructured,
+    child: &FieldRef,
+    len: usize,
+    nullable: bool,
+) -> arbitrary::Result<(Vec<usize>, Option<NullBuffer>, ArrayRef)> {
+    let nulls = null_buffer(u, len, nullable)?;
+    let mut offsets = vec![0];
+    for i in 0..len {
+        let n = match &nulls {
+            Some(nulls) if nulls.is_null(i) => 0,
+            _ => u.int_in_range(0..=MAX_LEN / 2)?,
+        };
+        offsets.push(offsets[i] + n);
+    }
+    let values = array(u, child, offsets[len])?;
+    Ok((offsets, nulls, values))
+}
+
+/// Generate a float, favoring special values.
+fn float(u: &mut Unstructured) -> arbitrary::Result<f64> {
+    const SPECIAL: [f64; 8] = [
+        0.0,
+        -0.0,
+        1.0,
+        f64::NAN,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::MAX,
+        f64::MIN_POSITIVE,
+    ];
+    if u.ratio(1, 4)? {
+        return u.choose(&SPECIAL).copied();
+    }
+    let value = f64::from_bits(u.arbitrary()?);
+    // NaN payloads are not preserved by languages
+    Ok(if value.is_nan() { f64::NAN } else { value })
+}
+
+/// Generate a JSON text in the compact form.
+fn json(u: &mut Unstructured, depth: usize) -> arbitrary::Result<String> {
+    let choice = u.int_in_range(0..=if depth == 0 { 3 } else { 5 })?;
+    Ok(match choice {
+        0 => "null".to_string(),
+        1 => bool::arbitrary(u)?.to_string(),
+        2 => i32::arbitrary(u)?.to_string(),
+        3 => {
+            let len = u.int_in_range(0..=MAX_LEN)?;
+            let s: String = (0..len)
+                .map(|_| u.choose(b"abcxyz019 _").map(|c| *c as char))
+                .collect::<arbitrary::Result<_>>()?;
+            format!("\"{s}\"")
+        }
+        4 => {
+            let len = u.int_in_range(0..=MAX_LEN / 2)?;
+            let items = (0..len)
+                .map(|_| json(u, depth - 1))
+                .collect::<arbitrary::Result<Vec<_>>>()?;
+            format!("[{}]", items.join(","))
+        }
+        _ => {
+            let len = u.int_in_range(0..=MAX_LEN / 2)?;
+            let items = (0..len)
+                .map(|i| Ok(format!("\"k{i}\":{}", json(u, depth - 1)?)))
+                .collect::<arbitrary::Result<Vec<_>>>()?;
+            format!("{{{}}}", items.join(","))
+        }
+    })
+}
+
+/// Generate a decimal text in the canonical form, without leading or trailing zeros.
+fn decimal(u: &mut Unstructured) -> arbitrary::Result<String> {
+    let int = u.int_in_range(-999_999_999_999i64..=999_999_999_999)?;
+    let frac = u.int_in_range(0..=999_999u32)?;
+    let mut s = int.unsigned_abs().to_string();
+    if frac != 0 {
+        s = format!("{s}.{frac:06}").trim_end_matches('0').to_string();
+    }
+    if int < 0 || (int == 0 && frac != 0 && bool::arbitrary(u)?) {
+        s.insert(0, '-');
+    }
+    Ok(s)
+}
+
+/// A random case that failed in [`fuzz`].
+#[derive(Debug, Clone)]
+pub struct FuzzFailure {
+    /// The name of the fixture whose field was used.
+    pub name: &'static str,
+    /// The seed of random bytes to reproduce the case.
+    pub seed: u64,
+    /// The input values.
+    pub input: ArrayRef,
+    /// The outcome of the case.
+    pub outcome: Outcome,
+}
+
+impl Display for FuzzFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let input = format_array(self.input.as_ref());
+        write!(f, "{} (seed {}) on {input}: ", self.name, self.seed)?;
+        match &self.outcome {
+            Outcome::Pass => write!(f, "ok"),
+            Outcome::Unsupported(e) => write!(f, "unsupported: {e}"),
+            Outcome::Error(e) => write!(f, "error: {e}"),
+            Outcome::Mismatch { expected, actual } => {
+                write!(f, "expected {expected}, actual {actual}")
+            }
+        }
+    }
+}
+
+/// Run random arrays of each fixture's field on the runtime, and return the first failed case of each field.
+///
+/// Only the fields whose fixtures pass on the runtime are fuzzed,
+/// so that known differences of a runtime don't hide new ones.
+/// The arrays of each seed are generated from the same bytes on all platforms.
+pub fn fuzz(adapter: &mut dyn Adapter, seeds: Range<u64>) -> Vec<FuzzFailure> {
+    let mut failures = vec![];
+    for fixture in fixtures() {
+        if run_fixture(adapter, &fixture) != Outcome::Pass {
+            continue;
+        }
+        for seed in seeds.clone() {
+            let bytes = random_bytes(seed, 1024);
+            let input = arbitrary_array(&mut Unstructured::new(&bytes), &fixture.field)
+                .expect("failed to generate array");
+            let case = Fixture::new(fixture.name, fixture.field.clone(), input);
+            let outcome = run_fixture(adapter, &case);
+            if outcome != Outcome::Pass {
+                failures.push(FuzzFailure {
+                    name: fixture.name,
+                    seed,
+                    input: case.input,
+                    outcome,
+                });
+                break;
+            }
+        }
+    }
+    failures
+}
+
+/// Returns pseudo-random bytes of the seed, generated by SplitMix64.
+pub fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    let mut bytes = Vec::with_capacity(len + 8);
+    while bytes.len() < len {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        bytes.extend_from_slice(&(z ^ (z >> 31)).to_le_bytes());
+    }
+    bytes.truncate(len);
+    bytes
+}
@@ -0,0 +1,496 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![doc = include_str!("../README.md")]
+
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use arrow_array::builder::{Int32Builder, ListBuilder, StringBuilder};
+use arrow_array::*;
+use arrow_buffer::{IntervalMonthDayNano, OffsetBuffer};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::{DataType, Field, Fields, IntervalUnit, Schema, TimeUnit};
+use arrow_udf_core::{CallMode, Result, UdfRuntime};
+
+pub use self::fuzz::{arbitrary_array, fuzz, random_bytes, FuzzFailure};
+
+mod fuzz;
+
+/// A test case of converting values of a type into a language and back.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    /// The name of the case.
+    pub name: &'static str,
+    /// The field of the argument and the return value.
+    pub field: Field,
+    /// The input values.
+    pub input: ArrayRef,
+    /// The reference output of a function returning its argument.
+    pub expected: ArrayRef,
+}
+
+impl Fixture {
+    /// Create a case whose reference output is the input itself.
+    pub fn new(name: &'static str, field: Field, input: ArrayRef) -> Self {
+        Self {
+            name,
+            field,
+            expected: input.clone(),
+            input,
+        }
+    }
+
+    /// Set the reference output.
+    pub fn with_expected(mut self, expected: ArrayRef) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Returns the case with the input sliced from the middle of a larger array.
+    ///
+    /// The sliced input has a non-zero offset, so that validity bitmaps start at an unaligned bit
+    /// and the offsets of strings and lists don't start at zero.
+    pub fn sliced(&self) -> Self {
+        let len = self.input.len();
+        let padded = arrow_select::concat::concat(&[&self.input, &self.input, &self.input])
+            .expect("failed to concat arrays");
+        Self {
+            input: padded.slice(len, len),
+            ..self.clone()
+        }
+    }
+}
+
+/// The extension name of JSON type.
+const JSON: &str = "arrowudf.json";
+/// The extension name of decimal type.
+const DECIMAL: &str = "arrowudf.decimal";
+
+/// Returns a field of the extension type.
+fn extension(data_type: DataType, name: &str) -> Field {
+    Field::new("x", data_type, true)
+        .with_metadata([("ARROW:extension:name".into(), name.into())].into())
+}
+
+/// Returns the corpus of fixtures covering types, nulls, nested data and edge values.
+pub fn fixtures() -> Vec<Fixture> {
+    let field = |data_type| Field::new("x", data_type, true);
+    let mut int_list = ListBuilder::new(Int32Builder::new());
+    int_list.append_value([Some(1), None, Some(3)]);
+    int_list.append_value([]);
+    int_list.append_null();
+    let int_list = int_list.finish();
+    let mut string_list = ListBuilder::new(StringBuilder::new());
+    string_list.append_value([Some("a"), None, Some("")]);
+    string_list.append_null();
+    let string_list = string_list.finish();
+    let nested_list = ListArray::new(
+        Arc::new(Field::new_list_field(int_list.data_type().clone(), true)),
+        OffsetBuffer::from_lengths([2, 0, 1]),
+        Arc::new(int_list.clone()),
+        Some(vec![true, true, false].into()),
+    );
+    let struct_fields = Fields::from(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Utf8, true),
+    ]);
+    let struct_array = StructArray::new(
+        struct_fields.clone(),
+        vec![
+            Arc::new(Int32Array::from(vec![Some(1), None, None])),
+            Arc::new(StringArray::from(vec![Some("x"), None, None])),
+        ],
+        Some(vec![true, true, false].into()),
+    );
+
+    vec![
+        Fixture::new("null", field(DataType::Null), Arc::new(NullArray::new(2))),
+        Fixture::new(
+            "boolean",
+            field(DataType::Boolean),
+            Arc::new(BooleanArray::from(vec![Some(true), Some(false), None])),
+        ),
+        Fixture::new(
+            "int8",
+            field(DataType::Int8),
+            Arc::new(Int8Array::from(vec![
+                Some(i8::MIN),
+                Some(-1),
+                Some(0),
+                None,
+                Some(i8::MAX),
+            ])),
+        ),
+        Fixture::new(
+            "int16",
+            field(DataType::Int16),
+            Arc::new(Int16Array::from(vec![
+                Some(i16::MIN),
+                Some(-1),
+                None,
+                Some(i16::MAX),
+            ])),
+        ),
+        Fixture::new(
+            "int32",
+            field(DataType::Int32),
+            Arc::new(Int32Array::from(vec![
+                Some(i32::MIN),
+                Some(-1),
+                None,
+                Some(i32::MAX),
+            ])),
+        ),
+        Fixture::new(
+            "int64",
+            field(DataType::Int64),
+            Arc::new(Int64Array::from(vec![
+                Some(i64::MIN),
+                Some(-1),
+                None,
+                Some(i64::MAX),
+            ])),
+        ),
+        Fixture::new(
+            "uint8",
+            field(DataType::UInt8),
+            Arc::new(UInt8Array::from(vec![Some(0), None, Some(u8::MAX)])),
+        ),
+        Fixture::new(
+            "uint16",
+            field(DataType::UInt16),
+            Arc::new(UInt16Array::from(vec![Some(0), None, Some(u16::MAX)])),
+        ),
+        Fixture::new(
+            "uint32",
+            field(DataType::UInt32),
+            Arc::new(UInt32Array::from(vec![Some(0), None, Some(u32::MAX)])),
+        ),
+        Fixture::new(
+            "uint64",
+            field(DataType::UInt64),
+            Arc::new(UInt64Array::from(vec![Some(0), None, Some(u64::MAX)])),
+        ),
+        Fixture::new(
+            "float32",
+            field(DataType::Float32),
+            Arc::new(Float32Array::from(vec![
+                Some(f32::MIN),
+                Some(-0.5),
+                Some(f32::INFINITY),
+                Some(f32::NAN),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "float64",
+            field(DataType::Float64),
+            Arc::new(Float64Array::from(vec![
+                Some(f64::MIN_POSITIVE),
+                Some(-0.5),
+                Some(f64::NEG_INFINITY),
+                Some(f64::NAN),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "decimal128",
+            field(DataType::Decimal128(10, 2)),
+            Arc::new(
+                Decimal128Array::from(vec![Some(12345), Some(-1), None])
+                    .with_precision_and_scale(10, 2)
+                    .unwrap(),
+            ),
+        ),
+        Fixture::new(
+            "date32",
+            field(DataType::Date32),
+            Arc::new(Date32Array::from(vec![
+                Some(0),
+                Some(-1),
+                Some(19000),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "time64",
+            field(DataType::Time64(TimeUnit::Microsecond)),
+            Arc::new(Time64MicrosecondArray::from(vec![
+                Some(0),
+                Some(86_399_999_999),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "timestamp",
+            field(DataType::Timestamp(TimeUnit::Microsecond, None)),
+            Arc::new(TimestampMicrosecondArray::from(vec![
+                Some(0),
+                Some(1_700_000_000_123_456),
+                Some(-1),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "interval",
+            field(DataType::Interval(IntervalUnit::MonthDayNano)),
+            Arc::new(IntervalMonthDayNanoArray::from(vec![
+                Some(IntervalMonthDayNano::new(1, 2, 3_000)),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "string",
+            field(DataType::Utf8),
+            Arc::new(StringArray::from(vec![
+                Some(""),
+                Some("hello"),
+                Some("你好🦀"),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "large_string",
+            field(DataType::LargeUtf8),
+            Arc::new(LargeStringArray::from(vec![Some(""), Some("你好🦀"), None])),
+        ),
+        Fixture::new(
+            "binary",
+            field(DataType::Binary),
+            Arc::new(BinaryArray::from(vec![
+                Some(&b""[..]),
+                Some(b"\x00\xff"),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "large_binary",
+            field(DataType::LargeBinary),
+            Arc::new(LargeBinaryArray::from(vec![
+                Some(&b""[..]),
+                Some(b"\x00\xff"),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "decimal",
+            extension(DataType::Utf8, DECIMAL),
+            Arc::new(StringArray::from(vec![
+                Some("0"),
+                Some("-1.23"),
+                Some("0.0001"),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "json",
+            extension(DataType::Utf8, JSON),
+            Arc::new(StringArray::from(vec![
+                Some(r#"{"a":1}"#),
+                Some("[1,null]"),
+                Some(r#""s""#),
+                Some("null"),
+                None,
+            ])),
+        ),
+        Fixture::new(
+            "list",
+            field(int_list.data_type().clone()),
+            Arc::new(int_list),
+        ),
+        Fixture::new(
+            "string_list",
+            field(string_list.data_type().clone()),
+            Arc::new(string_list),
+        ),
+        Fixture::new(
+            "nested_list",
+            field(nested_list.data_type().clone()),
+            Arc::new(nested_list),
+        ),
+        Fixture::new(
+            "struct",
+            field(DataType::Struct(struct_fields)),
+            Arc::new(struct_array),
+        ),
+    ]
+}
+
+/// A runtime under test.
+pub trait Adapter {
+    /// Returns the runtime.
+    fn runtime(&self) -> &dyn UdfRuntime;
+
+    /// Prepare a function that returns its only argument of the field, and return its name.
+    fn identity(&mut self, field: &Field) -> Result<String>;
+}
+
+/// An adapter of runtimes that add functions from source code, e.g. JavaScript and Python.
+pub struct ScriptAdapter {
+    runtime: Box<dyn UdfRuntime>,
+    code: fn(&str) -> String,
+    next_id: usize,
+}
+
+impl ScriptAdapter {
+    /// Create an adapter with the code of an identity function with the given name.
+    pub fn new(runtime: Box<dyn UdfRuntime>, code: fn(&str) -> String) -> Self {
+        Self {
+            runtime,
+            code,
+            next_id: 0,
+        }
+    }
+}
+
+impl Adapter for ScriptAdapter {
+    fn runtime(&self) -> &dyn UdfRuntime {
+        self.runtime.as_ref()
+    }
+
+    fn identity(&mut self, field: &Field) -> Result<String> {
+        let name = format!("identity{}", self.next_id);
+        self.next_id += 1;
+        let field = field.clone().with_name(&name);
+        let code = (self.code)(&name);
+        (self.runtime).add_function(&name, field, CallMode::CalledOnNullInput, &code)?;
+        Ok(name)
+    }
+}
+
+/// The outcome of a fixture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The output matches the reference.
+    Pass,
+    /// The function can not be defined for the type.
+    Unsupported(String),
+    /// The call failed.
+    Error(String),
+    /// The output differs from the reference.
+    Mismatch {
+        /// The reference output.
+        expected: String,
+        /// The actual output.
+        actual: String,
+    },
+}
+
+/// The outcomes of all fixtures on a runtime.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The name and outcome of each fixture.
+    pub outcomes: Vec<(&'static str, Outcome)>,
+}
+
+impl Report {
+    /// Whether all fixtures pass.
+    pub fn is_pass(&self) -> bool {
+        self.outcomes.iter().all(|(_, o)| *o == Outcome::Pass)
+    }
+}
+
+/// Renders one line per fixture.
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, outcome) in &self.outcomes {
+            match outcome {
+                Outcome::Pass => writeln!(f, "{name}: ok")?,
+                Outcome::Unsupported(e) => writeln!(f, "{name}: unsupported: {e}")?,
+                Outcome::Error(e) => writeln!(f, "{name}: error: {e}")?,
+                Outcome::Mismatch { expected, actual } => {
+                    writeln!(f, "{name}: expected {expected}, actual {actual}")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run all fixtures on the runtime.
+pub fn run(adapter: &mut dyn Adapter) -> Report {
+    let outcomes = fixtures()
+        .into_iter()
+        .map(|fixture| (fixture.name, run_fixture(adapter, &fixture)))
+        .collect();
+    Report { outcomes }
+}
+
+/// Run all fixtures on the runtime, with the inputs sliced from larger arrays.
+///
+/// The report is expected to be the same as the one of [`run`].
+pub fn run_sliced(adapter: &mut dyn Adapter) -> Report {
+    let outcomes = fixtures()
+        .into_iter()
+        .map(|fixture| (fixture.name, run_fixture(adapter, &fixture.sliced())))
+        .collect();
+    Report { outcomes }
+}
+
+/// Run a fixture on the runtime.
+pub fn run_fixture(adapter: &mut dyn Adapter, fixture: &Fixture) -> Outcome {
+    let name = match adapter.identity(&fixture.field) {
+        Ok(name) => name,
+        Err(e) => return Outcome::Unsupported(first_line(&e)),
+    };
+    let schema = Schema::new(vec![fixture.field.clone()]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![fixture.input.clone()]).unwrap();
+    let output = match adapter.runtime().call(&name, &input) {
+        Ok(output) => output,
+        Err(e) => return Outcome::Error(first_line(&e)),
+    };
+    let actual_field = output.schema().field(0).clone();
+    let actual = output.column(0);
+    if actual_field.data_type() != fixture.field.data_type()
+        || actual_field.metadata() != fixture.field.metadata()
+    {
+        return Outcome::Mismatch {
+            expected: describe_field(&fixture.field),
+            actual: describe_field(&actual_field),
+        };
+    }
+    if actual.to_data() != fixture.expected.to_data() {
+        return Outcome::Mismatch {
+            expected: format_array(fixture.expected.as_ref()),
+            actual: format_array(actual.as_ref()),
+        };
+    }
+    Outcome::Pass
+}
+
+/// Returns the first line of an error message.
+fn first_line(e: &dyn Display) -> String {
+    e.to_string().lines().next().unwrap_or_default().to_string()
+}
+
+/// Describe the type and extension of a field.
+fn describe_field(field: &Field) -> String {
+    match field.metadata().get("ARROW:extension:name") {
+        Some(extension) => format!("{} ({extension})", field.data_type()),
+        None => field.data_type().to_string(),
+    }
+}
+
+/// Format the values of an array like `[1, NULL, 3]`.
+fn format_array(array: &dyn Array) -> String {
+    let options = FormatOptions::default().with_null("NULL");
+    let formatter = match ArrayFormatter::try_new(array, &options) {
+        Ok(formatter) => formatter,
+        Err(e) => return format!("<{e}>"),
+    };
+    let values: Vec<_> = (0..array.len())
+        .map(|i| formatter.value(i).to_string())
+        .collect();
+    format!("[{}]", values.join(", "))
+}
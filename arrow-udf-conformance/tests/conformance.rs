@@ -0,0 +1,432 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Field, IntervalUnit, TimeUnit};
+use arrow_udf::{function, ScalarFunction};
+use arrow_udf_conformance::{fuzz, run, run_sliced, Adapter, ScriptAdapter};
+use arrow_udf_core::{CallMode, Error, RecordBatchIter, Result, UdfRuntime};
+use expect_test::{expect, Expect};
+
+#[test]
+fn test_rust() {
+    check(
+        &mut NativeAdapter,
+        expect![[r#"
+            null: unsupported: function not found: identity
+            boolean: ok
+            int8: ok
+            int16: ok
+            int32: ok
+            int64: ok
+            uint8: ok
+            uint16: ok
+            uint32: ok
+            uint64: ok
+            float32: ok
+            float64: ok
+            decimal128: unsupported: function not found: identity
+            date32: ok
+            time64: ok
+            timestamp: ok
+            interval: ok
+            string: ok
+            large_string: ok
+            binary: ok
+            large_binary: ok
+            decimal: ok
+            json: ok
+            list: unsupported: function not found: identity
+            string_list: unsupported: function not found: identity
+            nested_list: unsupported: function not found: identity
+            struct: unsupported: function not found: identity
+        "#]],
+    );
+}
+
+#[test]
+fn test_wasm() {
+    let binary = std::fs::read("../target/wasm32-wasip1/release/arrow_udf_example.wasm")
+        .expect("failed to load wasm binary");
+    let runtime = arrow_udf_wasm::Runtime::new(&binary).unwrap();
+    check(
+        &mut WasmAdapter(runtime),
+        expect![[r#"
+            null: unsupported: function not found: identity
+            boolean: ok
+            int8: ok
+            int16: ok
+            int32: ok
+            int64: ok
+            uint8: ok
+            uint16: ok
+            uint32: ok
+            uint64: ok
+            float32: ok
+            float64: ok
+            decimal128: unsupported: function not found: identity
+            date32: ok
+            time64: ok
+            timestamp: ok
+            interval: ok
+            string: ok
+            large_string: ok
+            binary: ok
+            large_binary: ok
+            decimal: ok
+            json: ok
+            list: unsupported: function not found: identity
+            string_list: unsupported: function not found: identity
+            nested_list: unsupported: function not found: identity
+            struct: unsupported: function not found: identity
+        "#]],
+    );
+}
+
+#[test]
+fn test_js() {
+    let runtime = arrow_udf_js::Runtime::new().unwrap();
+    let mut adapter = ScriptAdapter::new(Box::new(runtime), |name| {
+        format!("export function {name}(x) {{ return x; }}")
+    });
+    check(
+        &mut adapter,
+        expect![[r#"
+            null: ok
+            boolean: ok
+            int8: ok
+            int16: ok
+            int32: ok
+            int64: ok
+            uint8: ok
+            uint16: ok
+            uint32: error: failed to build arrow array from return values
+            uint64: error: failed to build arrow array from return values
+            float32: ok
+            float64: ok
+            decimal128: expected [123.45, -0.01, NULL], actual [123.45, 0.01, NULL]
+            date32: ok
+            time64: error: failed to get jsvalue from arrow array
+            timestamp: expected [1970-01-01T00:00:00, 2023-11-14T22:13:20.123456, 1969-12-31T23:59:59.999999, NULL], actual [1970-01-01T00:00:00, 2023-11-14T22:13:20.123, 1969-12-31T23:59:59.999, NULL]
+            interval: error: failed to get jsvalue from arrow array
+            string: ok
+            large_string: ok
+            binary: ok
+            large_binary: ok
+            decimal: ok
+            json: expected [{"a":1}, [1,null], "s", null, NULL], actual [{"a":1}, [1,null], "s", NULL, NULL]
+            list: error: failed to build arrow array from return values
+            string_list: ok
+            nested_list: error: failed to build arrow array from return values
+            struct: ok
+        "#]],
+    );
+}
+
+#[test]
+fn test_python() {
+    let runtime = arrow_udf_python::Runtime::new().unwrap();
+    let mut adapter = ScriptAdapter::new(Box::new(runtime), |name| {
+        format!("def {name}(x):\n    return x\n")
+    });
+    check(
+        &mut adapter,
+        expect![[r#"
+            null: ok
+            boolean: ok
+            int8: ok
+            int16: ok
+            int32: ok
+            int64: ok
+            uint8: ok
+            uint16: ok
+            uint32: ok
+            uint64: ok
+            float32: ok
+            float64: ok
+            decimal128: ok
+            date32: ok
+            time64: ok
+            timestamp: ok
+            interval: ok
+            string: ok
+            large_string: ok
+            binary: ok
+            large_binary: ok
+            decimal: ok
+            json: expected [{"a":1}, [1,null], "s", null, NULL], actual [{"a": 1}, [1, null], "s", NULL, NULL]
+            list: ok
+            string_list: ok
+            nested_list: ok
+            struct: ok
+        "#]],
+    );
+}
+
+#[test]
+fn test_starlark() {
+    let runtime = arrow_udf_starlark::Runtime::new().unwrap();
+    let mut adapter = ScriptAdapter::new(Box::new(runtime), |name| {
+        format!("def {name}(x):\n    return x\n")
+    });
+    check(
+        &mut adapter,
+        expect![[r#"
+        null: error: failed to get starlark value from arrow array
+        boolean: ok
+        int8: ok
+        int16: ok
+        int32: ok
+        int64: ok
+        uint8: ok
+        uint16: ok
+        uint32: ok
+        uint64: ok
+        float32: ok
+        float64: ok
+        decimal128: error: failed to get starlark value from arrow array
+        date32: error: failed to get starlark value from arrow array
+        time64: error: failed to get starlark value from arrow array
+        timestamp: error: failed to get starlark value from arrow array
+        interval: error: failed to get starlark value from arrow array
+        string: ok
+        large_string: ok
+        binary: error: failed to get starlark value from arrow array
+        large_binary: error: failed to get starlark value from arrow array
+        decimal: ok
+        json: ok
+        list: ok
+        string_list: ok
+        nested_list: ok
+        struct: ok
+    "#]],
+    );
+}
+
+#[test]
+fn test_fuzz_rust() {
+    check_fuzz(&mut NativeAdapter, expect![[""]]);
+}
+
+#[test]
+fn test_fuzz_wasm() {
+    let binary = std::fs::read("../target/wasm32-wasip1/release/arrow_udf_example.wasm")
+        .expect("failed to load wasm binary");
+    let runtime = arrow_udf_wasm::Runtime::new(&binary).unwrap();
+    check_fuzz(&mut WasmAdapter(runtime), expect![[""]]);
+}
+
+#[test]
+fn test_fuzz_js() {
+    let runtime = arrow_udf_js::Runtime::new().unwrap();
+    check_fuzz(
+        &mut ScriptAdapter::new(Box::new(runtime), |name| {
+            format!("export function {name}(x) {{ return x; }}")
+        }),
+        expect![[r#"
+            int64 (seed 0) on [7346745522037291805, 668027455623031457, NULL, NULL]: expected [7346745522037291805, 668027455623031457, NULL, NULL], actual [7346745522037292032, 668027455623031424, NULL, NULL]
+        "#]],
+    );
+}
+
+#[test]
+fn test_fuzz_python() {
+    let runtime = arrow_udf_python::Runtime::new().unwrap();
+    check_fuzz(
+        &mut ScriptAdapter::new(Box::new(runtime), |name| {
+            format!("def {name}(x):\n    return x\n")
+        }),
+        expect![[""]],
+    );
+}
+
+#[test]
+fn test_fuzz_starlark() {
+    let runtime = arrow_udf_starlark::Runtime::new().unwrap();
+    check_fuzz(
+        &mut ScriptAdapter::new(Box::new(runtime), |name| {
+            format!("def {name}(x):\n    return x\n")
+        }),
+        expect![[""]],
+    );
+}
+
+/// Run the fixtures and compare the report with the snapshot, which sliced inputs must also match.
+#[track_caller]
+fn check(adapter: &mut dyn Adapter, expect: Expect) {
+    let report = run(adapter).to_string();
+    expect.assert_eq(&report);
+    assert_eq!(run_sliced(adapter).to_string(), report, "sliced input");
+}
+
+/// Fuzz the types that pass the fixtures with random arrays, and check the failures.
+fn check_fuzz(adapter: &mut dyn Adapter, expect: Expect) {
+    let failures: String = (fuzz(adapter, 0..64).iter())
+        .map(|failure| format!("{failure}\n"))
+        .collect();
+    expect.assert_eq(&failures);
+}
+
+/// Adapts the WebAssembly module of `arrow-udf-example`, which defines `identity` for each type.
+struct WasmAdapter(arrow_udf_wasm::Runtime);
+
+impl Adapter for WasmAdapter {
+    fn runtime(&self) -> &dyn UdfRuntime {
+        &self.0
+    }
+
+    fn identity(&mut self, field: &Field) -> Result<String> {
+        let ty = type_name(field)?;
+        let name = format!("identity({ty})->{ty}");
+        if !self.0.functions().any(|f| f == name) {
+            return Err(Error::FunctionNotFound(name));
+        }
+        Ok(name)
+    }
+}
+
+/// Returns the name of a type in `#[function]` signatures.
+fn type_name(field: &Field) -> Result<&'static str> {
+    let not_found = || Error::FunctionNotFound("identity".into());
+    if let Some(extension) = field.metadata().get("ARROW:extension:name") {
+        return match extension.as_str() {
+            "arrowudf.json" => Ok("json"),
+            "arrowudf.decimal" => Ok("decimal"),
+            _ => Err(not_found()),
+        };
+    }
+    Ok(match field.data_type() {
+        DataType::Boolean => "boolean",
+        DataType::Int8 => "int8",
+        DataType::Int16 => "int16",
+        DataType::Int32 => "int32",
+        DataType::Int64 => "int64",
+        DataType::UInt8 => "uint8",
+        DataType::UInt16 => "uint16",
+        DataType::UInt32 => "uint32",
+        DataType::UInt64 => "uint64",
+        DataType::Float32 => "float32",
+        DataType::Float64 => "float64",
+        DataType::Date32 => "date32",
+        DataType::Time64(TimeUnit::Microsecond) => "time64",
+        DataType::Timestamp(TimeUnit::Microsecond, None) => "timestamp",
+        DataType::Interval(IntervalUnit::MonthDayNano) => "interval",
+        DataType::Utf8 => "string",
+        DataType::LargeUtf8 => "largestring",
+        DataType::Binary => "binary",
+        DataType::LargeBinary => "largebinary",
+        _ => return Err(not_found()),
+    })
+}
+
+#[function("identity(boolean) -> boolean", output = "identity_boolean")]
+#[function("identity(int8) -> int8", output = "identity_int8")]
+#[function("identity(int16) -> int16", output = "identity_int16")]
+#[function("identity(int32) -> int32", output = "identity_int32")]
+#[function("identity(int64) -> int64", output = "identity_int64")]
+#[function("identity(uint8) -> uint8", output = "identity_uint8")]
+#[function("identity(uint16) -> uint16", output = "identity_uint16")]
+#[function("identity(uint32) -> uint32", output = "identity_uint32")]
+#[function("identity(uint64) -> uint64", output = "identity_uint64")]
+#[function("identity(float32) -> float32", output = "identity_float32")]
+#[function("identity(float64) -> float64", output = "identity_float64")]
+#[function("identity(decimal) -> decimal", output = "identity_decimal")]
+#[function("identity(date32) -> date32", output = "identity_date32")]
+#[function("identity(time64) -> time64", output = "identity_time64")]
+#[function("identity(timestamp) -> timestamp", output = "identity_timestamp")]
+#[function("identity(interval) -> interval", output = "identity_interval")]
+#[function("identity(json) -> json", output = "identity_json")]
+#[function("identity(string) -> string", output = "identity_string")]
+#[function("identity(binary) -> binary", output = "identity_binary")]
+#[function(
+    "identity(largestring) -> largestring",
+    output = "identity_largestring"
+)]
+#[function(
+    "identity(largebinary) -> largebinary",
+    output = "identity_largebinary"
+)]
+fn identity<T>(x: T) -> T {
+    x
+}
+
+/// Adapts the functions generated by `#[function]` in this file.
+struct NativeAdapter;
+
+impl Adapter for NativeAdapter {
+    fn runtime(&self) -> &dyn UdfRuntime {
+        &NativeRuntime
+    }
+
+    /// The name of the function is the name of the type.
+    fn identity(&mut self, field: &Field) -> Result<String> {
+        let ty = type_name(field)?;
+        NativeRuntime::function(ty)?;
+        Ok(ty.into())
+    }
+}
+
+/// A runtime that calls the identity functions in this file by the name of the type.
+struct NativeRuntime;
+
+impl NativeRuntime {
+    fn function(ty: &str) -> Result<ScalarFunction> {
+        Ok(match ty {
+            "boolean" => identity_boolean,
+            "int8" => identity_int8,
+            "int16" => identity_int16,
+            "int32" => identity_int32,
+            "int64" => identity_int64,
+            "uint8" => identity_uint8,
+            "uint16" => identity_uint16,
+            "uint32" => identity_uint32,
+            "uint64" => identity_uint64,
+            "float32" => identity_float32,
+            "float64" => identity_float64,
+            "decimal" => identity_decimal,
+            "date32" => identity_date32,
+            "time64" => identity_time64,
+            "timestamp" => identity_timestamp,
+            "interval" => identity_interval,
+            "json" => identity_json,
+            "string" => identity_string,
+            "binary" => identity_binary,
+            "largestring" => identity_largestring,
+            "largebinary" => identity_largebinary,
+            _ => return Err(Error::FunctionNotFound("identity".into())),
+        })
+    }
+}
+
+impl UdfRuntime for NativeRuntime {
+    fn add_function(&mut self, name: &str, _: Field, _: CallMode, _: &str) -> Result<()> {
+        Err(Error::FunctionNotFound(name.into()))
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        Ok(Self::function(name)?(input)?)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        _: &'a RecordBatch,
+        _: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        Err(Error::FunctionNotFound(name.into()))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        vec![]
+    }
+}
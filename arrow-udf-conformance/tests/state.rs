@@ -0,0 +1,179 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, Float64Array, Int32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Fields, Schema};
+use arrow_udf_core::{decode_state, Error, UdfRuntime};
+
+/// Partial states computed in JavaScript and Python are merged in Starlark.
+#[test]
+fn test_merge_across_runtimes() {
+    let js = js_runtime();
+    let python = python_runtime();
+    let starlark = starlark_runtime(state_type());
+
+    let mut states = vec![];
+    for (runtime, values) in [
+        (&js as &dyn UdfRuntime, vec![1, 2, 3]),
+        (&python, vec![4, 5]),
+    ] {
+        let state = runtime.create_state("avg").unwrap();
+        let state = runtime.accumulate("avg", &state, &input(values)).unwrap();
+        states.push(runtime.serialize_state("avg", &state).unwrap());
+    }
+
+    let (function, field, _) = decode_state(&states[0]).unwrap();
+    assert_eq!(function, "avg");
+    assert_eq!(field.data_type(), &state_type());
+
+    let states: Vec<ArrayRef> = states
+        .iter()
+        .map(|bytes| starlark.deserialize_state("avg", bytes).unwrap())
+        .collect();
+    let states: Vec<&dyn Array> = states.iter().map(|s| s.as_ref()).collect();
+    let states = arrow_select::concat::concat(&states).unwrap();
+    let state = starlark.merge("avg", &states).unwrap();
+    let output = starlark.finish("avg", &state).unwrap();
+    assert_eq!(
+        output.as_any().downcast_ref::<Float64Array>().unwrap(),
+        &Float64Array::from(vec![3.0])
+    );
+}
+
+#[test]
+fn test_state_type_mismatch() {
+    let js = js_runtime();
+    let state = js.create_state("avg").unwrap();
+    let bytes = js.serialize_state("avg", &state).unwrap();
+
+    let starlark = starlark_runtime(DataType::Int32);
+    let err = starlark.deserialize_state("avg", &bytes).unwrap_err();
+    assert!(matches!(err, Error::TypeMismatch(_)), "{err}");
+    assert_eq!(
+        err.to_string(),
+        r#"type mismatch: expect state of type Int32, got Struct([Field { name: "sum", data_type: Int32, nullable: true, dict_id: 0, dict_is_ordered: false, metadata: {} }, Field { name: "count", data_type: Int32, nullable: true, dict_id: 0, dict_is_ordered: false, metadata: {} }])"#
+    );
+
+    let err = starlark
+        .deserialize_state("avg", b"not a state")
+        .unwrap_err();
+    assert!(err.to_string().contains("failed to read state"), "{err}");
+    let err = starlark.serialize_state("count", &state).unwrap_err();
+    assert_eq!(err.to_string(), "function not found: count");
+}
+
+fn state_type() -> DataType {
+    DataType::Struct(Fields::from(vec![
+        Field::new("sum", DataType::Int32, true),
+        Field::new("count", DataType::Int32, true),
+    ]))
+}
+
+fn input(values: Vec<i32>) -> RecordBatch {
+    let schema = Schema::new(vec![Field::new("value", DataType::Int32, true)]);
+    RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int32Array::from(values))]).unwrap()
+}
+
+fn js_runtime() -> arrow_udf_js::Runtime {
+    let mut runtime = arrow_udf_js::Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "avg",
+            state_type(),
+            DataType::Float64,
+            arrow_udf_js::CallMode::ReturnNullOnNullInput,
+            r#"
+            export function create_state() {
+                return {sum: 0, count: 0};
+            }
+            export function accumulate(state, value) {
+                state.sum += value;
+                state.count += 1;
+                return state;
+            }
+            export function merge(state1, state2) {
+                state1.sum += state2.sum;
+                state1.count += state2.count;
+                return state1;
+            }
+            export function finish(state) {
+                return state.sum / state.count;
+            }
+            "#,
+        )
+        .unwrap();
+    runtime
+}
+
+fn python_runtime() -> arrow_udf_python::Runtime {
+    let mut runtime = arrow_udf_python::Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "avg",
+            state_type(),
+            DataType::Float64,
+            arrow_udf_python::CallMode::ReturnNullOnNullInput,
+            r#"
+class State:
+    def __init__(self):
+        self.sum = 0
+        self.count = 0
+
+def create_state():
+    return State()
+
+def accumulate(state, value):
+    state.sum += value
+    state.count += 1
+    return state
+
+def merge(state1, state2):
+    state1.sum += state2.sum
+    state1.count += state2.count
+    return state1
+
+def finish(state):
+    return state.sum / state.count
+"#,
+        )
+        .unwrap();
+    runtime
+}
+
+fn starlark_runtime(state_type: DataType) -> arrow_udf_starlark::Runtime {
+    let mut runtime = arrow_udf_starlark::Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "avg",
+            state_type,
+            DataType::Float64,
+            arrow_udf_starlark::CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return {"sum": 0, "count": 0}
+
+def accumulate(state, value):
+    return {"sum": state["sum"] + value, "count": state["count"] + 1}
+
+def merge(a, b):
+    return {"sum": a["sum"] + b["sum"], "count": a["count"] + b["count"]}
+
+def finish(state):
+    return state["sum"] / state["count"]
+"#,
+        )
+        .unwrap();
+    runtime
+}
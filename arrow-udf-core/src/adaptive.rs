@@ -0,0 +1,262 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Tune the number of rows in each call of a function by its latency and memory.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
+use arrow_schema::{Field, FieldRef};
+
+use crate::{
+    check_selection, slice_memory_size, CallMode, CancelToken, Capabilities, Error, FunctionInfo,
+    FunctionProperties, RecordBatchIter, Result, UdfRuntime,
+};
+
+/// A runtime that slices input batches into chunks, adapting the chunk size of each function.
+///
+/// The chunk size of a function starts at the lower bound and is adjusted after every chunk,
+/// so that a chunk takes about the target latency, and its outputs stay within the memory limit.
+/// It grows or shrinks by at most a factor of 2 each time, within the bounds.
+///
+/// Table functions and aggregate functions are called with the whole input.
+///
+/// # Example
+///
+/// ```ignore
+/// let runtime = AdaptiveRuntime::new(Box::new(arrow_udf_python::Runtime::new()?))
+///     .with_bounds(64, 8192)
+///     .with_target_latency(Duration::from_millis(10));
+/// runtime.call("gcd", &input)?;
+/// println!("{}", runtime.batch_size("gcd"));
+/// ```
+pub struct AdaptiveRuntime {
+    inner: Box<dyn UdfRuntime>,
+    min_rows: usize,
+    max_rows: usize,
+    target_latency: Duration,
+    max_output_bytes: Option<usize>,
+    batch_sizes: Mutex<HashMap<String, usize>>,
+}
+
+impl AdaptiveRuntime {
+    /// Wrap a runtime to call its functions in adaptive chunks.
+    pub fn new(inner: Box<dyn UdfRuntime>) -> Self {
+        Self {
+            inner,
+            min_rows: 64,
+            max_rows: 8192,
+            target_latency: Duration::from_millis(10),
+            max_output_bytes: None,
+            batch_sizes: Mutex::default(),
+        }
+    }
+
+    /// Set the bounds of chunk sizes in rows. Defaults to 64 and 8192.
+    pub fn with_bounds(mut self, min_rows: usize, max_rows: usize) -> Self {
+        self.min_rows = min_rows.max(1);
+        self.max_rows = max_rows.max(self.min_rows);
+        self
+    }
+
+    /// Set the target latency of each chunk. Defaults to 10ms.
+    pub fn with_target_latency(mut self, latency: Duration) -> Self {
+        self.target_latency = latency;
+        self
+    }
+
+    /// Set the maximum memory size of outputs of each chunk in bytes. Unlimited by default.
+    pub fn with_max_output_bytes(mut self, bytes: usize) -> Self {
+        self.max_output_bytes = Some(bytes);
+        self
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        self.inner.as_ref()
+    }
+
+    /// Returns the current chunk size of a function.
+    pub fn batch_size(&self, function: &str) -> usize {
+        let batch_sizes = self.batch_sizes.lock().unwrap();
+        batch_sizes.get(function).copied().unwrap_or(self.min_rows)
+    }
+
+    /// Call `f` on each chunk of the input and concatenate the outputs.
+    fn call_chunked(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        mut f: impl FnMut(&RecordBatch, usize) -> Result<RecordBatch>,
+    ) -> Result<RecordBatch> {
+        let mut outputs = vec![];
+        let mut offset = 0;
+        loop {
+            let len = self.batch_size(name).min(input.num_rows() - offset);
+            let start = Instant::now();
+            let output = f(&input.slice(offset, len), offset).map_err(|e| shift_row(e, offset))?;
+            self.adjust(name, len, start.elapsed(), slice_memory_size(&output));
+            outputs.push(output);
+            offset += len;
+            if offset == input.num_rows() {
+                break;
+            }
+        }
+        if outputs.len() == 1 {
+            return Ok(outputs.pop().unwrap());
+        }
+        let schema = outputs[0].schema();
+        Ok(arrow_select::concat::concat_batches(&schema, &outputs)?)
+    }
+
+    /// Adjust the chunk size of a function by the statistics of a chunk.
+    fn adjust(&self, name: &str, rows: usize, elapsed: Duration, output_bytes: usize) {
+        if rows == 0 {
+            return;
+        }
+        let mut batch_sizes = self.batch_sizes.lock().unwrap();
+        let size = match batch_sizes.get_mut(name) {
+            Some(size) => size,
+            None => batch_sizes.entry(name.to_string()).or_insert(self.min_rows),
+        };
+        let secs = elapsed.as_secs_f64();
+        let mut target = if secs > 0.0 {
+            (rows as f64 * self.target_latency.as_secs_f64() / secs) as usize
+        } else {
+            usize::MAX
+        };
+        if let Some(max_bytes) = self.max_output_bytes {
+            if output_bytes > 0 {
+                target =
+                    target.min((rows as f64 * max_bytes as f64 / output_bytes as f64) as usize);
+            }
+        }
+        *size = target
+            .clamp(*size / 2, size.saturating_mul(2))
+            .clamp(self.min_rows, self.max_rows);
+    }
+}
+
+impl UdfRuntime for AdaptiveRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.batch_sizes.get_mut().unwrap().remove(name);
+        self.inner.add_function(name, return_field, mode, code)
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.batch_sizes.get_mut().unwrap().remove(name);
+        self.inner
+            .add_function_with_properties(name, return_field, properties, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.call_chunked(name, input, |chunk, _| self.inner.call(name, chunk))
+    }
+
+    /// The token is also checked between chunks.
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        self.call_chunked(name, input, |chunk, _| {
+            token.check()?;
+            self.inner.call_cancellable(name, chunk, token)
+        })
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        check_selection(input, selection)?;
+        self.call_chunked(name, input, |chunk, offset| {
+            let selection = selection.slice(offset, chunk.num_rows());
+            self.inner.call_selected(name, chunk, &selection)
+        })
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        self.inner.call_table_function(name, input, chunk_size)
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.inner.functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.inner.create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.inner.accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.inner.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.inner.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+/// Shift the row of an execution error in a chunk to the row in the whole input.
+pub(crate) fn shift_row(mut e: Error, offset: usize) -> Error {
+    if let Error::Execution { row: Some(row), .. } = &mut e {
+        *row += offset;
+    }
+    e
+}
@@ -0,0 +1,175 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Arguments of a function call with scalars broadcast to all rows.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use arrow_array::{Array, ArrayRef, RecordBatch, RecordBatchOptions, UInt32Array};
+use arrow_schema::{Field, FieldRef, Schema};
+use arrow_select::take::take;
+
+use crate::{Error, Result};
+
+/// The arguments of a scalar function call.
+///
+/// Each argument is either an array with a value for each row,
+/// or a scalar that applies to all rows without building an array of the batch length.
+///
+/// # Example
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use arrow_array::{Int32Array, StringArray};
+/// # use arrow_schema::{DataType, Field};
+/// # use arrow_udf_core::Arguments;
+/// // repeat(s, 3) for each row of `s`
+/// let args = Arguments::new(2)
+///     .with_array(
+///         Field::new("s", DataType::Utf8, true),
+///         Arc::new(StringArray::from(vec!["a", "b"])),
+///     )
+///     .unwrap()
+///     .with_scalar(
+///         Field::new("n", DataType::Int32, true),
+///         Arc::new(Int32Array::from(vec![3])),
+///     )
+///     .unwrap();
+/// assert_eq!(args.materialize().unwrap().column(1).len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Arguments {
+    fields: Vec<FieldRef>,
+    columns: Vec<ArrayRef>,
+    scalars: Vec<bool>,
+    num_rows: usize,
+}
+
+impl Arguments {
+    /// Create empty arguments for the number of rows.
+    pub fn new(num_rows: usize) -> Self {
+        Self {
+            fields: vec![],
+            columns: vec![],
+            scalars: vec![],
+            num_rows,
+        }
+    }
+
+    /// Add an argument with a value for each row.
+    pub fn with_array(self, field: Field, array: ArrayRef) -> Result<Self> {
+        if array.len() != self.num_rows {
+            return Err(Error::Other(anyhow!(
+                "array length {} does not match the number of rows {}",
+                array.len(),
+                self.num_rows
+            )));
+        }
+        Ok(self.push(field, array, false))
+    }
+
+    /// Add a scalar argument, given as an array of length 1.
+    pub fn with_scalar(self, field: Field, value: ArrayRef) -> Result<Self> {
+        if value.len() != 1 {
+            return Err(Error::Other(anyhow!(
+                "scalar must have exactly one value, but got {}",
+                value.len()
+            )));
+        }
+        Ok(self.push(field, value, true))
+    }
+
+    fn push(mut self, field: Field, array: ArrayRef, scalar: bool) -> Self {
+        self.fields.push(Arc::new(field));
+        self.columns.push(array);
+        self.scalars.push(scalar);
+        self
+    }
+
+    /// Returns the number of rows.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Returns the number of arguments.
+    pub fn num_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Returns the fields of arguments.
+    pub fn fields(&self) -> &[FieldRef] {
+        &self.fields
+    }
+
+    /// Returns the arrays of arguments, where scalars have only one value.
+    pub fn columns(&self) -> &[ArrayRef] {
+        &self.columns
+    }
+
+    /// Whether the argument is a scalar.
+    pub fn is_scalar(&self, column: usize) -> bool {
+        self.scalars[column]
+    }
+
+    /// Returns the index of the value at `row` in the array of the argument.
+    pub fn index(&self, column: usize, row: usize) -> usize {
+        if self.scalars[column] {
+            0
+        } else {
+            row
+        }
+    }
+
+    /// Whether the argument is null at `row`.
+    pub fn is_null(&self, column: usize, row: usize) -> bool {
+        self.columns[column].is_null(self.index(column, row))
+    }
+
+    /// Returns the arrays of the arguments with the index of their values at `row`.
+    pub fn row_values(&self, row: usize) -> impl Iterator<Item = (&dyn Array, usize)> + '_ {
+        (self.columns.iter().enumerate())
+            .map(move |(i, column)| (column.as_ref(), self.index(i, row)))
+    }
+
+    /// Build a record batch of the arguments, repeating scalars for each row.
+    pub fn materialize(&self) -> Result<RecordBatch> {
+        let indices = UInt32Array::from(vec![0; self.num_rows]);
+        let columns = (self.columns.iter().zip(&self.scalars))
+            .map(|(column, &scalar)| {
+                if scalar {
+                    take(column, &indices, None)
+                } else {
+                    Ok(column.clone())
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let schema = Arc::new(Schema::new(self.fields.clone()));
+        let options = RecordBatchOptions::default().with_row_count(Some(self.num_rows));
+        Ok(RecordBatch::try_new_with_options(
+            schema, columns, &options,
+        )?)
+    }
+}
+
+/// All columns of the batch are arrays.
+impl From<&RecordBatch> for Arguments {
+    fn from(batch: &RecordBatch) -> Self {
+        Self {
+            fields: batch.schema().fields().to_vec(),
+            columns: batch.columns().to_vec(),
+            scalars: vec![false; batch.num_columns()],
+            num_rows: batch.num_rows(),
+        }
+    }
+}
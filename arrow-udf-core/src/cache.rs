@@ -0,0 +1,557 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use arrow_array::{Array, ArrayRef, RecordBatch, UInt32Array};
+use arrow_row::{RowConverter, SortField};
+use arrow_schema::{Field, FieldRef, Fields, Schema};
+use arrow_select::take::{take, take_record_batch};
+
+use crate::tolerant::error_column;
+use crate::{
+    CallMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionProperties, RecordBatchIter,
+    Result, UdfRuntime,
+};
+
+/// The approximate memory used by a cache entry in bytes, in addition to its input and output.
+const ENTRY_OVERHEAD: usize = 64;
+
+/// Statistics of a [`CachedRuntime`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of rows answered without calling the inner runtime.
+    pub hits: u64,
+    /// The number of rows evaluated by the inner runtime.
+    pub misses: u64,
+    /// The number of results evicted to stay within the capacity.
+    pub evictions: u64,
+    /// The number of cached results.
+    pub entries: usize,
+    /// The approximate memory used by the cached results in bytes.
+    pub memory: usize,
+}
+
+/// A runtime that caches the results of deterministic scalar functions.
+///
+/// Results are cached per row, keyed by the function name, the argument fields
+/// and the argument values encoded in the Arrow [row format](arrow_row).
+/// Only rows missing from the cache are passed to the inner runtime,
+/// and duplicate rows in a batch are evaluated once.
+/// The least recently used results are evicted once the cache exceeds its capacity.
+/// Rows failed with an error in the `error` column of outputs are returned as is, but not cached.
+///
+/// Functions are only cached once declared deterministic,
+/// or added with [`Volatility::Immutable`](crate::Volatility::Immutable).
+/// Table functions and aggregate functions are always passed to the inner runtime.
+/// Adding a function again discards its cached results.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut runtime = CachedRuntime::new(Box::new(arrow_udf_js::Runtime::new()?), 64 << 20);
+/// runtime.add_function("gcd", field, CallMode::ReturnNullOnNullInput, code)?;
+/// runtime.set_deterministic("gcd", true);
+/// runtime.call("gcd", &input)?;
+/// println!("{:?}", runtime.stats());
+/// ```
+pub struct CachedRuntime {
+    inner: Box<dyn UdfRuntime>,
+    deterministic: HashSet<String>,
+    cache: Mutex<Cache>,
+}
+
+impl CachedRuntime {
+    /// Wrap a runtime with a cache of at most `capacity` bytes.
+    pub fn new(inner: Box<dyn UdfRuntime>, capacity: usize) -> Self {
+        Self {
+            inner,
+            deterministic: HashSet::new(),
+            cache: Mutex::new(Cache::new(capacity)),
+        }
+    }
+
+    /// Declare a function as deterministic, so that its results are cached.
+    pub fn with_deterministic(mut self, name: &str) -> Self {
+        self.set_deterministic(name, true);
+        self
+    }
+
+    /// Set whether a function is deterministic.
+    ///
+    /// A deterministic function always returns the same result for the same arguments.
+    /// Declaring a function as not deterministic discards its cached results.
+    pub fn set_deterministic(&mut self, name: &str, deterministic: bool) {
+        if deterministic {
+            self.deterministic.insert(name.to_string());
+        } else {
+            self.deterministic.remove(name);
+            self.cache.lock().unwrap().remove_function(name);
+        }
+    }
+
+    /// Returns whether the results of a function are cached.
+    pub fn is_deterministic(&self, name: &str) -> bool {
+        self.deterministic.contains(name)
+    }
+
+    /// Returns the statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+
+    /// Discard all cached results.
+    pub fn clear(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        let capacity = cache.capacity;
+        let stats = cache.stats;
+        *cache = Cache::new(capacity);
+        cache.stats = CacheStats {
+            entries: 0,
+            memory: 0,
+            ..stats
+        };
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        self.inner.as_ref()
+    }
+
+    /// Evaluate the rows missing from the cache with `call` and merge them with the cached results.
+    fn call_cached(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        call: impl FnOnce(&RecordBatch) -> Result<RecordBatch>,
+    ) -> Result<RecordBatch> {
+        if !self.deterministic.contains(name) || input.num_rows() == 0 || input.num_columns() == 0 {
+            return call(input);
+        }
+        let schema = input.schema();
+        let sort_fields = (schema.fields().iter())
+            .map(|field| SortField::new(field.data_type().clone()))
+            .collect();
+        let Ok(converter) = RowConverter::new(sort_fields) else {
+            return call(input);
+        };
+        let rows = converter.convert_columns(input.columns())?;
+        let key = PartitionKey::new(name, &schema);
+
+        // the partition is unknown until the first call returns the output field
+        let mut cache = self.cache.lock().unwrap();
+        let (codec, cached) = match cache.index.get(&key) {
+            Some(&id) => match cache.partitions[&id].codec.clone() {
+                Some(codec) => {
+                    let cached: Vec<_> = (0..rows.num_rows())
+                        .map(|i| cache.get(id, rows.row(i).data()))
+                        .collect();
+                    (Some(codec), cached)
+                }
+                None => {
+                    drop(cache);
+                    return call(input);
+                }
+            },
+            None => (None, vec![None; rows.num_rows()]),
+        };
+        drop(cache);
+
+        // evaluate each distinct missing row once
+        let mut distinct = HashMap::<&[u8], u32>::new();
+        let mut indices = vec![];
+        let mut slots = vec![0; rows.num_rows()];
+        for (i, value) in cached.iter().enumerate() {
+            if value.is_none() {
+                let next = indices.len() as u32;
+                slots[i] = *distinct.entry(rows.row(i).data()).or_insert_with(|| {
+                    indices.push(i as u32);
+                    next
+                });
+            }
+        }
+        self.record(rows.num_rows() - indices.len(), indices.len());
+        if indices.is_empty() {
+            let codec = codec.unwrap();
+            let parser = codec.converter.parser();
+            let values = (cached.iter().flatten()).map(|value| parser.parse(value));
+            let columns = codec.converter.convert_rows(values)?;
+            let schema = Arc::new(Schema::new(codec.fields.clone()));
+            return Ok(RecordBatch::try_new(schema, columns)?);
+        }
+
+        let missing = if indices.len() == rows.num_rows() {
+            input.clone()
+        } else {
+            take_record_batch(input, &UInt32Array::from(indices.clone()))?
+        };
+        let output = call(&missing).map_err(|e| remap_row(e, &indices))?;
+        if output.num_rows() != indices.len() {
+            return Err(Error::Other(anyhow!(
+                "expect {} rows from function {name}, got {}",
+                indices.len(),
+                output.num_rows()
+            )));
+        }
+        // errors of rows are passed through, and their rows are not cached
+        let errors = error_column(&output);
+        let num_values = output.num_columns() - errors.is_some() as usize;
+        let first = codec.is_none();
+        let codec = match codec {
+            Some(codec) => Some(codec),
+            None => {
+                let fields = Fields::from(&output.schema().fields()[..num_values]);
+                let sort_fields = (fields.iter())
+                    .map(|field| SortField::new(field.data_type().clone()))
+                    .collect();
+                RowConverter::new(sort_fields)
+                    .ok()
+                    .map(|converter| Arc::new(Codec { fields, converter }))
+            }
+        };
+        let output_rows = match &codec {
+            Some(codec) => {
+                let values = codec.cast(&output.columns()[..num_values])?;
+                Some(codec.converter.convert_columns(&values)?)
+            }
+            None => None,
+        };
+        let mut cache = self.cache.lock().unwrap();
+        let id = cache.partition(key, codec.clone());
+        if let Some(output_rows) = &output_rows {
+            for (j, &i) in indices.iter().enumerate() {
+                if errors.is_some_and(|errors| errors.is_valid(j)) {
+                    continue;
+                }
+                cache.insert(
+                    id,
+                    rows.row(i as usize).data().into(),
+                    output_rows.row(j).data().into(),
+                );
+            }
+        }
+        drop(cache);
+        if indices.len() == rows.num_rows() {
+            return Ok(output);
+        }
+        if first {
+            // all rows are evaluated, some of them only once
+            return Ok(take_record_batch(&output, &UInt32Array::from(slots))?);
+        }
+
+        let (codec, output_rows) = (codec.unwrap(), output_rows.unwrap());
+        let parser = codec.converter.parser();
+        let values = cached.iter().enumerate().map(|(i, value)| match value {
+            Some(value) => parser.parse(value),
+            None => output_rows.row(slots[i] as usize),
+        });
+        let mut columns = codec.converter.convert_rows(values)?;
+        let mut fields = codec.fields.to_vec();
+        if let Some(errors) = errors {
+            // cached rows have no errors
+            let indices: UInt32Array = (cached.iter().enumerate())
+                .map(|(i, value)| value.is_none().then_some(slots[i]))
+                .collect();
+            columns.push(take(errors, &indices, None)?);
+            fields.push(output.schema().fields()[num_values].clone());
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    /// Record the number of rows hit and missed.
+    fn record(&self, hits: usize, misses: usize) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.stats.hits += hits as u64;
+        cache.stats.misses += misses as u64;
+    }
+}
+
+impl UdfRuntime for CachedRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.inner.add_function(name, return_field, mode, code)?;
+        self.cache.lock().unwrap().remove_function(name);
+        Ok(())
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function_with_properties(name, return_field, properties, code)?;
+        self.cache.lock().unwrap().remove_function(name);
+        if properties.is_deterministic() {
+            self.deterministic.insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.call_cached(name, input, |input| self.inner.call(name, input))
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        token.check()?;
+        self.call_cached(name, input, |input| {
+            self.inner.call_cancellable(name, input, token)
+        })
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        self.inner.call_table_function(name, input, chunk_size)
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.inner.functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.inner.create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.inner.accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.inner.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.inner.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+/// Map the row of an execution error from the evaluated rows to the input rows.
+fn remap_row(mut e: Error, indices: &[u32]) -> Error {
+    if let Error::Execution { row: Some(row), .. } = &mut e {
+        if let Some(&index) = indices.get(*row) {
+            *row = index as usize;
+        }
+    }
+    e
+}
+
+/// Identifies the results of a function with the argument fields.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PartitionKey {
+    name: String,
+    /// The argument fields without names, as the metadata may decide the extension type.
+    fields: Vec<Field>,
+}
+
+impl PartitionKey {
+    fn new(name: &str, schema: &Schema) -> Self {
+        Self {
+            name: name.to_string(),
+            fields: (schema.fields().iter())
+                .map(|field| Field::clone(field).with_name(""))
+                .collect(),
+        }
+    }
+}
+
+/// Converts results of a function between arrays and rows.
+struct Codec {
+    /// The fields of the value columns, excluding the error column.
+    fields: Fields,
+    converter: RowConverter,
+}
+
+impl Codec {
+    /// Cast the value columns of an output to the types of the cached results,
+    /// which may differ if strings are promoted to their large types.
+    fn cast(&self, columns: &[ArrayRef]) -> Result<Vec<ArrayRef>> {
+        if columns.len() != self.fields.len() {
+            return Err(Error::Other(anyhow!(
+                "expect {} value columns, got {}",
+                self.fields.len(),
+                columns.len()
+            )));
+        }
+        (columns.iter().zip(self.fields.iter()))
+            .map(
+                |(column, field)| match column.data_type() == field.data_type() {
+                    true => Ok(column.clone()),
+                    false => Ok(arrow_cast::cast(column, field.data_type())?),
+                },
+            )
+            .collect()
+    }
+}
+
+/// The cached results of a function with the argument fields.
+struct Partition {
+    name: String,
+    /// `None` if the output type is not supported by the row format.
+    codec: Option<Arc<Codec>>,
+    entries: HashMap<Box<[u8]>, Entry>,
+}
+
+struct Entry {
+    value: Arc<[u8]>,
+    /// The time of last use, which is also the key in [`Cache::lru`].
+    tick: u64,
+    size: usize,
+}
+
+/// The cached results of all functions.
+struct Cache {
+    capacity: usize,
+    index: HashMap<PartitionKey, u64>,
+    partitions: HashMap<u64, Partition>,
+    next_partition: u64,
+    /// The partition and input of entries in the order of last use.
+    lru: BTreeMap<u64, (u64, Box<[u8]>)>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            index: HashMap::new(),
+            partitions: HashMap::new(),
+            next_partition: 0,
+            lru: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the partition of the key, creating it if not exists.
+    fn partition(&mut self, key: PartitionKey, codec: Option<Arc<Codec>>) -> u64 {
+        if let Some(&id) = self.index.get(&key) {
+            return id;
+        }
+        let id = self.next_partition;
+        self.next_partition += 1;
+        self.partitions.insert(
+            id,
+            Partition {
+                name: key.name.clone(),
+                codec,
+                entries: HashMap::new(),
+            },
+        );
+        self.index.insert(key, id);
+        id
+    }
+
+    /// Get the cached result of an input and mark it as recently used.
+    fn get(&mut self, id: u64, input: &[u8]) -> Option<Arc<[u8]>> {
+        self.tick += 1;
+        let entry = self.partitions.get_mut(&id)?.entries.get_mut(input)?;
+        let lru_entry = self.lru.remove(&entry.tick).unwrap();
+        entry.tick = self.tick;
+        self.lru.insert(self.tick, lru_entry);
+        Some(entry.value.clone())
+    }
+
+    /// Insert a result, evicting the least recently used ones if the capacity is exceeded.
+    fn insert(&mut self, id: u64, input: Box<[u8]>, value: Arc<[u8]>) {
+        let size = input.len() * 2 + value.len() + ENTRY_OVERHEAD;
+        if size > self.capacity {
+            return;
+        }
+        let Some(partition) = self.partitions.get_mut(&id) else {
+            return;
+        };
+        self.tick += 1;
+        let entry = Entry {
+            value,
+            tick: self.tick,
+            size,
+        };
+        if let Some(old) = partition.entries.insert(input.clone(), entry) {
+            self.lru.remove(&old.tick);
+            self.stats.entries -= 1;
+            self.stats.memory -= old.size;
+        }
+        self.lru.insert(self.tick, (id, input));
+        self.stats.entries += 1;
+        self.stats.memory += size;
+        while self.stats.memory > self.capacity {
+            let (_, (id, input)) = self.lru.pop_first().unwrap();
+            let entry = (self.partitions.get_mut(&id).unwrap().entries)
+                .remove(&input)
+                .unwrap();
+            self.stats.entries -= 1;
+            self.stats.memory -= entry.size;
+            self.stats.evictions += 1;
+        }
+    }
+
+    /// Remove all results of a function.
+    fn remove_function(&mut self, name: &str) {
+        self.index.retain(|key, _| key.name != name);
+        let ids: Vec<_> = (self.partitions.iter())
+            .filter(|(_, partition)| partition.name == name)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            let partition = self.partitions.remove(&id).unwrap();
+            for entry in partition.entries.into_values() {
+                self.lru.remove(&entry.tick);
+                self.stats.entries -= 1;
+                self.stats.memory -= entry.size;
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Cancellation of running function calls.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::{Error, Result};
+
+/// A token to cancel function calls, e.g. when the query calling them is cancelled.
+///
+/// Clones of a token share the same state, so one clone can be passed to the calls
+/// and another one kept to cancel them from a different thread.
+/// Once cancelled, a token stays cancelled.
+///
+/// Runtimes interrupt the running function with their own mechanism, registered
+/// with [`CancelToken::on_cancel`] for the duration of a call.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    callbacks: Mutex<Callbacks>,
+}
+
+#[derive(Default)]
+struct Callbacks {
+    next_id: u64,
+    map: BTreeMap<u64, Box<dyn FnOnce() + Send>>,
+}
+
+impl Debug for CancelToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl CancelToken {
+    /// Create a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the calls using this token.
+    ///
+    /// Callbacks registered by [`CancelToken::on_cancel`] are run in the current thread.
+    pub fn cancel(&self) {
+        let mut callbacks = self.inner.callbacks.lock().unwrap();
+        if self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // the lock is held until all callbacks have returned, see `CancelGuard`
+        for (_, callback) in std::mem::take(&mut callbacks.map) {
+            callback();
+        }
+    }
+
+    /// Returns true if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns [`Error::Cancelled`] if the token has been cancelled.
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(Error::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Register a callback to run when the token is cancelled.
+    ///
+    /// The callback is run immediately if the token is already cancelled.
+    /// It is unregistered when the returned guard is dropped.
+    pub fn on_cancel(&self, callback: impl FnOnce() + Send + 'static) -> CancelGuard {
+        let mut callbacks = self.inner.callbacks.lock().unwrap();
+        if self.is_cancelled() {
+            drop(callbacks);
+            callback();
+            return CancelGuard { registered: None };
+        }
+        let id = callbacks.next_id;
+        callbacks.next_id += 1;
+        callbacks.map.insert(id, Box::new(callback));
+        CancelGuard {
+            registered: Some((self.inner.clone(), id)),
+        }
+    }
+}
+
+/// A guard that unregisters the callback of [`CancelToken::on_cancel`] when dropped.
+///
+/// If the callback is running in another thread, dropping the guard waits for it to return,
+/// so that the callback may safely refer to resources released after the guard.
+#[must_use = "the callback is unregistered when the guard is dropped"]
+pub struct CancelGuard {
+    registered: Option<(Arc<Inner>, u64)>,
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some((inner, id)) = self.registered.take() {
+            inner.callbacks.lock().unwrap().map.remove(&id);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Features of runtimes that engines detect at registration time.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use arrow_schema::DataType;
+
+use crate::properties::impl_names;
+use crate::{Error, Result};
+
+/// The metadata key of [`Capabilities::data_types`] in [`Capabilities::to_metadata`].
+pub const DATA_TYPES_KEY: &str = "arrowudf.data_types";
+/// The metadata key of [`Capabilities::function_kinds`] in [`Capabilities::to_metadata`].
+pub const FUNCTION_KINDS_KEY: &str = "arrowudf.function_kinds";
+/// The metadata key of [`Capabilities::batch_modes`] in [`Capabilities::to_metadata`].
+pub const BATCH_MODES_KEY: &str = "arrowudf.batch_modes";
+/// The metadata key of [`Capabilities::resource_limits`] in [`Capabilities::to_metadata`].
+pub const RESOURCE_LIMITS_KEY: &str = "arrowudf.resource_limits";
+
+/// A kind of functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FunctionKind {
+    /// Scalar functions, returning one value for each row.
+    Scalar,
+    /// Table functions, returning any number of rows for each row.
+    Table,
+    /// Aggregate functions, returning one value for a group of rows.
+    Aggregate,
+    /// Window functions, returning one value for each row of a partition from the rows in its frame.
+    Window,
+}
+
+/// A way to call scalar functions that a runtime implements natively,
+/// instead of by the default methods of [`UdfRuntime`](crate::UdfRuntime).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BatchMode {
+    /// Scalar arguments are read for each row without being materialized, see `call_broadcast`.
+    Broadcast,
+    /// Rows that are not selected are skipped without being converted, see `call_selected`.
+    Selected,
+    /// Running calls are interrupted once cancelled, see `call_cancellable`.
+    Cancellable,
+}
+
+/// A limit on the resources used by functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ResourceLimit {
+    /// The memory used by the interpreter.
+    Memory,
+    /// The wall time of each call.
+    Timeout,
+    /// The instructions executed by each call, e.g. fuel of WebAssembly.
+    Instructions,
+}
+
+/// The features supported by a runtime, returned by [`UdfRuntime::capabilities`].
+///
+/// Engines check them when a function is registered, instead of failing at its first call
+/// on runtimes that don't support a feature.
+///
+/// # Example
+///
+/// ```
+/// use arrow_schema::{DataType, Field};
+/// use arrow_udf_core::{Capabilities, FunctionKind};
+///
+/// let capabilities = Capabilities::new()
+///     .with_data_types(["Int32", "Utf8", "List"])
+///     .with_function_kinds([FunctionKind::Scalar]);
+/// assert!(capabilities.supports_type(&DataType::new_list(DataType::Utf8, true)));
+/// assert!(!capabilities.supports_type(&DataType::Float64));
+/// assert!(!capabilities.supports(FunctionKind::Table));
+/// ```
+///
+/// [`UdfRuntime::capabilities`]: crate::UdfRuntime::capabilities
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// The names of supported data types as returned by [`type_name`], or `None` if unknown.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub data_types: Option<BTreeSet<String>>,
+    /// The kinds of functions that can be called.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub function_kinds: BTreeSet<FunctionKind>,
+    /// The ways to call scalar functions that are implemented natively.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub batch_modes: BTreeSet<BatchMode>,
+    /// The limits on resources that can be set.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resource_limits: BTreeSet<ResourceLimit>,
+}
+
+impl Capabilities {
+    /// Create capabilities without any feature and with unknown data types.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the names of supported data types as returned by [`type_name`].
+    pub fn with_data_types<'a>(mut self, data_types: impl IntoIterator<Item = &'a str>) -> Self {
+        self.data_types = Some(data_types.into_iter().map(String::from).collect());
+        self
+    }
+
+    /// Add kinds of functions that can be called.
+    pub fn with_function_kinds(mut self, kinds: impl IntoIterator<Item = FunctionKind>) -> Self {
+        self.function_kinds.extend(kinds);
+        self
+    }
+
+    /// Add ways to call scalar functions that are implemented natively.
+    pub fn with_batch_modes(mut self, modes: impl IntoIterator<Item = BatchMode>) -> Self {
+        self.batch_modes.extend(modes);
+        self
+    }
+
+    /// Add limits on resources that can be set.
+    pub fn with_resource_limits(mut self, limits: impl IntoIterator<Item = ResourceLimit>) -> Self {
+        self.resource_limits.extend(limits);
+        self
+    }
+
+    /// Returns true if functions of the kind can be called.
+    pub fn supports(&self, kind: FunctionKind) -> bool {
+        self.function_kinds.contains(&kind)
+    }
+
+    /// Returns true if the type and all types nested in it are supported,
+    /// or if the supported types are unknown.
+    pub fn supports_type(&self, data_type: &DataType) -> bool {
+        let Some(data_types) = &self.data_types else {
+            return true;
+        };
+        if !data_types.contains(&type_name(data_type)) {
+            return false;
+        }
+        match data_type {
+            DataType::List(field)
+            | DataType::LargeList(field)
+            | DataType::ListView(field)
+            | DataType::LargeListView(field)
+            | DataType::FixedSizeList(field, _)
+            | DataType::Map(field, _) => self.supports_type(field.data_type()),
+            DataType::Struct(fields) => fields.iter().all(|f| self.supports_type(f.data_type())),
+            _ => true,
+        }
+    }
+
+    /// Encode the capabilities as key-value pairs, e.g. in the schema metadata of a handshake.
+    ///
+    /// Each set is encoded as a comma separated list of names.
+    pub fn to_metadata(&self) -> HashMap<String, String> {
+        fn join<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+            let items: Vec<_> = items.into_iter().map(|item| item.to_string()).collect();
+            items.join(",")
+        }
+        let mut metadata = HashMap::from([
+            (FUNCTION_KINDS_KEY.to_string(), join(&self.function_kinds)),
+            (BATCH_MODES_KEY.to_string(), join(&self.batch_modes)),
+            (RESOURCE_LIMITS_KEY.to_string(), join(&self.resource_limits)),
+        ]);
+        if let Some(data_types) = &self.data_types {
+            metadata.insert(DATA_TYPES_KEY.to_string(), join(data_types));
+        }
+        metadata
+    }
+
+    /// Decode the capabilities from key-value pairs.
+    ///
+    /// Missing sets are empty, and data types are unknown if missing.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Self> {
+        fn split<T: FromStr<Err = Error> + Ord>(
+            metadata: &HashMap<String, String>,
+            key: &str,
+        ) -> Result<BTreeSet<T>> {
+            match metadata.get(key) {
+                Some(value) => (value.split(',').filter(|s| !s.is_empty()))
+                    .map(|s| s.parse())
+                    .collect(),
+                None => Ok(BTreeSet::new()),
+            }
+        }
+        Ok(Self {
+            data_types: (metadata.get(DATA_TYPES_KEY)).map(|value| {
+                (value.split(',').filter(|s| !s.is_empty()))
+                    .map(String::from)
+                    .collect()
+            }),
+            function_kinds: split(metadata, FUNCTION_KINDS_KEY)?,
+            batch_modes: split(metadata, BATCH_MODES_KEY)?,
+            resource_limits: split(metadata, RESOURCE_LIMITS_KEY)?,
+        })
+    }
+}
+
+/// Returns the name of a data type without its parameters, e.g. `Timestamp` for `Timestamp(Second, None)`.
+///
+/// Nested types are named by their outer types, e.g. `List` for a list of integers.
+pub fn type_name(data_type: &DataType) -> String {
+    let name = format!("{data_type:?}");
+    match name.split_once('(') {
+        Some((name, _)) => name.to_string(),
+        None => name,
+    }
+}
+
+impl_names!(FunctionKind, "function kind" {
+    Scalar => "scalar",
+    Table => "table",
+    Aggregate => "aggregate",
+    Window => "window",
+});
+impl_names!(BatchMode, "batch mode" {
+    Broadcast => "broadcast",
+    Selected => "selected",
+    Cancellable => "cancellable",
+});
+impl_names!(ResourceLimit, "resource limit" {
+    Memory => "memory",
+    Timeout => "timeout",
+    Instructions => "instructions",
+});
@@ -0,0 +1,107 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// A specialized `Result` type for UDF runtimes.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The error type of [`UdfRuntime`](crate::UdfRuntime).
+///
+/// The variants distinguish errors caused by user code or input from errors of the system,
+/// so that engines can decide how to report them without matching messages.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// The function is not defined in the runtime.
+    #[error("function not found: {0}")]
+    FunctionNotFound(String),
+
+    /// The type of an argument or return value does not match what the function expects.
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+
+    /// The function raised an error.
+    #[error("{}", display_execution(*.row, .message))]
+    Execution {
+        /// The index of the input row being evaluated, if known.
+        row: Option<usize>,
+        /// The error message.
+        message: String,
+        /// The stack trace in the language of the function, if available.
+        traceback: Option<String>,
+    },
+
+    /// The function exceeded a resource limit, e.g. timeout, memory or fuel.
+    #[error("resource exceeded: {0}")]
+    ResourceExceeded(String),
+
+    /// The call was cancelled.
+    #[error("cancelled")]
+    Cancelled,
+
+    /// Other errors of the runtime.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// Create an execution error without row and traceback.
+    pub fn execution(message: impl Into<String>) -> Self {
+        Self::Execution {
+            row: None,
+            message: message.into(),
+            traceback: None,
+        }
+    }
+
+    /// Set the row of an execution error if it is unknown.
+    pub fn with_row(mut self, index: usize) -> Self {
+        if let Self::Execution { row, .. } = &mut self {
+            row.get_or_insert(index);
+        }
+        self
+    }
+
+    /// Whether the error is caused by user code or input rather than the system.
+    ///
+    /// Cancellation and exceeded resource limits are decided by the engine or operator,
+    /// so they are not user errors.
+    pub fn is_user_error(&self) -> bool {
+        match self {
+            Self::FunctionNotFound(_) | Self::TypeMismatch(_) | Self::Execution { .. } => true,
+            Self::ResourceExceeded(_) | Self::Cancelled | Self::Other(_) => false,
+        }
+    }
+}
+
+/// Recover the structured error raised inside a runtime, or wrap others as [`Error::Other`].
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<Error>() {
+            Ok(e) => e,
+            Err(e) => Self::Other(e),
+        }
+    }
+}
+
+impl From<arrow_schema::ArrowError> for Error {
+    fn from(e: arrow_schema::ArrowError) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+fn display_execution(row: Option<usize>, message: &str) -> String {
+    match row {
+        Some(row) => format!("error at row {row}: {message}"),
+        None => message.to_string(),
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluate functions over a stream of batches in parallel.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::{ArrowError, Schema};
+use rayon::prelude::*;
+
+use crate::{Pipeline, Result, UdfRuntime};
+
+/// Evaluate a [`Pipeline`] over batches from a reader, e.g. of Parquet files, for backfills and ETL jobs.
+///
+/// Up to `parallelism` batches are evaluated concurrently on the rayon thread pool,
+/// and the augmented batches, the input columns followed by the outputs of the pipeline,
+/// are yielded in the order of the input.
+/// Pass a [`RuntimePool`](crate::RuntimePool) to spread the calls over multiple runtimes.
+///
+/// # Example
+///
+/// ```ignore
+/// let pool = RuntimePool::from_fn(4, || Ok(Box::new(arrow_udf_python::Runtime::new()?)))?;
+/// let pipeline = Pipeline::new().with_output("score", "score(title, body)".parse()?);
+/// let evaluator = ParallelEvaluator::new(&pool, pipeline).with_parallelism(4);
+///
+/// let reader = ParquetRecordBatchReaderBuilder::try_new(File::open("in.parquet")?)?.build()?;
+/// let mut writer = None;
+/// for batch in evaluator.evaluate_all(reader) {
+///     let batch = batch?;
+///     let writer = writer.get_or_insert_with(|| {
+///         ArrowWriter::try_new(File::create("out.parquet").unwrap(), batch.schema(), None).unwrap()
+///     });
+///     writer.write(&batch)?;
+/// }
+/// ```
+pub struct ParallelEvaluator<'a> {
+    runtime: &'a dyn UdfRuntime,
+    pipeline: Pipeline,
+    parallelism: usize,
+    keep_input: bool,
+}
+
+impl<'a> ParallelEvaluator<'a> {
+    /// Create an evaluator of the pipeline, evaluating batches one by one.
+    pub fn new(runtime: &'a dyn UdfRuntime, pipeline: Pipeline) -> Self {
+        Self {
+            runtime,
+            pipeline,
+            parallelism: 1,
+            keep_input: true,
+        }
+    }
+
+    /// Set the number of batches evaluated concurrently, at least 1.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Set whether the input columns are kept before the outputs. Enabled by default.
+    pub fn with_input_columns(mut self, keep: bool) -> Self {
+        self.keep_input = keep;
+        self
+    }
+
+    /// Evaluate the pipeline over a batch and return the augmented batch.
+    pub fn evaluate(&self, input: &RecordBatch) -> Result<RecordBatch> {
+        let output = self.pipeline.evaluate(self.runtime, input)?;
+        if !self.keep_input {
+            return Ok(output);
+        }
+        let input_schema = input.schema();
+        let output_schema = output.schema();
+        let fields = (input_schema.fields().iter())
+            .chain(output_schema.fields())
+            .cloned()
+            .collect::<Vec<_>>();
+        let columns = (input.columns().iter())
+            .chain(output.columns())
+            .cloned()
+            .collect();
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    /// Evaluate the pipeline over all batches of a reader, yielding the augmented batches in order.
+    ///
+    /// The iteration stops after the first error.
+    pub fn evaluate_all<I>(&'a self, reader: I) -> impl Iterator<Item = Result<RecordBatch>> + 'a
+    where
+        I: IntoIterator<Item = std::result::Result<RecordBatch, ArrowError>>,
+        I::IntoIter: 'a,
+    {
+        let mut reader = reader.into_iter();
+        let mut ready = VecDeque::new();
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if ready.is_empty() && !failed {
+                let inputs: Vec<_> = reader.by_ref().take(self.parallelism).collect();
+                let outputs: Vec<_> = inputs
+                    .into_par_iter()
+                    .map(|input| self.evaluate(&input?))
+                    .collect();
+                ready.extend(outputs);
+            }
+            let next = ready.pop_front()?;
+            if next.is_err() {
+                failed = true;
+                ready.clear();
+            }
+            Some(next)
+        })
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Descriptions of the functions registered in runtimes.
+
+use std::sync::Arc;
+
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, FieldRef, Schema};
+
+use crate::{CallMode, FunctionKind, Result, Volatility};
+
+/// The description of a function, returned by [`UdfRuntime::functions`].
+///
+/// Runtimes fill in what they know about the function. The others are `None`.
+///
+/// # Example
+///
+/// ```
+/// use arrow_schema::{DataType, Field};
+/// use arrow_udf_core::{functions_to_batch, CallMode, FunctionInfo, FunctionKind, Volatility};
+///
+/// let function = FunctionInfo::new("gcd")
+///     .with_kind(FunctionKind::Scalar)
+///     .with_arg_types(vec![DataType::Int32, DataType::Int32])
+///     .with_return_field(Field::new("gcd", DataType::Int32, true))
+///     .with_mode(CallMode::ReturnNullOnNullInput)
+///     .with_volatility(Volatility::Immutable);
+/// let batch = functions_to_batch(&[function]).unwrap();
+/// assert_eq!(batch.num_rows(), 1);
+/// ```
+///
+/// [`UdfRuntime::functions`]: crate::UdfRuntime::functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionInfo {
+    /// The name of the function.
+    pub name: String,
+    /// The kind of the function, or `None` if it can be called as a scalar or table function.
+    pub kind: Option<FunctionKind>,
+    /// The types of arguments, or `None` if unknown.
+    pub arg_types: Option<Vec<DataType>>,
+    /// The field of the return value, or `None` if unknown.
+    ///
+    /// This is the field of the aggregate value for aggregate functions.
+    pub return_field: Option<FieldRef>,
+    /// Whether the function will be called when some of its arguments are null, or `None` if unknown.
+    pub mode: Option<CallMode>,
+    /// Whether the function returns the same result for the same arguments, or `None` if unknown.
+    pub volatility: Option<Volatility>,
+}
+
+impl FunctionInfo {
+    /// Create the description of a function with only its name.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            kind: None,
+            arg_types: None,
+            return_field: None,
+            mode: None,
+            volatility: None,
+        }
+    }
+
+    /// Set the kind of the function.
+    pub fn with_kind(mut self, kind: FunctionKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Set the types of arguments.
+    pub fn with_arg_types(mut self, arg_types: Vec<DataType>) -> Self {
+        self.arg_types = Some(arg_types);
+        self
+    }
+
+    /// Set the field of the return value.
+    pub fn with_return_field(mut self, return_field: impl Into<FieldRef>) -> Self {
+        self.return_field = Some(return_field.into());
+        self
+    }
+
+    /// Set the call mode.
+    pub fn with_mode(mut self, mode: CallMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the volatility.
+    pub fn with_volatility(mut self, volatility: Volatility) -> Self {
+        self.volatility = Some(volatility);
+        self
+    }
+}
+
+/// Returns the schema of the batches returned by [`functions_to_batch`].
+///
+/// The columns are `name`, `kind`, `arg_types` as a list, `return_type`, `mode` and `volatility`.
+/// Types and other values are their names, and unknown values are null.
+pub fn functions_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("kind", DataType::Utf8, true),
+        Field::new_list("arg_types", Field::new("item", DataType::Utf8, true), true),
+        Field::new("return_type", DataType::Utf8, true),
+        Field::new("mode", DataType::Utf8, true),
+        Field::new("volatility", DataType::Utf8, true),
+    ])
+}
+
+/// Convert descriptions of functions to a batch with a row for each function,
+/// e.g. for engines to list loaded functions in a system table.
+///
+/// See [`functions_schema`] for the columns.
+pub fn functions_to_batch(functions: &[FunctionInfo]) -> Result<RecordBatch> {
+    let names: StringArray = functions.iter().map(|f| Some(&f.name)).collect();
+    let kinds: StringArray = (functions.iter())
+        .map(|f| f.kind.map(|kind| kind.to_string()))
+        .collect();
+    let mut arg_types = ListBuilder::new(StringBuilder::new());
+    for function in functions {
+        match &function.arg_types {
+            Some(types) => arg_types.append_value(types.iter().map(|t| Some(t.to_string()))),
+            None => arg_types.append_null(),
+        }
+    }
+    let return_types: StringArray = (functions.iter())
+        .map(|f| (f.return_field.as_ref()).map(|field| field.data_type().to_string()))
+        .collect();
+    let modes: StringArray = (functions.iter())
+        .map(|f| f.mode.map(|mode| mode.to_string()))
+        .collect();
+    let volatilities: StringArray = (functions.iter())
+        .map(|f| f.volatility.map(|volatility| volatility.to_string()))
+        .collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(names),
+        Arc::new(kinds),
+        Arc::new(arg_types.finish()),
+        Arc::new(return_types),
+        Arc::new(modes),
+        Arc::new(volatilities),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(functions_schema()), columns)?)
+}
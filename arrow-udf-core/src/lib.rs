@@ -0,0 +1,361 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use arrow_array::{Array, ArrayRef, BooleanArray, Datum, RecordBatch, Scalar, UInt32Array};
+use arrow_schema::{Field, FieldRef, Schema};
+use arrow_select::filter::filter_record_batch;
+use arrow_select::take::take;
+
+pub use self::adaptive::AdaptiveRuntime;
+pub use self::arguments::Arguments;
+pub use self::cache::{CacheStats, CachedRuntime};
+pub use self::cancel::{CancelGuard, CancelToken};
+pub use self::capabilities::{
+    type_name, BatchMode, Capabilities, FunctionKind, ResourceLimit, BATCH_MODES_KEY,
+    DATA_TYPES_KEY, FUNCTION_KINDS_KEY, RESOURCE_LIMITS_KEY,
+};
+pub use self::error::{Error, Result};
+pub use self::evaluator::ParallelEvaluator;
+pub use self::info::{functions_schema, functions_to_batch, FunctionInfo};
+#[cfg(feature = "tracing")]
+pub use self::log::TracingLogSink;
+pub use self::log::{LogLevel, UdfLogSink};
+pub use self::memory::{AccountedRuntime, GreedyMemoryPool, MemoryPool, MemoryReservation};
+#[cfg(feature = "metrics")]
+pub use self::metrics::GlobalMetrics;
+pub use self::metrics::{
+    metrics_schema, CallStats, FunctionMetrics, MeteredRuntime, Metrics, MetricsRegistry,
+};
+pub use self::nulls::{has_nested_null, NestedNullPolicy};
+pub use self::pipeline::{Expr, Pipeline};
+pub use self::pool::{concat_outputs, partition_rows, unify_outputs, RuntimePool};
+pub use self::properties::{
+    FunctionProperties, ParallelSafety, StrictArguments, Volatility, CALL_MODE_KEY,
+    PARALLEL_SAFETY_KEY, VOLATILITY_KEY,
+};
+pub use self::quota::{QuotaManager, QuotaPermit, QuotaRuntime};
+pub use self::signature::{check_arguments, check_output, is_implicit_cast, CheckedRuntime};
+pub use self::state::{decode_state, encode_state, STATE_VERSION};
+pub use self::stream::{AsyncTableFunction, RecordBatchStream, StreamingRuntime};
+pub use self::tolerant::TolerantRuntime;
+
+mod adaptive;
+mod arguments;
+mod cache;
+mod cancel;
+mod capabilities;
+mod error;
+mod evaluator;
+mod info;
+mod log;
+mod memory;
+mod metrics;
+mod nulls;
+mod pipeline;
+mod pool;
+mod properties;
+mod quota;
+mod signature;
+mod state;
+mod stream;
+mod tolerant;
+
+/// An iterator over the output batches of a table function.
+pub type RecordBatchIter<'a> = Box<dyn Iterator<Item = Result<RecordBatch>> + Send + 'a>;
+
+/// A runtime to execute user defined functions.
+///
+/// This trait is object safe, so that different runtimes can be used interchangeably
+/// through `Box<dyn UdfRuntime>`.
+pub trait UdfRuntime: Send + Sync {
+    /// Add a new scalar function or table function.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the function.
+    /// - `return_field`: The field of the return value.
+    /// - `mode`: Whether the function will be called when some of its arguments are null.
+    /// - `code`: The source code of the function. The meaning depends on the runtime.
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()>;
+
+    /// Add a new function with its properties.
+    ///
+    /// The default implementation only passes the call mode to [`add_function`](UdfRuntime::add_function).
+    /// Wrappers that depend on other properties, e.g. [`CachedRuntime`] on volatility
+    /// and [`RuntimePool`] on parallel safety, override this method.
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.add_function(name, return_field, properties.mode, code)
+    }
+
+    /// Call a scalar function.
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch>;
+
+    /// Call a scalar function, returning the input columns followed by the output columns.
+    ///
+    /// The input columns are shared with the returned batch without copying.
+    fn call_with_input(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let output = self.call(name, input)?;
+        let input_schema = input.schema();
+        let output_schema = output.schema();
+        let fields = (input_schema.fields().iter())
+            .chain(output_schema.fields())
+            .cloned()
+            .collect::<Vec<_>>();
+        let columns = (input.columns().iter())
+            .chain(output.columns())
+            .cloned()
+            .collect();
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    /// Call a scalar function with arguments, some of which may be scalars broadcast to all rows.
+    ///
+    /// The default implementation materializes the scalars into arrays of the batch length.
+    fn call_broadcast(&self, name: &str, args: &Arguments) -> Result<RecordBatch> {
+        self.call(name, &args.materialize()?)
+    }
+
+    /// Call a scalar function on a single row of scalar arguments and return the scalar result.
+    ///
+    /// This is useful for constant folding and interactive testing.
+    /// The arguments are passed as nullable fields without metadata,
+    /// so use [`call_broadcast`](UdfRuntime::call_broadcast) for extension types.
+    fn call_scalar(&self, name: &str, args: &[Scalar<ArrayRef>]) -> Result<Scalar<ArrayRef>> {
+        let mut arguments = Arguments::new(1);
+        for (i, arg) in args.iter().enumerate() {
+            let (array, _) = arg.get();
+            let field = Field::new(format!("arg{i}"), array.data_type().clone(), true);
+            arguments = arguments.with_scalar(field, array.slice(0, 1))?;
+        }
+        let output = self.call_broadcast(name, &arguments)?;
+        if output.num_rows() != 1 {
+            return Err(Error::Other(anyhow!(
+                "expect 1 row from function {name}, got {}",
+                output.num_rows()
+            )));
+        }
+        Ok(Scalar::new(output.column(0).clone()))
+    }
+
+    /// Call a scalar function, stopping with [`Error::Cancelled`] once the token is cancelled.
+    ///
+    /// Runtimes interrupt the running function where they are able to.
+    /// The default implementation only checks the token before the call.
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        token.check()?;
+        self.call(name, input)
+    }
+
+    /// Call a scalar function on the selected rows of the input.
+    ///
+    /// Rows that are not selected, including those where `selection` is null,
+    /// are neither converted nor evaluated, and their outputs are null.
+    ///
+    /// The default implementation calls the function on the filtered input
+    /// and scatters the outputs back to their rows.
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        check_selection(input, selection)?;
+        if selection.true_count() == input.num_rows() {
+            return self.call(name, input);
+        }
+        let output = self.call(name, &filter_record_batch(input, selection)?)?;
+        let mut next = 0;
+        let indices: UInt32Array = selection
+            .iter()
+            .map(|selected| {
+                selected.unwrap_or(false).then(|| {
+                    let index = next;
+                    next += 1;
+                    index
+                })
+            })
+            .collect();
+        let columns = output
+            .columns()
+            .iter()
+            .map(|column| take(column, &indices, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        let fields: Vec<_> = (output.schema().fields().iter())
+            .map(|field| Field::clone(field).with_nullable(true))
+            .collect();
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    /// Call a table function.
+    ///
+    /// `chunk_size` is the maximum number of rows in each output batch.
+    /// Runtimes that decide the batch size by themselves may ignore it.
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>>;
+
+    /// Return the names of all functions that can be called.
+    fn list_functions(&self) -> Vec<String>;
+
+    /// Return the names of all aggregate functions.
+    fn list_aggregates(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Return the descriptions of all functions, including aggregate functions.
+    ///
+    /// The default implementation only reports the names of functions,
+    /// and the kind of aggregate functions.
+    fn functions(&self) -> Vec<FunctionInfo> {
+        let functions = self.list_functions().into_iter().map(FunctionInfo::new);
+        let aggregates = (self.list_aggregates().into_iter())
+            .map(|name| FunctionInfo::new(name).with_kind(FunctionKind::Aggregate));
+        functions.chain(aggregates).collect()
+    }
+
+    /// Create a new state for an aggregate function.
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        Err(Error::FunctionNotFound(name.to_string()))
+    }
+
+    /// Accumulate the input rows into the state of an aggregate function.
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        _ = (state, input);
+        Err(Error::FunctionNotFound(name.to_string()))
+    }
+
+    /// Merge states of an aggregate function into one.
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        _ = states;
+        Err(Error::FunctionNotFound(name.to_string()))
+    }
+
+    /// Get the results of an aggregate function from the states.
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        _ = states;
+        Err(Error::FunctionNotFound(name.to_string()))
+    }
+
+    /// Returns the field of the states of an aggregate function.
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        Err(Error::FunctionNotFound(name.to_string()))
+    }
+
+    /// Serialize the states of an aggregate function in the format of [`encode_state`].
+    fn serialize_state(&self, name: &str, states: &ArrayRef) -> Result<Vec<u8>> {
+        let field = self.state_field(name)?;
+        encode_state(name, &field, states)
+    }
+
+    /// Deserialize the states of an aggregate function, which may be serialized by another runtime.
+    ///
+    /// Returns [`Error::TypeMismatch`] if the state type differs from the one of the function.
+    fn deserialize_state(&self, name: &str, bytes: &[u8]) -> Result<ArrayRef> {
+        let expected = self.state_field(name)?;
+        let (_, field, states) = decode_state(bytes)?;
+        state::check_state_field(&expected, &field)?;
+        Ok(states)
+    }
+
+    /// Returns the approximate memory used by the interpreter in bytes, if the runtime knows it.
+    ///
+    /// This does not include the arrays returned to the caller.
+    fn memory_usage(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the features supported by the runtime, so that engines can check them
+    /// when functions are registered.
+    ///
+    /// The default implementation reports scalar and table functions with unknown data types.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new().with_function_kinds([FunctionKind::Scalar, FunctionKind::Table])
+    }
+}
+
+/// Check that the selection has a value for each row of the input.
+pub fn check_selection(input: &RecordBatch, selection: &BooleanArray) -> Result<()> {
+    if selection.len() != input.num_rows() {
+        return Err(Error::Other(anyhow!(
+            "selection length {} does not match the number of rows {}",
+            selection.len(),
+            input.num_rows()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the memory size of the rows in a batch in bytes.
+///
+/// Unlike [`RecordBatch::get_array_memory_size`], the parts of buffers outside
+/// a sliced batch are not counted, so that a slice of a large batch is measured by its own rows.
+pub fn slice_memory_size(batch: &RecordBatch) -> usize {
+    (batch.columns().iter())
+        .map(|column| {
+            let data = column.to_data();
+            data.get_slice_memory_size()
+                .unwrap_or_else(|_| column.get_array_memory_size())
+        })
+        .sum()
+}
+
+/// Whether the function will be called when some of its arguments are null.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallMode {
+    /// The function will be called normally when some of its arguments are null.
+    /// It is then the function author's responsibility to check for null values if necessary and respond appropriately.
+    #[default]
+    CalledOnNullInput,
+
+    /// The function always returns null whenever any of its arguments are null.
+    /// If this parameter is specified, the function is not executed when there are null arguments;
+    /// instead a null result is assumed automatically.
+    ReturnNullOnNullInput,
+
+    /// The function returns null without being called whenever any of the strict arguments are null,
+    /// and is called normally when only the other arguments are null, e.g. optional parameters.
+    ReturnNullOnNullArguments(StrictArguments),
+}
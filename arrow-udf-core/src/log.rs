@@ -0,0 +1,120 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A logging interface shared by runtimes.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::{Error, Result};
+
+/// The level of a log message from a user defined function.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LogLevel {
+    /// Detailed messages for debugging, e.g. `console.debug` or `logging.debug`.
+    Debug,
+    /// Informational messages, e.g. `console.log` or `print`.
+    #[default]
+    Info,
+    /// Warnings, e.g. `console.warn`, `logging.warning` or the standard error.
+    Warn,
+    /// Errors, e.g. `console.error` or `logging.error`.
+    Error,
+}
+
+/// A sink of the messages logged by user defined functions.
+///
+/// A sink is set once on each runtime with `set_log_sink`, and messages are delivered
+/// with the name of the function that logs them, regardless of the language:
+///
+/// - JavaScript: `console.log`, `console.info`, `console.debug`, `console.warn` and `console.error`.
+/// - Python: `print`, writes to `sys.stdout` and `sys.stderr`, and the `logging` module.
+/// - WebAssembly: the `arrowudf.log` import, and lines written to the standard output and error.
+///
+/// Closures of `Fn(LogLevel, &str, &str)` are sinks.
+///
+/// # Example
+///
+/// ```
+/// use arrow_udf_core::{LogLevel, UdfLogSink};
+///
+/// let sink = |level: LogLevel, function: &str, message: &str| {
+///     eprintln!("[{level}] {function}: {message}");
+/// };
+/// sink.log(LogLevel::Info, "gcd", "hello");
+/// ```
+pub trait UdfLogSink: Send + Sync {
+    /// Log a message of a function.
+    ///
+    /// This is called in the middle of function calls, so it should not block for long.
+    fn log(&self, level: LogLevel, function: &str, message: &str);
+}
+
+impl<F> UdfLogSink for F
+where
+    F: Fn(LogLevel, &str, &str) + Send + Sync,
+{
+    fn log(&self, level: LogLevel, function: &str, message: &str) {
+        self(level, function, message)
+    }
+}
+
+/// Emits messages as events of the [`tracing`](https://docs.rs/tracing) crate,
+/// with the target `arrow_udf` and the name of the function in the `function` field.
+///
+/// The levels map to the same levels of `tracing`.
+#[cfg(feature = "tracing")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingLogSink;
+
+#[cfg(feature = "tracing")]
+impl UdfLogSink for TracingLogSink {
+    fn log(&self, level: LogLevel, function: &str, message: &str) {
+        match level {
+            LogLevel::Debug => tracing::debug!(target: "arrow_udf", function, "{message}"),
+            LogLevel::Info => tracing::info!(target: "arrow_udf", function, "{message}"),
+            LogLevel::Warn => tracing::warn!(target: "arrow_udf", function, "{message}"),
+            LogLevel::Error => tracing::error!(target: "arrow_udf", function, "{message}"),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        })
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "debug" => Ok(Self::Debug),
+            "info" => Ok(Self::Info),
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => Err(Error::Other(anyhow!("invalid LogLevel: {s}"))),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Account memory used by function calls against a memory budget.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
+use arrow_schema::{Field, FieldRef};
+
+use crate::{
+    CallMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionProperties, RecordBatchIter,
+    Result, UdfRuntime,
+};
+
+/// A memory budget shared with the engine.
+///
+/// Implement this trait to track memory of function calls in the engine's own memory manager,
+/// so that it can decide to spill or abort before running out of memory,
+/// or use [`GreedyMemoryPool`] for a simple limit.
+pub trait MemoryPool: Send + Sync {
+    /// Reserve more memory, or return an error if the budget is exceeded.
+    ///
+    /// Implementations should return [`Error::ResourceExceeded`] when the budget is exceeded.
+    fn try_grow(&self, bytes: usize) -> Result<()>;
+
+    /// Release memory reserved by [`try_grow`](MemoryPool::try_grow).
+    fn shrink(&self, bytes: usize);
+
+    /// Returns the memory currently reserved in bytes.
+    fn reserved(&self) -> usize;
+}
+
+/// A pool that grants reservations until the limit is reached.
+#[derive(Debug)]
+pub struct GreedyMemoryPool {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl GreedyMemoryPool {
+    /// Create a pool with the limit in bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl MemoryPool for GreedyMemoryPool {
+    fn try_grow(&self, bytes: usize) -> Result<()> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                let new_used = used.checked_add(bytes)?;
+                (new_used <= self.limit).then_some(new_used)
+            })
+            .map_err(|used| {
+                Error::ResourceExceeded(format!(
+                    "memory limit of {} bytes exceeded: {used} bytes reserved, {bytes} bytes requested",
+                    self.limit
+                ))
+            })?;
+        Ok(())
+    }
+
+    fn shrink(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn reserved(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+}
+
+/// Memory reserved from a [`MemoryPool`], released when dropped.
+pub struct MemoryReservation {
+    pool: Arc<dyn MemoryPool>,
+    size: usize,
+}
+
+impl MemoryReservation {
+    /// Create an empty reservation from the pool.
+    pub fn new(pool: Arc<dyn MemoryPool>) -> Self {
+        Self { pool, size: 0 }
+    }
+
+    /// Returns the reserved memory in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Resize the reservation, leaving it unchanged if the pool refuses to grow.
+    pub fn try_resize(&mut self, size: usize) -> Result<()> {
+        if size > self.size {
+            self.pool.try_grow(size - self.size)?;
+        } else {
+            self.pool.shrink(self.size - size);
+        }
+        self.size = size;
+        Ok(())
+    }
+
+    /// Release all reserved memory.
+    pub fn free(&mut self) {
+        self.pool.shrink(self.size);
+        self.size = 0;
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
+
+impl std::fmt::Debug for MemoryReservation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryReservation")
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+/// A runtime that accounts the memory of the inner runtime against a [`MemoryPool`].
+///
+/// Two kinds of memory are accounted:
+///
+/// - The memory used by the interpreter, as reported by [`UdfRuntime::memory_usage`].
+///   It is held in the pool and updated after every call.
+/// - The output arrays of each call. They are accounted when they are produced,
+///   so that a call fails with [`Error::ResourceExceeded`] if its outputs exceed the budget.
+///   [`call_reserved`](AccountedRuntime::call_reserved) keeps the reservation
+///   until the caller drops the outputs.
+///
+/// # Example
+///
+/// ```ignore
+/// let pool = Arc::new(GreedyMemoryPool::new(64 << 20));
+/// let runtime = AccountedRuntime::new(Box::new(arrow_udf_js::Runtime::new()?), pool.clone());
+/// let (output, reservation) = runtime.call_reserved("gcd", &input)?;
+/// ```
+pub struct AccountedRuntime {
+    inner: Box<dyn UdfRuntime>,
+    pool: Arc<dyn MemoryPool>,
+    interpreter: Mutex<MemoryReservation>,
+}
+
+impl AccountedRuntime {
+    /// Wrap a runtime to account its memory in the pool.
+    pub fn new(inner: Box<dyn UdfRuntime>, pool: Arc<dyn MemoryPool>) -> Self {
+        let interpreter = Mutex::new(MemoryReservation::new(pool.clone()));
+        Self {
+            inner,
+            pool,
+            interpreter,
+        }
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        self.inner.as_ref()
+    }
+
+    /// Returns the memory reserved for the interpreter in bytes.
+    pub fn interpreter_reserved(&self) -> usize {
+        self.interpreter.lock().unwrap().size()
+    }
+
+    /// Call a scalar function, returning the outputs with the memory reserved for them.
+    ///
+    /// The memory is released when the reservation is dropped.
+    pub fn call_reserved(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+    ) -> Result<(RecordBatch, MemoryReservation)> {
+        self.account_reserved(|| self.inner.call(name, input), batch_size)
+    }
+
+    /// Call `f` and reserve memory for the interpreter and the outputs.
+    fn account_reserved<T>(
+        &self,
+        f: impl FnOnce() -> Result<T>,
+        size: impl Fn(&T) -> usize,
+    ) -> Result<(T, MemoryReservation)> {
+        let output = f();
+        let updated = self.update_interpreter();
+        let output = output?;
+        updated?;
+        let reservation = self.reserve(size(&output))?;
+        Ok((output, reservation))
+    }
+
+    /// Call `f` and check that the interpreter and the outputs fit in the pool.
+    fn account<T>(&self, f: impl FnOnce() -> Result<T>, size: impl Fn(&T) -> usize) -> Result<T> {
+        Ok(self.account_reserved(f, size)?.0)
+    }
+
+    /// Resize the reservation of the interpreter to its current memory usage.
+    fn update_interpreter(&self) -> Result<()> {
+        match self.inner.memory_usage() {
+            Some(usage) => self.interpreter.lock().unwrap().try_resize(usage),
+            None => Ok(()),
+        }
+    }
+
+    /// Reserve memory from the pool.
+    fn reserve(&self, bytes: usize) -> Result<MemoryReservation> {
+        let mut reservation = MemoryReservation::new(self.pool.clone());
+        reservation.try_resize(bytes)?;
+        Ok(reservation)
+    }
+}
+
+impl UdfRuntime for AccountedRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.inner.add_function(name, return_field, mode, code)?;
+        self.update_interpreter()
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function_with_properties(name, return_field, properties, code)?;
+        self.update_interpreter()
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.account(|| self.inner.call(name, input), batch_size)
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        self.account(
+            || self.inner.call_cancellable(name, input, token),
+            batch_size,
+        )
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        self.account(
+            || self.inner.call_selected(name, input, selection),
+            batch_size,
+        )
+    }
+
+    /// Each output batch is accounted when it is produced.
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        let mut iter = self.account(
+            || self.inner.call_table_function(name, input, chunk_size),
+            |_| 0,
+        )?;
+        Ok(Box::new(std::iter::from_fn(move || {
+            let next = iter.next()?;
+            Some(self.account(|| next, batch_size))
+        })))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.inner.functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.account(|| self.inner.create_state(name), array_size)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.account(|| self.inner.accumulate(name, state, input), array_size)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.account(|| self.inner.merge(name, states), array_size)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.account(|| self.inner.finish(name, states), array_size)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+fn batch_size(batch: &RecordBatch) -> usize {
+    batch.get_array_memory_size()
+}
+
+fn array_size(array: &ArrayRef) -> usize {
+    array.get_array_memory_size()
+}
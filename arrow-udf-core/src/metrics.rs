@@ -0,0 +1,477 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Metrics of function calls.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, FieldRef, Schema};
+
+use crate::{
+    slice_memory_size, CallMode, CancelToken, Capabilities, FunctionInfo, FunctionProperties,
+    RecordBatchIter, Result, UdfRuntime,
+};
+
+/// Statistics of a function call.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CallStats {
+    /// The number of input rows.
+    pub input_rows: usize,
+    /// The number of output rows.
+    pub output_rows: usize,
+    /// The memory size of the input rows in bytes, see [`slice_memory_size`].
+    pub input_bytes: usize,
+    /// The memory size of the output rows in bytes.
+    pub output_bytes: usize,
+    /// The time spent in the call.
+    pub elapsed: Duration,
+    /// Whether the call failed.
+    pub error: bool,
+}
+
+/// A sink of function call metrics.
+///
+/// Implement this trait to export metrics to a monitoring system,
+/// or use [`MetricsRegistry`] to collect them in memory.
+pub trait Metrics: Send + Sync {
+    /// Record a call of the function.
+    fn record(&self, function: &str, stats: &CallStats);
+}
+
+/// A runtime that records metrics of every call to the inner runtime.
+///
+/// Scalar and table function calls, as well as aggregate accumulations, are recorded
+/// under the name of the function.
+///
+/// # Example
+///
+/// ```ignore
+/// let registry = Arc::new(MetricsRegistry::new());
+/// let runtime = MeteredRuntime::new(Box::new(arrow_udf_js::Runtime::new()?), registry.clone());
+/// runtime.call("gcd", &input)?;
+/// println!("{}", registry.render_prometheus());
+/// ```
+pub struct MeteredRuntime {
+    inner: Box<dyn UdfRuntime>,
+    metrics: Arc<dyn Metrics>,
+}
+
+impl MeteredRuntime {
+    /// Wrap a runtime to record metrics into the sink.
+    pub fn new(inner: Box<dyn UdfRuntime>, metrics: Arc<dyn Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        self.inner.as_ref()
+    }
+
+    /// Call `f` and record the statistics of the call.
+    fn record(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        f: impl FnOnce() -> Result<RecordBatch>,
+    ) -> Result<RecordBatch> {
+        let start = Instant::now();
+        let output = f();
+        let mut stats = CallStats {
+            input_rows: input.num_rows(),
+            input_bytes: slice_memory_size(input),
+            elapsed: start.elapsed(),
+            error: output.is_err(),
+            ..Default::default()
+        };
+        if let Ok(output) = &output {
+            stats.output_rows = output.num_rows();
+            stats.output_bytes = slice_memory_size(output);
+        }
+        self.metrics.record(name, &stats);
+        output
+    }
+}
+
+impl UdfRuntime for MeteredRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.inner.add_function(name, return_field, mode, code)
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function_with_properties(name, return_field, properties, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.record(name, input, || self.inner.call(name, input))
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        self.record(name, input, || {
+            self.inner.call_cancellable(name, input, token)
+        })
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        self.record(name, input, || {
+            self.inner.call_selected(name, input, selection)
+        })
+    }
+
+    /// The call is recorded when the iterator is dropped,
+    /// with the time spent in creating the iterator and producing the batches.
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        let start = Instant::now();
+        let mut recorder = TableRecorder {
+            name,
+            metrics: self.metrics.as_ref(),
+            stats: CallStats {
+                input_rows: input.num_rows(),
+                input_bytes: slice_memory_size(input),
+                ..Default::default()
+            },
+        };
+        let mut iter = match self.inner.call_table_function(name, input, chunk_size) {
+            Ok(iter) => iter,
+            Err(e) => {
+                recorder.stats.error = true;
+                recorder.stats.elapsed = start.elapsed();
+                return Err(e);
+            }
+        };
+        recorder.stats.elapsed = start.elapsed();
+        Ok(Box::new(std::iter::from_fn(move || {
+            // capture the whole recorder, which records when the iterator is dropped
+            let recorder = &mut recorder;
+            let start = Instant::now();
+            let next = iter.next();
+            recorder.stats.elapsed += start.elapsed();
+            match &next {
+                Some(Ok(batch)) => {
+                    recorder.stats.output_rows += batch.num_rows();
+                    recorder.stats.output_bytes += slice_memory_size(batch);
+                }
+                Some(Err(_)) => recorder.stats.error = true,
+                None => {}
+            }
+            next
+        })))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.inner.functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.inner.create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        let start = Instant::now();
+        let output = self.inner.accumulate(name, state, input);
+        let stats = CallStats {
+            input_rows: input.num_rows(),
+            input_bytes: slice_memory_size(input),
+            elapsed: start.elapsed(),
+            error: output.is_err(),
+            ..Default::default()
+        };
+        self.metrics.record(name, &stats);
+        output
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.inner.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.inner.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+/// Records the statistics of a table function call when dropped.
+struct TableRecorder<'a> {
+    name: &'a str,
+    metrics: &'a dyn Metrics,
+    stats: CallStats,
+}
+
+impl Drop for TableRecorder<'_> {
+    fn drop(&mut self) {
+        self.metrics.record(self.name, &self.stats);
+    }
+}
+
+/// The upper bounds of latency histogram buckets in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 1.0, 10.0,
+];
+
+/// Metrics of a function collected by [`MetricsRegistry`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FunctionMetrics {
+    /// The number of calls.
+    pub calls: u64,
+    /// The number of failed calls.
+    pub errors: u64,
+    /// The total number of input rows.
+    pub input_rows: u64,
+    /// The total number of output rows.
+    pub output_rows: u64,
+    /// The total memory size of input arrays in bytes.
+    pub input_bytes: u64,
+    /// The total memory size of output arrays in bytes.
+    pub output_bytes: u64,
+    /// The number of calls in each latency bucket, not cumulative.
+    pub latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    /// The total latency of calls in seconds.
+    pub latency_sum: f64,
+}
+
+impl FunctionMetrics {
+    /// Returns the values of counters in the order of [`COUNTERS`].
+    fn counters(&self) -> [u64; COUNTERS.len()] {
+        [
+            self.calls,
+            self.errors,
+            self.input_rows,
+            self.output_rows,
+            self.input_bytes,
+            self.output_bytes,
+        ]
+    }
+}
+
+/// The names and descriptions of counters rendered by [`MetricsRegistry`].
+const COUNTERS: [(&str, &str); 6] = [
+    ("calls", "Number of function calls."),
+    ("errors", "Number of failed function calls."),
+    ("input_rows", "Number of input rows."),
+    ("output_rows", "Number of output rows."),
+    ("input_bytes", "Memory size of input arrays in bytes."),
+    ("output_bytes", "Memory size of output arrays in bytes."),
+];
+
+/// Returns the schema of the batches returned by [`MetricsRegistry::to_batch`].
+///
+/// The columns are `function`, the counters `calls`, `errors`, `input_rows`, `output_rows`,
+/// `input_bytes` and `output_bytes`, and `latency_sum`, the total latency of calls in seconds.
+pub fn metrics_schema() -> Schema {
+    let mut fields = vec![Field::new("function", DataType::Utf8, false)];
+    for (name, _) in COUNTERS {
+        fields.push(Field::new(name, DataType::UInt64, false));
+    }
+    fields.push(Field::new("latency_sum", DataType::Float64, false));
+    Schema::new(fields)
+}
+
+/// An in-memory collection of metrics, which can be rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    functions: Mutex<BTreeMap<String, FunctionMetrics>>,
+}
+
+impl MetricsRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the metrics of a function.
+    pub fn get(&self, function: &str) -> Option<FunctionMetrics> {
+        self.functions.lock().unwrap().get(function).cloned()
+    }
+
+    /// Returns the metrics of all functions ordered by their names.
+    pub fn snapshot(&self) -> BTreeMap<String, FunctionMetrics> {
+        self.functions.lock().unwrap().clone()
+    }
+
+    /// Remove the metrics of all functions.
+    pub fn reset(&self) {
+        self.functions.lock().unwrap().clear();
+    }
+
+    /// Returns the metrics as a batch with a row for each function,
+    /// e.g. for engines to show them in a system table.
+    ///
+    /// See [`metrics_schema`] for the columns.
+    pub fn to_batch(&self) -> Result<RecordBatch> {
+        let functions = self.snapshot();
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(
+            functions.keys().map(Some).collect::<StringArray>(),
+        )];
+        for i in 0..COUNTERS.len() {
+            let counters: UInt64Array = functions.values().map(|m| m.counters()[i]).collect();
+            columns.push(Arc::new(counters));
+        }
+        let latencies: Float64Array = functions.values().map(|m| m.latency_sum).collect();
+        columns.push(Arc::new(latencies));
+        Ok(RecordBatch::try_new(Arc::new(metrics_schema()), columns)?)
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let functions = self.functions.lock().unwrap();
+        let mut out = String::new();
+        for (i, (name, help)) in COUNTERS.iter().enumerate() {
+            _ = writeln!(out, "# HELP arrow_udf_{name}_total {help}");
+            _ = writeln!(out, "# TYPE arrow_udf_{name}_total counter");
+            for (function, metrics) in functions.iter() {
+                let function = escape_label(function);
+                _ = writeln!(
+                    out,
+                    "arrow_udf_{name}_total{{function=\"{function}\"}} {}",
+                    metrics.counters()[i]
+                );
+            }
+        }
+        let name = "arrow_udf_call_duration_seconds";
+        _ = writeln!(out, "# HELP {name} Latency of function calls.");
+        _ = writeln!(out, "# TYPE {name} histogram");
+        for (function, metrics) in functions.iter() {
+            let function = escape_label(function);
+            let mut count = 0;
+            for (i, bucket) in metrics.latency_buckets.iter().enumerate() {
+                count += bucket;
+                let le = match LATENCY_BUCKETS.get(i) {
+                    Some(bound) => bound.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                _ = writeln!(
+                    out,
+                    "{name}_bucket{{function=\"{function}\",le=\"{le}\"}} {count}"
+                );
+            }
+            _ = writeln!(
+                out,
+                "{name}_sum{{function=\"{function}\"}} {}",
+                metrics.latency_sum
+            );
+            _ = writeln!(out, "{name}_count{{function=\"{function}\"}} {count}");
+        }
+        out
+    }
+}
+
+impl Metrics for MetricsRegistry {
+    fn record(&self, function: &str, stats: &CallStats) {
+        let mut functions = self.functions.lock().unwrap();
+        let metrics = match functions.get_mut(function) {
+            Some(metrics) => metrics,
+            None => functions.entry(function.to_string()).or_default(),
+        };
+        metrics.calls += 1;
+        metrics.errors += stats.error as u64;
+        metrics.input_rows += stats.input_rows as u64;
+        metrics.output_rows += stats.output_rows as u64;
+        metrics.input_bytes += stats.input_bytes as u64;
+        metrics.output_bytes += stats.output_bytes as u64;
+        let seconds = stats.elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        metrics.latency_buckets[bucket] += 1;
+        metrics.latency_sum += seconds;
+    }
+}
+
+/// Escape a label value in the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Forwards metrics to the global recorder of the [`metrics`](https://docs.rs/metrics) crate,
+/// e.g. to be exported by `metrics-exporter-prometheus`.
+///
+/// The metrics have the same names as those rendered by [`MetricsRegistry`],
+/// labeled by `function`.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GlobalMetrics;
+
+#[cfg(feature = "metrics")]
+impl Metrics for GlobalMetrics {
+    fn record(&self, function: &str, stats: &CallStats) {
+        let labels = [("function", function.to_string())];
+        ::metrics::counter!("arrow_udf_calls_total", &labels).increment(1);
+        ::metrics::counter!("arrow_udf_errors_total", &labels).increment(stats.error as u64);
+        ::metrics::counter!("arrow_udf_input_rows_total", &labels)
+            .increment(stats.input_rows as u64);
+        ::metrics::counter!("arrow_udf_output_rows_total", &labels)
+            .increment(stats.output_rows as u64);
+        ::metrics::counter!("arrow_udf_input_bytes_total", &labels)
+            .increment(stats.input_bytes as u64);
+        ::metrics::counter!("arrow_udf_output_bytes_total", &labels)
+            .increment(stats.output_bytes as u64);
+        ::metrics::histogram!("arrow_udf_call_duration_seconds", &labels)
+            .record(stats.elapsed.as_secs_f64());
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! The handling of nulls inside nested values.
+
+use arrow_array::cast::AsArray;
+use arrow_array::Array;
+use arrow_schema::DataType;
+
+use crate::{Error, Result};
+
+/// How a null inside a nested value is handled when a function is called with
+/// [`CallMode::ReturnNullOnNullInput`](crate::CallMode::ReturnNullOnNullInput),
+/// or in the strict arguments of
+/// [`CallMode::ReturnNullOnNullArguments`](crate::CallMode::ReturnNullOnNullArguments).
+///
+/// A nested null is a null list element, a null struct field or a null map value
+/// in an argument that is not null itself.
+/// Functions called on null input always receive the nested nulls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NestedNullPolicy {
+    /// Pass the nested nulls to the function as null values of the language.
+    #[default]
+    PassThrough,
+    /// Return null without calling the function, as if the argument was null.
+    Propagate,
+    /// Fail the call with an execution error.
+    Error,
+}
+
+impl NestedNullPolicy {
+    /// Check the argument values of a row and return true if the function should not be called.
+    ///
+    /// Each item is an array and the index of the value in the array.
+    /// Only nested nulls are checked. The nulls of the arguments themselves are left to the caller.
+    pub fn check_row<'a>(
+        self,
+        values: impl IntoIterator<Item = (&'a dyn Array, usize)>,
+    ) -> Result<bool> {
+        if self == Self::PassThrough {
+            return Ok(false);
+        }
+        for (i, (array, index)) in values.into_iter().enumerate() {
+            if array.is_null(index) || !has_nested_null(array, index) {
+                continue;
+            }
+            return match self {
+                Self::Error => Err(nested_null_error(i)),
+                _ => Ok(true),
+            };
+        }
+        Ok(false)
+    }
+}
+
+/// Returns the error of a nested null in the argument at the index.
+pub(crate) fn nested_null_error(index: usize) -> Error {
+    Error::execution(format!("argument {} contains a nested null", index + 1))
+}
+
+/// Returns true if the value at the index contains a null at any nesting level.
+///
+/// The nullness of the value itself is not considered.
+pub fn has_nested_null(array: &dyn Array, index: usize) -> bool {
+    let any_null = |child: &dyn Array, start: usize, end: usize| {
+        (start..end).any(|i| child.is_null(i) || has_nested_null(child, i))
+    };
+    match array.data_type() {
+        DataType::List(_) => {
+            let list = array.as_list::<i32>();
+            let offsets = list.value_offsets();
+            let (start, end) = (offsets[index] as usize, offsets[index + 1] as usize);
+            any_null(list.values().as_ref(), start, end)
+        }
+        DataType::LargeList(_) => {
+            let list = array.as_list::<i64>();
+            let offsets = list.value_offsets();
+            let (start, end) = (offsets[index] as usize, offsets[index + 1] as usize);
+            any_null(list.values().as_ref(), start, end)
+        }
+        DataType::FixedSizeList(_, size) => {
+            let list = array.as_fixed_size_list();
+            let size = *size as usize;
+            any_null(list.values().as_ref(), index * size, (index + 1) * size)
+        }
+        DataType::Struct(_) => array
+            .as_struct()
+            .columns()
+            .iter()
+            .any(|column| any_null(column.as_ref(), index, index + 1)),
+        DataType::Map(_, _) => {
+            let map = array.as_map();
+            let offsets = map.value_offsets();
+            let (start, end) = (offsets[index] as usize, offsets[index + 1] as usize);
+            any_null(map.values().as_ref(), start, end)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compose multiple functions into a pipeline.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_schema::{FieldRef, Schema};
+
+use crate::UdfRuntime;
+
+/// An expression of nested function calls over the input columns.
+///
+/// An expression can be parsed from a string like `upper(trim(name))`,
+/// where a name without parentheses refers to an input column.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+    /// Reference to an input column by name.
+    Column(String),
+    /// Call a function with arguments.
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// Create a column reference.
+    pub fn column(name: &str) -> Self {
+        Expr::Column(name.to_string())
+    }
+
+    /// Create a function call.
+    pub fn call(function: &str, args: Vec<Expr>) -> Self {
+        Expr::Call(function.to_string(), args)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser { s, pos: 0 };
+        let expr = parser.parse_expr()?;
+        parser.skip_whitespace();
+        if parser.pos != s.len() {
+            bail!("unexpected character at {}: {s}", parser.pos);
+        }
+        Ok(expr)
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn parse_expr(&mut self) -> Result<Expr> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let rest = &self.s[start..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            bail!("expect identifier at {start}: {}", self.s);
+        }
+        self.pos += len;
+        let name = &rest[..len];
+        self.skip_whitespace();
+        if !self.eat('(') {
+            return Ok(Expr::column(name));
+        }
+        let mut args = vec![];
+        self.skip_whitespace();
+        if !self.eat(')') {
+            loop {
+                args.push(self.parse_expr()?);
+                self.skip_whitespace();
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    bail!("expect ',' or ')' at {}: {}", self.pos, self.s);
+                }
+            }
+        }
+        Ok(Expr::call(name, args))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.s[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A pipeline of function calls evaluated over a `RecordBatch`.
+///
+/// Expressions are compiled into a DAG, so that common sub-expressions
+/// are evaluated only once, and intermediate columns stay in Arrow format
+/// between functions.
+///
+/// # Example
+///
+/// ```ignore
+/// let pipeline = Pipeline::new()
+///     .with_output("upper", "upper(trim(name))".parse()?)
+///     .with_output("length", "length(trim(name))".parse()?);
+/// let output = pipeline.evaluate(&runtime, &input)?;
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Pipeline {
+    nodes: Vec<Node>,
+    /// Node -> index in `nodes`.
+    index: HashMap<Node, usize>,
+    /// (output name, node index)
+    outputs: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Column(String),
+    Call(String, Vec<usize>),
+}
+
+impl Pipeline {
+    /// Create an empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an output column computed by the expression.
+    pub fn with_output(mut self, name: &str, expr: Expr) -> Self {
+        let node = self.add_expr(&expr);
+        self.outputs.push((name.to_string(), node));
+        self
+    }
+
+    /// Returns the number of distinct function calls in the pipeline.
+    pub fn num_calls(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| matches!(n, Node::Call(..)))
+            .count()
+    }
+
+    fn add_expr(&mut self, expr: &Expr) -> usize {
+        let node = match expr {
+            Expr::Column(name) => Node::Column(name.clone()),
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|arg| self.add_expr(arg)).collect();
+                Node::Call(function.clone(), args)
+            }
+        };
+        if let Some(&index) = self.index.get(&node) {
+            return index;
+        }
+        // arguments are always added before the call, so nodes are in topological order
+        self.nodes.push(node.clone());
+        self.index.insert(node, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Evaluate the pipeline over the input and return the output columns.
+    pub fn evaluate(&self, runtime: &dyn UdfRuntime, input: &RecordBatch) -> Result<RecordBatch> {
+        let mut columns: Vec<(FieldRef, ArrayRef)> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let column = match node {
+                Node::Column(name) => {
+                    let (index, field) = input
+                        .schema()
+                        .column_with_name(name)
+                        .map(|(i, f)| (i, f.clone()))
+                        .with_context(|| format!("column not found: {name}"))?;
+                    (Arc::new(field), input.column(index).clone())
+                }
+                Node::Call(function, args) => {
+                    let fields: Vec<_> = args.iter().map(|&i| columns[i].0.clone()).collect();
+                    let arrays = args.iter().map(|&i| columns[i].1.clone()).collect();
+                    let batch = RecordBatch::try_new_with_options(
+                        Arc::new(Schema::new(fields)),
+                        arrays,
+                        &RecordBatchOptions::default().with_row_count(Some(input.num_rows())),
+                    )?;
+                    let output = runtime
+                        .call(function, &batch)
+                        .with_context(|| format!("failed to call function {function}"))?;
+                    (
+                        output.schema().field(0).clone().into(),
+                        output.column(0).clone(),
+                    )
+                }
+            };
+            columns.push(column);
+        }
+        let (fields, arrays): (Vec<_>, Vec<_>) = self
+            .outputs
+            .iter()
+            .map(|(name, i)| {
+                let (field, array) = &columns[*i];
+                (field.as_ref().clone().with_name(name), array.clone())
+            })
+            .unzip();
+        Ok(RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(fields)),
+            arrays,
+            &RecordBatchOptions::default().with_row_count(Some(input.num_rows())),
+        )?)
+    }
+}
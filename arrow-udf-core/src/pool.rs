@@ -0,0 +1,383 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Distribute function calls across a pool of runtimes.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int32Type;
+use arrow_array::{new_null_array, Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, FieldRef, Fields, Schema};
+use rayon::prelude::*;
+
+use crate::adaptive::shift_row;
+use crate::{
+    CallMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionProperties, RecordBatchIter,
+    Result, UdfRuntime,
+};
+
+/// A pool of runtimes that evaluates partitions of an input batch concurrently.
+///
+/// Functions added to the pool are added to every member.
+/// A call splits the input into contiguous partitions, one for each member,
+/// evaluates them in parallel on the rayon thread pool, and reassembles the outputs in the order of input rows.
+/// The outputs of table functions are collected for each partition and returned in the order of input rows,
+/// unless the input fits in one partition, in which case they are streamed from the first member.
+/// Functions added with [`ParallelSafety::Restricted`](crate::ParallelSafety::Restricted)
+/// or [`ParallelSafety::Unsafe`](crate::ParallelSafety::Unsafe) are evaluated by the first member instead,
+/// while those added by [`add_function`](UdfRuntime::add_function) are assumed to be parallel safe.
+///
+/// # Example
+///
+/// ```ignore
+/// let members = (0..4)
+///     .map(|_| Ok(Box::new(arrow_udf_python::Runtime::new()?) as Box<dyn UdfRuntime>))
+///     .collect::<Result<Vec<_>>>()?;
+/// let mut pool = RuntimePool::new(members)?.with_min_partition_rows(256);
+/// pool.add_function("gcd", field, CallMode::ReturnNullOnNullInput, code)?;
+/// let output = pool.call("gcd", &input)?;
+/// ```
+pub struct RuntimePool {
+    members: Vec<Box<dyn UdfRuntime>>,
+    parallelism: usize,
+    min_partition_rows: usize,
+    /// Functions that are not parallel safe, which are evaluated by one member.
+    serial: HashSet<String>,
+}
+
+impl RuntimePool {
+    /// Create a pool of runtimes.
+    ///
+    /// All members are expected to have the same functions.
+    pub fn new(members: Vec<Box<dyn UdfRuntime>>) -> Result<Self> {
+        if members.is_empty() {
+            return Err(Error::Other(anyhow!(
+                "runtime pool must have at least one member"
+            )));
+        }
+        Ok(Self {
+            parallelism: members.len(),
+            members,
+            min_partition_rows: 1,
+            serial: HashSet::new(),
+        })
+    }
+
+    /// Create a pool of `parallelism` runtimes created by `f`.
+    ///
+    /// ```ignore
+    /// let pool = RuntimePool::from_fn(4, || Ok(Box::new(arrow_udf_js::Runtime::new()?)))?;
+    /// ```
+    pub fn from_fn(
+        parallelism: usize,
+        f: impl FnMut() -> Result<Box<dyn UdfRuntime>>,
+    ) -> Result<Self> {
+        Self::new(
+            std::iter::repeat_with(f)
+                .take(parallelism)
+                .collect::<Result<_>>()?,
+        )
+    }
+
+    /// Set the maximum number of partitions evaluated in parallel.
+    /// Defaults to the number of members, which is also the upper bound.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.clamp(1, self.members.len());
+        self
+    }
+
+    /// Set the minimum number of rows in each partition. Defaults to 1.
+    ///
+    /// Smaller inputs are evaluated by fewer members, to avoid the overhead of tiny partitions.
+    pub fn with_min_partition_rows(mut self, rows: usize) -> Self {
+        self.min_partition_rows = rows.max(1);
+        self
+    }
+
+    /// Returns the members of the pool.
+    pub fn members(&self) -> &[Box<dyn UdfRuntime>] {
+        &self.members
+    }
+
+    /// Evaluate `f` on each partition of the input concurrently, returning outputs in order.
+    ///
+    /// Functions that are not parallel safe are evaluated on the whole input by the first member.
+    fn scatter<T: Send>(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        f: impl Fn(&dyn UdfRuntime, &RecordBatch) -> Result<T> + Sync,
+    ) -> Result<Vec<(usize, T)>> {
+        let partitions =
+            partition_rows(input.num_rows(), self.parallelism, self.min_partition_rows);
+        if partitions.len() == 1 || self.serial.contains(name) {
+            return Ok(vec![(0, f(self.members[0].as_ref(), input)?)]);
+        }
+        partitions
+            .par_iter()
+            .zip(&self.members)
+            .map(|(&(offset, len), member)| {
+                let output = f(member.as_ref(), &input.slice(offset, len))
+                    .map_err(|e| shift_row(e, offset))?;
+                Ok((offset, output))
+            })
+            .collect()
+    }
+}
+
+impl UdfRuntime for RuntimePool {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        for member in &mut self.members {
+            member.add_function(name, return_field.clone(), mode, code)?;
+        }
+        Ok(())
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        for member in &mut self.members {
+            member.add_function_with_properties(name, return_field.clone(), properties, code)?;
+        }
+        if properties.is_parallel_safe() {
+            self.serial.remove(name);
+        } else {
+            self.serial.insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let outputs = self.scatter(name, input, |member, input| member.call(name, input))?;
+        let batches: Vec<_> = outputs.into_iter().map(|(_, batch)| batch).collect();
+        concat_outputs(batches)
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        let outputs = self.scatter(name, input, |member, input| {
+            member.call_cancellable(name, input, token)
+        })?;
+        let batches: Vec<_> = outputs.into_iter().map(|(_, batch)| batch).collect();
+        concat_outputs(batches)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        let partitions =
+            partition_rows(input.num_rows(), self.parallelism, self.min_partition_rows);
+        if partitions.len() == 1 || self.serial.contains(name) {
+            return self.members[0].call_table_function(name, input, chunk_size);
+        }
+        let outputs = self.scatter(name, input, |member, input| {
+            (member.call_table_function(name, input, chunk_size)?).collect::<Result<Vec<_>>>()
+        })?;
+        Ok(Box::new(outputs.into_iter().flat_map(
+            |(offset, batches)| (batches.into_iter()).map(move |batch| offset_rows(batch, offset)),
+        )))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.members[0].list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.members[0].list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.members[0].functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.members[0].create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.members[0].accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.members[0].merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.members[0].finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.members[0].state_field(name)
+    }
+
+    /// Returns the total memory usage of members that know theirs.
+    fn memory_usage(&self) -> Option<usize> {
+        let usages = self
+            .members
+            .iter()
+            .filter_map(|member| member.memory_usage());
+        usages.reduce(|a, b| a + b)
+    }
+
+    /// Returns the capabilities of the first member, as all members are expected to be alike.
+    fn capabilities(&self) -> Capabilities {
+        self.members[0].capabilities()
+    }
+}
+
+/// Split `num_rows` into at most `max_parts` contiguous partitions of nearly equal size,
+/// each with at least `min_rows` rows unless there is only one.
+///
+/// Returns the offset and length of each partition.
+pub fn partition_rows(num_rows: usize, max_parts: usize, min_rows: usize) -> Vec<(usize, usize)> {
+    let parts = (num_rows / min_rows).clamp(1, max_parts);
+    let (base, remainder) = (num_rows / parts, num_rows % parts);
+    let mut offset = 0;
+    (0..parts)
+        .map(|i| {
+            let len = base + (i < remainder) as usize;
+            offset += len;
+            (offset - len, len)
+        })
+        .collect()
+}
+
+/// Concatenate the outputs of partitions.
+///
+/// The schemas of outputs may differ, because some runtimes add an `error` column
+/// only if some rows fail, or promote strings to their large types only if they are too long.
+/// See [`unify_outputs`] for how they are unified.
+pub fn concat_outputs(batches: Vec<RecordBatch>) -> Result<RecordBatch> {
+    if batches.len() == 1 {
+        return Ok(batches.into_iter().next().unwrap());
+    }
+    let batches = unify_outputs(batches)?;
+    let Some(first) = batches.first() else {
+        return Err(Error::Other(anyhow!("no outputs to concatenate")));
+    };
+    Ok(arrow_select::concat::concat_batches(
+        &first.schema(),
+        &batches,
+    )?)
+}
+
+/// Unify the schemas of output batches, e.g. before writing them to a stream with one schema.
+///
+/// Columns are matched by name. Missing columns are filled with nulls,
+/// and columns are cast to their widest types.
+pub fn unify_outputs(batches: Vec<RecordBatch>) -> Result<Vec<RecordBatch>> {
+    let mut fields: Vec<FieldRef> = vec![];
+    for batch in &batches {
+        for field in batch.schema().fields() {
+            match fields.iter_mut().find(|f| f.name() == field.name()) {
+                Some(unified) => *unified = widen_field(unified, field)?,
+                None => fields.push(field.clone()),
+            }
+        }
+    }
+    let Some(first) = batches.first() else {
+        return Ok(batches);
+    };
+    let schema = Arc::new(Schema::new(fields).with_metadata(first.schema().metadata().clone()));
+    batches
+        .iter()
+        .map(|batch| {
+            if batch.schema() == schema {
+                return Ok(batch.clone());
+            }
+            let columns = (schema.fields().iter())
+                .map(|field| match batch.column_by_name(field.name()) {
+                    Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+                    Some(column) => Ok(arrow_cast::cast(column, field.data_type())?),
+                    None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(RecordBatch::try_new(schema.clone(), columns)?)
+        })
+        .collect()
+}
+
+/// Returns a field that both fields can be cast to, with the large types of strings, binaries and lists.
+fn widen_field(a: &FieldRef, b: &FieldRef) -> Result<FieldRef> {
+    if a == b {
+        return Ok(a.clone());
+    }
+    let data_type = widen_type(a.data_type(), b.data_type()).ok_or_else(|| {
+        Error::Other(anyhow!(
+            "outputs of partitions have incompatible types for column {}: {} and {}",
+            a.name(),
+            a.data_type(),
+            b.data_type()
+        ))
+    })?;
+    Ok(Arc::new(
+        a.as_ref()
+            .clone()
+            .with_data_type(data_type)
+            .with_nullable(a.is_nullable() || b.is_nullable()),
+    ))
+}
+
+fn widen_type(a: &DataType, b: &DataType) -> Option<DataType> {
+    use DataType::*;
+    Some(match (a, b) {
+        _ if a == b => a.clone(),
+        (Utf8 | LargeUtf8, Utf8 | LargeUtf8) => LargeUtf8,
+        (Binary | LargeBinary, Binary | LargeBinary) => LargeBinary,
+        (List(a), List(b)) => List(widen_field(a, b).ok()?),
+        (List(a) | LargeList(a), List(b) | LargeList(b)) => LargeList(widen_field(a, b).ok()?),
+        (Struct(a), Struct(b)) if a.len() == b.len() => Struct(
+            (a.iter().zip(b.iter()))
+                .map(|(a, b)| (a.name() == b.name()).then(|| widen_field(a, b).ok())?)
+                .collect::<Option<Fields>>()?,
+        ),
+        _ => return None,
+    })
+}
+
+/// Shift the row index in the first column of a table function output by `offset`.
+fn offset_rows(batch: RecordBatch, offset: usize) -> Result<RecordBatch> {
+    if offset == 0 {
+        return Ok(batch);
+    }
+    let rows = batch
+        .column(0)
+        .as_primitive_opt::<Int32Type>()
+        .context("expect the first column of table function output to be row index")?;
+    let offset = offset as i32;
+    let rows = rows.unary::<_, Int32Type>(|row| row + offset);
+    let mut columns = batch.columns().to_vec();
+    columns[0] = std::sync::Arc::new(rows);
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
@@ -0,0 +1,398 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Properties of functions that engines and wrappers rely on.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use arrow_array::Array;
+
+use crate::nulls::nested_null_error;
+use crate::{has_nested_null, CallMode, Error, NestedNullPolicy, Result};
+
+/// The metadata key of [`CallMode`] in [`FunctionProperties::to_metadata`].
+pub const CALL_MODE_KEY: &str = "arrowudf.call_mode";
+/// The metadata key of [`Volatility`] in [`FunctionProperties::to_metadata`].
+pub const VOLATILITY_KEY: &str = "arrowudf.volatility";
+/// The metadata key of [`ParallelSafety`] in [`FunctionProperties::to_metadata`].
+pub const PARALLEL_SAFETY_KEY: &str = "arrowudf.parallel";
+
+/// Whether a function returns the same result for the same arguments.
+///
+/// The levels follow the ones of Postgres.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Volatility {
+    /// The function always returns the same result for the same arguments,
+    /// so that its results can be cached or folded into constants.
+    Immutable,
+    /// The function returns the same result for the same arguments within a query,
+    /// but may return different results across queries, e.g. depending on the current time.
+    Stable,
+    /// The function may return different results for the same arguments, e.g. `random()`.
+    #[default]
+    Volatile,
+}
+
+/// Whether a function can be evaluated in parallel.
+///
+/// The levels follow the ones of Postgres.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ParallelSafety {
+    /// The function may be evaluated on partitions of the input concurrently.
+    Safe,
+    /// The function may be evaluated concurrently, but only in the process of the caller.
+    Restricted,
+    /// The function relies on state shared between calls, so all rows must be evaluated in order by one runtime.
+    #[default]
+    Unsafe,
+}
+
+/// The properties of a function, declared once and understood by all runtimes and protocols.
+///
+/// The defaults are the most conservative ones: the function is called on null input,
+/// volatile and unsafe to evaluate in parallel.
+///
+/// # Example
+///
+/// ```
+/// use arrow_udf_core::{CallMode, FunctionProperties, ParallelSafety, Volatility};
+///
+/// let properties = FunctionProperties::new()
+///     .with_mode(CallMode::ReturnNullOnNullInput)
+///     .with_volatility(Volatility::Immutable)
+///     .with_parallel_safety(ParallelSafety::Safe);
+/// assert!(properties.is_strict());
+/// assert!(properties.is_deterministic());
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionProperties {
+    /// Whether the function will be called when some of its arguments are null.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mode: CallMode,
+    /// Whether the function returns the same result for the same arguments.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub volatility: Volatility,
+    /// Whether the function can be evaluated in parallel.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub parallel_safety: ParallelSafety,
+}
+
+impl FunctionProperties {
+    /// Create the default properties.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the call mode.
+    pub fn with_mode(mut self, mode: CallMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the volatility.
+    pub fn with_volatility(mut self, volatility: Volatility) -> Self {
+        self.volatility = volatility;
+        self
+    }
+
+    /// Set the parallel safety.
+    pub fn with_parallel_safety(mut self, parallel_safety: ParallelSafety) -> Self {
+        self.parallel_safety = parallel_safety;
+        self
+    }
+
+    /// Returns true if the function returns null on null input without being called.
+    pub fn is_strict(&self) -> bool {
+        self.mode.is_strict()
+    }
+
+    /// Returns true if the results of the function can be cached across queries.
+    pub fn is_deterministic(&self) -> bool {
+        self.volatility == Volatility::Immutable
+    }
+
+    /// Returns true if partitions of the input can be evaluated concurrently.
+    pub fn is_parallel_safe(&self) -> bool {
+        self.parallel_safety == ParallelSafety::Safe
+    }
+
+    /// Encode the properties as key-value pairs, e.g. in the schema metadata of a remote function.
+    pub fn to_metadata(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (CALL_MODE_KEY.to_string(), self.mode.to_string()),
+            (VOLATILITY_KEY.to_string(), self.volatility.to_string()),
+            (
+                PARALLEL_SAFETY_KEY.to_string(),
+                self.parallel_safety.to_string(),
+            ),
+        ])
+    }
+
+    /// Decode the properties from key-value pairs. Missing properties are set to the defaults.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Self> {
+        fn parse<T: FromStr<Err = Error> + Default>(
+            metadata: &HashMap<String, String>,
+            key: &str,
+        ) -> Result<T> {
+            metadata.get(key).map_or(Ok(T::default()), |v| v.parse())
+        }
+        Ok(Self {
+            mode: parse(metadata, CALL_MODE_KEY)?,
+            volatility: parse(metadata, VOLATILITY_KEY)?,
+            parallel_safety: parse(metadata, PARALLEL_SAFETY_KEY)?,
+        })
+    }
+}
+
+impl From<CallMode> for FunctionProperties {
+    fn from(mode: CallMode) -> Self {
+        Self::new().with_mode(mode)
+    }
+}
+
+/// The arguments of a function with [`CallMode::ReturnNullOnNullArguments`],
+/// whose nulls make the function return null without being called.
+///
+/// Only the first [`StrictArguments::MAX`] arguments can be strict.
+///
+/// # Example
+///
+/// ```
+/// use arrow_udf_core::{CallMode, StrictArguments};
+///
+/// // skip the call if the first argument is null, but pass nulls of the second one
+/// let mode = CallMode::ReturnNullOnNullArguments(StrictArguments::from_indices([0]));
+/// assert!(mode.is_strict_argument(0));
+/// assert!(!mode.is_strict_argument(1));
+/// assert_eq!(mode.to_string(), "return_null_on_null_arguments(0)");
+/// assert_eq!("return_null_on_null_arguments(0)".parse::<CallMode>().unwrap(), mode);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StrictArguments(u64);
+
+impl StrictArguments {
+    /// The maximum number of arguments that can be strict.
+    pub const MAX: usize = u64::BITS as usize;
+
+    /// Create an empty set of strict arguments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a set of strict arguments from their indices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is not less than [`StrictArguments::MAX`].
+    pub fn from_indices(indices: impl IntoIterator<Item = usize>) -> Self {
+        indices.into_iter().fold(Self::new(), Self::with)
+    }
+
+    /// Add the argument at the index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is not less than [`StrictArguments::MAX`].
+    pub fn with(self, index: usize) -> Self {
+        assert!(
+            index < Self::MAX,
+            "argument {index} can not be strict, only the first {} arguments can",
+            Self::MAX
+        );
+        Self(self.0 | 1 << index)
+    }
+
+    /// Returns true if the argument at the index is strict.
+    pub fn contains(&self, index: usize) -> bool {
+        index < Self::MAX && self.0 & (1 << index) != 0
+    }
+
+    /// Returns true if no argument is strict.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the indices of the strict arguments in ascending order.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..Self::MAX).filter(|&i| self.contains(i))
+    }
+}
+
+impl CallMode {
+    /// Returns true if the function returns null on null input without being called,
+    /// which is declared as `STRICT` in SQL.
+    ///
+    /// Functions with [`CallMode::ReturnNullOnNullArguments`] are not strict,
+    /// since they are called when only their other arguments are null.
+    pub fn is_strict(&self) -> bool {
+        *self == CallMode::ReturnNullOnNullInput
+    }
+
+    /// Returns true if the function returns null without being called when the argument at the index is null.
+    pub fn is_strict_argument(&self, index: usize) -> bool {
+        match self {
+            CallMode::CalledOnNullInput => false,
+            CallMode::ReturnNullOnNullInput => true,
+            CallMode::ReturnNullOnNullArguments(arguments) => arguments.contains(index),
+        }
+    }
+
+    /// Returns true if an aggregate function skips null states when merging them,
+    /// and returns null for null states without calling `finish`, which is the case if any argument is strict.
+    pub fn skips_null_states(&self) -> bool {
+        match self {
+            CallMode::CalledOnNullInput => false,
+            CallMode::ReturnNullOnNullInput => true,
+            CallMode::ReturnNullOnNullArguments(arguments) => !arguments.is_empty(),
+        }
+    }
+
+    /// Returns true if any strict argument of a row is null, so that the function returns null without being called.
+    ///
+    /// Each item is the array of an argument and the index of the value in the array.
+    pub fn is_null_input<'a>(
+        &self,
+        values: impl IntoIterator<Item = (&'a dyn Array, usize)>,
+    ) -> bool {
+        (values.into_iter().enumerate())
+            .any(|(i, (array, index))| self.is_strict_argument(i) && array.is_null(index))
+    }
+
+    /// Returns true if the function returns null at a row without being called,
+    /// because a strict argument is null, or contains a nested null and the policy propagates it.
+    ///
+    /// Each item is the array of an argument and the index of the value in the array.
+    /// Fails if a strict argument contains a nested null and the policy is [`NestedNullPolicy::Error`],
+    /// unless another strict argument is null.
+    pub fn skips_row<'a>(
+        &self,
+        nested_nulls: NestedNullPolicy,
+        values: impl IntoIterator<Item = (&'a dyn Array, usize)>,
+    ) -> Result<bool> {
+        if *self == CallMode::CalledOnNullInput {
+            return Ok(false);
+        }
+        // the first strict argument with a nested null
+        let mut nested = None;
+        for (i, (array, index)) in values.into_iter().enumerate() {
+            if !self.is_strict_argument(i) {
+                continue;
+            }
+            if array.is_null(index) {
+                return Ok(true);
+            }
+            if nested.is_none()
+                && nested_nulls != NestedNullPolicy::PassThrough
+                && has_nested_null(array, index)
+            {
+                nested = Some(i);
+            }
+        }
+        match nested {
+            Some(i) if nested_nulls == NestedNullPolicy::Error => Err(nested_null_error(i)),
+            Some(_) => Ok(true),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Implement `Display` and `FromStr` with the names of variants.
+macro_rules! impl_names {
+    ($ty:ident, $what:literal { $($variant:ident => $name:literal),* $(,)? }) => {
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(match self {
+                    $(Self::$variant => $name,)*
+                })
+            }
+        }
+
+        impl FromStr for $ty {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                match s {
+                    $($name => Ok(Self::$variant),)*
+                    _ => Err(Error::Other(anyhow!(concat!("invalid ", $what, ": {}"), s))),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use impl_names;
+
+impl fmt::Display for CallMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallMode::CalledOnNullInput => f.write_str("called_on_null_input"),
+            CallMode::ReturnNullOnNullInput => f.write_str("return_null_on_null_input"),
+            CallMode::ReturnNullOnNullArguments(arguments) => {
+                f.write_str("return_null_on_null_arguments(")?;
+                for (i, index) in arguments.indices().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{index}")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl FromStr for CallMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Other(anyhow!("invalid call mode: {}", s));
+        match s {
+            "called_on_null_input" => Ok(CallMode::CalledOnNullInput),
+            "return_null_on_null_input" => Ok(CallMode::ReturnNullOnNullInput),
+            _ => {
+                let indices = (s.strip_prefix("return_null_on_null_arguments("))
+                    .and_then(|s| s.strip_suffix(')'))
+                    .ok_or_else(invalid)?;
+                let mut arguments = StrictArguments::new();
+                for index in indices.split(',').filter(|i| !i.is_empty()) {
+                    let index: usize = index.trim().parse().map_err(|_| invalid())?;
+                    if index >= StrictArguments::MAX {
+                        return Err(invalid());
+                    }
+                    arguments = arguments.with(index);
+                }
+                Ok(CallMode::ReturnNullOnNullArguments(arguments))
+            }
+        }
+    }
+}
+impl_names!(Volatility, "volatility" {
+    Immutable => "immutable",
+    Stable => "stable",
+    Volatile => "volatile",
+});
+impl_names!(ParallelSafety, "parallel safety" {
+    Safe => "safe",
+    Restricted => "restricted",
+    Unsafe => "unsafe",
+});
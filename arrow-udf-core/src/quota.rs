@@ -0,0 +1,328 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Admission control of function calls.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
+use arrow_schema::{Field, FieldRef};
+
+use crate::{
+    Arguments, CallMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionProperties,
+    RecordBatchIter, Result, UdfRuntime,
+};
+
+/// Limits of concurrent calls shared by runtimes wrapped in [`QuotaRuntime`].
+///
+/// A call is admitted while the number of running calls is below the limit,
+/// both in total and of its function. Otherwise it waits in a queue,
+/// and fails with [`Error::ResourceExceeded`] if the queue is full or the timeout expires,
+/// so that a burst of calls is rejected early instead of piling up on the interpreters.
+///
+/// All limits are unlimited by default.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use arrow_udf_core::QuotaManager;
+///
+/// let quota = QuotaManager::new()
+///     .with_max_concurrent_calls(8)
+///     .with_max_queued_calls(64)
+///     .with_function_limit("embed", 2)
+///     .with_queue_timeout(Duration::from_secs(10));
+/// let permit = quota.acquire("embed").unwrap();
+/// assert_eq!(quota.running(), 1);
+/// drop(permit);
+/// assert_eq!(quota.running(), 0);
+/// ```
+#[derive(Debug, Default)]
+pub struct QuotaManager {
+    max_calls: Option<usize>,
+    max_queued: Option<usize>,
+    /// Function name -> maximum number of concurrent calls.
+    function_limits: HashMap<String, usize>,
+    queue_timeout: Option<Duration>,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    queued: usize,
+    /// Function name -> number of running calls.
+    functions: HashMap<String, usize>,
+}
+
+impl QuotaManager {
+    /// Create a manager without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of calls running at the same time.
+    pub fn with_max_concurrent_calls(mut self, n: usize) -> Self {
+        self.max_calls = Some(n);
+        self
+    }
+
+    /// Set the maximum number of calls, each with its input batch, waiting to be admitted.
+    ///
+    /// With 0, calls beyond the concurrency limits fail immediately.
+    pub fn with_max_queued_calls(mut self, n: usize) -> Self {
+        self.max_queued = Some(n);
+        self
+    }
+
+    /// Set the maximum number of calls of a function running at the same time.
+    pub fn with_function_limit(mut self, name: &str, n: usize) -> Self {
+        self.function_limits.insert(name.to_string(), n);
+        self
+    }
+
+    /// Set the maximum time a call waits in the queue.
+    pub fn with_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the number of running calls.
+    pub fn running(&self) -> usize {
+        self.state.lock().unwrap().running
+    }
+
+    /// Returns the number of calls waiting to be admitted.
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().queued
+    }
+
+    /// Wait until a call of the function is admitted, returning a permit released when dropped.
+    pub fn acquire(&self, name: &str) -> Result<QuotaPermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if !self.admissible(&state, name) {
+            if let Some(max_queued) = self.max_queued {
+                if state.queued >= max_queued {
+                    return Err(Error::ResourceExceeded(format!(
+                        "too many calls waiting for quota: {max_queued} queued"
+                    )));
+                }
+            }
+            state.queued += 1;
+            let deadline = self.queue_timeout.map(|timeout| Instant::now() + timeout);
+            while !self.admissible(&state, name) {
+                state = match deadline {
+                    None => self.released.wait(state).unwrap(),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            state.queued -= 1;
+                            return Err(Error::ResourceExceeded(format!(
+                                "timed out waiting for quota of function {name}"
+                            )));
+                        }
+                        self.released.wait_timeout(state, deadline - now).unwrap().0
+                    }
+                };
+            }
+            state.queued -= 1;
+        }
+        state.running += 1;
+        *state.functions.entry(name.to_string()).or_default() += 1;
+        Ok(QuotaPermit {
+            manager: self,
+            name: name.to_string(),
+        })
+    }
+
+    /// Whether a call of the function can run now.
+    fn admissible(&self, state: &State, name: &str) -> bool {
+        let function_running = state.functions.get(name).copied().unwrap_or(0);
+        self.max_calls.is_none_or(|max| state.running < max)
+            && (self.function_limits.get(name)).is_none_or(|max| function_running < *max)
+    }
+
+    fn release(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.running -= 1;
+        if let Some(running) = state.functions.get_mut(name) {
+            *running -= 1;
+            if *running == 0 {
+                state.functions.remove(name);
+            }
+        }
+        self.released.notify_all();
+    }
+}
+
+/// A call admitted by [`QuotaManager`], released when dropped.
+#[derive(Debug)]
+pub struct QuotaPermit<'a> {
+    manager: &'a QuotaManager,
+    name: String,
+}
+
+impl Drop for QuotaPermit<'_> {
+    fn drop(&mut self) {
+        self.manager.release(&self.name);
+    }
+}
+
+/// A runtime whose calls are admitted by a [`QuotaManager`], which may be shared with other runtimes.
+///
+/// A permit is held for the duration of each call,
+/// and while the iterator of a table function is alive.
+///
+/// # Example
+///
+/// ```ignore
+/// let quota = Arc::new(QuotaManager::new().with_max_concurrent_calls(8));
+/// let js = QuotaRuntime::new(Box::new(arrow_udf_js::Runtime::new()?), quota.clone());
+/// let python = QuotaRuntime::new(Box::new(arrow_udf_python::Runtime::new()?), quota);
+/// ```
+pub struct QuotaRuntime {
+    inner: Box<dyn UdfRuntime>,
+    quota: Arc<QuotaManager>,
+}
+
+impl QuotaRuntime {
+    /// Wrap a runtime to admit its calls by the manager.
+    pub fn new(inner: Box<dyn UdfRuntime>, quota: Arc<QuotaManager>) -> Self {
+        Self { inner, quota }
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        self.inner.as_ref()
+    }
+
+    /// Returns the quota manager.
+    pub fn quota(&self) -> &Arc<QuotaManager> {
+        &self.quota
+    }
+
+    /// Call `f` with a permit of the function.
+    fn admit<T>(&self, name: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let _permit = self.quota.acquire(name)?;
+        f()
+    }
+}
+
+impl UdfRuntime for QuotaRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.inner.add_function(name, return_field, mode, code)
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function_with_properties(name, return_field, properties, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.admit(name, || self.inner.call(name, input))
+    }
+
+    fn call_broadcast(&self, name: &str, args: &Arguments) -> Result<RecordBatch> {
+        self.admit(name, || self.inner.call_broadcast(name, args))
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        self.admit(name, || self.inner.call_cancellable(name, input, token))
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        self.admit(name, || self.inner.call_selected(name, input, selection))
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        let permit = self.quota.acquire(name)?;
+        let mut iter = self.inner.call_table_function(name, input, chunk_size)?;
+        Ok(Box::new(std::iter::from_fn(move || {
+            // release the permit with the iterator
+            let _permit = &permit;
+            iter.next()
+        })))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.inner.functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.inner.create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.admit(name, || self.inner.accumulate(name, state, input))
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.admit(name, || self.inner.merge(name, states))
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.admit(name, || self.inner.finish(name, states))
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
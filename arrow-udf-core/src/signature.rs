@@ -0,0 +1,465 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Checking inputs against the signatures of functions before dispatching them to runtimes.
+
+use std::collections::HashMap;
+
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
+use arrow_schema::{DataType, Field, FieldRef};
+
+use crate::{
+    Arguments, CallMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionProperties,
+    RecordBatchIter, Result, UdfRuntime,
+};
+
+/// Check that the input fields match the argument types of a function.
+///
+/// Returns [`Error::TypeMismatch`] naming the first mismatched argument,
+/// with a hint if the input can be cast to the expected type without loss.
+///
+/// # Example
+///
+/// ```
+/// # use arrow_schema::{DataType, Field};
+/// let input = [Field::new("x", DataType::Int32, true).into()];
+/// let err = arrow_udf_core::check_arguments("f", &[DataType::Int64], &input).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "type mismatch: argument 1 (x) of function f: expected Int64, got Int32; it can be cast to Int64 without loss"
+/// );
+/// ```
+pub fn check_arguments(function: &str, expected: &[DataType], input: &[FieldRef]) -> Result<()> {
+    if expected.len() != input.len() {
+        return Err(Error::TypeMismatch(format!(
+            "function {function} expects {} argument{}, got {}",
+            expected.len(),
+            if expected.len() == 1 { "" } else { "s" },
+            input.len()
+        )));
+    }
+    for (i, (expected, field)) in expected.iter().zip(input).enumerate() {
+        let actual = field.data_type();
+        if actual == expected {
+            continue;
+        }
+        let mut message = format!(
+            "argument {} ({}) of function {function}: expected {expected}, got {actual}",
+            i + 1,
+            field.name(),
+        );
+        if is_implicit_cast(actual, expected) {
+            message += &format!("; it can be cast to {expected} without loss");
+        }
+        return Err(Error::TypeMismatch(message));
+    }
+    Ok(())
+}
+
+/// Check that the output of a function matches its declared return field.
+///
+/// The data types must be equal, including the names and nullability of nested fields
+/// and the timezones of timestamps, and non-nullable fields must not contain nulls.
+/// Returns [`Error::TypeMismatch`] with the path to the first mismatch.
+///
+/// # Example
+///
+/// ```
+/// # use arrow_array::Int64Array;
+/// # use arrow_schema::{DataType, Field};
+/// let expected = Field::new("x", DataType::Int32, true);
+/// let actual = Field::new("x", DataType::Int64, true);
+/// let array = Int64Array::from(vec![1]);
+/// let err = arrow_udf_core::check_output("f", &expected, &actual, &array).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "type mismatch: return value of function f at `x`: expected Int32, got Int64"
+/// );
+/// ```
+pub fn check_output(
+    function: &str,
+    expected: &Field,
+    actual: &Field,
+    array: &dyn Array,
+) -> Result<()> {
+    let mut path = expected.name().clone();
+    let mismatch = diff_types(expected.data_type(), actual.data_type(), &mut path)
+        .or_else(|| check_nulls(expected, array, &mut path));
+    match mismatch {
+        Some(message) => Err(Error::TypeMismatch(format!(
+            "return value of function {function} at `{path}`: {message}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Describe the first difference between two types, extending `path` to it.
+fn diff_types(expected: &DataType, actual: &DataType, path: &mut String) -> Option<String> {
+    use DataType::*;
+    if expected == actual {
+        return None;
+    }
+    match (expected, actual) {
+        (Struct(expected), Struct(actual)) => {
+            if expected.len() != actual.len() {
+                return Some(format!(
+                    "expected {} fields, got {}",
+                    expected.len(),
+                    actual.len()
+                ));
+            }
+            expected
+                .iter()
+                .zip(actual)
+                .find_map(|(e, a)| diff_fields(e, a, path))
+        }
+        (List(e), List(a)) | (LargeList(e), LargeList(a)) | (Map(e, _), Map(a, _)) => {
+            diff_fields(e, a, path)
+        }
+        (FixedSizeList(e, n), FixedSizeList(a, m)) if n == m => diff_fields(e, a, path),
+        _ => Some(format!("expected {expected}, got {actual}")),
+    }
+}
+
+/// Describe the first difference between two nested fields.
+fn diff_fields(expected: &Field, actual: &Field, path: &mut String) -> Option<String> {
+    if expected.name() != actual.name() {
+        return Some(format!(
+            "expected field `{}`, got `{}`",
+            expected.name(),
+            actual.name()
+        ));
+    }
+    path.push('.');
+    path.push_str(expected.name());
+    if expected.is_nullable() != actual.is_nullable() {
+        return Some(match expected.is_nullable() {
+            true => "expected nullable field, got non-nullable".to_string(),
+            false => "expected non-nullable field, got nullable".to_string(),
+        });
+    }
+    let diff = diff_types(expected.data_type(), actual.data_type(), path);
+    if diff.is_none() {
+        path.truncate(path.len() - expected.name().len() - 1);
+    }
+    diff
+}
+
+/// Describe nulls in non-nullable fields of the array, extending `path` to them.
+///
+/// Nested fields are checked in struct arrays, where the nulls under null parents are ignored.
+fn check_nulls(field: &Field, array: &dyn Array, path: &mut String) -> Option<String> {
+    if !field.is_nullable() && array.null_count() > 0 {
+        return Some(format!(
+            "{} nulls in non-nullable field",
+            array.null_count()
+        ));
+    }
+    let (DataType::Struct(fields), Some(array)) = (field.data_type(), array.as_struct_opt()) else {
+        return None;
+    };
+    for (field, column) in fields.iter().zip(array.columns()) {
+        path.push('.');
+        path.push_str(field.name());
+        let nulls = match array.nulls() {
+            // nulls under null parents
+            Some(parent) => (0..array.len())
+                .filter(|i| parent.is_valid(*i) && column.is_null(*i))
+                .count(),
+            None => column.null_count(),
+        };
+        if !field.is_nullable() && nulls > 0 {
+            return Some(format!("{nulls} nulls in non-nullable field"));
+        }
+        if let Some(message) = check_nulls(&Field::clone(field).with_nullable(true), column, path) {
+            return Some(message);
+        }
+        path.truncate(path.len() - field.name().len() - 1);
+    }
+    None
+}
+
+/// Returns true if values of type `from` can be cast to type `to` without loss.
+pub fn is_implicit_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    if from == to {
+        return true;
+    }
+    match (from, to) {
+        (Null, _) => true,
+        (Int8, Int16 | Int32 | Int64 | Float32 | Float64) => true,
+        (Int16, Int32 | Int64 | Float32 | Float64) => true,
+        (Int32, Int64 | Float64) => true,
+        (UInt8, UInt16 | UInt32 | UInt64 | Int16 | Int32 | Int64 | Float32 | Float64) => true,
+        (UInt16, UInt32 | UInt64 | Int32 | Int64 | Float32 | Float64) => true,
+        (UInt32, UInt64 | Int64 | Float64) => true,
+        (Float16, Float32 | Float64) => true,
+        (Float32, Float64) => true,
+        (Utf8, LargeUtf8 | Utf8View) => true,
+        (Binary, LargeBinary | BinaryView) => true,
+        (Date32, Date64) => true,
+        (Decimal128(p1, s1), Decimal128(p2, s2) | Decimal256(p2, s2)) => {
+            s2 >= s1 && (*p2 as i16 - *s2 as i16) >= (*p1 as i16 - *s1 as i16)
+        }
+        (List(a) | LargeList(a), LargeList(b)) | (List(a), List(b)) => {
+            is_implicit_cast(a.data_type(), b.data_type())
+        }
+        _ => false,
+    }
+}
+
+/// A runtime that checks inputs against declared signatures before calling the inner runtime.
+///
+/// Runtimes of dynamic languages don't know the argument types of their functions,
+/// and would otherwise fail in converting the values.
+/// Functions without a declared signature are called without checks.
+///
+/// With [`with_strict_outputs`](CheckedRuntime::with_strict_outputs), the outputs of functions
+/// added through this runtime are also checked against their return fields by [`check_output`],
+/// so that a mismatched output fails the call instead of crashing the engine later.
+///
+/// # Example
+///
+/// ```ignore
+/// let runtime = CheckedRuntime::new(Box::new(arrow_udf_js::Runtime::new()?))
+///     .with_signature("gcd", vec![DataType::Int32, DataType::Int32]);
+/// ```
+pub struct CheckedRuntime {
+    inner: Box<dyn UdfRuntime>,
+    /// Function name -> argument types.
+    signatures: HashMap<String, Vec<DataType>>,
+    /// Function name -> return field.
+    returns: HashMap<String, Field>,
+    strict_outputs: bool,
+}
+
+impl CheckedRuntime {
+    /// Wrap a runtime without declared signatures.
+    pub fn new(inner: Box<dyn UdfRuntime>) -> Self {
+        Self {
+            inner,
+            signatures: HashMap::new(),
+            returns: HashMap::new(),
+            strict_outputs: false,
+        }
+    }
+
+    /// Check the outputs of functions against their return fields. Disabled by default.
+    pub fn with_strict_outputs(mut self, strict: bool) -> Self {
+        self.strict_outputs = strict;
+        self
+    }
+
+    /// Declare the argument types of a function.
+    pub fn with_signature(mut self, name: &str, arg_types: Vec<DataType>) -> Self {
+        self.add_signature(name, arg_types);
+        self
+    }
+
+    /// Declare the argument types of a function, replacing the previous declaration.
+    pub fn add_signature(&mut self, name: &str, arg_types: Vec<DataType>) {
+        self.signatures.insert(name.to_string(), arg_types);
+    }
+
+    /// Declare the return field of a function, whose outputs are checked if strict.
+    ///
+    /// Return fields of functions added through this runtime are declared automatically.
+    pub fn with_return_field(mut self, name: &str, field: Field) -> Self {
+        self.returns.insert(name.to_string(), field);
+        self
+    }
+
+    /// Returns the declared argument types of a function.
+    pub fn signature(&self, name: &str) -> Option<&[DataType]> {
+        self.signatures.get(name).map(|s| s.as_slice())
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        self.inner.as_ref()
+    }
+
+    /// Check the input fields against the signature of a function, if declared.
+    fn check(&self, name: &str, input: &[FieldRef]) -> Result<()> {
+        match self.signatures.get(name) {
+            Some(arg_types) => check_arguments(name, arg_types, input),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the output column at `index` against the return field of a function, if strict.
+    ///
+    /// Rows that are not evaluated may be null, so `nullable` relaxes the return field.
+    fn check_output(
+        &self,
+        name: &str,
+        output: &RecordBatch,
+        index: usize,
+        nullable: bool,
+    ) -> Result<()> {
+        let Some(expected) = self.returns.get(name).filter(|_| self.strict_outputs) else {
+            return Ok(());
+        };
+        if index >= output.num_columns() {
+            return Err(Error::TypeMismatch(format!(
+                "return value of function {name}: missing in the output"
+            )));
+        }
+        let expected = expected
+            .clone()
+            .with_nullable(expected.is_nullable() || nullable);
+        let schema = output.schema();
+        check_output(name, &expected, schema.field(index), output.column(index))
+    }
+
+    /// Call a scalar function with `f` and check its output.
+    fn call_checked(
+        &self,
+        name: &str,
+        nullable: bool,
+        f: impl FnOnce() -> Result<RecordBatch>,
+    ) -> Result<RecordBatch> {
+        let output = f()?;
+        self.check_output(name, &output, 0, nullable)?;
+        Ok(output)
+    }
+}
+
+impl UdfRuntime for CheckedRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function(name, return_field.clone(), mode, code)?;
+        self.returns.insert(name.to_string(), return_field);
+        Ok(())
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function_with_properties(name, return_field.clone(), properties, code)?;
+        self.returns.insert(name.to_string(), return_field);
+        Ok(())
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.check(name, input.schema().fields())?;
+        self.call_checked(name, false, || self.inner.call(name, input))
+    }
+
+    fn call_broadcast(&self, name: &str, args: &Arguments) -> Result<RecordBatch> {
+        self.check(name, args.fields())?;
+        self.call_checked(name, false, || self.inner.call_broadcast(name, args))
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        self.check(name, input.schema().fields())?;
+        self.call_checked(name, false, || {
+            self.inner.call_cancellable(name, input, token)
+        })
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        self.check(name, input.schema().fields())?;
+        self.call_checked(name, true, || {
+            self.inner.call_selected(name, input, selection)
+        })
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        self.check(name, input.schema().fields())?;
+        let iter = self.inner.call_table_function(name, input, chunk_size)?;
+        // the outputs start with the row index
+        Ok(Box::new(iter.map(move |output| {
+            let output = output?;
+            self.check_output(name, &output, 1, false)?;
+            Ok(output)
+        })))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        let mut functions = self.inner.functions();
+        for function in &mut functions {
+            if let Some(arg_types) = self.signatures.get(&function.name) {
+                function.arg_types = Some(arg_types.clone());
+            }
+            if let Some(field) = self.returns.get(&function.name) {
+                function.return_field = Some(field.clone().into());
+            }
+        }
+        functions
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.inner.create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.check(name, input.schema().fields())?;
+        self.inner.accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.inner.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.inner.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
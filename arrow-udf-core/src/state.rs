@@ -0,0 +1,116 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! The serialization format of aggregate states shared by all runtimes.
+//!
+//! A serialized state is an Arrow IPC stream with a single batch of one column named `state`.
+//! The field keeps the state type and its extension metadata, and the schema metadata records
+//! the name of the aggregate function and the version of the format.
+//! Since the format only depends on Arrow, partial states computed in one runtime can be merged in
+//! another runtime that defines the aggregate with the same state type.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{Field, FieldRef, Schema};
+
+use crate::{Error, Result};
+
+/// The schema metadata key of the aggregate function name.
+pub const STATE_FUNCTION_KEY: &str = "arrowudf.aggregate";
+
+/// The schema metadata key of the format version.
+pub const STATE_VERSION_KEY: &str = "arrowudf.state_version";
+
+/// The current version of the format.
+pub const STATE_VERSION: &str = "1";
+
+/// The name of the state column.
+const STATE_COLUMN: &str = "state";
+
+/// Serialize the states of an aggregate function.
+pub fn encode_state(function: &str, field: &Field, states: &ArrayRef) -> Result<Vec<u8>> {
+    let field = field.clone().with_name(STATE_COLUMN);
+    let metadata = HashMap::from([
+        (STATE_FUNCTION_KEY.to_string(), function.to_string()),
+        (STATE_VERSION_KEY.to_string(), STATE_VERSION.to_string()),
+    ]);
+    let schema = Arc::new(Schema::new_with_metadata(vec![field], metadata));
+    let batch = RecordBatch::try_new(schema.clone(), vec![states.clone()])
+        .map_err(|e| Error::TypeMismatch(e.to_string()))?;
+    let mut bytes = vec![];
+    let mut writer = StreamWriter::try_new(&mut bytes, &schema).context("failed to write state")?;
+    writer.write(&batch).context("failed to write state")?;
+    writer.finish().context("failed to write state")?;
+    drop(writer);
+    Ok(bytes)
+}
+
+/// Deserialize the states of an aggregate function.
+///
+/// Returns the name of the function, the state field and the states.
+pub fn decode_state(bytes: &[u8]) -> Result<(String, FieldRef, ArrayRef)> {
+    let mut reader = StreamReader::try_new(bytes, None).context("failed to read state")?;
+    let schema = reader.schema();
+    match schema.metadata().get(STATE_VERSION_KEY) {
+        Some(version) if version == STATE_VERSION => {}
+        Some(version) => return Err(anyhow!("unsupported state version: {version}").into()),
+        None => return Err(anyhow!("missing state version").into()),
+    }
+    let function = schema
+        .metadata()
+        .get(STATE_FUNCTION_KEY)
+        .cloned()
+        .unwrap_or_default();
+    if schema.fields().len() != 1 {
+        return Err(anyhow!("expect 1 state column, got {}", schema.fields().len()).into());
+    }
+    let batch = reader
+        .next()
+        .ok_or_else(|| anyhow!("missing state batch"))?
+        .context("failed to read state")?;
+    if reader.next().is_some() {
+        return Err(anyhow!("expect 1 state batch").into());
+    }
+    Ok((
+        function,
+        schema.fields()[0].clone(),
+        batch.column(0).clone(),
+    ))
+}
+
+/// Check that a decoded state field can be used as the state of an aggregate function.
+///
+/// The names of fields are ignored, since runtimes may name the state differently.
+pub(crate) fn check_state_field(expected: &Field, actual: &Field) -> Result<()> {
+    if expected.data_type() != actual.data_type() {
+        return Err(Error::TypeMismatch(format!(
+            "expect state of type {}, got {}",
+            expected.data_type(),
+            actual.data_type()
+        )));
+    }
+    let extension = |f: &Field| f.metadata().get("ARROW:extension:name").cloned();
+    if extension(expected) != extension(actual) {
+        return Err(Error::TypeMismatch(format!(
+            "expect state of extension type {:?}, got {:?}",
+            extension(expected),
+            extension(actual)
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Asynchronous streams of table function outputs.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::{executor, FutureExt, SinkExt, Stream};
+
+use crate::{Error, Result, UdfRuntime};
+
+/// An asynchronous stream of the output batches of a table function.
+pub type RecordBatchStream = Pin<Box<dyn Stream<Item = Result<RecordBatch>> + Send + 'static>>;
+
+/// Call table functions asynchronously, returning their outputs as a stream.
+///
+/// This is implemented by [`StreamingRuntime`] for local runtimes and by the
+/// client of `arrow-udf-flight` for remote ones, so that async operators of an engine
+/// can consume set-returning functions without blocking on them.
+///
+/// The streams are pull-based: a producer buffers a bounded number of batches ahead
+/// of the consumer and then waits until they are polled, and dropping the stream stops it.
+pub trait AsyncTableFunction: Send + Sync {
+    /// Call a table function.
+    ///
+    /// `chunk_size` is the maximum number of rows in each output batch.
+    /// Implementations that decide the batch size by themselves may ignore it.
+    fn call_table_function_stream<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> BoxFuture<'a, Result<RecordBatchStream>>;
+}
+
+/// Run table functions of a local runtime on a background thread and stream their outputs.
+///
+/// The thread evaluates the function until `buffer` batches are waiting to be polled,
+/// and stops at the next batch once the stream is dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let runtime = StreamingRuntime::new(Arc::new(js_runtime)).with_buffer(4);
+/// let mut outputs = runtime.call_table_function_stream("series", &input, 1024).await?;
+/// while let Some(batch) = outputs.next().await {
+///     println!("{}", pretty_format_batches(&[batch?])?);
+/// }
+/// ```
+pub struct StreamingRuntime {
+    inner: Arc<dyn UdfRuntime>,
+    buffer: usize,
+}
+
+impl StreamingRuntime {
+    /// Wrap a runtime, buffering 1 batch ahead of the consumer.
+    pub fn new(inner: Arc<dyn UdfRuntime>) -> Self {
+        Self { inner, buffer: 1 }
+    }
+
+    /// Set the maximum number of batches evaluated ahead of the consumer, at least 1.
+    pub fn with_buffer(mut self, batches: usize) -> Self {
+        self.buffer = batches.max(1);
+        self
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &Arc<dyn UdfRuntime> {
+        &self.inner
+    }
+}
+
+impl AsyncTableFunction for StreamingRuntime {
+    fn call_table_function_stream<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> BoxFuture<'a, Result<RecordBatchStream>> {
+        let runtime = self.inner.clone();
+        let name = name.to_string();
+        let input = input.clone();
+        // the sender has a slot of its own in addition to the capacity
+        let (mut tx, rx) = mpsc::channel(self.buffer - 1);
+        let spawned = std::thread::Builder::new()
+            .name(format!("udtf-{name}"))
+            .spawn(move || {
+                let iter = match runtime.call_table_function(&name, &input, chunk_size) {
+                    Ok(iter) => iter,
+                    Err(e) => {
+                        _ = executor::block_on(tx.send(Err(e)));
+                        return;
+                    }
+                };
+                for batch in iter {
+                    // the stream has been dropped
+                    if executor::block_on(tx.send(batch)).is_err() {
+                        return;
+                    }
+                }
+            });
+        async move {
+            spawned.map_err(|e| Error::Other(e.into()))?;
+            Ok(Box::pin(rx) as RecordBatchStream)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tolerate a bounded number of row errors in each call.
+
+use std::sync::Arc;
+
+use arrow_array::builder::StringBuilder;
+use arrow_array::{new_null_array, Array, ArrayRef, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, FieldRef, Schema};
+use arrow_select::concat::concat;
+
+use crate::{
+    CallMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionProperties, RecordBatchIter,
+    Result, UdfRuntime,
+};
+
+/// Record errors of individual rows in an error column, until a budget of errors is exceeded.
+///
+/// The output has the return column followed by an `error` column of type `Utf8`
+/// if any row fails, where failed rows are null in the return column.
+/// Once more than `max_row_errors` rows fail in a call, the call fails with
+/// an [`Error::Execution`] at the first failed row, summarizing the number of failures.
+///
+/// Runtimes that already return an error column, e.g. Python, only have their errors counted.
+/// For other runtimes that stop at the first failed row, the rows after it are called again,
+/// and so are the rows before it, whose outputs are lost with the error.
+///
+/// # Example
+///
+/// ```ignore
+/// let runtime = TolerantRuntime::new(Box::new(arrow_udf_js::Runtime::new()?))
+///     .with_max_row_errors(10);
+/// ```
+pub struct TolerantRuntime {
+    inner: Box<dyn UdfRuntime>,
+    max_row_errors: usize,
+}
+
+/// A part of the output.
+enum Segment {
+    /// The output of a call on consecutive rows.
+    Output(RecordBatch),
+    /// The error of a row.
+    Failed(String),
+}
+
+impl TolerantRuntime {
+    /// Wrap a runtime without tolerating any row errors.
+    pub fn new(inner: Box<dyn UdfRuntime>) -> Self {
+        Self {
+            inner,
+            max_row_errors: 0,
+        }
+    }
+
+    /// Set the maximum number of failed rows in each call.
+    pub fn with_max_row_errors(mut self, n: usize) -> Self {
+        self.max_row_errors = n;
+        self
+    }
+
+    /// Returns the maximum number of failed rows in each call.
+    pub fn max_row_errors(&self) -> usize {
+        self.max_row_errors
+    }
+
+    /// Returns the inner runtime.
+    pub fn inner(&self) -> &dyn UdfRuntime {
+        &*self.inner
+    }
+
+    /// Call `f` on the remaining rows after each failed row and assemble the outputs.
+    fn tolerate(
+        &self,
+        input: &RecordBatch,
+        f: impl Fn(&RecordBatch) -> Result<RecordBatch>,
+    ) -> Result<RecordBatch> {
+        let num_rows = input.num_rows();
+        let mut segments = vec![];
+        let mut budget = Budget::new(self.max_row_errors);
+        let mut offset = 0;
+        while offset < num_rows || segments.is_empty() {
+            match f(&input.slice(offset, num_rows - offset)) {
+                Ok(output) => {
+                    budget.count(offset, &output)?;
+                    segments.push(Segment::Output(output));
+                    offset = num_rows;
+                }
+                Err(Error::Execution {
+                    row: Some(row),
+                    message,
+                    ..
+                }) if offset + row < num_rows => {
+                    if row > 0 {
+                        let output = f(&input.slice(offset, row))?;
+                        budget.count(offset, &output)?;
+                        segments.push(Segment::Output(output));
+                    }
+                    budget.fail(offset + row, &message)?;
+                    segments.push(Segment::Failed(message));
+                    offset += row + 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        // a single call without failed rows, which may have its own error column
+        if let [Segment::Output(output)] = segments.as_slice() {
+            return Ok(output.clone());
+        }
+        if !segments.iter().any(|s| matches!(s, Segment::Output(_))) {
+            // take the output fields from a call without rows
+            segments.insert(0, Segment::Output(f(&input.slice(0, 0))?));
+        }
+        assemble(&segments, num_rows)
+    }
+}
+
+/// Count the row errors of a call.
+struct Budget {
+    max_errors: usize,
+    errors: usize,
+    first: Option<(usize, String)>,
+}
+
+impl Budget {
+    fn new(max_errors: usize) -> Self {
+        Self {
+            max_errors,
+            errors: 0,
+            first: None,
+        }
+    }
+
+    /// Count the errors in the error column of an output starting at `offset`.
+    fn count(&mut self, offset: usize, output: &RecordBatch) -> Result<()> {
+        let Some(errors) = error_column(output) else {
+            return Ok(());
+        };
+        for (i, message) in errors.iter().enumerate() {
+            if let Some(message) = message {
+                self.fail(offset + i, message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Count an error of a row, and return the summary once the budget is exceeded.
+    fn fail(&mut self, row: usize, message: &str) -> Result<()> {
+        self.errors += 1;
+        let (first_row, first_message) =
+            (self.first).get_or_insert_with(|| (row, message.to_string()));
+        if self.errors <= self.max_errors {
+            return Ok(());
+        }
+        Err(Error::Execution {
+            row: Some(*first_row),
+            message: format!(
+                "{} rows failed, more than the limit of {}, the first error: {first_message}",
+                self.errors, self.max_errors
+            ),
+            traceback: None,
+        })
+    }
+}
+
+/// Returns the error column of an output, if any.
+pub(crate) fn error_column(output: &RecordBatch) -> Option<&StringArray> {
+    let schema = output.schema_ref();
+    let field = schema.fields().last()?;
+    if output.num_columns() < 2 || field.name() != "error" || field.data_type() != &DataType::Utf8 {
+        return None;
+    }
+    output.columns().last()?.as_any().downcast_ref()
+}
+
+/// Concatenate the segments into the return columns followed by an error column.
+fn assemble(segments: &[Segment], num_rows: usize) -> Result<RecordBatch> {
+    let template = segments
+        .iter()
+        .find_map(|s| match s {
+            Segment::Output(output) => Some(output),
+            Segment::Failed(_) => None,
+        })
+        .expect("no output");
+    let num_values = template.num_columns() - error_column(template).is_some() as usize;
+    let mut fields: Vec<FieldRef> = template.schema().fields()[..num_values]
+        .iter()
+        .map(|field| Field::clone(field).with_nullable(true).into())
+        .collect();
+    let mut columns = Vec::with_capacity(num_values + 1);
+    for (i, field) in fields.iter().enumerate() {
+        let null = new_null_array(field.data_type(), 1);
+        let arrays: Vec<&dyn Array> = (segments.iter())
+            .map(|s| match s {
+                Segment::Output(output) => output.column(i).as_ref(),
+                Segment::Failed(_) => null.as_ref(),
+            })
+            .collect();
+        columns.push(concat(&arrays)?);
+    }
+    let mut errors = StringBuilder::with_capacity(num_rows, 0);
+    for segment in segments {
+        match segment {
+            Segment::Output(output) => match error_column(output) {
+                Some(column) => column.iter().for_each(|e| errors.append_option(e)),
+                None => (0..output.num_rows()).for_each(|_| errors.append_null()),
+            },
+            Segment::Failed(message) => errors.append_value(message),
+        }
+    }
+    fields.push(Field::new("error", DataType::Utf8, true).into());
+    columns.push(Arc::new(errors.finish()) as ArrayRef);
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+impl UdfRuntime for TolerantRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.inner.add_function(name, return_field, mode, code)
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.inner
+            .add_function_with_properties(name, return_field, properties, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.tolerate(input, |input| self.inner.call(name, input))
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        self.tolerate(input, |input| {
+            self.inner.call_cancellable(name, input, token)
+        })
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        self.inner.call_table_function(name, input, chunk_size)
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.inner.list_aggregates()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        self.inner.functions()
+    }
+
+    fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        self.inner.create_state(name)
+    }
+
+    fn accumulate(&self, name: &str, state: &dyn Array, input: &RecordBatch) -> Result<ArrayRef> {
+        self.inner.accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        self.inner.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        self.inner.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> Result<FieldRef> {
+        self.inner.state_field(name)
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.inner.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
@@ -0,0 +1,1253 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the wrappers of runtimes, using a mock runtime whose functions are written in Rust.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Int32Type, Int64Type};
+use arrow_array::{
+    Array, ArrayRef, Int32Array, Int64Array, RecordBatch, StringArray, StructArray,
+    TimestampSecondArray,
+};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Fields, Schema, TimeUnit};
+use arrow_udf_core::*;
+use expect_test::{expect, Expect};
+
+#[test]
+fn test_pipeline() {
+    let mut runtime = Mock::default();
+    runtime.add("trim", DataType::Utf8);
+    runtime.add("upper", DataType::Utf8);
+    runtime.add("length", DataType::Int32);
+
+    let pipeline = Pipeline::new()
+        .with_output("upper", "upper(trim(name))".parse().unwrap())
+        .with_output("length", "length( trim(name) )".parse().unwrap())
+        .with_output("name", "name".parse().unwrap());
+    // `trim(name)` is evaluated only once
+    assert_eq!(pipeline.num_calls(), 3);
+
+    let schema = Schema::new(vec![Field::new("name", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec![Some("  hello "), None, Some("world")]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = pipeline.evaluate(&runtime, &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------+--------+----------+
+        | upper | length | name     |
+        +-------+--------+----------+
+        | HELLO | 5      |   hello  |
+        |       |        |          |
+        | WORLD | 5      | world    |
+        +-------+--------+----------+"#]],
+    );
+
+    let err = Pipeline::new()
+        .with_output("x", "upper(unknown)".parse().unwrap())
+        .evaluate(&runtime, &input)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "column not found: unknown");
+    "upper(name".parse::<Expr>().unwrap_err();
+}
+
+#[test]
+fn test_runtime_pool() {
+    let members = (0..3)
+        .map(|_| Box::new(Mock::default()) as Box<dyn UdfRuntime>)
+        .collect();
+    let mut pool = RuntimePool::new(members)
+        .unwrap()
+        .with_min_partition_rows(2);
+    pool.add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+    pool.add_function(
+        "series",
+        int32("series"),
+        CallMode::ReturnNullOnNullInput,
+        "series",
+    )
+    .unwrap();
+    assert_eq!(pool.list_functions().len(), 2);
+
+    let input = int32_input(vec![Some(1), None, Some(2), Some(3), Some(4)]);
+
+    // 5 rows are split into partitions of 3 and 2 rows
+    let output = pool.call("neg", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | neg |
+        +-----+
+        | -1  |
+        |     |
+        | -2  |
+        | -3  |
+        | -4  |
+        +-----+"#]],
+    );
+
+    // row indexes of the second partition are shifted
+    let outputs = pool
+        .call_table_function("series", &input, 10)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 2   | 0      |
+        | 2   | 1      |
+        | 3   | 0      |
+        | 3   | 1      |
+        | 3   | 2      |
+        | 4   | 0      |
+        | 4   | 1      |
+        | 4   | 2      |
+        | 4   | 3      |
+        +-----+--------+"#]],
+    );
+
+    RuntimePool::new(vec![]).err().unwrap();
+}
+
+#[test]
+fn test_runtime_pool_parallelism() {
+    let mut pool = RuntimePool::from_fn(4, || Ok(Box::new(Mock::default())))
+        .unwrap()
+        .with_parallelism(2);
+    assert_eq!(pool.members().len(), 4);
+    // each member counts the rows it has evaluated
+    pool.add_function(
+        "count",
+        int32("count"),
+        CallMode::CalledOnNullInput,
+        "count",
+    )
+    .unwrap();
+
+    let input = int32_input(vec![Some(1), None, Some(2), Some(3), Some(4)]);
+
+    // 5 rows are split into 2 partitions instead of 4
+    let output = pool.call("count", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------+
+        | count |
+        +-------+
+        | 1     |
+        | 2     |
+        | 3     |
+        | 1     |
+        | 2     |
+        +-------+"#]],
+    );
+}
+
+#[test]
+fn test_runtime_pool_row_errors() {
+    let mut pool =
+        RuntimePool::from_fn(2, || Ok(Box::new(Mock::default().with_error_column()))).unwrap();
+    pool.add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+
+    // only the output of the second partition has an error column
+    let input = int32_input(vec![Some(1), Some(2), Some(3), Some(0)]);
+    let output = pool.call("neg", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+-------+
+        | neg | error |
+        +-----+-------+
+        | -1  |       |
+        | -2  |       |
+        | -3  |       |
+        |     | zero  |
+        +-----+-------+"#]],
+    );
+
+    // the error in the second partition is at the row of the whole input
+    let mut pool = RuntimePool::from_fn(2, || Ok(Box::new(Mock::default()))).unwrap();
+    pool.add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+    let err = pool.call("neg", &input).unwrap_err();
+    assert!(err.is_user_error());
+    match err {
+        Error::Execution { row, .. } => assert_eq!(row, Some(3)),
+        e => panic!("unexpected error: {e}"),
+    }
+}
+
+#[test]
+fn test_partition_outputs() {
+    assert_eq!(partition_rows(10, 3, 1), vec![(0, 4), (4, 3), (7, 3)]);
+    assert_eq!(partition_rows(10, 3, 4), vec![(0, 5), (5, 5)]);
+    assert_eq!(partition_rows(0, 3, 1), vec![(0, 0)]);
+
+    // the error column of one output is padded with nulls in the others
+    let mut runtime = Mock::default().with_error_column();
+    runtime
+        .add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+    let outputs = vec![
+        runtime.call("neg", &int32_input(vec![Some(1)])).unwrap(),
+        runtime.call("neg", &int32_input(vec![Some(0)])).unwrap(),
+    ];
+    let outputs = unify_outputs(outputs).unwrap();
+    assert_eq!(outputs[0].schema(), outputs[1].schema());
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+-------+
+        | neg | error |
+        +-----+-------+
+        | -1  |       |
+        |     | zero  |
+        +-----+-------+"#]],
+    );
+}
+
+#[test]
+fn test_adaptive_batch_size() {
+    // latency never limits the chunk size
+    let mut runtime = AdaptiveRuntime::new(Box::new(Mock::default()))
+        .with_bounds(2, 16)
+        .with_target_latency(Duration::from_secs(3600))
+        .with_max_output_bytes(16 << 10);
+    runtime
+        .add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+    runtime
+        .add_function(
+            "repeat",
+            Field::new("repeat", DataType::Utf8, true),
+            CallMode::ReturnNullOnNullInput,
+            "repeat",
+        )
+        .unwrap();
+    assert_eq!(runtime.batch_size("neg"), 2);
+
+    let input = int32_input((1..=40).map(Some).collect());
+
+    // chunks of 2, 4, 8, 16 and 10 rows
+    let output = runtime.call("neg", &input).unwrap();
+    let expected = Int32Array::from((1..=40).map(|x| -x).collect::<Vec<_>>());
+    assert_eq!(output.column(0).as_ref(), &expected as &dyn Array);
+    assert_eq!(runtime.batch_size("neg"), 16);
+
+    // the error in the second chunk is at the row of the whole input
+    let input = int32_input((-17..3).map(Some).collect());
+    match runtime.call("neg", &input).unwrap_err() {
+        Error::Execution { row, .. } => assert_eq!(row, Some(17)),
+        e => panic!("unexpected error: {e}"),
+    }
+
+    // outputs of 4KB per row shrink the chunks to 4 rows
+    let input = int32_input(vec![Some(4096); 20]);
+    let output = runtime.call("repeat", &input).unwrap();
+    assert_eq!(output.num_rows(), 20);
+    assert!(runtime.batch_size("repeat") <= 4);
+}
+
+#[test]
+fn test_checked_runtime() {
+    let mut runtime = CheckedRuntime::new(Box::new(Mock::default()))
+        .with_signature("add", vec![DataType::Int64, DataType::Int64]);
+    runtime
+        .add_function(
+            "add",
+            Field::new("add", DataType::Int64, true),
+            CallMode::ReturnNullOnNullInput,
+            "add",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int64, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let arg0 = Int64Array::from(vec![1, 2]);
+    let arg1 = Int32Array::from(vec![3, 4]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let err = runtime.call("add", &input).unwrap_err();
+    assert!(matches!(err, Error::TypeMismatch(_)));
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: argument 2 (b) of function add: expected Int64, got Int32; it can be cast to Int64 without loss"
+    );
+    let err = runtime.call_with_input("add", &input).unwrap_err();
+    assert!(matches!(err, Error::TypeMismatch(_)));
+
+    let args = Arguments::from(&input.project(&[0]).unwrap());
+    let err = runtime.call_broadcast("add", &args).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: function add expects 2 arguments, got 1"
+    );
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int64, true),
+        Field::new("b", DataType::Utf8, true),
+    ]);
+    let arg1 = StringArray::from(vec!["3", "4"]);
+    let input = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![input.column(0).clone(), Arc::new(arg1)],
+    )
+    .unwrap();
+    let err = runtime.call("add", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: argument 2 (b) of function add: expected Int64, got Utf8"
+    );
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int64, true),
+        Field::new("b", DataType::Int64, true),
+    ]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![input.column(0).clone(); 2]).unwrap();
+    let output = runtime.call("add", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | add |
+        +-----+
+        | 2   |
+        | 4   |
+        +-----+"#]],
+    );
+}
+
+#[test]
+fn test_strict_outputs() {
+    let point = |x: bool, y: &str| {
+        DataType::Struct(Fields::from(vec![
+            Field::new("x", DataType::Int32, x),
+            Field::new(y, DataType::Int32, true),
+        ]))
+    };
+    let utc = DataType::Timestamp(TimeUnit::Second, Some("UTC".into()));
+    let mut mock = Mock::default();
+    mock.add("point", point(true, "y"));
+    mock.add("ts", DataType::Timestamp(TimeUnit::Second, None));
+
+    let input = int32_input(vec![Some(1), None, Some(2)]);
+
+    // the outputs of the runtime differ from the declared return fields
+    let cases = [
+        (
+            Field::new("point", point(true, "z"), true),
+            "at `point`: expected field `z`, got `y`",
+        ),
+        (
+            Field::new("point", point(false, "y"), true),
+            "at `point.x`: expected non-nullable field, got nullable",
+        ),
+    ];
+    let mut runtime = CheckedRuntime::new(Box::new(mock)).with_strict_outputs(true);
+    for (field, message) in cases {
+        runtime = runtime.with_return_field("point", field);
+        let err = runtime.call("point", &input).unwrap_err();
+        assert!(matches!(err, Error::TypeMismatch(_)));
+        assert_eq!(
+            err.to_string(),
+            format!("type mismatch: return value of function point {message}")
+        );
+    }
+    let err = runtime
+        .with_return_field("ts", Field::new("ts", utc, true))
+        .call("ts", &input)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"type mismatch: return value of function ts at `ts`: expected Timestamp(Second, Some("UTC")), got Timestamp(Second, None)"#
+    );
+
+    // functions added through the checked runtime are declared automatically
+    let mut runtime = CheckedRuntime::new(Box::new(Mock::default())).with_strict_outputs(true);
+    runtime
+        .add_function(
+            "point",
+            Field::new("point", point(true, "y"), true),
+            CallMode::ReturnNullOnNullInput,
+            "point",
+        )
+        .unwrap();
+    let output = runtime.call("point", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------------+
+        | point        |
+        +--------------+
+        | {x: 1, y: 1} |
+        |              |
+        | {x: 2, y: 2} |
+        +--------------+"#]],
+    );
+    runtime
+        .add_function(
+            "point",
+            Field::new("point", point(true, "z"), true),
+            CallMode::ReturnNullOnNullInput,
+            "point",
+        )
+        .unwrap();
+    let err = runtime.call("point", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: return value of function point at `point`: expected field `z`, got `y`"
+    );
+}
+
+#[test]
+fn test_metrics() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let mut runtime = MeteredRuntime::new(Box::new(Mock::default()), registry.clone());
+    runtime
+        .add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            int32("series"),
+            CallMode::ReturnNullOnNullInput,
+            "series",
+        )
+        .unwrap();
+
+    let input = int32_input(vec![Some(1), None, Some(2)]);
+
+    runtime.call("neg", &input).unwrap();
+    runtime.call("neg", &input).unwrap();
+    runtime.call("unknown", &input).unwrap_err();
+    let outputs = runtime
+        .call_table_function("series", &input, 2)
+        .unwrap()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(outputs.len(), 2);
+
+    let neg = registry.get("neg").unwrap();
+    assert_eq!((neg.calls, neg.errors), (2, 0));
+    assert_eq!((neg.input_rows, neg.output_rows), (6, 6));
+    assert!(neg.input_bytes > 0 && neg.output_bytes > 0);
+    let unknown = registry.get("unknown").unwrap();
+    assert_eq!((unknown.calls, unknown.errors), (1, 1));
+    let series = registry.get("series").unwrap();
+    assert_eq!(
+        (series.calls, series.input_rows, series.output_rows),
+        (1, 3, 3)
+    );
+
+    // latencies and memory sizes vary, only check the counts
+    let rendered = registry.render_prometheus();
+    let lines: Vec<_> = rendered
+        .lines()
+        .filter(|line| {
+            line.starts_with("arrow_udf_calls_total")
+                || line.starts_with("arrow_udf_errors_total")
+                || line.starts_with("arrow_udf_output_rows_total")
+                || line.starts_with("arrow_udf_call_duration_seconds_count")
+                || line.starts_with("# TYPE")
+        })
+        .collect();
+    expect![[r##"
+        # TYPE arrow_udf_calls_total counter
+        arrow_udf_calls_total{function="neg"} 2
+        arrow_udf_calls_total{function="series"} 1
+        arrow_udf_calls_total{function="unknown"} 1
+        # TYPE arrow_udf_errors_total counter
+        arrow_udf_errors_total{function="neg"} 0
+        arrow_udf_errors_total{function="series"} 0
+        arrow_udf_errors_total{function="unknown"} 1
+        # TYPE arrow_udf_input_rows_total counter
+        # TYPE arrow_udf_output_rows_total counter
+        arrow_udf_output_rows_total{function="neg"} 6
+        arrow_udf_output_rows_total{function="series"} 3
+        arrow_udf_output_rows_total{function="unknown"} 0
+        # TYPE arrow_udf_input_bytes_total counter
+        # TYPE arrow_udf_output_bytes_total counter
+        # TYPE arrow_udf_call_duration_seconds histogram
+        arrow_udf_call_duration_seconds_count{function="neg"} 2
+        arrow_udf_call_duration_seconds_count{function="series"} 1
+        arrow_udf_call_duration_seconds_count{function="unknown"} 1"##]]
+    .assert_eq(&lines.join("\n"));
+}
+
+#[test]
+fn test_metrics_sliced() {
+    let registry = Arc::new(MetricsRegistry::new());
+    let mut runtime = MeteredRuntime::new(Box::new(Mock::default()), registry.clone());
+    runtime
+        .add_function("neg", int32("neg"), CallMode::ReturnNullOnNullInput, "neg")
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, false)]);
+    let arg0 = Int32Array::from_iter_values(1..=1024);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let output = runtime.call("neg", &input.slice(100, 3)).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+
+        | neg  |
+        +------+
+        | -101 |
+        | -102 |
+        | -103 |
+        +------+"#]],
+    );
+
+    // only the 3 rows of the slice are counted, not the buffer of 1024 rows
+    let neg = registry.get("neg").unwrap();
+    assert_eq!(neg.input_bytes, 3 * 4);
+    assert!(neg.output_bytes <= 3 * 4 + 1);
+}
+
+#[test]
+fn test_memory_accounting() {
+    let pool = Arc::new(GreedyMemoryPool::new(64 << 20));
+    let mut runtime = AccountedRuntime::new(Box::new(Mock::default()), pool.clone());
+    runtime
+        .add_function(
+            "repeat",
+            Field::new("repeat", DataType::Utf8, true),
+            CallMode::ReturnNullOnNullInput,
+            "repeat",
+        )
+        .unwrap();
+    // the interpreter is accounted after adding the function
+    let interpreter = runtime.interpreter_reserved();
+    assert!(interpreter > 0);
+    assert_eq!(pool.reserved(), interpreter);
+
+    let input = int32_input(vec![Some(1 << 20), Some(1 << 20)]);
+
+    // outputs are released after the call, unless the reservation is kept
+    runtime.call("repeat", &input).unwrap();
+    assert_eq!(pool.reserved(), runtime.interpreter_reserved());
+    let (output, reservation) = runtime.call_reserved("repeat", &input).unwrap();
+    assert_eq!(reservation.size(), output.get_array_memory_size());
+    assert!(reservation.size() >= 2 << 20);
+    assert_eq!(
+        pool.reserved(),
+        runtime.interpreter_reserved() + reservation.size()
+    );
+    drop(reservation);
+    assert_eq!(pool.reserved(), runtime.interpreter_reserved());
+
+    // outputs exceeding the budget fail the call
+    let input = int32_input(vec![Some(32 << 20), Some(32 << 20)]);
+    let err = runtime.call("repeat", &input).unwrap_err();
+    assert!(matches!(err, Error::ResourceExceeded(_)));
+    assert!(!err.is_user_error());
+    assert_eq!(pool.reserved(), runtime.interpreter_reserved());
+}
+
+#[test]
+fn test_cached_runtime() {
+    let mut runtime = CachedRuntime::new(Box::new(Mock::default()), 1024);
+    // the counter shows which rows are evaluated, and nulls are passed to the function
+    runtime
+        .add_function("next", int32("next"), CallMode::CalledOnNullInput, "next")
+        .unwrap();
+    runtime.set_deterministic("next", true);
+
+    let call = |runtime: &CachedRuntime, values: Vec<Option<i32>>| {
+        runtime.call("next", &int32_input(values))
+    };
+    let output = call(&runtime, vec![Some(1), Some(2), Some(1), None]).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+
+        | next |
+        +------+
+        | 101  |
+        | 202  |
+        | 101  |
+        | 3    |
+        +------+"#]],
+    );
+    let output = call(&runtime, vec![Some(3), None, Some(2), Some(3)]).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+
+        | next |
+        +------+
+        | 304  |
+        | 3    |
+        | 202  |
+        | 304  |
+        +------+"#]],
+    );
+    let stats = runtime.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (4, 4, 4));
+
+    // the row of an error refers to the input
+    let err = call(&runtime, vec![Some(2), Some(0)]).unwrap_err();
+    assert!(matches!(err, Error::Execution { row: Some(1), .. }));
+
+    // the least recently used results are evicted
+    let values: Vec<_> = (10..30).map(Some).collect();
+    call(&runtime, values).unwrap();
+    let stats = runtime.stats();
+    assert!(stats.memory <= 1024);
+    assert!(stats.evictions > 0);
+
+    // adding the function again discards its results
+    runtime
+        .add_function("next", int32("next"), CallMode::CalledOnNullInput, "next")
+        .unwrap();
+    assert_eq!(runtime.stats().entries, 0);
+    runtime.clear();
+    assert_eq!(
+        runtime.stats(),
+        CacheStats {
+            hits: 5,
+            misses: 25,
+            evictions: runtime.stats().evictions,
+            entries: 0,
+            memory: 0,
+        }
+    );
+
+    // functions not declared deterministic are not cached
+    runtime.set_deterministic("next", false);
+    let output = call(&runtime, vec![Some(5)]).unwrap();
+    assert_eq!(output.column(0).as_primitive::<Int32Type>().value(0), 525);
+    assert_eq!(runtime.stats().misses, 25);
+}
+
+#[test]
+fn test_cached_runtime_row_errors() {
+    let mut runtime = CachedRuntime::new(Box::new(Mock::default().with_error_column()), 1 << 20);
+    runtime
+        .add_function("next", int32("next"), CallMode::CalledOnNullInput, "next")
+        .unwrap();
+    runtime.set_deterministic("next", true);
+
+    // failed rows are returned with their errors, and evaluated again by the next call
+    let output = runtime
+        .call("next", &int32_input(vec![Some(1), Some(0), Some(1)]))
+        .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+-------+
+        | next | error |
+        +------+-------+
+        | 101  |       |
+        |      | zero  |
+        | 101  |       |
+        +------+-------+"#]],
+    );
+    let output = runtime
+        .call("next", &int32_input(vec![Some(0), Some(1), Some(2)]))
+        .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+-------+
+        | next | error |
+        +------+-------+
+        |      | zero  |
+        | 101  |       |
+        | 202  |       |
+        +------+-------+"#]],
+    );
+    let stats = runtime.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (2, 4, 2));
+
+    // cached rows have no errors
+    let output = runtime
+        .call("next", &int32_input(vec![Some(2), Some(1)]))
+        .unwrap();
+    assert_eq!(output.num_columns(), 1);
+}
+
+#[test]
+fn test_function_properties() {
+    // the counter shows which member evaluated the rows
+    let field = int32("count");
+    let input = int32_input(vec![Some(1), Some(2), Some(3), Some(4)]);
+    let values = |output: RecordBatch| {
+        let output = output.column(0).as_primitive::<Int32Type>().clone();
+        output.values().to_vec()
+    };
+
+    let mut pool = RuntimePool::from_fn(2, || Ok(Box::new(Mock::default()))).unwrap();
+    let properties = FunctionProperties::new().with_parallel_safety(ParallelSafety::Safe);
+    pool.add_function_with_properties("count", field.clone(), properties, "count")
+        .unwrap();
+    assert_eq!(values(pool.call("count", &input).unwrap()), [1, 2, 1, 2]);
+
+    // functions not parallel safe are evaluated by one member
+    let properties = FunctionProperties::new().with_parallel_safety(ParallelSafety::Restricted);
+    pool.add_function_with_properties("count", field.clone(), properties, "count")
+        .unwrap();
+    assert_eq!(values(pool.call("count", &input).unwrap()), [3, 4, 5, 6]);
+
+    // immutable functions are cached
+    let mut runtime = CachedRuntime::new(Box::new(Mock::default()), 1 << 20);
+    let properties = FunctionProperties::new().with_volatility(Volatility::Immutable);
+    runtime
+        .add_function_with_properties("count", field, properties, "count")
+        .unwrap();
+    assert!(runtime.is_deterministic("count"));
+    runtime.call("count", &input).unwrap();
+    assert_eq!(values(runtime.call("count", &input).unwrap()), [1, 2, 3, 4]);
+
+    // the properties are carried in metadata by remote functions
+    let metadata = properties.to_metadata();
+    assert_eq!(metadata["arrowudf.volatility"], "immutable");
+    assert_eq!(metadata["arrowudf.parallel"], "unsafe");
+    assert_eq!(
+        FunctionProperties::from_metadata(&metadata).unwrap(),
+        properties
+    );
+    let metadata = [("arrowudf.volatility".to_string(), "pure".to_string())].into();
+    let err = FunctionProperties::from_metadata(&metadata).unwrap_err();
+    assert_eq!(err.to_string(), "invalid volatility: pure");
+}
+
+#[test]
+fn test_streaming_runtime() {
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    let registry = Arc::new(MetricsRegistry::new());
+    let mut runtime = MeteredRuntime::new(Box::new(Mock::default()), registry.clone());
+    runtime
+        .add_function(
+            "series",
+            int32("series"),
+            CallMode::ReturnNullOnNullInput,
+            "series",
+        )
+        .unwrap();
+    let runtime = StreamingRuntime::new(Arc::new(runtime)).with_buffer(1);
+
+    let input = int32_input(vec![Some(3)]);
+    let outputs = block_on(runtime.call_table_function_stream("series", &input, 2)).unwrap();
+    let outputs: Vec<_> = block_on(outputs.map(|batch| batch.unwrap()).collect());
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 0   | 1      |
+        | 0   | 2      |
+        +-----+--------+"#]],
+    );
+
+    // errors are returned by the stream
+    let mut outputs = block_on(runtime.call_table_function_stream("unknown", &input, 2)).unwrap();
+    let err = block_on(outputs.next()).unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "function not found: unknown");
+    assert!(block_on(outputs.next()).is_none());
+
+    // the producer stops after the buffer is full, and ends once the stream is dropped
+    let input = int32_input(vec![Some(1000)]);
+    let mut outputs = block_on(runtime.call_table_function_stream("series", &input, 1)).unwrap();
+    block_on(outputs.next()).unwrap().unwrap();
+    drop(outputs);
+    let series = loop {
+        match registry.get("series").filter(|s| s.calls == 2) {
+            Some(series) => break series,
+            None => std::thread::sleep(Duration::from_millis(1)),
+        }
+    };
+    // 3 rows of the first call, then 1 polled, 1 buffered and 1 waiting to be sent
+    assert!(series.output_rows <= 3 + 3, "{}", series.output_rows);
+}
+
+#[test]
+fn test_tolerant_runtime() {
+    let mut runtime = TolerantRuntime::new(Box::new(Mock::default())).with_max_row_errors(2);
+    runtime
+        .add_function("div", int32("div"), CallMode::ReturnNullOnNullInput, "div")
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![4, 5, 6, 7]);
+    let arg1 = Int32Array::from(vec![2, 0, 0, 1]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    // the failed rows are recorded in the error column
+    let output = runtime.call("div", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+------------------+
+        | div | error            |
+        +-----+------------------+
+        | 2   |                  |
+        |     | division by zero |
+        |     | division by zero |
+        | 7   |                  |
+        +-----+------------------+"#]],
+    );
+
+    // no error column without errors
+    let output = runtime.call("div", &input.slice(3, 1)).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | div |
+        +-----+
+        | 7   |
+        +-----+"#]],
+    );
+
+    // all rows fail
+    let output = runtime.call("div", &input.slice(1, 2)).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+------------------+
+        | div | error            |
+        +-----+------------------+
+        |     | division by zero |
+        |     | division by zero |
+        +-----+------------------+"#]],
+    );
+
+    // the call fails once the budget is exceeded
+    let runtime = runtime.with_max_row_errors(1);
+    match runtime.call("div", &input).unwrap_err() {
+        Error::Execution { row, message, .. } => {
+            assert_eq!(row, Some(1));
+            assert_eq!(
+                message,
+                "2 rows failed, more than the limit of 1, the first error: division by zero"
+            );
+        }
+        e => panic!("unexpected error: {e}"),
+    }
+
+    // errors in the error column of the runtime are counted
+    let mut runtime =
+        TolerantRuntime::new(Box::new(Mock::default().with_error_column())).with_max_row_errors(1);
+    runtime
+        .add_function("div", int32("div"), CallMode::ReturnNullOnNullInput, "div")
+        .unwrap();
+    let output = runtime.call("div", &input.slice(0, 2)).unwrap();
+    assert_eq!(output.num_columns(), 2);
+    runtime.call("div", &input).unwrap_err();
+}
+
+#[test]
+fn test_quota_runtime() {
+    let quota = Arc::new(
+        QuotaManager::new()
+            .with_max_concurrent_calls(2)
+            .with_max_queued_calls(1)
+            .with_function_limit("slow", 1)
+            .with_queue_timeout(Duration::from_millis(10)),
+    );
+    let mut runtime = QuotaRuntime::new(Box::new(Mock::default()), quota.clone());
+    for name in ["slow", "fast"] {
+        runtime
+            .add_function(name, int32(name), CallMode::ReturnNullOnNullInput, "neg")
+            .unwrap();
+    }
+
+    let input = int32_input(vec![Some(1)]);
+
+    // a running call of `slow` holds its quota
+    let permit = quota.acquire("slow").unwrap();
+    runtime.call("fast", &input).unwrap();
+    match runtime.call("slow", &input).unwrap_err() {
+        Error::ResourceExceeded(message) => {
+            assert_eq!(message, "timed out waiting for quota of function slow")
+        }
+        e => panic!("unexpected error: {e}"),
+    }
+    assert_eq!(quota.queued(), 0);
+
+    drop(permit);
+    runtime.call("slow", &input).unwrap();
+    assert_eq!(quota.running(), 0);
+
+    // the iterator of a table function holds a permit until dropped
+    runtime
+        .add_function(
+            "series",
+            int32("series"),
+            CallMode::ReturnNullOnNullInput,
+            "series",
+        )
+        .unwrap();
+    let mut iter = runtime.call_table_function("series", &input, 1).unwrap();
+    iter.next().unwrap().unwrap();
+    assert_eq!(quota.running(), 1);
+    drop(iter);
+    assert_eq!(quota.running(), 0);
+
+    // calls beyond the queue are rejected immediately
+    let quota = Arc::new(
+        QuotaManager::new()
+            .with_max_concurrent_calls(1)
+            .with_max_queued_calls(1),
+    );
+    let mut runtime = QuotaRuntime::new(Box::new(Mock::default()), quota.clone());
+    runtime
+        .add_function(
+            "fast",
+            int32("fast"),
+            CallMode::ReturnNullOnNullInput,
+            "neg",
+        )
+        .unwrap();
+    let permit = quota.acquire("other").unwrap();
+    std::thread::scope(|s| {
+        let waiting = s.spawn(|| runtime.call("fast", &input));
+        while quota.queued() == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        match runtime.call("fast", &input).unwrap_err() {
+            Error::ResourceExceeded(message) => {
+                assert_eq!(message, "too many calls waiting for quota: 1 queued")
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        // the queued call is admitted once the running one finishes
+        drop(permit);
+        waiting.join().unwrap().unwrap();
+    });
+}
+
+#[test]
+fn test_parallel_evaluator() {
+    use arrow_schema::ArrowError;
+
+    let mut pool = RuntimePool::from_fn(2, || Ok(Box::new(Mock::default()))).unwrap();
+    pool.add_function(
+        "square",
+        int32("square"),
+        CallMode::ReturnNullOnNullInput,
+        "square",
+    )
+    .unwrap();
+    let pipeline = Pipeline::new().with_output("y", "square(square(x))".parse().unwrap());
+    let evaluator = ParallelEvaluator::new(&pool, pipeline).with_parallelism(2);
+
+    let batches: Vec<RecordBatch> = (0..5)
+        .map(|i| int32_input(vec![Some(i * 2), Some(i * 2 + 1)]))
+        .collect();
+
+    // the outputs are in the order of the input
+    let outputs = evaluator
+        .evaluate_all(batches.iter().cloned().map(Ok))
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(outputs.len(), 5);
+    check(
+        &outputs,
+        expect![[r#"
+        +---+------+
+        | x | y    |
+        +---+------+
+        | 0 | 0    |
+        | 1 | 1    |
+        | 2 | 16   |
+        | 3 | 81   |
+        | 4 | 256  |
+        | 5 | 625  |
+        | 6 | 1296 |
+        | 7 | 2401 |
+        | 8 | 4096 |
+        | 9 | 6561 |
+        +---+------+"#]],
+    );
+
+    // the iteration stops at the first error
+    let mut batches: Vec<_> = batches.into_iter().map(Ok).collect();
+    batches[3] = Err(ArrowError::IoError(
+        "broken file".into(),
+        std::io::ErrorKind::Other.into(),
+    ));
+    let outputs: Vec<_> = evaluator.evaluate_all(batches).collect();
+    assert_eq!(outputs.len(), 4);
+    assert!(outputs[..3].iter().all(|output| output.is_ok()));
+    assert!(outputs[3]
+        .as_ref()
+        .unwrap_err()
+        .to_string()
+        .contains("broken file"));
+}
+
+/// A runtime whose functions are written in Rust.
+///
+/// The code of a function is the name of its implementation in [`Mock::evaluate`].
+/// Like JavaScript, a failed row fails the call with its row index,
+/// unless the errors are returned in an `error` column like Python.
+#[derive(Default)]
+struct Mock {
+    functions: HashMap<String, (Field, CallMode, String)>,
+    /// The number of rows evaluated by counting functions.
+    counter: AtomicI32,
+    /// Whether errors of rows are returned in an `error` column.
+    error_column: bool,
+}
+
+impl Mock {
+    fn with_error_column(mut self) -> Self {
+        self.error_column = true;
+        self
+    }
+
+    /// Add a function of the same name as its implementation.
+    fn add(&mut self, name: &str, return_type: DataType) {
+        let field = Field::new(name, return_type, true);
+        self.add_function(name, field, CallMode::ReturnNullOnNullInput, name)
+            .unwrap();
+    }
+
+    /// Evaluate a row of a scalar function, returning `None` for null.
+    fn evaluate(&self, code: &str, input: &RecordBatch, i: usize) -> Result<Option<Value>, String> {
+        let int = |j: usize| {
+            let column = input.column(j);
+            (column.is_valid(i)).then(|| match column.data_type() {
+                DataType::Int64 => column.as_primitive::<Int64Type>().value(i),
+                _ => column.as_primitive::<Int32Type>().value(i) as i64,
+            })
+        };
+        let string = |j: usize| input.column(j).as_string::<i32>().value(i).to_string();
+        Ok(Some(match code {
+            "neg" => match int(0).unwrap() {
+                0 => return Err("zero".into()),
+                x => Value::Int(-x),
+            },
+            "square" => Value::Int(int(0).unwrap().pow(2)),
+            "add" => Value::Int(int(0).unwrap() + int(1).unwrap()),
+            "div" => match (int(0).unwrap(), int(1).unwrap()) {
+                (_, 0) => return Err("division by zero".into()),
+                (a, b) => Value::Int(a / b),
+            },
+            "count" => Value::Int(self.counter.fetch_add(1, Ordering::Relaxed) as i64 + 1),
+            "next" => {
+                let x = int(0);
+                if x == Some(0) {
+                    return Err("zero".into());
+                }
+                let n = self.counter.fetch_add(1, Ordering::Relaxed) as i64 + 1;
+                Value::Int(x.unwrap_or(0) * 100 + n)
+            }
+            "repeat" => Value::String("x".repeat(int(0).unwrap() as usize)),
+            "trim" => Value::String(string(0).trim().to_string()),
+            "upper" => Value::String(string(0).to_uppercase()),
+            "length" => Value::Int(string(0).len() as i64),
+            "point" => match int(0).unwrap() {
+                x if x > 0 => Value::Point(Some(x as i32)),
+                _ => Value::Point(None),
+            },
+            "ts" => Value::Int(int(0).unwrap()),
+            _ => return Err(format!("unknown function: {code}")),
+        }))
+    }
+}
+
+/// A value returned by a function of [`Mock`].
+enum Value {
+    Int(i64),
+    String(String),
+    /// A struct with nullable `x` and `y` of the same value.
+    Point(Option<i32>),
+}
+
+impl UdfRuntime for Mock {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let function = (return_field, mode, code.to_string());
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let (field, mode, code) =
+            (self.functions.get(name)).ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let mut values = Vec::with_capacity(input.num_rows());
+        let mut errors = Vec::with_capacity(input.num_rows());
+        for i in 0..input.num_rows() {
+            let any_null = input.columns().iter().any(|column| column.is_null(i));
+            if *mode == CallMode::ReturnNullOnNullInput && any_null {
+                values.push(None);
+                errors.push(None);
+                continue;
+            }
+            match self.evaluate(code, input, i) {
+                Ok(value) => {
+                    values.push(value);
+                    errors.push(None);
+                }
+                Err(message) if self.error_column => {
+                    values.push(None);
+                    errors.push(Some(message));
+                }
+                Err(message) => return Err(Error::execution(message).with_row(i)),
+            }
+        }
+        let output = build_array(field.data_type(), values);
+        let mut fields = vec![Field::new(field.name(), output.data_type().clone(), true)];
+        let mut columns = vec![output];
+        if errors.iter().any(|error| error.is_some()) {
+            fields.push(Field::new("error", DataType::Utf8, true));
+            columns.push(Arc::new(StringArray::from(errors)));
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+
+    /// Only `series` is a table function, which yields `0..n` for each row.
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        let (field, _, code) =
+            (self.functions.get(name)).ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        if code != "series" {
+            return Err(Error::execution(format!("{name} is not a table function")));
+        }
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("row", DataType::Int32, true),
+            field.clone(),
+        ]));
+        let n = input.column(0).as_primitive::<Int32Type>();
+        let mut rows = (0..input.num_rows())
+            .filter(|&i| n.is_valid(i))
+            .flat_map(move |i| (0..n.value(i)).map(move |value| (i as i32, value)));
+        Ok(Box::new(std::iter::from_fn(move || {
+            let (rows, values): (Vec<_>, Vec<_>) = rows.by_ref().take(chunk_size).unzip();
+            if rows.is_empty() {
+                return None;
+            }
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(Int32Array::from(rows)),
+                Arc::new(Int32Array::from(values)),
+            ];
+            Some(RecordBatch::try_new(schema.clone(), columns).map_err(Into::into))
+        })))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    /// Pretends that each function takes 1KB in the interpreter.
+    fn memory_usage(&self) -> Option<usize> {
+        Some(self.functions.len() << 10)
+    }
+}
+
+/// Build an array of values returned by a function of [`Mock`].
+fn build_array(data_type: &DataType, values: Vec<Option<Value>>) -> ArrayRef {
+    let ints = || values.iter().map(|v| v.as_ref().map(Value::int));
+    match data_type {
+        DataType::Int32 => Arc::new(ints().map(|v| v.map(|v| v as i32)).collect::<Int32Array>()),
+        DataType::Int64 => Arc::new(ints().collect::<Int64Array>()),
+        DataType::Timestamp(TimeUnit::Second, None) => {
+            Arc::new(ints().collect::<TimestampSecondArray>())
+        }
+        DataType::Utf8 => Arc::new(
+            (values.iter())
+                .map(|v| match v {
+                    Some(Value::String(s)) => Some(s.as_str()),
+                    _ => None,
+                })
+                .collect::<StringArray>(),
+        ),
+        DataType::Struct(_) => {
+            let xs: Int32Array = (values.iter())
+                .map(|v| match v {
+                    Some(Value::Point(x)) => *x,
+                    _ => None,
+                })
+                .collect();
+            let fields = Fields::from(vec![
+                Field::new("x", DataType::Int32, true),
+                Field::new("y", DataType::Int32, true),
+            ]);
+            let nulls = values.iter().map(|v| v.is_some()).collect();
+            let column: ArrayRef = Arc::new(xs);
+            Arc::new(StructArray::new(
+                fields,
+                vec![column.clone(), column],
+                Some(nulls),
+            ))
+        }
+        _ => panic!("unsupported return type: {data_type}"),
+    }
+}
+
+impl Value {
+    fn int(&self) -> i64 {
+        match self {
+            Value::Int(i) => *i,
+            _ => panic!("not an integer"),
+        }
+    }
+}
+
+/// Returns a nullable `Int32` field.
+fn int32(name: &str) -> Field {
+    Field::new(name, DataType::Int32, true)
+}
+
+/// Returns a batch with a column `x` of the values.
+fn int32_input(values: Vec<Option<i32>>) -> RecordBatch {
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int32Array::from(values))]).unwrap()
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
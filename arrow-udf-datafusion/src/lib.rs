@@ -0,0 +1,477 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::any::Any;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_schema::{DataType, Field, FieldRef, Schema, SchemaRef};
+use arrow_udf_core::UdfRuntime;
+use datafusion::catalog::{TableFunctionImpl, TableProvider};
+use datafusion::datasource::MemTable;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::function::{AccumulatorArgs, StateFieldsArgs};
+use datafusion::logical_expr::utils::format_state_name;
+use datafusion::logical_expr::{
+    Accumulator, AggregateUDF, AggregateUDFImpl, ColumnarValue, Expr, ScalarUDF, ScalarUDFImpl,
+    Signature, Volatility,
+};
+use datafusion::scalar::ScalarValue;
+
+/// The maximum number of rows in each output batch of table functions.
+const CHUNK_SIZE: usize = 1024;
+
+/// Register functions of a runtime into a DataFusion [`SessionContext`].
+///
+/// # Example
+///
+/// ```ignore
+/// Registration::new(Arc::new(runtime))
+///     .scalar("gcd", vec![DataType::Int32, DataType::Int32], DataType::Int32)
+///     .aggregate("sum", vec![DataType::Int32], DataType::Int32, DataType::Int32)
+///     .table("series", vec![DataType::Int32], Field::new("series", DataType::Int32, true))
+///     .register(&ctx);
+/// ```
+pub struct Registration {
+    runtime: Arc<dyn UdfRuntime>,
+    scalars: Vec<ScalarFunction>,
+    aggregates: Vec<AggregateFunction>,
+    tables: Vec<TableFunction>,
+}
+
+impl Registration {
+    /// Create a registration for functions in the runtime.
+    pub fn new(runtime: Arc<dyn UdfRuntime>) -> Self {
+        Self {
+            runtime,
+            scalars: vec![],
+            aggregates: vec![],
+            tables: vec![],
+        }
+    }
+
+    /// Add a scalar function.
+    pub fn scalar(mut self, name: &str, arg_types: Vec<DataType>, return_type: DataType) -> Self {
+        let function = ScalarFunction::new(self.runtime.clone(), name, arg_types, return_type);
+        self.scalars.push(function);
+        self
+    }
+
+    /// Add an aggregate function.
+    pub fn aggregate(
+        mut self,
+        name: &str,
+        arg_types: Vec<DataType>,
+        state_type: DataType,
+        return_type: DataType,
+    ) -> Self {
+        let function = AggregateFunction::new(
+            self.runtime.clone(),
+            name,
+            arg_types,
+            state_type,
+            return_type,
+        );
+        self.aggregates.push(function);
+        self
+    }
+
+    /// Add a table function.
+    pub fn table(mut self, name: &str, arg_types: Vec<DataType>, return_field: Field) -> Self {
+        let function = TableFunction::new(self.runtime.clone(), name, arg_types, return_field);
+        self.tables.push(function);
+        self
+    }
+
+    /// Register all functions into the context.
+    pub fn register(self, ctx: &SessionContext) {
+        for function in self.scalars {
+            ctx.register_udf(ScalarUDF::new_from_impl(function));
+        }
+        for function in self.aggregates {
+            ctx.register_udaf(AggregateUDF::new_from_impl(function));
+        }
+        for function in self.tables {
+            let name = function.name.clone();
+            ctx.register_udtf(&name, Arc::new(function));
+        }
+    }
+}
+
+/// A scalar function in a runtime, wrapped as a DataFusion [`ScalarUDFImpl`].
+pub struct ScalarFunction {
+    runtime: Arc<dyn UdfRuntime>,
+    /// The name of function in the runtime.
+    function: String,
+    /// The name of function in SQL.
+    name: String,
+    signature: Signature,
+    arg_types: Vec<DataType>,
+    return_type: DataType,
+}
+
+impl ScalarFunction {
+    /// Wrap a scalar function in the runtime.
+    pub fn new(
+        runtime: Arc<dyn UdfRuntime>,
+        name: &str,
+        arg_types: Vec<DataType>,
+        return_type: DataType,
+    ) -> Self {
+        Self {
+            runtime,
+            function: name.to_string(),
+            name: name.to_string(),
+            signature: Signature::user_defined(Volatility::Volatile),
+            arg_types,
+            return_type,
+        }
+    }
+
+    /// Set the name of function in SQL. Defaults to the name in the runtime.
+    ///
+    /// This is useful for WebAssembly functions whose names are signatures.
+    pub fn with_sql_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Set the volatility of function. Defaults to [`Volatility::Volatile`].
+    pub fn with_volatility(mut self, volatility: Volatility) -> Self {
+        self.signature.volatility = volatility;
+        self
+    }
+}
+
+impl Debug for ScalarFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScalarFunction")
+            .field("function", &self.function)
+            .field("name", &self.name)
+            .field("arg_types", &self.arg_types)
+            .field("return_type", &self.return_type)
+            .finish()
+    }
+}
+
+impl ScalarUDFImpl for ScalarFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.return_type.clone())
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        coerce_types(&self.name, arg_types, &self.arg_types)
+    }
+
+    fn invoke_batch(&self, args: &[ColumnarValue], number_rows: usize) -> Result<ColumnarValue> {
+        let arrays = args
+            .iter()
+            .map(|arg| arg.clone().into_array(number_rows))
+            .collect::<Result<Vec<_>>>()?;
+        let input = build_batch(arrays, number_rows)?;
+        let output = self
+            .runtime
+            .call(&self.function, &input)
+            .map_err(external)?;
+        Ok(ColumnarValue::Array(output.column(0).clone()))
+    }
+}
+
+/// An aggregate function in a runtime, wrapped as a DataFusion [`AggregateUDFImpl`].
+pub struct AggregateFunction {
+    runtime: Arc<dyn UdfRuntime>,
+    function: String,
+    name: String,
+    signature: Signature,
+    arg_types: Vec<DataType>,
+    state_type: DataType,
+    return_type: DataType,
+}
+
+impl AggregateFunction {
+    /// Wrap an aggregate function in the runtime.
+    pub fn new(
+        runtime: Arc<dyn UdfRuntime>,
+        name: &str,
+        arg_types: Vec<DataType>,
+        state_type: DataType,
+        return_type: DataType,
+    ) -> Self {
+        Self {
+            runtime,
+            function: name.to_string(),
+            name: name.to_string(),
+            signature: Signature::user_defined(Volatility::Volatile),
+            arg_types,
+            state_type,
+            return_type,
+        }
+    }
+
+    /// Set the name of function in SQL. Defaults to the name in the runtime.
+    pub fn with_sql_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+}
+
+impl Debug for AggregateFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AggregateFunction")
+            .field("function", &self.function)
+            .field("name", &self.name)
+            .field("arg_types", &self.arg_types)
+            .field("state_type", &self.state_type)
+            .field("return_type", &self.return_type)
+            .finish()
+    }
+}
+
+impl AggregateUDFImpl for AggregateFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.return_type.clone())
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        coerce_types(&self.name, arg_types, &self.arg_types)
+    }
+
+    fn accumulator(&self, _acc_args: AccumulatorArgs) -> Result<Box<dyn Accumulator>> {
+        let state = self
+            .runtime
+            .create_state(&self.function)
+            .map_err(external)?;
+        Ok(Box::new(RuntimeAccumulator {
+            runtime: self.runtime.clone(),
+            function: self.function.clone(),
+            state,
+        }))
+    }
+
+    fn state_fields(&self, args: StateFieldsArgs) -> Result<Vec<Field>> {
+        Ok(vec![Field::new(
+            format_state_name(args.name, "state"),
+            self.state_type.clone(),
+            true,
+        )])
+    }
+}
+
+/// An accumulator that keeps the state of an aggregate function in a runtime.
+struct RuntimeAccumulator {
+    runtime: Arc<dyn UdfRuntime>,
+    function: String,
+    /// The state array with exactly one row.
+    state: ArrayRef,
+}
+
+impl Debug for RuntimeAccumulator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuntimeAccumulator")
+            .field("function", &self.function)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl Accumulator for RuntimeAccumulator {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        let num_rows = values.first().map_or(0, |v| v.len());
+        let input = build_batch(values.to_vec(), num_rows)?;
+        self.state = self
+            .runtime
+            .accumulate(&self.function, &self.state, &input)
+            .map_err(external)?;
+        Ok(())
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        let states = arrow_select::concat::concat(&[&self.state, &states[0]])?;
+        self.state = self
+            .runtime
+            .merge(&self.function, &states)
+            .map_err(external)?;
+        Ok(())
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![ScalarValue::try_from_array(&self.state, 0)?])
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        let output = self
+            .runtime
+            .finish(&self.function, &self.state)
+            .map_err(external)?;
+        ScalarValue::try_from_array(&output, 0)
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self) + self.state.get_array_memory_size()
+    }
+}
+
+/// A table function in a runtime, wrapped as a DataFusion [`TableFunctionImpl`].
+///
+/// The arguments must be constants. The function is evaluated when the query is planned.
+pub struct TableFunction {
+    runtime: Arc<dyn UdfRuntime>,
+    function: String,
+    name: String,
+    arg_types: Vec<DataType>,
+    schema: SchemaRef,
+}
+
+impl TableFunction {
+    /// Wrap a table function in the runtime.
+    pub fn new(
+        runtime: Arc<dyn UdfRuntime>,
+        name: &str,
+        arg_types: Vec<DataType>,
+        return_field: Field,
+    ) -> Self {
+        Self {
+            runtime,
+            function: name.to_string(),
+            name: name.to_string(),
+            arg_types,
+            schema: Arc::new(Schema::new(vec![return_field])),
+        }
+    }
+
+    /// Set the name of function in SQL. Defaults to the name in the runtime.
+    pub fn with_sql_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+}
+
+impl Debug for TableFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TableFunction")
+            .field("function", &self.function)
+            .field("name", &self.name)
+            .field("arg_types", &self.arg_types)
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl TableFunctionImpl for TableFunction {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        if args.len() != self.arg_types.len() {
+            return Err(DataFusionError::Plan(format!(
+                "{} expects {} arguments, but got {}",
+                self.name,
+                self.arg_types.len(),
+                args.len()
+            )));
+        }
+        let mut arrays = Vec::with_capacity(args.len());
+        for (arg, ty) in args.iter().zip(&self.arg_types) {
+            let Expr::Literal(value) = arg else {
+                return Err(DataFusionError::Plan(format!(
+                    "arguments of {} must be constants, but got {arg}",
+                    self.name
+                )));
+            };
+            arrays.push(value.cast_to(ty)?.to_array()?);
+        }
+        let input = build_batch(arrays, 1)?;
+        let mut batches = vec![];
+        for output in self
+            .runtime
+            .call_table_function(&self.function, &input, CHUNK_SIZE)
+            .map_err(external)?
+        {
+            // the first column is the row index
+            let output = output.map_err(external)?;
+            batches.push(RecordBatch::try_new(
+                self.schema.clone(),
+                vec![output.column(1).clone()],
+            )?);
+        }
+        Ok(Arc::new(MemTable::try_new(
+            self.schema.clone(),
+            vec![batches],
+        )?))
+    }
+}
+
+/// Build a batch from the argument arrays.
+fn build_batch(arrays: Vec<ArrayRef>, num_rows: usize) -> Result<RecordBatch> {
+    let fields: Vec<FieldRef> = arrays
+        .iter()
+        .enumerate()
+        .map(|(i, array)| {
+            Arc::new(Field::new(
+                format!("arg{i}"),
+                array.data_type().clone(),
+                true,
+            ))
+        })
+        .collect();
+    Ok(RecordBatch::try_new_with_options(
+        Arc::new(Schema::new(fields)),
+        arrays,
+        &RecordBatchOptions::default().with_row_count(Some(num_rows)),
+    )?)
+}
+
+/// Cast arguments to the declared types if possible.
+fn coerce_types(name: &str, actual: &[DataType], expected: &[DataType]) -> Result<Vec<DataType>> {
+    if actual.len() != expected.len()
+        || !actual
+            .iter()
+            .zip(expected)
+            .all(|(from, to)| arrow_cast::can_cast_types(from, to))
+    {
+        return Err(DataFusionError::Plan(format!(
+            "{name} expects arguments {expected:?}, but got {actual:?}"
+        )));
+    }
+    Ok(expected.to_vec())
+}
+
+fn external(e: arrow_udf_core::Error) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
@@ -0,0 +1,213 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_datafusion::Registration;
+use arrow_udf_js::{CallMode, Runtime};
+use datafusion::execution::context::SessionContext;
+use expect_test::{expect, Expect};
+
+#[tokio::test]
+async fn test_scalar_function() {
+    let ctx = context();
+    check(
+        &ctx.sql("SELECT x, y, gcd(x, y) AS gcd FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap(),
+        expect![[r#"
+            +----+----+-----+
+            | x  | y  | gcd |
+            +----+----+-----+
+            | 25 | 15 | 5   |
+            | 12 | 15 | 3   |
+            |    | 4  |     |
+            | 9  | 4  | 1   |
+            +----+----+-----+"#]],
+    );
+    // constant arguments are cast and expanded to arrays
+    check(
+        &ctx.sql("SELECT gcd(x, 6) AS gcd FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap(),
+        expect![[r#"
+            +-----+
+            | gcd |
+            +-----+
+            | 1   |
+            | 6   |
+            |     |
+            | 3   |
+            +-----+"#]],
+    );
+    let err = ctx.sql("SELECT gcd(x) FROM t").await.unwrap_err();
+    assert!(err.to_string().contains("gcd expects arguments"), "{err}");
+}
+
+#[tokio::test]
+async fn test_aggregate_function() {
+    let ctx = context();
+    check(
+        &ctx.sql("SELECT js_sum(x) AS sum FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap(),
+        expect![[r#"
+            +-----+
+            | sum |
+            +-----+
+            | 46  |
+            +-----+"#]],
+    );
+    check(
+        &ctx.sql("SELECT y, js_sum(x) AS sum FROM t GROUP BY y ORDER BY y")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap(),
+        expect![[r#"
+            +----+-----+
+            | y  | sum |
+            +----+-----+
+            | 4  | 9   |
+            | 15 | 37  |
+            +----+-----+"#]],
+    );
+}
+
+#[tokio::test]
+async fn test_table_function() {
+    let ctx = context();
+    check(
+        &ctx.sql("SELECT * FROM series(3)")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap(),
+        expect![[r#"
+            +--------+
+            | series |
+            +--------+
+            | 0      |
+            | 1      |
+            | 2      |
+            +--------+"#]],
+    );
+    let err = ctx.sql("SELECT * FROM series(x)").await.unwrap_err();
+    assert!(err.to_string().contains("must be constants"), "{err}");
+}
+
+/// Create a context with functions from a JavaScript runtime and a table `t`.
+fn context() -> SessionContext {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function* series(n) {
+                for (let i = 0; i < n; i++) {
+                    yield i;
+                }
+            }
+            "#,
+        )
+        .unwrap();
+    runtime
+        .add_aggregate(
+            "js_sum",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function create_state() {
+                return 0;
+            }
+            export function accumulate(state, value) {
+                return state + value;
+            }
+            export function merge(state1, state2) {
+                return state1 + state2;
+            }
+            "#,
+        )
+        .unwrap();
+
+    let ctx = SessionContext::new();
+    Registration::new(Arc::new(runtime))
+        .scalar(
+            "gcd",
+            vec![DataType::Int32, DataType::Int32],
+            DataType::Int32,
+        )
+        .aggregate(
+            "js_sum",
+            vec![DataType::Int32],
+            DataType::Int32,
+            DataType::Int32,
+        )
+        .table(
+            "series",
+            vec![DataType::Int32],
+            Field::new("series", DataType::Int32, true),
+        )
+        .register(&ctx);
+
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let x = Int32Array::from(vec![Some(25), Some(12), None, Some(9)]);
+    let y = Int32Array::from(vec![Some(15), Some(15), Some(4), Some(4)]);
+    let batch = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(x), Arc::new(y)]).unwrap();
+    ctx.register_batch("t", batch).unwrap();
+    ctx
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
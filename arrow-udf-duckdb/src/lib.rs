@@ -0,0 +1,598 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::ffi::{c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use arrow_array::cast::AsArray;
+use arrow_array::ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::{Array, ArrayRef, RecordBatch, RecordBatchOptions, StructArray};
+use arrow_schema::{DataType, Field, FieldRef, Fields, Schema, SchemaRef};
+use arrow_udf_core::{UdfRuntime, Volatility};
+pub use libduckdb_sys as ffi;
+use libduckdb_sys::*;
+
+mod types;
+mod values;
+
+use self::types::LogicalType;
+use self::values::Value;
+
+/// The minimum version of the DuckDB C API required by extensions.
+const MINIMUM_API_VERSION: &str = "v1.2.0";
+
+/// Initialize a loadable DuckDB extension and register functions with its connection.
+///
+/// Call this from the `{extension}_init_c_api` entrypoint of the extension library.
+/// The connection passed to `register` is kept open as long as the database,
+/// because functions use it to convert their results.
+///
+/// # Example
+///
+/// ```ignore
+/// #[no_mangle]
+/// pub unsafe extern "C" fn udfs_init_c_api(
+///     info: ffi::duckdb_extension_info,
+///     access: *const ffi::duckdb_extension_access,
+/// ) -> bool {
+///     arrow_udf_duckdb::init_extension(info, access, |connection| {
+///         Registration::new(Arc::new(runtime()?))
+///             .scalar("gcd", vec![DataType::Int32, DataType::Int32], DataType::Int32)
+///             .register(connection)
+///     })
+/// }
+/// ```
+///
+/// # Safety
+///
+/// `info` and `access` must be the arguments passed by DuckDB to the entrypoint.
+pub unsafe fn init_extension(
+    info: duckdb_extension_info,
+    access: *const duckdb_extension_access,
+    register: impl FnOnce(duckdb_connection) -> Result<()>,
+) -> bool {
+    let result = (|| {
+        if !duckdb_rs_extension_api_init(info, access, MINIMUM_API_VERSION)
+            .map_err(|e| anyhow!(e))?
+        {
+            // DuckDB has set the error if the API version is not supported
+            return Ok(false);
+        }
+        let get_database = (*access).get_database.context("no database")?;
+        let database = get_database(info);
+        if database.is_null() {
+            bail!("failed to get database");
+        }
+        let mut connection = ptr::null_mut();
+        if duckdb_connect(*database, &mut connection) != duckdb_state_DuckDBSuccess {
+            bail!("failed to connect to database");
+        }
+        if let Err(e) = register(connection) {
+            duckdb_disconnect(&mut connection);
+            return Err(e);
+        }
+        Ok(true)
+    })();
+    match result {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            if let Some(set_error) = (*access).set_error {
+                let message = error_message(&e);
+                set_error(info, message.as_ptr());
+            }
+            false
+        }
+    }
+}
+
+/// Register functions of a runtime as DuckDB scalar and table functions.
+///
+/// # Example
+///
+/// ```ignore
+/// Registration::new(Arc::new(runtime))
+///     .scalar("gcd", vec![DataType::Int32, DataType::Int32], DataType::Int32)
+///     .table("series", vec![DataType::Int32], Field::new("series", DataType::Int32, true))
+///     .register(connection)?;
+/// ```
+pub struct Registration {
+    runtime: Arc<dyn UdfRuntime>,
+    scalars: Vec<FunctionSignature>,
+    tables: Vec<FunctionSignature>,
+}
+
+/// The name and types of a function.
+struct FunctionSignature {
+    name: String,
+    arg_types: Vec<DataType>,
+    return_field: FieldRef,
+}
+
+impl Registration {
+    /// Create a registration for functions in the runtime.
+    pub fn new(runtime: Arc<dyn UdfRuntime>) -> Self {
+        Self {
+            runtime,
+            scalars: vec![],
+            tables: vec![],
+        }
+    }
+
+    /// Add a scalar function.
+    pub fn scalar(mut self, name: &str, arg_types: Vec<DataType>, return_type: DataType) -> Self {
+        self.scalars.push(FunctionSignature {
+            name: name.to_string(),
+            arg_types,
+            return_field: Arc::new(Field::new(name, return_type, true)),
+        });
+        self
+    }
+
+    /// Add a table function.
+    ///
+    /// The name of `return_field` is the name of the output column.
+    pub fn table(mut self, name: &str, arg_types: Vec<DataType>, return_field: Field) -> Self {
+        self.tables.push(FunctionSignature {
+            name: name.to_string(),
+            arg_types,
+            return_field: Arc::new(return_field),
+        });
+        self
+    }
+
+    /// Register all functions with the connection.
+    ///
+    /// # Safety
+    ///
+    /// `connection` must be a valid connection that outlives the registered functions,
+    /// and the DuckDB API must have been initialized, e.g. by [`init_extension`].
+    pub unsafe fn register(self, connection: duckdb_connection) -> Result<()> {
+        for signature in self.scalars {
+            register_scalar(connection, self.runtime.clone(), signature)?;
+        }
+        for signature in self.tables {
+            register_table(connection, self.runtime.clone(), signature)?;
+        }
+        Ok(())
+    }
+}
+
+/// The state of a scalar function, stored as the extra info of the DuckDB function.
+struct ScalarFunction {
+    runtime: Arc<dyn UdfRuntime>,
+    signature: FunctionSignature,
+    /// The Arrow options of the connection, to convert input chunks to Arrow.
+    options: duckdb_arrow_options,
+    /// The Arrow struct type of input chunks.
+    input_type: FFI_ArrowSchema,
+    /// The schema of input batches passed to the runtime.
+    input_schema: SchemaRef,
+    output: OutputConverter,
+}
+
+impl Drop for ScalarFunction {
+    fn drop(&mut self) {
+        unsafe { duckdb_destroy_arrow_options(&mut self.options) };
+    }
+}
+
+unsafe fn register_scalar(
+    connection: duckdb_connection,
+    runtime: Arc<dyn UdfRuntime>,
+    signature: FunctionSignature,
+) -> Result<()> {
+    let name = CString::new(signature.name.as_str())?;
+    let arg_types = signature
+        .arg_types
+        .iter()
+        .map(LogicalType::new)
+        .collect::<Result<Vec<_>>>()?;
+    let return_type = LogicalType::new(signature.return_field.data_type())?;
+
+    let mut options = ptr::null_mut();
+    duckdb_connection_get_arrow_options(connection, &mut options);
+    let mut function = Box::new(ScalarFunction {
+        output: OutputConverter::new(connection, signature.return_field.clone())?,
+        runtime,
+        signature,
+        options,
+        input_type: FFI_ArrowSchema::empty(),
+        input_schema: Arc::new(Schema::empty()),
+    });
+
+    // ask DuckDB how it exports the arguments, since it may differ from the declared types
+    let names = (0..arg_types.len())
+        .map(|i| CString::new(format!("arg{i}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut name_ptrs: Vec<_> = names.iter().map(|n| n.as_ptr()).collect();
+    let mut type_ptrs: Vec<_> = arg_types.iter().map(|t| t.0).collect();
+    let mut schema = FFI_ArrowSchema::empty();
+    check(duckdb_to_arrow_schema(
+        options,
+        type_ptrs.as_mut_ptr(),
+        name_ptrs.as_mut_ptr(),
+        arg_types.len() as idx_t,
+        &mut schema as *mut FFI_ArrowSchema as *mut ArrowSchema,
+    ))?;
+    let schema = Schema::try_from(&schema)?;
+    function.input_type = FFI_ArrowSchema::try_from(DataType::Struct(schema.fields().clone()))?;
+    function.input_schema = Arc::new(schema);
+
+    let mut scalar = duckdb_create_scalar_function();
+    duckdb_scalar_function_set_name(scalar, name.as_ptr());
+    for ty in &arg_types {
+        duckdb_scalar_function_add_parameter(scalar, ty.0);
+    }
+    duckdb_scalar_function_set_return_type(scalar, return_type.0);
+    // null inputs are handled by the runtime according to its call mode
+    duckdb_scalar_function_set_special_handling(scalar);
+    if !function.is_immutable() {
+        duckdb_scalar_function_set_volatile(scalar);
+    }
+    duckdb_scalar_function_set_extra_info(
+        scalar,
+        Box::into_raw(function) as *mut c_void,
+        Some(drop_boxed::<ScalarFunction>),
+    );
+    duckdb_scalar_function_set_function(scalar, Some(invoke_scalar));
+    let state = duckdb_register_scalar_function(connection, scalar);
+    duckdb_destroy_scalar_function(&mut scalar);
+    if state != duckdb_state_DuckDBSuccess {
+        bail!(
+            "failed to register scalar function: {}",
+            name.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn invoke_scalar(
+    info: duckdb_function_info,
+    input: duckdb_data_chunk,
+    output: duckdb_vector,
+) {
+    let function = &*(duckdb_scalar_function_get_extra_info(info) as *const ScalarFunction);
+    if let Err(e) = guard(|| function.invoke(input, output)) {
+        duckdb_scalar_function_set_error(info, error_message(&e).as_ptr());
+    }
+}
+
+impl ScalarFunction {
+    /// Whether the runtime declares the function immutable, so that DuckDB may fold it into constants.
+    ///
+    /// Functions are volatile if the runtime does not know their volatility.
+    fn is_immutable(&self) -> bool {
+        (self.runtime.functions().into_iter())
+            .find(|f| f.name == self.signature.name)
+            .and_then(|f| f.volatility)
+            == Some(Volatility::Immutable)
+    }
+
+    unsafe fn invoke(&self, input: duckdb_data_chunk, output: duckdb_vector) -> Result<()> {
+        let num_rows = duckdb_data_chunk_get_size(input) as usize;
+        let mut array = FFI_ArrowArray::empty();
+        check(duckdb_data_chunk_to_arrow(
+            self.options,
+            input,
+            &mut array as *mut FFI_ArrowArray as *mut ArrowArray,
+        ))?;
+        let (_, columns, _) = StructArray::from(from_ffi(array, &self.input_type)?).into_parts();
+        let input = RecordBatch::try_new_with_options(
+            self.input_schema.clone(),
+            columns,
+            &RecordBatchOptions::default().with_row_count(Some(num_rows)),
+        )?;
+        let result = self.runtime.call(&self.signature.name, &input)?;
+        check_row_errors(&result, 1)?;
+        let mut chunk = self.output.convert(result.column(0).clone())?;
+        duckdb_vector_reference_vector(output, duckdb_data_chunk_get_vector(chunk, 0));
+        duckdb_destroy_data_chunk(&mut chunk);
+        Ok(())
+    }
+}
+
+/// The state of a table function, stored as the extra info of the DuckDB function.
+struct TableFunction {
+    runtime: Arc<dyn UdfRuntime>,
+    signature: FunctionSignature,
+    output: OutputConverter,
+}
+
+/// The constant arguments of a table function call, stored as the bind data.
+struct TableInput {
+    input: RecordBatch,
+}
+
+/// The output batches of a table function call, stored as the init data.
+///
+/// The function is evaluated in a thread, one batch ahead of the scan.
+struct TableOutput {
+    batches: Mutex<Receiver<arrow_udf_core::Result<RecordBatch>>>,
+}
+
+unsafe fn register_table(
+    connection: duckdb_connection,
+    runtime: Arc<dyn UdfRuntime>,
+    signature: FunctionSignature,
+) -> Result<()> {
+    let name = CString::new(signature.name.as_str())?;
+    let arg_types = signature
+        .arg_types
+        .iter()
+        .map(LogicalType::new)
+        .collect::<Result<Vec<_>>>()?;
+    // validate the return type before registering
+    LogicalType::new(signature.return_field.data_type())?;
+    let function = Box::new(TableFunction {
+        output: OutputConverter::new(connection, signature.return_field.clone())?,
+        runtime,
+        signature,
+    });
+
+    let mut table = duckdb_create_table_function();
+    duckdb_table_function_set_name(table, name.as_ptr());
+    for ty in &arg_types {
+        duckdb_table_function_add_parameter(table, ty.0);
+    }
+    duckdb_table_function_set_extra_info(
+        table,
+        Box::into_raw(function) as *mut c_void,
+        Some(drop_boxed::<TableFunction>),
+    );
+    duckdb_table_function_set_bind(table, Some(bind_table));
+    duckdb_table_function_set_init(table, Some(init_table));
+    duckdb_table_function_set_function(table, Some(invoke_table));
+    let state = duckdb_register_table_function(connection, table);
+    duckdb_destroy_table_function(&mut table);
+    if state != duckdb_state_DuckDBSuccess {
+        bail!(
+            "failed to register table function: {}",
+            name.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn bind_table(info: duckdb_bind_info) {
+    let function = &*(duckdb_bind_get_extra_info(info) as *const TableFunction);
+    match guard(|| function.bind(info)) {
+        Ok(input) => {
+            duckdb_bind_set_bind_data(
+                info,
+                Box::into_raw(Box::new(input)) as *mut c_void,
+                Some(drop_boxed::<TableInput>),
+            );
+        }
+        Err(e) => duckdb_bind_set_error(info, error_message(&e).as_ptr()),
+    }
+}
+
+unsafe extern "C" fn init_table(info: duckdb_init_info) {
+    let function = &*(duckdb_init_get_extra_info(info) as *const TableFunction);
+    let input = &*(duckdb_init_get_bind_data(info) as *const TableInput);
+    // output batches must be returned in order
+    duckdb_init_set_max_threads(info, 1);
+    match guard(|| function.init(input)) {
+        Ok(output) => {
+            duckdb_init_set_init_data(
+                info,
+                Box::into_raw(Box::new(output)) as *mut c_void,
+                Some(drop_boxed::<TableOutput>),
+            );
+        }
+        Err(e) => duckdb_init_set_error(info, error_message(&e).as_ptr()),
+    }
+}
+
+unsafe extern "C" fn invoke_table(info: duckdb_function_info, output: duckdb_data_chunk) {
+    let function = &*(duckdb_function_get_extra_info(info) as *const TableFunction);
+    let batches = &*(duckdb_function_get_init_data(info) as *const TableOutput);
+    if let Err(e) = guard(|| function.invoke(batches, output)) {
+        duckdb_function_set_error(info, error_message(&e).as_ptr());
+    }
+}
+
+impl TableFunction {
+    /// Add the output column and convert the constant arguments when the query is bound.
+    unsafe fn bind(&self, info: duckdb_bind_info) -> Result<TableInput> {
+        let name = CString::new(self.signature.return_field.name().as_str())?;
+        let return_type = LogicalType::new(self.signature.return_field.data_type())?;
+        duckdb_bind_add_result_column(info, name.as_ptr(), return_type.0);
+
+        let mut fields = Vec::with_capacity(self.signature.arg_types.len());
+        let mut arrays: Vec<ArrayRef> = Vec::with_capacity(self.signature.arg_types.len());
+        for (i, ty) in self.signature.arg_types.iter().enumerate() {
+            let value = Value(duckdb_bind_get_parameter(info, i as idx_t));
+            arrays.push(value.to_array(ty)?);
+            fields.push(Field::new(format!("arg{i}"), ty.clone(), true));
+        }
+        let input = RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(fields)),
+            arrays,
+            &RecordBatchOptions::default().with_row_count(Some(1)),
+        )?;
+        Ok(TableInput { input })
+    }
+
+    /// Start evaluating the function when the scan is initialized.
+    fn init(&self, input: &TableInput) -> Result<TableOutput> {
+        let runtime = self.runtime.clone();
+        let name = self.signature.name.clone();
+        let input = input.input.clone();
+        let chunk_size = unsafe { duckdb_vector_size() } as usize;
+        let (tx, rx) = sync_channel(0);
+        std::thread::Builder::new()
+            .name(format!("udtf-{name}"))
+            .spawn(move || {
+                let iter = match runtime.call_table_function(&name, &input, chunk_size) {
+                    Ok(iter) => iter,
+                    Err(e) => {
+                        _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+                for batch in iter {
+                    // the scan has been finished or cancelled
+                    if tx.send(batch).is_err() {
+                        return;
+                    }
+                }
+            })?;
+        Ok(TableOutput {
+            batches: Mutex::new(rx),
+        })
+    }
+
+    unsafe fn invoke(&self, output: &TableOutput, chunk: duckdb_data_chunk) -> Result<()> {
+        let batch = loop {
+            // the channel is disconnected after the last batch
+            let Ok(batch) = output.batches.lock().unwrap().recv() else {
+                duckdb_data_chunk_set_size(chunk, 0);
+                return Ok(());
+            };
+            let batch = batch?;
+            // an empty chunk would end the scan
+            if batch.num_rows() > 0 {
+                break batch;
+            }
+        };
+        check_row_errors(&batch, 2)?;
+        // the first column is the row index
+        let mut converted = self.output.convert(batch.column(1).clone())?;
+        duckdb_vector_reference_vector(
+            duckdb_data_chunk_get_vector(chunk, 0),
+            duckdb_data_chunk_get_vector(converted, 0),
+        );
+        duckdb_data_chunk_set_size(chunk, batch.num_rows() as idx_t);
+        duckdb_destroy_data_chunk(&mut converted);
+        Ok(())
+    }
+}
+
+/// Converts output arrays of functions to DuckDB data chunks.
+struct OutputConverter {
+    connection: duckdb_connection,
+    field: FieldRef,
+    schema: duckdb_arrow_converted_schema,
+}
+
+impl Drop for OutputConverter {
+    fn drop(&mut self) {
+        unsafe { duckdb_destroy_arrow_converted_schema(&mut self.schema) };
+    }
+}
+
+impl OutputConverter {
+    unsafe fn new(connection: duckdb_connection, field: FieldRef) -> Result<Self> {
+        let mut ffi_schema = FFI_ArrowSchema::try_from(Schema::new(vec![field.clone()]))?;
+        let mut schema = ptr::null_mut();
+        check(duckdb_schema_from_arrow(
+            connection,
+            &mut ffi_schema as *mut FFI_ArrowSchema as *mut ArrowSchema,
+            &mut schema,
+        ))?;
+        Ok(Self {
+            connection,
+            field,
+            schema,
+        })
+    }
+
+    /// Convert an array into a data chunk with a single column.
+    ///
+    /// The chunk must be destroyed by the caller.
+    unsafe fn convert(&self, array: ArrayRef) -> Result<duckdb_data_chunk> {
+        if array.data_type() != self.field.data_type() {
+            bail!(
+                "expect return type {}, but got {}",
+                self.field.data_type(),
+                array.data_type()
+            );
+        }
+        let array = StructArray::new(Fields::from(vec![self.field.clone()]), vec![array], None);
+        let mut ffi_array = FFI_ArrowArray::new(&array.to_data());
+        let mut chunk = ptr::null_mut();
+        let result = check(duckdb_data_chunk_from_arrow(
+            self.connection,
+            &mut ffi_array as *mut FFI_ArrowArray as *mut ArrowArray,
+            self.schema,
+            &mut chunk,
+        ));
+        // the ownership of data has been passed to DuckDB
+        std::mem::forget(ffi_array);
+        result?;
+        Ok(chunk)
+    }
+}
+
+/// Convert the error data returned by DuckDB into a result.
+unsafe fn check(mut error: duckdb_error_data) -> Result<()> {
+    if error.is_null() {
+        return Ok(());
+    }
+    let result = if duckdb_error_data_has_error(error) {
+        let message = CStr::from_ptr(duckdb_error_data_message(error));
+        Err(anyhow!("{}", message.to_string_lossy()))
+    } else {
+        Ok(())
+    };
+    duckdb_destroy_error_data(&mut error);
+    result
+}
+
+/// Fail with the first error in the error column of an output, if any.
+///
+/// Some runtimes, e.g. Python, return errors of rows in an `error` column
+/// after the `num_values` columns of values, which can not be passed to DuckDB.
+fn check_row_errors(output: &RecordBatch, num_values: usize) -> Result<()> {
+    if output.num_columns() <= num_values {
+        return Ok(());
+    }
+    let schema = output.schema();
+    let field = schema.field(output.num_columns() - 1);
+    if field.name() != "error" {
+        return Ok(());
+    }
+    let Some(errors) = output.columns().last().unwrap().as_string_opt::<i32>() else {
+        return Ok(());
+    };
+    match errors.iter().flatten().next() {
+        Some(error) => bail!("{error}"),
+        None => Ok(()),
+    }
+}
+
+/// Run a callback, converting panics into errors so that they do not unwind into DuckDB.
+fn guard<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|e| {
+        let message = (e.downcast_ref::<&str>().copied())
+            .or_else(|| e.downcast_ref::<String>().map(|s| s.as_str()))
+            .unwrap_or("unknown panic");
+        Err(anyhow!("panicked: {message}"))
+    })
+}
+
+fn error_message(e: &anyhow::Error) -> CString {
+    CString::new(format!("{e:#}").replace('\0', " ")).unwrap()
+}
+
+unsafe extern "C" fn drop_boxed<T>(ptr: *mut c_void) {
+    drop(Box::from_raw(ptr as *mut T));
+}
@@ -0,0 +1,93 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion from Arrow types to DuckDB logical types.
+
+use std::ffi::CString;
+
+use anyhow::{bail, Result};
+use arrow_schema::{DataType, TimeUnit};
+use libduckdb_sys::*;
+
+/// An owned DuckDB logical type, destroyed on drop.
+pub(crate) struct LogicalType(pub duckdb_logical_type);
+
+impl Drop for LogicalType {
+    fn drop(&mut self) {
+        unsafe { duckdb_destroy_logical_type(&mut self.0) };
+    }
+}
+
+impl LogicalType {
+    /// Create the DuckDB logical type corresponding to an Arrow type.
+    pub fn new(ty: &DataType) -> Result<Self> {
+        let id = match ty {
+            DataType::Boolean => DUCKDB_TYPE_DUCKDB_TYPE_BOOLEAN,
+            DataType::Int8 => DUCKDB_TYPE_DUCKDB_TYPE_TINYINT,
+            DataType::Int16 => DUCKDB_TYPE_DUCKDB_TYPE_SMALLINT,
+            DataType::Int32 => DUCKDB_TYPE_DUCKDB_TYPE_INTEGER,
+            DataType::Int64 => DUCKDB_TYPE_DUCKDB_TYPE_BIGINT,
+            DataType::UInt8 => DUCKDB_TYPE_DUCKDB_TYPE_UTINYINT,
+            DataType::UInt16 => DUCKDB_TYPE_DUCKDB_TYPE_USMALLINT,
+            DataType::UInt32 => DUCKDB_TYPE_DUCKDB_TYPE_UINTEGER,
+            DataType::UInt64 => DUCKDB_TYPE_DUCKDB_TYPE_UBIGINT,
+            DataType::Float32 => DUCKDB_TYPE_DUCKDB_TYPE_FLOAT,
+            DataType::Float64 => DUCKDB_TYPE_DUCKDB_TYPE_DOUBLE,
+            DataType::Utf8 | DataType::LargeUtf8 => DUCKDB_TYPE_DUCKDB_TYPE_VARCHAR,
+            DataType::Binary | DataType::LargeBinary => DUCKDB_TYPE_DUCKDB_TYPE_BLOB,
+            DataType::Date32 => DUCKDB_TYPE_DUCKDB_TYPE_DATE,
+            DataType::Time64(TimeUnit::Microsecond) => DUCKDB_TYPE_DUCKDB_TYPE_TIME,
+            DataType::Timestamp(TimeUnit::Second, None) => DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_S,
+            DataType::Timestamp(TimeUnit::Millisecond, None) => {
+                DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_MS
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, None) => DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP,
+            DataType::Timestamp(TimeUnit::Nanosecond, None) => DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_NS,
+            DataType::Timestamp(TimeUnit::Microsecond, Some(_)) => {
+                DUCKDB_TYPE_DUCKDB_TYPE_TIMESTAMP_TZ
+            }
+            DataType::Interval(_) => DUCKDB_TYPE_DUCKDB_TYPE_INTERVAL,
+            DataType::Decimal128(precision, scale) if *precision <= 38 && *scale >= 0 => {
+                let ty = unsafe { duckdb_create_decimal_type(*precision, *scale as u8) };
+                return Ok(Self(ty));
+            }
+            DataType::List(field) | DataType::LargeList(field) => {
+                let child = Self::new(field.data_type())?;
+                return Ok(Self(unsafe { duckdb_create_list_type(child.0) }));
+            }
+            DataType::Struct(fields) => {
+                let names = fields
+                    .iter()
+                    .map(|f| CString::new(f.name().as_str()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let types = fields
+                    .iter()
+                    .map(|f| Self::new(f.data_type()))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut names: Vec<_> = names.iter().map(|n| n.as_ptr()).collect();
+                let mut types: Vec<_> = types.iter().map(|t| t.0).collect();
+                let ty = unsafe {
+                    duckdb_create_struct_type(
+                        types.as_mut_ptr(),
+                        names.as_mut_ptr(),
+                        fields.len() as idx_t,
+                    )
+                };
+                return Ok(Self(ty));
+            }
+            _ => bail!("type not supported by DuckDB: {ty}"),
+        };
+        Ok(Self(unsafe { duckdb_create_logical_type(id) }))
+    }
+}
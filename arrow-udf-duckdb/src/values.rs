@@ -0,0 +1,191 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion from DuckDB values to Arrow arrays.
+
+use std::ffi::{c_void, CStr};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use arrow_array::types::{IntervalDayTime, IntervalMonthDayNano};
+use arrow_array::*;
+use arrow_buffer::OffsetBuffer;
+use arrow_schema::{DataType, IntervalUnit, TimeUnit};
+use libduckdb_sys::*;
+
+/// An owned DuckDB value, destroyed on drop.
+pub(crate) struct Value(pub duckdb_value);
+
+impl Drop for Value {
+    fn drop(&mut self) {
+        unsafe { duckdb_destroy_value(&mut self.0) };
+    }
+}
+
+impl Value {
+    /// Convert the value into an array with a single element of the Arrow type.
+    ///
+    /// The value must have the DuckDB type corresponding to the Arrow type,
+    /// e.g. a parameter declared with [`LogicalType::new`](crate::types::LogicalType::new).
+    pub unsafe fn to_array(&self, ty: &DataType) -> Result<ArrayRef> {
+        if duckdb_is_null_value(self.0) {
+            return Ok(new_null_array(ty, 1));
+        }
+        let value = self.0;
+        let array: ArrayRef = match ty {
+            DataType::Boolean => Arc::new(BooleanArray::from(vec![duckdb_get_bool(value)])),
+            DataType::Int8 => Arc::new(Int8Array::from(vec![duckdb_get_int8(value)])),
+            DataType::Int16 => Arc::new(Int16Array::from(vec![duckdb_get_int16(value)])),
+            DataType::Int32 => Arc::new(Int32Array::from(vec![duckdb_get_int32(value)])),
+            DataType::Int64 => Arc::new(Int64Array::from(vec![duckdb_get_int64(value)])),
+            DataType::UInt8 => Arc::new(UInt8Array::from(vec![duckdb_get_uint8(value)])),
+            DataType::UInt16 => Arc::new(UInt16Array::from(vec![duckdb_get_uint16(value)])),
+            DataType::UInt32 => Arc::new(UInt32Array::from(vec![duckdb_get_uint32(value)])),
+            DataType::UInt64 => Arc::new(UInt64Array::from(vec![duckdb_get_uint64(value)])),
+            DataType::Float32 => Arc::new(Float32Array::from(vec![duckdb_get_float(value)])),
+            DataType::Float64 => Arc::new(Float64Array::from(vec![duckdb_get_double(value)])),
+            DataType::Utf8 => Arc::new(StringArray::from(vec![self.varchar()])),
+            DataType::LargeUtf8 => Arc::new(LargeStringArray::from(vec![self.varchar()])),
+            DataType::Binary => Arc::new(BinaryArray::from_vec(vec![&self.blob()])),
+            DataType::LargeBinary => Arc::new(LargeBinaryArray::from_vec(vec![&self.blob()])),
+            DataType::Date32 => Arc::new(Date32Array::from(vec![duckdb_get_date(value).days])),
+            DataType::Time64(TimeUnit::Microsecond) => {
+                Arc::new(Time64MicrosecondArray::from(vec![
+                    duckdb_get_time(value).micros,
+                ]))
+            }
+            DataType::Timestamp(TimeUnit::Second, None) => {
+                Arc::new(TimestampSecondArray::from(vec![
+                    duckdb_get_timestamp_s(value).seconds,
+                ]))
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, None) => {
+                Arc::new(TimestampMillisecondArray::from(vec![
+                    duckdb_get_timestamp_ms(value).millis,
+                ]))
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, None) => {
+                Arc::new(TimestampMicrosecondArray::from(vec![
+                    duckdb_get_timestamp(value).micros,
+                ]))
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+                Arc::new(TimestampNanosecondArray::from(vec![
+                    duckdb_get_timestamp_ns(value).nanos,
+                ]))
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, Some(tz)) => Arc::new(
+                TimestampMicrosecondArray::from(vec![duckdb_get_timestamp_tz(value).micros])
+                    .with_timezone(tz.clone()),
+            ),
+            DataType::Interval(unit) => {
+                let interval = duckdb_get_interval(value);
+                match unit {
+                    IntervalUnit::YearMonth if interval.days == 0 && interval.micros == 0 => {
+                        Arc::new(IntervalYearMonthArray::from(vec![interval.months]))
+                    }
+                    IntervalUnit::DayTime
+                        if interval.months == 0 && interval.micros % 1000 == 0 =>
+                    {
+                        let Ok(millis) = i32::try_from(interval.micros / 1000) else {
+                            bail!("interval out of range of {ty}");
+                        };
+                        Arc::new(IntervalDayTimeArray::from(vec![IntervalDayTime::new(
+                            interval.days,
+                            millis,
+                        )]))
+                    }
+                    IntervalUnit::MonthDayNano => Arc::new(IntervalMonthDayNanoArray::from(vec![
+                        IntervalMonthDayNano::new(
+                            interval.months,
+                            interval.days,
+                            interval.micros * 1000,
+                        ),
+                    ])),
+                    _ => bail!("interval out of range of {ty}"),
+                }
+            }
+            DataType::Decimal128(precision, scale) => {
+                let decimal = duckdb_get_decimal(value);
+                if decimal.scale as i8 != *scale {
+                    bail!(
+                        "expect decimal with scale {scale}, but got {}",
+                        decimal.scale
+                    );
+                }
+                let value = ((decimal.value.upper as i128) << 64) | decimal.value.lower as i128;
+                Arc::new(
+                    Decimal128Array::from(vec![value])
+                        .with_precision_and_scale(*precision, *scale)?,
+                )
+            }
+            DataType::List(field) | DataType::LargeList(field) => {
+                let children = (0..duckdb_get_list_size(value))
+                    .map(|i| Value(duckdb_get_list_child(value, i)).to_array(field.data_type()))
+                    .collect::<Result<Vec<_>>>()?;
+                let values = match children.is_empty() {
+                    true => new_empty_array(field.data_type()),
+                    false => {
+                        let children: Vec<_> = children.iter().map(|c| c.as_ref()).collect();
+                        arrow_select::concat::concat(&children)?
+                    }
+                };
+                let lengths = [values.len()];
+                match ty {
+                    DataType::List(_) => Arc::new(ListArray::try_new(
+                        field.clone(),
+                        OffsetBuffer::from_lengths(lengths),
+                        values,
+                        None,
+                    )?),
+                    _ => Arc::new(LargeListArray::try_new(
+                        field.clone(),
+                        OffsetBuffer::from_lengths(lengths),
+                        values,
+                        None,
+                    )?),
+                }
+            }
+            DataType::Struct(fields) => {
+                let children = (fields.iter().enumerate())
+                    .map(|(i, f)| {
+                        Value(duckdb_get_struct_child(value, i as idx_t)).to_array(f.data_type())
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(StructArray::try_new(fields.clone(), children, None)?)
+            }
+            _ => bail!("type not supported by DuckDB: {ty}"),
+        };
+        Ok(array)
+    }
+
+    /// Returns the string of a `VARCHAR` value.
+    unsafe fn varchar(&self) -> String {
+        let ptr = duckdb_get_varchar(self.0);
+        let string = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+        duckdb_free(ptr as *mut c_void);
+        string
+    }
+
+    /// Returns the bytes of a `BLOB` value.
+    unsafe fn blob(&self) -> Vec<u8> {
+        let blob = duckdb_get_blob(self.0);
+        if blob.data.is_null() {
+            return vec![];
+        }
+        let bytes = std::slice::from_raw_parts(blob.data as *const u8, blob.size as usize).to_vec();
+        duckdb_free(blob.data);
+        bytes
+    }
+}
@@ -0,0 +1,534 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of functions in an in-memory database.
+//!
+//! The bundled DuckDB library is linked into the tests, and its C API is passed to
+//! `init_extension` in the same way as DuckDB does when loading an extension.
+
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+use std::sync::{Arc, OnceLock};
+
+use arrow_schema::{DataType, Field};
+use arrow_udf_duckdb::ffi::*;
+use arrow_udf_duckdb::{init_extension, Registration};
+use arrow_udf_python::{CallMode, FunctionProperties, Runtime, Volatility};
+
+/// Define the API struct with the functions of the linked library used by the bridge and tests.
+macro_rules! api {
+    ($(fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*) => {
+        mod linked {
+            use super::*;
+
+            extern "C" {
+                $(pub fn $name($($arg: $ty),*) $(-> $ret)?;)*
+            }
+        }
+
+        fn api() -> duckdb_ext_api_v1 {
+            // SAFETY: all fields are optional function pointers
+            let mut api: duckdb_ext_api_v1 = unsafe { std::mem::zeroed() };
+            $(api.$name = Some(linked::$name);)*
+            api
+        }
+    };
+}
+
+api! {
+    fn duckdb_bind_add_result_column(
+        info: duckdb_bind_info,
+        name: *const c_char,
+        type_: duckdb_logical_type,
+    );
+    fn duckdb_bind_get_extra_info(info: duckdb_bind_info) -> *mut c_void;
+    fn duckdb_bind_get_parameter(info: duckdb_bind_info, index: idx_t) -> duckdb_value;
+    fn duckdb_bind_set_bind_data(
+        info: duckdb_bind_info,
+        bind_data: *mut c_void,
+        destroy: duckdb_delete_callback_t,
+    );
+    fn duckdb_bind_set_error(info: duckdb_bind_info, error: *const c_char);
+    fn duckdb_close(database: *mut duckdb_database);
+    fn duckdb_column_count(result: *mut duckdb_result) -> idx_t;
+    fn duckdb_connect(
+        database: duckdb_database,
+        out_connection: *mut duckdb_connection,
+    ) -> duckdb_state;
+    fn duckdb_connection_get_arrow_options(
+        connection: duckdb_connection,
+        out_arrow_options: *mut duckdb_arrow_options,
+    );
+    fn duckdb_create_decimal_type(width: u8, scale: u8) -> duckdb_logical_type;
+    fn duckdb_create_list_type(type_: duckdb_logical_type) -> duckdb_logical_type;
+    fn duckdb_create_logical_type(type_: duckdb_type) -> duckdb_logical_type;
+    fn duckdb_create_scalar_function() -> duckdb_scalar_function;
+    fn duckdb_create_struct_type(
+        member_types: *mut duckdb_logical_type,
+        member_names: *mut *const c_char,
+        member_count: idx_t,
+    ) -> duckdb_logical_type;
+    fn duckdb_create_table_function() -> duckdb_table_function;
+    fn duckdb_data_chunk_from_arrow(
+        connection: duckdb_connection,
+        arrow_array: *mut ArrowArray,
+        converted_schema: duckdb_arrow_converted_schema,
+        out_chunk: *mut duckdb_data_chunk,
+    ) -> duckdb_error_data;
+    fn duckdb_data_chunk_get_size(chunk: duckdb_data_chunk) -> idx_t;
+    fn duckdb_data_chunk_get_vector(chunk: duckdb_data_chunk, col_idx: idx_t) -> duckdb_vector;
+    fn duckdb_data_chunk_set_size(chunk: duckdb_data_chunk, size: idx_t);
+    fn duckdb_data_chunk_to_arrow(
+        arrow_options: duckdb_arrow_options,
+        chunk: duckdb_data_chunk,
+        out_arrow_array: *mut ArrowArray,
+    ) -> duckdb_error_data;
+    fn duckdb_destroy_arrow_converted_schema(
+        arrow_converted_schema: *mut duckdb_arrow_converted_schema,
+    );
+    fn duckdb_destroy_arrow_options(arrow_options: *mut duckdb_arrow_options);
+    fn duckdb_destroy_data_chunk(chunk: *mut duckdb_data_chunk);
+    fn duckdb_destroy_error_data(error_data: *mut duckdb_error_data);
+    fn duckdb_destroy_logical_type(type_: *mut duckdb_logical_type);
+    fn duckdb_destroy_result(result: *mut duckdb_result);
+    fn duckdb_destroy_scalar_function(scalar_function: *mut duckdb_scalar_function);
+    fn duckdb_destroy_table_function(table_function: *mut duckdb_table_function);
+    fn duckdb_destroy_value(value: *mut duckdb_value);
+    fn duckdb_disconnect(connection: *mut duckdb_connection);
+    fn duckdb_error_data_has_error(error_data: duckdb_error_data) -> bool;
+    fn duckdb_error_data_message(error_data: duckdb_error_data) -> *const c_char;
+    fn duckdb_free(ptr: *mut c_void);
+    fn duckdb_function_get_extra_info(info: duckdb_function_info) -> *mut c_void;
+    fn duckdb_function_get_init_data(info: duckdb_function_info) -> *mut c_void;
+    fn duckdb_function_set_error(info: duckdb_function_info, error: *const c_char);
+    fn duckdb_get_blob(val: duckdb_value) -> duckdb_blob;
+    fn duckdb_get_bool(val: duckdb_value) -> bool;
+    fn duckdb_get_date(val: duckdb_value) -> duckdb_date;
+    fn duckdb_get_decimal(val: duckdb_value) -> duckdb_decimal;
+    fn duckdb_get_double(val: duckdb_value) -> f64;
+    fn duckdb_get_float(val: duckdb_value) -> f32;
+    fn duckdb_get_int16(val: duckdb_value) -> i16;
+    fn duckdb_get_int32(val: duckdb_value) -> i32;
+    fn duckdb_get_int64(val: duckdb_value) -> i64;
+    fn duckdb_get_int8(val: duckdb_value) -> i8;
+    fn duckdb_get_interval(val: duckdb_value) -> duckdb_interval;
+    fn duckdb_get_list_child(value: duckdb_value, index: idx_t) -> duckdb_value;
+    fn duckdb_get_list_size(value: duckdb_value) -> idx_t;
+    fn duckdb_get_struct_child(value: duckdb_value, index: idx_t) -> duckdb_value;
+    fn duckdb_get_time(val: duckdb_value) -> duckdb_time;
+    fn duckdb_get_timestamp(val: duckdb_value) -> duckdb_timestamp;
+    fn duckdb_get_timestamp_ms(val: duckdb_value) -> duckdb_timestamp_ms;
+    fn duckdb_get_timestamp_ns(val: duckdb_value) -> duckdb_timestamp_ns;
+    fn duckdb_get_timestamp_s(val: duckdb_value) -> duckdb_timestamp_s;
+    fn duckdb_get_timestamp_tz(val: duckdb_value) -> duckdb_timestamp;
+    fn duckdb_get_uint16(val: duckdb_value) -> u16;
+    fn duckdb_get_uint32(val: duckdb_value) -> u32;
+    fn duckdb_get_uint64(val: duckdb_value) -> u64;
+    fn duckdb_get_uint8(val: duckdb_value) -> u8;
+    fn duckdb_get_varchar(value: duckdb_value) -> *mut c_char;
+    fn duckdb_init_get_bind_data(info: duckdb_init_info) -> *mut c_void;
+    fn duckdb_init_get_extra_info(info: duckdb_init_info) -> *mut c_void;
+    fn duckdb_init_set_error(info: duckdb_init_info, error: *const c_char);
+    fn duckdb_init_set_init_data(
+        info: duckdb_init_info,
+        init_data: *mut c_void,
+        destroy: duckdb_delete_callback_t,
+    );
+    fn duckdb_init_set_max_threads(info: duckdb_init_info, max_threads: idx_t);
+    fn duckdb_is_null_value(value: duckdb_value) -> bool;
+    fn duckdb_open(path: *const c_char, out_database: *mut duckdb_database) -> duckdb_state;
+    fn duckdb_query(
+        connection: duckdb_connection,
+        query: *const c_char,
+        out_result: *mut duckdb_result,
+    ) -> duckdb_state;
+    fn duckdb_register_scalar_function(
+        con: duckdb_connection,
+        scalar_function: duckdb_scalar_function,
+    ) -> duckdb_state;
+    fn duckdb_register_table_function(
+        con: duckdb_connection,
+        function: duckdb_table_function,
+    ) -> duckdb_state;
+    fn duckdb_result_error(result: *mut duckdb_result) -> *const c_char;
+    fn duckdb_row_count(result: *mut duckdb_result) -> idx_t;
+    fn duckdb_scalar_function_add_parameter(
+        scalar_function: duckdb_scalar_function,
+        type_: duckdb_logical_type,
+    );
+    fn duckdb_scalar_function_get_extra_info(info: duckdb_function_info) -> *mut c_void;
+    fn duckdb_scalar_function_set_error(info: duckdb_function_info, error: *const c_char);
+    fn duckdb_scalar_function_set_extra_info(
+        scalar_function: duckdb_scalar_function,
+        extra_info: *mut c_void,
+        destroy: duckdb_delete_callback_t,
+    );
+    fn duckdb_scalar_function_set_function(
+        scalar_function: duckdb_scalar_function,
+        function: duckdb_scalar_function_t,
+    );
+    fn duckdb_scalar_function_set_name(
+        scalar_function: duckdb_scalar_function,
+        name: *const c_char,
+    );
+    fn duckdb_scalar_function_set_return_type(
+        scalar_function: duckdb_scalar_function,
+        type_: duckdb_logical_type,
+    );
+    fn duckdb_scalar_function_set_special_handling(scalar_function: duckdb_scalar_function);
+    fn duckdb_scalar_function_set_volatile(scalar_function: duckdb_scalar_function);
+    fn duckdb_schema_from_arrow(
+        connection: duckdb_connection,
+        schema: *mut ArrowSchema,
+        out_types: *mut duckdb_arrow_converted_schema,
+    ) -> duckdb_error_data;
+    fn duckdb_table_function_add_parameter(
+        table_function: duckdb_table_function,
+        type_: duckdb_logical_type,
+    );
+    fn duckdb_table_function_set_bind(
+        table_function: duckdb_table_function,
+        bind: duckdb_table_function_bind_t,
+    );
+    fn duckdb_table_function_set_extra_info(
+        table_function: duckdb_table_function,
+        extra_info: *mut c_void,
+        destroy: duckdb_delete_callback_t,
+    );
+    fn duckdb_table_function_set_function(
+        table_function: duckdb_table_function,
+        function: duckdb_table_function_t,
+    );
+    fn duckdb_table_function_set_init(
+        table_function: duckdb_table_function,
+        init: duckdb_table_function_init_t,
+    );
+    fn duckdb_table_function_set_name(table_function: duckdb_table_function, name: *const c_char);
+    fn duckdb_to_arrow_schema(
+        arrow_options: duckdb_arrow_options,
+        types: *mut duckdb_logical_type,
+        names: *mut *const c_char,
+        column_count: idx_t,
+        out_schema: *mut ArrowSchema,
+    ) -> duckdb_error_data;
+    fn duckdb_value_is_null(result: *mut duckdb_result, col: idx_t, row: idx_t) -> bool;
+    fn duckdb_value_varchar(result: *mut duckdb_result, col: idx_t, row: idx_t) -> *mut c_char;
+    fn duckdb_vector_reference_vector(to_vector: duckdb_vector, from_vector: duckdb_vector);
+    fn duckdb_vector_size() -> idx_t;
+}
+
+/// The state of loading an extension, passed as its info.
+struct Loading {
+    database: duckdb_database,
+    error: Option<String>,
+}
+
+static ACCESS: duckdb_extension_access = duckdb_extension_access {
+    set_error: Some(set_error),
+    get_database: Some(get_database),
+    get_api: Some(get_api),
+};
+
+unsafe extern "C" fn set_error(info: duckdb_extension_info, error: *const c_char) {
+    let loading = &mut *(info as *mut Loading);
+    loading.error = Some(CStr::from_ptr(error).to_string_lossy().into_owned());
+}
+
+unsafe extern "C" fn get_database(info: duckdb_extension_info) -> *mut duckdb_database {
+    &mut (*(info as *mut Loading)).database
+}
+
+unsafe extern "C" fn get_api(_: duckdb_extension_info, _: *const c_char) -> *const c_void {
+    static API: OnceLock<duckdb_ext_api_v1> = OnceLock::new();
+    API.get_or_init(api) as *const duckdb_ext_api_v1 as *const c_void
+}
+
+/// An in-memory database with a connection.
+struct Database {
+    database: duckdb_database,
+    connection: duckdb_connection,
+}
+
+impl Database {
+    fn new() -> Self {
+        unsafe {
+            // initialize the API to open the database before loading any extension
+            let info = ptr::null_mut();
+            assert!(duckdb_rs_extension_api_init(info, &ACCESS, "v1.2.0").unwrap());
+            let mut database = ptr::null_mut();
+            assert_eq!(
+                duckdb_open(ptr::null(), &mut database),
+                duckdb_state_DuckDBSuccess
+            );
+            let mut connection = ptr::null_mut();
+            assert_eq!(
+                duckdb_connect(database, &mut connection),
+                duckdb_state_DuckDBSuccess
+            );
+            Self {
+                database,
+                connection,
+            }
+        }
+    }
+
+    /// Load an extension registering functions with its connection.
+    fn load(
+        &self,
+        register: impl FnOnce(duckdb_connection) -> anyhow::Result<()>,
+    ) -> Result<(), String> {
+        let mut loading = Loading {
+            database: self.database,
+            error: None,
+        };
+        let info = &mut loading as *mut Loading as duckdb_extension_info;
+        match unsafe { init_extension(info, &ACCESS, register) } {
+            true => Ok(()),
+            false => Err(loading.error.unwrap_or_default()),
+        }
+    }
+
+    /// Run a query and return its rows as strings.
+    fn query(&self, sql: &str) -> Result<Vec<Vec<Option<String>>>, String> {
+        let sql = CString::new(sql).unwrap();
+        unsafe {
+            let mut result = std::mem::zeroed();
+            let state = duckdb_query(self.connection, sql.as_ptr(), &mut result);
+            let output = if state == duckdb_state_DuckDBSuccess {
+                let rows = (0..duckdb_row_count(&mut result))
+                    .map(|row| {
+                        (0..duckdb_column_count(&mut result))
+                            .map(|column| {
+                                if duckdb_value_is_null(&mut result, column, row) {
+                                    return None;
+                                }
+                                let ptr = duckdb_value_varchar(&mut result, column, row);
+                                let value = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+                                duckdb_free(ptr as *mut c_void);
+                                Some(value)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                Ok(rows)
+            } else {
+                let error = CStr::from_ptr(duckdb_result_error(&mut result));
+                Err(error.to_string_lossy().into_owned())
+            };
+            duckdb_destroy_result(&mut result);
+            output
+        }
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        unsafe {
+            duckdb_disconnect(&mut self.connection);
+            duckdb_close(&mut self.database);
+        }
+    }
+}
+
+/// Returns rows of a single column.
+fn column(values: &[Option<&str>]) -> Vec<Vec<Option<String>>> {
+    values.iter().map(|v| vec![v.map(String::from)]).collect()
+}
+
+/// Load the functions of a Python runtime.
+fn load_python(database: &Database) {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function_with_properties(
+            "gcd",
+            DataType::Int32,
+            FunctionProperties::new()
+                .with_mode(CallMode::ReturnNullOnNullInput)
+                .with_volatility(Volatility::Immutable),
+            r#"
+def gcd(a: int, b: int) -> int:
+    while b:
+        a, b = b, a % b
+    return a
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "checked_div",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def checked_div(a: int, b: int) -> int:
+    if b == 0:
+        raise ValueError("division by zero")
+    return a // b
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def series(n: int):
+    if n < 0:
+        raise ValueError("negative length")
+    for i in range(n):
+        yield i
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "byte_values",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def byte_values(data: bytes, scale: float):
+    for byte in data:
+        yield int(byte * scale)
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "naturals",
+            DataType::Int64,
+            CallMode::CalledOnNullInput,
+            r#"
+def naturals():
+    i = 0
+    while True:
+        yield i
+        i += 1
+"#,
+        )
+        .unwrap();
+    let runtime = Arc::new(runtime);
+    database
+        .load(|connection| unsafe {
+            Registration::new(runtime)
+                .scalar(
+                    "gcd",
+                    vec![DataType::Int32, DataType::Int32],
+                    DataType::Int32,
+                )
+                .scalar(
+                    "checked_div",
+                    vec![DataType::Int32, DataType::Int32],
+                    DataType::Int32,
+                )
+                .table(
+                    "series",
+                    vec![DataType::Int32],
+                    Field::new("series", DataType::Int32, true),
+                )
+                .table(
+                    "byte_values",
+                    vec![DataType::Binary, DataType::Float64],
+                    Field::new("value", DataType::Int32, true),
+                )
+                .table("naturals", vec![], Field::new("n", DataType::Int64, true))
+                .register(connection)
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_scalar_function() {
+    let database = Database::new();
+    load_python(&database);
+
+    let rows = database
+        .query("SELECT gcd(a, b) FROM (VALUES (15, 25), (5, NULL), (NULL, 3), (7, 0)) t(a, b)")
+        .unwrap();
+    assert_eq!(rows, column(&[Some("5"), None, None, Some("7")]));
+
+    let rows = database.query("SELECT checked_div(7, 2)").unwrap();
+    assert_eq!(rows, column(&[Some("3")]));
+
+    let error = database.query("SELECT checked_div(7, 0)").unwrap_err();
+    assert!(error.contains("ValueError: division by zero"), "{error}");
+}
+
+#[test]
+fn test_table_function() {
+    let database = Database::new();
+    load_python(&database);
+
+    let rows = database.query("SELECT * FROM series(3)").unwrap();
+    assert_eq!(rows, column(&[Some("0"), Some("1"), Some("2")]));
+
+    let rows = database.query("SELECT * FROM series(NULL)").unwrap();
+    assert_eq!(rows, column(&[]));
+
+    let error = database.query("SELECT * FROM series(-1)").unwrap_err();
+    assert!(error.contains("ValueError: negative length"), "{error}");
+}
+
+#[test]
+fn test_volatility() {
+    let database = Database::new();
+    load_python(&database);
+    let plan = |sql: &str| {
+        let rows = database.query(&format!("EXPLAIN {sql}")).unwrap();
+        rows.into_iter().flatten().flatten().collect::<String>()
+    };
+
+    // immutable functions are folded into constants
+    assert!(!plan("SELECT gcd(15, 25)").contains("gcd"));
+    assert!(plan("SELECT checked_div(7, 2)").contains("checked_div"));
+}
+
+#[test]
+fn test_table_function_arguments() {
+    let database = Database::new();
+    load_python(&database);
+
+    let rows = database
+        .query(r"SELECT * FROM byte_values('\x00\x80\xFF'::BLOB, 0.5)")
+        .unwrap();
+    assert_eq!(rows, column(&[Some("0"), Some("64"), Some("127")]));
+
+    let rows = database
+        .query(r"SELECT * FROM byte_values('\x03'::BLOB, 1 / 3)")
+        .unwrap();
+    assert_eq!(rows, column(&[Some("1")]));
+}
+
+#[test]
+fn test_table_function_streaming() {
+    let database = Database::new();
+    load_python(&database);
+
+    // the function never ends, so it must be evaluated as the output is consumed
+    let rows = database.query("SELECT * FROM naturals() LIMIT 3").unwrap();
+    assert_eq!(rows, column(&[Some("0"), Some("1"), Some("2")]));
+}
+
+#[test]
+fn test_unsupported_type() {
+    let database = Database::new();
+    let runtime = Arc::new(Runtime::new().unwrap());
+    let error = database
+        .load(|connection| unsafe {
+            Registration::new(runtime)
+                .scalar("f", vec![DataType::Float16], DataType::Int32)
+                .register(connection)
+        })
+        .unwrap_err();
+    assert_eq!(error, "type not supported by DuckDB: Float16");
+}
@@ -51,6 +51,21 @@ fn sleep(second: i32) -> i32 {
     0
 }
 
+#[function("spin()")]
+fn spin() {
+    loop {
+        std::hint::spin_loop();
+    }
+}
+
+#[function("log(varchar) -> int")]
+fn log(message: &str) -> i32 {
+    arrow_udf::ffi::log(arrow_udf::ffi::LOG_WARN, message);
+    println!("printed {message}");
+    eprintln!("error {message}");
+    message.len() as i32
+}
+
 #[function("decimal_add(decimal, decimal) -> decimal")]
 fn decimal_add(a: Decimal, b: Decimal) -> Decimal {
     a + b
@@ -88,3 +103,28 @@ fn key_value(kv: &str) -> Option<KeyValue<'_>> {
 fn range(x: i32) -> impl Iterator<Item = i32> {
     0..x
 }
+
+#[function("identity(boolean) -> boolean")]
+#[function("identity(int8) -> int8")]
+#[function("identity(int16) -> int16")]
+#[function("identity(int32) -> int32")]
+#[function("identity(int64) -> int64")]
+#[function("identity(uint8) -> uint8")]
+#[function("identity(uint16) -> uint16")]
+#[function("identity(uint32) -> uint32")]
+#[function("identity(uint64) -> uint64")]
+#[function("identity(float32) -> float32")]
+#[function("identity(float64) -> float64")]
+#[function("identity(decimal) -> decimal")]
+#[function("identity(date32) -> date32")]
+#[function("identity(time64) -> time64")]
+#[function("identity(timestamp) -> timestamp")]
+#[function("identity(interval) -> interval")]
+#[function("identity(json) -> json")]
+#[function("identity(string) -> string")]
+#[function("identity(binary) -> binary")]
+#[function("identity(largestring) -> largestring")]
+#[function("identity(largebinary) -> largebinary")]
+fn identity<T>(x: T) -> T {
+    x
+}
@@ -14,6 +14,7 @@
 
 package com.risingwave.functions;
 
+import java.nio.charset.StandardCharsets;
 import java.util.ArrayList;
 import java.util.Collections;
 import java.util.HashMap;
@@ -43,6 +44,10 @@ class UdfProducer extends NoOpFlightProducer {
         if (action.getType().equals("protocol_version")) {
             listener.onNext(new Result(new byte[] { 2 }));
             listener.onCompleted();
+        } else if (action.getType().equals("capabilities")) {
+            String capabilities = "arrowudf.function_kinds=scalar,table\n";
+            listener.onNext(new Result(capabilities.getBytes(StandardCharsets.UTF_8)));
+            listener.onCompleted();
         } else {
             listener.onError(CallStatus.UNIMPLEMENTED.toRuntimeException());
         }
@@ -33,6 +33,7 @@ class UserDefinedFunction:
     _name: str
     _input_schema: pa.Schema
     _result_schema: pa.Schema
+    _properties: Dict[str, str] = {}
     _io_threads: Optional[int]
     _executor: Optional[ThreadPoolExecutor]
 
@@ -208,7 +209,16 @@ class UserDefinedScalarFunctionWrapper(ScalarFunction):
 
     _func: Callable
 
-    def __init__(self, func, input_types, result_type, name=None, io_threads=None):
+    def __init__(
+        self,
+        func,
+        input_types,
+        result_type,
+        name=None,
+        io_threads=None,
+        volatility=None,
+        parallel=None,
+    ):
         self._func = func
         self._name = name or (
             func.__name__ if hasattr(func, "__name__") else func.__class__.__name__
@@ -220,6 +230,7 @@ class UserDefinedScalarFunctionWrapper(ScalarFunction):
             )
         )
         self._result_schema = pa.schema([(self._name, _to_data_type(result_type))])
+        self._properties = _to_properties(volatility, parallel)
 
         super().__init__(io_threads=io_threads)
 
@@ -269,6 +280,22 @@ class UserDefinedTableFunctionWrapper(TableFunction):
         return self._func(*args)
 
 
+def _to_properties(
+    volatility: Optional[str], parallel: Optional[str]
+) -> Dict[str, str]:
+    """Return the function properties to be sent in the schema metadata."""
+    properties = {}
+    if volatility is not None:
+        if volatility not in ("immutable", "stable", "volatile"):
+            raise ValueError(f"invalid volatility: {volatility}")
+        properties["arrowudf.volatility"] = volatility
+    if parallel is not None:
+        if parallel not in ("safe", "restricted", "unsafe"):
+            raise ValueError(f"invalid parallel safety: {parallel}")
+        properties["arrowudf.parallel"] = parallel
+    return properties
+
+
 def _to_list(x):
     if isinstance(x, list):
         return x
@@ -281,6 +308,8 @@ def udf(
     result_type: Union[str, pa.DataType],
     name: Optional[str] = None,
     io_threads: Optional[int] = None,
+    volatility: Optional[str] = None,
+    parallel: Optional[str] = None,
 ) -> Callable:
     """
     Annotation for creating a user-defined scalar function.
@@ -290,6 +319,8 @@ def udf(
     - result_type: A string or an Arrow data type that specifies the return value type.
     - name: An optional string specifying the function name. If not provided, the original name will be used.
     - io_threads: Number of I/O threads used per data chunk for I/O bound functions.
+    - volatility: An optional string among `immutable`, `stable` and `volatile`, telling clients whether the results can be cached.
+    - parallel: An optional string among `safe`, `restricted` and `unsafe`, telling clients whether the calls can be sharded.
 
     Example:
     ```
@@ -311,11 +342,17 @@ def udf(
 
     if io_threads is not None and io_threads > 1:
         return lambda f: UserDefinedScalarFunctionWrapper(
-            f, input_types, result_type, name, io_threads=io_threads
+            f,
+            input_types,
+            result_type,
+            name,
+            io_threads=io_threads,
+            volatility=volatility,
+            parallel=parallel,
         )
     else:
         return lambda f: UserDefinedScalarFunctionWrapper(
-            f, input_types, result_type, name
+            f, input_types, result_type, name, volatility=volatility, parallel=parallel
         )
 
 
@@ -375,7 +412,10 @@ class UdfServer(pa.flight.FlightServerBase):
     def _make_flight_info(self, udf: UserDefinedFunction) -> pa.flight.FlightInfo:
         """Return the flight info of a function."""
         # return the concatenation of input and output schema
-        full_schema = pa.schema(list(udf._input_schema) + list(udf._result_schema))
+        full_schema = pa.schema(
+            list(udf._input_schema) + list(udf._result_schema),
+            metadata=udf._properties or None,
+        )
         # we use `total_records` to indicate the number of input arguments
         return pa.flight.FlightInfo(
             schema=full_schema,
@@ -413,6 +453,8 @@ class UdfServer(pa.flight.FlightServerBase):
     def do_action(self, context, action):
         if action.type == "protocol_version":
             yield b"\x02"
+        elif action.type == "capabilities":
+            yield b"arrowudf.function_kinds=scalar,table\n"
         else:
             raise NotImplementedError
 
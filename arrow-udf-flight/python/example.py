@@ -32,7 +32,12 @@ def sleep(s: int) -> int:
     return 0
 
 
-@udf(input_types=["INT", "INT"], result_type="INT")
+@udf(
+    input_types=["INT", "INT"],
+    result_type="INT",
+    volatility="immutable",
+    parallel="safe",
+)
 def gcd(x: int, y: int) -> int:
     while y != 0:
         (x, y) = (y, x % y)
@@ -0,0 +1,104 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dispatch function calls across multiple UDF services.
+
+use arrow_array::RecordBatch;
+use arrow_udf_core::{concat_outputs, partition_rows};
+use futures_util::future::try_join_all;
+
+use crate::{Client, Error, Result};
+
+/// A dispatcher that shards function calls across a pool of UDF services.
+///
+/// A call splits the input into contiguous partitions, one for each client,
+/// sends them concurrently, and reassembles the outputs in the order of input rows.
+///
+/// # Example
+///
+/// ```ignore
+/// let clients = vec![
+///     Client::connect("http://worker-0:8815").await?,
+///     Client::connect("http://worker-1:8815").await?,
+/// ];
+/// let dispatcher = Dispatcher::new(clients)?.with_min_partition_rows(1024);
+/// let output = dispatcher.call("gcd", &input).await?;
+/// ```
+#[derive(Debug)]
+pub struct Dispatcher {
+    clients: Vec<Client>,
+    min_partition_rows: usize,
+}
+
+impl Dispatcher {
+    /// Create a dispatcher over clients of services with the same functions.
+    pub fn new(clients: Vec<Client>) -> Result<Self> {
+        if clients.is_empty() {
+            return Err(Error::Service(
+                "dispatcher must have at least one client".into(),
+            ));
+        }
+        Ok(Self {
+            clients,
+            min_partition_rows: 1,
+        })
+    }
+
+    /// Set the minimum number of rows in each partition. Defaults to 1.
+    ///
+    /// Smaller inputs are sent to fewer services, to avoid the overhead of tiny requests.
+    pub fn with_min_partition_rows(mut self, rows: usize) -> Self {
+        self.min_partition_rows = rows.max(1);
+        self
+    }
+
+    /// Returns the clients of the dispatcher.
+    pub fn clients(&self) -> &[Client] {
+        &self.clients
+    }
+
+    /// Call a function, evaluating partitions of the input concurrently.
+    #[tracing::instrument(name = "dispatch", level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    pub async fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let partitions = partition_rows(
+            input.num_rows(),
+            self.clients.len(),
+            self.min_partition_rows,
+        );
+        if partitions.len() == 1 {
+            return self.clients[0].call(name, input).await;
+        }
+        let outputs = try_join_all(partitions.iter().zip(&self.clients).map(
+            |(&(offset, len), client)| async move {
+                (client.call(name, &input.slice(offset, len)).await)
+                    .map_err(|e| shift_rows(e, offset, len))
+            },
+        ))
+        .await?;
+        concat_outputs(outputs).map_err(|e| Error::Decode(e.to_string()))
+    }
+}
+
+/// Locate the error of a partition in the whole input.
+///
+/// Services report errors of a call without the failed row,
+/// so the message is prefixed with the range of rows sent to the service.
+fn shift_rows(e: Error, offset: usize, len: usize) -> Error {
+    match e {
+        Error::Service(message) => {
+            Error::Service(format!("rows {offset}..{}: {message}", offset + len))
+        }
+        e => e,
+    }
+}
@@ -38,4 +38,18 @@ pub enum Error {
 
     #[error("Flight service error: {0}")]
     Service(String),
+
+    #[error("cancelled")]
+    Cancelled,
+}
+
+/// Convert to the error of runtimes, e.g. in [`AsyncTableFunction`](arrow_udf_core::AsyncTableFunction).
+impl From<Error> for arrow_udf_core::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Cancelled => Self::Cancelled,
+            Error::Service(message) => Self::execution(message),
+            e => Self::Other(e.into()),
+        }
+    }
 }
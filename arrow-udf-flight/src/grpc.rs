@@ -0,0 +1,208 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Messages of the plain gRPC protocol defined in `proto/udf.proto`.
+//!
+//! Schemas and record batches are encoded in the Arrow IPC streaming format.
+
+use arrow_array::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::Schema;
+use futures_util::{Stream, StreamExt};
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+
+use crate::{Error, Function, Result};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListFunctionsRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListFunctionsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub functions: Vec<FunctionInfo>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetFunctionRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FunctionInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// The schema of function arguments, as an IPC stream without batches.
+    #[prost(bytes = "vec", tag = "2")]
+    pub args: Vec<u8>,
+    /// The schema of function return values, as an IPC stream without batches.
+    #[prost(bytes = "vec", tag = "3")]
+    pub returns: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CallRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// The input batch as an IPC stream.
+    #[prost(bytes = "vec", tag = "2")]
+    pub input: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CallResponse {
+    /// The output batches as an IPC stream.
+    #[prost(bytes = "vec", tag = "1")]
+    pub output: Vec<u8>,
+}
+
+/// The full name of the gRPC service.
+pub const SERVICE_NAME: &str = "arrowudf.v1.UdfService";
+
+/// Client of `arrowudf.v1.UdfService`.
+#[derive(Debug, Clone)]
+pub(crate) struct GrpcClient {
+    inner: tonic::client::Grpc<Channel>,
+}
+
+impl GrpcClient {
+    /// Create a client on the channel.
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            inner: tonic::client::Grpc::new(channel),
+        }
+    }
+
+    pub async fn list(&self) -> Result<Vec<Function>> {
+        let response: ListFunctionsResponse =
+            self.unary("ListFunctions", ListFunctionsRequest {}).await?;
+        response
+            .functions
+            .into_iter()
+            .map(Function::from_grpc)
+            .collect()
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Function> {
+        let request = GetFunctionRequest { name: name.into() };
+        Function::from_grpc(self.unary("GetFunction", request).await?)
+    }
+
+    pub async fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let request = CallRequest {
+            name: name.into(),
+            input: encode_batch(input)?,
+        };
+        let response: CallResponse = self.unary("Call", request).await?;
+        let (schema, batches) = decode_batches(&response.output)?;
+        Ok(arrow_select::concat::concat_batches(&schema, &batches)?)
+    }
+
+    pub async fn call_table_function(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+    ) -> Result<impl Stream<Item = Result<RecordBatch>> + Send + 'static> {
+        let request = CallRequest {
+            name: name.into(),
+            input: encode_batch(input)?,
+        };
+        let mut inner = self.inner.clone();
+        inner.ready().await.map_err(not_ready)?;
+        let response = inner
+            .server_streaming(
+                tonic::Request::new(request),
+                path("CallTable"),
+                ProstCodec::<CallRequest, CallResponse>::default(),
+            )
+            .await?;
+        Ok(response.into_inner().flat_map(|response| {
+            let batches = match response {
+                Ok(response) => match decode_batches(&response.output) {
+                    Ok((_, batches)) => batches.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                },
+                Err(e) => vec![Err(e.into())],
+            };
+            futures_util::stream::iter(batches)
+        }))
+    }
+
+    async fn unary<Req, Resp>(&self, method: &'static str, request: Req) -> Result<Resp>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut inner = self.inner.clone();
+        inner.ready().await.map_err(not_ready)?;
+        let response = inner
+            .unary(
+                tonic::Request::new(request),
+                path(method),
+                ProstCodec::<Req, Resp>::default(),
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+}
+
+impl Function {
+    fn from_grpc(info: FunctionInfo) -> Result<Self> {
+        let returns = decode_batches(&info.returns)?.0.as_ref().clone();
+        Ok(Self {
+            name: info.name,
+            args: decode_batches(&info.args)?.0.as_ref().clone(),
+            properties: crate::decode_properties(returns.metadata())?,
+            returns,
+        })
+    }
+}
+
+fn path(method: &str) -> PathAndQuery {
+    PathAndQuery::try_from(format!("/{SERVICE_NAME}/{method}")).unwrap()
+}
+
+fn not_ready(e: tonic::transport::Error) -> Error {
+    Error::Tonic(tonic::Status::unavailable(format!(
+        "service was not ready: {e}"
+    )))
+}
+
+/// Encode a schema as an IPC stream without batches.
+pub fn encode_schema(schema: &Schema) -> Result<Vec<u8>> {
+    let mut buf = vec![];
+    StreamWriter::try_new(&mut buf, schema)?.finish()?;
+    Ok(buf)
+}
+
+/// Encode a record batch as an IPC stream.
+pub fn encode_batch(batch: &RecordBatch) -> Result<Vec<u8>> {
+    let mut buf = vec![];
+    let mut writer = StreamWriter::try_new(&mut buf, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    drop(writer);
+    Ok(buf)
+}
+
+/// Decode the schema and all batches from an IPC stream.
+pub fn decode_batches(bytes: &[u8]) -> Result<(std::sync::Arc<Schema>, Vec<RecordBatch>)> {
+    let reader = StreamReader::try_new(bytes, None)?;
+    let schema = reader.schema();
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    Ok((schema, batches))
+}
@@ -13,25 +13,48 @@
 // limitations under the License.
 
 #![doc = include_str!("../README.md")]
+// `tonic::Status` is large, but boxing it would break the public `Error` type.
+#![allow(clippy::result_large_err)]
 
+mod dispatch;
 mod error;
+pub mod grpc;
 
+pub use dispatch::Dispatcher;
 pub use error::{Error, Result};
 
+use std::collections::HashMap;
+
 use arrow_array::RecordBatch;
 use arrow_flight::decode::FlightRecordBatchStream;
 use arrow_flight::encode::FlightDataEncoderBuilder;
 use arrow_flight::flight_service_client::FlightServiceClient;
 use arrow_flight::{Action, Criteria, FlightData, FlightDescriptor};
 use arrow_schema::Schema;
-use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use arrow_udf_core::{AsyncTableFunction, CancelToken, Capabilities, RecordBatchStream};
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
+use futures_util::future::{self, BoxFuture, Either};
+use futures_util::{stream, FutureExt, Stream, StreamExt, TryStreamExt};
+use grpc::GrpcClient;
 use tonic::transport::Channel;
 
 /// Client for a remote Arrow UDF service.
 #[derive(Debug)]
 pub struct Client {
-    client: FlightServiceClient<Channel>,
+    backend: Backend,
     protocol_version: u8,
+    capabilities: Option<Capabilities>,
+}
+
+/// The protocol to talk to the service.
+#[derive(Debug)]
+enum Backend {
+    /// Arrow Flight RPC.
+    Flight(FlightServiceClient<Channel>),
+    /// Plain gRPC with Arrow IPC payloads, defined in `proto/udf.proto`.
+    Grpc(GrpcClient),
 }
 
 impl Client {
@@ -43,6 +66,27 @@ impl Client {
         Self::new(FlightServiceClient::new(conn)).await
     }
 
+    /// Connect to a UDF service speaking the plain gRPC protocol instead of Arrow Flight.
+    ///
+    /// This is useful when the infrastructure in between can not handle Arrow Flight.
+    /// See `proto/udf.proto` for the service definition.
+    pub async fn connect_grpc(addr: impl Into<String>) -> Result<Self> {
+        let conn = tonic::transport::Endpoint::new(addr.into())?
+            .connect()
+            .await?;
+        Ok(Self::new_grpc(conn))
+    }
+
+    /// Create a new client speaking the plain gRPC protocol on the channel.
+    pub fn new_grpc(channel: Channel) -> Self {
+        Self {
+            backend: Backend::Grpc(GrpcClient::new(channel)),
+            // the gRPC protocol has a single version
+            protocol_version: 1,
+            capabilities: None,
+        }
+    }
+
     /// Create a new client.
     pub async fn new(mut client: FlightServiceClient<Channel>) -> Result<Self> {
         // get protocol version in server
@@ -60,9 +104,24 @@ impl Client {
                 .ok_or_else(|| Error::Decode("invalid protocol version".into()))?,
         };
 
+        // get capabilities in server
+        let capabilities = match client.do_action(Action::new("capabilities", "")).await {
+            // if the action is not implemented, capabilities are unknown
+            Err(_) => None,
+            Ok(response) => {
+                let result = response
+                    .into_inner()
+                    .next()
+                    .await
+                    .ok_or_else(|| Error::Decode("no capabilities".into()))??;
+                Some(decode_capabilities(&result.body)?)
+            }
+        };
+
         Ok(Self {
-            client,
+            backend: Backend::Flight(client),
             protocol_version,
+            capabilities,
         })
     }
 
@@ -71,20 +130,35 @@ impl Client {
         self.protocol_version
     }
 
+    /// Get the capabilities reported by the service in the handshake,
+    /// or `None` if the service doesn't report them.
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Returns true if the client speaks the plain gRPC protocol.
+    pub fn is_grpc(&self) -> bool {
+        matches!(self.backend, Backend::Grpc(_))
+    }
+
     /// Get function schema.
     pub async fn get(&self, name: &str) -> Result<Function> {
+        let client = match &self.backend {
+            Backend::Flight(client) => client,
+            Backend::Grpc(client) => return client.get(name).await,
+        };
         let descriptor = FlightDescriptor::new_path(vec![name.into()]);
-        let response = self.client.clone().get_flight_info(descriptor).await?;
+        let response = client.clone().get_flight_info(descriptor).await?;
         Function::from_flight_info(response.into_inner())
     }
 
     /// List all available functions.
     pub async fn list(&self) -> Result<Vec<Function>> {
-        let response = self
-            .client
-            .clone()
-            .list_flights(Criteria::default())
-            .await?;
+        let client = match &self.backend {
+            Backend::Flight(client) => client,
+            Backend::Grpc(client) => return client.list().await,
+        };
+        let response = client.clone().list_flights(Criteria::default()).await?;
         let mut functions = vec![];
         let mut response = response.into_inner();
         while let Some(flight_info) = response.next().await {
@@ -99,9 +173,36 @@ impl Client {
         self.call_internal(name, input).await
     }
 
+    /// Call a function, cancelling the request once the token is cancelled.
+    ///
+    /// The request is dropped on cancellation, which cancels the RPC on the service.
+    /// Returns [`Error::Cancelled`] if the call is cancelled.
+    pub async fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let _guard = token.on_cancel(move || _ = tx.send(()));
+        let call = std::pin::pin!(self.call_internal(name, input));
+        match future::select(call, rx).await {
+            Either::Left((output, _)) => output,
+            Either::Right(_) => Err(Error::Cancelled),
+        }
+    }
+
+    #[tracing::instrument(name = "call", level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
     async fn call_internal(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let client = match &self.backend {
+            Backend::Flight(client) => client,
+            Backend::Grpc(client) => return client.call(name, input).await,
+        };
         let input = input.clone();
-        let mut output_stream = self.call_stream_internal(name, input).await?;
+        let mut output_stream = Self::call_stream_internal(client, name, input).await?;
         let mut batches = vec![];
         while let Some(batch) = output_stream.next().await {
             batches.push(batch?);
@@ -115,20 +216,27 @@ impl Client {
     }
 
     /// Call a table function.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
     pub async fn call_table_function(
         &self,
         name: &str,
         input: &RecordBatch,
     ) -> Result<impl Stream<Item = Result<RecordBatch>> + Send + 'static> {
+        let client = match &self.backend {
+            Backend::Flight(client) => client,
+            Backend::Grpc(client) => {
+                return Ok(client.call_table_function(name, input).await?.boxed())
+            }
+        };
         let input = input.clone();
-        Ok(self
-            .call_stream_internal(name, input)
+        Ok(Self::call_stream_internal(client, name, input)
             .await?
-            .map_err(|e| e.into()))
+            .map_err(|e| e.into())
+            .boxed())
     }
 
     async fn call_stream_internal(
-        &self,
+        client: &FlightServiceClient<Channel>,
         name: &str,
         input: RecordBatch,
     ) -> Result<FlightRecordBatchStream> {
@@ -141,7 +249,7 @@ impl Client {
             });
 
         // call `do_exchange` on Flight server
-        let response = self.client.clone().do_exchange(flight_data_stream).await?;
+        let response = client.clone().do_exchange(flight_data_stream).await?;
 
         // decode response
         let stream = response.into_inner();
@@ -152,6 +260,26 @@ impl Client {
     }
 }
 
+/// Stream the outputs of remote table functions.
+///
+/// The service decides the batch size, so `chunk_size` is ignored.
+/// The stream is pulled from the connection, whose flow control bounds the batches
+/// buffered ahead of the consumer and pauses the service until they are polled.
+impl AsyncTableFunction for Client {
+    fn call_table_function_stream<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        _chunk_size: usize,
+    ) -> BoxFuture<'a, arrow_udf_core::Result<RecordBatchStream>> {
+        async move {
+            let stream = self.call_table_function(name, input).await?;
+            Ok(stream.map_err(arrow_udf_core::Error::from).boxed())
+        }
+        .boxed()
+    }
+}
+
 /// Function signature.
 #[derive(Debug)]
 pub struct Function {
@@ -161,6 +289,10 @@ pub struct Function {
     pub args: Schema,
     /// The schema of function return values.
     pub returns: Schema,
+    /// The properties of the function, carried in the schema metadata.
+    ///
+    /// Services that don't declare them get the default properties.
+    pub properties: FunctionProperties,
 }
 
 impl Function {
@@ -181,11 +313,36 @@ impl Function {
         if input_num > schema.fields.len() {
             return Err(Error::Decode(format!("invalid input_number: {input_num}")));
         }
+        let properties = decode_properties(schema.metadata())?;
         let (input_fields, return_fields) = schema.fields.split_at(input_num);
         Ok(Self {
             name,
             args: Schema::new(input_fields),
             returns: Schema::new(return_fields),
+            properties,
         })
     }
 }
+
+/// Decode the properties of a function from the schema metadata, which may be empty.
+fn decode_properties(metadata: &HashMap<String, String>) -> Result<FunctionProperties> {
+    FunctionProperties::from_metadata(metadata)
+        .map_err(|e| Error::Decode(format!("failed to decode function properties: {e}")))
+}
+
+/// Decode the body of the `capabilities` action.
+///
+/// The body is UTF-8 text of `key=value` lines, in the format of [`Capabilities::to_metadata`].
+fn decode_capabilities(body: &[u8]) -> Result<Capabilities> {
+    let text = std::str::from_utf8(body)
+        .map_err(|e| Error::Decode(format!("invalid capabilities: {e}")))?;
+    let metadata = (text.lines().filter(|line| !line.is_empty()))
+        .map(|line| {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| Error::Decode(format!("invalid capabilities: {line}")))?;
+            Ok((key.to_string(), value.to_string()))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    Capabilities::from_metadata(&metadata).map_err(|e| Error::Decode(e.to_string()))
+}
@@ -12,12 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::{Duration, Instant};
 use std::{collections::HashSet, sync::Arc};
 
 use arrow_array::{Int32Array, RecordBatch, StringArray};
 use arrow_cast::pretty::pretty_format_batches;
 use arrow_schema::{DataType, Field, Schema};
-use arrow_udf_flight::Client;
+use arrow_udf_core::{CancelToken, FunctionKind};
+use arrow_udf_flight::{Client, Dispatcher, Error, FunctionProperties, ParallelSafety, Volatility};
 use expect_test::{expect, Expect};
 use futures_util::StreamExt;
 
@@ -125,12 +127,47 @@ async fn test_range() {
     );
 }
 
+#[tokio::test]
+async fn test_cancel() {
+    let client = Client::connect(SERVER_ADDR).await.unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![10]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let token = CancelToken::new();
+    tokio::spawn({
+        let token = token.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            token.cancel();
+        }
+    });
+    let start = Instant::now();
+    let err = client
+        .call_cancellable("sleep", &input, &token)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Cancelled), "{err}");
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
 #[tokio::test]
 async fn test_get_protocol_version() {
     let client = Client::connect(SERVER_ADDR).await.unwrap();
     assert_eq!(client.protocol_version(), 2);
 }
 
+#[tokio::test]
+async fn test_capabilities() {
+    let client = Client::connect(SERVER_ADDR).await.unwrap();
+    let capabilities = client.capabilities().unwrap();
+    assert!(capabilities.supports(FunctionKind::Scalar));
+    assert!(capabilities.supports(FunctionKind::Table));
+    assert!(!capabilities.supports(FunctionKind::Aggregate));
+    assert_eq!(capabilities.data_types, None);
+}
+
 #[tokio::test]
 async fn test_get() {
     let client = Client::connect(SERVER_ADDR).await.unwrap();
@@ -141,6 +178,12 @@ async fn test_get() {
     assert_eq!(function.args.field(0).data_type(), &DataType::Int32);
     assert_eq!(function.args.field(1).data_type(), &DataType::Int32);
     assert_eq!(function.returns.field(0).data_type(), &DataType::Int32);
+    assert_eq!(
+        function.properties,
+        FunctionProperties::new()
+            .with_volatility(Volatility::Immutable)
+            .with_parallel_safety(ParallelSafety::Safe)
+    );
 }
 
 #[tokio::test]
@@ -161,6 +204,37 @@ async fn test_list_function() {
 
 /// Compare the actual output with the expected output.
 #[track_caller]
+#[tokio::test]
+async fn test_dispatch() {
+    let clients = vec![
+        Client::connect(SERVER_ADDR).await.unwrap(),
+        Client::connect(SERVER_ADDR).await.unwrap(),
+    ];
+    let dispatcher = Dispatcher::new(clients).unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from_iter(vec![1, 6, 10]);
+    let arg1 = Int32Array::from_iter(vec![3, 4, 15]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let output = dispatcher.call("gcd", &input).await.unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 1   |
+        | 2   |
+        | 5   |
+        +-----+"#]],
+    );
+}
+
 fn check(actual: &[RecordBatch], expect: Expect) {
     expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
 }
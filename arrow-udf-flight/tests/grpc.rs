@@ -0,0 +1,296 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Test the plain gRPC protocol against an in-process server.
+
+// `tonic::Status` is large, but it is the error type of gRPC services.
+#![allow(clippy::result_large_err)]
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::AsyncTableFunction;
+use arrow_udf_flight::grpc::*;
+use arrow_udf_flight::{CallMode, Client, FunctionProperties, Volatility};
+use expect_test::{expect, Expect};
+use futures_util::StreamExt;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, BoxFuture, BoxStream, Service};
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::{Request, Response, Status};
+
+#[tokio::test]
+async fn test_grpc() {
+    let client = Client::connect_grpc(start_server().await).await.unwrap();
+    assert!(client.is_grpc());
+
+    let functions = client.list().await.unwrap();
+    assert_eq!(functions.len(), 2);
+    let function = client.get("gcd").await.unwrap();
+    assert_eq!(function.args.fields().len(), 2);
+    assert_eq!(function.returns.field(0).name(), "gcd");
+    assert!(function.properties.is_strict());
+    assert!(function.properties.is_deterministic());
+    assert_eq!(
+        client.get("range").await.unwrap().properties,
+        FunctionProperties::default()
+    );
+    client.get("unknown").await.unwrap_err();
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![1, 6, 10])),
+            Arc::new(Int32Array::from(vec![3, 4, 15])),
+        ],
+    )
+    .unwrap();
+    let output = client.call("gcd", &input).await.unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 1   |
+        | 2   |
+        | 5   |
+        +-----+"#]],
+    );
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![1, 2]))],
+    )
+    .unwrap();
+    let mut outputs = client.call_table_function("range", &input).await.unwrap();
+    let mut batches = vec![];
+    while let Some(batch) = outputs.next().await {
+        batches.push(batch.unwrap());
+    }
+    check(
+        &batches,
+        expect![[r#"
+        +-----+-------+
+        | row | range |
+        +-----+-------+
+        | 0   | 0     |
+        | 1   | 0     |
+        | 1   | 1     |
+        +-----+-------+"#]],
+    );
+
+    // the same outputs through the trait shared with local runtimes
+    let runtime: &dyn AsyncTableFunction = &client;
+    let outputs = runtime
+        .call_table_function_stream("range", &input, 1024)
+        .await
+        .unwrap();
+    let batches: Vec<_> = outputs.map(|batch| batch.unwrap()).collect().await;
+    check(
+        &batches,
+        expect![[r#"
+        +-----+-------+
+        | row | range |
+        +-----+-------+
+        | 0   | 0     |
+        | 1   | 0     |
+        | 1   | 1     |
+        +-----+-------+"#]],
+    );
+}
+
+/// Start a server with `gcd` and `range` functions, returning its address.
+async fn start_server() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let incoming =
+        tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(UdfServer)
+            .serve_with_incoming(incoming),
+    );
+    format!("http://{addr}")
+}
+
+#[derive(Clone)]
+struct UdfServer;
+
+impl NamedService for UdfServer {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> Service<http::Request<B>> for UdfServer
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<tonic::codegen::StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let path = req.uri().path().rsplit('/').next().unwrap().to_string();
+        Box::pin(async move {
+            Ok(match path.as_str() {
+                "ListFunctions" => {
+                    let codec =
+                        ProstCodec::<ListFunctionsResponse, ListFunctionsRequest>::default();
+                    Grpc::new(codec).unary(Handler, req).await
+                }
+                "GetFunction" => {
+                    let codec = ProstCodec::<FunctionInfo, GetFunctionRequest>::default();
+                    Grpc::new(codec).unary(Handler, req).await
+                }
+                "Call" => {
+                    let codec = ProstCodec::<CallResponse, CallRequest>::default();
+                    Grpc::new(codec).unary(Handler, req).await
+                }
+                "CallTable" => {
+                    let codec = ProstCodec::<CallResponse, CallRequest>::default();
+                    Grpc::new(codec).server_streaming(Handler, req).await
+                }
+                _ => Status::unimplemented(path).into_http(),
+            })
+        })
+    }
+}
+
+struct Handler;
+
+fn functions() -> Vec<FunctionInfo> {
+    let int = |name| Field::new(name, DataType::Int32, true);
+    let info = |name: &str, args: Vec<Field>, returns: Vec<Field>, metadata| FunctionInfo {
+        name: name.into(),
+        args: encode_schema(&Schema::new(args)).unwrap(),
+        returns: encode_schema(&Schema::new(returns).with_metadata(metadata)).unwrap(),
+    };
+    let gcd = FunctionProperties::new()
+        .with_mode(CallMode::ReturnNullOnNullInput)
+        .with_volatility(Volatility::Immutable);
+    vec![
+        info(
+            "gcd",
+            vec![int("a"), int("b")],
+            vec![int("gcd")],
+            gcd.to_metadata(),
+        ),
+        info(
+            "range",
+            vec![int("n")],
+            vec![int("row"), int("range")],
+            Default::default(),
+        ),
+    ]
+}
+
+impl UnaryService<ListFunctionsRequest> for Handler {
+    type Response = ListFunctionsResponse;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, _: Request<ListFunctionsRequest>) -> Self::Future {
+        let functions = functions();
+        Box::pin(async move { Ok(Response::new(ListFunctionsResponse { functions })) })
+    }
+}
+
+impl UnaryService<GetFunctionRequest> for Handler {
+    type Response = FunctionInfo;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<GetFunctionRequest>) -> Self::Future {
+        let name = request.into_inner().name;
+        let function = functions().into_iter().find(|f| f.name == name);
+        Box::pin(async move {
+            function
+                .map(Response::new)
+                .ok_or_else(|| Status::not_found(name))
+        })
+    }
+}
+
+impl UnaryService<CallRequest> for Handler {
+    type Response = CallResponse;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<CallRequest>) -> Self::Future {
+        let (_, batches) = decode_batches(&request.into_inner().input).unwrap();
+        let a = batches[0].column(0).as_any().downcast_ref::<Int32Array>();
+        let b = batches[0].column(1).as_any().downcast_ref::<Int32Array>();
+        let gcd: Int32Array = (a.unwrap().iter().zip(b.unwrap().iter()))
+            .map(|(a, b)| {
+                let (mut a, mut b) = (a?, b?);
+                while b != 0 {
+                    (a, b) = (b, a % b);
+                }
+                Some(a)
+            })
+            .collect();
+        let schema = Schema::new(vec![Field::new("gcd", DataType::Int32, true)]);
+        let output = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(gcd)]).unwrap();
+        let output = encode_batch(&output).unwrap();
+        Box::pin(async move { Ok(Response::new(CallResponse { output })) })
+    }
+}
+
+impl ServerStreamingService<CallRequest> for Handler {
+    type Response = CallResponse;
+    type ResponseStream = BoxStream<CallResponse>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<CallRequest>) -> Self::Future {
+        let (_, batches) = decode_batches(&request.into_inner().input).unwrap();
+        let n = batches[0].column(0).as_any().downcast_ref::<Int32Array>();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("row", DataType::Int32, true),
+            Field::new("range", DataType::Int32, true),
+        ]));
+        // one response per input row
+        let responses: Vec<_> = (n.unwrap().iter().enumerate())
+            .map(|(row, n)| {
+                let n = n.unwrap();
+                let rows = Int32Array::from(vec![row as i32; n as usize]);
+                let values = Int32Array::from_iter_values(0..n);
+                let batch =
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(rows), Arc::new(values)])
+                        .unwrap();
+                Ok(CallResponse {
+                    output: encode_batch(&batch).unwrap(),
+                })
+            })
+            .collect();
+        Box::pin(async move {
+            Ok(Response::new(
+                futures_util::stream::iter(responses).boxed() as Self::ResponseStream
+            ))
+        })
+    }
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
@@ -555,11 +555,11 @@ impl Converter {
                     .iter()
                     .map(|v| !v.is_null() && !v.is_undefined())
                     .collect();
-                Ok(Arc::new(StructArray::new(
+                Ok(Arc::new(StructArray::try_new(
                     fields.clone(),
                     arrays,
                     Some(nulls),
-                )))
+                )?))
             }
             other => Err(anyhow::anyhow!("Unimplemented datatype {}", other)),
         }
@@ -16,19 +16,28 @@
 
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::{atomic::Ordering, Arc};
+use std::sync::{atomic::Ordering, Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, bail, Context as _, Result};
+use anyhow::{anyhow, Context as _};
 use arrow_array::{builder::Int32Builder, Array, ArrayRef, BooleanArray, RecordBatch};
 use arrow_schema::{DataType, Field, FieldRef, Schema, SchemaRef};
+use arrow_udf_core::{
+    Arguments, BatchMode, CancelToken, Capabilities, Error, FunctionInfo, FunctionKind, LogLevel,
+    NestedNullPolicy, ResourceLimit, Result, UdfLogSink,
+};
 pub use rquickjs::runtime::MemoryUsage;
 use rquickjs::{
-    context::intrinsic::All, function::Args, module::Evaluated, Context, Ctx, FromJs, Module,
-    Object, Persistent, Value,
+    context::intrinsic::All,
+    function::{Args, Rest},
+    module::Evaluated,
+    Coerced, Context, Ctx, FromJs, Module, Object, Persistent, Value,
 };
 
 pub use self::into_field::IntoField;
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
 
 mod into_field;
 mod jsarrow;
@@ -68,6 +77,19 @@ pub struct Runtime {
     timeout: Option<Duration>,
     /// Deadline of the current function call.
     deadline: Arc<atomic_time::AtomicOptionInstant>,
+    /// Cancel token of the current function call.
+    cancel: Arc<Mutex<Option<CancelToken>>>,
+    /// How nulls inside nested arguments are handled.
+    nested_nulls: NestedNullPolicy,
+    /// The log sink of `console`.
+    logger: Arc<Logger>,
+}
+
+/// The log sink and the function being called, shared with the `console` object.
+#[derive(Default)]
+struct Logger {
+    sink: Mutex<Option<Arc<dyn UdfLogSink>>>,
+    function: Mutex<String>,
 }
 
 impl Debug for Runtime {
@@ -106,20 +128,154 @@ type JsFunction = Persistent<rquickjs::Function<'static>>;
 unsafe impl Send for Runtime {}
 unsafe impl Sync for Runtime {}
 
-/// Whether the function will be called when some of its arguments are null.
-#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub enum CallMode {
-    /// The function will be called normally when some of its arguments are null.
-    /// It is then the function author's responsibility to check for null values if necessary and respond appropriately.
-    #[default]
-    CalledOnNullInput,
-
-    /// The function always returns null whenever any of its arguments are null.
-    /// If this parameter is specified, the function is not executed when there are null arguments;
-    /// instead a null result is assumed automatically.
-    ReturnNullOnNullInput,
+impl arrow_udf_core::UdfRuntime for Runtime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        self.add_function(name, return_field, mode, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        self.call(name, input)
+    }
+
+    fn call_broadcast(&self, name: &str, args: &Arguments) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_broadcast(name, args)
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_cancellable(name, input, token)
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_selected(name, input, selection)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        Ok(Box::new(self.call_table_function(name, input, chunk_size)?))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.aggregates.keys().cloned().collect()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        let functions = self.functions.iter().map(|(name, function)| {
+            FunctionInfo::new(name)
+                .with_return_field(function.return_field.clone())
+                .with_mode(function.mode)
+        });
+        let aggregates = self.aggregates.iter().map(|(name, aggregate)| {
+            FunctionInfo::new(name)
+                .with_kind(FunctionKind::Aggregate)
+                .with_return_field(aggregate.output_field.clone())
+                .with_mode(aggregate.mode)
+        });
+        functions.chain(aggregates).collect()
+    }
+
+    fn create_state(&self, name: &str) -> arrow_udf_core::Result<ArrayRef> {
+        self.create_state(name)
+    }
+
+    fn accumulate(
+        &self,
+        name: &str,
+        state: &dyn Array,
+        input: &RecordBatch,
+    ) -> arrow_udf_core::Result<ArrayRef> {
+        self.accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> arrow_udf_core::Result<ArrayRef> {
+        self.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> arrow_udf_core::Result<ArrayRef> {
+        self.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> arrow_udf_core::Result<FieldRef> {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| arrow_udf_core::Error::FunctionNotFound(name.to_string()))?;
+        Ok(aggregate.state_field.clone())
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        Some(self.memory_usage().malloc_size.max(0) as usize)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new()
+            .with_data_types(DATA_TYPES.iter().copied())
+            .with_function_kinds([
+                FunctionKind::Scalar,
+                FunctionKind::Table,
+                FunctionKind::Aggregate,
+            ])
+            .with_batch_modes([
+                BatchMode::Broadcast,
+                BatchMode::Selected,
+                BatchMode::Cancellable,
+            ])
+            .with_resource_limits([ResourceLimit::Memory, ResourceLimit::Timeout])
+    }
 }
 
+/// The names of data types that can be converted between Arrow and JavaScript.
+const DATA_TYPES: &[&str] = &[
+    "Binary",
+    "BinaryView",
+    "Boolean",
+    "Date32",
+    "Decimal128",
+    "Decimal256",
+    "Float32",
+    "Float64",
+    "Int8",
+    "Int16",
+    "Int32",
+    "Int64",
+    "LargeBinary",
+    "LargeList",
+    "LargeUtf8",
+    "List",
+    "Null",
+    "Struct",
+    "Timestamp",
+    "UInt8",
+    "UInt16",
+    "UInt32",
+    "UInt64",
+    "Utf8",
+    "Utf8View",
+];
+
 impl Runtime {
     /// Create a new `Runtime`.
     pub fn new() -> Result<Self> {
@@ -127,14 +283,59 @@ impl Runtime {
         let context = rquickjs::Context::custom::<All>(&runtime)
             .context("failed to create quickjs context")?;
 
+        let deadline: Arc<atomic_time::AtomicOptionInstant> = Default::default();
+        let cancel: Arc<Mutex<Option<CancelToken>>> = Default::default();
+        runtime.set_interrupt_handler(Some(Box::new({
+            let deadline = deadline.clone();
+            let cancel = cancel.clone();
+            move || {
+                if let Some(deadline) = deadline.load(Ordering::Relaxed) {
+                    if deadline <= Instant::now() {
+                        return true;
+                    }
+                }
+                let cancel = cancel.lock().unwrap();
+                cancel.as_ref().is_some_and(|token| token.is_cancelled())
+            }
+        })));
+
+        let logger: Arc<Logger> = Default::default();
+        context
+            .with(|ctx| {
+                let console = Object::new(ctx.clone())?;
+                for (method, level) in [
+                    ("log", LogLevel::Info),
+                    ("info", LogLevel::Info),
+                    ("debug", LogLevel::Debug),
+                    ("warn", LogLevel::Warn),
+                    ("error", LogLevel::Error),
+                ] {
+                    let logger = logger.clone();
+                    let log = move |args: Rest<Value<'_>>| {
+                        let Some(sink) = logger.sink.lock().unwrap().clone() else {
+                            return Ok(());
+                        };
+                        let message = format_log_message(args.0)?;
+                        sink.log(level, &logger.function.lock().unwrap(), &message);
+                        Ok(()) as rquickjs::Result<()>
+                    };
+                    console.set(method, rquickjs::Function::new(ctx.clone(), log)?)?;
+                }
+                ctx.globals().set("console", console)
+            })
+            .context("failed to create console")?;
+
         Ok(Self {
             functions: HashMap::new(),
             aggregates: HashMap::new(),
             runtime,
             context,
             timeout: None,
-            deadline: Default::default(),
+            deadline,
+            cancel,
             converter: jsarrow::Converter::new(),
+            nested_nulls: NestedNullPolicy::default(),
+            logger,
         })
     }
 
@@ -163,17 +364,41 @@ impl Runtime {
     /// ```
     pub fn set_timeout(&mut self, timeout: Option<Duration>) {
         self.timeout = timeout;
-        if timeout.is_some() {
-            let deadline = self.deadline.clone();
-            self.runtime.set_interrupt_handler(Some(Box::new(move || {
-                if let Some(deadline) = deadline.load(Ordering::Relaxed) {
-                    return deadline <= Instant::now();
-                }
-                false
-            })));
-        } else {
-            self.runtime.set_interrupt_handler(None);
-        }
+    }
+
+    /// Set how nulls inside nested arguments are handled for functions that return null on null input.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_js::Runtime;
+    /// # use arrow_udf_core::NestedNullPolicy;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_nested_null_policy(NestedNullPolicy::Propagate);
+    /// ```
+    pub fn set_nested_null_policy(&mut self, policy: NestedNullPolicy) {
+        self.nested_nulls = policy;
+    }
+
+    /// Set the sink of messages logged by `console.log`, `console.info`, `console.debug`,
+    /// `console.warn` and `console.error`.
+    ///
+    /// Arguments are joined by spaces, and objects are formatted as JSON.
+    /// Messages are dropped if no sink is set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_js::Runtime;
+    /// # use arrow_udf_core::LogLevel;
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_log_sink(Some(Arc::new(|level: LogLevel, function: &str, message: &str| {
+    ///     eprintln!("[{level}] {function}: {message}");
+    /// })));
+    /// ```
+    pub fn set_log_sink(&mut self, sink: Option<Arc<dyn UdfLogSink>>) {
+        *self.logger.sink.lock().unwrap() = sink;
     }
 
     /// Get memory usage of the internal quickjs runtime.
@@ -264,6 +489,7 @@ impl Runtime {
     /// - others: Same as [`add_function`].
     ///
     /// [`add_function`]: Runtime::add_function
+    #[tracing::instrument(name = "add_function", level = "debug", skip_all, fields(function = name))]
     pub fn add_function_with_handler(
         &mut self,
         name: &str,
@@ -272,7 +498,7 @@ impl Runtime {
         code: &str,
         handler: &str,
     ) -> Result<()> {
-        let function = self.context.with(|ctx| {
+        let function = self.with_context(name, |ctx| {
             let (module, _) = Module::declare(ctx.clone(), name, code)
                 .map_err(|e| check_exception(e, &ctx))
                 .context("failed to declare module")?
@@ -320,8 +546,8 @@ impl Runtime {
     /// optionally, the code can define:
     ///
     /// - `finish(state) -> value`: Get the result of the aggregate function.
-    ///     If not defined, the state is returned as the result.
-    ///     In this case, `output_type` must be the same as `state_type`.
+    ///   If not defined, the state is returned as the result.
+    ///   In this case, `output_type` must be the same as `state_type`.
     /// - `retract(state, *args) -> state`: Retract a value from the state, returning the updated state.
     /// - `merge(state, state) -> state`: Merge two states, returning the merged state.
     ///
@@ -356,6 +582,7 @@ impl Runtime {
     ///     )
     ///     .unwrap();
     /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name))]
     pub fn add_aggregate(
         &mut self,
         name: &str,
@@ -364,7 +591,7 @@ impl Runtime {
         mode: CallMode,
         code: &str,
     ) -> Result<()> {
-        let aggregate = self.context.with(|ctx| {
+        let aggregate = self.with_context(name, |ctx| {
             let (module, _) = Module::declare(ctx.clone(), name, code)
                 .map_err(|e| check_exception(e, &ctx))
                 .context("failed to declare module")?
@@ -383,7 +610,10 @@ impl Runtime {
             }) as Result<Aggregate>
         })?;
         if aggregate.finish.is_none() && aggregate.state_field != aggregate.output_field {
-            bail!("`output_type` must be the same as `state_type` when `finish` is not defined");
+            return Err(anyhow!(
+                "`output_type` must be the same as `state_type` when `finish` is not defined"
+            )
+            .into());
         }
         self.aggregates.insert(name.to_string(), aggregate);
         Ok(())
@@ -410,24 +640,82 @@ impl Runtime {
     /// assert_eq!(&**output.column(0), &Int32Array::from(vec![Some(5), None]));
     /// ```
     pub fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
-        let function = self.functions.get(name).context("function not found")?;
+        self.call_rows(name, &input.into(), None)
+    }
+
+    /// Call a scalar function with arguments, some of which may be scalars broadcast to all rows.
+    ///
+    /// Scalars are read for each row without building arrays of the batch length.
+    pub fn call_broadcast(&self, name: &str, args: &Arguments) -> Result<RecordBatch> {
+        self.call_rows(name, args, None)
+    }
+
+    /// Call a scalar function, interrupting it once the token is cancelled.
+    ///
+    /// Returns [`Error::Cancelled`] if the call is cancelled.
+    pub fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        token.check()?;
+        *self.cancel.lock().unwrap() = Some(token.clone());
+        let result = self.call_rows(name, &input.into(), None);
+        self.cancel.lock().unwrap().take();
+        match result {
+            Err(_) if token.is_cancelled() => Err(Error::Cancelled),
+            result => result,
+        }
+    }
+
+    /// Call a scalar function on the selected rows of the input.
+    ///
+    /// Rows that are not selected, including those where `selection` is null,
+    /// are neither converted nor evaluated, and their outputs are null.
+    pub fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        arrow_udf_core::check_selection(input, selection)?;
+        self.call_rows(name, &input.into(), Some(selection))
+    }
+
+    #[tracing::instrument(name = "call", level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    fn call_rows(
+        &self,
+        name: &str,
+        input: &Arguments,
+        selection: Option<&BooleanArray>,
+    ) -> Result<RecordBatch> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
         // convert each row to python objects and call the function
-        self.context.with(|ctx| {
+        let output = self.with_context(name, |ctx| {
             let js_function = function.function.clone().restore(&ctx)?;
             let mut results = Vec::with_capacity(input.num_rows());
             let mut row = Vec::with_capacity(input.num_columns());
             for i in 0..input.num_rows() {
+                if selection.is_some_and(|s| !s.is_valid(i) || !s.value(i)) {
+                    results.push(Value::new_null(ctx.clone()));
+                    continue;
+                }
                 row.clear();
-                for (column, field) in input.columns().iter().zip(input.schema().fields()) {
+                for (j, (column, field)) in input.columns().iter().zip(input.fields()).enumerate() {
                     let val = self
                         .converter
-                        .get_jsvalue(&ctx, field, column, i)
+                        .get_jsvalue(&ctx, field, column, input.index(j, i))
                         .context("failed to get jsvalue from arrow array")?;
 
                     row.push(val);
                 }
-                if function.mode == CallMode::ReturnNullOnNullInput
-                    && row.iter().any(|v| v.is_null())
+                if (function.mode)
+                    .skips_row(self.nested_nulls, input.row_values(i))
+                    .map_err(|e| e.with_row(i))?
                 {
                     results.push(Value::new_null(ctx.clone()));
                     continue;
@@ -436,17 +724,20 @@ impl Runtime {
                 args.push_args(row.drain(..))?;
                 let result = self
                     .call_user_fn(&ctx, &js_function, args)
-                    .context("failed to call function")?;
+                    .map_err(|e| with_row(e, i))?;
                 results.push(result);
             }
 
-            let array = self
-                .converter
-                .build_array(&function.return_field, &ctx, results)
+            let array = tracing::trace_span!("build_array", rows = results.len())
+                .in_scope(|| {
+                    self.converter
+                        .build_array(&function.return_field, &ctx, results)
+                })
                 .context("failed to build arrow array from return values")?;
             let schema = Schema::new(vec![function.return_field.clone()]);
-            Ok(RecordBatch::try_new(Arc::new(schema), vec![array])?)
-        })
+            Ok(RecordBatch::try_new(Arc::new(schema), vec![array])?) as anyhow::Result<_>
+        })?;
+        Ok(output)
     }
 
     /// Call a table function.
@@ -475,6 +766,7 @@ impl Runtime {
     /// | 2   | 2      |
     /// +-----+--------+"#.trim());
     /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
     pub fn call_table_function<'a>(
         &'a self,
         name: &'a str,
@@ -482,11 +774,15 @@ impl Runtime {
         chunk_size: usize,
     ) -> Result<RecordBatchIter<'a>> {
         assert!(chunk_size > 0);
-        let function = self.functions.get(name).context("function not found")?;
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
 
         // initial state
         Ok(RecordBatchIter {
             rt: self,
+            name,
             input,
             function,
             schema: Arc::new(Schema::new(vec![
@@ -509,8 +805,11 @@ impl Runtime {
     /// assert_eq!(&*state, &Int32Array::from(vec![0]));
     /// ```
     pub fn create_state(&self, name: &str) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
-        let state = self.context.with(|ctx| {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let state = self.with_context(name, |ctx| {
             let create_state = aggregate.create_state.clone().restore(&ctx)?;
             let state = self
                 .call_user_fn(&ctx, &create_state, Args::new(ctx.clone(), 0))
@@ -518,7 +817,7 @@ impl Runtime {
             let state = self
                 .converter
                 .build_array(&aggregate.state_field, &ctx, vec![state])?;
-            Ok(state) as Result<_>
+            Ok(state) as anyhow::Result<_>
         })?;
         Ok(state)
     }
@@ -537,15 +836,19 @@ impl Runtime {
     /// let state = runtime.accumulate("sum", &state, &input).unwrap();
     /// assert_eq!(&*state, &Int32Array::from(vec![9]));
     /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
     pub fn accumulate(
         &self,
         name: &str,
         state: &dyn Array,
         input: &RecordBatch,
     ) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
         // convert each row to python objects and call the accumulate function
-        let new_state = self.context.with(|ctx| {
+        let new_state = self.with_context(name, |ctx| {
             let accumulate = aggregate.accumulate.clone().restore(&ctx)?;
             let mut state = self
                 .converter
@@ -553,8 +856,12 @@ impl Runtime {
 
             let mut row = Vec::with_capacity(1 + input.num_columns());
             for i in 0..input.num_rows() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput
-                    && input.columns().iter().any(|column| column.is_null(i))
+                if (aggregate.mode)
+                    .skips_row(
+                        self.nested_nulls,
+                        input.columns().iter().map(|c| (c.as_ref(), i)),
+                    )
+                    .map_err(|e| e.with_row(i))?
                 {
                     continue;
                 }
@@ -573,7 +880,7 @@ impl Runtime {
             let output = self
                 .converter
                 .build_array(&aggregate.state_field, &ctx, vec![state])?;
-            Ok(output) as Result<_>
+            Ok(output) as anyhow::Result<_>
         })?;
         Ok(new_state)
     }
@@ -596,6 +903,7 @@ impl Runtime {
     /// let state = runtime.accumulate_or_retract("sum", &state, &ops, &input).unwrap();
     /// assert_eq!(&*state, &Int32Array::from(vec![3]));
     /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
     pub fn accumulate_or_retract(
         &self,
         name: &str,
@@ -603,9 +911,12 @@ impl Runtime {
         ops: &BooleanArray,
         input: &RecordBatch,
     ) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
         // convert each row to python objects and call the accumulate function
-        let new_state = self.context.with(|ctx| {
+        let new_state = self.with_context(name, |ctx| {
             let accumulate = aggregate.accumulate.clone().restore(&ctx)?;
             let retract = aggregate
                 .retract
@@ -619,8 +930,12 @@ impl Runtime {
 
             let mut row = Vec::with_capacity(1 + input.num_columns());
             for i in 0..input.num_rows() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput
-                    && input.columns().iter().any(|column| column.is_null(i))
+                if (aggregate.mode)
+                    .skips_row(
+                        self.nested_nulls,
+                        input.columns().iter().map(|c| (c.as_ref(), i)),
+                    )
+                    .map_err(|e| e.with_row(i))?
                 {
                     continue;
                 }
@@ -644,7 +959,7 @@ impl Runtime {
             let output = self
                 .converter
                 .build_array(&aggregate.state_field, &ctx, vec![state])?;
-            Ok(output) as Result<_>
+            Ok(output) as anyhow::Result<_>
         })?;
         Ok(new_state)
     }
@@ -660,8 +975,11 @@ impl Runtime {
     /// assert_eq!(&*state, &Int32Array::from(vec![9]));
     /// ```
     pub fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
-        let output = self.context.with(|ctx| {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let output = self.with_context(name, |ctx| {
             let merge = aggregate
                 .merge
                 .clone()
@@ -671,7 +989,7 @@ impl Runtime {
                 .converter
                 .get_jsvalue(&ctx, &aggregate.state_field, states, 0)?;
             for i in 1..states.len() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput && states.is_null(i) {
+                if aggregate.mode.skips_null_states() && states.is_null(i) {
                     continue;
                 }
                 let state2 = self
@@ -686,7 +1004,7 @@ impl Runtime {
             let output = self
                 .converter
                 .build_array(&aggregate.state_field, &ctx, vec![state])?;
-            Ok(output) as Result<_>
+            Ok(output) as anyhow::Result<_>
         })?;
         Ok(output)
     }
@@ -704,15 +1022,18 @@ impl Runtime {
     /// assert_eq!(&outputs, &states);
     /// ```
     pub fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
         let Some(finish) = &aggregate.finish else {
             return Ok(states.clone());
         };
-        let output = self.context.with(|ctx| {
+        let output = self.with_context(name, |ctx| {
             let finish = finish.clone().restore(&ctx)?;
             let mut results = Vec::with_capacity(states.len());
             for i in 0..states.len() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput && states.is_null(i) {
+                if aggregate.mode.skips_null_states() && states.is_null(i) {
                     results.push(Value::new_null(ctx.clone()));
                     continue;
                 }
@@ -729,11 +1050,23 @@ impl Runtime {
             let output = self
                 .converter
                 .build_array(&aggregate.output_field, &ctx, results)?;
-            Ok(output) as Result<_>
+            Ok(output) as anyhow::Result<_>
         })?;
         Ok(output)
     }
 
+    /// Enter the context to run the code of a function, whose name is attached to the logs.
+    fn with_context<F, R>(&self, function: &str, f: F) -> R
+    where
+        F: for<'js> FnOnce(Ctx<'js>) -> R,
+    {
+        let mut current = self.logger.function.lock().unwrap();
+        current.clear();
+        current.push_str(function);
+        drop(current);
+        self.context.with(f)
+    }
+
     /// Call a user function.
     ///
     /// If `timeout` is set, the function will be interrupted after the timeout.
@@ -742,7 +1075,7 @@ impl Runtime {
         ctx: &Ctx<'js>,
         f: &rquickjs::Function<'js>,
         args: Args<'js>,
-    ) -> Result<T> {
+    ) -> anyhow::Result<T> {
         let result = if let Some(timeout) = self.timeout {
             self.deadline
                 .store(Some(Instant::now() + timeout), Ordering::Relaxed);
@@ -759,6 +1092,7 @@ impl Runtime {
 /// An iterator over the result of a table function.
 pub struct RecordBatchIter<'a> {
     rt: &'a Runtime,
+    name: &'a str,
     input: &'a RecordBatch,
     function: &'a Function,
     schema: SchemaRef,
@@ -780,11 +1114,11 @@ impl RecordBatchIter<'_> {
         &self.schema
     }
 
-    fn next(&mut self) -> Result<Option<RecordBatch>> {
+    fn next(&mut self) -> anyhow::Result<Option<RecordBatch>> {
         if self.row == self.input.num_rows() {
             return Ok(None);
         }
-        self.rt.context.with(|ctx| {
+        self.rt.with_context(self.name, |ctx| {
             let js_function = self.function.function.clone().restore(&ctx)?;
             let mut indexes = Int32Builder::with_capacity(self.chunk_size);
             let mut results = Vec::with_capacity(self.input.num_rows());
@@ -814,8 +1148,12 @@ impl RecordBatchIter<'_> {
                             .context("failed to get jsvalue from arrow array")?;
                         row.push(val);
                     }
-                    if self.function.mode == CallMode::ReturnNullOnNullInput
-                        && row.iter().any(|v| v.is_null())
+                    if (self.function.mode)
+                        .skips_row(
+                            self.rt.nested_nulls,
+                            self.input.columns().iter().map(|c| (c.as_ref(), self.row)),
+                        )
+                        .map_err(|e| e.with_row(self.row))?
                     {
                         self.row += 1;
                         continue;
@@ -825,7 +1163,7 @@ impl RecordBatchIter<'_> {
                     let gen: Object = self
                         .rt
                         .call_user_fn(&ctx, &js_function, args)
-                        .context("failed to call function")?;
+                        .map_err(|e| with_row(e, self.row))?;
                     let next: rquickjs::Function =
                         gen.get("next").context("failed to get 'next' method")?;
                     let mut args = Args::new(ctx.clone(), 0);
@@ -837,7 +1175,7 @@ impl RecordBatchIter<'_> {
                 let object: Object = self
                     .rt
                     .call_user_fn(&ctx, next, args)
-                    .context("failed to call next")?;
+                    .map_err(|e| with_row(e, self.row))?;
                 let value: Value = object.get("value")?;
                 let done: bool = object.get("done")?;
                 if done {
@@ -869,7 +1207,44 @@ impl RecordBatchIter<'_> {
 impl Iterator for RecordBatchIter<'_> {
     type Item = Result<RecordBatch>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next().transpose()
+        self.next().map_err(Into::into).transpose()
+    }
+}
+
+/// Format the arguments of `console.log` into a message.
+fn format_log_message(args: Vec<Value<'_>>) -> rquickjs::Result<String> {
+    let mut message = String::new();
+    for (i, arg) in args.into_iter().enumerate() {
+        if i > 0 {
+            message.push(' ');
+        }
+        if let Some(s) = arg.as_string() {
+            message.push_str(&s.to_string()?);
+            continue;
+        }
+        if arg.is_object() && !arg.is_function() {
+            // objects that can not be stringified, e.g. cyclic ones, fall back to `toString`
+            let ctx = arg.ctx().clone();
+            match ctx.json_stringify(arg.clone()) {
+                Ok(Some(json)) => {
+                    message.push_str(&json.to_string()?);
+                    continue;
+                }
+                Ok(None) => {}
+                Err(_) => _ = ctx.catch(),
+            }
+        }
+        let Coerced(s): Coerced<String> = arg.get()?;
+        message.push_str(&s);
+    }
+    Ok(message)
+}
+
+/// Set the row index of an execution error.
+fn with_row(e: anyhow::Error, row: usize) -> anyhow::Error {
+    match e.downcast::<Error>() {
+        Ok(e) => e.with_row(row).into(),
+        Err(e) => e,
     }
 }
 
@@ -877,7 +1252,22 @@ impl Iterator for RecordBatchIter<'_> {
 fn check_exception(err: rquickjs::Error, ctx: &Ctx) -> anyhow::Error {
     match err {
         rquickjs::Error::Exception => {
-            anyhow!("exception generated by QuickJS: {:?}", ctx.catch())
+            let value = ctx.catch();
+            let Some(exception) = value.as_exception() else {
+                return Error::execution(format!("exception generated by QuickJS: {value:?}"))
+                    .into();
+            };
+            let message = exception.message().unwrap_or_default();
+            // raised by the interrupt handler on timeout, or by the allocator
+            if message == "interrupted" || message == "out of memory" {
+                return Error::ResourceExceeded(message).into();
+            }
+            Error::Execution {
+                row: None,
+                message,
+                traceback: exception.stack(),
+            }
+            .into()
         }
         e => e.into(),
     }
@@ -15,14 +15,15 @@
 use std::{sync::Arc, time::Duration};
 
 use arrow_array::{
-    types::*, ArrayRef, BinaryArray, Date32Array, Decimal128Array, Decimal256Array, Int32Array,
-    LargeBinaryArray, LargeStringArray, ListArray, RecordBatch, StringArray, StringViewArray,
-    StructArray, TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
-    TimestampSecondArray,
+    types::*, ArrayRef, BinaryArray, BooleanArray, Date32Array, Decimal128Array, Decimal256Array,
+    Int32Array, LargeBinaryArray, LargeStringArray, ListArray, RecordBatch, StringArray,
+    StringViewArray, StructArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+    TimestampNanosecondArray, TimestampSecondArray,
 };
 use arrow_buffer::i256;
 use arrow_cast::pretty::{pretty_format_batches, pretty_format_columns};
 use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::CancelToken;
 use arrow_udf_js::{CallMode, Runtime};
 use expect_test::{expect, Expect};
 
@@ -71,6 +72,123 @@ fn test_gcd() {
     );
 }
 
+#[test]
+fn test_execution_error() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "check",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            r#"
+            export function check(x) {
+                if (x < 0) {
+                    throw new Error("negative");
+                }
+                return x;
+            }
+            "#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, -1]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let runtime: &dyn arrow_udf_core::UdfRuntime = &runtime;
+    let err = runtime.call("check", &input).unwrap_err();
+    assert_eq!(err.to_string(), "error at row 1: negative");
+    let arrow_udf_core::Error::Execution { row, traceback, .. } = &err else {
+        panic!("expect execution error: {err:?}");
+    };
+    assert_eq!(*row, Some(1));
+    assert!(traceback.as_ref().unwrap().contains("check"));
+    assert!(err.is_user_error());
+
+    let err = runtime.call("unknown", &input).unwrap_err();
+    assert!(matches!(err, arrow_udf_core::Error::FunctionNotFound(_)));
+}
+
+#[test]
+fn test_call_with_input() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "double",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "export function double(x) { return x * 2; }",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = arrow_udf_core::UdfRuntime::call_with_input(&runtime, "double", &input).unwrap();
+    // the input column is shared with the output
+    assert!(Arc::ptr_eq(input.column(0), output.column(0)));
+    check(
+        &[output],
+        expect![[r#"
+        +---+--------+
+        | x | double |
+        +---+--------+
+        | 1 | 2      |
+        |   |        |
+        | 3 | 6      |
+        +---+--------+"#]],
+    );
+}
+
+#[test]
+fn test_call_selected() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "double",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            r#"
+            export function double(x) {
+                if (x < 0) {
+                    throw new Error("negative");
+                }
+                return x * 2;
+            }
+            "#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), Some(-1), Some(2), Some(-2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // rows with negative values are not evaluated
+    let selection = BooleanArray::from(vec![Some(true), Some(false), Some(true), None]);
+    let output = runtime.call_selected("double", &input, &selection).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | double |
+        +--------+
+        | 2      |
+        |        |
+        | 4      |
+        |        |
+        +--------+"#]],
+    );
+
+    let err = runtime
+        .call_selected("double", &input, &BooleanArray::from(vec![true]))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "selection length 1 does not match the number of rows 4"
+    );
+}
+
 #[test]
 fn test_to_string() {
     let mut runtime = Runtime::new().unwrap();
@@ -1043,6 +1161,49 @@ fn test_timeout() {
     assert!(format!("{err:?}").contains("interrupted"))
 }
 
+#[test]
+fn test_cancel() {
+    let mut runtime = Runtime::new().unwrap();
+    let js_code = r#"
+        export function spin(x) {
+            while (true) {}
+        }
+    "#;
+    runtime
+        .add_function(
+            "spin",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            js_code,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let token = CancelToken::new();
+    let canceller = std::thread::spawn({
+        let token = token.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(50));
+            token.cancel();
+        }
+    });
+    let err = runtime
+        .call_cancellable("spin", &input, &token)
+        .unwrap_err();
+    canceller.join().unwrap();
+    assert!(matches!(err, arrow_udf_core::Error::Cancelled), "{err:?}");
+    assert!(!err.is_user_error());
+
+    // a cancelled token fails before the function is called
+    let err = runtime
+        .call_cancellable("spin", &input, &token)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "cancelled");
+}
+
 #[test]
 fn test_memory_limit() {
     let mut runtime = Runtime::new().unwrap();
@@ -1127,6 +1288,306 @@ fn test_send_sync() {
     assert_send_sync::<Runtime>();
 }
 
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(Runtime::new().unwrap());
+    runtime
+        .add_function(
+            "neg",
+            Field::new("neg", DataType::Int32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            r#"
+            export function neg(x) {
+                return -x;
+            }
+            "#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            Field::new("series", DataType::Int32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            r#"
+            export function* series(n) {
+                for (let i = 0; i < n; i++) {
+                    yield i;
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+    let mut functions = runtime.list_functions();
+    functions.sort();
+    assert_eq!(functions, ["neg", "series"]);
+
+    let function = (runtime.functions().into_iter())
+        .find(|f| f.name == "neg")
+        .unwrap();
+    assert_eq!(function.return_field.unwrap().data_type(), &DataType::Int32);
+    assert_eq!(
+        function.mode,
+        Some(arrow_udf_core::CallMode::ReturnNullOnNullInput)
+    );
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("neg", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | neg |
+        +-----+
+        | -1  |
+        |     |
+        | -2  |
+        +-----+"#]],
+    );
+
+    let outputs = runtime
+        .call_table_function("series", &input, 2)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 2   | 0      |
+        | 2   | 1      |
+        +-----+--------+"#]],
+    );
+}
+
+#[test]
+fn test_call_broadcast() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "pad",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            "export function pad(s, n, c) { return s.padStart(n, c); }",
+        )
+        .unwrap();
+
+    // only the string varies by row
+    let s = StringArray::from(vec![Some("a"), None, Some("bcd")]);
+    let args = arrow_udf_core::Arguments::new(3)
+        .with_array(Field::new("s", DataType::Utf8, true), Arc::new(s))
+        .unwrap()
+        .with_scalar(
+            Field::new("n", DataType::Int32, true),
+            Arc::new(Int32Array::from(vec![3])),
+        )
+        .unwrap()
+        .with_scalar(
+            Field::new("c", DataType::Utf8, true),
+            Arc::new(StringArray::from(vec!["*"])),
+        )
+        .unwrap();
+    let output = runtime.call_broadcast("pad", &args).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | pad |
+        +-----+
+        | **a |
+        |     |
+        | bcd |
+        +-----+"#]],
+    );
+
+    // scalars must have one value, and arrays one for each row
+    let args = arrow_udf_core::Arguments::new(3);
+    let one = Arc::new(Int32Array::from(vec![1]));
+    let n = Field::new("n", DataType::Int32, true);
+    args.clone().with_array(n.clone(), one.clone()).unwrap_err();
+    let three = Arc::new(Int32Array::from(vec![1, 2, 3]));
+    args.with_scalar(n, three).unwrap_err();
+}
+
+#[test]
+fn test_call_scalar() {
+    use arrow_array::Scalar;
+    use arrow_udf_core::UdfRuntime;
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "pad",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            "export function pad(s, n, c) { return s.padStart(n, c); }",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "answer",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "export function answer() { return 42; }",
+        )
+        .unwrap();
+
+    let args = [
+        Scalar::new(Arc::new(StringArray::from(vec!["a"])) as ArrayRef),
+        Scalar::new(Arc::new(Int32Array::from(vec![3])) as ArrayRef),
+        Scalar::new(Arc::new(StringArray::from(vec!["*"])) as ArrayRef),
+    ];
+    let output = runtime.call_scalar("pad", &args).unwrap().into_inner();
+    assert_eq!(
+        output.as_ref(),
+        &StringArray::from(vec!["**a"]) as &dyn arrow_array::Array
+    );
+
+    let args = [
+        Scalar::new(Arc::new(StringArray::from(vec![None::<&str>])) as ArrayRef),
+        Scalar::new(Arc::new(Int32Array::from(vec![3])) as ArrayRef),
+        Scalar::new(Arc::new(StringArray::from(vec!["*"])) as ArrayRef),
+    ];
+    let output = runtime.call_scalar("pad", &args).unwrap().into_inner();
+    assert!(output.is_null(0));
+
+    let output = runtime.call_scalar("answer", &[]).unwrap().into_inner();
+    assert_eq!(
+        output.as_ref(),
+        &Int32Array::from(vec![42]) as &dyn arrow_array::Array
+    );
+
+    let err = runtime.call_scalar("unknown", &[]).unwrap_err();
+    assert!(matches!(err, arrow_udf_core::Error::FunctionNotFound(_)));
+}
+
+#[test]
+fn test_nested_null_policy() {
+    use arrow_udf_core::{Error, NestedNullPolicy};
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "len",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "export function len(a) { return a.length; }",
+        )
+        .unwrap();
+
+    let list = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+        Some(vec![Some(1), None, Some(3)]),
+        None,
+        Some(vec![Some(2)]),
+    ]);
+    let schema = Schema::new(vec![Field::new(
+        "a",
+        arrow_array::Array::data_type(&list).clone(),
+        true,
+    )]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(list)]).unwrap();
+
+    let output = runtime.call("len", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | len |
+        +-----+
+        | 3   |
+        |     |
+        | 1   |
+        +-----+"#]],
+    );
+
+    runtime.set_nested_null_policy(NestedNullPolicy::Propagate);
+    let output = runtime.call("len", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | len |
+        +-----+
+        |     |
+        |     |
+        | 1   |
+        +-----+"#]],
+    );
+
+    runtime.set_nested_null_policy(NestedNullPolicy::Error);
+    let err = arrow_udf_core::UdfRuntime::call(&runtime, "len", &input).unwrap_err();
+    match err {
+        Error::Execution { row, message, .. } => {
+            assert_eq!(row, Some(0));
+            assert_eq!(message, "argument 1 contains a nested null");
+        }
+        e => panic!("unexpected error: {e}"),
+    }
+}
+
+#[test]
+fn test_tracing() {
+    use std::sync::Mutex;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    /// A writer that collects the formatted events.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(false)
+        .with_writer({
+            let buffer = buffer.clone();
+            move || buffer.clone()
+        })
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .add_function(
+                "neg",
+                DataType::Int32,
+                CallMode::ReturnNullOnNullInput,
+                "export function neg(x) { return -x; }",
+            )
+            .unwrap();
+        let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+        let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+        let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+        runtime.call("neg", &input).unwrap();
+    });
+
+    // spans are reported with their durations when closed
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    for span in [
+        "add_function{function=\"neg\"}: arrow_udf_js: close time.busy",
+        "call{function=\"neg\" rows=3}: arrow_udf_js: close time.busy",
+        "call{function=\"neg\" rows=3}:build_array{rows=3}: arrow_udf_js: close time.busy",
+    ] {
+        assert!(output.contains(span), "{span} not found in:\n{output}");
+    }
+}
+
 /// Compare the actual output with the expected output.
 #[track_caller]
 fn check(actual: &[RecordBatch], expect: Expect) {
@@ -1162,3 +1623,173 @@ fn decimal_field(name: &str) -> Field {
     Field::new(name, DataType::Utf8, true)
         .with_metadata([("ARROW:extension:name".into(), "arrowudf.decimal".into())].into())
 }
+
+#[test]
+fn test_non_nullable_nested_field() {
+    use arrow_schema::Fields;
+
+    let point = DataType::Struct(Fields::from(vec![
+        Field::new("x", DataType::Int32, false),
+        Field::new("y", DataType::Int32, true),
+    ]));
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "point",
+            Field::new("point", point, true),
+            CallMode::ReturnNullOnNullInput,
+            "export function point(x) { return x > 0 ? { x, y: x } : { x: null, y: null }; }",
+        )
+        .unwrap();
+
+    // nulls in non-nullable nested fields are rejected
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 0]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let err = runtime.call("point", &input).unwrap_err();
+    assert!(format!("{err:#}").contains("unmasked nulls"), "{err:#}");
+}
+
+#[test]
+fn test_log_sink() {
+    use arrow_udf_core::LogLevel;
+    use std::sync::Mutex;
+
+    let mut runtime = Runtime::new().unwrap();
+    // logging without a sink is a no-op
+    runtime
+        .add_function(
+            "add",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            console.info("loaded");
+            export function add(a, b) {
+                console.log("add", a, b, { a, b: [b] });
+                if (a < 0) {
+                    console.warn("negative", a);
+                }
+                return a + b;
+            }
+            "#,
+        )
+        .unwrap();
+
+    let logs = Arc::new(Mutex::new(vec![]));
+    runtime.set_log_sink(Some(Arc::new({
+        let logs = logs.clone();
+        move |level: LogLevel, function: &str, message: &str| {
+            logs.lock()
+                .unwrap()
+                .push(format!("[{level}] {function}: {message}"));
+        }
+    })));
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function* series(n) {
+                for (let i = 0; i < n; i++) {
+                    console.debug("yield", i);
+                    yield i;
+                }
+                console.error("done", n);
+            }
+            "#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(1), Some(-2), None]);
+    let arg1 = Int32Array::from(vec![Some(3), Some(4), Some(5)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    runtime.call("add", &input).unwrap();
+
+    let schema = Schema::new(vec![Field::new("n", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let outputs = runtime.call_table_function("series", &input, 10).unwrap();
+    outputs.for_each(|output| _ = output.unwrap());
+
+    expect![[r#"
+        [info] add: add 1 3 {"a":1,"b":[3]}
+        [info] add: add -2 4 {"a":-2,"b":[4]}
+        [warn] add: negative -2
+        [debug] series: yield 0
+        [debug] series: yield 1
+        [error] series: done 2"#]]
+    .assert_eq(&logs.lock().unwrap().join("\n"));
+}
+
+#[test]
+fn test_capabilities() {
+    use arrow_udf_core::{
+        BatchMode, Capabilities, FunctionKind, ResourceLimit, TolerantRuntime, UdfRuntime,
+    };
+
+    let runtime = Runtime::new().unwrap();
+    let capabilities = UdfRuntime::capabilities(&runtime);
+    assert!(capabilities.supports(FunctionKind::Aggregate));
+    assert!(capabilities.batch_modes.contains(&BatchMode::Selected));
+    assert!(capabilities
+        .resource_limits
+        .contains(&ResourceLimit::Timeout));
+    assert!(!capabilities
+        .resource_limits
+        .contains(&ResourceLimit::Instructions));
+
+    // nested types are checked recursively
+    let list = DataType::new_list(DataType::Int32, true);
+    assert!(capabilities.supports_type(&list));
+    assert!(capabilities.supports_type(&DataType::Timestamp(
+        arrow_schema::TimeUnit::Millisecond,
+        None
+    )));
+    assert!(!capabilities.supports_type(&DataType::Float16));
+    assert!(!capabilities.supports_type(&DataType::new_list(DataType::Float16, true)));
+
+    // wrappers report the capabilities of the inner runtime
+    let tolerant = TolerantRuntime::new(Box::new(Runtime::new().unwrap()));
+    assert_eq!(tolerant.capabilities(), capabilities);
+
+    // round trip through metadata
+    let metadata = capabilities.to_metadata();
+    check_metadata(
+        &metadata,
+        expect![[r#"
+            arrowudf.batch_modes=broadcast,selected,cancellable
+            arrowudf.data_types=Binary,BinaryView,Boolean,Date32,Decimal128,Decimal256,Float32,Float64,Int16,Int32,Int64,Int8,LargeBinary,LargeList,LargeUtf8,List,Null,Struct,Timestamp,UInt16,UInt32,UInt64,UInt8,Utf8,Utf8View
+            arrowudf.function_kinds=scalar,table,aggregate
+            arrowudf.resource_limits=memory,timeout"#]],
+    );
+    assert_eq!(
+        Capabilities::from_metadata(&metadata).unwrap(),
+        capabilities
+    );
+
+    // missing data types are unknown, and unknown names are rejected
+    let capabilities = Capabilities::from_metadata(
+        &[("arrowudf.function_kinds".to_string(), "scalar".to_string())].into(),
+    )
+    .unwrap();
+    assert_eq!(capabilities.data_types, None);
+    assert!(capabilities.supports_type(&DataType::Float16));
+    let err = Capabilities::from_metadata(
+        &[("arrowudf.function_kinds".to_string(), "stream".to_string())].into(),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "invalid function kind: stream");
+}
+
+/// Compare sorted `key=value` lines of the metadata with the expected string.
+fn check_metadata(metadata: &std::collections::HashMap<String, String>, expect: Expect) {
+    let mut lines: Vec<_> = metadata.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    lines.sort();
+    expect.assert_eq(&lines.join("\n"));
+}
@@ -0,0 +1,71 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Wraps the native runtime to work on apache-arrow tables instead of IPC buffers.
+
+const { Field, Schema, Table, tableFromIPC, tableToIPC } = require('apache-arrow')
+const native = require('./native.js')
+
+class Runtime {
+  constructor(inner) {
+    this.inner = inner
+  }
+
+  /** Create a JavaScript runtime. */
+  static javascript() {
+    return new Runtime(native.Runtime.javascript())
+  }
+
+  /** Create a WebAssembly runtime from a compiled binary. */
+  static wasm(binary) {
+    return new Runtime(native.Runtime.wasm(binary))
+  }
+
+  /**
+   * Add a function.
+   *
+   * `returnType` is an apache-arrow `DataType` or `Field`.
+   */
+  addFunction(name, returnType, code, { nullOnNullInput = false } = {}) {
+    const field = returnType instanceof Field ? returnType : new Field(name, returnType, true)
+    const schema = tableToIPC(new Table(new Schema([field])), 'stream')
+    this.inner.addFunction(name, Buffer.from(schema), code, nullOnNullInput)
+  }
+
+  /** Call a scalar function on a table, returning a table of the results. */
+  call(name, input) {
+    return tableFromIPC(this.inner.call(name, toIPC(input)))
+  }
+
+  /**
+   * Call a table function on a table.
+   *
+   * The first column of the result is the index of the row in its input batch.
+   */
+  callTableFunction(name, input, { chunkSize = 1024 } = {}) {
+    return tableFromIPC(this.inner.callTableFunction(name, toIPC(input), chunkSize))
+  }
+
+  /** Returns the names of all functions. */
+  listFunctions() {
+    return this.inner.listFunctions()
+  }
+}
+
+function toIPC(input) {
+  const table = input instanceof Table ? input : new Table(input)
+  return Buffer.from(tableToIPC(table, 'stream'))
+}
+
+module.exports = { Runtime }
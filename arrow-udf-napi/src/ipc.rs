@@ -0,0 +1,136 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtimes working on Arrow IPC streams, independent of N-API.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use arrow_array::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::{CallMode, UdfRuntime};
+
+/// A UDF runtime that calls functions on record batches in Arrow IPC streams.
+pub struct IpcRuntime {
+    runtime: Box<dyn UdfRuntime>,
+    /// Return fields of added functions, used when a table function returns no rows.
+    return_fields: HashMap<String, Field>,
+}
+
+impl IpcRuntime {
+    /// Wrap a runtime.
+    pub fn new(runtime: Box<dyn UdfRuntime>) -> Self {
+        Self {
+            runtime,
+            return_fields: HashMap::new(),
+        }
+    }
+
+    /// Add a function.
+    ///
+    /// `return_field` is an IPC stream of a schema with the return field as its only field.
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        return_field: &[u8],
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let (schema, _) = decode(return_field)?;
+        let [return_field] = &schema.fields()[..] else {
+            bail!("return field must be the only field of the schema");
+        };
+        (self.runtime).add_function(name, Field::clone(return_field), mode, code)?;
+        (self.return_fields).insert(name.to_string(), Field::clone(return_field));
+        Ok(())
+    }
+
+    /// Call a scalar function on each batch of the input IPC stream,
+    /// returning an IPC stream with one output batch for each input batch.
+    pub fn call(&self, name: &str, input: &[u8]) -> Result<Vec<u8>> {
+        let (schema, batches) = decode(input)?;
+        let mut outputs = vec![];
+        for batch in &batches {
+            outputs.push(self.runtime.call(name, batch)?);
+        }
+        let schema = match outputs.first() {
+            Some(batch) => batch.schema(),
+            // call on an empty batch to get the output schema
+            None => (self.runtime)
+                .call(name, &RecordBatch::new_empty(schema))?
+                .schema(),
+        };
+        encode(&schema, &outputs)
+    }
+
+    /// Call a table function on each batch of the input IPC stream,
+    /// returning an IPC stream of output batches with up to `chunk_size` rows.
+    ///
+    /// The first column of outputs is the index of the row in its input batch.
+    pub fn call_table_function(
+        &self,
+        name: &str,
+        input: &[u8],
+        chunk_size: usize,
+    ) -> Result<Vec<u8>> {
+        let (_, batches) = decode(input)?;
+        let mut outputs = vec![];
+        for batch in &batches {
+            for output in (self.runtime).call_table_function(name, batch, chunk_size)? {
+                outputs.push(output?);
+            }
+        }
+        let schema = match outputs.first() {
+            Some(batch) => batch.schema(),
+            None => {
+                let return_field = (self.return_fields)
+                    .get(name)
+                    .with_context(|| format!("unknown output schema of function: {name}"))?;
+                Arc::new(Schema::new(vec![
+                    Field::new("row", DataType::Int32, true),
+                    return_field.clone(),
+                ]))
+            }
+        };
+        encode(&schema, &outputs)
+    }
+
+    /// Returns the names of all functions.
+    pub fn list_functions(&self) -> Vec<String> {
+        self.runtime.list_functions()
+    }
+}
+
+/// Decode the schema and all batches from an IPC stream.
+fn decode(bytes: &[u8]) -> Result<(Arc<Schema>, Vec<RecordBatch>)> {
+    let reader = StreamReader::try_new(bytes, None).context("failed to read IPC stream")?;
+    let schema = reader.schema();
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    Ok((schema, batches))
+}
+
+/// Encode batches as an IPC stream.
+fn encode(schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+    let mut buf = vec![];
+    let mut writer = StreamWriter::try_new(&mut buf, schema)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    drop(writer);
+    Ok(buf)
+}
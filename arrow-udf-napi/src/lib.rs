@@ -0,0 +1,108 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use arrow_udf_core::CallMode;
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+
+mod ipc;
+
+pub use self::ipc::IpcRuntime;
+
+/// A UDF runtime that calls functions on record batches in Arrow IPC streams.
+#[napi]
+pub struct Runtime {
+    inner: IpcRuntime,
+}
+
+#[napi]
+impl Runtime {
+    /// Create a JavaScript runtime.
+    #[cfg(feature = "javascript")]
+    #[napi(factory)]
+    pub fn javascript() -> napi::Result<Self> {
+        let runtime = arrow_udf_js::Runtime::new().map_err(to_napi_err)?;
+        Ok(Self {
+            inner: IpcRuntime::new(Box::new(runtime)),
+        })
+    }
+
+    /// Create a WebAssembly runtime from a binary.
+    #[cfg(feature = "wasm")]
+    #[napi(factory)]
+    pub fn wasm(binary: Buffer) -> napi::Result<Self> {
+        let runtime = arrow_udf_wasm::Runtime::new(&binary).map_err(to_napi_err)?;
+        Ok(Self {
+            inner: IpcRuntime::new(Box::new(runtime)),
+        })
+    }
+
+    /// Add a function.
+    ///
+    /// `return_field` is an IPC stream of a schema with the return field as its only field.
+    #[napi]
+    pub fn add_function(
+        &mut self,
+        name: String,
+        return_field: Buffer,
+        code: String,
+        null_on_null_input: Option<bool>,
+    ) -> napi::Result<()> {
+        let mode = match null_on_null_input.unwrap_or(false) {
+            true => CallMode::ReturnNullOnNullInput,
+            false => CallMode::CalledOnNullInput,
+        };
+        (self.inner)
+            .add_function(&name, &return_field, mode, &code)
+            .map_err(to_napi_err)
+    }
+
+    /// Call a scalar function on each batch of the input IPC stream,
+    /// returning an IPC stream with one output batch for each input batch.
+    #[napi]
+    pub fn call(&self, name: String, input: Buffer) -> napi::Result<Buffer> {
+        let output = self.inner.call(&name, &input).map_err(to_napi_err)?;
+        Ok(output.into())
+    }
+
+    /// Call a table function on each batch of the input IPC stream,
+    /// returning an IPC stream of output batches with up to `chunk_size` rows.
+    ///
+    /// The first column of outputs is the index of the row in its input batch.
+    #[napi]
+    pub fn call_table_function(
+        &self,
+        name: String,
+        input: Buffer,
+        chunk_size: Option<u32>,
+    ) -> napi::Result<Buffer> {
+        let chunk_size = chunk_size.unwrap_or(1024) as usize;
+        let output = (self.inner)
+            .call_table_function(&name, &input, chunk_size)
+            .map_err(to_napi_err)?;
+        Ok(output.into())
+    }
+
+    /// Returns the names of all functions.
+    #[napi]
+    pub fn list_functions(&self) -> Vec<String> {
+        self.inner.list_functions()
+    }
+}
+
+fn to_napi_err(e: impl Into<anyhow::Error>) -> napi::Error {
+    napi::Error::from_reason(format!("{:#}", e.into()))
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+const test = require('node:test')
+const assert = require('node:assert')
+const { Int32, tableFromArrays } = require('apache-arrow')
+const { Runtime } = require('..')
+
+test('call', () => {
+  const runtime = Runtime.javascript()
+  runtime.addFunction(
+    'gcd',
+    new Int32(),
+    `
+    export function gcd(a, b) {
+        while (b != 0) {
+            let t = b;
+            b = a % b;
+            a = t;
+        }
+        return a;
+    }
+    `,
+    { nullOnNullInput: true },
+  )
+  assert.deepStrictEqual(runtime.listFunctions(), ['gcd'])
+
+  const input = tableFromArrays({ x: Int32Array.from([25, 6]), y: Int32Array.from([15, 4]) })
+  const output = runtime.call('gcd', input)
+  assert.deepStrictEqual(output.getChild('gcd').toArray(), Int32Array.from([5, 2]))
+})
+
+test('call table function', () => {
+  const runtime = Runtime.javascript()
+  runtime.addFunction(
+    'range',
+    new Int32(),
+    `
+    export function* range(n) {
+        for (let i = 0; i < n; i++) {
+            yield i;
+        }
+    }
+    `,
+  )
+  const input = tableFromArrays({ x: Int32Array.from([1, 2]) })
+  const output = runtime.callTableFunction('range', input, { chunkSize: 2 })
+  assert.deepStrictEqual(output.getChild('row').toArray(), Int32Array.from([0, 1, 1]))
+  assert.deepStrictEqual(output.getChild('range').toArray(), Int32Array.from([0, 0, 1]))
+})
+
+test('unknown function', () => {
+  const runtime = Runtime.javascript()
+  const input = tableFromArrays({ x: Int32Array.from([1]) })
+  assert.throws(() => runtime.call('unknown', input), /function not found/)
+})
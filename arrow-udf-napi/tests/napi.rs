@@ -0,0 +1,173 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::CallMode;
+use arrow_udf_napi::IpcRuntime;
+use expect_test::{expect, Expect};
+
+#[test]
+fn test_call() {
+    let mut runtime = IpcRuntime::new(Box::new(arrow_udf_js::Runtime::new().unwrap()));
+    runtime
+        .add_function(
+            "gcd",
+            &return_field("gcd"),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+    assert_eq!(runtime.list_functions(), ["gcd"]);
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]));
+    let batch = |x: Vec<Option<i32>>, y: Vec<Option<i32>>| {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(x)), Arc::new(Int32Array::from(y))],
+        )
+        .unwrap()
+    };
+    let input = encode(
+        &schema,
+        &[
+            batch(vec![Some(25), None], vec![Some(15), Some(1)]),
+            batch(vec![Some(6)], vec![Some(4)]),
+        ],
+    );
+    let output = runtime.call("gcd", &input).unwrap();
+    let outputs = decode(&output);
+    assert_eq!(outputs.len(), 2);
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        |     |
+        | 2   |
+        +-----+"#]],
+    );
+
+    let err = runtime.call("unknown", &encode(&schema, &[])).unwrap_err();
+    assert_eq!(err.to_string(), "function not found: unknown");
+}
+
+#[test]
+fn test_call_table_function() {
+    let mut runtime = IpcRuntime::new(Box::new(arrow_udf_js::Runtime::new().unwrap()));
+    runtime
+        .add_function(
+            "range",
+            &return_field("range"),
+            CallMode::CalledOnNullInput,
+            r#"
+            export function* range(n) {
+                for (let i = 0; i < n; i++) {
+                    yield i;
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+    let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(Int32Array::from(vec![Some(1), None, Some(2)]))],
+    )
+    .unwrap();
+    let output = runtime
+        .call_table_function("range", &encode(&schema, &[batch]), 2)
+        .unwrap();
+    check(
+        &decode(&output),
+        expect![[r#"
+        +-----+-------+
+        | row | range |
+        +-----+-------+
+        | 0   | 0     |
+        | 2   | 0     |
+        | 2   | 1     |
+        +-----+-------+"#]],
+    );
+
+    // the output schema is known even if there is no row
+    let output = runtime
+        .call_table_function("range", &encode(&schema, &[]), 1024)
+        .unwrap();
+    let reader = StreamReader::try_new(&output[..], None).unwrap();
+    assert_eq!(reader.schema().fields().len(), 2);
+}
+
+#[test]
+fn test_invalid_return_field() {
+    let mut runtime = IpcRuntime::new(Box::new(arrow_udf_js::Runtime::new().unwrap()));
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let err = runtime
+        .add_function("f", &encode(&schema, &[]), CallMode::CalledOnNullInput, "")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "return field must be the only field of the schema"
+    );
+}
+
+fn return_field(name: &str) -> Vec<u8> {
+    let schema = Schema::new(vec![Field::new(name, DataType::Int32, true)]);
+    encode(&schema, &[])
+}
+
+fn encode(schema: &Schema, batches: &[RecordBatch]) -> Vec<u8> {
+    let mut buf = vec![];
+    let mut writer = StreamWriter::try_new(&mut buf, schema).unwrap();
+    for batch in batches {
+        writer.write(batch).unwrap();
+    }
+    writer.finish().unwrap();
+    drop(writer);
+    buf
+}
+
+fn decode(bytes: &[u8]) -> Vec<RecordBatch> {
+    let reader = StreamReader::try_new(bytes, None).unwrap();
+    reader.collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
@@ -0,0 +1,250 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use arrow_array::{RecordBatch, RecordBatchOptions};
+use arrow_schema::{Field, Schema};
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
+use arrow_udf_core::{Capabilities, Error, FunctionKind};
+use ort::session::Session;
+use ort::tensor::TensorElementType;
+use ort::value::{DynValue, ValueType};
+
+pub mod tensor;
+
+use self::tensor::{Tensor, TensorData};
+
+/// A runtime to execute ONNX models as user defined functions.
+#[derive(Default)]
+pub struct Runtime {
+    models: HashMap<String, Model>,
+}
+
+/// A model loaded in ONNX Runtime.
+struct Model {
+    /// Running a session requires exclusive access.
+    session: Mutex<Session>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl std::fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("models", &self.models.keys())
+            .finish()
+    }
+}
+
+impl Runtime {
+    /// Create a new runtime.
+    ///
+    /// With the `load-dynamic` feature, the ONNX Runtime library is loaded from `ORT_DYLIB_PATH`
+    /// when the first model is added.
+    pub fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    /// Add an ONNX model as a function.
+    ///
+    /// Input columns are mapped to the inputs of the model by position,
+    /// and each output of the model becomes a column of the output batch.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name))]
+    pub fn add_model(&mut self, name: &str, model: &[u8]) -> Result<()> {
+        let session = Session::builder()?
+            .commit_from_memory(model)
+            .context("failed to load ONNX model")?;
+        self.insert(name, session);
+        Ok(())
+    }
+
+    /// Add an ONNX model from a file as a function.
+    pub fn add_model_from_file(&mut self, name: &str, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let session = Session::builder()?
+            .commit_from_file(path)
+            .with_context(|| format!("failed to load ONNX model from {}", path.display()))?;
+        self.insert(name, session);
+        Ok(())
+    }
+
+    fn insert(&mut self, name: &str, session: Session) {
+        let model = Model {
+            inputs: session.inputs.iter().map(|i| i.name.clone()).collect(),
+            outputs: session.outputs.iter().map(|o| o.name.clone()).collect(),
+            session: Mutex::new(session),
+        };
+        self.models.insert(name.to_string(), model);
+    }
+
+    /// Remove a model.
+    pub fn del_model(&mut self, name: &str) -> Result<()> {
+        self.models
+            .remove(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Ok(())
+    }
+
+    /// Run a model on the input batch.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    pub fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let model = self
+            .models
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        if input.num_columns() != model.inputs.len() {
+            bail!(
+                "model {name} expects {} inputs, but got {} columns",
+                model.inputs.len(),
+                input.num_columns()
+            );
+        }
+        let mut inputs = Vec::with_capacity(model.inputs.len());
+        for (name, column) in model.inputs.iter().zip(input.columns()) {
+            let tensor = Tensor::from_array(column)
+                .with_context(|| format!("failed to convert column to input {name}"))?;
+            inputs.push((name.as_str(), into_value(tensor)?));
+        }
+
+        let mut session = model.session.lock().unwrap();
+        let outputs = session.run(inputs)?;
+        let mut fields = Vec::with_capacity(model.outputs.len());
+        let mut columns = Vec::with_capacity(model.outputs.len());
+        for name in &model.outputs {
+            let tensor = from_value(&outputs[name.as_str()])
+                .with_context(|| format!("failed to convert output {name}"))?;
+            if tensor.shape.first() != Some(&input.num_rows()) {
+                bail!(
+                    "output {name} of shape {:?} does not match {} rows",
+                    tensor.shape,
+                    input.num_rows()
+                );
+            }
+            let array = tensor.into_array()?;
+            fields.push(Field::new(name, array.data_type().clone(), true));
+            columns.push(array);
+        }
+        Ok(RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(fields)),
+            columns,
+            &RecordBatchOptions::default().with_row_count(Some(input.num_rows())),
+        )?)
+    }
+}
+
+impl arrow_udf_core::UdfRuntime for Runtime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        _return_field: Field,
+        _mode: CallMode,
+        _code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        Err(anyhow::anyhow!("ONNX models must be added by `add_model`: {name}").into())
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        Ok(self.call(name, input)?)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        _input: &'a RecordBatch,
+        _chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        Err(anyhow::anyhow!("table function not supported: {name}").into())
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.models.keys().cloned().collect()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new()
+            .with_data_types([
+                "Boolean",
+                "FixedSizeList",
+                "Float32",
+                "Float64",
+                "Int8",
+                "Int16",
+                "Int32",
+                "Int64",
+                "UInt8",
+                "UInt16",
+                "UInt32",
+                "UInt64",
+            ])
+            .with_function_kinds([FunctionKind::Scalar])
+    }
+}
+
+/// Convert a tensor into an ONNX Runtime value.
+fn into_value(tensor: Tensor) -> Result<DynValue> {
+    let shape: Vec<i64> = tensor.shape.iter().map(|&d| d as i64).collect();
+    use ort::value::Tensor as T;
+    Ok(match tensor.data {
+        TensorData::Bool(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::Int8(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::Int16(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::Int32(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::Int64(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::UInt8(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::UInt16(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::UInt32(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::UInt64(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::Float32(v) => T::from_array((shape, v))?.into_dyn(),
+        TensorData::Float64(v) => T::from_array((shape, v))?.into_dyn(),
+    })
+}
+
+/// Convert an ONNX Runtime value into a tensor.
+fn from_value(value: &DynValue) -> Result<Tensor> {
+    let ValueType::Tensor { ty, .. } = value.dtype() else {
+        bail!("output is not a tensor: {}", value.dtype());
+    };
+    macro_rules! extract {
+        ($variant:ident) => {{
+            let (shape, data) = value.try_extract_tensor()?;
+            let shape = shape.iter().map(|&d| d as usize).collect();
+            Tensor {
+                shape,
+                data: TensorData::$variant(data.to_vec()),
+            }
+        }};
+    }
+    Ok(match ty {
+        TensorElementType::Bool => extract!(Bool),
+        TensorElementType::Int8 => extract!(Int8),
+        TensorElementType::Int16 => extract!(Int16),
+        TensorElementType::Int32 => extract!(Int32),
+        TensorElementType::Int64 => extract!(Int64),
+        TensorElementType::Uint8 => extract!(UInt8),
+        TensorElementType::Uint16 => extract!(UInt16),
+        TensorElementType::Uint32 => extract!(UInt32),
+        TensorElementType::Uint64 => extract!(UInt64),
+        TensorElementType::Float32 => extract!(Float32),
+        TensorElementType::Float64 => extract!(Float64),
+        ty => bail!("tensor element type not supported: {ty:?}"),
+    })
+}
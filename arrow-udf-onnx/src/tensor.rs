@@ -0,0 +1,157 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion between Arrow arrays and dense tensors.
+//!
+//! The first dimension of a tensor is the rows of the array. A primitive array becomes a tensor of
+//! shape `[rows]`, and a fixed-size list array of size `k` becomes a tensor of shape `[rows, k, ...]`.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use arrow_array::PrimitiveArray;
+use arrow_array::{cast::AsArray, types::*, Array, ArrayRef, BooleanArray, FixedSizeListArray};
+use arrow_schema::{DataType, Field};
+
+/// A dense tensor in row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    /// The dimensions of the tensor. The first dimension is the number of rows.
+    pub shape: Vec<usize>,
+    /// The elements of the tensor.
+    pub data: TensorData,
+}
+
+/// The elements of a tensor.
+#[derive(Debug, Clone, PartialEq)]
+#[allow(missing_docs)]
+pub enum TensorData {
+    Bool(Vec<bool>),
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    UInt8(Vec<u8>),
+    UInt16(Vec<u16>),
+    UInt32(Vec<u32>),
+    UInt64(Vec<u64>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+}
+
+impl TensorData {
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Bool(v) => v.len(),
+            Self::Int8(v) => v.len(),
+            Self::Int16(v) => v.len(),
+            Self::Int32(v) => v.len(),
+            Self::Int64(v) => v.len(),
+            Self::UInt8(v) => v.len(),
+            Self::UInt16(v) => v.len(),
+            Self::UInt32(v) => v.len(),
+            Self::UInt64(v) => v.len(),
+            Self::Float32(v) => v.len(),
+            Self::Float64(v) => v.len(),
+        }
+    }
+
+    /// Returns `true` if there is no element.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Tensor {
+    /// Convert an array into a tensor.
+    ///
+    /// Returns error if the array contains null values or the type is not supported.
+    pub fn from_array(array: &dyn Array) -> Result<Self> {
+        if array.null_count() > 0 {
+            bail!("null values are not supported in tensors");
+        }
+        let data = match array.data_type() {
+            DataType::Boolean => TensorData::Bool(array.as_boolean().values().iter().collect()),
+            DataType::Int8 => TensorData::Int8(values::<Int8Type>(array)),
+            DataType::Int16 => TensorData::Int16(values::<Int16Type>(array)),
+            DataType::Int32 => TensorData::Int32(values::<Int32Type>(array)),
+            DataType::Int64 => TensorData::Int64(values::<Int64Type>(array)),
+            DataType::UInt8 => TensorData::UInt8(values::<UInt8Type>(array)),
+            DataType::UInt16 => TensorData::UInt16(values::<UInt16Type>(array)),
+            DataType::UInt32 => TensorData::UInt32(values::<UInt32Type>(array)),
+            DataType::UInt64 => TensorData::UInt64(values::<UInt64Type>(array)),
+            DataType::Float32 => TensorData::Float32(values::<Float32Type>(array)),
+            DataType::Float64 => TensorData::Float64(values::<Float64Type>(array)),
+            DataType::FixedSizeList(_, size) => {
+                let list = array.as_fixed_size_list();
+                let inner = Self::from_array(list.values())?;
+                let mut shape = vec![array.len(), *size as usize];
+                shape.extend_from_slice(&inner.shape[1..]);
+                return Ok(Self {
+                    shape,
+                    data: inner.data,
+                });
+            }
+            ty => bail!("type not supported in tensors: {ty}"),
+        };
+        Ok(Self {
+            shape: vec![array.len()],
+            data,
+        })
+    }
+
+    /// Convert the tensor into an array with one row per element of the first dimension.
+    pub fn into_array(self) -> Result<ArrayRef> {
+        if self.shape.is_empty() {
+            bail!("scalar tensors can not be converted to arrays");
+        }
+        let expected: usize = self.shape.iter().product();
+        if expected != self.data.len() {
+            bail!(
+                "tensor of shape {:?} has {} elements",
+                self.shape,
+                self.data.len()
+            );
+        }
+        let mut array: ArrayRef = match self.data {
+            TensorData::Bool(v) => Arc::new(BooleanArray::from(v)),
+            TensorData::Int8(v) => Arc::new(PrimitiveArray::<Int8Type>::from(v)),
+            TensorData::Int16(v) => Arc::new(PrimitiveArray::<Int16Type>::from(v)),
+            TensorData::Int32(v) => Arc::new(PrimitiveArray::<Int32Type>::from(v)),
+            TensorData::Int64(v) => Arc::new(PrimitiveArray::<Int64Type>::from(v)),
+            TensorData::UInt8(v) => Arc::new(PrimitiveArray::<UInt8Type>::from(v)),
+            TensorData::UInt16(v) => Arc::new(PrimitiveArray::<UInt16Type>::from(v)),
+            TensorData::UInt32(v) => Arc::new(PrimitiveArray::<UInt32Type>::from(v)),
+            TensorData::UInt64(v) => Arc::new(PrimitiveArray::<UInt64Type>::from(v)),
+            TensorData::Float32(v) => Arc::new(PrimitiveArray::<Float32Type>::from(v)),
+            TensorData::Float64(v) => Arc::new(PrimitiveArray::<Float64Type>::from(v)),
+        };
+        // wrap the inner dimensions from the last one
+        for &size in self.shape[1..].iter().rev() {
+            let field = Arc::new(Field::new("item", array.data_type().clone(), true));
+            array = Arc::new(FixedSizeListArray::try_new(
+                field,
+                size.try_into()?,
+                array,
+                None,
+            )?);
+        }
+        Ok(array)
+    }
+}
+
+fn values<T: ArrowPrimitiveType>(array: &dyn Array) -> Vec<T::Native> {
+    array.as_primitive::<T>().values().to_vec()
+}
@@ -0,0 +1,242 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::*;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_onnx::tensor::{Tensor, TensorData};
+use arrow_udf_onnx::Runtime;
+use prost::Message;
+
+#[test]
+fn test_tensor_primitive() {
+    let array = Float32Array::from(vec![1.0, 2.0, 3.0]);
+    let tensor = Tensor::from_array(&array).unwrap();
+    assert_eq!(tensor.shape, [3]);
+    assert_eq!(tensor.data, TensorData::Float32(vec![1.0, 2.0, 3.0]));
+
+    let output = tensor.into_array().unwrap();
+    assert_eq!(output.as_ref(), &array as &dyn Array);
+}
+
+#[test]
+fn test_tensor_fixed_size_list() {
+    let field = Arc::new(Field::new("item", DataType::Int64, true));
+    let array = FixedSizeListArray::new(
+        field,
+        2,
+        Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5, 6])),
+        None,
+    );
+    // sliced arrays are supported
+    let array = array.slice(1, 2);
+    let tensor = Tensor::from_array(&array).unwrap();
+    assert_eq!(tensor.shape, [2, 2]);
+    assert_eq!(tensor.data, TensorData::Int64(vec![3, 4, 5, 6]));
+
+    let output = tensor.into_array().unwrap();
+    assert_eq!(output.as_ref(), &array as &dyn Array);
+}
+
+#[test]
+fn test_tensor_error() {
+    let err = Tensor::from_array(&Int32Array::from(vec![Some(1), None])).unwrap_err();
+    assert_eq!(err.to_string(), "null values are not supported in tensors");
+
+    let err = Tensor::from_array(&StringArray::from(vec!["a"])).unwrap_err();
+    assert_eq!(err.to_string(), "type not supported in tensors: Utf8");
+
+    let tensor = Tensor {
+        shape: vec![2, 2],
+        data: TensorData::Int32(vec![1, 2, 3]),
+    };
+    let err = tensor.into_array().unwrap_err();
+    assert_eq!(err.to_string(), "tensor of shape [2, 2] has 3 elements");
+}
+
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(Runtime::new().unwrap());
+    runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Float32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            "",
+        )
+        .unwrap_err();
+    assert!(runtime.list_functions().is_empty());
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("x", DataType::Float32, true)])),
+        vec![Arc::new(Float32Array::from(vec![1.0]))],
+    )
+    .unwrap();
+    let err = runtime.call("f", &input).unwrap_err();
+    assert_eq!(err.to_string(), "function not found: f");
+}
+
+/// Run a model computing `z = x + y`.
+///
+/// This test requires the ONNX Runtime library in `ORT_DYLIB_PATH`.
+#[test]
+fn test_add_model() {
+    if std::env::var_os("ORT_DYLIB_PATH").is_none() {
+        eprintln!("skipped: ORT_DYLIB_PATH is not set");
+        return;
+    }
+    let mut runtime = Runtime::new().unwrap();
+    runtime.add_model("add", &add_model()).unwrap();
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Float32, true),
+            Field::new("b", DataType::Float32, true),
+        ])),
+        vec![
+            Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0])),
+            Arc::new(Float32Array::from(vec![10.0, 20.0, 30.0])),
+        ],
+    )
+    .unwrap();
+    let output = runtime.call("add", &input).unwrap();
+    assert_eq!(output.schema().field(0).name(), "z");
+    assert_eq!(
+        output.column(0).as_ref(),
+        &Float32Array::from(vec![11.0, 22.0, 33.0]) as &dyn Array
+    );
+
+    // nulls can not be mapped to tensors
+    let input = RecordBatch::try_new(
+        input.schema(),
+        vec![
+            Arc::new(Float32Array::from(vec![Some(1.0), None])),
+            Arc::new(Float32Array::from(vec![1.0, 2.0])),
+        ],
+    )
+    .unwrap();
+    runtime.call("add", &input).unwrap_err();
+}
+
+/// Encode an ONNX model with a single `Add` node on 1-D float tensors.
+fn add_model() -> Vec<u8> {
+    let value = |name: &str| ValueInfoProto {
+        name: name.into(),
+        r#type: Some(TypeProto {
+            tensor_type: Some(TensorTypeProto {
+                elem_type: 1, // FLOAT
+                shape: Some(TensorShapeProto {
+                    dim: vec![Dimension {
+                        dim_param: "N".into(),
+                    }],
+                }),
+            }),
+        }),
+    };
+    ModelProto {
+        ir_version: 8,
+        opset_import: vec![OperatorSetIdProto {
+            domain: "".into(),
+            version: 13,
+        }],
+        graph: Some(GraphProto {
+            node: vec![NodeProto {
+                input: vec!["x".into(), "y".into()],
+                output: vec!["z".into()],
+                op_type: "Add".into(),
+            }],
+            name: "add".into(),
+            input: vec![value("x"), value("y")],
+            output: vec![value("z")],
+        }),
+    }
+    .encode_to_vec()
+}
+
+// The subset of `onnx.proto` used by `add_model`.
+
+#[derive(Clone, PartialEq, Message)]
+struct ModelProto {
+    #[prost(int64, tag = "1")]
+    ir_version: i64,
+    #[prost(message, optional, tag = "7")]
+    graph: Option<GraphProto>,
+    #[prost(message, repeated, tag = "8")]
+    opset_import: Vec<OperatorSetIdProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct OperatorSetIdProto {
+    #[prost(string, tag = "1")]
+    domain: String,
+    #[prost(int64, tag = "2")]
+    version: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct GraphProto {
+    #[prost(message, repeated, tag = "1")]
+    node: Vec<NodeProto>,
+    #[prost(string, tag = "2")]
+    name: String,
+    #[prost(message, repeated, tag = "11")]
+    input: Vec<ValueInfoProto>,
+    #[prost(message, repeated, tag = "12")]
+    output: Vec<ValueInfoProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct NodeProto {
+    #[prost(string, repeated, tag = "1")]
+    input: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    output: Vec<String>,
+    #[prost(string, tag = "4")]
+    op_type: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueInfoProto {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(message, optional, tag = "2")]
+    r#type: Option<TypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TypeProto {
+    #[prost(message, optional, tag = "1")]
+    tensor_type: Option<TensorTypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorTypeProto {
+    #[prost(int32, tag = "1")]
+    elem_type: i32,
+    #[prost(message, optional, tag = "2")]
+    shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TensorShapeProto {
+    #[prost(message, repeated, tag = "1")]
+    dim: Vec<Dimension>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Dimension {
+    #[prost(string, tag = "2")]
+    dim_param: String,
+}
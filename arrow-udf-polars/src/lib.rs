@@ -0,0 +1,163 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::sync::Arc;
+
+use arrow_array::ffi::{from_ffi, to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::{make_array, ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_schema::{Field, Schema};
+use arrow_udf_core::UdfRuntime;
+use polars_arrow::ffi::{ArrowArray, ArrowSchema};
+use polars_core::prelude::{ArrowField, CompatLevel, PolarsError, PolarsResult, Series};
+
+#[cfg(feature = "plugin")]
+pub mod plugin;
+
+/// Call a scalar function in the runtime with the series as arguments.
+///
+/// All series must have the same length. The output series is named after the function.
+///
+/// If all series are split into chunks at the same rows, the function is called on each chunk,
+/// and the output series has the same chunks. Otherwise the series are rechunked first,
+/// which copies their data.
+///
+/// Strings and binaries are passed as views without copying if the runtime supports
+/// `Utf8View` and `BinaryView`, or copied into `LargeUtf8` and `LargeBinary` otherwise.
+pub fn call_series(
+    runtime: &dyn UdfRuntime,
+    name: &str,
+    inputs: &[Series],
+) -> PolarsResult<Series> {
+    let chunk_lengths = |s: &Series| s.chunks().iter().map(|c| c.len()).collect::<Vec<_>>();
+    let aligned = (inputs.windows(2)).all(|w| chunk_lengths(&w[0]) == chunk_lengths(&w[1]));
+    let rechunked;
+    let inputs = if aligned {
+        inputs
+    } else {
+        rechunked = inputs.iter().map(|s| s.rechunk()).collect::<Vec<_>>();
+        &rechunked
+    };
+    let capabilities = runtime.capabilities();
+    let compat_levels = (inputs.iter())
+        .map(|series| {
+            let field = arrow_field(series, CompatLevel::newest())?;
+            Ok(match capabilities.supports_type(field.data_type()) {
+                true => CompatLevel::newest(),
+                false => CompatLevel::oldest(),
+            })
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    let num_chunks = inputs.first().map_or(1, |s| s.n_chunks());
+    let mut outputs = Vec::with_capacity(num_chunks);
+    for i in 0..num_chunks {
+        let num_rows = inputs.first().map_or(0, |s| s.chunks()[i].len());
+        let mut fields = Vec::with_capacity(inputs.len());
+        let mut arrays = Vec::with_capacity(inputs.len());
+        for (series, compat_level) in inputs.iter().zip(&compat_levels) {
+            let array = chunk_to_arrow(series, i, *compat_level)?;
+            fields.push(Field::new(
+                series.name().as_str(),
+                array.data_type().clone(),
+                true,
+            ));
+            arrays.push(array);
+        }
+        let input = RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(fields)),
+            arrays,
+            &RecordBatchOptions::default().with_row_count(Some(num_rows)),
+        )
+        .map_err(compute_error)?;
+        let output = runtime.call(name, &input).map_err(compute_error)?;
+        outputs.push(array_to_polars(output.column(0))?);
+    }
+    Series::from_arrow_chunks(name.into(), outputs)
+}
+
+/// Convert a series into an Arrow array through the C data interface.
+///
+/// The series is rechunked into a single array if it has multiple chunks, which copies the data.
+/// Strings and binaries are copied from views into `LargeUtf8` and `LargeBinary`.
+pub fn series_to_arrow(series: &Series) -> PolarsResult<ArrayRef> {
+    chunk_to_arrow(&series.rechunk(), 0, CompatLevel::oldest())
+}
+
+/// Convert a chunk of a series into an Arrow array through the C data interface.
+fn chunk_to_arrow(
+    series: &Series,
+    chunk: usize,
+    compat_level: CompatLevel,
+) -> PolarsResult<ArrayRef> {
+    let array = series.to_arrow(chunk, compat_level);
+    let field = ArrowField::new(series.name().clone(), array.dtype().clone(), true);
+    let mut array = polars_arrow::ffi::export_array_to_c(array);
+    let mut schema = polars_arrow::ffi::export_field_to_c(&field);
+    // SAFETY: the structs are exported by Polars through the C data interface,
+    // and are taken over by arrow-rs, which leaves released structs behind
+    let data = unsafe {
+        let array = FFI_ArrowArray::from_raw(&mut array as *mut ArrowArray as *mut FFI_ArrowArray);
+        let schema =
+            FFI_ArrowSchema::from_raw(&mut schema as *mut ArrowSchema as *mut FFI_ArrowSchema);
+        from_ffi(array, &schema)
+    }
+    .map_err(compute_error)?;
+    Ok(make_array(data))
+}
+
+/// Returns the Arrow field of a series exported at the compat level.
+fn arrow_field(series: &Series, compat_level: CompatLevel) -> PolarsResult<Field> {
+    let field = ArrowField::new(
+        series.name().clone(),
+        series.dtype().to_arrow(compat_level),
+        true,
+    );
+    let mut schema = polars_arrow::ffi::export_field_to_c(&field);
+    // SAFETY: the struct is exported by Polars through the C data interface
+    let schema = unsafe {
+        FFI_ArrowSchema::from_raw(&mut schema as *mut ArrowSchema as *mut FFI_ArrowSchema)
+    };
+    Field::try_from(&schema).map_err(compute_error)
+}
+
+/// Convert an Arrow array into a series through the C data interface.
+pub fn series_from_arrow(name: &str, array: &ArrayRef) -> PolarsResult<Series> {
+    Series::from_arrow(name.into(), array_to_polars(array)?)
+}
+
+/// Convert an Arrow array into a Polars array through the C data interface.
+fn array_to_polars(array: &ArrayRef) -> PolarsResult<Box<dyn polars_arrow::array::Array>> {
+    let (ffi_array, ffi_schema) = to_ffi(&array.to_data()).map_err(compute_error)?;
+    let mut array = ArrowArray::empty();
+    let mut schema = ArrowSchema::empty();
+    // SAFETY: the structs are exported by arrow-rs through the C data interface,
+    // and are moved into the empty structs of Polars, which release them once dropped
+    unsafe {
+        std::ptr::write(
+            &mut array as *mut ArrowArray as *mut FFI_ArrowArray,
+            ffi_array,
+        );
+        std::ptr::write(
+            &mut schema as *mut ArrowSchema as *mut FFI_ArrowSchema,
+            ffi_schema,
+        );
+        let field = polars_arrow::ffi::import_field_from_c(&schema)?;
+        polars_arrow::ffi::import_array_from_c(array, field.dtype)
+    }
+}
+
+fn compute_error(e: impl std::fmt::Display) -> PolarsError {
+    PolarsError::ComputeError(e.to_string().into())
+}
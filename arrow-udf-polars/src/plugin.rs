@@ -0,0 +1,78 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A Polars expression plugin calling the functions of a runtime.
+//!
+//! Linking this module into a `cdylib` exports the expression `call_udf`, which calls the function
+//! named by its `function` keyword argument in the runtime set by [`set_runtime`].
+//! The runtime is typically set when the Python module of the plugin is initialized.
+
+use std::sync::{Arc, OnceLock};
+
+use arrow_udf_core::UdfRuntime;
+use polars_core::prelude::*;
+use pyo3_polars::derive::polars_expr;
+use serde::Deserialize;
+
+/// The runtime of the plugin.
+static RUNTIME: OnceLock<Arc<dyn UdfRuntime>> = OnceLock::new();
+
+/// Set the runtime whose functions are called by the plugin.
+///
+/// Returns the runtime back if it has been set before.
+pub fn set_runtime(runtime: Arc<dyn UdfRuntime>) -> Result<(), Arc<dyn UdfRuntime>> {
+    RUNTIME.set(runtime)
+}
+
+/// Returns the runtime of the plugin.
+fn runtime() -> PolarsResult<&'static dyn UdfRuntime> {
+    match RUNTIME.get() {
+        Some(runtime) => Ok(runtime.as_ref()),
+        None => polars_bail!(ComputeError: "the runtime of the plugin is not set"),
+    }
+}
+
+/// The keyword arguments of `call_udf`.
+#[derive(Debug, Deserialize)]
+pub struct CallKwargs {
+    /// The name of the function to call.
+    pub function: String,
+}
+
+/// Returns the output field of `call_udf`, named after the first input.
+fn call_udf_field(inputs: &[Field], kwargs: CallKwargs) -> PolarsResult<Field> {
+    let function = (runtime()?.functions().into_iter())
+        .find(|f| f.name == kwargs.function)
+        .ok_or_else(|| polars_err!(ComputeError: "function not found: {}", kwargs.function))?;
+    let Some(field) = function.return_field else {
+        polars_bail!(ComputeError: "return type of function {} is unknown", kwargs.function);
+    };
+    // convert the type through an empty array
+    let empty = arrow_array::new_empty_array(field.data_type());
+    let dtype = crate::series_from_arrow(field.name(), &empty)?
+        .dtype()
+        .clone();
+    let name = (inputs.first()).map_or(field.name().as_str().into(), |f| f.name().clone());
+    Ok(Field::new(name, dtype))
+}
+
+/// Call the function named by the keyword arguments with the series, see [`call_series`](crate::call_series).
+#[polars_expr(output_type_func_with_kwargs = call_udf_field)]
+fn call_udf(inputs: &[Series], kwargs: CallKwargs) -> PolarsResult<Series> {
+    let output = crate::call_series(runtime()?, &kwargs.function, inputs)?;
+    Ok(match inputs.first() {
+        Some(input) => output.with_name(input.name().clone()),
+        None => output,
+    })
+}
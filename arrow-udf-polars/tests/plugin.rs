@@ -0,0 +1,142 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the expression plugin, called through its exported symbols in the same way as Polars.
+
+#![cfg(feature = "plugin")]
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+use arrow_udf_js::{CallMode, Runtime};
+use arrow_udf_polars::plugin::*;
+use polars_arrow::ffi::{export_field_to_c, import_field_from_c, ArrowSchema};
+use polars_core::prelude::*;
+use polars_ffi::version_0::{export_series, import_series, CallerContext, SeriesExport};
+
+fn set_gcd_runtime() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            arrow_schema::DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+    _ = set_runtime(Arc::new(runtime));
+}
+
+fn kwargs(function: &str) -> Vec<u8> {
+    let kwargs = HashMap::from([("function", function)]);
+    serde_pickle::to_vec(&kwargs, Default::default()).unwrap()
+}
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(_polars_plugin_get_last_error_message()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Call `call_udf` with the series.
+fn call_udf(function: &str, inputs: &[Series]) -> Option<Series> {
+    // the inputs are taken over by the plugin
+    let mut inputs = (inputs.iter())
+        .map(|s| ManuallyDrop::new(export_series(s)))
+        .collect::<Vec<_>>();
+    let kwargs = kwargs(function);
+    let mut output = SeriesExport::empty();
+    let mut context = CallerContext::default();
+    unsafe {
+        _polars_plugin_call_udf(
+            inputs.as_mut_ptr() as *mut SeriesExport,
+            inputs.len(),
+            kwargs.as_ptr(),
+            kwargs.len(),
+            &mut output,
+            &mut context,
+        );
+    }
+    match output.is_null() {
+        true => None,
+        false => Some(unsafe { import_series(output) }.unwrap()),
+    }
+}
+
+/// Get the output field of `call_udf` with the input fields.
+fn call_udf_field(function: &str, inputs: &[Field]) -> Option<Field> {
+    let mut inputs = (inputs.iter())
+        .map(|f| export_field_to_c(&f.to_arrow(CompatLevel::newest())))
+        .collect::<Vec<_>>();
+    let kwargs = kwargs(function);
+    let mut output = ArrowSchema::empty();
+    unsafe {
+        _polars_plugin_field_call_udf(
+            inputs.as_mut_ptr(),
+            inputs.len(),
+            &mut output,
+            kwargs.as_ptr(),
+            kwargs.len(),
+        );
+    }
+    if output.is_null() {
+        return None;
+    }
+    Some(Field::from(
+        &unsafe { import_field_from_c(&output) }.unwrap(),
+    ))
+}
+
+#[test]
+fn test_plugin() {
+    set_gcd_runtime();
+
+    let x = Field::new("x".into(), DataType::Int32);
+    let y = Field::new("y".into(), DataType::Int32);
+    let field = call_udf_field("gcd", &[x, y]).unwrap();
+    assert_eq!(field, Field::new("x".into(), DataType::Int32));
+
+    let x = Series::new("x".into(), &[Some(25), Some(12), None]);
+    let y = Series::new("y".into(), &[Some(15), Some(9), Some(1)]);
+    let output = call_udf("gcd", &[x, y]).unwrap();
+    assert_eq!(output.name().as_str(), "x");
+    assert_eq!(
+        output.i32().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some(5), Some(3), None]
+    );
+}
+
+#[test]
+fn test_plugin_error() {
+    set_gcd_runtime();
+
+    let x = Field::new("x".into(), DataType::Int32);
+    assert!(call_udf_field("unknown", &[x]).is_none());
+    assert!(last_error().contains("function not found: unknown"));
+
+    let x = Series::new("x".into(), &[1]);
+    assert!(call_udf("unknown", &[x]).is_none());
+    assert!(last_error().contains("function not found"));
+}
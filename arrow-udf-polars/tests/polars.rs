@@ -0,0 +1,197 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Mutex;
+
+use arrow_array::RecordBatch;
+use arrow_udf_core::{Capabilities, RecordBatchIter, UdfRuntime};
+use arrow_udf_js::{CallMode, Runtime};
+use polars_core::prelude::*;
+
+#[test]
+fn test_gcd() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            arrow_schema::DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+
+    let x = Series::new("x".into(), &[Some(25), Some(12), None]);
+    let y = Series::new("y".into(), &[Some(15), Some(9), Some(1)]);
+    let output = arrow_udf_polars::call_series(&runtime, "gcd", &[x, y]).unwrap();
+    assert_eq!(output.name().as_str(), "gcd");
+    assert_eq!(
+        output.i32().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some(5), Some(3), None]
+    );
+}
+
+#[test]
+fn test_string() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "upper",
+            arrow_schema::DataType::LargeUtf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function upper(s) {
+                return s.toUpperCase();
+            }
+            "#,
+        )
+        .unwrap();
+
+    // the function is called on each chunk
+    let mut s = Series::new("s".into(), &[Some("hello"), None]);
+    s.append(&Series::new("s".into(), &["world"])).unwrap();
+    assert_eq!(s.n_chunks(), 2);
+
+    let output = arrow_udf_polars::call_series(&runtime, "upper", &[s]).unwrap();
+    assert_eq!(output.n_chunks(), 2);
+    assert_eq!(
+        output.str().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some("HELLO"), None, Some("WORLD")]
+    );
+}
+
+/// A runtime returning the first argument, which records the types of arguments.
+#[derive(Default)]
+struct Identity {
+    capabilities: Capabilities,
+    arg_types: Mutex<Vec<arrow_schema::DataType>>,
+}
+
+impl UdfRuntime for Identity {
+    fn add_function(
+        &mut self,
+        _name: &str,
+        _return_field: arrow_schema::Field,
+        _mode: CallMode,
+        _code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        unimplemented!()
+    }
+
+    fn call(&self, _name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        let schema = input.schema();
+        (self.arg_types.lock().unwrap())
+            .extend(schema.fields().iter().map(|f| f.data_type().clone()));
+        Ok(input.project(&[0])?)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        _name: &'a str,
+        _input: &'a RecordBatch,
+        _chunk_size: usize,
+    ) -> arrow_udf_core::Result<RecordBatchIter<'a>> {
+        unimplemented!()
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+}
+
+#[test]
+fn test_string_views() {
+    // strings are shared as views with runtimes supporting them
+    let runtime = Identity::default();
+    let s = Series::new(
+        "s".into(),
+        &[Some("hello"), None, Some("a long string of the buffer")],
+    );
+    let output =
+        arrow_udf_polars::call_series(&runtime, "identity", std::slice::from_ref(&s)).unwrap();
+    assert_eq!(
+        *runtime.arg_types.lock().unwrap(),
+        [arrow_schema::DataType::Utf8View]
+    );
+    let buffer = |s: &Series| {
+        let array = s.str().unwrap().downcast_iter().next().unwrap();
+        array.data_buffers()[0].as_ptr()
+    };
+    assert_eq!(buffer(&output), buffer(&s));
+
+    // and copied into large strings otherwise
+    let runtime = Identity {
+        capabilities: Capabilities::new().with_data_types(["LargeUtf8"]),
+        ..Default::default()
+    };
+    let output = arrow_udf_polars::call_series(&runtime, "identity", &[s]).unwrap();
+    assert_eq!(
+        *runtime.arg_types.lock().unwrap(),
+        [arrow_schema::DataType::LargeUtf8]
+    );
+    assert_eq!(
+        output.str().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some("hello"), None, Some("a long string of the buffer")]
+    );
+}
+
+#[test]
+fn test_unaligned_chunks() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "add",
+            arrow_schema::DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function add(a, b) {
+                return a + b;
+            }
+            "#,
+        )
+        .unwrap();
+
+    // series split at different rows are rechunked before calling
+    let mut x = Series::new("x".into(), &[1]);
+    x.append(&Series::new("x".into(), &[2, 3])).unwrap();
+    let mut y = Series::new("y".into(), &[10, 20]);
+    y.append(&Series::new("y".into(), &[30])).unwrap();
+
+    let output = arrow_udf_polars::call_series(&runtime, "add", &[x, y]).unwrap();
+    assert_eq!(output.n_chunks(), 1);
+    assert_eq!(
+        output.i32().unwrap().into_iter().collect::<Vec<_>>(),
+        [Some(11), Some(22), Some(33)]
+    );
+}
+
+#[test]
+fn test_error() {
+    let runtime = Runtime::new().unwrap();
+    let x = Series::new("x".into(), &[1]);
+    let err = arrow_udf_polars::call_series(&runtime, "unknown", &[x]).unwrap_err();
+    assert!(err.to_string().contains("function not found"), "{err}");
+}
@@ -0,0 +1,72 @@
+# Copyright 2024 RisingWave Labs
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+import pyarrow as pa
+import pytest
+from arrow_udf_runtime import Runtime
+
+GCD = """
+export function gcd(a, b) {
+    while (b != 0) {
+        let t = b;
+        b = a % b;
+        a = t;
+    }
+    return a;
+}
+"""
+
+RANGE = """
+export function* range(n) {
+    for (let i = 0; i < n; i++) {
+        yield i;
+    }
+}
+"""
+
+
+def test_call():
+    runtime = Runtime.javascript()
+    runtime.add_function("gcd", pa.int32(), GCD, null_on_null_input=True)
+    assert runtime.list_functions() == ["gcd"]
+
+    input = pa.record_batch(
+        [pa.array([25, None], pa.int32()), pa.array([15, 1], pa.int32())],
+        names=["x", "y"],
+    )
+    output = runtime.call("gcd", input)
+    assert output.schema == pa.schema([pa.field("gcd", pa.int32())])
+    assert output.column(0).to_pylist() == [5, None]
+
+
+def test_call_table_function():
+    runtime = Runtime.javascript()
+    runtime.add_function("range", pa.field("value", pa.int32()), RANGE)
+
+    input = pa.record_batch([pa.array([1, 0, 2], pa.int32())], names=["n"])
+    outputs = runtime.call_table_function("range", input, chunk_size=2)
+    assert len(outputs) == 2
+    output = pa.Table.from_batches(outputs)
+    assert output.column("row").to_pylist() == [0, 2, 2]
+    assert output.column("value").to_pylist() == [0, 0, 1]
+
+
+def test_errors():
+    runtime = Runtime.javascript()
+    with pytest.raises(TypeError):
+        runtime.add_function("gcd", "int32", GCD)
+    with pytest.raises(ValueError, match="function not found"):
+        runtime.call("gcd", pa.record_batch([pa.array([1])], names=["x"]))
+    with pytest.raises(ValueError):
+        Runtime.wasm(b"not a wasm binary")
@@ -0,0 +1,177 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::ffi::c_void;
+
+use arrow_array::ffi::{from_ffi, to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::{Array, RecordBatch, StructArray};
+use arrow_schema::{DataType, Field};
+use arrow_udf_core::{CallMode, UdfRuntime};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
+
+/// A UDF runtime that calls functions on `pyarrow.RecordBatch`.
+#[pyclass(module = "arrow_udf_runtime")]
+pub struct Runtime {
+    runtime: Box<dyn UdfRuntime>,
+}
+
+#[pymethods]
+impl Runtime {
+    /// Create a JavaScript runtime.
+    #[cfg(feature = "javascript")]
+    #[staticmethod]
+    fn javascript() -> PyResult<Self> {
+        let runtime = arrow_udf_js::Runtime::new().map_err(to_py_err)?;
+        Ok(Self {
+            runtime: Box::new(runtime),
+        })
+    }
+
+    /// Create a WebAssembly runtime from a binary.
+    #[cfg(feature = "wasm")]
+    #[staticmethod]
+    fn wasm(binary: &Bound<'_, PyBytes>) -> PyResult<Self> {
+        let runtime = arrow_udf_wasm::Runtime::new(binary.as_bytes()).map_err(to_py_err)?;
+        Ok(Self {
+            runtime: Box::new(runtime),
+        })
+    }
+
+    /// Add a function.
+    ///
+    /// `return_type` is a `pyarrow.DataType` or `pyarrow.Field`.
+    /// If `null_on_null_input` is true, the function is not called on rows with null arguments,
+    /// and returns null instead.
+    #[pyo3(signature = (name, return_type, code, null_on_null_input = false))]
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_type: &Bound<'_, PyAny>,
+        code: &str,
+        null_on_null_input: bool,
+    ) -> PyResult<()> {
+        let return_field = field_from_pyarrow(name, return_type)?;
+        let mode = match null_on_null_input {
+            true => CallMode::ReturnNullOnNullInput,
+            false => CallMode::CalledOnNullInput,
+        };
+        (self.runtime)
+            .add_function(name, return_field, mode, code)
+            .map_err(to_py_err)
+    }
+
+    /// Call a scalar function, returning a `pyarrow.RecordBatch`.
+    fn call(&self, py: Python<'_>, name: &str, input: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let input = batch_from_pyarrow(input)?;
+        let runtime = &self.runtime;
+        let output = py
+            .allow_threads(|| runtime.call(name, &input))
+            .map_err(to_py_err)?;
+        batch_to_pyarrow(py, output)
+    }
+
+    /// Call a table function, returning a list of `pyarrow.RecordBatch`.
+    #[pyo3(signature = (name, input, chunk_size = 1024))]
+    fn call_table_function(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        input: &Bound<'_, PyAny>,
+        chunk_size: usize,
+    ) -> PyResult<Py<PyList>> {
+        let input = batch_from_pyarrow(input)?;
+        let runtime = &self.runtime;
+        let outputs = py
+            .allow_threads(|| {
+                runtime
+                    .call_table_function(name, &input, chunk_size)?
+                    .collect::<arrow_udf_core::Result<Vec<_>>>()
+            })
+            .map_err(to_py_err)?;
+        let outputs = outputs
+            .into_iter()
+            .map(|batch| batch_to_pyarrow(py, batch))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyList::new_bound(py, outputs).unbind())
+    }
+
+    /// Returns the names of all functions.
+    fn list_functions(&self) -> Vec<String> {
+        self.runtime.list_functions()
+    }
+}
+
+/// Convert a `pyarrow.Field` or `pyarrow.DataType` to a field.
+fn field_from_pyarrow(name: &str, value: &Bound<'_, PyAny>) -> PyResult<Field> {
+    let pyarrow = value.py().import_bound("pyarrow")?;
+    let is_field = value.is_instance(&pyarrow.getattr("Field")?)?;
+    if !is_field && !value.is_instance(&pyarrow.getattr("DataType")?)? {
+        return Err(PyTypeError::new_err(
+            "expected pyarrow.Field or pyarrow.DataType",
+        ));
+    }
+    let mut schema = FFI_ArrowSchema::empty();
+    value.call_method1("_export_to_c", (addr(&mut schema),))?;
+    if is_field {
+        Field::try_from(&schema).map_err(to_py_err)
+    } else {
+        let data_type = DataType::try_from(&schema).map_err(to_py_err)?;
+        Ok(Field::new(name, data_type, true))
+    }
+}
+
+/// Convert a `pyarrow.RecordBatch` to a record batch.
+fn batch_from_pyarrow(value: &Bound<'_, PyAny>) -> PyResult<RecordBatch> {
+    let pyarrow = value.py().import_bound("pyarrow")?;
+    if !value.is_instance(&pyarrow.getattr("RecordBatch")?)? {
+        return Err(PyTypeError::new_err("expected pyarrow.RecordBatch"));
+    }
+    let mut array = FFI_ArrowArray::empty();
+    let mut schema = FFI_ArrowSchema::empty();
+    value.call_method1("_export_to_c", (addr(&mut array), addr(&mut schema)))?;
+    // a record batch is exported as a struct array
+    let data = unsafe { from_ffi(array, &schema) }.map_err(to_py_err)?;
+    Ok(RecordBatch::from(StructArray::from(data)))
+}
+
+/// Convert a record batch to a `pyarrow.RecordBatch`.
+fn batch_to_pyarrow(py: Python<'_>, batch: RecordBatch) -> PyResult<PyObject> {
+    let data = StructArray::from(batch).into_data();
+    let (mut array, mut schema) = to_ffi(&data).map_err(to_py_err)?;
+    // pyarrow moves the structs, so that dropping them afterwards is a no-op
+    let batch = py
+        .import_bound("pyarrow")?
+        .getattr("RecordBatch")?
+        .call_method1("_import_from_c", (addr(&mut array), addr(&mut schema)))?;
+    Ok(batch.unbind())
+}
+
+/// Returns the address of a value to be passed to pyarrow.
+fn addr<T>(value: &mut T) -> usize {
+    value as *mut T as *mut c_void as usize
+}
+
+fn to_py_err(e: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(format!("{e:#}"))
+}
+
+/// Run JavaScript and WebAssembly UDFs on pyarrow record batches.
+#[pymodule]
+fn arrow_udf_runtime(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Runtime>()
+}
@@ -0,0 +1,236 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Accounting of the memory allocated by each sub-interpreter.
+//!
+//! Python has no per-interpreter memory limit, so the `PYMEM_DOMAIN_MEM` and `PYMEM_DOMAIN_OBJ`
+//! allocators are wrapped before Python is initialized, if the first runtime enables memory accounting. Each block is prefixed with a header
+//! recording its size and the counter of the sub-interpreter running on the thread that
+//! allocated it, and allocations over the limit return null, which raises `MemoryError`.
+
+use std::cell::Cell;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use pyo3::ffi::*;
+
+/// The size of the header before each block, keeping the alignment of the wrapped allocator.
+const HEADER: usize = 16;
+
+/// The memory allocated by a sub-interpreter.
+#[derive(Debug)]
+pub struct MemoryCounter {
+    used: AtomicUsize,
+    /// `usize::MAX` if unlimited.
+    limit: AtomicUsize,
+}
+
+impl MemoryCounter {
+    /// Create a counter for a new sub-interpreter, wrapping the allocators on the first call.
+    ///
+    /// Fails if the allocators were not wrapped before Python was initialized.
+    /// Counters are leaked, since blocks can be freed after the sub-interpreter is destroyed.
+    pub fn new() -> anyhow::Result<&'static Self> {
+        if !install() {
+            anyhow::bail!("memory accounting must be enabled in the first runtime, before Python is initialized");
+        }
+        Ok(Box::leak(Box::new(Self {
+            used: AtomicUsize::new(0),
+            limit: AtomicUsize::new(usize::MAX),
+        })))
+    }
+
+    /// Returns the number of bytes allocated.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the maximum number of bytes that can be allocated, or `None` if unlimited.
+    pub fn limit(&self) -> Option<usize> {
+        Some(self.limit.load(Ordering::Relaxed)).filter(|&limit| limit != usize::MAX)
+    }
+
+    /// Set the maximum number of bytes that can be allocated.
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit
+            .store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// Account the allocation of `size` bytes, returning false if it exceeds the limit.
+    fn reserve(&self, size: usize) -> bool {
+        let used = self.used.fetch_add(size, Ordering::Relaxed);
+        if used.saturating_add(size) > self.limit.load(Ordering::Relaxed) {
+            self.used.fetch_sub(size, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    fn release(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+thread_local! {
+    /// The counter of the sub-interpreter running on this thread.
+    static CURRENT: Cell<*const MemoryCounter> = const { Cell::new(ptr::null()) };
+}
+
+/// Account allocations of this thread to the counter until the returned guard is dropped.
+pub fn enter(counter: Option<&'static MemoryCounter>) -> impl Drop {
+    struct Guard(*const MemoryCounter);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CURRENT.set(self.0);
+        }
+    }
+    let counter = counter.map_or(ptr::null(), |c| c as *const _);
+    Guard(CURRENT.replace(counter))
+}
+
+fn current() -> *const MemoryCounter {
+    CURRENT.try_with(|c| c.get()).unwrap_or(ptr::null())
+}
+
+/// Wrap the allocators once, returning false if Python has already been initialized.
+///
+/// Blocks allocated before wrapping have no header, so the allocators can't be replaced afterwards.
+fn install() -> bool {
+    static INSTALLED: OnceLock<bool> = OnceLock::new();
+    *INSTALLED.get_or_init(|| unsafe {
+        if Py_IsInitialized() != 0 {
+            return false;
+        }
+        for domain in [
+            PyMemAllocatorDomain::PYMEM_DOMAIN_MEM,
+            PyMemAllocatorDomain::PYMEM_DOMAIN_OBJ,
+        ] {
+            let mut inner = PyMemAllocatorEx {
+                ctx: ptr::null_mut(),
+                malloc: None,
+                calloc: None,
+                realloc: None,
+                free: None,
+            };
+            PyMem_GetAllocator(domain, &mut inner);
+            let mut wrapper = PyMemAllocatorEx {
+                ctx: Box::into_raw(Box::new(inner)) as *mut c_void,
+                malloc: Some(malloc),
+                calloc: Some(calloc),
+                realloc: Some(realloc),
+                free: Some(free),
+            };
+            PyMem_SetAllocator(domain, &mut wrapper);
+        }
+        true
+    })
+}
+
+/// Write the header of a block allocated by the wrapped allocator and return the user pointer.
+unsafe fn init(block: *mut c_void, size: usize, counter: *const MemoryCounter) -> *mut c_void {
+    let header = block as *mut usize;
+    header.write(size);
+    (header.add(1) as *mut *const MemoryCounter).write(counter);
+    block.byte_add(HEADER)
+}
+
+/// Returns the block, size and counter of a user pointer.
+unsafe fn header(ptr: *mut c_void) -> (*mut c_void, usize, *const MemoryCounter) {
+    let block = ptr.byte_sub(HEADER);
+    let header = block as *const usize;
+    let counter = (header.add(1) as *const *const MemoryCounter).read();
+    (block, header.read(), counter)
+}
+
+unsafe fn reserve(counter: *const MemoryCounter, size: usize) -> bool {
+    counter.is_null() || (*counter).reserve(size)
+}
+
+unsafe fn release(counter: *const MemoryCounter, size: usize) {
+    if !counter.is_null() {
+        (*counter).release(size);
+    }
+}
+
+extern "C" fn malloc(ctx: *mut c_void, size: usize) -> *mut c_void {
+    unsafe {
+        let inner = &*(ctx as *const PyMemAllocatorEx);
+        let counter = current();
+        if size > isize::MAX as usize - HEADER || !reserve(counter, size) {
+            return ptr::null_mut();
+        }
+        let block = inner.malloc.unwrap()(inner.ctx, size + HEADER);
+        if block.is_null() {
+            release(counter, size);
+            return ptr::null_mut();
+        }
+        init(block, size, counter)
+    }
+}
+
+extern "C" fn calloc(ctx: *mut c_void, nelem: usize, elsize: usize) -> *mut c_void {
+    unsafe {
+        let inner = &*(ctx as *const PyMemAllocatorEx);
+        let counter = current();
+        let Some(size) = nelem.checked_mul(elsize) else {
+            return ptr::null_mut();
+        };
+        if size > isize::MAX as usize - HEADER || !reserve(counter, size) {
+            return ptr::null_mut();
+        }
+        let block = inner.calloc.unwrap()(inner.ctx, 1, size + HEADER);
+        if block.is_null() {
+            release(counter, size);
+            return ptr::null_mut();
+        }
+        init(block, size, counter)
+    }
+}
+
+extern "C" fn realloc(ctx: *mut c_void, ptr: *mut c_void, new_size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(ctx, new_size);
+    }
+    unsafe {
+        let inner = &*(ctx as *const PyMemAllocatorEx);
+        // the block stays accounted to the sub-interpreter that allocated it
+        let (block, size, counter) = header(ptr);
+        if new_size > isize::MAX as usize - HEADER
+            || (new_size > size && !reserve(counter, new_size - size))
+        {
+            return ptr::null_mut();
+        }
+        let block = inner.realloc.unwrap()(inner.ctx, block, new_size + HEADER);
+        if block.is_null() {
+            release(counter, new_size.saturating_sub(size));
+            return ptr::null_mut();
+        }
+        release(counter, size.saturating_sub(new_size));
+        init(block, new_size, counter)
+    }
+}
+
+extern "C" fn free(ctx: *mut c_void, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    unsafe {
+        let inner = &*(ctx as *const PyMemAllocatorEx);
+        let (block, size, counter) = header(ptr);
+        release(counter, size);
+        inner.free.unwrap()(inner.ctx, block);
+    }
+}
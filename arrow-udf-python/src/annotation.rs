@@ -0,0 +1,121 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Inference of Arrow types from Python type hints.
+
+use arrow_schema::{DataType, Field, TimeUnit};
+
+/// Returns the return field of a function from the source of its return annotation,
+/// e.g. `int`, `Optional[str]` or `list[float]`.
+///
+/// The yielded type of generators, e.g. `Iterator[int]`, is the return type of table functions.
+/// Returns `None` if the annotation is not supported.
+pub fn return_field(name: &str, annotation: &str) -> Option<Field> {
+    let annotation = annotation.trim();
+    if let Some((head, args)) = split_generic(annotation) {
+        if matches!(head, "Iterator" | "Iterable" | "Generator") {
+            return annotation_field(name, args.first()?);
+        }
+    }
+    annotation_field(name, annotation)
+}
+
+/// Returns the field of a type annotation.
+fn annotation_field(name: &str, annotation: &str) -> Option<Field> {
+    let annotation = annotation.trim().trim_matches(|c| c == '\'' || c == '"');
+    // `X | None`
+    let members = split_top_level(annotation, '|');
+    if members.len() > 1 {
+        return not_none(name, &members);
+    }
+    if let Some((head, args)) = split_generic(annotation) {
+        return match (head, args.as_slice()) {
+            ("Optional" | "Annotated", [arg, ..]) => annotation_field(name, arg),
+            ("Union", _) => not_none(name, &args),
+            ("list" | "List" | "Sequence", [item]) => {
+                let item = annotation_field("item", item)?;
+                Some(Field::new_list(name, item, true))
+            }
+            ("dict" | "Dict" | "Mapping", [key, value]) => {
+                let key = annotation_field("key", key)?.with_nullable(false);
+                let value = annotation_field("value", value)?;
+                Some(Field::new_map(name, "entries", key, value, false, true))
+            }
+            _ => None,
+        };
+    }
+    let data_type = match strip_module(annotation) {
+        "bool" => DataType::Boolean,
+        "int" => DataType::Int64,
+        "float" => DataType::Float64,
+        "str" => DataType::Utf8,
+        "bytes" => DataType::Binary,
+        "date" => DataType::Date32,
+        "time" => DataType::Time64(TimeUnit::Microsecond),
+        "datetime" => DataType::Timestamp(TimeUnit::Microsecond, None),
+        "timedelta" => DataType::Duration(TimeUnit::Microsecond),
+        "Decimal" => {
+            return Some(Field::new(name, DataType::Utf8, true).with_metadata(
+                [("ARROW:extension:name".into(), "arrowudf.decimal".into())].into(),
+            ))
+        }
+        _ => return None,
+    };
+    Some(Field::new(name, data_type, true))
+}
+
+/// Returns the field of the only member of a union that is not `None`.
+fn not_none(name: &str, members: &[&str]) -> Option<Field> {
+    match members
+        .iter()
+        .filter(|m| !matches!(m.trim(), "None" | "NoneType"))
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [member] => annotation_field(name, member),
+        _ => None,
+    }
+}
+
+/// Splits a generic type like `list[int]` into its name without the module and its arguments.
+fn split_generic(annotation: &str) -> Option<(&str, Vec<&str>)> {
+    let (head, rest) = annotation.split_once('[')?;
+    let args = rest.strip_suffix(']')?;
+    Some((strip_module(head.trim()), split_top_level(args, ',')))
+}
+
+/// Removes the module of a name, e.g. `typing.` of `typing.Optional`.
+fn strip_module(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+/// Splits at the separator outside brackets.
+fn split_top_level(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
@@ -0,0 +1,122 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An asynchronous runtime running calls on the blocking thread pool of tokio.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use arrow_array::RecordBatch;
+use arrow_udf_core::{CancelToken, Result};
+
+use crate::Runtime;
+
+/// A [`Runtime`] whose calls run on the blocking thread pool of tokio.
+///
+/// A call holds the GIL of the interpreter until it returns, which would block an executor thread
+/// of an async engine. Calls of `AsyncRuntime` run in [`tokio::task::spawn_blocking`] instead,
+/// and return futures resolved with their results.
+///
+/// Dropping a future before it is resolved cancels the running call.
+///
+/// # Example
+///
+/// ```
+/// # use arrow_udf_python::{AsyncRuntime, CallMode, Runtime};
+/// # use arrow_array::{Int32Array, RecordBatch};
+/// # use arrow_schema::{DataType, Field, Schema};
+/// # use std::sync::Arc;
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let mut runtime = Runtime::new().unwrap();
+/// runtime
+///     .add_function(
+///         "square",
+///         DataType::Int32,
+///         CallMode::ReturnNullOnNullInput,
+///         "def square(x):\n    return x * x",
+///     )
+///     .unwrap();
+/// let runtime = AsyncRuntime::new(runtime);
+///
+/// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+/// let arg0 = Int32Array::from(vec![1, 2, 3]);
+/// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+/// let output = runtime.call("square", &input).await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AsyncRuntime {
+    runtime: Arc<Runtime>,
+}
+
+impl AsyncRuntime {
+    /// Create an asynchronous runtime from a runtime with its functions added.
+    pub fn new(runtime: Runtime) -> Self {
+        Self {
+            runtime: Arc::new(runtime),
+        }
+    }
+
+    /// Returns the inner runtime.
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// Call a scalar function on the blocking thread pool.
+    ///
+    /// This must be called in the context of a tokio runtime.
+    pub async fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        self.call_cancellable(name, input, &CancelToken::new())
+            .await
+    }
+
+    /// Call a scalar function on the blocking thread pool, interrupting it once the token is cancelled.
+    ///
+    /// See [`Runtime::call_cancellable`].
+    pub async fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        let runtime = self.runtime.clone();
+        let name = name.to_string();
+        let input = input.clone();
+        // the call is cancelled by the given token, or once the future is dropped
+        let call_token = CancelToken::new();
+        let _forward = token.on_cancel({
+            let call_token = call_token.clone();
+            move || call_token.cancel()
+        });
+        let _cancel_on_drop = CancelOnDrop(call_token.clone());
+        let task = tokio::task::spawn_blocking(move || {
+            runtime.call_cancellable(&name, &input, &call_token)
+        });
+        match task.await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(anyhow!("failed to run the call: {e}").into()),
+        }
+    }
+}
+
+/// Cancels the token when dropped.
+struct CancelOnDrop(CancelToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
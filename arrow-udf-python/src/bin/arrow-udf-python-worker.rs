@@ -0,0 +1,46 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A worker process for `arrow_udf_python::process::ProcessRuntime`.
+//!
+//! Usage: `arrow-udf-python-worker [--sandboxed] [--allow-module MODULE]... [--path DIR]... [SANDBOX OPTIONS]`
+//!
+//! See `arrow_udf_python::sandbox::Sandbox::to_args` for the sandbox options.
+
+use anyhow::bail;
+use arrow_udf_python::sandbox::Sandbox;
+
+fn main() -> anyhow::Result<()> {
+    let (sandbox, args) = Sandbox::from_args(std::env::args_os().skip(1))?;
+    let mut builder = arrow_udf_python::Runtime::builder();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--sandboxed") => builder = builder.sandboxed(true),
+            Some("--allow-module") => match args.next().as_ref().and_then(|m| m.to_str()) {
+                Some(module) => builder = builder.allow_module(module),
+                None => bail!("--allow-module requires a module name"),
+            },
+            Some("--path") => match args.next() {
+                Some(path) => builder = builder.append_path(path),
+                None => bail!("--path requires a directory"),
+            },
+            _ => bail!("unknown argument: {}", arg.to_string_lossy()),
+        }
+    }
+    sandbox.apply_limits()?;
+    let runtime = builder.build()?;
+    sandbox.apply_restrictions()?;
+    arrow_udf_python::process::run_worker(runtime)
+}
@@ -0,0 +1,108 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion between primitive arrays and Python objects supporting the buffer protocol.
+
+use std::sync::Arc;
+
+use arrow_array::types::*;
+use arrow_array::{Array, ArrayRef, PrimitiveArray};
+use arrow_buffer::NullBuffer;
+use arrow_schema::DataType;
+use arrow_udf_core::Error;
+use pyo3::buffer::PyBuffer;
+use pyo3::types::{PyAnyMethods, PyBytes, PyMemoryView};
+use pyo3::{Bound, PyAny, PyResult, Python};
+
+use crate::interpreter::PyError;
+
+/// Returns the format of `struct` module for the values of a data type,
+/// or `None` if the values can not be passed as a buffer.
+pub fn buffer_format(data_type: &DataType) -> Option<&'static str> {
+    Some(match data_type {
+        DataType::Int8 => "b",
+        DataType::Int16 => "h",
+        DataType::Int32 => "i",
+        DataType::Int64 => "q",
+        DataType::UInt8 => "B",
+        DataType::UInt16 => "H",
+        DataType::UInt32 => "I",
+        DataType::UInt64 => "Q",
+        DataType::Float32 => "f",
+        DataType::Float64 => "d",
+        _ => return None,
+    })
+}
+
+/// Returns a read-only `memoryview` of the values of a primitive array with `num_rows` items.
+///
+/// The value of a scalar array of one row is repeated for each row.
+/// The array must be of a type with [`buffer_format`].
+pub fn get_buffer<'py>(
+    py: Python<'py>,
+    array: &dyn Array,
+    num_rows: usize,
+) -> PyResult<Bound<'py, PyAny>> {
+    let format = buffer_format(array.data_type()).expect("not a buffer type");
+    let width = array.data_type().primitive_width().unwrap();
+    let data = array.to_data();
+    let values = &data.buffers()[0].as_slice()[data.offset() * width..];
+    let bytes = if array.len() == num_rows {
+        PyBytes::new_bound(py, &values[..num_rows * width])
+    } else {
+        PyBytes::new_bound(py, &values[..width].repeat(num_rows))
+    };
+    PyMemoryView::from_bound(&bytes)?.call_method1("cast", (format,))
+}
+
+/// Build a primitive array from an object supporting the buffer protocol, e.g. an `array.array`.
+///
+/// The items of the buffer must be of the same size and kind as the data type,
+/// and there must be `num_rows` items.
+pub fn build_buffer_array(
+    py: Python<'_>,
+    data_type: &DataType,
+    object: &Bound<'_, PyAny>,
+    num_rows: usize,
+    nulls: Option<NullBuffer>,
+) -> Result<ArrayRef, PyError> {
+    macro_rules! build {
+        ($arrow_type:ty) => {{
+            let buffer =
+                PyBuffer::<<$arrow_type as ArrowPrimitiveType>::Native>::get_bound(object)?;
+            let values = buffer.to_vec(py)?;
+            if values.len() != num_rows {
+                return Err(anyhow::Error::from(Error::execution(format!(
+                    "expect {num_rows} values from buffer function, got {}",
+                    values.len()
+                )))
+                .into());
+            }
+            Arc::new(PrimitiveArray::<$arrow_type>::new(values.into(), nulls))
+        }};
+    }
+    Ok(match data_type {
+        DataType::Int8 => build!(Int8Type),
+        DataType::Int16 => build!(Int16Type),
+        DataType::Int32 => build!(Int32Type),
+        DataType::Int64 => build!(Int64Type),
+        DataType::UInt8 => build!(UInt8Type),
+        DataType::UInt16 => build!(UInt16Type),
+        DataType::UInt32 => build!(UInt32Type),
+        DataType::UInt64 => build!(UInt64Type),
+        DataType::Float32 => build!(Float32Type),
+        DataType::Float64 => build!(Float64Type),
+        _ => unreachable!("not a buffer type: {data_type}"),
+    })
+}
@@ -0,0 +1,74 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measurement of the CPU time of a thread from other threads.
+
+use std::time::Duration;
+
+/// The CPU clock of a thread, which can be read from other threads while the thread is alive.
+#[derive(Debug)]
+pub struct ThreadCpuClock {
+    #[cfg(target_os = "linux")]
+    clock: libc::clockid_t,
+    /// The CPU time of the thread when the clock is started.
+    #[cfg(target_os = "linux")]
+    start: Duration,
+}
+
+impl ThreadCpuClock {
+    /// Start measuring the CPU time of the current thread,
+    /// or returns `None` if it can not be measured on this platform.
+    pub fn start() -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let mut clock = 0;
+            if unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock) } != 0 {
+                return None;
+            }
+            let start = read(clock)?;
+            Some(Self { clock, start })
+        }
+        #[cfg(not(target_os = "linux"))]
+        None
+    }
+
+    /// Returns the CPU time used by the thread since the clock is started.
+    ///
+    /// The thread must be alive.
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(target_os = "linux")]
+        {
+            read(self.clock).map_or(Duration::ZERO, |now| now.saturating_sub(self.start))
+        }
+        #[cfg(not(target_os = "linux"))]
+        unreachable!()
+    }
+}
+
+/// Returns whether the CPU time of threads can be measured on this platform.
+pub fn is_supported() -> bool {
+    cfg!(target_os = "linux")
+}
+
+#[cfg(target_os = "linux")]
+fn read(clock: libc::clockid_t) -> Option<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(clock, &mut time) } != 0 {
+        return None;
+    }
+    Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
@@ -0,0 +1,154 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions declared by `@udf` decorators in Python scripts,
+//! in the same way as in the `arrow_udf` package of remote functions.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use arrow_schema::{DataType, Field, IntervalUnit, TimeUnit};
+use arrow_udf_core::FunctionProperties;
+
+/// A function decorated with `@udf`.
+#[derive(Debug)]
+pub struct Decorated {
+    /// The name of the Python function.
+    pub handler: String,
+    /// The name of the function, which is the `name` argument of the decorator if any.
+    pub name: String,
+    /// The return field parsed from the `result_type` argument.
+    pub return_field: Field,
+    /// The call mode and volatility.
+    pub properties: FunctionProperties,
+}
+
+impl Decorated {
+    /// Create from the name of a Python function and the string arguments of its decorator.
+    ///
+    /// Arguments other than `name`, `result_type`, `call_mode` and `volatility` are ignored.
+    pub fn new(handler: String, arguments: &HashMap<String, String>) -> Result<Self> {
+        let name = arguments.get("name").unwrap_or(&handler).clone();
+        let result_type = (arguments.get("result_type"))
+            .ok_or_else(|| anyhow!("the @udf of function {handler} has no result_type"))?;
+        let return_field = field(&name, result_type)
+            .with_context(|| format!("invalid result_type of function {handler}"))?;
+        let mut properties = FunctionProperties::new();
+        if let Some(mode) = arguments.get("call_mode") {
+            properties.mode = mode.parse()?;
+        }
+        if let Some(volatility) = arguments.get("volatility") {
+            properties.volatility = volatility.parse()?;
+        }
+        Ok(Self {
+            handler,
+            name,
+            return_field,
+            properties,
+        })
+    }
+}
+
+/// Returns the field of a SQL type name, e.g. `INT`, `VARCHAR[]` or `STRUCT<a: INT, b: VARCHAR>`.
+///
+/// Names are case-insensitive.
+pub fn field(name: &str, type_name: &str) -> Result<Field> {
+    let type_name = type_name.trim();
+    let upper = type_name.to_ascii_uppercase();
+    if let Some(item) = type_name.strip_suffix("[]") {
+        return Ok(Field::new_list(name, field("item", item)?, true));
+    }
+    if upper.starts_with("STRUCT") {
+        let fields = (type_name[6..].trim().strip_prefix('<'))
+            .and_then(|s| s.strip_suffix('>'))
+            .ok_or_else(|| anyhow!("invalid struct type: {type_name}"))?;
+        let fields = split_top_level(fields)
+            .into_iter()
+            .filter(|f| !f.trim().is_empty())
+            .map(|f| {
+                let (name, type_name) = (f.split_once(':'))
+                    .ok_or_else(|| anyhow!("expect `name: type` in struct type, got {f:?}"))?;
+                field(name.trim(), type_name)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(Field::new_struct(name, fields, true));
+    }
+    if let Some(args) = (upper
+        .strip_prefix("DECIMAL")
+        .or(upper.strip_prefix("NUMERIC")))
+    .and_then(|s| s.trim().strip_prefix('('))
+    .and_then(|s| s.strip_suffix(')'))
+    {
+        let (precision, scale) = args.split_once(',').unwrap_or((args, "0"));
+        let precision = precision.trim().parse::<u8>()?;
+        let scale = scale.trim().parse::<i8>()?;
+        return Ok(Field::new(
+            name,
+            DataType::Decimal128(precision, scale),
+            true,
+        ));
+    }
+    let extension = |extension: &str| {
+        Field::new(name, DataType::Utf8, true)
+            .with_metadata([("ARROW:extension:name".into(), extension.into())].into())
+    };
+    let data_type = match upper.as_str() {
+        "NULL" => DataType::Null,
+        "BOOLEAN" | "BOOL" => DataType::Boolean,
+        "TINYINT" | "INT8" => DataType::Int8,
+        "SMALLINT" | "INT16" => DataType::Int16,
+        "INT" | "INTEGER" | "INT32" => DataType::Int32,
+        "BIGINT" | "INT64" => DataType::Int64,
+        "UINT8" => DataType::UInt8,
+        "UINT16" => DataType::UInt16,
+        "UINT32" => DataType::UInt32,
+        "UINT64" => DataType::UInt64,
+        "FLOAT32" | "REAL" => DataType::Float32,
+        "FLOAT64" | "DOUBLE PRECISION" => DataType::Float64,
+        "DECIMAL" | "NUMERIC" => return Ok(extension("arrowudf.decimal")),
+        "DATE32" | "DATE" => DataType::Date32,
+        "TIME64" | "TIME" | "TIME WITHOUT TIME ZONE" => DataType::Time64(TimeUnit::Microsecond),
+        "TIMESTAMP" | "TIMESTAMP WITHOUT TIME ZONE" => {
+            DataType::Timestamp(TimeUnit::Microsecond, None)
+        }
+        s if s.starts_with("INTERVAL") => DataType::Interval(IntervalUnit::MonthDayNano),
+        "STRING" | "VARCHAR" => DataType::Utf8,
+        "LARGE_STRING" => DataType::LargeUtf8,
+        "JSON" | "JSONB" => return Ok(extension("arrowudf.json")),
+        "BINARY" | "BYTEA" => DataType::Binary,
+        "LARGE_BINARY" => DataType::LargeBinary,
+        _ => bail!("unsupported type: {type_name}"),
+    };
+    Ok(Field::new(name, data_type, true))
+}
+
+/// Split the fields of a struct type by commas outside of nested types.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
@@ -13,34 +13,73 @@
 // limitations under the License.
 
 //! High-level API for Python sub-interpreters.
+//!
+//! Sub-interpreters with their own GIL require Python 3.12 or later.
+//! On earlier versions, functions run in the main interpreter, see `main_interpreter`.
 
+#[cfg(Py_3_12)]
 use std::ffi::CStr;
+#[cfg(Py_3_12)]
+use std::os::raw::c_ulong;
+#[cfg(Py_3_12)]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(Py_3_12)]
+use std::sync::{Arc, Mutex};
 
+#[cfg(Py_3_12)]
+use arrow_udf_core::{CancelToken, Error};
+#[cfg(Py_3_12)]
 #[allow(deprecated)]
 use pyo3::GILPool;
-use pyo3::{ffi::*, prepare_freethreaded_python, PyErr, Python};
+use pyo3::PyErr;
+#[cfg(Py_3_12)]
+use pyo3::{ffi::*, prepare_freethreaded_python, Python};
+
+#[cfg(Py_3_12)]
+use crate::allocator::{self, MemoryCounter};
+
+// not exported by `pyo3::ffi`
+#[cfg(Py_3_12)]
+extern "C" {
+    fn PyThreadState_GetInterpreter(tstate: *mut PyThreadState) -> *mut PyInterpreterState;
+    fn PyThread_get_thread_ident() -> c_ulong;
+}
 
 /// A Python sub-interpreter with its own GIL.
+#[cfg(Py_3_12)]
 #[derive(Debug)]
 pub struct SubInterpreter {
     // XXX: according to the Python C API, the thread state is only valid in the thread that created it.
     //      but we allow the `SubInterpreter` to be sent to other threads for practical reasons.
     state: *mut PyThreadState,
+    /// The identifier of the thread that created the thread state, used to raise async exceptions.
+    thread_id: c_ulong,
+    /// The memory allocated in the sub-interpreter, or `None` if it is not accounted.
+    memory: Option<&'static MemoryCounter>,
+    /// Held while the thread state is in use, since it can't be shared by threads at the same time.
+    lock: Mutex<()>,
 }
 
 // XXX: not sure if this is safe
+#[cfg(Py_3_12)]
 unsafe impl Send for SubInterpreter {}
+#[cfg(Py_3_12)]
 unsafe impl Sync for SubInterpreter {}
 
+#[cfg(Py_3_12)]
 impl SubInterpreter {
-    /// Create a new sub-interpreter.
-    pub fn new() -> Result<Self, PyError> {
+    /// Create a new sub-interpreter, accounting its memory if `memory_accounting` is set.
+    pub fn new(memory_accounting: bool) -> Result<Self, PyError> {
+        // the allocators must be wrapped before Python is initialized
+        let memory = memory_accounting.then(MemoryCounter::new).transpose()?;
         prepare_freethreaded_python();
         // XXX: import the `decimal` module in the main interpreter before creating sub-interpreters.
         //      otherwise it will cause `SIGABRT: pointer being freed was not allocated`
         //      when importing decimal in the second sub-interpreter.
+        //      the same applies to `datetime`, which crashes when imported in sub-interpreters concurrently.
         Python::with_gil(|py| {
             py.import_bound("decimal").unwrap();
+            py.import_bound("datetime").unwrap();
         });
 
         // reference: https://github.com/PyO3/pyo3/blob/9a36b5078989a7c07a5e880aea3c6da205585ee3/examples/sequential/tests/test.rs
@@ -50,6 +89,7 @@ impl SubInterpreter {
             allow_exec: 0,
             allow_threads: 0,
             allow_daemon_threads: 0,
+            // extensions that don't support sub-interpreters, e.g. `numpy` and `pyarrow`, fail to import
             check_multi_interp_extensions: 1,
             gil: PyInterpreterConfig_OWN_GIL,
         };
@@ -67,9 +107,15 @@ impl SubInterpreter {
             )
             .into());
         }
+        let thread_id = unsafe { PyThread_get_thread_ident() };
         // release the GIL
         unsafe { PyEval_SaveThread() };
-        Ok(Self { state })
+        Ok(Self {
+            state,
+            thread_id,
+            memory,
+            lock: Mutex::new(()),
+        })
     }
 
     /// Run a closure in the sub-interpreter.
@@ -77,12 +123,16 @@ impl SubInterpreter {
     /// Please note that if the return value contains any `Py` object (e.g. `PyErr`),
     /// this object must be dropped in this sub-interpreter, otherwise it will cause
     /// `SIGABRT: pointer being freed was not allocated`.
+    ///
+    /// Closures from different threads run one at a time.
     pub fn with_gil<F, R>(&self, f: F) -> Result<R, PyError>
     where
         F: for<'py> FnOnce(Python<'py>) -> Result<R, PyError>,
     {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
         // switch to the sub-interpreter and acquire GIL
         unsafe { PyEval_RestoreThread(self.state) };
+        let memory = allocator::enter(self.memory);
 
         // Safety: the GIL is already held
         // this pool is used to increment the internal GIL count of pyo3.
@@ -90,18 +140,85 @@ impl SubInterpreter {
         let pool = unsafe { GILPool::new() };
         let ret = f(pool.python());
         drop(pool);
+        drop(memory);
 
         // release the GIL
         unsafe { PyEval_SaveThread() };
         ret
     }
 
+    /// Run a closure in the sub-interpreter, raising `KeyboardInterrupt` in the running Python code
+    /// once the token is cancelled.
+    ///
+    /// Cancelling the token blocks until the running code releases the GIL at the switch interval.
+    pub fn with_gil_cancellable<F, R>(
+        &self,
+        token: Option<&CancelToken>,
+        f: F,
+    ) -> Result<R, PyError>
+    where
+        F: for<'py> FnOnce(Python<'py>) -> Result<R, PyError>,
+    {
+        let Some(token) = token else {
+            return self.with_gil(f);
+        };
+        // whether `f` is running, only accessed with the GIL held
+        let running = Arc::new(AtomicBool::new(false));
+        let interrupter = Interrupter {
+            state: self.state,
+            thread_id: self.thread_id,
+            running: running.clone(),
+        };
+        let guard = token.on_cancel(move || interrupter.interrupt());
+        let ret = self.with_gil(|py| {
+            // the token may be cancelled while waiting for other threads
+            if token.is_cancelled() {
+                return Err(anyhow::Error::from(Error::Cancelled).into());
+            }
+            running.store(true, Ordering::SeqCst);
+            let ret = f(py);
+            running.store(false, Ordering::SeqCst);
+            // clear the exception if it is not raised before `f` returns
+            unsafe { PyThreadState_SetAsyncExc(self.thread_id as _, std::ptr::null_mut()) };
+            ret
+        });
+        // wait for the interrupter to return before the sub-interpreter may be dropped
+        drop(guard);
+        ret
+    }
+
+    /// Returns the number of bytes allocated in the sub-interpreter,
+    /// or `None` if it is not accounted.
+    pub fn memory_usage(&self) -> Option<usize> {
+        self.memory.map(|memory| memory.used())
+    }
+
+    /// Set the maximum number of bytes allocated in the sub-interpreter,
+    /// beyond which allocations raise `MemoryError`.
+    ///
+    /// Returns false if the memory can not be accounted.
+    pub fn set_memory_limit(&self, limit: Option<usize>) -> bool {
+        match self.memory {
+            Some(memory) => {
+                memory.set_limit(limit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the maximum number of bytes allocated in the sub-interpreter, or `None` if unlimited.
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory.and_then(|memory| memory.limit())
+    }
+
     /// Run Python code in the sub-interpreter.
     pub fn run(&self, code: &str) -> Result<(), PyError> {
         self.with_gil(|py| py.run_bound(code, None, None).map_err(|e| e.into()))
     }
 }
 
+#[cfg(Py_3_12)]
 impl Drop for SubInterpreter {
     fn drop(&mut self) {
         unsafe {
@@ -113,6 +230,48 @@ impl Drop for SubInterpreter {
     }
 }
 
+/// Raises `KeyboardInterrupt` in a sub-interpreter from another thread.
+#[cfg(Py_3_12)]
+struct Interrupter {
+    state: *mut PyThreadState,
+    thread_id: c_ulong,
+    running: Arc<AtomicBool>,
+}
+
+// SAFETY: the sub-interpreter outlives the interrupter, see `with_gil_cancellable`
+#[cfg(Py_3_12)]
+unsafe impl Send for Interrupter {}
+#[cfg(Py_3_12)]
+unsafe impl Sync for Interrupter {}
+
+#[cfg(Py_3_12)]
+impl Interrupter {
+    fn interrupt(&self) {
+        // `PyThreadState_SetAsyncExc` raises in the first thread state with the identifier,
+        // so the temporary thread state is created in a new thread that is not the target.
+        std::thread::scope(|s| {
+            s.spawn(|| self.interrupt_in_current_thread());
+        });
+    }
+
+    fn interrupt_in_current_thread(&self) {
+        if unsafe { PyThread_get_thread_ident() } == self.thread_id {
+            // the identifier of an exited thread is reused, try another one
+            return self.interrupt();
+        }
+        unsafe {
+            // acquire the GIL of the sub-interpreter with a temporary thread state
+            let tstate = PyThreadState_New(PyThreadState_GetInterpreter(self.state));
+            PyEval_RestoreThread(tstate);
+            if self.running.load(Ordering::SeqCst) {
+                PyThreadState_SetAsyncExc(self.thread_id as _, PyExc_KeyboardInterrupt);
+            }
+            PyThreadState_Clear(tstate);
+            PyThreadState_DeleteCurrent();
+        }
+    }
+}
+
 /// The error type for Python sub-interpreters.
 ///
 /// This type is a wrapper around `anyhow::Error`. The special thing is that
@@ -144,3 +303,10 @@ impl From<PyError> for anyhow::Error {
         err.anyhow
     }
 }
+
+/// Recover the structured errors of runtimes raised in the interpreter.
+impl From<PyError> for arrow_udf_core::Error {
+    fn from(err: PyError) -> Self {
+        err.anyhow.into()
+    }
+}
@@ -23,23 +23,72 @@
 // Otherwise, it will cause a crash the next time Python is called.
 // Special attention is needed for PyErr in PyResult.
 // Remember to convert `PyErr` using the `pyerr_to_anyhow` function before passing it out of the sub-interpreter.
+// On Python 3.10 and 3.11, `Py_3_12` is not set and runtimes share the main interpreter instead,
+// see `main_interpreter`.
 
-use self::interpreter::SubInterpreter;
+#[cfg(feature = "tokio")]
+pub use self::async_runtime::AsyncRuntime;
+use self::interpreter::PyError;
+#[cfg(Py_3_12)]
+use self::interpreter::SubInterpreter as Interpreter;
 pub use self::into_field::IntoField;
-use anyhow::{bail, Context, Result};
+#[cfg(not(Py_3_12))]
+use self::main_interpreter::MainInterpreter as Interpreter;
+pub use self::pyarrow::ConverterKey;
+pub use self::row_errors::{RowErrorPolicy, SUPPRESSED_ERRORS_KEY};
+pub use self::time_zone::{NaiveTimestampReturn, TimestampArgument};
+use self::timing::CallTimes;
+pub use self::validation::ReturnValidation;
+use anyhow::{anyhow, Context};
 use arrow_array::builder::{ArrayBuilder, Int32Builder, StringBuilder};
-use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch};
-use arrow_schema::{DataType, Field, FieldRef, Schema, SchemaRef};
-use pyo3::types::{PyAnyMethods, PyIterator, PyModule, PyTuple};
-use pyo3::{Py, PyObject};
-use std::collections::HashMap;
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int32Type;
+use arrow_array::{
+    make_array, new_empty_array, new_null_array, Array, ArrayRef, BooleanArray, RecordBatch,
+    RecordBatchIterator, RecordBatchReader, StructArray, UInt32Array,
+};
+use arrow_buffer::{BooleanBuffer, NullBuffer};
+use arrow_schema::{ArrowError, DataType, Field, FieldRef, Schema, SchemaRef};
+use arrow_udf_core::{
+    slice_memory_size, Arguments, BatchMode, CallStats, CancelToken, Capabilities, Error,
+    FunctionInfo, FunctionKind, LogLevel, Metrics, MetricsRegistry, NestedNullPolicy,
+    ResourceLimit, Result, UdfLogSink,
+};
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, RuntimePool, StrictArguments, Volatility,
+};
+use pyo3::exceptions::PyTypeError;
+use pyo3::types::{
+    PyAnyMethods, PyDict, PyDictMethods, PyIterator, PyList, PyModule, PyTuple, PyTypeMethods,
+};
+use pyo3::{Py, PyObject, PyResult, Python};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
-// #[cfg(Py_3_12)]
+mod allocator;
+mod annotation;
+#[cfg(feature = "tokio")]
+mod async_runtime;
+mod buffer;
+mod cpu_time;
+mod decorator;
 mod interpreter;
 mod into_field;
+#[cfg(not(Py_3_12))]
+mod main_interpreter;
+pub mod process;
 mod pyarrow;
+mod row_errors;
+pub mod sandbox;
+mod time_zone;
+mod timing;
+mod validation;
 
 /// A runtime to execute user defined functions in Python.
 ///
@@ -48,6 +97,7 @@ mod pyarrow;
 /// - Create a new runtime with [`Runtime::new`] or [`Runtime::builder`].
 /// - For scalar functions, use [`add_function`] and [`call`].
 /// - For table functions, use [`add_function`] and [`call_table_function`].
+/// - For window functions, use [`add_window_function`] and [`call_window`].
 /// - For aggregate functions, create the function with [`add_aggregate`], and then
 ///     - create a new state with [`create_state`],
 ///     - update the state with [`accumulate`] or [`accumulate_or_retract`],
@@ -61,27 +111,78 @@ mod pyarrow;
 /// As we know, Python has a Global Interpreter Lock (GIL) that prevents multiple threads from executing Python code simultaneously.
 /// To work around this limitation, each runtime creates a sub-interpreter with its own GIL. This feature requires Python 3.12 or later.
 ///
+/// When built with Python 3.10 or 3.11, runtimes share the main interpreter and its GIL instead,
+/// so calls of all runtimes run one at a time, and runtimes can't be sandboxed.
+///
+/// # Thread Safety
+///
+/// `Runtime` is `Send` and `Sync`, so it can be stored in the shared state of an engine, e.g. in an `Arc`.
+/// Calls take `&self` and can be made from any thread. They are serialized by the GIL of the sub-interpreter,
+/// which each call acquires on its own thread.
+///
+/// Adding and removing functions take `&mut self`, since they can't run while calls are running.
+/// To do that in a shared runtime, wrap it in an `RwLock`, whose read guards are held by calls,
+/// or build a new runtime and swap it in.
+///
 /// [`add_function`]: Runtime::add_function
 /// [`add_aggregate`]: Runtime::add_aggregate
 /// [`call`]: Runtime::call
 /// [`call_table_function`]: Runtime::call_table_function
+/// [`add_window_function`]: Runtime::add_window_function
+/// [`call_window`]: Runtime::call_window
 /// [`create_state`]: Runtime::create_state
 /// [`accumulate`]: Runtime::accumulate
 /// [`accumulate_or_retract`]: Runtime::accumulate_or_retract
 /// [`merge`]: Runtime::merge
 /// [`finish`]: Runtime::finish
 pub struct Runtime {
-    interpreter: SubInterpreter,
-    functions: HashMap<String, Function>,
-    aggregates: HashMap<String, Aggregate>,
+    /// The interpreter and the functions loaded in it, replaced once the interpreter is restarted.
+    instance: RwLock<Arc<Instance>>,
+    /// The options to build the interpreter again.
+    options: Builder,
+    /// The number of times the interpreter has been restarted.
+    restarts: AtomicUsize,
     converter: pyarrow::Converter,
+    /// How nulls inside nested arguments are handled.
+    nested_nulls: NestedNullPolicy,
+    /// Timeout of each function call.
+    timeout: Option<Duration>,
+    /// The CPU time limit of each function call.
+    cpu_time_limit: Option<Duration>,
+    /// Whether struct outputs of scalar functions are expanded into a column for each field.
+    expand_struct: bool,
+    /// Whether conversion errors are reported in the error column instead of failing the call.
+    row_conversion_errors: bool,
+    /// How failed rows are reported.
+    row_error_policy: RowErrorPolicy,
+    /// Whether objects converted from the values of dictionary arguments are reused in a call.
+    dictionary_cache: bool,
+    /// Whether arguments of scalar functions are passed by the names of their fields.
+    keyword_arguments: bool,
+    /// The maximum number of rows in each output batch of table functions and streams.
+    max_output_rows: Option<usize>,
+    /// Metrics of calls to each function.
+    metrics: MetricsRegistry,
+    /// The token cancelling the running calls, replaced once cancelled.
+    cancel: Mutex<CancelToken>,
 }
 
+// calls are made from the threads of engines, see "Thread Safety" above
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Runtime>();
+    assert_send_sync::<Builder>();
+    assert_send_sync::<RecordBatchIter<'static>>();
+};
+
 impl Debug for Runtime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let instance = self.instance();
         f.debug_struct("Runtime")
-            .field("functions", &self.functions.keys())
-            .field("aggregates", &self.aggregates.keys())
+            .field("functions", &instance.functions.keys())
+            .field("aggregates", &instance.aggregates.keys())
+            .field("aliases", &instance.aliases)
+            .field("restarts", &self.restarts())
             .finish()
     }
 }
@@ -91,6 +192,79 @@ struct Function {
     function: PyObject,
     return_field: FieldRef,
     mode: CallMode,
+    /// How the function is called with the arguments.
+    convention: Convention,
+    /// The minimum and maximum number of positional arguments, or `None` if unknown.
+    ///
+    /// The maximum is `None` for functions with `*args`.
+    arity: Option<Arity>,
+    /// Where the function is loaded from.
+    source: Source,
+    /// How return values of other Python types are handled.
+    validation: ReturnValidation,
+    /// The argument types of an overload, or `None` if the function is called with any types.
+    arg_types: Option<Vec<DataType>>,
+    /// Whether the function returns the same result for the same arguments.
+    volatility: Volatility,
+}
+
+/// Where a function is loaded from, kept to load it again once the interpreter is restarted.
+#[derive(Debug, Clone)]
+enum Source {
+    /// The code and the name of the handler in it.
+    Code { code: String, handler: String },
+    /// A pickled callable.
+    Pickle(Vec<u8>),
+}
+
+/// The minimum and maximum number of positional arguments of a function.
+type Arity = (usize, Option<usize>);
+
+/// The functions decorated with `@udf` in a script with the string arguments of their decorators,
+/// and the code without the decorators.
+type UdfFunctions = (Vec<(String, HashMap<String, String>)>, String);
+
+/// How a function is called with the arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Convention {
+    /// Called for each row with a value for each argument.
+    Row,
+    /// Called once for a batch with a list for each argument.
+    List,
+    /// Called once for a batch with a `memoryview` of primitive values for each argument.
+    Buffer,
+    /// Called once for a batch with a `numpy.ndarray` of primitive values for each argument.
+    Numpy,
+    /// Called once for a batch with a `pandas.Series` for each argument.
+    Pandas,
+    /// Called once for a batch with an array implementing the Arrow PyCapsule Interface for each argument.
+    Arrow,
+    /// Called once for a partition with its columns and the frame of each row.
+    Window,
+}
+
+impl Function {
+    /// Check that the function can be called with the number of positional arguments.
+    fn check_arity(&self, name: &str, num_args: usize) -> Result<()> {
+        let Some((min, max)) = self.arity else {
+            return Ok(());
+        };
+        if num_args >= min && max.is_none_or(|max| num_args <= max) {
+            return Ok(());
+        }
+        let expected = match max {
+            Some(max) if max == min => format!("{min}"),
+            Some(max) => format!("{min} to {max}"),
+            None => format!("at least {min}"),
+        };
+        let plural = match (min, max) {
+            (1, Some(1) | None) => "",
+            _ => "s",
+        };
+        Err(Error::TypeMismatch(format!(
+            "function {name} expects {expected} argument{plural}, but got {num_args}"
+        )))
+    }
 }
 
 /// A user defined aggregate function.
@@ -103,13 +277,356 @@ struct Aggregate {
     retract: Option<PyObject>,
     finish: Option<PyObject>,
     merge: Option<PyObject>,
+    /// The code the functions are loaded from.
+    code: String,
+}
+
+/// The sink of logs and the object capturing them in the interpreter.
+struct LogCapture {
+    sink: Arc<dyn UdfLogSink>,
+    capture: PyObject,
+}
+
+impl LogCapture {
+    /// Deliver the logs captured since the last flush to the sink.
+    fn flush(&self, py: Python<'_>, function: &str) -> PyResult<()> {
+        let records = self.capture.call_method0(py, "drain")?;
+        for record in records.bind(py).iter()? {
+            let (levelno, message): (i32, String) = record?.extract()?;
+            let level = match levelno {
+                ..20 => LogLevel::Debug,
+                20..30 => LogLevel::Info,
+                30..40 => LogLevel::Warn,
+                _ => LogLevel::Error,
+            };
+            self.sink.log(level, function, &message);
+        }
+        Ok(())
+    }
+}
+
+/// A sub-interpreter and the functions loaded in it.
+struct Instance {
+    interpreter: Interpreter,
+    functions: HashMap<String, Function>,
+    aggregates: HashMap<String, Aggregate>,
+    /// The names of functions with overloads, which are stored by [`overload_key`].
+    overloaded: HashSet<String>,
+    /// The names of scalar, table or aggregate functions by their aliases.
+    aliases: HashMap<String, String>,
+    /// The code of custom type converters by their ids in `_converters`.
+    converters: HashMap<String, String>,
+    /// The sink of logs, if set.
+    logs: Option<LogCapture>,
+    /// Whether the interpreter fails the health check and should be restarted.
+    broken: AtomicBool,
+}
+
+impl Instance {
+    /// Run a closure in the interpreter for a function, delivering its logs to the sink afterwards.
+    ///
+    /// The interpreter is marked as broken if it fails the health check afterwards.
+    fn with_gil<F, R>(&self, function: &str, token: Option<&CancelToken>, f: F) -> Result<R>
+    where
+        F: for<'py> FnOnce(Python<'py>) -> Result<R, PyError>,
+    {
+        Ok(self.interpreter.with_gil_cancellable(token, |py| {
+            let ret = f(py);
+            // the interrupt of a cancelled call may be raised in the check
+            if !token.is_some_and(|token| token.is_cancelled()) && !is_healthy(py) {
+                self.broken.store(true, Ordering::Relaxed);
+            }
+            if let Some(logs) = &self.logs {
+                logs.flush(py, function)?;
+            }
+            ret
+        })?)
+    }
+
+    /// Load the callable of a function, returning it with its arity.
+    fn load(&self, name: &str, source: &Source) -> Result<(PyObject, Option<Arity>)> {
+        self.with_gil(name, None, |py| {
+            let function = match source {
+                Source::Code { code, handler } => {
+                    PyModule::from_code_bound(py, code, name, name)?.getattr(handler.as_str())?
+                }
+                Source::Pickle(bytes) => py
+                    .eval_bound("pickle.loads", None, None)?
+                    .call1((bytes.as_slice(),))?,
+            };
+            let function = py
+                .eval_bound("_instantiate", None, None)?
+                .call1((function,))?;
+            if matches!(source, Source::Pickle(_)) && !function.is_callable() {
+                return Err(PyTypeError::new_err(format!(
+                    "expect a callable from pickle, got {}",
+                    function.get_type().name()?
+                ))
+                .into());
+            }
+            let arity = py.eval_bound("_arity", None, None)?.call1((&function,))?;
+            Ok((function.into(), arity.extract()?))
+        })
+    }
+
+    /// Load a custom type converter into `_converters` by its id.
+    fn load_converter(&self, id: &str, code: &str) -> Result<()> {
+        self.with_gil(id, None, |py| {
+            let module = PyModule::from_code_bound(py, code, id, id)?;
+            if !module.hasattr("to_python")? && !module.hasattr("from_python")? {
+                return Err(PyTypeError::new_err(
+                    "converter must define `to_python` or `from_python`",
+                )
+                .into());
+            }
+            py.eval_bound("_add_converter", None, None)?
+                .call1((id, module))?;
+            Ok(())
+        })
+    }
+
+    /// Returns the source of the return annotation of a loaded function, or `None` if there is no annotation.
+    fn return_annotation(&self, name: &str, function: &PyObject) -> Result<Option<String>> {
+        self.with_gil(name, None, |py| {
+            let annotation = py
+                .eval_bound("_return_annotation", None, None)?
+                .call1((function,))?;
+            Ok(annotation.extract()?)
+        })
+    }
+
+    /// Returns the functions decorated with `@udf` in a script with the arguments of their decorators,
+    /// and the code to load them from.
+    fn udf_functions(&self, code: &str) -> Result<UdfFunctions> {
+        self.with_gil("<script>", None, |py| {
+            let functions = py
+                .eval_bound("_udf_functions", None, None)?
+                .call1((code,))?;
+            Ok(functions.extract()?)
+        })
+    }
+
+    /// Load an aggregate function from the code.
+    fn load_aggregate(
+        &self,
+        name: &str,
+        state_field: FieldRef,
+        output_field: FieldRef,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<Aggregate> {
+        self.with_gil(name, None, |py| {
+            let module = PyModule::from_code_bound(py, code, name, name)?;
+            Ok(Aggregate {
+                state_field,
+                output_field,
+                mode,
+                create_state: module.getattr("create_state")?.into(),
+                accumulate: module.getattr("accumulate")?.into(),
+                retract: module.getattr("retract").ok().map(|f| f.into()),
+                finish: module.getattr("finish").ok().map(|f| f.into()),
+                merge: module.getattr("merge").ok().map(|f| f.into()),
+                code: code.to_string(),
+            })
+        })
+    }
+
+    /// Set the sink of logs, see [`Runtime::set_log_sink`].
+    fn set_log_sink(&mut self, sink: Option<Arc<dyn UdfLogSink>>) -> Result<()> {
+        let logs = self.logs.take();
+        self.logs = self.interpreter.with_gil(|py| {
+            let capture = match logs {
+                Some(logs) => logs.capture,
+                None => {
+                    let capture: PyObject = py.eval_bound("_log_capture", None, None)?.into();
+                    capture.call_method0(py, "enable")?;
+                    capture
+                }
+            };
+            match sink {
+                Some(sink) => Ok(Some(LogCapture { sink, capture })),
+                None => {
+                    capture.call_method0(py, "disable")?;
+                    Ok(None)
+                }
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Drop the objects of a removed function inside the interpreter,
+    /// and remove its module unless another function of the name is left.
+    fn unload<T>(&self, name: &str, removed: T) -> Result<()> {
+        let used = self.functions.contains_key(name) || self.aggregates.contains_key(name);
+        self.interpreter.with_gil(|py| {
+            drop(removed);
+            if !used {
+                py.eval_bound("_unload", None, None)?.call1((name,))?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Returns the key of the scalar or table function called by the name with the input fields.
+    ///
+    /// Aliases are resolved first, and the overload of the argument types is preferred over the
+    /// function without a signature.
+    fn resolve<'a>(&self, name: &'a str, fields: &[FieldRef]) -> Result<Cow<'a, str>> {
+        if let Some(target) = self.aliases.get(name) {
+            return Ok(Cow::Owned(self.resolve(target, fields)?.into_owned()));
+        }
+        if !self.overloaded.contains(name) {
+            return Ok(name.into());
+        }
+        let key = overload_key(name, fields.iter().map(|field| field.data_type()));
+        if self.functions.contains_key(&key) {
+            return Ok(key.into());
+        }
+        let prefix = format!("{name}(");
+        let has_overloads = (self.functions.iter())
+            .any(|(key, function)| key.starts_with(&prefix) && function.arg_types.is_some());
+        if has_overloads && !self.functions.contains_key(name) {
+            return Err(Error::TypeMismatch(format!(
+                "no overload of function {name} takes {}",
+                &key[name.len()..]
+            )));
+        }
+        Ok(name.into())
+    }
+
+    /// Returns the aggregate function of the name or alias.
+    fn aggregate(&self, name: &str) -> Result<&Aggregate, Error> {
+        let target = self.aliases.get(name).map_or(name, String::as_str);
+        (self.aggregates.get(target)).ok_or_else(|| Error::FunctionNotFound(name.to_string()))
+    }
+
+    /// Drop objects replaced by a new function inside the interpreter.
+    fn drop_in_interpreter<T>(&self, replaced: T) {
+        _ = self.interpreter.with_gil(|_| {
+            drop(replaced);
+            Ok(())
+        });
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // `PyObject` must be dropped inside the interpreter
+        _ = self.interpreter.with_gil(|_| {
+            self.functions.clear();
+            self.aggregates.clear();
+            self.logs.take();
+            Ok(())
+        });
+    }
 }
 
+/// Returns true if the interpreter is still usable, see `_check_health`.
+fn is_healthy(py: Python<'_>) -> bool {
+    py.eval_bound("_check_health()", None, None)
+        .is_ok_and(|healthy| healthy.is_truthy().unwrap_or(false))
+}
+
+/// The modules that can be imported in the sandbox by default, including the ones imported by them.
+// FIXME: 'sys' should not be allowed, but it is required by 'decimal'
+// FIXME: 'time.sleep' should not be allowed, but 'time' is required by 'datetime'
+const SANDBOX_ALLOWED_MODULES: &[&str] = &[
+    "json",
+    "decimal",
+    "re",
+    "math",
+    "datetime",
+    "time",
+    "operator",
+    "numbers",
+    "abc",
+    "sys",
+    "contextvars",
+    "_io",
+    "_contextvars",
+    "_pydecimal",
+    "_pydatetime",
+];
+
+/// The builtins removed in the sandbox by default.
+const SANDBOX_REMOVED_SYMBOLS: &[&str] = &[
+    "__builtins__.breakpoint",
+    "__builtins__.exit",
+    "__builtins__.eval",
+    "__builtins__.help",
+    "__builtins__.input",
+    "__builtins__.open",
+    "__builtins__.print",
+];
+
 /// A builder for `Runtime`.
-#[derive(Default, Debug)]
+///
+/// The default builds the same runtime as [`Runtime::new`].
+///
+/// # Example
+///
+/// ```
+/// # use arrow_udf_python::Runtime;
+/// # use std::time::Duration;
+/// let runtime = Runtime::builder()
+///     .sandboxed(true)
+///     .keep_symbol("__builtins__.print")
+///     .preload_module("string")
+///     .timeout(Some(Duration::from_secs(1)))
+///     .keyword_arguments(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Default, Clone)]
 pub struct Builder {
     sandboxed: bool,
     removed_symbols: Vec<String>,
+    kept_symbols: Vec<String>,
+    allowed_modules: Vec<String>,
+    preloaded_modules: Vec<String>,
+    paths: Vec<PathBuf>,
+    timeout: Option<Duration>,
+    cpu_time_limit: Option<Duration>,
+    memory_accounting: bool,
+    memory_limit: Option<usize>,
+    nested_nulls: NestedNullPolicy,
+    timestamp_argument: TimestampArgument,
+    naive_timestamp_return: NaiveTimestampReturn,
+    expand_struct: bool,
+    row_conversion_errors: bool,
+    row_error_policy: RowErrorPolicy,
+    dictionary_cache: bool,
+    keyword_arguments: bool,
+    max_output_rows: Option<usize>,
+    log_sink: Option<Arc<dyn UdfLogSink>>,
+}
+
+impl Debug for Builder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Builder")
+            .field("sandboxed", &self.sandboxed)
+            .field("removed_symbols", &self.removed_symbols)
+            .field("kept_symbols", &self.kept_symbols)
+            .field("allowed_modules", &self.allowed_modules)
+            .field("preloaded_modules", &self.preloaded_modules)
+            .field("paths", &self.paths)
+            .field("timeout", &self.timeout)
+            .field("cpu_time_limit", &self.cpu_time_limit)
+            .field("memory_accounting", &self.memory_accounting)
+            .field("memory_limit", &self.memory_limit)
+            .field("nested_nulls", &self.nested_nulls)
+            .field("timestamp_argument", &self.timestamp_argument)
+            .field("naive_timestamp_return", &self.naive_timestamp_return)
+            .field("expand_struct", &self.expand_struct)
+            .field("row_conversion_errors", &self.row_conversion_errors)
+            .field("row_error_policy", &self.row_error_policy)
+            .field("dictionary_cache", &self.dictionary_cache)
+            .field("keyword_arguments", &self.keyword_arguments)
+            .field("max_output_rows", &self.max_output_rows)
+            .field("log_sink", &self.log_sink.is_some())
+            .finish()
+    }
 }
 
 impl Builder {
@@ -119,22 +636,18 @@ impl Builder {
     /// This is useful for running untrusted code.
     ///
     /// Allowed modules: `json`, `decimal`, `re`, `math`, `datetime`, `time`.
+    /// More modules can be allowed by [`allow_module`](Builder::allow_module).
     ///
     /// Disallowed builtins: `breakpoint`, `exit`, `eval`, `help`, `input`, `open`, `print`.
+    /// Some of them can be kept by [`keep_symbol`](Builder::keep_symbol).
     ///
     /// The default is `false`.
     pub fn sandboxed(mut self, sandboxed: bool) -> Self {
         self.sandboxed = sandboxed;
-        self.remove_symbol("__builtins__.breakpoint")
-            .remove_symbol("__builtins__.exit")
-            .remove_symbol("__builtins__.eval")
-            .remove_symbol("__builtins__.help")
-            .remove_symbol("__builtins__.input")
-            .remove_symbol("__builtins__.open")
-            .remove_symbol("__builtins__.print")
+        self
     }
 
-    /// Remove a symbol from builtins.
+    /// Remove a symbol from builtins in the sandbox, in addition to the default ones.
     ///
     /// # Examples
     ///
@@ -147,165 +660,2058 @@ impl Builder {
         self
     }
 
-    /// Build the `Runtime`.
-    pub fn build(self) -> Result<Runtime> {
-        let interpreter = SubInterpreter::new()?;
-        interpreter.run(
-            r#"
-# internal use for json types
-import json
-import pickle
-import decimal
+    /// Keep a symbol that is removed from builtins in the sandbox by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder()
+    ///     .sandboxed(true)
+    ///     .keep_symbol("__builtins__.print")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn keep_symbol(mut self, symbol: &str) -> Self {
+        self.kept_symbols.push(symbol.to_string());
+        self
+    }
 
-# an internal class used for struct input arguments
-class Struct:
-    pass
-"#,
-        )?;
-        if self.sandboxed {
-            let mut script = r#"
-# limit the modules that can be imported
-original_import = __builtins__.__import__
+    /// Allow a module to be imported in the sandbox, in addition to the default ones.
+    ///
+    /// Only the module itself is allowed. Its submodules and the modules it imports
+    /// must be allowed as well, e.g. `collections` needs `keyword`, `operator`, `reprlib` and others.
+    /// To avoid that, use [`preload_module`](Builder::preload_module) instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder()
+    ///     .sandboxed(true)
+    ///     .allow_module("string")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn allow_module(mut self, module: &str) -> Self {
+        self.allowed_modules.push(module.to_string());
+        self
+    }
 
-def limited_import(name, globals=None, locals=None, fromlist=(), level=0):
-    # FIXME: 'sys' should not be allowed, but it is required by 'decimal'
-    # FIXME: 'time.sleep' should not be allowed, but 'time' is required by 'datetime'
-    allowlist = (
-        'json',
-        'decimal',
-        're',
-        'math',
-        'datetime',
-        'time',
-        'operator',
-        'numbers',
-        'abc',
-        'sys',
-        'contextvars',
-        '_io',
-        '_contextvars',
-        '_pydecimal',
-        '_pydatetime',
-    )
-    if level == 0 and name in allowlist:
-        return original_import(name, globals, locals, fromlist, level)
-    raise ImportError(f'import {name} is not allowed')
+    /// Import a module when the runtime is built, before the sandbox is applied.
+    ///
+    /// Functions can then import the module in the sandbox, without allowing the modules it imports.
+    /// Building fails if the module can not be imported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder()
+    ///     .sandboxed(true)
+    ///     .preload_module("collections")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn preload_module(mut self, module: &str) -> Self {
+        self.preloaded_modules.push(module.to_string());
+        self
+    }
 
-__builtins__.__import__ = limited_import
-del limited_import
-"#
-            .to_string();
-            for symbol in self.removed_symbols {
-                script.push_str(&format!("del {}\n", symbol));
-            }
-            interpreter.run(&script)?;
-        }
-        Ok(Runtime {
-            interpreter,
-            functions: HashMap::new(),
-            aggregates: HashMap::new(),
-            converter: pyarrow::Converter::new(),
-        })
+    /// Append a directory to `sys.path` before the sandbox is applied,
+    /// e.g. a `site-packages` directory of vetted third-party packages.
+    ///
+    /// In the sandbox, the packages must also be allowed by [`allow_module`](Builder::allow_module),
+    /// or imported by [`preload_module`](Builder::preload_module).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder()
+    ///     .sandboxed(true)
+    ///     .append_path("/opt/udf/site-packages")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn append_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
     }
-}
 
-impl Runtime {
-    /// Create a new `Runtime`.
-    pub fn new() -> Result<Self> {
-        Builder::default().build()
+    /// Set the timeout of each function call. See [`Runtime::set_timeout`].
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
     }
 
-    /// Return a new builder for `Runtime`.
-    pub fn builder() -> Builder {
-        Builder::default()
+    /// Set the CPU time limit of each function call. See [`Runtime::set_cpu_time_limit`].
+    pub fn cpu_time_limit(mut self, limit: Option<Duration>) -> Self {
+        self.cpu_time_limit = limit;
+        self
     }
 
-    /// Add a new scalar function or table function.
+    /// Set whether the memory allocated by the interpreter is accounted.
     ///
-    /// # Arguments
+    /// Accounting wraps the allocators of Python, adding a header of 16 bytes to every allocation,
+    /// which can only be done before Python is initialized.
+    /// So it must be enabled for the first runtime created in the process,
+    /// otherwise [`build`](Builder::build) fails.
+    /// Once wrapped, the allocators stay wrapped for all runtimes, while only those enabling it are accounted.
     ///
-    /// - `name`: The name of the function.
-    /// - `return_type`: The data type of the return value.
-    /// - `mode`: Whether the function will be called when some of its arguments are null.
-    /// - `code`: The Python code of the function.
+    /// It is required by [`Runtime::set_memory_limit`] and [`Runtime::memory_usage`],
+    /// and enabled by [`memory_limit`](Builder::memory_limit).
     ///
-    /// The code should define a function with the same name as the function.
-    /// The function should return a value for scalar functions, or yield values for table functions.
+    /// The default is `false`.
     ///
     /// # Example
     ///
     /// ```
-    /// # use arrow_udf_python::{Runtime, CallMode};
-    /// # use arrow_schema::DataType;
-    /// let mut runtime = Runtime::new().unwrap();
-    /// // add a scalar function
-    /// runtime
-    ///     .add_function(
-    ///         "gcd",
-    ///         DataType::Int32,
-    ///         CallMode::ReturnNullOnNullInput,
-    ///         r#"
-    /// def gcd(a: int, b: int) -> int:
-    ///     while b:
-    ///         a, b = b, a % b
-    ///     return a
-    /// "#,
-    ///     )
-    ///     .unwrap();
-    /// // add a table function
-    /// runtime
-    ///     .add_function(
-    ///         "series",
-    ///         DataType::Int32,
-    ///         CallMode::ReturnNullOnNullInput,
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder().memory_accounting(true).build().unwrap();
+    /// assert!(runtime.memory_usage().is_some());
+    /// ```
+    pub fn memory_accounting(mut self, enable: bool) -> Self {
+        self.memory_accounting = enable;
+        self
+    }
+
+    /// Set the memory limit of the runtime in bytes, enabling [`memory_accounting`](Builder::memory_accounting)
+    /// if it is not `None`. See [`Runtime::set_memory_limit`].
+    pub fn memory_limit(mut self, limit: Option<usize>) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Set how nulls inside nested arguments are handled. See [`Runtime::set_nested_null_policy`].
+    pub fn nested_null_policy(mut self, policy: NestedNullPolicy) -> Self {
+        self.nested_nulls = policy;
+        self
+    }
+
+    /// Set how arguments of timestamps with time zones are converted.
+    /// See [`Runtime::set_timestamp_argument`].
+    pub fn timestamp_argument(mut self, argument: TimestampArgument) -> Self {
+        self.timestamp_argument = argument;
+        self
+    }
+
+    /// Set how naive `datetime` returned for timestamps with time zones are interpreted.
+    /// See [`Runtime::set_naive_timestamp_return`].
+    pub fn naive_timestamp_return(mut self, naive: NaiveTimestampReturn) -> Self {
+        self.naive_timestamp_return = naive;
+        self
+    }
+
+    /// Set whether struct outputs are expanded into columns. See [`Runtime::set_expand_struct`].
+    pub fn expand_struct(mut self, expand: bool) -> Self {
+        self.expand_struct = expand;
+        self
+    }
+
+    /// Set whether conversion errors are reported in the error column.
+    /// See [`Runtime::set_row_conversion_errors`].
+    pub fn row_conversion_errors(mut self, enabled: bool) -> Self {
+        self.row_conversion_errors = enabled;
+        self
+    }
+
+    /// Set how failed rows are reported. See [`Runtime::set_row_error_policy`].
+    pub fn row_error_policy(mut self, policy: RowErrorPolicy) -> Self {
+        self.row_error_policy = policy;
+        self
+    }
+
+    /// Set whether objects converted from dictionary values are reused.
+    /// See [`Runtime::set_dictionary_cache`].
+    pub fn dictionary_cache(mut self, enabled: bool) -> Self {
+        self.dictionary_cache = enabled;
+        self
+    }
+
+    /// Set whether arguments are passed by keywords. See [`Runtime::set_keyword_arguments`].
+    pub fn keyword_arguments(mut self, enabled: bool) -> Self {
+        self.keyword_arguments = enabled;
+        self
+    }
+
+    /// Set the maximum number of rows in each output batch. See [`Runtime::set_max_output_rows`].
+    pub fn max_output_rows(mut self, rows: Option<usize>) -> Self {
+        self.max_output_rows = rows.map(|rows| rows.max(1));
+        self
+    }
+
+    /// Set the sink of messages printed and logged by functions. See [`Runtime::set_log_sink`].
+    ///
+    /// With a sink, `print` is kept in sandboxed runtimes, since its output goes to the sink
+    /// with the name of the function instead of the standard output of the process.
+    /// It can still be removed by [`remove_symbol`](Self::remove_symbol).
+    /// With the `tracing` feature, [`TracingLogSink`](arrow_udf_core::TracingLogSink)
+    /// emits the messages as `tracing` events.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// # use arrow_udf_core::LogLevel;
+    /// # use std::sync::Arc;
+    /// let runtime = Runtime::builder()
+    ///     .sandboxed(true)
+    ///     .log_sink(Arc::new(|level: LogLevel, function: &str, message: &str| {
+    ///         eprintln!("[{level}] {function}: {message}");
+    ///     }))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn log_sink(mut self, sink: Arc<dyn UdfLogSink>) -> Self {
+        self.log_sink = Some(sink);
+        self
+    }
+
+    /// Build a pool of `size` runtimes with the same options, each in a sub-interpreter with its own GIL.
+    ///
+    /// Functions added to the pool are loaded into every runtime.
+    /// A call splits the input batch into partitions that are evaluated by the runtimes in parallel,
+    /// and concurrent calls from multiple threads don't wait for the GIL of a single interpreter.
+    /// See [`RuntimePool`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_udf_core::UdfRuntime;
+    /// # use arrow_schema::{DataType, Field};
+    /// let mut pool = Runtime::builder().sandboxed(true).build_pool(4).unwrap();
+    /// pool.add_function(
+    ///     "square",
+    ///     Field::new("square", DataType::Int32, true),
+    ///     CallMode::ReturnNullOnNullInput,
+    ///     "def square(x):\n    return x * x",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(pool.members().len(), 4);
+    /// ```
+    pub fn build_pool(self, size: usize) -> Result<RuntimePool> {
+        let members = (0..size)
+            .map(|_| Ok(Box::new(self.clone().build()?) as Box<dyn arrow_udf_core::UdfRuntime>))
+            .collect::<Result<Vec<_>>>()?;
+        RuntimePool::new(members)
+    }
+
+    /// Build the `Runtime`.
+    pub fn build(self) -> Result<Runtime> {
+        let instance = self.build_instance()?;
+        let mut runtime = Runtime {
+            instance: RwLock::new(Arc::new(instance)),
+            restarts: AtomicUsize::new(0),
+            metrics: MetricsRegistry::new(),
+            cancel: Mutex::default(),
+            converter: pyarrow::Converter::new(),
+            nested_nulls: self.nested_nulls,
+            timeout: self.timeout,
+            cpu_time_limit: None,
+            expand_struct: self.expand_struct,
+            row_conversion_errors: self.row_conversion_errors,
+            row_error_policy: self.row_error_policy,
+            dictionary_cache: self.dictionary_cache,
+            keyword_arguments: self.keyword_arguments,
+            max_output_rows: self.max_output_rows,
+            options: self,
+        };
+        runtime.set_timestamp_argument(runtime.options.timestamp_argument);
+        runtime.set_naive_timestamp_return(runtime.options.naive_timestamp_return);
+        if runtime.options.cpu_time_limit.is_some() {
+            runtime.set_cpu_time_limit(runtime.options.cpu_time_limit)?;
+        }
+        if runtime.options.memory_limit.is_some() {
+            runtime.set_memory_limit(runtime.options.memory_limit)?;
+        }
+        if let Some(sink) = runtime.options.log_sink.clone() {
+            runtime.set_log_sink(Some(sink))?;
+        }
+        Ok(runtime)
+    }
+
+    /// Create and initialize an interpreter without any function.
+    fn build_instance(&self) -> Result<Instance> {
+        if let Some(module) = (self.allowed_modules.iter())
+            .chain(&self.preloaded_modules)
+            .find(|m| !is_module_name(m))
+        {
+            return Err(anyhow!("invalid module name: {module:?}").into());
+        }
+        #[cfg(not(Py_3_12))]
+        if self.sandboxed {
+            return Err(anyhow!("the sandbox requires Python 3.12 or later, where functions run in sub-interpreters").into());
+        }
+        let memory_accounting = self.memory_accounting || self.memory_limit.is_some();
+        let interpreter = Interpreter::new(memory_accounting)?;
+        interpreter.run(
+            r#"
+# internal use for json types
+import json
+import pickle
+import decimal
+import uuid
+
+# an internal class used for struct input arguments,
+# a dict of the fields whose values can also be read as attributes
+class Struct(dict):
+    __slots__ = ()
+
+    def __getattr__(self, name):
+        try:
+            return self[name]
+        except KeyError:
+            raise AttributeError(name) from None
+
+    __setattr__ = dict.__setitem__
+    __delattr__ = dict.__delitem__
+
+    # for functions reading the fields from `__dict__`
+    @property
+    def __dict__(self):
+        return self
+
+# an internal class used for arguments of arrow functions, implementing the Arrow PyCapsule Interface
+# by exporting the array through the C Data Interface each time it is requested
+class ArrowArray:
+    __slots__ = ('_export', '_len')
+
+    def __init__(self, export, len):
+        self._export = export
+        self._len = len
+
+    def __len__(self):
+        return self._len
+
+    def __arrow_c_array__(self, requested_schema=None):
+        return self._export()
+
+# internal use for functions defined as classes, which are instantiated once
+# so that the states initialized in `__init__` persist across calls
+def _instantiate(function):
+    return function() if isinstance(function, type) else function
+
+# internal use for checking the number of arguments of functions,
+# returns the minimum and maximum, where the maximum is None for `*args`,
+# or None for callables other than Python functions
+def _arity(function):
+    # instances of classes are called by `__call__`
+    if not hasattr(function, '__code__'):
+        function = getattr(function, '__call__', function)
+    code = getattr(function, '__code__', None)
+    if code is None:
+        return None
+    positional = code.co_argcount
+    # `self` of bound methods is already given
+    if getattr(function, '__self__', None) is not None:
+        positional -= 1
+    defaults = len(getattr(function, '__defaults__', None) or ())
+    varargs = code.co_flags & 0x04
+    return (positional - defaults, None if varargs else positional)
+
+# internal use for inferring return types, returns the source of the return annotation,
+# or None if the function has no return annotation
+def _return_annotation(function):
+    if not hasattr(function, '__code__'):
+        function = getattr(function, '__call__', function)
+    annotations = getattr(function, '__annotations__', None) or {}
+    if 'return' not in annotations:
+        return None
+    annotation = annotations['return']
+    if isinstance(annotation, str):
+        return annotation
+    if isinstance(annotation, type) and getattr(annotation, '__args__', None) is None:
+        if annotation.__module__ == 'builtins':
+            return annotation.__qualname__
+        return f'{annotation.__module__}.{annotation.__qualname__}'
+    return repr(annotation)
+
+# internal use for scripts of functions decorated with `@udf`, returns the names of the decorated
+# functions with the arguments of their decorators, and the code without the decorators and the
+# imports of `arrow_udf`, which are replaced by blank lines so that line numbers are kept
+import ast as _ast
+
+_UDF_ARGUMENTS = ('input_types', 'result_type', 'name')
+
+def _udf_functions(code):
+    tree = _ast.parse(code)
+    lines = code.splitlines(keepends=True)
+    def blank(node):
+        for i in range(node.lineno - 1, node.end_lineno):
+            lines[i] = '\n'
+    functions = []
+    for node in tree.body:
+        if isinstance(node, _ast.Import) and all(a.name.split('.')[0] == 'arrow_udf' for a in node.names):
+            blank(node)
+        elif isinstance(node, _ast.ImportFrom) and (node.module or '').split('.')[0] == 'arrow_udf':
+            blank(node)
+        elif isinstance(node, _ast.FunctionDef):
+            for decorator in node.decorator_list:
+                call = decorator.func if isinstance(decorator, _ast.Call) else decorator
+                if not (isinstance(call, _ast.Name) and call.id == 'udf'
+                        or isinstance(call, _ast.Attribute) and call.attr == 'udf'):
+                    continue
+                if not isinstance(decorator, _ast.Call):
+                    raise TypeError(f'@udf of function {node.name} must be called with its result_type')
+                arguments = dict(zip(_UDF_ARGUMENTS, decorator.args))
+                arguments.update((k.arg, k.value) for k in decorator.keywords if k.arg is not None)
+                values = {}
+                for key, value in arguments.items():
+                    try:
+                        value = _ast.literal_eval(value)
+                    except ValueError:
+                        raise ValueError(f'argument {key} of @udf of function {node.name} must be a literal') from None
+                    if isinstance(value, str):
+                        values[key] = value
+                functions.append((node.name, values))
+                blank(decorator)
+    return functions, ''.join(lines)
+
+# internal use for uuid types
+def _to_uuid(value):
+    return uuid.UUID(bytes=value)
+
+# returns the bytes of a `uuid.UUID` or a string of UUID, leaving other values as they are
+def _from_uuid(value):
+    if isinstance(value, uuid.UUID):
+        return value.bytes
+    if isinstance(value, str):
+        return uuid.UUID(value).bytes
+    return value
+
+# internal use for NumPy functions, where `numpy` is imported once they are called
+def _to_ndarray(buffer):
+    import numpy
+    return numpy.asarray(buffer)
+
+# returns a contiguous array of the values, cast to the item type of a `struct` format
+def _from_ndarray(values, format):
+    import numpy
+    return numpy.ascontiguousarray(values, dtype=format)
+
+# internal use for pandas functions, where `pandas` is imported once they are called
+def _to_series(values):
+    import pandas
+    return pandas.Series(values)
+
+# returns a list of the values, where missing values are None
+def _from_series(series):
+    import pandas
+    values = series.tolist() if hasattr(series, 'tolist') else list(series)
+    return [
+        None if v is None or v is pandas.NA or v is pandas.NaT or (isinstance(v, float) and v != v) else v
+        for v in values
+    ]
+
+# internal use for checking whether the interpreter is still usable after a call,
+# which is not once the import hook or the builtins removed by the sandbox are restored
+import builtins as _builtins
+
+_import_hook = None
+_removed_builtins = ()
+
+def _check_health():
+    if _import_hook is not None and _builtins.__import__ is not _import_hook:
+        return False
+    return not any(hasattr(_builtins, name) for name in _removed_builtins)
+
+# internal use for removing the module created from the code of a function,
+# whose file name is the name of the function
+def _unload(name):
+    import sys
+    module = sys.modules.get(name)
+    if getattr(module, '__file__', None) == name:
+        del sys.modules[name]
+
+# internal use for decimal types, returns the string of the value scaled to an integer
+def _decimal_to_int(value, scale):
+    # enough digits for 256-bit decimals, ties are rounded away from zero
+    context = decimal.Context(prec=100, rounding=decimal.ROUND_HALF_UP)
+    value = value if isinstance(value, decimal.Decimal) else decimal.Decimal(value)
+    if not value.is_finite():
+        raise ValueError(f"can not convert {value} to decimal")
+    return str(int(context.to_integral_value(context.scaleb(value, scale))))
+
+# internal use for temporal types, where `unit` is the number of values per second
+import datetime
+
+_EPOCH = datetime.datetime(1970, 1, 1)
+_EPOCH_ORDINAL = _EPOCH.toordinal()
+
+def _to_date(days):
+    return datetime.date.fromordinal(days + _EPOCH_ORDINAL)
+
+def _from_date(value, unit):
+    return (value.toordinal() - _EPOCH_ORDINAL) * unit
+
+def _to_time(value, unit):
+    seconds, fraction = divmod(value, unit)
+    minutes, second = divmod(seconds, 60)
+    hour, minute = divmod(minutes, 60)
+    return datetime.time(hour, minute, second, fraction * 1000000 // unit)
+
+def _from_time(value, unit):
+    seconds = (value.hour * 60 + value.minute) * 60 + value.second
+    return seconds * unit + value.microsecond * unit // 1000000
+
+# `zoneinfo` can't be imported in sub-interpreters, so only offsets are supported
+def _timezone(tz):
+    if tz[:1] in ('+', '-'):
+        sign = -1 if tz[0] == '-' else 1
+        hours, minutes = int(tz[1:3]), int(tz[-2:]) if len(tz) > 3 else 0
+        return datetime.timezone(sign * datetime.timedelta(hours=hours, minutes=minutes))
+    if tz.upper() in ('UTC', 'Z'):
+        return datetime.timezone.utc
+    raise ValueError(f"unsupported time zone {tz!r}, expect UTC or an offset like '+08:00'")
+
+# `mode` is 0 for aware values in the time zone, 1 for aware values in UTC, or 2 for naive values in UTC
+def _to_datetime(value, unit, tz, mode=0):
+    seconds, fraction = divmod(value, unit)
+    delta = datetime.timedelta(seconds=seconds, microseconds=fraction * 1000000 // unit)
+    if tz is None or mode == 2:
+        return _EPOCH + delta
+    utc = _EPOCH.replace(tzinfo=datetime.timezone.utc) + delta
+    return utc if mode == 1 else utc.astimezone(_timezone(tz))
+
+# naive values of timestamps with time zones are in UTC if `naive` is 0, in the time zone if 1,
+# or rejected if 2
+def _from_datetime(value, unit, tz=None, naive=0):
+    if value.tzinfo is None and tz is not None:
+        if naive == 1:
+            value = value.replace(tzinfo=_timezone(tz))
+        elif naive == 2:
+            raise ValueError(f"expect an aware datetime for timestamps in time zone {tz}, got a naive datetime")
+    # values with time zones are converted to UTC
+    if value.tzinfo is not None:
+        value = value.astimezone(datetime.timezone.utc).replace(tzinfo=None)
+    delta = value - _EPOCH
+    return (delta.days * 86400 + delta.seconds) * unit + delta.microseconds * unit // 1000000
+
+def _to_timedelta(value, unit):
+    seconds, fraction = divmod(value, unit)
+    return datetime.timedelta(seconds=seconds, microseconds=fraction * 1000000 // unit)
+
+def _from_timedelta(value, unit):
+    seconds = value.days * 86400 + value.seconds
+    return seconds * unit + value.microseconds * unit // 1000000
+
+# an internal class used for interval input arguments
+from collections import namedtuple
+
+Interval = namedtuple('Interval', ['months', 'days', 'nanoseconds'])
+
+# internal use for interval types, returns a tuple of months, days and nanoseconds
+def _from_interval(value):
+    if isinstance(value, datetime.timedelta):
+        return (0, value.days, (value.seconds * 1000000 + value.microseconds) * 1000)
+    months, days, nanoseconds = value
+    return (months, days, nanoseconds)
+
+# internal use for custom type converters, a tuple of `to_python` and `from_python` by their ids,
+# where the missing one keeps the values
+_converters = {}
+
+def _add_converter(id, module):
+    identity = lambda value: value
+    _converters[id] = (getattr(module, 'to_python', identity), getattr(module, 'from_python', identity))
+
+# internal use for capturing logs
+import sys
+
+class _LogWriter:
+    def __init__(self, records, level):
+        self.records = records
+        self.level = level
+        self.buffer = ''
+
+    def write(self, s):
+        lines = (self.buffer + s).split('\n')
+        self.buffer = lines.pop()
+        self.records.extend((self.level, line) for line in lines)
+        return len(s)
+
+    def flush(self):
+        if self.buffer:
+            self.records.append((self.level, self.buffer))
+            self.buffer = ''
+
+class _LogCapture:
+    def __init__(self):
+        self.records = []
+        # the levels of `logging.INFO` and `logging.WARNING`
+        self.stdout = _LogWriter(self.records, 20)
+        self.stderr = _LogWriter(self.records, 30)
+        self.handler = None
+
+    def enable(self):
+        sys.stdout, sys.stderr = self.stdout, self.stderr
+        # functions can not use `logging` in the sandbox, where it can not be imported
+        try:
+            import logging
+        except ImportError:
+            return
+        if self.handler is None:
+            records = self.records
+            class Handler(logging.Handler):
+                def emit(self, record):
+                    records.append((record.levelno, self.format(record)))
+            self.handler = Handler(logging.DEBUG)
+        logging.getLogger().addHandler(self.handler)
+        logging.getLogger().setLevel(logging.DEBUG)
+
+    def disable(self):
+        sys.stdout, sys.stderr = sys.__stdout__, sys.__stderr__
+        if self.handler is not None:
+            logging = sys.modules['logging']
+            logging.getLogger().removeHandler(self.handler)
+            logging.getLogger().setLevel(logging.WARNING)
+
+    def drain(self):
+        self.stdout.flush()
+        self.stderr.flush()
+        records = self.records[:]
+        self.records.clear()
+        return records
+
+_log_capture = _LogCapture()
+"#,
+        )?;
+        for path in &self.paths {
+            interpreter.with_gil(|py| {
+                let sys_path = py.import_bound("sys")?.getattr("path")?;
+                sys_path.call_method1("append", (path,))?;
+                Ok(())
+            })?;
+        }
+        for module in &self.preloaded_modules {
+            interpreter.run(&format!("import {module}"))?;
+        }
+        if self.sandboxed {
+            let allowed_modules: String = (SANDBOX_ALLOWED_MODULES.iter().copied())
+                .chain(self.allowed_modules.iter().map(String::as_str))
+                .chain(self.preloaded_modules.iter().map(String::as_str))
+                .map(|module| format!("'{module}',"))
+                .collect();
+            let mut script = r#"
+# limit the modules that can be imported
+original_import = __builtins__.__import__
+
+def limited_import(name, globals=None, locals=None, fromlist=(), level=0):
+    if level == 0 and name in ALLOWED_MODULES:
+        return original_import(name, globals, locals, fromlist, level)
+    raise ImportError(f'import {name} is not allowed')
+
+__builtins__.__import__ = limited_import
+del limited_import
+"#
+            .replace("ALLOWED_MODULES", &format!("({allowed_modules})"));
+            let mut removed_symbols: Vec<&str> = vec![];
+            for symbol in (SANDBOX_REMOVED_SYMBOLS.iter().copied())
+                .filter(|&s| self.log_sink.is_none() || s != "__builtins__.print")
+                .chain(self.removed_symbols.iter().map(String::as_str))
+            {
+                if !removed_symbols.contains(&symbol)
+                    && !self.kept_symbols.iter().any(|s| s == symbol)
+                {
+                    removed_symbols.push(symbol);
+                }
+            }
+            for symbol in &removed_symbols {
+                script.push_str(&format!("del {}\n", symbol));
+            }
+            // the sandbox is broken once they are restored
+            let removed_builtins: String = (removed_symbols.iter())
+                .filter_map(|symbol| symbol.strip_prefix("__builtins__."))
+                .map(|name| format!("'{name}',"))
+                .collect();
+            script.push_str(&format!(
+                "_import_hook = __builtins__.__import__\n_removed_builtins = ({removed_builtins})\n"
+            ));
+            interpreter.run(&script)?;
+        }
+        Ok(Instance {
+            interpreter,
+            functions: HashMap::new(),
+            aggregates: HashMap::new(),
+            overloaded: HashSet::new(),
+            aliases: HashMap::new(),
+            converters: HashMap::new(),
+            logs: None,
+            broken: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Returns true if the name is a dotted path of Python identifiers.
+fn is_module_name(name: &str) -> bool {
+    name.split('.').all(|part| {
+        part.chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+impl Runtime {
+    /// Create a new `Runtime`.
+    pub fn new() -> Result<Self> {
+        Builder::default().build()
+    }
+
+    /// Return a new builder for `Runtime`.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Set how nulls inside nested arguments are handled for functions that return null on null input.
+    pub fn set_nested_null_policy(&mut self, policy: NestedNullPolicy) {
+        self.nested_nulls = policy;
+    }
+
+    /// Set how arguments of timestamps with time zones, i.e. `Timestamp(_, Some(tz))`, are converted.
+    ///
+    /// By default, they are aware `datetime` in the time zone of the type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, TimestampArgument};
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_timestamp_argument(TimestampArgument::Utc);
+    /// ```
+    pub fn set_timestamp_argument(&mut self, argument: TimestampArgument) {
+        self.converter.set_timestamp_argument(argument);
+    }
+
+    /// Set how naive `datetime` returned for timestamps with time zones are interpreted.
+    ///
+    /// By default, they are the wall clock in UTC. Aware `datetime` are always converted to UTC.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{NaiveTimestampReturn, Runtime};
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_naive_timestamp_return(NaiveTimestampReturn::Reject);
+    /// ```
+    pub fn set_naive_timestamp_return(&mut self, naive: NaiveTimestampReturn) {
+        self.converter.set_naive_timestamp_return(naive);
+    }
+
+    /// Set whether the struct output of a scalar function is expanded into a column for each field.
+    ///
+    /// By default, the output is a single `StructArray` column named by the function.
+    /// Once expanded, the output batch has a column for each field of the struct,
+    /// and the error column, if any, comes after them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{RecordBatch, StringArray};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_expand_struct(true);
+    /// let return_type = DataType::Struct(
+    ///     vec![
+    ///         Field::new("key", DataType::Utf8, true),
+    ///         Field::new("value", DataType::Utf8, true),
+    ///     ]
+    ///     .into(),
+    /// );
+    /// runtime
+    ///     .add_function(
+    ///         "key_value",
+    ///         return_type,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def key_value(s):\n    return tuple(s.split('='))",
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    /// let arg0 = StringArray::from(vec!["a=1"]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// let output = runtime.call("key_value", &input).unwrap();
+    /// assert_eq!(output.schema().field(0).name(), "key");
+    /// assert_eq!(output.schema().field(1).name(), "value");
+    /// ```
+    pub fn set_expand_struct(&mut self, expand: bool) {
+        self.expand_struct = expand;
+    }
+
+    /// Set whether arguments and return values that fail to be converted are reported
+    /// in the error column of their rows, like exceptions, instead of failing the whole call.
+    ///
+    /// By default, a value of a wrong type fails the whole call,
+    /// since it is usually a bug of the function rather than of the data.
+    pub fn set_row_conversion_errors(&mut self, enabled: bool) {
+        self.row_conversion_errors = enabled;
+    }
+
+    /// Set how rows of scalar, table and window functions that raise exceptions are reported,
+    /// including those failing to be converted if [`set_row_conversion_errors`](Self::set_row_conversion_errors) is enabled.
+    ///
+    /// By default, they are reported in the `error` column.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, RowErrorPolicy, Runtime, SUPPRESSED_ERRORS_KEY};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function("inv", DataType::Int32, CallMode::ReturnNullOnNullInput, "def inv(x): return 100 // x")
+    ///     .unwrap();
+    /// runtime.set_row_error_policy(RowErrorPolicy::Null);
+    ///
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![0, 10]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// let output = runtime.call("inv", &input).unwrap();
+    /// assert_eq!(&**output.column(0), &Int32Array::from(vec![None, Some(10)]));
+    /// assert_eq!(output.schema().metadata()[SUPPRESSED_ERRORS_KEY], "1");
+    /// ```
+    pub fn set_row_error_policy(&mut self, policy: RowErrorPolicy) {
+        self.row_error_policy = policy;
+    }
+
+    /// Set whether the Python objects converted from the values of dictionary-encoded arguments
+    /// are reused for rows with the same key in a scalar function call.
+    ///
+    /// Dictionary arguments are always resolved row by row without being cast to plain arrays.
+    /// Caching saves converting repeated values again, e.g. strings of low cardinality,
+    /// but rows then share the same objects, so a function mutating a list or dict argument
+    /// changes it for the other rows.
+    pub fn set_dictionary_cache(&mut self, enabled: bool) {
+        self.dictionary_cache = enabled;
+    }
+
+    /// Set whether the arguments of scalar functions are passed as keyword arguments
+    /// named by the fields of the input, instead of by their positions.
+    ///
+    /// The columns of the input then don't have to be in the order of the parameters,
+    /// but their names must match the parameters, or the function accepts `**kwargs`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{RecordBatch, Int32Array};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_keyword_arguments(true);
+    /// runtime
+    ///     .add_function(
+    ///         "sub",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def sub(a, b):\n    return a - b",
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![
+    ///     Field::new("b", DataType::Int32, true),
+    ///     Field::new("a", DataType::Int32, true),
+    /// ]);
+    /// let arg0 = Int32Array::from(vec![1]);
+    /// let arg1 = Int32Array::from(vec![3]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    /// let output = runtime.call("sub", &input).unwrap();
+    /// assert_eq!(output.column(0).as_ref(), &Int32Array::from(vec![2]));
+    /// ```
+    pub fn set_keyword_arguments(&mut self, enabled: bool) {
+        self.keyword_arguments = enabled;
+    }
+
+    /// Set the maximum number of rows in each output batch, at least 1, or `None` for no limit.
+    ///
+    /// Table functions yield batches of at most this number of rows, even with a larger `chunk_size`,
+    /// and [`call_stream`](Runtime::call_stream) evaluates input batches in slices of this number of rows,
+    /// so that functions yielding many rows or wide structs don't build huge batches at once.
+    /// The output of [`call`](Runtime::call) always has a row for each input row.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "series",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def series(n):\n    yield from range(n)",
+    ///     )
+    ///     .unwrap();
+    /// runtime.set_max_output_rows(Some(100));
+    ///
+    /// let schema = Schema::new(vec![Field::new("n", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![1000]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// let outputs = runtime.call_table_function("series", &input, 1024).unwrap();
+    /// assert!(outputs.map(|batch| batch.unwrap().num_rows()).all(|rows| rows <= 100));
+    /// ```
+    pub fn set_max_output_rows(&mut self, rows: Option<usize>) {
+        self.max_output_rows = rows.map(|rows| rows.max(1));
+    }
+
+    /// Set the timeout of each function call.
+    ///
+    /// Once the timeout expires, the running Python code is interrupted by raising `KeyboardInterrupt` in it,
+    /// and the call fails with [`Error::ResourceExceeded`]. A watchdog thread is started for each call.
+    /// For table functions, the timeout applies to each output batch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// # use std::time::Duration;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_timeout(Some(Duration::from_secs(1)));
+    /// ```
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Set the limit of the CPU time used by each function call.
+    ///
+    /// Unlike the [timeout](Runtime::set_timeout), the time spent waiting, e.g. for I/O, sleeping or
+    /// other calls holding the GIL, is not counted. Once the CPU time of the thread running the call
+    /// exceeds the limit, the running Python code is interrupted by raising `KeyboardInterrupt` in it,
+    /// and the call fails with [`Error::ResourceExceeded`]. The CPU time is sampled every 10ms by a
+    /// watchdog thread. For table functions, the limit applies to each output batch.
+    ///
+    /// This is only supported on Linux.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// # use std::time::Duration;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_cpu_time_limit(Some(Duration::from_secs(1))).unwrap();
+    /// ```
+    pub fn set_cpu_time_limit(&mut self, limit: Option<Duration>) -> Result<()> {
+        if limit.is_some() && !cpu_time::is_supported() {
+            return Err(anyhow!("CPU time limit is only supported on Linux").into());
+        }
+        self.cpu_time_limit = limit;
+        Ok(())
+    }
+
+    /// Set the memory limit of the runtime in bytes.
+    ///
+    /// Allocations of the interpreter beyond the limit raise `MemoryError` in the running Python code,
+    /// which fails the row like any other exception.
+    ///
+    /// This fails unless the runtime is built with [`Builder::memory_accounting`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder().memory_accounting(true).build().unwrap();
+    /// runtime.set_memory_limit(Some(16 << 20)).unwrap(); // 16MB
+    /// ```
+    pub fn set_memory_limit(&self, limit: Option<usize>) -> Result<()> {
+        if !self.instance().interpreter.set_memory_limit(limit) {
+            return Err(anyhow!(
+                "memory limit requires the runtime to be built with memory accounting"
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes allocated by the interpreter,
+    /// or `None` unless the runtime is built with [`Builder::memory_accounting`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let runtime = Runtime::builder().memory_accounting(true).build().unwrap();
+    /// let usage = runtime.memory_usage();
+    /// ```
+    pub fn memory_usage(&self) -> Option<usize> {
+        self.instance().interpreter.memory_usage()
+    }
+
+    /// Set the sink of messages logged by functions.
+    ///
+    /// Once set, `print` and other writes to `sys.stdout` are logged as [`LogLevel::Info`],
+    /// writes to `sys.stderr` as [`LogLevel::Warn`], and records of all levels of the `logging` module,
+    /// which is imported once a sink is set, are logged by their levels.
+    /// Messages are delivered after each call.
+    /// Without a sink, the standard output and error of the process are used.
+    ///
+    /// Note that `print` is disabled in sandboxed runtimes, unless the sink is set by
+    /// [`Builder::log_sink`] or `print` is kept by [`Builder::keep_symbol`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// # use arrow_udf_core::LogLevel;
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime.set_log_sink(Some(Arc::new(|level: LogLevel, function: &str, message: &str| {
+    ///     eprintln!("[{level}] {function}: {message}");
+    /// })))
+    /// .unwrap();
+    /// ```
+    pub fn set_log_sink(&mut self, sink: Option<Arc<dyn UdfLogSink>>) -> Result<()> {
+        self.instance_mut().set_log_sink(sink)
+    }
+
+    /// Recreate the interpreter and load all functions again.
+    ///
+    /// The interpreter is restarted automatically before the next call once it is broken,
+    /// e.g. if a function restores the builtins removed by the sandbox, or if it runs out of memory
+    /// after a call fails. Module-level variables and the states of functions defined as classes
+    /// are initialized again. Calls running in the old interpreter complete before it is destroyed.
+    ///
+    /// Crashes of the process, e.g. aborts in C extensions, can not be recovered.
+    /// Use [`ProcessRuntime`](crate::process::ProcessRuntime) to isolate them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "neg",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def neg(x): return -x",
+    ///     )
+    ///     .unwrap();
+    /// runtime.restart().unwrap();
+    /// assert_eq!(runtime.restarts(), 1);
+    /// ```
+    pub fn restart(&self) -> Result<()> {
+        self.restart_if(|_| true)
+    }
+
+    /// Returns the number of times the interpreter has been restarted.
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Returns the metrics of calls to each function.
+    ///
+    /// Calls of scalar, table and window functions and accumulations of aggregate functions
+    /// are counted with their input and output rows, errors and the time spent in them.
+    /// Calls rejected before the function runs, e.g. of unknown functions, are not recorded.
+    /// Table function calls are recorded once their iterators are dropped.
+    /// The metrics are kept across restarts, and can be cleared by [`MetricsRegistry::reset`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "square",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def square(x):\n    return x * x",
+    ///     )
+    ///     .unwrap();
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// runtime.call("square", &input).unwrap();
+    ///
+    /// let metrics = runtime.metrics().get("square").unwrap();
+    /// assert_eq!((metrics.calls, metrics.errors, metrics.input_rows), (1, 0, 3));
+    /// // or as a batch with a row for each function
+    /// let batch = runtime.metrics().to_batch().unwrap();
+    /// assert_eq!(batch.num_rows(), 1);
+    /// ```
+    pub fn metrics(&self) -> &MetricsRegistry {
+        &self.metrics
+    }
+
+    /// Returns the token to cancel the calls running in the runtime, e.g. once the query is cancelled
+    /// in an engine that doesn't pass a token to each call by [`call_cancellable`](Runtime::call_cancellable).
+    ///
+    /// Cancelling the token interrupts the scalar, table, window and aggregate functions running or
+    /// waiting for the interpreter, and they fail with [`Error::Cancelled`]. Table functions called before
+    /// fail in their next batches. Calls started afterwards run with a new token.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "spin",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def spin(x):\n    while True:\n        pass",
+    ///     )
+    ///     .unwrap();
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![1]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    ///
+    /// let token = runtime.cancellation_token();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(Duration::from_millis(100));
+    ///     token.cancel();
+    /// });
+    /// let err = runtime.call("spin", &input).unwrap_err();
+    /// assert_eq!(err.to_string(), "cancelled");
+    /// ```
+    pub fn cancellation_token(&self) -> CancelToken {
+        let mut token = self.cancel.lock().unwrap_or_else(|e| e.into_inner());
+        if token.is_cancelled() {
+            *token = CancelToken::new();
+        }
+        token.clone()
+    }
+
+    /// Restart the interpreter if the condition holds on the current one.
+    fn restart_if(&self, condition: impl FnOnce(&Instance) -> bool) -> Result<()> {
+        let mut current = self.instance.write().unwrap_or_else(|e| e.into_inner());
+        if !condition(&current) {
+            return Ok(());
+        }
+        let mut instance = self.options.build_instance()?;
+        instance
+            .interpreter
+            .set_memory_limit(current.interpreter.memory_limit());
+        if let Some(logs) = &current.logs {
+            instance.set_log_sink(Some(logs.sink.clone()))?;
+        }
+        for (id, code) in &current.converters {
+            instance.load_converter(id, code)?;
+        }
+        instance.converters = current.converters.clone();
+        for (name, function) in &current.functions {
+            let (object, arity) = instance.load(name, &function.source)?;
+            let function = Function {
+                function: object,
+                return_field: function.return_field.clone(),
+                mode: function.mode,
+                convention: function.convention,
+                arity,
+                source: function.source.clone(),
+                validation: function.validation,
+                arg_types: function.arg_types.clone(),
+                volatility: function.volatility,
+            };
+            instance.functions.insert(name.clone(), function);
+        }
+        instance.overloaded = current.overloaded.clone();
+        instance.aliases = current.aliases.clone();
+        for (name, aggregate) in &current.aggregates {
+            let aggregate = instance.load_aggregate(
+                name,
+                aggregate.state_field.clone(),
+                aggregate.output_field.clone(),
+                aggregate.mode,
+                &aggregate.code,
+            )?;
+            instance.aggregates.insert(name.clone(), aggregate);
+        }
+        // the old interpreter is destroyed once the calls running in it complete
+        *current = Arc::new(instance);
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the interpreter and its functions, restarting the interpreter first if it is broken.
+    fn instance(&self) -> Arc<Instance> {
+        let instance = self
+            .instance
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if !instance.broken.load(Ordering::Relaxed) {
+            return instance;
+        }
+        // other threads may have restarted it
+        match self.restart_if(|current| current.broken.load(Ordering::Relaxed)) {
+            Ok(()) => self
+                .instance
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %e, "failed to restart the interpreter");
+                instance
+            }
+        }
+    }
+
+    /// Returns the interpreter and its functions to be modified.
+    fn instance_mut(&mut self) -> &mut Instance {
+        let instance = self.instance.get_mut().unwrap_or_else(|e| e.into_inner());
+        Arc::get_mut(instance).expect("no calls running while the runtime is borrowed mutably")
+    }
+
+    /// Run a closure in the interpreter for a function, interrupted once the timeout expires.
+    fn with_gil_timeout<F, R>(&self, instance: &Instance, function: &str, f: F) -> Result<R>
+    where
+        F: for<'py> FnOnce(Python<'py>) -> Result<R, PyError>,
+    {
+        self.with_cancellation(None, |token| instance.with_gil(function, token, f))
+    }
+
+    /// Run a closure with a token that is cancelled once the timeout expires, the CPU time limit is
+    /// exceeded, the given token is cancelled, or the calls of the runtime are cancelled by
+    /// [`cancellation_token`](Runtime::cancellation_token).
+    fn with_cancellation<R>(
+        &self,
+        token: Option<&CancelToken>,
+        f: impl FnOnce(Option<&CancelToken>) -> Result<R>,
+    ) -> Result<R> {
+        let limits = (self.timeout, self.cpu_time_limit);
+        with_cancellation(&self.cancellation_token(), limits, token, f)
+    }
+
+    /// Call `f` and record the call into [`Runtime::metrics`],
+    /// where `output_size` returns the number of rows and the memory size of the output.
+    fn record<T>(
+        &self,
+        name: &str,
+        input_rows: usize,
+        input_bytes: usize,
+        output_size: impl FnOnce(&T) -> (usize, usize),
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let output = f();
+        let mut stats = CallStats {
+            input_rows,
+            input_bytes,
+            elapsed: start.elapsed(),
+            error: output.is_err(),
+            ..Default::default()
+        };
+        if let Ok(output) = &output {
+            (stats.output_rows, stats.output_bytes) = output_size(output);
+        }
+        self.metrics.record(name, &stats);
+        output
+    }
+
+    /// Add a new scalar function or table function.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the function.
+    /// - `return_type`: The data type of the return value.
+    /// - `mode`: Whether the function will be called when some of its arguments are null.
+    /// - `code`: The Python code of the function.
+    ///
+    /// The code should define a function with the same name as the function.
+    /// The function should return a value for scalar functions, or yield values for table functions.
+    /// An existing scalar or table function of the same name is replaced.
+    ///
+    /// The code runs once when the function is added, so module-level variables persist across calls.
+    /// The name can also be a class, which is instantiated once without arguments,
+    /// and whose instance is called by `__call__` with the states initialized in `__init__`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// // add a scalar function
+    /// runtime
+    ///     .add_function(
+    ///         "gcd",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def gcd(a: int, b: int) -> int:
+    ///     while b:
+    ///         a, b = b, a % b
+    ///     return a
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// // add a table function
+    /// runtime
+    ///     .add_function(
+    ///         "series",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def series(n: int):
+    ///     for i in range(n):
+    ///         yield i
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.add_function_with_handler(name, return_type, mode, code, name)
+    }
+
+    /// Add a new scalar function or table function with custom handler name.
+    ///
+    /// # Arguments
+    ///
+    /// - `handler`: The name of function in Python code to be called.
+    /// - others: Same as [`add_function`].
+    ///
+    /// [`add_function`]: Runtime::add_function
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "add_function", level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_with_handler(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+        handler: &str,
+    ) -> Result<()> {
+        let return_field = return_type.into_field(name).into();
+        self.load_function(name, handler, return_field, mode, code, Convention::Row)
+    }
+
+    /// Add a new scalar function or table function with its properties.
+    ///
+    /// The volatility is reported by [`UdfRuntime::functions`](arrow_udf_core::UdfRuntime::functions),
+    /// e.g. for engines to fold calls of immutable functions into constants.
+    /// Rows of an immutable function with equal arguments are evaluated once in a call,
+    /// unless their arguments are not hashable in Python, e.g. lists.
+    ///
+    /// Others are the same as [`add_function`](Runtime::add_function).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, FunctionProperties, Runtime, Volatility};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// let properties = FunctionProperties::new()
+    ///     .with_mode(CallMode::ReturnNullOnNullInput)
+    ///     .with_volatility(Volatility::Immutable);
+    /// runtime
+    ///     .add_function_with_properties("square", DataType::Int64, properties, "def square(x): return x * x")
+    ///     .unwrap();
+    /// ```
+    pub fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> Result<()> {
+        self.add_function_with_handler_and_properties(name, return_type, properties, code, name)
+    }
+
+    /// Add a new scalar function or table function with its properties and custom handler name.
+    fn add_function_with_handler_and_properties(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        properties: FunctionProperties,
+        code: &str,
+        handler: &str,
+    ) -> Result<()> {
+        self.add_function_with_handler(name, return_type, properties.mode, code, handler)?;
+        if let Some(function) = self.instance_mut().functions.get_mut(name) {
+            function.volatility = properties.volatility;
+        }
+        Ok(())
+    }
+
+    /// Add the scalar functions and table functions decorated with `@udf` in a script,
+    /// and returns their names.
+    ///
+    /// The decorator is the one of the `arrow_udf` package of remote functions, so that the same script
+    /// can be served remotely or loaded here. These arguments of the decorator are read from the source,
+    /// which must be literals:
+    ///
+    /// - `result_type`: The SQL name of the return type, e.g. `INT`, `VARCHAR[]` or `STRUCT<a: INT>`.
+    /// - `name`: The name of the function. Defaults to the name of the Python function.
+    /// - `call_mode`: The [`CallMode`], e.g. `return_null_on_null_input`. Defaults to `called_on_null_input`.
+    /// - `volatility`: The [`Volatility`], e.g. `immutable`. Defaults to `volatile`.
+    ///
+    /// Other arguments are ignored, e.g. `input_types`, since the argument types are those of the input columns.
+    /// The decorators and the imports of `arrow_udf` are removed from the code, which does not need
+    /// the package to be installed. Only functions defined at the top level of the script are added.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// let names = runtime
+    ///     .add_functions_from_script(
+    ///         r#"
+    /// from arrow_udf import udf
+    ///
+    /// @udf(input_types=["INT", "INT"], result_type="INT", call_mode="return_null_on_null_input")
+    /// def gcd(x, y):
+    ///     while y != 0:
+    ///         (x, y) = (y, x % y)
+    ///     return x
+    ///
+    /// @udf(input_types=["VARCHAR"], result_type="VARCHAR[]", name="split_words")
+    /// def split(s):
+    ///     return s.split()
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// assert_eq!(names, ["gcd", "split_words"]);
+    /// ```
+    pub fn add_functions_from_script(&mut self, code: &str) -> Result<Vec<String>> {
+        let (functions, code) = self.instance().udf_functions(code)?;
+        // check all decorators before adding any function
+        let functions = (functions.into_iter())
+            .map(|(handler, arguments)| decorator::Decorated::new(handler, &arguments))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut names = Vec::with_capacity(functions.len());
+        for function in functions {
+            self.add_function_with_handler_and_properties(
+                &function.name,
+                function.return_field,
+                function.properties,
+                &code,
+                &function.handler,
+            )?;
+            names.push(function.name);
+        }
+        Ok(names)
+    }
+
+    /// Add an overload of a scalar function or table function for the argument types.
+    ///
+    /// Functions of the same name can be added for different argument types, e.g. `length` of strings and
+    /// of binaries, and calls are dispatched to the overload whose argument types equal the types of the input columns.
+    /// Calls with other types fall back to the function added by [`add_function`](Runtime::add_function)
+    /// if any, or fail with [`Error::TypeMismatch`].
+    ///
+    /// Each overload is stored by the name followed by its argument types, e.g. `length(Utf8)`,
+    /// by which it is listed, and can be configured or removed.
+    /// An existing overload of the same argument types is replaced.
+    ///
+    /// The code should define a function with the same name as the function.
+    /// Others are the same as [`add_function`](Runtime::add_function).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{BinaryArray, Int64Array, RecordBatch, StringArray};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_overload(
+    ///         "length",
+    ///         &[DataType::Utf8],
+    ///         DataType::Int64,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def length(s): return len(s)",
+    ///     )
+    ///     .unwrap();
+    /// runtime
+    ///     .add_overload(
+    ///         "length",
+    ///         &[DataType::Binary],
+    ///         DataType::Int64,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def length(b): return len(b) * 8",
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Binary, true)]);
+    /// let arg0 = BinaryArray::from(vec![b"ab".as_ref()]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// let output = runtime.call("length", &input).unwrap();
+    /// assert_eq!(&**output.column(0), &Int64Array::from(vec![16]));
+    ///
+    /// runtime.del_function("length(Binary)").unwrap();
+    /// assert!(runtime.call("length", &input).is_err());
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "add_function", level = "debug", skip_all, fields(function = name)))]
+    pub fn add_overload(
+        &mut self,
+        name: &str,
+        arg_types: &[DataType],
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let key = overload_key(name, arg_types);
+        let return_field = return_type.into_field(name).into();
+        self.load_function(&key, name, return_field, mode, code, Convention::Row)?;
+        let instance = self.instance_mut();
+        if let Some(function) = instance.functions.get_mut(&key) {
+            function.arg_types = Some(arg_types.to_vec());
+        }
+        instance.overloaded.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Add a new scalar function or table function, whose return type is inferred from the
+    /// return annotation of the Python function.
+    ///
+    /// These annotations are supported, including those of `typing`, and in strings:
+    ///
+    /// | Annotation                                   | Arrow Type                                |
+    /// | -------------------------------------------- | ----------------------------------------- |
+    /// | `bool`                                       | `Boolean`                                 |
+    /// | `int`                                        | `Int64`                                   |
+    /// | `float`                                      | `Float64`                                 |
+    /// | `str`                                        | `Utf8`                                    |
+    /// | `bytes`                                      | `Binary`                                  |
+    /// | `decimal.Decimal`                            | `Utf8` of the `arrowudf.decimal` extension |
+    /// | `datetime.date`                              | `Date32`                                  |
+    /// | `datetime.time`                              | `Time64(Microsecond)`                     |
+    /// | `datetime.datetime`                          | `Timestamp(Microsecond, None)`            |
+    /// | `datetime.timedelta`                         | `Duration(Microsecond)`                   |
+    /// | `list[T]`, `List[T]`, `Sequence[T]`          | `List(T)`                                 |
+    /// | `dict[K, V]`, `Dict[K, V]`, `Mapping[K, V]`  | `Map(K, V)`                               |
+    /// | `Optional[T]`, `T \| None`, `Union[T, None]` | `T`                                       |
+    ///
+    /// Table functions are annotated with the type of yielded values, e.g. `Iterator[int]`.
+    /// Returns [`Error::TypeMismatch`] if the function has no return annotation or it is not supported.
+    /// Others are the same as [`add_function`](Runtime::add_function).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_udf_core::UdfRuntime;
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function_with_inferred_type(
+    ///         "split",
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// from typing import Optional
+    ///
+    /// def split(s: str) -> Optional[list[str]]:
+    ///     return s.split(",") if s else None
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// let function = &runtime.functions()[0];
+    /// assert_eq!(
+    ///     function.return_field.as_ref().unwrap().data_type(),
+    ///     &DataType::new_list(DataType::Utf8, true)
+    /// );
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "add_function", level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_with_inferred_type(
+        &mut self,
+        name: &str,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let source = Source::Code {
+            code: code.to_string(),
+            handler: name.to_string(),
+        };
+        self.insert_function(name, None, mode, Convention::Row, source)
+    }
+
+    /// Set how the return values of a scalar, table or window function that are not of the Python type
+    /// of its return type are handled. See [`ReturnValidation`].
+    ///
+    /// The validation is reset to [`ReturnValidation::Cast`] once the function is replaced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, ReturnValidation, Runtime};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "half",
+    ///         DataType::Float64,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def half(x):\n    return x // 2 if x % 2 == 0 else x / 2",
+    ///     )
+    ///     .unwrap();
+    /// runtime.set_return_validation("half", ReturnValidation::Strict).unwrap();
+    ///
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![1, 2]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// let output = runtime.call("half", &input).unwrap();
+    /// // `int` is not accepted for floats
+    /// assert!(output.column(0).is_null(1));
+    /// assert_eq!(output.num_columns(), 2);
+    /// ```
+    pub fn set_return_validation(
+        &mut self,
+        name: &str,
+        validation: ReturnValidation,
+    ) -> Result<()> {
+        let function = (self.instance_mut().functions)
+            .get_mut(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        function.validation = validation;
+        Ok(())
+    }
+
+    /// Add a new scalar function called with lists of argument values for each batch.
+    ///
+    /// Instead of being called for each row, the function is called once for each batch
+    /// with a Python `list` of values for each argument, and should return a sequence of the same length.
+    /// This saves the overhead of calling Python for each row, which dominates cheap functions,
+    /// but every value is still converted to a Python object.
+    ///
+    /// Rows that are skipped, e.g. rows with null arguments in [`CallMode::ReturnNullOnNullInput`]
+    /// or rows that are not selected in [`call_selected`], are not passed to the function.
+    /// An exception fails the whole call.
+    ///
+    /// The values are Python objects converted in the same way as for other functions.
+    /// To receive Arrow arrays without converting the values, use [`add_function_arrow`] instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function_batched(
+    ///         "add",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def add(a: list, b: list) -> list:
+    ///     return [x + y for x, y in zip(a, b)]
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![
+    ///     Field::new("a", DataType::Int32, true),
+    ///     Field::new("b", DataType::Int32, true),
+    /// ]);
+    /// let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    /// let arg1 = Int32Array::from(vec![Some(2), Some(2), Some(4)]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    ///
+    /// let output = runtime.call("add", &input).unwrap();
+    /// assert_eq!(&**output.column(0), &Int32Array::from(vec![Some(3), None, Some(7)]));
+    /// ```
+    ///
+    /// [`add_function_arrow`]: Runtime::add_function_arrow
+    /// [`call_selected`]: Runtime::call_selected
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_batched(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let return_field = return_type.into_field(name).into();
+        self.load_function(name, name, return_field, mode, code, Convention::List)
+    }
+
+    /// Add a new scalar function called for each batch with buffers of primitive values.
+    ///
+    /// The function is called once for each batch with a read-only `memoryview` for each argument,
+    /// and should return an object supporting the buffer protocol with a value for each row,
+    /// e.g. an `array.array`, a `bytearray` or a `memoryview`.
+    /// Unlike [`add_function_batched`], no Python object is created for each value,
+    /// which speeds up numeric functions by orders of magnitude.
+    ///
+    /// Only integer and floating-point types are supported, for both arguments and the return type.
+    /// The function is called with all rows, where the values of null arguments are unspecified.
+    /// In [`CallMode::ReturnNullOnNullInput`], rows with null arguments are null in the output,
+    /// as are rows that are not selected in [`call_selected`]. An exception fails the whole call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_array::{Int32Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function_buffer(
+    ///         "add",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// from array import array
+    ///
+    /// def add(a, b):
+    ///     return array('i', map(sum, zip(a, b)))
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![
+    ///     Field::new("a", DataType::Int32, true),
+    ///     Field::new("b", DataType::Int32, true),
+    /// ]);
+    /// let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    /// let arg1 = Int32Array::from(vec![Some(2), Some(2), Some(4)]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    ///
+    /// let output = runtime.call("add", &input).unwrap();
+    /// assert_eq!(&**output.column(0), &Int32Array::from(vec![Some(3), None, Some(7)]));
+    /// ```
+    ///
+    /// [`add_function_batched`]: Runtime::add_function_batched
+    /// [`call_selected`]: Runtime::call_selected
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_buffer(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let return_field: FieldRef = return_type.into_field(name).into();
+        if buffer::buffer_format(return_field.data_type()).is_none() {
+            return Err(anyhow!(
+                "unsupported return type of buffer function: {}",
+                return_field.data_type()
+            )
+            .into());
+        }
+        self.load_function(name, name, return_field, mode, code, Convention::Buffer)
+    }
+
+    /// Add a new scalar function called for each batch with NumPy arrays.
+    ///
+    /// The function is called once for each batch with a read-only `numpy.ndarray` for each argument,
+    /// wrapping the same buffer as [`add_function_buffer`] without copying it,
+    /// and should return an array with a value for each row, which is cast to the return type.
+    /// Types and rows are supported in the same way as [`add_function_buffer`].
+    ///
+    /// NumPy can not be imported in the sub-interpreters of runtimes on Python 3.12 or later,
+    /// so this is only supported on Python 3.10 and 3.11, where functions run in the main interpreter,
+    /// and fails on later versions. The runtime imports `numpy` when calling the function,
+    /// so it must be installed, and allowed or preloaded in the sandbox.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function_numpy(
+    ///         "add",
+    ///         DataType::Int64,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def add(a, b):
+    ///     return a + b
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`add_function_buffer`]: Runtime::add_function_buffer
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_numpy(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        if cfg!(Py_3_12) {
+            return Err(anyhow!("NumPy functions require Python 3.10 or 3.11, since NumPy can not be imported in sub-interpreters").into());
+        }
+        let return_field: FieldRef = return_type.into_field(name).into();
+        if buffer::buffer_format(return_field.data_type()).is_none() {
+            return Err(anyhow!(
+                "unsupported return type of NumPy function: {}",
+                return_field.data_type()
+            )
+            .into());
+        }
+        self.load_function(name, name, return_field, mode, code, Convention::Numpy)
+    }
+
+    /// Add a new scalar function called for each batch with Arrow arrays.
+    ///
+    /// The function is called once for each batch with an array for each argument, which implements the
+    /// [Arrow PyCapsule Interface] and shares the buffers with the input through the Arrow C Data Interface.
+    /// For example, `pyarrow.array` wraps the arguments as `pyarrow.Array`s without copying them,
+    /// where `pyarrow` can be imported. The function should return an object implementing
+    /// `__arrow_c_array__` of the return type with a value for each row, whose buffers are not copied either.
+    /// Unlike [`add_function_batched`], values are never converted to Python objects,
+    /// which avoids copying every value of large string or binary columns.
+    ///
+    /// The function is called with all rows. In [`CallMode::ReturnNullOnNullInput`], rows with null arguments
+    /// are null in the output, as are rows that are not selected in [`call_selected`].
+    /// An exception fails the whole call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_array::{RecordBatch, StringArray};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function_arrow(
+    ///         "first",
+    ///         DataType::Utf8,
+    ///         CallMode::CalledOnNullInput,
+    ///         r#"
+    /// def first(a, b):
+    ///     return a
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![
+    ///     Field::new("a", DataType::Utf8, true),
+    ///     Field::new("b", DataType::Utf8, true),
+    /// ]);
+    /// let arg0 = StringArray::from(vec![Some("a"), None, Some("c")]);
+    /// let arg1 = StringArray::from(vec![Some("x"), Some("y"), Some("z")]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    ///
+    /// let output = runtime.call("first", &input).unwrap();
+    /// assert_eq!(&**output.column(0), &StringArray::from(vec![Some("a"), None, Some("c")]));
+    /// ```
+    ///
+    /// [Arrow PyCapsule Interface]: https://arrow.apache.org/docs/format/CDataInterface/PyCapsuleInterface.html
+    /// [`add_function_batched`]: Runtime::add_function_batched
+    /// [`call_selected`]: Runtime::call_selected
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_arrow(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let return_field = return_type.into_field(name).into();
+        self.load_function(name, name, return_field, mode, code, Convention::Arrow)
+    }
+
+    /// Add a new scalar function called with `pandas.Series`, like pandas UDFs of Spark.
+    ///
+    /// The function is called once for each batch with a `pandas.Series` for each argument,
+    /// and should return a `pandas.Series` or a sequence of the same length.
+    /// Missing values in the result, i.e. `None`, `NaN`, `pandas.NA` and `pandas.NaT`, are null.
+    /// Rows are passed to the function in the same way as [`add_function_batched`].
+    ///
+    /// pandas can not be imported in the sub-interpreters of runtimes on Python 3.12 or later,
+    /// so this is only supported on Python 3.10 and 3.11, where functions run in the main interpreter,
+    /// and fails on later versions. The runtime imports `pandas` when calling the function,
+    /// so it must be installed, and allowed or preloaded in the sandbox.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function_pandas(
+    ///         "add",
+    ///         DataType::Int64,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// import pandas as pd
+    ///
+    /// def add(a: pd.Series, b: pd.Series) -> pd.Series:
+    ///     return a + b
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`add_function_batched`]: Runtime::add_function_batched
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_pandas(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        if cfg!(Py_3_12) {
+            return Err(anyhow!("pandas functions require Python 3.10 or 3.11, since pandas can not be imported in sub-interpreters").into());
+        }
+        let return_field = return_type.into_field(name).into();
+        self.load_function(name, name, return_field, mode, code, Convention::Pandas)
+    }
+
+    /// Add a new window function.
+    ///
+    /// The function is called once for each partition with two arguments:
+    ///
+    /// - `partition`: A dict from the name of each column to a list of its values.
+    /// - `frames`: A list of `(start, end)` of each row, the half-open range of rows in its frame.
+    ///
+    /// It should return a sequence with a value for each row of the partition,
+    /// e.g. the rank of each row, or an aggregate over the rows of its frame.
+    /// Null values are passed to the function. An exception fails the whole call.
+    ///
+    /// Call the function with [`call_window`](Self::call_window).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_window_function(
+    ///         "moving_sum",
+    ///         DataType::Int64,
     ///         r#"
-    /// def series(n: int):
-    ///     for i in range(n):
-    ///         yield i
+    /// def moving_sum(partition, frames):
+    ///     values = partition["x"]
+    ///     return [sum(v for v in values[start:end] if v is not None) for start, end in frames]
     /// "#,
     ///     )
     ///     .unwrap();
     /// ```
-    pub fn add_function(
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_window_function(
         &mut self,
         name: &str,
         return_type: impl IntoField,
+        code: &str,
+    ) -> Result<()> {
+        let return_field = return_type.into_field(name).into();
+        let mode = CallMode::CalledOnNullInput;
+        self.load_function(name, name, return_field, mode, code, Convention::Window)
+    }
+
+    /// Load a function from the code and add it, replacing the function of the same name.
+    fn load_function(
+        &mut self,
+        name: &str,
+        handler: &str,
+        return_field: FieldRef,
         mode: CallMode,
         code: &str,
+        convention: Convention,
     ) -> Result<()> {
-        self.add_function_with_handler(name, return_type, mode, code, name)
+        let source = Source::Code {
+            code: code.to_string(),
+            handler: handler.to_string(),
+        };
+        self.insert_function(name, Some(return_field), mode, convention, source)
     }
 
-    /// Add a new scalar function or table function with custom handler name.
-    ///
-    /// # Arguments
-    ///
-    /// - `handler`: The name of function in Python code to be called.
-    /// - others: Same as [`add_function`].
+    /// Load a function and add it, replacing the function of the same name.
     ///
-    /// [`add_function`]: Runtime::add_function
-    pub fn add_function_with_handler(
+    /// The return field is inferred from the return annotation if it is `None`.
+    fn insert_function(
         &mut self,
         name: &str,
-        return_type: impl IntoField,
+        return_field: Option<FieldRef>,
         mode: CallMode,
-        code: &str,
-        handler: &str,
+        convention: Convention,
+        source: Source,
     ) -> Result<()> {
-        let function = self.interpreter.with_gil(|py| {
-            Ok(PyModule::from_code_bound(py, code, name, name)?
-                .getattr(handler)?
-                .into())
-        })?;
+        let instance = self.instance_mut();
+        let (function, arity) = instance.load(name, &source)?;
+        let return_field = match return_field {
+            Some(field) => field,
+            None => match infer_return_field(instance, name, &function) {
+                Ok(field) => field,
+                Err(e) => {
+                    instance.drop_in_interpreter(function);
+                    return Err(e);
+                }
+            },
+        };
         let function = Function {
             function,
-            return_field: return_type.into_field(name).into(),
+            return_field,
             mode,
+            convention,
+            arity,
+            source,
+            validation: ReturnValidation::default(),
+            arg_types: None,
+            volatility: Volatility::default(),
         };
-        self.functions.insert(name.to_string(), function);
+        instance.aliases.remove(name);
+        let replaced = instance.functions.insert(name.to_string(), function);
+        instance.drop_in_interpreter(replaced);
         Ok(())
     }
 
+    /// Add a new scalar function or table function from a pickled callable.
+    ///
+    /// The bytes are unpickled in the interpreter, e.g. closures pickled by `cloudpickle` in clients,
+    /// which requires `cloudpickle` to be installed and, in the sandbox, allowed.
+    /// Unpickling can run arbitrary code, so only load pickles from trusted sources.
+    ///
+    /// Others are the same as [`add_function`](Runtime::add_function).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// // `pickle.dumps(math.gcd, protocol=0)`
+    /// let bytes = b"cmath\ngcd\np0\n.";
+    /// runtime
+    ///     .add_function_from_pickle("gcd", DataType::Int32, CallMode::ReturnNullOnNullInput, bytes)
+    ///     .unwrap();
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_from_pickle(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let return_field = return_type.into_field(name).into();
+        let source = Source::Pickle(bytes.to_vec());
+        self.insert_function(name, Some(return_field), mode, Convention::Row, source)
+    }
+
     /// Add a new aggregate function from Python code.
     ///
     /// # Arguments
@@ -324,11 +2730,13 @@ impl Runtime {
     /// optionally, the code can define:
     ///
     /// - `finish(state) -> value`: Get the result of the aggregate function.
-    ///     If not defined, the state is returned as the result.
-    ///     In this case, `output_type` must be the same as `state_type`.
+    ///   If not defined, the state is returned as the result.
+    ///   In this case, `output_type` must be the same as `state_type`.
     /// - `retract(state, *args) -> state`: Retract a value from the state, returning the updated state.
     /// - `merge(state, state) -> state`: Merge two states, returning the merged state.
     ///
+    /// An existing aggregate function of the same name is replaced.
+    ///
     /// # Example
     ///
     /// ```
@@ -357,6 +2765,7 @@ impl Runtime {
     ///     )
     ///     .unwrap();
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name)))]
     pub fn add_aggregate(
         &mut self,
         name: &str,
@@ -365,48 +2774,210 @@ impl Runtime {
         mode: CallMode,
         code: &str,
     ) -> Result<()> {
-        let aggregate = self.interpreter.with_gil(|py| {
-            let module = PyModule::from_code_bound(py, code, name, name)?;
-            Ok(Aggregate {
-                state_field: state_type.into_field(name).into(),
-                output_field: output_type.into_field(name).into(),
-                mode,
-                create_state: module.getattr("create_state")?.into(),
-                accumulate: module.getattr("accumulate")?.into(),
-                retract: module.getattr("retract").ok().map(|f| f.into()),
-                finish: module.getattr("finish").ok().map(|f| f.into()),
-                merge: module.getattr("merge").ok().map(|f| f.into()),
-            })
-        })?;
+        let instance = self.instance_mut();
+        let aggregate = instance.load_aggregate(
+            name,
+            state_type.into_field(name).into(),
+            output_type.into_field(name).into(),
+            mode,
+            code,
+        )?;
         if aggregate.finish.is_none() && aggregate.state_field != aggregate.output_field {
-            bail!("`output_type` must be the same as `state_type` when `finish` is not defined");
+            instance.drop_in_interpreter(aggregate);
+            return Err(anyhow!(
+                "`output_type` must be the same as `state_type` when `finish` is not defined"
+            )
+            .into());
         }
-        self.aggregates.insert(name.to_string(), aggregate);
+        instance.aliases.remove(name);
+        let replaced = instance.aggregates.insert(name.to_string(), aggregate);
+        instance.drop_in_interpreter(replaced);
+        Ok(())
+    }
+
+    /// Add a custom converter of values of the extension type or the data type.
+    ///
+    /// The code defines `to_python(value)` to convert arguments and `from_python(obj)` to convert
+    /// return values, either of which can be missing to keep the values. They are applied after and before
+    /// the conversion of the data type, e.g. `bytes` for binaries, so that engine-specific types like UUIDs,
+    /// geometries or vectors can be passed as Python objects. Null values are not converted.
+    ///
+    /// Converters of extension types apply to fields with the name in the `ARROW:extension:name` metadata,
+    /// including the built-in extension types, and converters of data types apply to fields without the metadata.
+    /// A converter replaces the previous converter of the same type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_type_converter(
+    ///         "arrow.uuid",
+    ///         r#"
+    /// import uuid
+    ///
+    /// def to_python(value):
+    ///     return uuid.UUID(bytes=value)
+    ///
+    /// def from_python(obj):
+    ///     return obj.bytes
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn add_type_converter(&mut self, key: impl Into<ConverterKey>, code: &str) -> Result<()> {
+        let key = key.into();
+        let id = key.id();
+        let instance = self.instance_mut();
+        instance.load_converter(&id, code)?;
+        instance.converters.insert(id.clone(), code.to_string());
+        self.converter.add_custom(key, id);
         Ok(())
     }
 
     /// Remove a scalar or table function.
     pub fn del_function(&mut self, name: &str) -> Result<()> {
-        let function = self.functions.remove(name).context("function not found")?;
-        _ = self.interpreter.with_gil(|_| {
-            drop(function);
-            Ok(())
-        });
-        Ok(())
+        let instance = self.instance_mut();
+        let function = instance
+            .functions
+            .remove(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        instance.aliases.retain(|_, target| target != name);
+        instance.unload(name, function)
     }
 
     /// Remove an aggregate function.
     pub fn del_aggregate(&mut self, name: &str) -> Result<()> {
-        let aggregate = self.functions.remove(name).context("function not found")?;
-        _ = self.interpreter.with_gil(|_| {
-            drop(aggregate);
+        let instance = self.instance_mut();
+        let aggregate = instance
+            .aggregates
+            .remove(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        instance.aliases.retain(|_, target| target != name);
+        instance.unload(name, aggregate)
+    }
+
+    /// Remove a scalar, table or aggregate function and its aliases, or an alias.
+    ///
+    /// The module created from the code of the function is removed from `sys.modules`,
+    /// so that it can be garbage collected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "neg",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def neg(x): return -x",
+    ///     )
+    ///     .unwrap();
+    /// runtime.remove_function("neg").unwrap();
+    /// assert!(runtime.remove_function("neg").is_err());
+    /// ```
+    pub fn remove_function(&mut self, name: &str) -> Result<()> {
+        let instance = self.instance_mut();
+        if instance.aliases.remove(name).is_some() {
+            return Ok(());
+        }
+        let (has_function, has_aggregate) = (
+            instance.functions.contains_key(name),
+            instance.aggregates.contains_key(name),
+        );
+        if !has_function && !has_aggregate {
+            return Err(Error::FunctionNotFound(name.to_string()));
+        }
+        if has_function {
+            self.del_function(name)?;
+        }
+        if has_aggregate {
+            self.del_aggregate(name)?;
+        }
+        Ok(())
+    }
+
+    /// Add another name of a scalar, table or aggregate function.
+    ///
+    /// The function is called by the alias without loading its code again.
+    /// Aliases of an alias are aliases of its function,
+    /// and aliases are removed along with their functions.
+    /// The alias must not be the name of a function, an overloaded function or another alias.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function(
+    ///         "length",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         "def length(s): return len(s)",
+    ///     )
+    ///     .unwrap();
+    /// runtime.add_alias("strlen", "length").unwrap();
+    /// assert!(runtime.add_alias("char_length", "len").is_err());
+    /// assert!(runtime.add_alias("strlen", "length").is_err());
+    /// ```
+    pub fn add_alias(&mut self, alias: &str, name: &str) -> Result<()> {
+        let instance = self.instance_mut();
+        let target = instance.aliases.get(name).map_or(name, String::as_str);
+        let exists = instance.functions.contains_key(target)
+            || instance.aggregates.contains_key(target)
+            || instance.overloaded.contains(target);
+        if !exists {
+            return Err(Error::FunctionNotFound(name.to_string()));
+        }
+        let taken = instance.functions.contains_key(alias)
+            || instance.aggregates.contains_key(alias)
+            || instance.overloaded.contains(alias)
+            || instance.aliases.contains_key(alias);
+        if taken {
+            return Err(anyhow!("function {alias} already exists").into());
+        }
+        let target = target.to_string();
+        instance.aliases.insert(alias.to_string(), target);
+        Ok(())
+    }
+
+    /// Remove all functions.
+    ///
+    /// Returns the error raised while releasing the functions in the interpreter, if any.
+    /// The functions are removed from the runtime anyway.
+    pub fn clear(&mut self) -> Result<()> {
+        let instance = self.instance_mut();
+        let mut names: Vec<_> = instance.functions.keys().cloned().collect();
+        names.extend(instance.aggregates.keys().cloned());
+        let removed = (
+            std::mem::take(&mut instance.functions),
+            std::mem::take(&mut instance.aggregates),
+        );
+        instance.overloaded.clear();
+        instance.aliases.clear();
+        instance.interpreter.with_gil(|py| {
+            drop(removed);
+            let unload = py.eval_bound("_unload", None, None)?;
+            for name in names {
+                unload.call1((name,))?;
+            }
             Ok(())
-        });
+        })?;
         Ok(())
     }
 
     /// Call a scalar function.
     ///
+    /// Rows that raise exceptions don't fail the whole call. They are null in the output, which is then
+    /// followed by an `error` column of type `Utf8` with their messages, and null for other rows.
+    /// See [`set_row_conversion_errors`](Self::set_row_conversion_errors) for values that fail to be converted.
+    ///
     /// # Example
     ///
     /// ```
@@ -426,49 +2997,558 @@ impl Runtime {
     /// assert_eq!(&**output.column(0), &Int32Array::from(vec![Some(5), None]));
     /// ```
     pub fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
-        let function = self.functions.get(name).context("function not found")?;
+        self.call_rows(name, &input.into(), None, None)
+    }
+
+    /// Call a scalar function on each batch of a reader, returning a reader of the outputs.
+    ///
+    /// Batches are read and evaluated lazily as the outputs are read, so that a whole scan can be
+    /// piped through the function. Input batches larger than [`set_max_output_rows`](Runtime::set_max_output_rows)
+    /// are evaluated in slices. All outputs end with the `error` column to have the same schema,
+    /// which is null in batches without failed rows, unless [`set_row_error_policy`](Runtime::set_row_error_policy)
+    /// doesn't report errors by the column. Errors of calls are returned as
+    /// [`ArrowError::ExternalError`] of the batch, and the following batches are still evaluated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::{CallMode, Runtime};
+    /// # use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_function("neg", DataType::Int32, CallMode::ReturnNullOnNullInput, "def neg(x): return -x")
+    ///     .unwrap();
+    ///
+    /// let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)]));
+    /// let batches = (0..3).map(|i| {
+    ///     let arg0 = Int32Array::from(vec![i; 1024]);
+    ///     RecordBatch::try_new(schema.clone(), vec![Arc::new(arg0)])
+    /// });
+    /// let input = RecordBatchIterator::new(batches, schema.clone());
+    ///
+    /// let outputs = runtime.call_stream("neg", input).unwrap();
+    /// assert_eq!(outputs.schema().field(0).name(), "neg");
+    /// let num_rows: usize = outputs.map(|batch| batch.unwrap().num_rows()).sum();
+    /// assert_eq!(num_rows, 3072);
+    /// ```
+    pub fn call_stream<'a>(
+        &'a self,
+        name: &'a str,
+        input: impl RecordBatchReader + 'a,
+    ) -> Result<impl RecordBatchReader + 'a> {
+        let instance = self.instance();
+        let key = instance.resolve(name, input.schema().fields())?;
+        let function = instance
+            .functions
+            .get(&*key)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        if function.convention == Convention::Window {
+            return Err(
+                anyhow!("window function can not be called as a scalar function: {name}").into(),
+            );
+        }
+        // the schema of an empty output with errors, unless they are not reported by the column
+        let field = &function.return_field;
+        let error = (self.row_error_policy == RowErrorPolicy::ErrorColumn)
+            .then(|| new_empty_array(&DataType::Utf8));
+        let output = output_batch(field, new_empty_array(field.data_type()), error)?;
+        let schema = match self.expand_struct {
+            true => expand_struct(output)?.schema(),
+            false => output.schema(),
+        };
+        let max_rows = self.max_output_rows;
+        let slices = input.flat_map(move |batch| match batch {
+            Ok(batch) => split_batch(batch, max_rows).into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        });
+        let outputs = slices.map({
+            let schema = schema.clone();
+            move |batch| {
+                let output =
+                    (self.call(name, &batch?)).map_err(|e| ArrowError::ExternalError(e.into()))?;
+                let mut columns = output.columns().to_vec();
+                if columns.len() < schema.fields().len() {
+                    columns.push(new_null_array(&DataType::Utf8, output.num_rows()));
+                }
+                RecordBatch::try_new(schema.clone(), columns)
+            }
+        });
+        Ok(RecordBatchIterator::new(outputs, schema))
+    }
+
+    /// Call a scalar function with arguments, some of which may be scalars broadcast to all rows.
+    ///
+    /// Scalars are read for each row without building arrays of the batch length.
+    pub fn call_broadcast(&self, name: &str, args: &Arguments) -> Result<RecordBatch> {
+        self.call_rows(name, args, None, None)
+    }
+
+    /// Call a scalar function, interrupting it once the token is cancelled.
+    ///
+    /// The running Python code is interrupted by raising `KeyboardInterrupt` in it.
+    /// Returns [`Error::Cancelled`] if the call is cancelled.
+    pub fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> Result<RecordBatch> {
+        token.check()?;
+        match self.call_rows(name, &input.into(), None, Some(token)) {
+            Err(_) if token.is_cancelled() => Err(Error::Cancelled),
+            result => result,
+        }
+    }
+
+    /// Call a scalar function on the selected rows of the input.
+    ///
+    /// Rows that are not selected, including those where `selection` is null,
+    /// are neither converted nor evaluated, and their outputs are null.
+    pub fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> Result<RecordBatch> {
+        arrow_udf_core::check_selection(input, selection)?;
+        self.call_rows(name, &input.into(), Some(selection), None)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "call", level = "debug", skip_all, fields(function = name, rows = input.num_rows(), convert_time = tracing::field::Empty, execute_time = tracing::field::Empty)))]
+    fn call_rows(
+        &self,
+        name: &str,
+        input: &Arguments,
+        selection: Option<&BooleanArray>,
+        token: Option<&CancelToken>,
+    ) -> Result<RecordBatch> {
+        let instance = self.instance();
+        let name = &*instance.resolve(name, input.fields())?;
+        let function = instance
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        if function.convention == Convention::Window {
+            return Err(
+                anyhow!("window function can not be called as a scalar function: {name}").into(),
+            );
+        }
+        if !self.keyword_arguments {
+            function.check_arity(name, input.num_columns())?;
+        }
+        let input_bytes = arguments_memory_size(input);
+        self.record(name, input.num_rows(), input_bytes, batch_size, || {
+            let output = self.with_cancellation(token, |token| match function.convention {
+                Convention::Row => {
+                    self.call_each_row(&instance, name, function, input, selection, token)
+                }
+                Convention::List | Convention::Pandas => {
+                    self.call_batched(&instance, name, function, input, selection, token)
+                }
+                Convention::Buffer | Convention::Numpy => {
+                    self.call_buffers(&instance, name, function, input, selection, token)
+                }
+                Convention::Arrow => {
+                    self.call_arrow(&instance, name, function, input, selection, token)
+                }
+                Convention::Window => unreachable!(),
+            })?;
+            let output = row_errors::apply(self.row_error_policy, output, 1, |i| i)?;
+            match self.expand_struct {
+                true => expand_struct(output),
+                false => Ok(output),
+            }
+        })
+    }
+
+    /// Call a function for each row to be evaluated.
+    fn call_each_row(
+        &self,
+        instance: &Instance,
+        name: &str,
+        function: &Function,
+        input: &Arguments,
+        selection: Option<&BooleanArray>,
+        token: Option<&CancelToken>,
+    ) -> Result<RecordBatch> {
         // convert each row to python objects and call the function
-        let (output, error) = self.interpreter.with_gil(|py| {
+        let mut times = CallTimes::default();
+        let result = instance.with_gil(name, token, |py| {
             let mut results = Vec::with_capacity(input.num_rows());
             let mut errors = vec![];
-            let mut row = Vec::with_capacity(input.num_columns());
+            let mut caches = self.dictionary_caches(input);
+            // results of immutable functions by the tuples of arguments
+            let memo =
+                (function.volatility == Volatility::Immutable).then(|| PyDict::new_bound(py));
             for i in 0..input.num_rows() {
-                if function.mode == CallMode::ReturnNullOnNullInput
-                    && input.columns().iter().any(|column| column.is_null(i))
-                {
+                if selection.is_some_and(|s| !s.is_valid(i) || !s.value(i)) {
                     results.push(py.None());
                     continue;
                 }
-                row.clear();
-                for (column, field) in input.columns().iter().zip(input.schema().fields()) {
-                    let pyobj = self.converter.get_pyobject(py, field, column, i)?;
-                    row.push(pyobj);
+                if (function.mode)
+                    .skips_row(self.nested_nulls, input.row_values(i))
+                    .map_err(|e| anyhow::Error::from(e.with_row(i)))?
+                {
+                    results.push(py.None());
+                    continue;
                 }
-                let args = PyTuple::new_bound(py, row.drain(..));
-                match function.function.call1(py, args) {
-                    Ok(result) => results.push(result),
+                let args = times.convert(|| {
+                    (input.columns().iter().zip(input.fields()).enumerate())
+                        .map(|(j, (column, field))| {
+                            self.get_argument(py, field, column, input.index(j, i), &mut caches[j])
+                        })
+                        .collect::<PyResult<Vec<_>>>()
+                });
+                let args = match args {
+                    Ok(args) => args,
+                    Err(e) if !self.row_conversion_errors => return Err(e.into()),
+                    Err(e) => {
+                        results.push(py.None());
+                        errors.push((i, e.to_string()));
+                        continue;
+                    }
+                };
+                let key = match &memo {
+                    Some(memo) => {
+                        let key = PyTuple::new_bound(py, &args);
+                        match memo.get_item(&key) {
+                            Ok(Some(result)) => {
+                                results.push(result.unbind());
+                                continue;
+                            }
+                            Ok(None) => Some(key),
+                            // unhashable arguments
+                            Err(_) => None,
+                        }
+                    }
+                    None => None,
+                };
+                match times
+                    .execute(|| self.call_with_args(py, &function.function, input.fields(), args))
+                {
+                    Ok(result) => {
+                        if let (Some(memo), Some(key)) = (&memo, key) {
+                            memo.set_item(key, &result)?;
+                        }
+                        results.push(result)
+                    }
+                    // the interrupt is not an error of this row
+                    Err(_) if token.is_some_and(|token| token.is_cancelled()) => {
+                        return Err(anyhow::Error::from(Error::Cancelled).into());
+                    }
                     Err(e) => {
                         results.push(py.None());
                         errors.push((i, e.to_string()));
                     }
                 }
-            }
-            let output = self
-                .converter
-                .build_array(&function.return_field, py, &results)?;
-            let error = build_error_array(input.num_rows(), errors);
-            Ok((output, error))
-        })?;
-        if let Some(error) = error {
-            let schema = Schema::new(vec![
-                function.return_field.clone(),
-                Field::new("error", DataType::Utf8, true).into(),
-            ]);
-            Ok(RecordBatch::try_new(Arc::new(schema), vec![output, error])?)
-        } else {
-            let schema = Schema::new(vec![function.return_field.clone()]);
-            Ok(RecordBatch::try_new(Arc::new(schema), vec![output])?)
+            }
+            let output =
+                times.convert(|| self.build_output(py, function, &mut results, &mut errors))?;
+            let error = build_error_array(input.num_rows(), errors);
+            Ok((output, error))
+        });
+        times.record();
+        let (output, error) = result?;
+        output_batch(&function.return_field, output, error)
+    }
+
+    /// Build the output array from the results of rows.
+    ///
+    /// If conversion errors are reported by rows, results that can't be converted to the return type
+    /// are replaced by null, with their errors recorded.
+    fn build_output(
+        &self,
+        py: Python<'_>,
+        function: &Function,
+        results: &mut [PyObject],
+        errors: &mut Vec<(usize, String)>,
+    ) -> PyResult<ArrayRef> {
+        let field = function.return_field.as_ref();
+        validation::validate(py, field, function.validation, results, errors)?;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("build_array", rows = results.len()).entered();
+        match self.converter.build_array(field, py, results) {
+            Err(_) if self.row_conversion_errors => {}
+            output => return output,
+        }
+        // find the rows that fail to be converted
+        for (i, result) in results.iter_mut().enumerate() {
+            let value = std::slice::from_ref(result);
+            if let Err(e) = self.converter.build_array(field, py, value) {
+                errors.push((i, e.to_string()));
+                *result = py.None();
+            }
+        }
+        errors.sort_by_key(|(i, _)| *i);
+        self.converter.build_array(field, py, results)
+    }
+
+    /// Returns an empty cache for each argument, used if the dictionary cache is enabled.
+    fn dictionary_caches(&self, input: &Arguments) -> Vec<Vec<Option<PyObject>>> {
+        (0..input.num_columns()).map(|_| vec![]).collect()
+    }
+
+    /// Convert an argument at a row to a Python object.
+    fn get_argument(
+        &self,
+        py: Python<'_>,
+        field: &Field,
+        array: &dyn Array,
+        i: usize,
+        cache: &mut Vec<Option<PyObject>>,
+    ) -> PyResult<PyObject> {
+        if self.dictionary_cache {
+            self.converter
+                .get_pyobject_cached(py, field, array, i, cache)
+        } else {
+            self.converter.get_pyobject(py, field, array, i)
+        }
+    }
+
+    /// Call a scalar function with the argument of each field, by name if keyword arguments are enabled.
+    fn call_with_args(
+        &self,
+        py: Python<'_>,
+        function: &PyObject,
+        fields: &[FieldRef],
+        args: Vec<PyObject>,
+    ) -> PyResult<PyObject> {
+        if !self.keyword_arguments {
+            return function.call1(py, PyTuple::new_bound(py, args));
+        }
+        let kwargs = PyDict::new_bound(py);
+        for (field, arg) in fields.iter().zip(args) {
+            kwargs.set_item(field.name(), arg)?;
+        }
+        function.call_bound(py, (), Some(&kwargs))
+    }
+
+    /// Call a batched or pandas function once with the rows to be evaluated.
+    fn call_batched(
+        &self,
+        instance: &Instance,
+        name: &str,
+        function: &Function,
+        input: &Arguments,
+        selection: Option<&BooleanArray>,
+        token: Option<&CancelToken>,
+    ) -> Result<RecordBatch> {
+        let mut times = CallTimes::default();
+        let result = instance.with_gil(name, token, |py| {
+            let mut rows = Vec::with_capacity(input.num_rows());
+            for i in 0..input.num_rows() {
+                if selection.is_some_and(|s| !s.is_valid(i) || !s.value(i)) {
+                    continue;
+                }
+                if (function.mode)
+                    .skips_row(self.nested_nulls, input.row_values(i))
+                    .map_err(|e| anyhow::Error::from(e.with_row(i)))?
+                {
+                    continue;
+                }
+                rows.push(i);
+            }
+            let pandas = function.convention == Convention::Pandas;
+            let mut args = Vec::with_capacity(input.num_columns());
+            let mut caches = self.dictionary_caches(input);
+            times.convert(|| {
+                for (j, (column, field)) in input.columns().iter().zip(input.fields()).enumerate() {
+                    let values = (rows.iter())
+                        .map(|&i| {
+                            self.get_argument(py, field, column, input.index(j, i), &mut caches[j])
+                        })
+                        .collect::<PyResult<Vec<_>>>()?;
+                    let values = PyList::new_bound(py, values).into_any();
+                    args.push(
+                        match pandas {
+                            true => py.eval_bound("_to_series", None, None)?.call1((values,))?,
+                            false => values,
+                        }
+                        .unbind(),
+                    );
+                }
+                PyResult::Ok(())
+            })?;
+            let values =
+                times.execute(|| self.call_with_args(py, &function.function, input.fields(), args));
+            let values = match values {
+                Ok(values) if pandas => times.convert(|| {
+                    PyResult::Ok(
+                        py.eval_bound("_from_series", None, None)?
+                            .call1((values,))?
+                            .unbind(),
+                    )
+                })?,
+                Ok(values) => values,
+                // the interrupt is not an error of the function
+                Err(_) if token.is_some_and(|token| token.is_cancelled()) => {
+                    return Err(anyhow::Error::from(Error::Cancelled).into());
+                }
+                Err(e) => return Err(anyhow::Error::from(Error::execution(e.to_string())).into()),
+            };
+            let values = times.convert(|| {
+                (values.bind(py).iter()?)
+                    .map(|value| value.map(Into::into))
+                    .collect::<PyResult<Vec<PyObject>>>()
+            })?;
+            if values.len() != rows.len() {
+                return Err(anyhow::Error::from(Error::execution(format!(
+                    "expect {} values from batched function, got {}",
+                    rows.len(),
+                    values.len()
+                )))
+                .into());
+            }
+            let mut results: Vec<PyObject> = (0..input.num_rows()).map(|_| py.None()).collect();
+            for (i, value) in rows.into_iter().zip(values) {
+                results[i] = value;
+            }
+            let mut errors = vec![];
+            let output =
+                times.convert(|| self.build_output(py, function, &mut results, &mut errors))?;
+            let error = build_error_array(input.num_rows(), errors);
+            Ok((output, error))
+        });
+        times.record();
+        let (output, error) = result?;
+        output_batch(&function.return_field, output, error)
+    }
+
+    /// Call a buffer or NumPy function once with all rows.
+    fn call_buffers(
+        &self,
+        instance: &Instance,
+        name: &str,
+        function: &Function,
+        input: &Arguments,
+        selection: Option<&BooleanArray>,
+        token: Option<&CancelToken>,
+    ) -> Result<RecordBatch> {
+        if let Some(field) =
+            (input.fields().iter()).find(|field| buffer::buffer_format(field.data_type()).is_none())
+        {
+            return Err(Error::TypeMismatch(format!(
+                "unsupported argument type of buffer function {name}: {}",
+                field.data_type()
+            )));
+        }
+        let num_rows = input.num_rows();
+        let valid: BooleanBuffer = (0..num_rows)
+            .map(|i| {
+                selection.is_none_or(|s| s.is_valid(i) && s.value(i))
+                    && !function.mode.is_null_input(input.row_values(i))
+            })
+            .collect();
+        let nulls = Some(NullBuffer::new(valid)).filter(|nulls| nulls.null_count() > 0);
+        let mut times = CallTimes::default();
+        let numpy = function.convention == Convention::Numpy;
+        let data_type = function.return_field.data_type();
+        let output = instance.with_gil(name, token, |py| {
+            let args = times.convert(|| {
+                (input.columns().iter())
+                    .map(|column| {
+                        let buffer = buffer::get_buffer(py, column, num_rows)?;
+                        Ok(match numpy {
+                            true => (py.eval_bound("_to_ndarray", None, None)?)
+                                .call1((buffer,))?
+                                .unbind(),
+                            false => buffer.unbind(),
+                        })
+                    })
+                    .collect::<PyResult<Vec<_>>>()
+            })?;
+            let values =
+                times.execute(|| self.call_with_args(py, &function.function, input.fields(), args));
+            let values = match values {
+                Ok(values) if numpy => times.convert(|| {
+                    let format = buffer::buffer_format(data_type).unwrap();
+                    PyResult::Ok(
+                        (py.eval_bound("_from_ndarray", None, None)?)
+                            .call1((values, format))?
+                            .unbind(),
+                    )
+                })?,
+                Ok(values) => values,
+                // the interrupt is not an error of the function
+                Err(_) if token.is_some_and(|token| token.is_cancelled()) => {
+                    return Err(anyhow::Error::from(Error::Cancelled).into());
+                }
+                Err(e) => return Err(anyhow::Error::from(Error::execution(e.to_string())).into()),
+            };
+            times.convert(|| {
+                buffer::build_buffer_array(py, data_type, values.bind(py), num_rows, nulls)
+            })
+        });
+        times.record();
+        output_batch(&function.return_field, output?, None)
+    }
+
+    /// Call an arrow function once with all rows.
+    fn call_arrow(
+        &self,
+        instance: &Instance,
+        name: &str,
+        function: &Function,
+        input: &Arguments,
+        selection: Option<&BooleanArray>,
+        token: Option<&CancelToken>,
+    ) -> Result<RecordBatch> {
+        let num_rows = input.num_rows();
+        let valid: BooleanBuffer = (0..num_rows)
+            .map(|i| {
+                selection.is_none_or(|s| s.is_valid(i) && s.value(i))
+                    && !function.mode.is_null_input(input.row_values(i))
+            })
+            .collect();
+        let nulls = Some(NullBuffer::new(valid)).filter(|nulls| nulls.null_count() > 0);
+        // scalars are repeated for each row
+        let input = input.materialize()?;
+        let mut times = CallTimes::default();
+        let output = instance.with_gil(name, token, |py| {
+            let args = times.convert(|| {
+                (input.columns().iter())
+                    .map(|column| Ok(pyarrow::get_arrow_array(py, column)?.unbind()))
+                    .collect::<PyResult<Vec<_>>>()
+            })?;
+            let values = times.execute(|| {
+                self.call_with_args(py, &function.function, input.schema().fields(), args)
+            });
+            let values = match values {
+                Ok(values) => values,
+                // the interrupt is not an error of the function
+                Err(_) if token.is_some_and(|token| token.is_cancelled()) => {
+                    return Err(anyhow::Error::from(Error::Cancelled).into());
+                }
+                Err(e) => return Err(anyhow::Error::from(Error::execution(e.to_string())).into()),
+            };
+            let array = times.convert(|| pyarrow::build_arrow_array(values.bind(py)))?;
+            Ok(array)
+        });
+        times.record();
+        let output = output?;
+        let data_type = function.return_field.data_type();
+        if output.data_type() != data_type {
+            return Err(Error::TypeMismatch(format!(
+                "expect {data_type} from arrow function {name}, got {}",
+                output.data_type()
+            )));
+        }
+        if output.len() != num_rows {
+            return Err(Error::execution(format!(
+                "expect {num_rows} values from arrow function, got {}",
+                output.len()
+            )));
         }
+        let output = match nulls {
+            Some(nulls) if output.data_type() != &DataType::Null => {
+                let nulls = NullBuffer::union(Some(&nulls), output.nulls());
+                make_array(output.to_data().into_builder().nulls(nulls).build()?)
+            }
+            _ => output,
+        };
+        output_batch(&function.return_field, output, None)
     }
 
     /// Call a table function.
@@ -497,6 +3577,7 @@ impl Runtime {
     /// | 2   | 2      |
     /// +-----+--------+"#.trim());
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows())))]
     pub fn call_table_function<'a>(
         &'a self,
         name: &'a str,
@@ -504,21 +3585,186 @@ impl Runtime {
         chunk_size: usize,
     ) -> Result<RecordBatchIter<'a>> {
         assert!(chunk_size > 0);
-        let function = self.functions.get(name).context("function not found")?;
+        let chunk_size = self
+            .max_output_rows
+            .map_or(chunk_size, |max| chunk_size.min(max));
+        let start = Instant::now();
+        let instance = self.instance();
+        let name = instance.resolve(name, input.schema().fields())?;
+        let function = instance
+            .functions
+            .get(&*name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        match function.convention {
+            Convention::Row => {}
+            Convention::Window => {
+                return Err(anyhow!(
+                    "window function can not be called as a table function: {name}"
+                )
+                .into())
+            }
+            _ => {
+                return Err(anyhow!(
+                    "batched function can not be called as a table function: {name}"
+                )
+                .into())
+            }
+        }
+        function.check_arity(&name, input.num_columns())?;
 
         // initial state
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("row", DataType::Int32, true).into(),
+            function.return_field.clone(),
+        ]));
         Ok(RecordBatchIter {
-            interpreter: &self.interpreter,
+            instance: instance.clone(),
+            name,
             input,
-            function,
-            schema: Arc::new(Schema::new(vec![
-                Field::new("row", DataType::Int32, true).into(),
-                function.return_field.clone(),
-            ])),
+            schema,
             chunk_size,
             row: 0,
             generator: None,
             converter: &self.converter,
+            nested_nulls: self.nested_nulls,
+            row_error_policy: self.row_error_policy,
+            timeout: self.timeout,
+            cpu_time_limit: self.cpu_time_limit,
+            cancel: self.cancellation_token(),
+            metrics: &self.metrics,
+            stats: CallStats {
+                input_rows: input.num_rows(),
+                input_bytes: slice_memory_size(input),
+                elapsed: start.elapsed(),
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Call a window function on a partition.
+    ///
+    /// `frames` is the half-open range of rows in the frame of each row,
+    /// or `None` if the frame of every row is the whole partition.
+    /// Returns a batch with the value of each row.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_python::Runtime;
+    /// # use arrow_array::{Int32Array, Int64Array, RecordBatch};
+    /// # use arrow_schema::{DataType, Field, Schema};
+    /// # use std::sync::Arc;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_window_function(
+    ///         "moving_sum",
+    ///         DataType::Int64,
+    ///         r#"
+    /// def moving_sum(partition, frames):
+    ///     values = partition["x"]
+    ///     return [sum(values[start:end]) for start, end in frames]
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![1, 2, 3, 4]);
+    /// let partition = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    ///
+    /// // the current row and the one before it
+    /// let frames = [0..1, 0..2, 1..3, 2..4];
+    /// let output = runtime.call_window("moving_sum", &partition, Some(&frames)).unwrap();
+    /// assert_eq!(&**output.column(0), &Int64Array::from(vec![1, 3, 5, 7]));
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = partition.num_rows(), convert_time = tracing::field::Empty, execute_time = tracing::field::Empty)))]
+    pub fn call_window(
+        &self,
+        name: &str,
+        partition: &RecordBatch,
+        frames: Option<&[Range<usize>]>,
+    ) -> Result<RecordBatch> {
+        let instance = self.instance();
+        let target = instance.aliases.get(name).map_or(name, String::as_str);
+        let function = instance
+            .functions
+            .get(target)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        if function.convention != Convention::Window {
+            return Err(anyhow!("not a window function: {name}").into());
+        }
+        function.check_arity(name, 2)?;
+        let num_rows = partition.num_rows();
+        if let Some(frames) = frames {
+            if frames.len() != num_rows {
+                return Err(anyhow!("expect {num_rows} frames, got {}", frames.len()).into());
+            }
+            if let Some((i, frame)) = (frames.iter().enumerate())
+                .find(|(_, frame)| frame.start > frame.end || frame.end > num_rows)
+            {
+                return Err(anyhow!(
+                    "frame {frame:?} of row {i} is out of the partition of {num_rows} rows"
+                )
+                .into());
+            }
+        }
+        let input = Arguments::from(partition);
+        let input_bytes = slice_memory_size(partition);
+        self.record(name, num_rows, input_bytes, batch_size, || {
+            let mut times = CallTimes::default();
+            let output = self.with_cancellation(None, |token| {
+                instance.with_gil(name, token, |py| {
+                    let columns = PyDict::new_bound(py);
+                    let mut caches = self.dictionary_caches(&input);
+                    times.convert(|| {
+                        for (j, (column, field)) in
+                            input.columns().iter().zip(input.fields()).enumerate()
+                        {
+                            let values = (0..num_rows)
+                                .map(|i| self.get_argument(py, field, column, i, &mut caches[j]))
+                                .collect::<PyResult<Vec<_>>>()?;
+                            columns.set_item(field.name(), PyList::new_bound(py, values))?;
+                        }
+                        PyResult::Ok(())
+                    })?;
+                    let frames = match frames {
+                        Some(frames) => (frames.iter())
+                            .map(|frame| (frame.start, frame.end))
+                            .collect::<Vec<_>>(),
+                        None => vec![(0, num_rows); num_rows],
+                    };
+                    let values = times.execute(|| function.function.call1(py, (columns, frames)));
+                    let values = match values {
+                        Ok(values) => values,
+                        // the interrupt is not an error of the function
+                        Err(_) if token.is_some_and(|token| token.is_cancelled()) => {
+                            return Err(anyhow::Error::from(Error::Cancelled).into());
+                        }
+                        Err(e) => {
+                            return Err(anyhow::Error::from(Error::execution(e.to_string())).into())
+                        }
+                    };
+                    let mut results = times.convert(|| {
+                        (values.bind(py).iter()?)
+                            .map(|value| value.map(Into::into))
+                            .collect::<PyResult<Vec<PyObject>>>()
+                    })?;
+                    if results.len() != num_rows {
+                        return Err(anyhow::Error::from(Error::execution(format!(
+                            "expect {num_rows} values from window function, got {}",
+                            results.len()
+                        )))
+                        .into());
+                    }
+                    let mut errors = vec![];
+                    let output = times
+                        .convert(|| self.build_output(py, function, &mut results, &mut errors))?;
+                    Ok((output, build_error_array(num_rows, errors)))
+                })
+            });
+            times.record();
+            let output = output?;
+            let output = output_batch(&function.return_field, output.0, output.1)?;
+            Ok(row_errors::apply(self.row_error_policy, output, 1, |i| i)?)
         })
     }
 
@@ -531,8 +3777,9 @@ impl Runtime {
     /// assert_eq!(&*state, &Int32Array::from(vec![0]));
     /// ```
     pub fn create_state(&self, name: &str) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
-        let state = self.interpreter.with_gil(|py| {
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
+        let state = self.with_gil_timeout(&instance, name, |py| {
             let state = aggregate.create_state.call0(py)?;
             let state = self
                 .converter
@@ -556,41 +3803,152 @@ impl Runtime {
     /// let state = runtime.accumulate("sum", &state, &input).unwrap();
     /// assert_eq!(&*state, &Int32Array::from(vec![9]));
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows())))]
     pub fn accumulate(
         &self,
         name: &str,
         state: &dyn Array,
         input: &RecordBatch,
     ) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
         // convert each row to python objects and call the accumulate function
-        let new_state = self.interpreter.with_gil(|py| {
-            let mut state = self
-                .converter
-                .get_pyobject(py, &aggregate.state_field, state, 0)?;
+        let input_bytes = slice_memory_size(input);
+        self.record(
+            name,
+            input.num_rows(),
+            input_bytes,
+            |_| (0, 0),
+            || {
+                self.with_gil_timeout(&instance, name, |py| {
+                    let mut state =
+                        self.converter
+                            .get_pyobject(py, &aggregate.state_field, state, 0)?;
 
-            let mut row = Vec::with_capacity(1 + input.num_columns());
-            for i in 0..input.num_rows() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput
-                    && input.columns().iter().any(|column| column.is_null(i))
-                {
-                    continue;
-                }
-                row.clear();
-                row.push(state.clone_ref(py));
-                for (column, field) in input.columns().iter().zip(input.schema().fields()) {
-                    let pyobj = self.converter.get_pyobject(py, field, column, i)?;
-                    row.push(pyobj);
-                }
-                let args = PyTuple::new_bound(py, row.drain(..));
-                state = aggregate.accumulate.call1(py, args)?;
-            }
-            let output = self
-                .converter
-                .build_array(&aggregate.state_field, py, &[state])?;
-            Ok(output)
-        })?;
-        Ok(new_state)
+                    let mut row = Vec::with_capacity(1 + input.num_columns());
+                    for i in 0..input.num_rows() {
+                        if (aggregate.mode)
+                            .skips_row(
+                                self.nested_nulls,
+                                input.columns().iter().map(|c| (c.as_ref(), i)),
+                            )
+                            .map_err(|e| anyhow::Error::from(e.with_row(i)))?
+                        {
+                            continue;
+                        }
+                        row.clear();
+                        row.push(state.clone_ref(py));
+                        for (column, field) in input.columns().iter().zip(input.schema().fields()) {
+                            let pyobj = self.converter.get_pyobject(py, field, column, i)?;
+                            row.push(pyobj);
+                        }
+                        let args = PyTuple::new_bound(py, row.drain(..));
+                        state = aggregate.accumulate.call1(py, args)?;
+                    }
+                    let output =
+                        self.converter
+                            .build_array(&aggregate.state_field, py, &[state])?;
+                    Ok(output)
+                })
+            },
+        )
+    }
+
+    /// Call accumulate of an aggregate function on the states of multiple groups.
+    ///
+    /// `states` has a state for each group, and `groups` has the index of the state of each input row.
+    /// Rows whose group is null are skipped. Returns the new states of all groups,
+    /// so that a query engine can accumulate a batch of a grouped aggregation in one call,
+    /// and ship the partial states to be merged elsewhere, e.g. by [`serialize_state`].
+    ///
+    /// [`serialize_state`]: arrow_udf_core::UdfRuntime::serialize_state
+    ///
+    /// # Example
+    /// ```
+    #[doc = include_str!("doc_create_aggregate.txt")]
+    /// use arrow_array::UInt32Array;
+    ///
+    /// let states = Int32Array::from(vec![0, 0]);
+    ///
+    /// let schema = Schema::new(vec![Field::new("value", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    /// let groups = UInt32Array::from(vec![0, 0, 1, 0]);
+    ///
+    /// let states = runtime.accumulate_batch("sum", &states, &groups, &input).unwrap();
+    /// assert_eq!(&*states, &Int32Array::from(vec![6, 3]));
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows())))]
+    pub fn accumulate_batch(
+        &self,
+        name: &str,
+        states: &dyn Array,
+        groups: &UInt32Array,
+        input: &RecordBatch,
+    ) -> Result<ArrayRef> {
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
+        if groups.len() != input.num_rows() {
+            return Err(anyhow!(
+                "groups length {} does not match the number of rows {}",
+                groups.len(),
+                input.num_rows()
+            )
+            .into());
+        }
+        if let Some(group) = groups
+            .iter()
+            .flatten()
+            .find(|&g| g as usize >= states.len())
+        {
+            return Err(anyhow!("group {group} out of bounds of {} states", states.len()).into());
+        }
+        let input_bytes = slice_memory_size(input);
+        self.record(
+            name,
+            input.num_rows(),
+            input_bytes,
+            |_| (0, 0),
+            || {
+                self.with_gil_timeout(&instance, name, |py| {
+                    let mut states = (0..states.len())
+                        .map(|i| {
+                            self.converter
+                                .get_pyobject(py, &aggregate.state_field, states, i)
+                        })
+                        .collect::<PyResult<Vec<_>>>()?;
+
+                    let mut row = Vec::with_capacity(1 + input.num_columns());
+                    for (i, group) in groups.iter().enumerate() {
+                        let Some(group) = group else {
+                            continue;
+                        };
+                        if (aggregate.mode)
+                            .skips_row(
+                                self.nested_nulls,
+                                input.columns().iter().map(|c| (c.as_ref(), i)),
+                            )
+                            .map_err(|e| anyhow::Error::from(e.with_row(i)))?
+                        {
+                            continue;
+                        }
+                        let state = &mut states[group as usize];
+                        row.clear();
+                        row.push(state.clone_ref(py));
+                        for (column, field) in input.columns().iter().zip(input.schema().fields()) {
+                            let pyobj = self.converter.get_pyobject(py, field, column, i)?;
+                            row.push(pyobj);
+                        }
+                        let args = PyTuple::new_bound(py, row.drain(..));
+                        *state = aggregate.accumulate.call1(py, args)?;
+                    }
+                    let output = self
+                        .converter
+                        .build_array(&aggregate.state_field, py, &states)?;
+                    Ok(output)
+                })
+            },
+        )
     }
 
     /// Call accumulate or retract of an aggregate function.
@@ -611,6 +3969,7 @@ impl Runtime {
     /// let state = runtime.accumulate_or_retract("sum", &state, &ops, &input).unwrap();
     /// assert_eq!(&*state, &Int32Array::from(vec![3]));
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows())))]
     pub fn accumulate_or_retract(
         &self,
         name: &str,
@@ -618,44 +3977,57 @@ impl Runtime {
         ops: &BooleanArray,
         input: &RecordBatch,
     ) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
         let retract = aggregate
             .retract
             .as_ref()
             .context("function does not support retraction")?;
         // convert each row to python objects and call the accumulate function
-        let new_state = self.interpreter.with_gil(|py| {
-            let mut state = self
-                .converter
-                .get_pyobject(py, &aggregate.state_field, state, 0)?;
+        let input_bytes = slice_memory_size(input);
+        self.record(
+            name,
+            input.num_rows(),
+            input_bytes,
+            |_| (0, 0),
+            || {
+                self.with_gil_timeout(&instance, name, |py| {
+                    let mut state =
+                        self.converter
+                            .get_pyobject(py, &aggregate.state_field, state, 0)?;
 
-            let mut row = Vec::with_capacity(1 + input.num_columns());
-            for i in 0..input.num_rows() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput
-                    && input.columns().iter().any(|column| column.is_null(i))
-                {
-                    continue;
-                }
-                row.clear();
-                row.push(state.clone_ref(py));
-                for (column, field) in input.columns().iter().zip(input.schema().fields()) {
-                    let pyobj = self.converter.get_pyobject(py, field, column, i)?;
-                    row.push(pyobj);
-                }
-                let args = PyTuple::new_bound(py, row.drain(..));
-                let func = if ops.is_valid(i) && ops.value(i) {
-                    retract
-                } else {
-                    &aggregate.accumulate
-                };
-                state = func.call1(py, args)?;
-            }
-            let output = self
-                .converter
-                .build_array(&aggregate.state_field, py, &[state])?;
-            Ok(output)
-        })?;
-        Ok(new_state)
+                    let mut row = Vec::with_capacity(1 + input.num_columns());
+                    for i in 0..input.num_rows() {
+                        if (aggregate.mode)
+                            .skips_row(
+                                self.nested_nulls,
+                                input.columns().iter().map(|c| (c.as_ref(), i)),
+                            )
+                            .map_err(|e| anyhow::Error::from(e.with_row(i)))?
+                        {
+                            continue;
+                        }
+                        row.clear();
+                        row.push(state.clone_ref(py));
+                        for (column, field) in input.columns().iter().zip(input.schema().fields()) {
+                            let pyobj = self.converter.get_pyobject(py, field, column, i)?;
+                            row.push(pyobj);
+                        }
+                        let args = PyTuple::new_bound(py, row.drain(..));
+                        let func = if ops.is_valid(i) && ops.value(i) {
+                            retract
+                        } else {
+                            &aggregate.accumulate
+                        };
+                        state = func.call1(py, args)?;
+                    }
+                    let output =
+                        self.converter
+                            .build_array(&aggregate.state_field, py, &[state])?;
+                    Ok(output)
+                })
+            },
+        )
     }
 
     /// Merge states of an aggregate function.
@@ -669,14 +4041,15 @@ impl Runtime {
     /// assert_eq!(&*state, &Int32Array::from(vec![9]));
     /// ```
     pub fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
         let merge = aggregate.merge.as_ref().context("merge not found")?;
-        let output = self.interpreter.with_gil(|py| {
+        let output = self.with_gil_timeout(&instance, name, |py| {
             let mut state = self
                 .converter
                 .get_pyobject(py, &aggregate.state_field, states, 0)?;
             for i in 1..states.len() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput && states.is_null(i) {
+                if aggregate.mode.skips_null_states() && states.is_null(i) {
                     continue;
                 }
                 let state2 = self
@@ -706,14 +4079,15 @@ impl Runtime {
     /// assert_eq!(&outputs, &states);
     /// ```
     pub fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
-        let aggregate = self.aggregates.get(name).context("function not found")?;
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
         let Some(finish) = &aggregate.finish else {
             return Ok(states.clone());
         };
-        let output = self.interpreter.with_gil(|py| {
+        let output = self.with_gil_timeout(&instance, name, |py| {
             let mut results = Vec::with_capacity(states.len());
             for i in 0..states.len() {
-                if aggregate.mode == CallMode::ReturnNullOnNullInput && states.is_null(i) {
+                if aggregate.mode.skips_null_states() && states.is_null(i) {
                     results.push(py.None());
                     continue;
                 }
@@ -735,9 +4109,10 @@ impl Runtime {
 
 /// An iterator over the result of a table function.
 pub struct RecordBatchIter<'a> {
-    interpreter: &'a SubInterpreter,
+    /// The interpreter of the function, kept until the iterator is dropped even if it is restarted.
+    instance: Arc<Instance>,
+    name: Cow<'a, str>,
     input: &'a RecordBatch,
-    function: &'a Function,
     schema: SchemaRef,
     chunk_size: usize,
     // mutable states
@@ -746,6 +4121,16 @@ pub struct RecordBatchIter<'a> {
     /// Generator of the current row.
     generator: Option<Py<PyIterator>>,
     converter: &'a pyarrow::Converter,
+    nested_nulls: NestedNullPolicy,
+    row_error_policy: RowErrorPolicy,
+    timeout: Option<Duration>,
+    cpu_time_limit: Option<Duration>,
+    /// The token of the runtime when the function is called, see [`Runtime::cancellation_token`].
+    cancel: CancelToken,
+    /// The metrics of the runtime, where the call is recorded once the iterator is dropped.
+    metrics: &'a MetricsRegistry,
+    /// Statistics of the call so far.
+    stats: CallStats,
 }
 
 impl RecordBatchIter<'_> {
@@ -758,102 +4143,156 @@ impl RecordBatchIter<'_> {
         if self.row == self.input.num_rows() {
             return Ok(None);
         }
-        let batch = self.interpreter.with_gil(|py| {
-            let mut indexes = Int32Builder::with_capacity(self.chunk_size);
-            let mut results = Vec::with_capacity(self.input.num_rows());
-            let mut errors = vec![];
-            let mut row = Vec::with_capacity(self.input.num_columns());
-            while self.row < self.input.num_rows() && results.len() < self.chunk_size {
-                let generator = if let Some(g) = self.generator.as_ref() {
-                    g
-                } else {
-                    // call the table function to get a generator
-                    if self.function.mode == CallMode::ReturnNullOnNullInput
-                        && (self.input.columns().iter()).any(|column| column.is_null(self.row))
-                    {
-                        self.row += 1;
-                        continue;
-                    }
-                    row.clear();
-                    for (column, field) in
-                        (self.input.columns().iter()).zip(self.input.schema().fields())
-                    {
-                        let val = self.converter.get_pyobject(py, field, column, self.row)?;
-                        row.push(val);
-                    }
-                    let args = PyTuple::new_bound(py, row.drain(..));
-                    match self.function.function.bind(py).call1(args) {
-                        Ok(result) => {
-                            let iter = result.iter()?.into();
-                            self.generator.insert(iter)
+        let instance = self.instance.clone();
+        let function = &instance.functions[&*self.name];
+        let limits = (self.timeout, self.cpu_time_limit);
+        let batch = with_cancellation(&self.cancel, limits, None, |token| {
+            instance.with_gil(&self.name, token, |py| {
+                let mut indexes = Int32Builder::with_capacity(self.chunk_size);
+                let mut results = Vec::with_capacity(self.input.num_rows());
+                let mut errors = vec![];
+                let mut row = Vec::with_capacity(self.input.num_columns());
+                while self.row < self.input.num_rows() && results.len() < self.chunk_size {
+                    let generator = if let Some(g) = self.generator.as_ref() {
+                        g
+                    } else {
+                        // call the table function to get a generator
+                        if (function.mode)
+                            .skips_row(
+                                self.nested_nulls,
+                                self.input.columns().iter().map(|c| (c.as_ref(), self.row)),
+                            )
+                            .map_err(|e| anyhow::Error::from(e.with_row(self.row)))?
+                        {
+                            self.row += 1;
+                            continue;
                         }
-                        Err(e) => {
-                            // append a row with null value and error message
+                        row.clear();
+                        for (column, field) in
+                            (self.input.columns().iter()).zip(self.input.schema().fields())
+                        {
+                            let val = self.converter.get_pyobject(py, field, column, self.row)?;
+                            row.push(val);
+                        }
+                        let args = PyTuple::new_bound(py, row.drain(..));
+                        match function.function.bind(py).call1(args) {
+                            Ok(result) => {
+                                let iter = result.iter()?.into();
+                                self.generator.insert(iter)
+                            }
+                            // the interrupt is not an error of this row
+                            Err(_) if token.is_some_and(|token| token.is_cancelled()) => {
+                                return Err(anyhow::Error::from(Error::Cancelled).into());
+                            }
+                            Err(e) => {
+                                // append a row with null value and error message
+                                indexes.append_value(self.row as i32);
+                                results.push(py.None());
+                                errors.push((indexes.len() - 1, e.to_string()));
+                                self.row += 1;
+                                continue;
+                            }
+                        }
+                    };
+                    match generator.bind(py).clone().next() {
+                        Some(Ok(value)) => {
+                            indexes.append_value(self.row as i32);
+                            results.push(value.into());
+                        }
+                        Some(Err(_)) if token.is_some_and(|token| token.is_cancelled()) => {
+                            return Err(anyhow::Error::from(Error::Cancelled).into());
+                        }
+                        Some(Err(e)) => {
                             indexes.append_value(self.row as i32);
                             results.push(py.None());
-                            errors.push((indexes.len(), e.to_string()));
+                            errors.push((indexes.len() - 1, e.to_string()));
                             self.row += 1;
-                            continue;
+                            self.generator = None;
+                        }
+                        None => {
+                            self.row += 1;
+                            self.generator = None;
                         }
-                    }
-                };
-                match generator.bind(py).clone().next() {
-                    Some(Ok(value)) => {
-                        indexes.append_value(self.row as i32);
-                        results.push(value.into());
-                    }
-                    Some(Err(e)) => {
-                        indexes.append_value(self.row as i32);
-                        results.push(py.None());
-                        errors.push((indexes.len(), e.to_string()));
-                        self.row += 1;
-                        self.generator = None;
-                    }
-                    None => {
-                        self.row += 1;
-                        self.generator = None;
                     }
                 }
-            }
 
-            if results.is_empty() {
-                return Ok(None);
-            }
-            let indexes = Arc::new(indexes.finish());
-            let output = self
-                .converter
-                .build_array(&self.function.return_field, py, &results)
-                .context("failed to build arrow array from return values")?;
-            let error = build_error_array(indexes.len(), errors);
-            if let Some(error) = error {
-                Ok(Some(
-                    RecordBatch::try_new(
-                        Arc::new(append_error_to_schema(&self.schema)),
-                        vec![indexes, output, error],
-                    )
-                    .unwrap(),
-                ))
-            } else {
-                Ok(Some(
-                    RecordBatch::try_new(self.schema.clone(), vec![indexes, output]).unwrap(),
-                ))
-            }
+                if results.is_empty() {
+                    return Ok(None);
+                }
+                let indexes = Arc::new(indexes.finish());
+                validation::validate(
+                    py,
+                    &function.return_field,
+                    function.validation,
+                    &mut results,
+                    &mut errors,
+                )?;
+                let output = self
+                    .converter
+                    .build_array(&function.return_field, py, &results)
+                    .context("failed to build arrow array from return values")?;
+                let error = build_error_array(indexes.len(), errors);
+                let schema =
+                    match pyarrow::promoted_field(&function.return_field, output.data_type()) {
+                        field if field == function.return_field => self.schema.clone(),
+                        field => Arc::new(Schema::new(vec![
+                            self.schema.field(0).clone().into(),
+                            field,
+                        ])),
+                    };
+                if let Some(error) = error {
+                    Ok(Some(
+                        RecordBatch::try_new(
+                            Arc::new(append_error_to_schema(&schema)),
+                            vec![indexes, output, error],
+                        )
+                        .unwrap(),
+                    ))
+                } else {
+                    Ok(Some(
+                        RecordBatch::try_new(schema, vec![indexes, output]).unwrap(),
+                    ))
+                }
+            })
         })?;
-        Ok(batch)
+        let Some(batch) = batch else {
+            return Ok(None);
+        };
+        // the first column is the index of the input row
+        let rows = batch.column(0).clone();
+        let input_row = |i| rows.as_primitive::<Int32Type>().value(i) as usize;
+        Ok(Some(row_errors::apply(
+            self.row_error_policy,
+            batch,
+            2,
+            input_row,
+        )?))
     }
 }
 
 impl Iterator for RecordBatchIter<'_> {
     type Item = Result<RecordBatch>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next().transpose()
+        let start = Instant::now();
+        let next = self.next().transpose();
+        self.stats.elapsed += start.elapsed();
+        match &next {
+            Some(Ok(batch)) => {
+                self.stats.output_rows += batch.num_rows();
+                self.stats.output_bytes += slice_memory_size(batch);
+            }
+            Some(Err(_)) => self.stats.error = true,
+            None => {}
+        }
+        next
     }
 }
 
 impl Drop for RecordBatchIter<'_> {
     fn drop(&mut self) {
+        self.metrics.record(&self.name, &self.stats);
         if let Some(generator) = self.generator.take() {
-            _ = self.interpreter.with_gil(|_| {
+            _ = self.instance.interpreter.with_gil(|_| {
                 drop(generator);
                 Ok(())
             });
@@ -861,39 +4300,361 @@ impl Drop for RecordBatchIter<'_> {
     }
 }
 
-/// Whether the function will be called when some of its arguments are null.
-#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub enum CallMode {
-    /// The function will be called normally when some of its arguments are null.
-    /// It is then the function author's responsibility to check for null values if necessary and respond appropriately.
-    #[default]
-    CalledOnNullInput,
+impl arrow_udf_core::UdfRuntime for Runtime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        self.add_function(name, return_field, mode, code)
+    }
+
+    fn add_function_with_properties(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        properties: FunctionProperties,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        self.add_function_with_properties(name, return_field, properties, code)
+    }
 
-    /// The function always returns null whenever any of its arguments are null.
-    /// If this parameter is specified, the function is not executed when there are null arguments;
-    /// instead a null result is assumed automatically.
-    ReturnNullOnNullInput,
-}
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        self.call(name, input)
+    }
 
-impl Drop for Runtime {
-    fn drop(&mut self) {
-        // `PyObject` must be dropped inside the interpreter
-        _ = self.interpreter.with_gil(|_| {
-            self.functions.clear();
-            self.aggregates.clear();
-            Ok(())
+    fn call_broadcast(&self, name: &str, args: &Arguments) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_broadcast(name, args)
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_cancellable(name, input, token)
+    }
+
+    fn call_selected(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        selection: &BooleanArray,
+    ) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_selected(name, input, selection)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        Ok(Box::new(self.call_table_function(name, input, chunk_size)?))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        let instance = self.instance();
+        let aliases = (instance.aliases.iter())
+            .filter(|(_, target)| !instance.aggregates.contains_key(*target))
+            .map(|(alias, _)| alias);
+        instance.functions.keys().chain(aliases).cloned().collect()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        let instance = self.instance();
+        let aliases = (instance.aliases.iter())
+            .filter(|(_, target)| instance.aggregates.contains_key(*target))
+            .map(|(alias, _)| alias);
+        instance.aggregates.keys().chain(aliases).cloned().collect()
+    }
+
+    fn functions(&self) -> Vec<FunctionInfo> {
+        let instance = self.instance();
+        let functions = instance.functions.iter().map(|(name, function)| {
+            let info = match &function.arg_types {
+                // overloads are called by the name before their argument types
+                Some(arg_types) => FunctionInfo::new(&name[..name.find('(').unwrap_or(name.len())])
+                    .with_arg_types(arg_types.clone()),
+                None => FunctionInfo::new(name),
+            };
+            let info = info
+                .with_return_field(function.return_field.clone())
+                .with_mode(function.mode)
+                .with_volatility(function.volatility);
+            match function.convention {
+                Convention::Window => info.with_kind(FunctionKind::Window),
+                _ => info,
+            }
+        });
+        let aggregates = instance.aggregates.iter().map(|(name, aggregate)| {
+            FunctionInfo::new(name)
+                .with_kind(FunctionKind::Aggregate)
+                .with_return_field(aggregate.output_field.clone())
+                .with_mode(aggregate.mode)
         });
+        let mut infos: Vec<_> = functions.chain(aggregates).collect();
+        // aliases are reported with the signatures of their functions
+        for (alias, target) in &instance.aliases {
+            let aliased: Vec<_> = (infos.iter())
+                .filter(|info| &info.name == target)
+                .map(|info| FunctionInfo {
+                    name: alias.clone(),
+                    ..info.clone()
+                })
+                .collect();
+            infos.extend(aliased);
+        }
+        infos
+    }
+
+    fn create_state(&self, name: &str) -> arrow_udf_core::Result<ArrayRef> {
+        self.create_state(name)
+    }
+
+    fn accumulate(
+        &self,
+        name: &str,
+        state: &dyn Array,
+        input: &RecordBatch,
+    ) -> arrow_udf_core::Result<ArrayRef> {
+        self.accumulate(name, state, input)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> arrow_udf_core::Result<ArrayRef> {
+        self.merge(name, states)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> arrow_udf_core::Result<ArrayRef> {
+        self.finish(name, states)
+    }
+
+    fn state_field(&self, name: &str) -> arrow_udf_core::Result<FieldRef> {
+        let instance = self.instance();
+        let aggregate = instance.aggregate(name)?;
+        Ok(aggregate.state_field.clone())
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        self.memory_usage()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let capabilities = Capabilities::new()
+            .with_data_types(DATA_TYPES.iter().copied())
+            .with_function_kinds([
+                FunctionKind::Scalar,
+                FunctionKind::Table,
+                FunctionKind::Aggregate,
+                FunctionKind::Window,
+            ])
+            .with_batch_modes([
+                BatchMode::Broadcast,
+                BatchMode::Selected,
+                BatchMode::Cancellable,
+            ])
+            .with_resource_limits([ResourceLimit::Timeout]);
+        match self.memory_usage() {
+            Some(_) => capabilities.with_resource_limits([ResourceLimit::Memory]),
+            None => capabilities,
+        }
+    }
+}
+
+/// The names of data types that can be converted between Arrow and Python.
+pub(crate) const DATA_TYPES: &[&str] = &[
+    "Binary",
+    "BinaryView",
+    "Boolean",
+    "Date32",
+    "Date64",
+    "Decimal128",
+    "Decimal256",
+    "Duration",
+    "FixedSizeBinary",
+    "Float16",
+    "Float32",
+    "Float64",
+    "Int8",
+    "Int16",
+    "Int32",
+    "Int64",
+    "Interval",
+    "LargeBinary",
+    "LargeList",
+    "LargeUtf8",
+    "List",
+    "Map",
+    "Null",
+    "Struct",
+    "Time32",
+    "Time64",
+    "Timestamp",
+    "UInt8",
+    "UInt16",
+    "UInt32",
+    "UInt64",
+    "Utf8",
+    "Utf8View",
+];
+
+/// Run a closure with a token that is cancelled once the token of the runtime is cancelled,
+/// in addition to [`with_timeout`].
+///
+/// Returns [`Error::Cancelled`] if the closure fails after the token of the runtime is cancelled.
+fn with_cancellation<R>(
+    cancel: &CancelToken,
+    limits: (Option<Duration>, Option<Duration>),
+    token: Option<&CancelToken>,
+    f: impl FnOnce(Option<&CancelToken>) -> Result<R>,
+) -> Result<R> {
+    let merged = CancelToken::new();
+    let _forward = [Some(cancel), token].map(|token| {
+        let merged = merged.clone();
+        token.map(|token| token.on_cancel(move || merged.cancel()))
+    });
+    match with_timeout(limits, Some(&merged), f) {
+        Err(_) if cancel.is_cancelled() => Err(Error::Cancelled),
+        result => result,
+    }
+}
+
+/// The interval of sampling the CPU time of calls with a CPU time limit.
+const CPU_TIME_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Run a closure with a token that is cancelled once the timeout expires, the CPU time of the current
+/// thread exceeds the limit, or the given token is cancelled. `limits` are the timeout and the CPU time limit.
+///
+/// Returns [`Error::ResourceExceeded`] if the closure fails after either limit is exceeded.
+fn with_timeout<R>(
+    (timeout, cpu_time_limit): (Option<Duration>, Option<Duration>),
+    token: Option<&CancelToken>,
+    f: impl FnOnce(Option<&CancelToken>) -> Result<R>,
+) -> Result<R> {
+    if timeout.is_none() && cpu_time_limit.is_none() {
+        return f(token);
+    }
+    // the closure runs in the current thread
+    let cpu_time =
+        cpu_time_limit.and_then(|limit| Some((cpu_time::ThreadCpuClock::start()?, limit)));
+    let deadline = CancelToken::new();
+    let _forward = token.map(|token| {
+        let deadline = deadline.clone();
+        token.on_cancel(move || deadline.cancel())
+    });
+    // the watchdog returns early once `done` is dropped, or the message once either limit is exceeded
+    let (done, wait) = mpsc::channel::<()>();
+    let watchdog = std::thread::spawn({
+        let deadline = deadline.clone();
+        move || {
+            let start = Instant::now();
+            let message = loop {
+                let mut interval =
+                    timeout.map_or(Duration::MAX, |t| t.saturating_sub(start.elapsed()));
+                if cpu_time.is_some() {
+                    interval = interval.min(CPU_TIME_SAMPLE_INTERVAL);
+                }
+                if wait.recv_timeout(interval) != Err(mpsc::RecvTimeoutError::Timeout) {
+                    return None;
+                }
+                if let Some(timeout) = timeout.filter(|&t| start.elapsed() >= t) {
+                    break format!("timeout after {timeout:?}");
+                }
+                if let Some((clock, limit)) = &cpu_time {
+                    if clock.elapsed() >= *limit {
+                        break format!("CPU time limit of {limit:?} exceeded");
+                    }
+                }
+            };
+            deadline.cancel();
+            Some(message)
+        }
+    });
+    let result = f(Some(&deadline));
+    drop(done);
+    match (result, watchdog.join().ok().flatten()) {
+        (Err(_), Some(message)) => Err(Error::ResourceExceeded(message)),
+        (result, _) => result,
+    }
+}
+
+/// Returns the key of the overload of a function for the argument types, e.g. `length(Utf8)`.
+fn overload_key<'a>(name: &str, arg_types: impl IntoIterator<Item = &'a DataType>) -> String {
+    let arg_types: Vec<_> = arg_types.into_iter().map(|t| t.to_string()).collect();
+    format!("{name}({})", arg_types.join(", "))
+}
+
+/// Infer the return field of a loaded function from its return annotation.
+fn infer_return_field(instance: &Instance, name: &str, function: &PyObject) -> Result<FieldRef> {
+    let annotation = instance
+        .return_annotation(name, function)?
+        .ok_or_else(|| Error::TypeMismatch(format!("function {name} has no return annotation")))?;
+    let field = annotation::return_field(name, &annotation).ok_or_else(|| {
+        Error::TypeMismatch(format!(
+            "can not infer the return type of function {name} from annotation `{annotation}`"
+        ))
+    })?;
+    Ok(field.into())
+}
+
+/// Returns the number of rows and the memory size of a batch.
+fn batch_size(batch: &RecordBatch) -> (usize, usize) {
+    (batch.num_rows(), slice_memory_size(batch))
+}
+
+/// Returns the memory size of arguments, where each scalar is counted once.
+fn arguments_memory_size(args: &Arguments) -> usize {
+    (args.columns().iter())
+        .map(|column| {
+            let data = column.to_data();
+            data.get_slice_memory_size()
+                .unwrap_or_else(|_| column.get_array_memory_size())
+        })
+        .sum()
+}
+
+/// Build the output batch of a scalar function, with the error column if any row fails.
+fn output_batch(
+    field: &FieldRef,
+    output: ArrayRef,
+    error: Option<ArrayRef>,
+) -> Result<RecordBatch> {
+    let field = pyarrow::promoted_field(field, output.data_type());
+    if let Some(error) = error {
+        let schema = Schema::new(vec![
+            field,
+            Field::new("error", DataType::Utf8, true).into(),
+        ]);
+        Ok(RecordBatch::try_new(Arc::new(schema), vec![output, error])?)
+    } else {
+        let schema = Schema::new(vec![field]);
+        Ok(RecordBatch::try_new(Arc::new(schema), vec![output])?)
+    }
+}
+
+/// Split a batch into slices of at most `max_rows` rows, or returns the batch if there is no limit.
+fn split_batch(batch: RecordBatch, max_rows: Option<usize>) -> Vec<RecordBatch> {
+    match max_rows {
+        Some(max) if batch.num_rows() > max => (0..batch.num_rows())
+            .step_by(max)
+            .map(|offset| batch.slice(offset, max.min(batch.num_rows() - offset)))
+            .collect(),
+        _ => vec![batch],
     }
 }
 
+/// Build the error column from the messages of failed rows, sorted by row index.
 fn build_error_array(num_rows: usize, errors: Vec<(usize, String)>) -> Option<ArrayRef> {
     if errors.is_empty() {
         return None;
     }
-    let data_capacity = errors.iter().map(|(i, _)| i).sum();
+    let data_capacity = errors.iter().map(|(_, msg)| msg.len()).sum();
     let mut builder = StringBuilder::with_capacity(num_rows, data_capacity);
     for (i, msg) in errors {
-        while builder.len() + 1 < i {
+        while builder.len() < i {
             builder.append_null();
         }
         builder.append_value(&msg);
@@ -904,6 +4665,30 @@ fn build_error_array(num_rows: usize, errors: Vec<(usize, String)>) -> Option<Ar
     Some(Arc::new(builder.finish()))
 }
 
+/// Replace the struct column at the beginning of a batch with a column for each of its fields.
+///
+/// Rows where the struct is null are null in all columns.
+fn expand_struct(batch: RecordBatch) -> Result<RecordBatch> {
+    let Some(array) = batch.column(0).as_any().downcast_ref::<StructArray>() else {
+        return Ok(batch);
+    };
+    let (fields, arrays, nulls) = array.clone().into_parts();
+    let mut columns = Vec::with_capacity(arrays.len() + batch.num_columns() - 1);
+    for array in arrays {
+        let nulls = NullBuffer::union(nulls.as_ref(), array.logical_nulls().as_ref());
+        columns.push(make_array(
+            array.into_data().into_builder().nulls(nulls).build()?,
+        ));
+    }
+    let mut schema_fields = fields.to_vec();
+    schema_fields.extend(batch.schema().fields().iter().skip(1).cloned());
+    columns.extend(batch.columns().iter().skip(1).cloned());
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(schema_fields)),
+        columns,
+    )?)
+}
+
 /// Append an error field to the schema.
 fn append_error_to_schema(schema: &Schema) -> Schema {
     let mut fields = schema.fields().to_vec();
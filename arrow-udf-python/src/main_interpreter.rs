@@ -0,0 +1,202 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running functions in the main interpreter on Python 3.10 and 3.11,
+//! which have no sub-interpreters with their own GIL.
+
+use std::os::raw::c_ulong;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use arrow_udf_core::{CancelToken, Error};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyModule};
+use pyo3::{ffi, prepare_freethreaded_python};
+
+use crate::allocator::{self, MemoryCounter};
+use crate::interpreter::PyError;
+
+// not exported by `pyo3::ffi`
+extern "C" {
+    fn PyThread_get_thread_ident() -> c_ulong;
+}
+
+/// Held while any `MainInterpreter` runs a closure, since their `__main__` modules are swapped in
+/// `sys.modules`, and the GIL may be released by Python code in between.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// The main Python interpreter, shared by all runtimes with the same GIL.
+///
+/// Each `MainInterpreter` has its own `__main__` module and modules created from the code of
+/// functions, so the globals are separate, while other modules and the built-in functions are shared.
+#[derive(Debug)]
+pub struct MainInterpreter {
+    /// The `__main__` module while running closures.
+    main: Py<PyModule>,
+    /// The modules created from the code of functions, which are in `sys.modules` while running closures.
+    modules: Py<PyDict>,
+    /// The memory allocated while running closures, or `None` if it is not accounted.
+    memory: Option<&'static MemoryCounter>,
+}
+
+impl MainInterpreter {
+    /// Create a new `__main__` module in the main interpreter,
+    /// accounting the memory allocated while running closures if `memory_accounting` is set.
+    pub fn new(memory_accounting: bool) -> Result<Self, PyError> {
+        // the allocators must be wrapped before Python is initialized
+        let memory = memory_accounting.then(MemoryCounter::new).transpose()?;
+        prepare_freethreaded_python();
+        let main = Python::with_gil(|py| -> PyResult<_> {
+            let main = PyModule::new_bound(py, "__main__")?;
+            main.setattr("__builtins__", py.import_bound("builtins")?)?;
+            Ok(main.unbind())
+        })?;
+        let modules = Python::with_gil(|py| PyDict::new_bound(py).unbind());
+        Ok(Self {
+            main,
+            modules,
+            memory,
+        })
+    }
+
+    /// Run a closure in the main interpreter with the `__main__` module of this interpreter.
+    ///
+    /// Closures of all interpreters run one at a time.
+    pub fn with_gil<F, R>(&self, f: F) -> Result<R, PyError>
+    where
+        F: for<'py> FnOnce(Python<'py>) -> Result<R, PyError>,
+    {
+        let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        Python::with_gil(|py| {
+            let memory = allocator::enter(self.memory);
+            let modules = py.import_bound("sys")?.getattr("modules")?;
+            let modules = modules.downcast::<PyDict>().map_err(PyErr::from)?;
+            let previous = modules.get_item("__main__")?;
+            modules.set_item("__main__", self.main.bind(py))?;
+            modules.update(self.modules.bind(py).as_mapping())?;
+            let ret = f(py);
+            take_function_modules(modules, self.modules.bind(py))?;
+            if let Some(previous) = previous {
+                modules.set_item("__main__", previous)?;
+            }
+            drop(memory);
+            ret
+        })
+    }
+
+    /// Run a closure in the main interpreter, raising `KeyboardInterrupt` in the running Python code
+    /// once the token is cancelled.
+    ///
+    /// Cancelling the token blocks until the running code releases the GIL at the switch interval.
+    pub fn with_gil_cancellable<F, R>(
+        &self,
+        token: Option<&CancelToken>,
+        f: F,
+    ) -> Result<R, PyError>
+    where
+        F: for<'py> FnOnce(Python<'py>) -> Result<R, PyError>,
+    {
+        let Some(token) = token else {
+            return self.with_gil(f);
+        };
+        // whether `f` is running, only accessed with the GIL held
+        let running = Arc::new(AtomicBool::new(false));
+        // the thread state of this thread is used by `Python::with_gil`
+        let thread_id = unsafe { PyThread_get_thread_ident() };
+        let interrupter = Interrupter {
+            thread_id,
+            running: running.clone(),
+        };
+        let guard = token.on_cancel(move || interrupter.interrupt());
+        let ret = self.with_gil(|py| {
+            // the token may be cancelled while waiting for other threads
+            if token.is_cancelled() {
+                return Err(anyhow::Error::from(Error::Cancelled).into());
+            }
+            running.store(true, Ordering::SeqCst);
+            let ret = f(py);
+            running.store(false, Ordering::SeqCst);
+            // clear the exception if it is not raised before `f` returns
+            unsafe { ffi::PyThreadState_SetAsyncExc(thread_id as _, std::ptr::null_mut()) };
+            ret
+        });
+        drop(guard);
+        ret
+    }
+
+    /// Returns the number of bytes allocated while running closures,
+    /// or `None` if it is not accounted.
+    pub fn memory_usage(&self) -> Option<usize> {
+        self.memory.map(|memory| memory.used())
+    }
+
+    /// Set the maximum number of bytes allocated while running closures,
+    /// beyond which allocations raise `MemoryError`.
+    ///
+    /// Returns false if the memory can not be accounted.
+    pub fn set_memory_limit(&self, limit: Option<usize>) -> bool {
+        match self.memory {
+            Some(memory) => {
+                memory.set_limit(limit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the maximum number of bytes allocated while running closures, or `None` if unlimited.
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory.and_then(|memory| memory.limit())
+    }
+
+    /// Run Python code with the `__main__` module of this interpreter.
+    pub fn run(&self, code: &str) -> Result<(), PyError> {
+        self.with_gil(|py| py.run_bound(code, None, None).map_err(|e| e.into()))
+    }
+}
+
+/// Move the modules created from the code of functions, whose file names are their names,
+/// from `sys.modules` to `own`.
+fn take_function_modules(modules: &Bound<'_, PyDict>, own: &Bound<'_, PyDict>) -> PyResult<()> {
+    own.clear();
+    for (name, module) in modules.copy()?.iter() {
+        let file = module.getattr("__file__").ok();
+        if file.is_some_and(|file| file.eq(&name).unwrap_or(false)) {
+            own.set_item(&name, module)?;
+            modules.del_item(name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Raises `KeyboardInterrupt` in a thread running a closure from another thread.
+struct Interrupter {
+    thread_id: c_ulong,
+    running: Arc<AtomicBool>,
+}
+
+impl Interrupter {
+    fn interrupt(&self) {
+        Python::with_gil(|_| {
+            if self.running.load(Ordering::SeqCst) {
+                unsafe {
+                    ffi::PyThreadState_SetAsyncExc(
+                        self.thread_id as _,
+                        ffi::PyExc_KeyboardInterrupt,
+                    )
+                };
+            }
+        });
+    }
+}
@@ -0,0 +1,580 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Run Python functions in a separate worker process.
+//!
+//! The sub-interpreter of [`Runtime`](crate::Runtime) shares the memory and the fate of the host process.
+//! A [`ProcessRuntime`] instead spawns a worker process, and exchanges batches with it
+//! through Arrow IPC over pipes. If the worker crashes, the call fails and the worker is
+//! restarted with all functions on the next call.
+//!
+//! The worker is the `arrow-udf-python-worker` binary of this crate, or any program calling [`run_worker`].
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use arrow_array::{RecordBatch, RecordBatchOptions};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{Field, Schema, SchemaRef};
+use arrow_udf_core::{Capabilities, FunctionKind, ResourceLimit};
+
+use crate::sandbox::Sandbox;
+use crate::{CallMode, IntoField, Runtime, DATA_TYPES};
+
+/// The default program of worker, which is searched in `PATH`.
+const DEFAULT_WORKER: &str = "arrow-udf-python-worker";
+
+/// The default maximum size of a response from the worker in bytes.
+const DEFAULT_MAX_MESSAGE_SIZE: u64 = 1 << 30;
+
+/// The default time to wait for a response from the worker.
+const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A builder for [`ProcessRuntime`].
+#[derive(Debug, Clone)]
+pub struct ProcessBuilder {
+    program: PathBuf,
+    args: Vec<OsString>,
+    sandboxed: bool,
+    allowed_modules: Vec<String>,
+    paths: Vec<PathBuf>,
+    sandbox: Sandbox,
+    max_message_size: u64,
+    response_timeout: Option<Duration>,
+}
+
+impl Default for ProcessBuilder {
+    fn default() -> Self {
+        Self {
+            program: DEFAULT_WORKER.into(),
+            args: vec![],
+            sandboxed: false,
+            allowed_modules: vec![],
+            paths: vec![],
+            sandbox: Sandbox::default(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            response_timeout: Some(DEFAULT_RESPONSE_TIMEOUT),
+        }
+    }
+}
+
+impl ProcessBuilder {
+    /// Set the program of worker. The default is `arrow-udf-python-worker` in `PATH`.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Add an argument passed to the worker.
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Set whether the runtime in worker is sandboxed. See [`Builder::sandboxed`](crate::Builder::sandboxed).
+    ///
+    /// The default is `false`.
+    pub fn sandboxed(mut self, sandboxed: bool) -> Self {
+        self.sandboxed = sandboxed;
+        self
+    }
+
+    /// Allow a module to be imported in the sandboxed runtime in worker.
+    /// See [`Builder::allow_module`](crate::Builder::allow_module).
+    pub fn allow_module(mut self, module: &str) -> Self {
+        self.allowed_modules.push(module.to_string());
+        self
+    }
+
+    /// Append a directory to `sys.path` of the runtime in worker.
+    /// See [`Builder::append_path`](crate::Builder::append_path).
+    pub fn append_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Set the OS-level restrictions applied by the worker to itself before executing user code.
+    ///
+    /// The default is no restriction.
+    pub fn os_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Set the maximum size of a response from the worker in bytes.
+    ///
+    /// A larger response is an error, and the worker is restarted, since it is not trusted
+    /// to announce the size of its responses honestly. The default is 1 GiB.
+    pub fn max_message_size(mut self, bytes: u64) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Set the maximum time to wait for a response from the worker, or `None` to wait forever.
+    ///
+    /// If the worker does not respond in time, the call fails and the worker is restarted.
+    /// The default is 5 minutes.
+    pub fn response_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// Spawn the worker and build the `ProcessRuntime`.
+    pub fn build(self) -> arrow_udf_core::Result<ProcessRuntime> {
+        let worker = Worker::spawn(&self)?;
+        Ok(ProcessRuntime {
+            builder: self,
+            worker: Mutex::new(Some(worker)),
+            functions: vec![],
+            restarts: AtomicUsize::new(0),
+        })
+    }
+}
+
+/// A runtime to execute user defined functions in a Python worker process.
+///
+/// Only scalar functions and table functions are supported.
+pub struct ProcessRuntime {
+    builder: ProcessBuilder,
+    /// The running worker. `None` if it has crashed and not been restarted yet.
+    worker: Mutex<Option<Worker>>,
+    /// All functions added, to be replayed when the worker is restarted.
+    functions: Vec<Request>,
+    restarts: AtomicUsize,
+}
+
+impl std::fmt::Debug for ProcessRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessRuntime")
+            .field("builder", &self.builder)
+            .field("functions", &self.functions.len())
+            .field("restarts", &self.restarts())
+            .finish()
+    }
+}
+
+impl ProcessRuntime {
+    /// Spawn a new worker with the default options.
+    pub fn new() -> arrow_udf_core::Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Return a new builder.
+    pub fn builder() -> ProcessBuilder {
+        ProcessBuilder::default()
+    }
+
+    /// Add a new scalar function or table function.
+    ///
+    /// See [`Runtime::add_function`](crate::Runtime::add_function).
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        self.add_function_with_handler(name, return_type, mode, code, name)
+    }
+
+    /// Add a new scalar function or table function with custom handler name.
+    ///
+    /// See [`Runtime::add_function_with_handler`](crate::Runtime::add_function_with_handler).
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "add_function", level = "debug", skip_all, fields(function = name)))]
+    pub fn add_function_with_handler(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+        handler: &str,
+    ) -> arrow_udf_core::Result<()> {
+        let mode = mode.to_string();
+        let request = Request::new(
+            "add_function",
+            [
+                ("name", name),
+                ("mode", &mode),
+                ("code", code),
+                ("handler", handler),
+            ],
+            Schema::new(vec![return_type.into_field(name)]),
+            vec![],
+        );
+        self.request(&request)?;
+        self.functions.push(request);
+        Ok(())
+    }
+
+    /// Call a scalar function.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows())))]
+    pub fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        let request = Request::with_input("call", [("name", name)], input)?;
+        let mut batches = self.request(&request)?;
+        if batches.len() != 1 {
+            return Err(anyhow!("expect 1 batch from worker, got {}", batches.len()).into());
+        }
+        Ok(batches.remove(0))
+    }
+
+    /// Call a table function and return all output batches.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows())))]
+    pub fn call_table_function(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        chunk_size: usize,
+    ) -> arrow_udf_core::Result<Vec<RecordBatch>> {
+        let chunk_size = chunk_size.to_string();
+        let request = Request::with_input(
+            "call_table_function",
+            [("name", name), ("chunk_size", &chunk_size)],
+            input,
+        )?;
+        Ok(self.request(&request)?)
+    }
+
+    /// Returns the number of times the worker has been restarted.
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Send a request to the worker, restarting it if it has crashed.
+    fn request(&self, request: &Request) -> Result<Vec<RecordBatch>> {
+        let mut guard = self.worker.lock().unwrap();
+        if guard.is_none() {
+            let mut worker = Worker::spawn(&self.builder)?;
+            for function in &self.functions {
+                worker
+                    .request(function)
+                    .context("failed to restore functions in worker")??;
+            }
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+            *guard = Some(worker);
+        }
+        match guard.as_mut().unwrap().request(request) {
+            Ok(response) => response,
+            Err(e) => {
+                // the worker is dropped and killed, and will be restarted on the next request
+                let status = guard.take().unwrap().kill();
+                Err(e.context(format!("worker process crashed: {status}")))
+            }
+        }
+    }
+}
+
+impl arrow_udf_core::UdfRuntime for ProcessRuntime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        self.add_function(name, return_field, mode, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        self.call(name, input)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        let batches = self.call_table_function(name, input, chunk_size)?;
+        Ok(Box::new(batches.into_iter().map(Ok)))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.functions
+            .iter()
+            .map(|f| f.schema.metadata()["name"].clone())
+            .collect()
+    }
+
+    /// The memory of workers can be limited by [`Sandbox::max_memory`].
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new()
+            .with_data_types(DATA_TYPES.iter().copied())
+            .with_function_kinds([FunctionKind::Scalar, FunctionKind::Table])
+            .with_resource_limits([ResourceLimit::Memory])
+    }
+}
+
+/// A running worker process.
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    /// The responses read from stdout of the worker by a thread, so that waiting for them can time out.
+    /// The thread exits after the first error, or once the worker is killed.
+    responses: mpsc::Receiver<Result<(SchemaRef, Vec<RecordBatch>)>>,
+    response_timeout: Option<Duration>,
+}
+
+impl Worker {
+    fn spawn(builder: &ProcessBuilder) -> Result<Self> {
+        let mut command = Command::new(&builder.program);
+        command.args(&builder.args);
+        if builder.sandboxed {
+            command.arg("--sandboxed");
+        }
+        for module in &builder.allowed_modules {
+            command.arg("--allow-module").arg(module);
+        }
+        for path in &builder.paths {
+            command.arg("--path").arg(path);
+        }
+        command.args(builder.sandbox.to_args());
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to spawn worker {}", builder.program.display()))?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let (tx, responses) = mpsc::channel();
+        let max_message_size = builder.max_message_size;
+        std::thread::Builder::new()
+            .name("arrow-udf-python-worker-reader".into())
+            .spawn(move || loop {
+                let response = read_message(&mut stdout, max_message_size);
+                let failed = response.is_err();
+                if tx.send(response).is_err() || failed {
+                    return;
+                }
+            })
+            .context("failed to spawn the reader thread of worker")?;
+        Ok(Self {
+            child,
+            stdin,
+            responses,
+            response_timeout: builder.response_timeout,
+        })
+    }
+
+    /// Send a request and wait for the response.
+    ///
+    /// The outer error means the worker is broken, and the inner error is returned by the function.
+    fn request(&mut self, request: &Request) -> Result<Result<Vec<RecordBatch>>> {
+        write_message(&mut self.stdin, &request.schema, &request.batches)?;
+        let response = match self.response_timeout {
+            Some(timeout) => self.responses.recv_timeout(timeout).map_err(|e| match e {
+                mpsc::RecvTimeoutError::Timeout => {
+                    anyhow!("worker did not respond in {timeout:?}")
+                }
+                mpsc::RecvTimeoutError::Disconnected => anyhow!("worker closed its stdout"),
+            })?,
+            None => (self.responses.recv()).map_err(|_| anyhow!("worker closed its stdout"))?,
+        };
+        let (schema, batches) = response?;
+        match schema.metadata().get("error") {
+            Some(message) => Ok(Err(anyhow!("{message}"))),
+            None => Ok(Ok(batches)),
+        }
+    }
+
+    /// Kill the worker and return its exit status.
+    fn kill(mut self) -> String {
+        _ = self.child.kill();
+        match self.child.wait() {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        _ = self.child.kill();
+        _ = self.child.wait();
+    }
+}
+
+/// A request to the worker.
+///
+/// The operation and its arguments are stored in the schema metadata.
+#[derive(Debug)]
+struct Request {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+}
+
+impl Request {
+    fn new<'a>(
+        op: &str,
+        args: impl IntoIterator<Item = (&'a str, &'a str)>,
+        schema: Schema,
+        batches: Vec<RecordBatch>,
+    ) -> Self {
+        let mut metadata: HashMap<String, String> = args
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        metadata.insert("op".into(), op.into());
+        Self {
+            schema: Arc::new(schema.with_metadata(metadata)),
+            batches,
+        }
+    }
+
+    fn with_input<'a>(
+        op: &str,
+        args: impl IntoIterator<Item = (&'a str, &'a str)>,
+        input: &RecordBatch,
+    ) -> Result<Self> {
+        let mut request = Self::new(op, args, input.schema().as_ref().clone(), vec![]);
+        request.batches.push(RecordBatch::try_new_with_options(
+            request.schema.clone(),
+            input.columns().to_vec(),
+            &RecordBatchOptions::default().with_row_count(Some(input.num_rows())),
+        )?);
+        Ok(request)
+    }
+}
+
+/// Write a message as a length-prefixed Arrow IPC stream.
+fn write_message(writer: &mut impl Write, schema: &Schema, batches: &[RecordBatch]) -> Result<()> {
+    let mut buf = vec![];
+    let mut stream = StreamWriter::try_new(&mut buf, schema)?;
+    for batch in batches {
+        stream.write(batch)?;
+    }
+    stream.finish()?;
+    drop(stream);
+    writer.write_all(&(buf.len() as u64).to_le_bytes())?;
+    writer.write_all(&buf)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a message written by [`write_message`], which is at most `max_size` bytes.
+fn read_message(reader: &mut impl Read, max_size: u64) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    if len > max_size {
+        bail!("message of {len} bytes exceeds the maximum size of {max_size} bytes");
+    }
+    // the buffer grows with the bytes actually read, instead of trusting the length
+    let mut buf = vec![];
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    let stream = StreamReader::try_new(buf.as_slice(), None)?;
+    let schema = stream.schema();
+    let batches = stream.collect::<Result<Vec<_>, _>>()?;
+    Ok((schema, batches))
+}
+
+/// Serve requests from stdin and write responses to stdout, until stdin is closed.
+///
+/// This is the main loop of a worker process. Anything printed by Python code goes to stderr,
+/// so that it does not corrupt the responses.
+pub fn run_worker(mut runtime: Runtime) -> Result<()> {
+    let mut output = protocol_stdout()?;
+    let mut input = BufReader::new(std::io::stdin().lock());
+    loop {
+        // requests come from the trusted host, which sets no limit
+        let (schema, batches) = match read_message(&mut input, u64::MAX) {
+            Ok(message) => message,
+            Err(e) => match e.downcast_ref::<std::io::Error>() {
+                Some(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                _ => return Err(e),
+            },
+        };
+        match handle_request(&mut runtime, &schema, batches) {
+            Ok((schema, batches)) => write_message(&mut output, &schema, &batches)?,
+            Err(e) => {
+                let metadata = [("error".to_string(), format!("{e:#}"))].into();
+                write_message(&mut output, &Schema::empty().with_metadata(metadata), &[])?
+            }
+        }
+    }
+}
+
+fn handle_request(
+    runtime: &mut Runtime,
+    schema: &Schema,
+    batches: Vec<RecordBatch>,
+) -> Result<(Schema, Vec<RecordBatch>)> {
+    let arg = |key: &str| {
+        schema
+            .metadata()
+            .get(key)
+            .with_context(|| format!("missing argument in request: {key}"))
+    };
+    let input = || batches.first().context("missing input batch in request");
+    match arg("op")?.as_str() {
+        "add_function" => {
+            let mode: CallMode = arg("mode")?.parse()?;
+            let return_field = schema.field(0).clone();
+            runtime.add_function_with_handler(
+                arg("name")?,
+                return_field,
+                mode,
+                arg("code")?,
+                arg("handler")?,
+            )?;
+            Ok((Schema::empty(), vec![]))
+        }
+        "call" => {
+            let output = runtime.call(arg("name")?, input()?)?;
+            Ok((output.schema().as_ref().clone(), vec![output]))
+        }
+        "call_table_function" => {
+            let chunk_size = arg("chunk_size")?.parse()?;
+            let outputs = runtime
+                .call_table_function(arg("name")?, input()?, chunk_size)?
+                .collect::<arrow_udf_core::Result<Vec<_>>>()?;
+            let schema = match outputs.first() {
+                Some(batch) => batch.schema().as_ref().clone(),
+                None => Schema::empty(),
+            };
+            Ok((schema, outputs))
+        }
+        op => bail!("invalid operation: {op}"),
+    }
+}
+
+/// Move stdout to a new file descriptor for responses, and redirect stdout to stderr.
+#[cfg(unix)]
+fn protocol_stdout() -> Result<std::fs::File> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: only standard file descriptors are duplicated
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(std::fs::File::from_raw_fd(fd))
+    }
+}
+
+#[cfg(not(unix))]
+fn protocol_stdout() -> Result<std::io::Stdout> {
+    Ok(std::io::stdout())
+}
@@ -13,12 +13,27 @@
 // limitations under the License.
 
 //! Convert arrow array from/to python objects.
+//!
+//! Values are converted element by element, except for [`get_arrow_array`] and [`build_arrow_array`],
+//! which share the buffers with Python through the Arrow C Data Interface.
 
-use arrow_array::{array::*, builder::*};
-use arrow_buffer::OffsetBuffer;
-use arrow_schema::{DataType, Field};
-use pyo3::{exceptions::PyTypeError, types::PyAnyMethods, IntoPy, PyObject, PyResult, Python};
-use std::{borrow::Cow, sync::Arc};
+use arrow_array::ffi::{from_ffi, to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::types::*;
+use arrow_array::{array::*, builder::*, cast::AsArray, downcast_dictionary_array};
+use arrow_buffer::{i256, ArrowNativeType, IntervalDayTime, IntervalMonthDayNano, OffsetBuffer};
+use arrow_schema::{DataType, Field, FieldRef, IntervalUnit, TimeUnit};
+use half::f16;
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    types::{
+        PyAnyMethods, PyBytes, PyCFunction, PyCapsule, PyCapsuleMethods, PyDict, PyDictMethods,
+        PySequence, PySequenceMethods, PyString, PyTuple, PyTupleMethods, PyTypeMethods,
+    },
+    Bound, IntoPy, PyAny, PyObject, PyResult, Python,
+};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use crate::time_zone::{NaiveTimestampReturn, TimestampArgument};
 
 macro_rules! get_pyobject {
     ($array_type: ty, $py:expr, $array:expr, $i:expr) => {{
@@ -77,28 +92,77 @@ macro_rules! build_array {
     }};
 }
 
-macro_rules! build_json_array {
-    ($py:expr, $pyobjects:expr) => {{
-        let json_dumps = $py.eval_bound("json.dumps", None, None)?;
-        let mut builder = StringBuilder::with_capacity($pyobjects.len(), 1024);
+/// Build a string or binary array, which is promoted to the large type of the second builder
+/// if its values exceed the `i32` offsets.
+macro_rules! build_byte_array {
+    ($builder_type: ty, $large_builder_type: ty, $elem_type: ty, $py:expr, $pyobjects:expr) => {{
+        let mut elems = Vec::with_capacity($pyobjects.len());
+        let mut data_len = 0;
         for pyobj in $pyobjects {
             if pyobj.is_none($py) {
-                builder.append_null();
-                continue;
-            };
-            let json_str = json_dumps.call1((pyobj,))?;
-            builder.append_value(json_str.extract::<&str>()?);
+                elems.push(None);
+            } else {
+                let elem = pyobj.extract::<$elem_type>($py)?;
+                data_len += elem.len();
+                elems.push(Some(elem));
+            }
+        }
+        if data_len > i32::MAX as usize {
+            let mut builder = <$large_builder_type>::with_capacity(elems.len(), data_len);
+            builder.extend(elems);
+            Ok(Arc::new(builder.finish()))
+        } else {
+            let mut builder = <$builder_type>::with_capacity(elems.len(), data_len);
+            builder.extend(elems);
+            Ok(Arc::new(builder.finish()))
         }
-        Ok(Arc::new(builder.finish()))
     }};
 }
 
+/// The types converted by a custom converter, see [`Runtime::add_type_converter`](crate::Runtime::add_type_converter).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConverterKey {
+    /// Fields of the extension type of the name in the `ARROW:extension:name` metadata.
+    Extension(String),
+    /// Fields of the data type without an extension type.
+    DataType(DataType),
+}
+
+impl ConverterKey {
+    /// Returns the key of the converter in `_converters` of the interpreter.
+    pub(crate) fn id(&self) -> String {
+        match self {
+            Self::Extension(name) => format!("extension:{name}"),
+            Self::DataType(data_type) => format!("type:{data_type}"),
+        }
+    }
+}
+
+impl From<&str> for ConverterKey {
+    fn from(name: &str) -> Self {
+        Self::Extension(name.to_string())
+    }
+}
+
+impl From<DataType> for ConverterKey {
+    fn from(data_type: DataType) -> Self {
+        Self::DataType(data_type)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Converter {
     arrow_extension_key: Cow<'static, str>,
     json_extension_name: Cow<'static, str>,
     decimal_extension_name: Cow<'static, str>,
     pickle_extension_name: Cow<'static, str>,
+    uuid_extension_name: Cow<'static, str>,
+    /// The ids of custom converters of extension types by their names.
+    extension_converters: HashMap<String, String>,
+    /// The ids of custom converters of data types.
+    type_converters: HashMap<DataType, String>,
+    timestamp_argument: TimestampArgument,
+    naive_timestamp_return: NaiveTimestampReturn,
 }
 
 impl Converter {
@@ -108,9 +172,48 @@ impl Converter {
             json_extension_name: "arrowudf.json".into(),
             decimal_extension_name: "arrowudf.decimal".into(),
             pickle_extension_name: "arrowudf.pickle".into(),
+            uuid_extension_name: "arrow.uuid".into(),
+            extension_converters: HashMap::new(),
+            type_converters: HashMap::new(),
+            timestamp_argument: TimestampArgument::default(),
+            naive_timestamp_return: NaiveTimestampReturn::default(),
         }
     }
 
+    /// Set how arguments of timestamps with time zones are converted.
+    pub fn set_timestamp_argument(&mut self, argument: TimestampArgument) {
+        self.timestamp_argument = argument;
+    }
+
+    /// Set how naive `datetime` returned for timestamps with time zones are interpreted.
+    pub fn set_naive_timestamp_return(&mut self, naive: NaiveTimestampReturn) {
+        self.naive_timestamp_return = naive;
+    }
+
+    /// Convert values of the types by the custom converter of the id in `_converters`.
+    pub fn add_custom(&mut self, key: ConverterKey, id: String) {
+        match key {
+            ConverterKey::Extension(name) => self.extension_converters.insert(name, id),
+            ConverterKey::DataType(data_type) => self.type_converters.insert(data_type, id),
+        };
+    }
+
+    /// Returns the id of the custom converter of values of the field in the array, if any.
+    fn custom(&self, field: &Field, data_type: &DataType) -> Option<&str> {
+        if self.extension_converters.is_empty() && self.type_converters.is_empty() {
+            return None;
+        }
+        // the values of dictionaries are converted instead
+        if matches!(data_type, DataType::Dictionary(_, _)) {
+            return None;
+        }
+        match field.metadata().get(self.arrow_extension_key.as_ref()) {
+            Some(name) => self.extension_converters.get(name),
+            None => self.type_converters.get(data_type),
+        }
+        .map(String::as_str)
+    }
+
     #[allow(dead_code)]
     pub fn set_arrow_extension_key(&mut self, key: &str) {
         self.arrow_extension_key = key.to_string().into();
@@ -126,6 +229,21 @@ impl Converter {
         self.decimal_extension_name = name.to_string().into();
     }
 
+    /// Returns true if the field is JSON type.
+    fn is_json(&self, field: &Field) -> bool {
+        (field.metadata().get(self.arrow_extension_key.as_ref()))
+            .is_some_and(|name| name == self.json_extension_name.as_ref())
+    }
+
+    /// Returns true if the field is UUID type, whose values are `FixedSizeBinary(16)`,
+    /// and it is not replaced by a custom converter.
+    fn is_uuid(&self, field: &Field) -> bool {
+        (field.metadata().get(self.arrow_extension_key.as_ref())).is_some_and(|name| {
+            name == self.uuid_extension_name.as_ref()
+                && !self.extension_converters.contains_key(name)
+        })
+    }
+
     /// Get array element as a python object.
     pub fn get_pyobject(
         &self,
@@ -137,6 +255,32 @@ impl Converter {
         if array.is_null(i) {
             return Ok(py.None());
         }
+        if let Some(id) = self.custom(field, array.data_type()) {
+            let value = self.get_builtin_pyobject(py, field, array, i)?;
+            let to_python = py
+                .eval_bound("_converters", None, None)?
+                .get_item(id)?
+                .get_item(0)?;
+            return Ok(to_python.call1((value,))?.into());
+        }
+        self.get_builtin_pyobject(py, field, array, i)
+    }
+
+    /// Get array element as a python object without custom converters.
+    fn get_builtin_pyobject(
+        &self,
+        py: Python<'_>,
+        field: &Field,
+        array: &dyn Array,
+        i: usize,
+    ) -> PyResult<PyObject> {
+        if self.is_json(field) {
+            if let Some(text) = byte_value(array, i) {
+                // XXX: it is slow to call eval every time
+                let json_loads = py.eval_bound("json.loads", None, None)?;
+                return Ok(json_loads.call1((PyBytes::new_bound(py, text),))?.into());
+            }
+        }
         Ok(match array.data_type() {
             DataType::Null => py.None(),
             DataType::Boolean => get_pyobject!(BooleanArray, py, array, i),
@@ -148,17 +292,74 @@ impl Converter {
             DataType::UInt16 => get_pyobject!(UInt16Array, py, array, i),
             DataType::UInt32 => get_pyobject!(UInt32Array, py, array, i),
             DataType::UInt64 => get_pyobject!(UInt64Array, py, array, i),
+            // Python has no half-precision floats
+            DataType::Float16 => {
+                let array = array.as_any().downcast_ref::<Float16Array>().unwrap();
+                array.value(i).to_f32().into_py(py)
+            }
             DataType::Float32 => get_pyobject!(Float32Array, py, array, i),
             DataType::Float64 => get_pyobject!(Float64Array, py, array, i),
+            DataType::Decimal128(_, _) => {
+                let array = array.as_any().downcast_ref::<Decimal128Array>().unwrap();
+                let decimal_constructor = py.eval_bound("decimal.Decimal", None, None)?;
+                decimal_constructor
+                    .call1((array.value_as_string(i),))?
+                    .into()
+            }
+            DataType::Decimal256(_, _) => {
+                let array = array.as_any().downcast_ref::<Decimal256Array>().unwrap();
+                let decimal_constructor = py.eval_bound("decimal.Decimal", None, None)?;
+                decimal_constructor
+                    .call1((array.value_as_string(i),))?
+                    .into()
+            }
+            DataType::Date32 => {
+                let array = array.as_any().downcast_ref::<Date32Array>().unwrap();
+                let to_date = py.eval_bound("_to_date", None, None)?;
+                to_date.call1((array.value(i),))?.into()
+            }
+            DataType::Date64 => {
+                let array = array.as_any().downcast_ref::<Date64Array>().unwrap();
+                let days = array.value(i).div_euclid(MILLISECONDS_PER_DAY);
+                let to_date = py.eval_bound("_to_date", None, None)?;
+                to_date.call1((days,))?.into()
+            }
+            DataType::Time32(unit) | DataType::Time64(unit) => {
+                let to_time = py.eval_bound("_to_time", None, None)?;
+                let value = temporal_value(array, i);
+                to_time.call1((value, units_per_second(unit)))?.into()
+            }
+            DataType::Timestamp(unit, tz) => {
+                let to_datetime = py.eval_bound("_to_datetime", None, None)?;
+                let value = temporal_value(array, i);
+                let mode = self.timestamp_argument.code();
+                let args = (value, units_per_second(unit), tz.as_deref(), mode);
+                to_datetime.call1(args)?.into()
+            }
+            DataType::Duration(unit) => {
+                let to_timedelta = py.eval_bound("_to_timedelta", None, None)?;
+                let value = temporal_value(array, i);
+                to_timedelta.call1((value, units_per_second(unit)))?.into()
+            }
+            DataType::Interval(unit) => {
+                let (months, days, nanoseconds) = match unit {
+                    IntervalUnit::YearMonth => {
+                        (array.as_primitive::<IntervalYearMonthType>().value(i), 0, 0)
+                    }
+                    IntervalUnit::DayTime => {
+                        let value = array.as_primitive::<IntervalDayTimeType>().value(i);
+                        (0, value.days, value.milliseconds as i64 * 1_000_000)
+                    }
+                    IntervalUnit::MonthDayNano => {
+                        let value = array.as_primitive::<IntervalMonthDayNanoType>().value(i);
+                        (value.months, value.days, value.nanoseconds)
+                    }
+                };
+                let interval = py.eval_bound("Interval", None, None)?;
+                interval.call1((months, days, nanoseconds))?.into()
+            }
             // TODO: make this a macro
             DataType::Utf8 => match field.metadata().get(self.arrow_extension_key.as_ref()) {
-                Some(x) if x == &self.json_extension_name => {
-                    let array = array.as_any().downcast_ref::<StringArray>().unwrap();
-                    let string = array.value(i);
-                    // XXX: it is slow to call eval every time
-                    let json_loads = py.eval_bound("json.loads", None, None)?;
-                    json_loads.call1((string,))?.into()
-                }
                 Some(x) if x == &self.decimal_extension_name => {
                     let array = array.as_any().downcast_ref::<StringArray>().unwrap();
                     let string = array.value(i);
@@ -179,6 +380,12 @@ impl Converter {
                 _ => get_pyobject!(BinaryArray, py, array, i),
             },
             DataType::LargeBinary => get_pyobject!(LargeBinaryArray, py, array, i),
+            DataType::FixedSizeBinary(_) if self.is_uuid(field) => {
+                let array = array.as_fixed_size_binary();
+                let to_uuid = py.eval_bound("_to_uuid", None, None)?;
+                to_uuid.call1((array.value(i),))?.into()
+            }
+            DataType::FixedSizeBinary(_) => get_pyobject!(FixedSizeBinaryArray, py, array, i),
             DataType::Utf8View => get_pyobject!(StringViewArray, py, array, i),
             DataType::BinaryView => match field.metadata().get(self.arrow_extension_key.as_ref()) {
                 Some(x) if x == &self.pickle_extension_name => {
@@ -190,30 +397,32 @@ impl Converter {
                 _ => get_pyobject!(BinaryViewArray, py, array, i),
             },
 
-            DataType::List(field) => {
-                let array = array.as_any().downcast_ref::<ListArray>().unwrap();
-                let list = array.value(i);
-                let mut values = Vec::with_capacity(list.len());
-                for j in 0..list.len() {
-                    values.push(self.get_pyobject(py, field, list.as_ref(), j)?);
+            DataType::List(field) => self.get_list(py, field, array.as_list::<i32>(), i)?,
+            DataType::LargeList(field) => self.get_list(py, field, array.as_list::<i64>(), i)?,
+            DataType::Map(_, _) => {
+                let entries = array.as_map().value(i);
+                let dict = PyDict::new_bound(py);
+                let [key_field, value_field] = entry_fields(field) else {
+                    unreachable!("map entries must have two fields");
+                };
+                for j in 0..entries.len() {
+                    let key = self.get_pyobject(py, key_field, entries.column(0).as_ref(), j)?;
+                    let value =
+                        self.get_pyobject(py, value_field, entries.column(1).as_ref(), j)?;
+                    dict.set_item(key, value)?;
                 }
-                values.into_py(py)
+                dict.into()
             }
-            DataType::LargeList(field) => {
-                let array = array.as_any().downcast_ref::<LargeListArray>().unwrap();
-                let list = array.value(i);
-                let mut values = Vec::with_capacity(list.len());
-                for j in 0..list.len() {
-                    values.push(self.get_pyobject(py, field, list.as_ref(), j)?);
-                }
-                values.into_py(py)
+            DataType::Dictionary(_, _) => {
+                let values = array.as_any_dictionary().values();
+                self.get_pyobject(py, field, values.as_ref(), dictionary_key(array, i))?
             }
             DataType::Struct(fields) => {
                 let array = array.as_any().downcast_ref::<StructArray>().unwrap();
                 let object = py.eval_bound("Struct()", None, None)?;
                 for (j, field) in fields.iter().enumerate() {
                     let value = self.get_pyobject(py, field, array.column(j).as_ref(), i)?;
-                    object.setattr(field.name().as_str(), value)?;
+                    object.set_item(field.name().as_str(), value)?;
                 }
                 object.into()
             }
@@ -226,6 +435,32 @@ impl Converter {
         })
     }
 
+    /// Get array element as a python object, reusing the objects converted from the values
+    /// of a dictionary array for rows with the same key.
+    ///
+    /// The cache is for one array, and other arrays are converted without it.
+    pub fn get_pyobject_cached(
+        &self,
+        py: Python<'_>,
+        field: &Field,
+        array: &dyn Array,
+        i: usize,
+        cache: &mut Vec<Option<PyObject>>,
+    ) -> PyResult<PyObject> {
+        if !matches!(array.data_type(), DataType::Dictionary(_, _)) || array.is_null(i) {
+            return self.get_pyobject(py, field, array, i);
+        }
+        let values = array.as_any_dictionary().values();
+        cache.resize_with(values.len(), || None);
+        let key = dictionary_key(array, i);
+        if let Some(object) = &cache[key] {
+            return Ok(object.clone_ref(py));
+        }
+        let object = self.get_pyobject(py, field, values.as_ref(), key)?;
+        cache[key] = Some(object.clone_ref(py));
+        Ok(object)
+    }
+
     /// Build arrow array from python objects.
     pub fn build_array(
         &self,
@@ -233,6 +468,32 @@ impl Converter {
         py: Python<'_>,
         values: &[PyObject],
     ) -> PyResult<ArrayRef> {
+        if let Some(id) = self.custom(field, field.data_type()) {
+            let from_python = py
+                .eval_bound("_converters", None, None)?
+                .get_item(id)?
+                .get_item(1)?;
+            let values = (values.iter())
+                .map(|value| match value.is_none(py) {
+                    true => Ok(py.None()),
+                    false => Ok(from_python.call1((value,))?.into()),
+                })
+                .collect::<PyResult<Vec<_>>>()?;
+            return self.build_builtin_array(field, py, &values);
+        }
+        self.build_builtin_array(field, py, values)
+    }
+
+    /// Build arrow array from python objects without custom converters.
+    fn build_builtin_array(
+        &self,
+        field: &Field,
+        py: Python<'_>,
+        values: &[PyObject],
+    ) -> PyResult<ArrayRef> {
+        if self.is_json(field) && is_byte_type(field.data_type()) {
+            return build_json_array(field.data_type(), py, values);
+        }
         match field.data_type() {
             DataType::Null => build_array!(NullBuilder, py, values),
             DataType::Boolean => build_array!(BooleanBuilder, py, values),
@@ -244,12 +505,153 @@ impl Converter {
             DataType::UInt16 => build_array!(UInt16Builder, py, values),
             DataType::UInt32 => build_array!(UInt32Builder, py, values),
             DataType::UInt64 => build_array!(UInt64Builder, py, values),
+            DataType::Float16 => build_float16_array(py, values),
             DataType::Float32 => build_array!(Float32Builder, py, values),
             DataType::Float64 => build_array!(Float64Builder, py, values),
-            DataType::Utf8 => match field.metadata().get(self.arrow_extension_key.as_ref()) {
-                Some(x) if x == &self.json_extension_name => {
-                    build_json_array!(py, values)
+            DataType::Decimal128(precision, scale) => {
+                build_decimal_array::<Decimal128Type>(py, values, *precision, *scale, |s| {
+                    s.parse().ok()
+                })
+            }
+            DataType::Decimal256(precision, scale) => {
+                build_decimal_array::<Decimal256Type>(py, values, *precision, *scale, |s| {
+                    i256::from_string(s)
+                })
+            }
+            DataType::Date32 => build_temporal_array::<Date32Type>(
+                field,
+                py,
+                values,
+                1,
+                self.naive_timestamp_return,
+            ),
+            DataType::Date64 => build_temporal_array::<Date64Type>(
+                field,
+                py,
+                values,
+                MILLISECONDS_PER_DAY,
+                self.naive_timestamp_return,
+            ),
+            DataType::Time32(unit)
+            | DataType::Time64(unit)
+            | DataType::Timestamp(unit, _)
+            | DataType::Duration(unit) => {
+                let ups = units_per_second(unit);
+                match field.data_type() {
+                    DataType::Time32(TimeUnit::Second) => build_temporal_array::<Time32SecondType>(
+                        field,
+                        py,
+                        values,
+                        ups,
+                        self.naive_timestamp_return,
+                    ),
+                    DataType::Time32(TimeUnit::Millisecond) => {
+                        build_temporal_array::<Time32MillisecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Time64(TimeUnit::Microsecond) => {
+                        build_temporal_array::<Time64MicrosecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Time64(TimeUnit::Nanosecond) => {
+                        build_temporal_array::<Time64NanosecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Timestamp(TimeUnit::Second, _) => {
+                        build_temporal_array::<TimestampSecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                        build_temporal_array::<TimestampMillisecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                        build_temporal_array::<TimestampMicrosecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                        build_temporal_array::<TimestampNanosecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Duration(TimeUnit::Second) => {
+                        build_temporal_array::<DurationSecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Duration(TimeUnit::Millisecond) => {
+                        build_temporal_array::<DurationMillisecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Duration(TimeUnit::Microsecond) => {
+                        build_temporal_array::<DurationMicrosecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    DataType::Duration(TimeUnit::Nanosecond) => {
+                        build_temporal_array::<DurationNanosecondType>(
+                            field,
+                            py,
+                            values,
+                            ups,
+                            self.naive_timestamp_return,
+                        )
+                    }
+                    other => Err(PyTypeError::new_err(format!(
+                        "Unimplemented datatype {}",
+                        other
+                    ))),
                 }
+            }
+            DataType::Interval(unit) => build_interval_array(py, values, unit),
+            DataType::Utf8 => match field.metadata().get(self.arrow_extension_key.as_ref()) {
                 Some(x) if x == &self.decimal_extension_name => {
                     let mut builder = StringBuilder::with_capacity(values.len(), 1024);
                     for val in values {
@@ -261,7 +663,7 @@ impl Converter {
                     }
                     Ok(Arc::new(builder.finish()))
                 }
-                _ => build_array!(StringBuilder, &str, py, values),
+                _ => build_byte_array!(StringBuilder, LargeStringBuilder, &str, py, values),
             },
             DataType::LargeUtf8 => build_array!(LargeStringBuilder, &str, py, values),
             DataType::Binary => match field.metadata().get(self.arrow_extension_key.as_ref()) {
@@ -280,82 +682,104 @@ impl Converter {
                     }
                     Ok(Arc::new(builder.finish()))
                 }
-                _ => build_array!(BinaryBuilder, &[u8], py, values),
+                _ => build_byte_array!(BinaryBuilder, LargeBinaryBuilder, &[u8], py, values),
             },
-            DataType::LargeBinary => {
-                match field.metadata().get(self.arrow_extension_key.as_ref()) {
-                    Some(x) if x == &self.json_extension_name => {
-                        build_json_array!(py, values)
+            DataType::LargeBinary => build_array!(LargeBinaryBuilder, &[u8], py, values),
+            DataType::FixedSizeBinary(size) => {
+                let from_uuid = match self.is_uuid(field) {
+                    true => Some(py.eval_bound("_from_uuid", None, None)?),
+                    false => None,
+                };
+                let mut builder = FixedSizeBinaryBuilder::with_capacity(values.len(), *size);
+                for val in values {
+                    if val.is_none(py) {
+                        builder.append_null();
+                    } else {
+                        let val = match &from_uuid {
+                            Some(from_uuid) => from_uuid.call1((val,))?,
+                            None => val.bind(py).clone(),
+                        };
+                        let bytes = val.extract::<&[u8]>()?;
+                        builder.append_value(bytes).map_err(|_| {
+                            PyValueError::new_err(format!(
+                                "expect {size} bytes, got {}",
+                                bytes.len()
+                            ))
+                        })?;
                     }
-                    _ => build_array!(LargeBinaryBuilder, &[u8], py, values),
                 }
+                Ok(Arc::new(builder.finish()))
             }
             DataType::Utf8View => build_array!(StringViewBuilder, &str, py, values, 1),
-            DataType::BinaryView => match field.metadata().get(self.arrow_extension_key.as_ref()) {
-                Some(x) if x == &self.json_extension_name => {
-                    build_json_array!(py, values)
-                }
-                _ => build_array!(BinaryViewBuilder, &[u8], py, values, 1),
-            },
-            // list
-            DataType::List(inner) => {
-                // flatten lists
-                let mut flatten_values = vec![];
+            DataType::BinaryView => build_array!(BinaryViewBuilder, &[u8], py, values, 1),
+            DataType::List(inner) => self.build_list_array::<i32>(inner, py, values),
+            DataType::LargeList(inner) => self.build_list_array::<i64>(inner, py, values),
+            DataType::Map(entries, sorted) => {
+                let [key_field, value_field] = entry_fields(field) else {
+                    return Err(PyTypeError::new_err("map entries must have two fields"));
+                };
+                let mut keys = vec![];
+                let mut items = vec![];
                 let mut offsets = Vec::<i32>::with_capacity(values.len() + 1);
                 offsets.push(0);
                 for val in values {
-                    if !val.is_none(py) {
-                        let array = val.bind(py);
-                        flatten_values.reserve(array.len()?);
-                        for elem in array.iter()? {
-                            flatten_values.push(elem?.into());
-                        }
-                    }
-                    offsets.push(flatten_values.len() as i32);
-                }
-                let values_array = self.build_array(inner, py, &flatten_values)?;
-                let nulls = values.iter().map(|v| !v.is_none(py)).collect();
-                Ok(Arc::new(ListArray::new(
-                    inner.clone(),
-                    OffsetBuffer::new(offsets.into()),
-                    values_array,
-                    Some(nulls),
-                )))
-            }
-            // large list
-            DataType::LargeList(inner) => {
-                // flatten lists
-                let mut flatten_values = vec![];
-                let mut offsets = Vec::<i64>::with_capacity(values.len() + 1);
-                offsets.push(0);
-                for val in values {
-                    if !val.is_none(py) {
-                        let array = val.bind(py);
-                        flatten_values.reserve(array.len()?);
-                        for elem in array.iter()? {
-                            flatten_values.push(elem?.into());
+                    let val = val.bind(py);
+                    if !val.is_none() {
+                        let Ok(entries) = val.call_method0("items") else {
+                            return Err(PyTypeError::new_err(format!(
+                                "expect a dict, got {}",
+                                val.get_type().name()?
+                            )));
+                        };
+                        for entry in entries.iter()? {
+                            let (key, value) = entry?.extract::<(PyObject, PyObject)>()?;
+                            if key.is_none(py) {
+                                return Err(PyValueError::new_err("map keys must not be None"));
+                            }
+                            keys.push(key);
+                            items.push(value);
                         }
                     }
-                    offsets.push(flatten_values.len() as i64);
+                    let offset = i32::try_from(keys.len())
+                        .map_err(|_| PyValueError::new_err("map offset overflow"))?;
+                    offsets.push(offset);
                 }
-                let values_array = self.build_array(inner, py, &flatten_values)?;
+                let DataType::Struct(fields) = entries.data_type() else {
+                    unreachable!("map entries must be a struct");
+                };
+                let columns = vec![
+                    self.build_array(key_field, py, &keys)?,
+                    self.build_array(value_field, py, &items)?,
+                ];
+                let fields = (fields.iter().zip(&columns))
+                    .map(|(field, array)| promoted_field(field, array.data_type()))
+                    .collect();
+                let entries_array = StructArray::try_new(fields, columns, None)
+                    .map_err(|e| PyTypeError::new_err(e.to_string()))?;
+                let entries = promoted_field(entries, entries_array.data_type());
                 let nulls = values.iter().map(|v| !v.is_none(py)).collect();
-                Ok(Arc::new(LargeListArray::new(
-                    inner.clone(),
+                let array = MapArray::try_new(
+                    entries,
                     OffsetBuffer::new(offsets.into()),
-                    values_array,
+                    entries_array,
                     Some(nulls),
-                )))
+                    *sorted,
+                )
+                .map_err(|e| PyTypeError::new_err(e.to_string()))?;
+                Ok(Arc::new(array))
             }
             DataType::Struct(fields) => {
                 let mut arrays = Vec::with_capacity(fields.len());
-                for field in fields {
+                for (i, field) in fields.iter().enumerate() {
                     let mut field_values = Vec::with_capacity(values.len());
                     for val in values {
                         let v = if val.is_none(py) {
                             py.None()
                         } else if let Ok(value) = val.getattr(py, field.name().as_str()) {
                             value
+                        } else if let Ok(tuple) = val.downcast_bound::<PyTuple>(py) {
+                            // fields of a tuple are in order
+                            tuple.get_item(i)?.into()
                         } else {
                             val.bind(py).get_item(field.name().as_str())?.into()
                         };
@@ -363,12 +787,13 @@ impl Converter {
                     }
                     arrays.push(self.build_array(field, py, &field_values)?);
                 }
+                let fields = (fields.iter().zip(&arrays))
+                    .map(|(field, array)| promoted_field(field, array.data_type()))
+                    .collect();
                 let nulls = values.iter().map(|v| !v.is_none(py)).collect();
-                Ok(Arc::new(StructArray::new(
-                    fields.clone(),
-                    arrays,
-                    Some(nulls),
-                )))
+                let array = StructArray::try_new(fields, arrays, Some(nulls))
+                    .map_err(|e| PyTypeError::new_err(e.to_string()))?;
+                Ok(Arc::new(array))
             }
             other => Err(PyTypeError::new_err(format!(
                 "Unimplemented datatype {}",
@@ -376,4 +801,389 @@ impl Converter {
             ))),
         }
     }
+
+    /// Get the list at a row as a Python list.
+    fn get_list<O: OffsetSizeTrait>(
+        &self,
+        py: Python<'_>,
+        field: &Field,
+        array: &GenericListArray<O>,
+        i: usize,
+    ) -> PyResult<PyObject> {
+        let list = array.value(i);
+        let mut values = Vec::with_capacity(list.len());
+        for j in 0..list.len() {
+            values.push(self.get_pyobject(py, field, list.as_ref(), j)?);
+        }
+        Ok(values.into_py(py))
+    }
+
+    /// Build a list array from Python sequences, e.g. `list` or `tuple`.
+    ///
+    /// Strings and bytes are rejected instead of being split into characters, and so are
+    /// iterators, which would be consumed if conversion is retried row by row.
+    fn build_list_array<O: OffsetSizeTrait>(
+        &self,
+        inner: &Arc<Field>,
+        py: Python<'_>,
+        values: &[PyObject],
+    ) -> PyResult<ArrayRef> {
+        // flatten lists
+        let mut flatten_values = vec![];
+        let mut offsets = Vec::<O>::with_capacity(values.len() + 1);
+        offsets.push(O::usize_as(0));
+        for val in values {
+            let val = val.bind(py);
+            if !val.is_none() {
+                let list = match val.downcast::<PySequence>() {
+                    Ok(list)
+                        if !val.is_instance_of::<PyString>()
+                            && !val.is_instance_of::<PyBytes>() =>
+                    {
+                        list
+                    }
+                    _ => {
+                        return Err(PyTypeError::new_err(format!(
+                            "expect a list, got {}",
+                            val.get_type().name()?
+                        )))
+                    }
+                };
+                flatten_values.reserve(list.len()?);
+                for elem in list.iter()? {
+                    flatten_values.push(elem?.into());
+                }
+            }
+            let offset = O::from_usize(flatten_values.len())
+                .ok_or_else(|| PyValueError::new_err("list offset overflow"))?;
+            offsets.push(offset);
+        }
+        let values_array = self.build_array(inner, py, &flatten_values)?;
+        let nulls = values.iter().map(|v| !v.is_none(py)).collect();
+        Ok(Arc::new(GenericListArray::<O>::new(
+            promoted_field(inner, values_array.data_type()),
+            OffsetBuffer::new(offsets.into()),
+            values_array,
+            Some(nulls),
+        )))
+    }
+}
+
+/// Build an interval array from `datetime.timedelta` or tuples of months, days and nanoseconds.
+///
+/// Values that can't be represented by the unit are rejected, e.g. days of `YearMonth` intervals.
+fn build_interval_array(
+    py: Python<'_>,
+    values: &[PyObject],
+    unit: &IntervalUnit,
+) -> PyResult<ArrayRef> {
+    let from_interval = py.eval_bound("_from_interval", None, None)?;
+    let mut intervals = Vec::with_capacity(values.len());
+    for val in values {
+        if val.is_none(py) {
+            intervals.push(None);
+            continue;
+        }
+        let (months, days, nanoseconds): (i32, i32, i64) =
+            from_interval.call1((val,))?.extract()?;
+        let out_of_range = || {
+            PyValueError::new_err(format!(
+                "{} is out of range of interval {unit:?}",
+                val.bind(py)
+            ))
+        };
+        let interval = match unit {
+            IntervalUnit::YearMonth if days == 0 && nanoseconds == 0 => {
+                IntervalMonthDayNano::new(months, 0, 0)
+            }
+            IntervalUnit::DayTime if months == 0 && nanoseconds % 1_000_000 == 0 => {
+                i32::try_from(nanoseconds / 1_000_000).map_err(|_| out_of_range())?;
+                IntervalMonthDayNano::new(0, days, nanoseconds)
+            }
+            IntervalUnit::MonthDayNano => IntervalMonthDayNano::new(months, days, nanoseconds),
+            _ => return Err(out_of_range()),
+        };
+        intervals.push(Some(interval));
+    }
+    let array: ArrayRef = match unit {
+        IntervalUnit::YearMonth => Arc::new(IntervalYearMonthArray::from_iter(
+            intervals.iter().map(|v| v.map(|v| v.months)),
+        )),
+        IntervalUnit::DayTime => {
+            Arc::new(IntervalDayTimeArray::from_iter(intervals.iter().map(|v| {
+                v.map(|v| IntervalDayTime::new(v.days, (v.nanoseconds / 1_000_000) as i32))
+            })))
+        }
+        IntervalUnit::MonthDayNano => Arc::new(IntervalMonthDayNanoArray::from_iter(intervals)),
+    };
+    Ok(array)
+}
+
+/// Returns the field with the type of an array built for it, which differs if the strings
+/// or binaries in the array are promoted to their large types.
+pub fn promoted_field(field: &FieldRef, data_type: &DataType) -> FieldRef {
+    if field.data_type() == data_type {
+        field.clone()
+    } else {
+        Arc::new(field.as_ref().clone().with_data_type(data_type.clone()))
+    }
+}
+
+/// Returns an object implementing the [Arrow PyCapsule Interface] for an array,
+/// e.g. to be imported by `pyarrow.array` without copying the buffers.
+///
+/// The array is exported through the C Data Interface each time `__arrow_c_array__` is called.
+///
+/// [Arrow PyCapsule Interface]: https://arrow.apache.org/docs/format/CDataInterface/PyCapsuleInterface.html
+pub fn get_arrow_array<'py>(py: Python<'py>, array: &dyn Array) -> PyResult<Bound<'py, PyAny>> {
+    let data = array.to_data();
+    let export = PyCFunction::new_closure_bound(py, None, None, move |args, _kwargs| {
+        let py = args.py();
+        let (array, schema) = to_ffi(&data).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let schema = PyCapsule::new_bound(py, schema, Some(c"arrow_schema".into()))?;
+        let array = PyCapsule::new_bound(py, array, Some(c"arrow_array".into()))?;
+        PyResult::<PyObject>::Ok((schema, array).into_py(py))
+    })?;
+    py.eval_bound("ArrowArray", None, None)?
+        .call1((export, array.len()))
+}
+
+/// Build an array from an object implementing the [Arrow PyCapsule Interface], e.g. a `pyarrow.Array`,
+/// without copying the buffers.
+///
+/// [Arrow PyCapsule Interface]: https://arrow.apache.org/docs/format/CDataInterface/PyCapsuleInterface.html
+pub fn build_arrow_array(object: &Bound<'_, PyAny>) -> PyResult<ArrayRef> {
+    if !object.hasattr("__arrow_c_array__")? {
+        return Err(PyTypeError::new_err(format!(
+            "expect an object implementing __arrow_c_array__, got {}",
+            object.get_type().name()?
+        )));
+    }
+    let (schema, array) = object
+        .call_method0("__arrow_c_array__")?
+        .extract::<(Bound<'_, PyCapsule>, Bound<'_, PyCapsule>)>()?;
+    for (capsule, name) in [(&schema, c"arrow_schema"), (&array, c"arrow_array")] {
+        if capsule.name()? != Some(name) {
+            return Err(PyValueError::new_err(format!(
+                "expect a capsule named {name:?} from __arrow_c_array__"
+            )));
+        }
+    }
+    // Safety: the capsules are named as required by the interface, and the array is moved out of
+    // its capsule, so that it is released by us rather than by the capsule.
+    let data = unsafe {
+        let schema = &*(schema.pointer() as *const FFI_ArrowSchema);
+        let array = std::ptr::replace(
+            array.pointer() as *mut FFI_ArrowArray,
+            FFI_ArrowArray::empty(),
+        );
+        from_ffi(array, schema)
+    }
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(make_array(data))
+}
+
+/// Returns true if the type is a string or binary type.
+fn is_byte_type(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Utf8View
+            | DataType::Binary
+            | DataType::LargeBinary
+            | DataType::BinaryView
+    )
+}
+
+/// Returns the bytes of a string or binary array at a row, or `None` for other types.
+fn byte_value(array: &dyn Array, i: usize) -> Option<&[u8]> {
+    Some(match array.data_type() {
+        DataType::Utf8 => array.as_string::<i32>().value(i).as_bytes(),
+        DataType::LargeUtf8 => array.as_string::<i64>().value(i).as_bytes(),
+        DataType::Utf8View => array.as_string_view().value(i).as_bytes(),
+        DataType::Binary => array.as_binary::<i32>().value(i),
+        DataType::LargeBinary => array.as_binary::<i64>().value(i),
+        DataType::BinaryView => array.as_binary_view().value(i),
+        _ => return None,
+    })
+}
+
+/// Build a half-precision float array from Python floats, which are rounded to the nearest values.
+fn build_float16_array(py: Python<'_>, values: &[PyObject]) -> PyResult<ArrayRef> {
+    let mut builder = Float16Builder::with_capacity(values.len());
+    for pyobj in values {
+        if pyobj.is_none(py) {
+            builder.append_null();
+        } else {
+            builder.append_value(f16::from_f32(pyobj.extract(py)?));
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+/// Build a string or binary array of JSON type from Python objects serialized by `json.dumps`.
+fn build_json_array(
+    data_type: &DataType,
+    py: Python<'_>,
+    values: &[PyObject],
+) -> PyResult<ArrayRef> {
+    let json_dumps = py.eval_bound("json.dumps", None, None)?;
+    let mut strings = Vec::with_capacity(values.len());
+    for val in values {
+        if val.is_none(py) {
+            strings.push(None);
+        } else {
+            strings.push(Some(json_dumps.call1((val,))?.extract::<String>()?));
+        }
+    }
+    Ok(match data_type {
+        DataType::Utf8 => Arc::new(StringArray::from_iter(strings)),
+        DataType::LargeUtf8 => Arc::new(LargeStringArray::from_iter(strings)),
+        DataType::Utf8View => Arc::new(StringViewArray::from_iter(strings)),
+        DataType::Binary => Arc::new(BinaryArray::from_iter(strings)),
+        DataType::LargeBinary => Arc::new(LargeBinaryArray::from_iter(strings)),
+        DataType::BinaryView => Arc::new(BinaryViewArray::from_iter(strings)),
+        other => unreachable!("not a string or binary type: {other}"),
+    })
+}
+
+/// Returns the key of a dictionary array at a row.
+fn dictionary_key(array: &dyn Array, i: usize) -> usize {
+    downcast_dictionary_array!(
+        array => array.keys().value(i).as_usize(),
+        other => unreachable!("not a dictionary type: {other}")
+    )
+}
+
+/// Returns the key and value fields of a map type.
+fn entry_fields(field: &Field) -> &[Arc<Field>] {
+    match field.data_type() {
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(fields) => fields,
+            _ => &[],
+        },
+        _ => &[],
+    }
+}
+
+/// Build a decimal array from Python objects convertible to `decimal.Decimal`,
+/// e.g. `Decimal`, `int`, `float` or `str`, rounding them to the scale.
+fn build_decimal_array<T: DecimalType>(
+    py: Python<'_>,
+    values: &[PyObject],
+    precision: u8,
+    scale: i8,
+    parse: impl Fn(&str) -> Option<T::Native>,
+) -> PyResult<ArrayRef> {
+    let to_int = py.eval_bound("_decimal_to_int", None, None)?;
+    let mut builder = PrimitiveBuilder::<T>::with_capacity(values.len());
+    for val in values {
+        if val.is_none(py) {
+            builder.append_null();
+            continue;
+        }
+        let string = to_int.call1((val, scale))?;
+        let value = parse(string.extract::<&str>()?)
+            .filter(|value| T::validate_decimal_precision(*value, precision).is_ok())
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "{} is out of range of decimal({precision}, {scale})",
+                    val.bind(py),
+                ))
+            })?;
+        builder.append_value(value);
+    }
+    let array = (builder.finish())
+        .with_precision_and_scale(precision, scale)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(Arc::new(array))
+}
+
+const MILLISECONDS_PER_DAY: i64 = 86_400_000;
+
+/// Returns the number of values per second of a time unit.
+fn units_per_second(unit: &TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Second => 1,
+        TimeUnit::Millisecond => 1_000,
+        TimeUnit::Microsecond => 1_000_000,
+        TimeUnit::Nanosecond => 1_000_000_000,
+    }
+}
+
+/// Returns the value of a time, timestamp or duration array as an integer.
+fn temporal_value(array: &dyn Array, i: usize) -> i64 {
+    match array.data_type() {
+        DataType::Time32(TimeUnit::Second) => {
+            array.as_primitive::<Time32SecondType>().value(i) as _
+        }
+        DataType::Time32(_) => array.as_primitive::<Time32MillisecondType>().value(i) as _,
+        DataType::Time64(TimeUnit::Microsecond) => {
+            array.as_primitive::<Time64MicrosecondType>().value(i)
+        }
+        DataType::Time64(_) => array.as_primitive::<Time64NanosecondType>().value(i),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            array.as_primitive::<TimestampSecondType>().value(i)
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            array.as_primitive::<TimestampMillisecondType>().value(i)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            array.as_primitive::<TimestampMicrosecondType>().value(i)
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            array.as_primitive::<TimestampNanosecondType>().value(i)
+        }
+        DataType::Duration(TimeUnit::Second) => array.as_primitive::<DurationSecondType>().value(i),
+        DataType::Duration(TimeUnit::Millisecond) => {
+            array.as_primitive::<DurationMillisecondType>().value(i)
+        }
+        DataType::Duration(TimeUnit::Microsecond) => {
+            array.as_primitive::<DurationMicrosecondType>().value(i)
+        }
+        DataType::Duration(TimeUnit::Nanosecond) => {
+            array.as_primitive::<DurationNanosecondType>().value(i)
+        }
+        other => unreachable!("not a time, timestamp or duration type: {other}"),
+    }
+}
+
+/// Build a temporal array from Python objects, converted to integers by the helper function
+/// of the type, with the number of values per day for dates, or per second for other types.
+fn build_temporal_array<T>(
+    field: &Field,
+    py: Python<'_>,
+    values: &[PyObject],
+    unit: i64,
+    naive: NaiveTimestampReturn,
+) -> PyResult<ArrayRef>
+where
+    T: ArrowPrimitiveType,
+    T::Native: for<'py> pyo3::FromPyObject<'py>,
+{
+    let helper = match field.data_type() {
+        DataType::Date32 | DataType::Date64 => "_from_date",
+        DataType::Time32(_) | DataType::Time64(_) => "_from_time",
+        DataType::Duration(_) => "_from_timedelta",
+        _ => "_from_datetime",
+    };
+    let helper = py.eval_bound(helper, None, None)?;
+    let tz = match field.data_type() {
+        DataType::Timestamp(_, Some(tz)) => Some(tz.as_ref()),
+        _ => None,
+    };
+    let mut builder = PrimitiveBuilder::<T>::with_capacity(values.len());
+    for val in values {
+        if val.is_none(py) {
+            builder.append_null();
+        } else if let Some(tz) = tz {
+            let value = helper.call1((val, unit, tz, naive.code()))?;
+            builder.append_value(value.extract()?);
+        } else {
+            builder.append_value(helper.call1((val, unit))?.extract()?);
+        }
+    }
+    let array = builder.finish().with_data_type(field.data_type().clone());
+    Ok(Arc::new(array))
 }
@@ -0,0 +1,74 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handling of the errors of individual rows.
+
+use anyhow::Result;
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, RecordBatch};
+use arrow_udf_core::Error;
+
+/// The schema metadata key of the number of row errors suppressed by [`RowErrorPolicy::Null`].
+pub const SUPPRESSED_ERRORS_KEY: &str = "arrowudf.suppressed_errors";
+
+/// How rows that raise exceptions or fail to be converted are reported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RowErrorPolicy {
+    /// The call fails with [`Error::Execution`] at the first failed row.
+    Fail,
+    /// Failed rows are null, with their messages in the `error` column following the outputs.
+    #[default]
+    ErrorColumn,
+    /// Failed rows are null without messages, for best-effort functions.
+    ///
+    /// The number of failed rows is in the [`SUPPRESSED_ERRORS_KEY`] metadata of the output schema
+    /// if any row fails.
+    Null,
+}
+
+/// Apply the policy to an output, whose `error` column follows the first `num_columns` columns
+/// if any row fails. `input_row` maps the rows of the output to those of the input.
+pub fn apply(
+    policy: RowErrorPolicy,
+    output: RecordBatch,
+    num_columns: usize,
+    input_row: impl Fn(usize) -> usize,
+) -> Result<RecordBatch> {
+    if policy == RowErrorPolicy::ErrorColumn || output.num_columns() == num_columns {
+        return Ok(output);
+    }
+    let errors = output.column(num_columns).as_string::<i32>();
+    match policy {
+        RowErrorPolicy::Fail => {
+            let (row, message) = (errors.iter().enumerate())
+                .find_map(|(i, error)| Some((i, error?)))
+                .expect("the error column has an error");
+            Err(Error::Execution {
+                row: Some(input_row(row)),
+                message: message.to_string(),
+                traceback: None,
+            }
+            .into())
+        }
+        RowErrorPolicy::Null => {
+            let suppressed = errors.len() - errors.null_count();
+            let output = output.project(&(0..num_columns).collect::<Vec<_>>())?;
+            let mut metadata = output.schema().metadata().clone();
+            metadata.insert(SUPPRESSED_ERRORS_KEY.into(), suppressed.to_string());
+            let schema = output.schema().as_ref().clone().with_metadata(metadata);
+            Ok(output.with_schema(schema.into())?)
+        }
+        RowErrorPolicy::ErrorColumn => unreachable!(),
+    }
+}
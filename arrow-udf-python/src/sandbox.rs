@@ -0,0 +1,314 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OS-level sandbox for worker processes.
+//!
+//! A [`Sandbox`] describes restrictions applied by a worker process to itself before it executes
+//! user code. It is a defense in depth on top of the sandboxed Python interpreter:
+//!
+//! - **rlimits**: limit the memory, CPU time, open files and file size of the worker.
+//! - **network namespace**: move the worker into a new network namespace without any interface.
+//! - **landlock**: deny filesystem access except reading from the allowed paths.
+//! - **seccomp**: deny system calls that user code never needs, such as `execve`, `ptrace` and `mount`.
+//!
+//! The host passes the sandbox to the worker as command line arguments, see [`Sandbox::to_args`].
+//! Network namespaces, landlock and seccomp are only supported on Linux.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+/// OS-level restrictions of a worker process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    max_memory: Option<u64>,
+    max_cpu_time: Option<u64>,
+    max_open_files: Option<u64>,
+    max_file_size: Option<u64>,
+    isolate_network: bool,
+    /// If `Some`, filesystem access is restricted to reading from these paths.
+    readable_paths: Option<Vec<PathBuf>>,
+    seccomp: bool,
+}
+
+impl Sandbox {
+    /// Create a sandbox without any restriction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the virtual memory of the worker in bytes.
+    pub fn max_memory(mut self, bytes: u64) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+
+    /// Limit the CPU time of the worker. The worker is killed by `SIGXCPU` when exceeded.
+    ///
+    /// The limit is rounded up to whole seconds.
+    pub fn max_cpu_time(mut self, time: Duration) -> Self {
+        self.max_cpu_time = Some(time.as_secs() + (time.subsec_nanos() > 0) as u64);
+        self
+    }
+
+    /// Limit the number of file descriptors opened by the worker.
+    pub fn max_open_files(mut self, n: u64) -> Self {
+        self.max_open_files = Some(n);
+        self
+    }
+
+    /// Limit the size of files written by the worker in bytes.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Move the worker into a new network namespace, so that it can not access the network.
+    pub fn isolate_network(mut self, isolate: bool) -> Self {
+        self.isolate_network = isolate;
+        self
+    }
+
+    /// Deny filesystem access except reading from the path and files beneath it.
+    ///
+    /// Can be called multiple times to allow multiple paths.
+    /// Modules imported by user code must be beneath one of the allowed paths.
+    pub fn allow_read(mut self, path: impl Into<PathBuf>) -> Self {
+        self.readable_paths
+            .get_or_insert_with(Vec::new)
+            .push(path.into());
+        self
+    }
+
+    /// Deny filesystem access. Equivalent to calling [`allow_read`](Self::allow_read) with no path.
+    pub fn deny_filesystem(mut self) -> Self {
+        self.readable_paths.get_or_insert_with(Vec::new);
+        self
+    }
+
+    /// Deny system calls that user code never needs, such as `execve`, `ptrace` and `mount`.
+    pub fn seccomp(mut self, enable: bool) -> Self {
+        self.seccomp = enable;
+        self
+    }
+
+    /// Encode the sandbox as command line arguments of the worker.
+    pub fn to_args(&self) -> Vec<OsString> {
+        let mut args = vec![];
+        let limits = [
+            ("--max-memory", self.max_memory),
+            ("--max-cpu-time", self.max_cpu_time),
+            ("--max-open-files", self.max_open_files),
+            ("--max-file-size", self.max_file_size),
+        ];
+        for (name, value) in limits {
+            if let Some(value) = value {
+                args.push(name.into());
+                args.push(value.to_string().into());
+            }
+        }
+        if self.isolate_network {
+            args.push("--isolate-network".into());
+        }
+        if let Some(paths) = &self.readable_paths {
+            args.push("--deny-filesystem".into());
+            for path in paths {
+                args.push("--allow-read".into());
+                args.push(path.into());
+            }
+        }
+        if self.seccomp {
+            args.push("--seccomp".into());
+        }
+        args
+    }
+
+    /// Decode the sandbox from command line arguments produced by [`to_args`](Self::to_args).
+    ///
+    /// Returns the sandbox and the other arguments.
+    pub fn from_args(args: impl IntoIterator<Item = OsString>) -> Result<(Self, Vec<OsString>)> {
+        let mut sandbox = Self::default();
+        let mut others = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value of {}", arg.to_string_lossy()))
+            };
+            let mut number = || -> Result<Option<u64>> {
+                let value = value()?;
+                let value = value.to_str().context("invalid number")?;
+                Ok(Some(value.parse().context("invalid number")?))
+            };
+            match arg.to_str() {
+                Some("--max-memory") => sandbox.max_memory = number()?,
+                Some("--max-cpu-time") => sandbox.max_cpu_time = number()?,
+                Some("--max-open-files") => sandbox.max_open_files = number()?,
+                Some("--max-file-size") => sandbox.max_file_size = number()?,
+                Some("--isolate-network") => sandbox.isolate_network = true,
+                Some("--deny-filesystem") => sandbox = sandbox.deny_filesystem(),
+                Some("--allow-read") => sandbox = sandbox.allow_read(value()?),
+                Some("--seccomp") => sandbox.seccomp = true,
+                _ => others.push(arg),
+            }
+        }
+        Ok((sandbox, others))
+    }
+
+    /// Apply the resource limits and namespaces to the current process.
+    ///
+    /// This must be called while the process is single-threaded, before initializing Python.
+    pub fn apply_limits(&self) -> Result<()> {
+        if self.isolate_network {
+            unshare_network()?;
+        }
+        #[cfg(unix)]
+        {
+            let limits = [
+                (libc::RLIMIT_AS, self.max_memory),
+                (libc::RLIMIT_CPU, self.max_cpu_time),
+                (libc::RLIMIT_NOFILE, self.max_open_files),
+                (libc::RLIMIT_FSIZE, self.max_file_size),
+            ];
+            for (resource, value) in limits {
+                if let Some(value) = value {
+                    let limit = libc::rlimit {
+                        rlim_cur: value as _,
+                        rlim_max: value as _,
+                    };
+                    // SAFETY: the pointer is valid during the call
+                    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                        return Err(std::io::Error::last_os_error())
+                            .context("failed to set resource limit");
+                    }
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        if self.max_memory.is_some()
+            || self.max_cpu_time.is_some()
+            || self.max_open_files.is_some()
+            || self.max_file_size.is_some()
+        {
+            bail!("resource limits are not supported on this platform");
+        }
+        Ok(())
+    }
+
+    /// Apply the filesystem and system call restrictions to the current thread.
+    ///
+    /// This should be called after initializing Python and before executing user code.
+    /// The restrictions can not be lifted once applied, and are inherited by child threads and processes.
+    pub fn apply_restrictions(&self) -> Result<()> {
+        if let Some(paths) = &self.readable_paths {
+            restrict_filesystem(paths)?;
+        }
+        if self.seccomp {
+            restrict_syscalls()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn unshare_network() -> Result<()> {
+    // an unprivileged process needs a user namespace to create a network namespace
+    // SAFETY: unshare has no memory safety requirement
+    let flags = match unsafe { libc::geteuid() } {
+        0 => libc::CLONE_NEWNET,
+        _ => libc::CLONE_NEWUSER | libc::CLONE_NEWNET,
+    };
+    // SAFETY: unshare has no memory safety requirement
+    if unsafe { libc::unshare(flags) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("failed to create network namespace");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn restrict_filesystem(paths: &[PathBuf]) -> Result<()> {
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetStatus, ABI,
+    };
+
+    let abi = ABI::V1;
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(paths, AccessFs::from_read(abi)))?
+        .restrict_self()?;
+    if status.ruleset == RulesetStatus::NotEnforced {
+        bail!("landlock is not supported by the kernel");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn restrict_syscalls() -> Result<()> {
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter};
+
+    let denied = [
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_reboot,
+        libc::SYS_kexec_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+    ];
+    let filter = SeccompFilter::new(
+        denied.into_iter().map(|n| (n, vec![])).collect(),
+        SeccompAction::Allow,
+        SeccompAction::Errno(libc::EPERM as u32),
+        std::env::consts::ARCH.try_into()?,
+    )?;
+    let program: BpfProgram = filter.try_into()?;
+    seccompiler::apply_filter(&program)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unshare_network() -> Result<()> {
+    bail!("network isolation is only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn restrict_filesystem(_paths: &[PathBuf]) -> Result<()> {
+    bail!("filesystem restriction is only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn restrict_syscalls() -> Result<()> {
+    bail!("seccomp is only supported on Linux")
+}
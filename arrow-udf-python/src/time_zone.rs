@@ -0,0 +1,64 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of timestamps with time zones.
+
+/// How arguments of timestamps with time zones, i.e. `Timestamp(_, Some(tz))`, are converted to `datetime`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimestampArgument {
+    /// Aware `datetime` in the time zone of the type.
+    #[default]
+    InTimeZone,
+    /// Aware `datetime` in UTC.
+    Utc,
+    /// Naive `datetime` of the wall clock in UTC.
+    Naive,
+}
+
+/// How naive `datetime` returned for timestamps with time zones are interpreted.
+///
+/// Aware `datetime` are always converted to UTC, which timestamps with time zones are stored in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NaiveTimestampReturn {
+    /// The wall clock in UTC.
+    #[default]
+    Utc,
+    /// The wall clock in the time zone of the type.
+    InTimeZone,
+    /// Naive `datetime` raise `ValueError`, which fails the call,
+    /// or the row if [row conversion errors](crate::Runtime::set_row_conversion_errors) are enabled.
+    Reject,
+}
+
+impl TimestampArgument {
+    /// Returns the `mode` argument of `_to_datetime` in the interpreter.
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Self::InTimeZone => 0,
+            Self::Utc => 1,
+            Self::Naive => 2,
+        }
+    }
+}
+
+impl NaiveTimestampReturn {
+    /// Returns the `naive` argument of `_from_datetime` in the interpreter.
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Self::Utc => 0,
+            Self::InTimeZone => 1,
+            Self::Reject => 2,
+        }
+    }
+}
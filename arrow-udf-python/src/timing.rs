@@ -0,0 +1,66 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time spent in the phases of a call, recorded in `tracing` spans.
+
+#[cfg(feature = "tracing")]
+use std::time::{Duration, Instant};
+
+/// The time spent in converting values between Arrow and Python, and in executing Python code.
+///
+/// They are recorded in the `convert_time` and `execute_time` fields of the current span.
+/// Without the `tracing` feature, nothing is measured.
+#[derive(Debug, Default)]
+pub struct CallTimes {
+    #[cfg(feature = "tracing")]
+    convert: Duration,
+    #[cfg(feature = "tracing")]
+    execute: Duration,
+}
+
+impl CallTimes {
+    /// Run a conversion and add its time.
+    pub fn convert<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        let result = f();
+        #[cfg(feature = "tracing")]
+        {
+            self.convert += start.elapsed();
+        }
+        result
+    }
+
+    /// Run Python code of the function and add its time.
+    pub fn execute<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+        let result = f();
+        #[cfg(feature = "tracing")]
+        {
+            self.execute += start.elapsed();
+        }
+        result
+    }
+
+    /// Record the times in the current span.
+    pub fn record(&self) {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("convert_time", tracing::field::debug(self.convert));
+            span.record("execute_time", tracing::field::debug(self.execute));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of the Python types of return values.
+
+use arrow_schema::{DataType, Field};
+use pyo3::types::{PyAnyMethods, PyTypeMethods};
+use pyo3::{PyObject, PyResult, Python};
+
+/// How return values that are not of the Python type of the return type are handled.
+///
+/// The Python type of each return type is listed in the README, e.g. `int` for integers.
+/// Only the outermost value is validated, and values of struct, JSON and pickle types are not.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReturnValidation {
+    /// Values must be exactly of the Python type, e.g. `int` but not `bool` or `float` for integers.
+    ///
+    /// Rows returning values of other types are reported in the error column.
+    Strict,
+    /// Values are converted if they can be, e.g. `int` for floats and `bool` for integers.
+    #[default]
+    Cast,
+    /// Values of other types are converted by calling the Python type on them first,
+    /// e.g. `str(value)` for strings or `int(value)` for integers.
+    ///
+    /// Values that can't be converted by the type are then converted as in [`Cast`](Self::Cast).
+    Coerce,
+}
+
+/// Validate or coerce non-null return values for the return field.
+///
+/// Rows failing the validation are replaced by `None`, with their errors added in the order of rows.
+pub fn validate(
+    py: Python<'_>,
+    field: &Field,
+    validation: ReturnValidation,
+    results: &mut [PyObject],
+    errors: &mut Vec<(usize, String)>,
+) -> PyResult<()> {
+    if validation == ReturnValidation::Cast {
+        return Ok(());
+    }
+    let Some(types) = python_types(field) else {
+        return Ok(());
+    };
+    let num_errors = errors.len();
+    for (i, result) in results.iter_mut().enumerate() {
+        if result.is_none(py) {
+            continue;
+        }
+        let ty = result.bind(py).get_type();
+        let name = ty.qualname()?;
+        let module: String = ty.getattr("__module__")?.extract()?;
+        let actual = match module.as_str() {
+            "builtins" => name,
+            _ => format!("{module}.{name}"),
+        };
+        if types.contains(&actual.as_str()) {
+            continue;
+        }
+        match validation {
+            ReturnValidation::Strict => {
+                errors.push((i, format!("TypeError: expect {}, got {actual}", types[0])));
+                *result = py.None();
+            }
+            ReturnValidation::Coerce => {
+                if let Some(value) = coerce(py, types[0], result)? {
+                    *result = value;
+                }
+            }
+            ReturnValidation::Cast => unreachable!(),
+        }
+    }
+    if errors.len() > num_errors {
+        errors.sort_by_key(|(i, _)| *i);
+    }
+    Ok(())
+}
+
+/// Returns the Python types of the values of a field, the first of which is built by coercion,
+/// or `None` if the values are not validated.
+fn python_types(field: &Field) -> Option<&'static [&'static str]> {
+    match field
+        .metadata()
+        .get("ARROW:extension:name")
+        .map(String::as_str)
+    {
+        Some("arrowudf.decimal") => return Some(&["decimal.Decimal"]),
+        Some("arrow.uuid") => return Some(&["uuid.UUID"]),
+        Some(_) => return None,
+        None => {}
+    }
+    Some(match field.data_type() {
+        DataType::Boolean => &["bool"],
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => &["int"],
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => &["float"],
+        DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => &["decimal.Decimal"],
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => &["str"],
+        DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => &["bytes"],
+        DataType::Date32 | DataType::Date64 => &["datetime.date"],
+        DataType::Time32(_) | DataType::Time64(_) => &["datetime.time"],
+        DataType::Timestamp(_, _) => &["datetime.datetime"],
+        DataType::Duration(_) => &["datetime.timedelta"],
+        DataType::List(_) | DataType::LargeList(_) => &["list", "tuple"],
+        DataType::Map(_, _) => &["dict"],
+        _ => return None,
+    })
+}
+
+/// Convert a value by calling the Python type on it, or returns `None` if it can't be converted.
+fn coerce(py: Python<'_>, ty: &str, value: &PyObject) -> PyResult<Option<PyObject>> {
+    let constructor = match ty {
+        "bool" | "int" | "float" | "str" => py.eval_bound(ty, None, None)?,
+        // only strings are converted to bytes, by encoding them in UTF-8
+        "bytes" => {
+            let bytes = value.bind(py).call_method1("encode", ("utf-8",));
+            return Ok(bytes.ok().map(|bytes| bytes.unbind()));
+        }
+        _ => return Ok(None),
+    };
+    Ok(constructor.call1((value,)).ok().map(|value| value.unbind()))
+}
@@ -0,0 +1,86 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "tokio")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::CancelToken;
+use arrow_udf_python::{AsyncRuntime, CallMode, Runtime};
+
+fn spin_runtime() -> AsyncRuntime {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "spin",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def spin(x):
+    while x > 0:
+        pass
+    return x
+"#,
+        )
+        .unwrap();
+    AsyncRuntime::new(runtime)
+}
+
+fn input(x: i32) -> RecordBatch {
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![x]);
+    RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap()
+}
+
+#[tokio::test]
+async fn test_call_async() {
+    let runtime = spin_runtime();
+    let output = runtime.call("spin", &input(0)).await.unwrap();
+    assert_eq!(output.num_rows(), 1);
+
+    let err = runtime.call("unknown", &input(0)).await.unwrap_err();
+    assert_eq!(err.to_string(), "function not found: unknown");
+}
+
+#[tokio::test]
+async fn test_cancel_async() {
+    let runtime = spin_runtime();
+    let token = CancelToken::new();
+    let input = input(1);
+    let call = runtime.call_cancellable("spin", &input, &token);
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        token.cancel();
+    };
+    let (result, ()) = tokio::join!(call, cancel);
+    assert_eq!(result.unwrap_err().to_string(), "cancelled");
+}
+
+#[tokio::test]
+async fn test_drop_async() {
+    let runtime = spin_runtime();
+    let start = Instant::now();
+    let timeout = Duration::from_millis(100);
+    tokio::time::timeout(timeout, runtime.call("spin", &input(1)))
+        .await
+        .unwrap_err();
+
+    // the dropped call is interrupted and releases the interpreter
+    let output = runtime.call("spin", &input(0)).await.unwrap();
+    assert_eq!(output.num_rows(), 1);
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
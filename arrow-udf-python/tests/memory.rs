@@ -0,0 +1,81 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Memory accounting must be enabled before Python is initialized,
+//! so these tests run in their own process.
+
+use std::sync::Arc;
+
+use arrow_array::*;
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_python::{CallMode, Runtime};
+use expect_test::{expect, Expect};
+
+#[test]
+fn test_memory_limit() {
+    // the allocators are wrapped by the first runtime, before Python is initialized
+    let mut runtime = Runtime::builder()
+        .memory_limit(Some(16 << 20))
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "alloc",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def alloc(n):
+    return len([0] * n)
+"#,
+        )
+        .unwrap();
+    assert!(runtime.memory_usage().unwrap() > 0);
+
+    let schema = Schema::new(vec![Field::new("n", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![10, 100_000_000, 10]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // the memory is released after the failed row
+    let output = runtime.call("alloc", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-------+---------------+
+            | alloc | error         |
+            +-------+---------------+
+            | 10    |               |
+            |       | MemoryError:  |
+            | 10    |               |
+            +-------+---------------+"#]],
+    );
+
+    runtime.set_memory_limit(None).unwrap();
+    let input = input.slice(1, 1);
+    let output = runtime.call("alloc", &input).unwrap();
+    assert_eq!(output.num_columns(), 1);
+
+    // other runtimes are not accounted unless enabled
+    let runtime = Runtime::new().unwrap();
+    assert_eq!(runtime.memory_usage(), None);
+    assert!(runtime.set_memory_limit(Some(16 << 20)).is_err());
+    let runtime = Runtime::builder().memory_accounting(true).build().unwrap();
+    assert!(runtime.memory_usage().unwrap() > 0);
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
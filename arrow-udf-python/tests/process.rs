@@ -0,0 +1,447 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::*;
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_python::process::ProcessRuntime;
+use arrow_udf_python::sandbox::Sandbox;
+use arrow_udf_python::CallMode;
+use expect_test::{expect, Expect};
+
+fn runtime() -> ProcessRuntime {
+    ProcessRuntime::builder()
+        .program(env!("CARGO_BIN_EXE_arrow-udf-python-worker"))
+        .build()
+        .unwrap()
+}
+
+fn int32_input(values: Vec<Option<i32>>) -> RecordBatch {
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int32Array::from(values))]).unwrap()
+}
+
+#[test]
+fn test_call() {
+    let mut runtime = runtime();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def gcd(a: int, b: int) -> int:
+    # printing should not corrupt the protocol
+    print("gcd", a, b)
+    while b:
+        a, b = b, a % b
+    return a
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(25), None]);
+    let arg1 = Int32Array::from(vec![Some(15), None]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let output = runtime.call("gcd", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        |     |
+        +-----+"#]],
+    );
+}
+
+#[test]
+fn test_table_function() {
+    let mut runtime = runtime();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def series(n: int):
+    for i in range(n):
+        yield i
+"#,
+        )
+        .unwrap();
+
+    let input = int32_input(vec![Some(1), Some(3)]);
+    let outputs = runtime.call_table_function("series", &input, 2).unwrap();
+    assert_eq!(outputs.len(), 2);
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 1   | 0      |
+        | 1   | 1      |
+        | 1   | 2      |
+        +-----+--------+"#]],
+    );
+}
+
+#[test]
+fn test_error() {
+    let mut runtime = runtime();
+    let err = runtime
+        .add_function(
+            "f",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def f(",
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("SyntaxError"), "{err}");
+
+    let err = runtime.call("f", &int32_input(vec![])).unwrap_err();
+    assert_eq!(err.to_string(), "function not found: f");
+    assert_eq!(runtime.restarts(), 0);
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_allow_module() {
+    let mut runtime = ProcessRuntime::builder()
+        .program(env!("CARGO_BIN_EXE_arrow-udf-python-worker"))
+        .sandboxed(true)
+        .allow_module("textwrap")
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "dedent",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import textwrap
+
+def dedent(x: int) -> int:
+    return int(textwrap.dedent(f"  {x}"))
+"#,
+        )
+        .unwrap();
+    let output = runtime.call("dedent", &int32_input(vec![Some(1)])).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +--------+
+            | dedent |
+            +--------+
+            | 1      |
+            +--------+"#]],
+    );
+
+    let err = runtime
+        .add_function(
+            "f",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "import os",
+        )
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("import os is not allowed"),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_restart_after_crash() {
+    let mut runtime = runtime();
+    runtime
+        .add_function(
+            "crash",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import os
+
+def crash(x: int) -> int:
+    if x < 0:
+        os._exit(1)
+    return x
+"#,
+        )
+        .unwrap();
+
+    let err = runtime
+        .call("crash", &int32_input(vec![Some(-1)]))
+        .unwrap_err();
+    assert!(
+        err.to_string().starts_with("worker process crashed"),
+        "{err}"
+    );
+
+    // the worker is restarted with the function restored
+    let output = runtime.call("crash", &int32_input(vec![Some(1)])).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------+
+        | crash |
+        +-------+
+        | 1     |
+        +-------+"#]],
+    );
+    assert_eq!(runtime.restarts(), 1);
+}
+
+#[test]
+fn test_max_message_size() {
+    let mut runtime = ProcessRuntime::builder()
+        .program(env!("CARGO_BIN_EXE_arrow-udf-python-worker"))
+        .max_message_size(1 << 16)
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "repeat",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def repeat(n: int) -> str:
+    return "x" * n
+"#,
+        )
+        .unwrap();
+
+    let err = runtime
+        .call("repeat", &int32_input(vec![Some(1 << 20)]))
+        .unwrap_err();
+    assert!(
+        format!("{err:#}").contains("exceeds the maximum size of 65536 bytes"),
+        "{err:#}"
+    );
+
+    // the worker is restarted
+    let output = runtime.call("repeat", &int32_input(vec![Some(3)])).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | repeat |
+        +--------+
+        | xxx    |
+        +--------+"#]],
+    );
+    assert_eq!(runtime.restarts(), 1);
+}
+
+#[test]
+fn test_response_timeout() {
+    let mut runtime = ProcessRuntime::builder()
+        .program(env!("CARGO_BIN_EXE_arrow-udf-python-worker"))
+        .response_timeout(Some(Duration::from_millis(500)))
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "sleep",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import time
+
+def sleep(x: int) -> int:
+    time.sleep(x)
+    return x
+"#,
+        )
+        .unwrap();
+
+    let err = runtime
+        .call("sleep", &int32_input(vec![Some(60)]))
+        .unwrap_err();
+    assert!(
+        format!("{err:#}").contains("worker did not respond in 500ms"),
+        "{err:#}"
+    );
+
+    let output = runtime.call("sleep", &int32_input(vec![Some(0)])).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------+
+        | sleep |
+        +-------+
+        | 0     |
+        +-------+"#]],
+    );
+    assert_eq!(runtime.restarts(), 1);
+}
+
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(runtime());
+    runtime
+        .add_function(
+            "neg",
+            Field::new("neg", DataType::Int32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            r#"
+def neg(x: int) -> int:
+    return -x
+"#,
+        )
+        .unwrap();
+    assert_eq!(runtime.list_functions(), ["neg"]);
+
+    let output = runtime.call("neg", &int32_input(vec![Some(1)])).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | neg |
+        +-----+
+        | -1  |
+        +-----+"#]],
+    );
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "`os.unshare` requires Python 3.12")]
+fn test_os_sandbox() {
+    let sandbox = Sandbox::new()
+        .max_open_files(64)
+        .isolate_network(true)
+        .seccomp(true);
+    let mut runtime = ProcessRuntime::builder()
+        .program(env!("CARGO_BIN_EXE_arrow-udf-python-worker"))
+        .os_sandbox(sandbox)
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "probe",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import os
+import socket
+
+def probe(x: int) -> str:
+    try:
+        if x == 0:
+            os.unshare(os.CLONE_NEWUTS)
+        if x == 1:
+            return ",".join(name for _, name in socket.if_nameindex())
+        if x == 2:
+            [os.open("/dev/null", os.O_RDONLY) for _ in range(100)]
+    except OSError as e:
+        return type(e).__name__
+"#,
+        )
+        .unwrap();
+
+    let output = runtime
+        .call("probe", &int32_input(vec![Some(0), Some(1), Some(2)]))
+        .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----------------+
+            | probe           |
+            +-----------------+
+            | PermissionError |
+            | lo              |
+            | OSError         |
+            +-----------------+"#]],
+    );
+}
+
+#[test]
+fn test_os_sandbox_filesystem() {
+    let dir = std::env::temp_dir().join(format!("arrow-udf-python-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("data.txt"), "hello").unwrap();
+
+    let sandbox = Sandbox::new().allow_read(&dir);
+    let mut runtime = ProcessRuntime::builder()
+        .program(env!("CARGO_BIN_EXE_arrow-udf-python-worker"))
+        .os_sandbox(sandbox)
+        .build()
+        .unwrap();
+    let code = format!(
+        r#"
+def read(x: int) -> str:
+    path = ["{}/data.txt", "/etc/hostname"][x]
+    try:
+        return open(path).read()
+    except OSError as e:
+        return type(e).__name__
+"#,
+        dir.display()
+    );
+    runtime
+        .add_function(
+            "read",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            &code,
+        )
+        .unwrap();
+
+    let output = runtime
+        .call("read", &int32_input(vec![Some(0), Some(1)]))
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----------------+
+            | read            |
+            +-----------------+
+            | hello           |
+            | PermissionError |
+            +-----------------+"#]],
+    );
+}
+
+#[test]
+fn test_os_sandbox_args() {
+    let sandbox = Sandbox::new()
+        .max_memory(1 << 30)
+        .max_cpu_time(Duration::from_millis(1500))
+        .allow_read("/usr/lib")
+        .seccomp(true);
+    let mut args = sandbox.to_args();
+    args.push("--sandboxed".into());
+    let (decoded, others) = Sandbox::from_args(args).unwrap();
+    assert_eq!(decoded, sandbox);
+    assert_eq!(others, ["--sandboxed"]);
+}
@@ -14,10 +14,12 @@
 
 use std::sync::Arc;
 
+use arrow_array::cast::AsArray;
 use arrow_array::*;
 use arrow_cast::pretty::{pretty_format_batches, pretty_format_columns};
 use arrow_schema::{DataType, Field, Schema};
-use arrow_udf_python::{CallMode, Runtime};
+use arrow_udf_core::CancelToken;
+use arrow_udf_python::{CallMode, RowErrorPolicy, Runtime, SUPPRESSED_ERRORS_KEY};
 use expect_test::{expect, Expect};
 
 #[test]
@@ -61,6 +63,198 @@ def gcd(a: int, b: int) -> int:
     runtime.del_function("gcd").unwrap();
 }
 
+#[test]
+fn test_pickle_function() {
+    let mut runtime = Runtime::new().unwrap();
+    // `pickle.dumps(math.gcd, protocol=0)`
+    runtime
+        .add_function_from_pickle(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            b"cmath\ngcd\np0\n.",
+        )
+        .unwrap();
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(25), None]);
+    let arg1 = Int32Array::from(vec![Some(15), Some(1)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let output = runtime.call("gcd", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        |     |
+        +-----+"#]],
+    );
+
+    // `pickle.dumps(1, protocol=0)`
+    let err = runtime
+        .add_function_from_pickle(
+            "one",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            b"I1\n.",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "TypeError: expect a callable from pickle, got int"
+    );
+
+    runtime.remove_function("gcd").unwrap();
+}
+
+#[test]
+fn test_class_function() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "count_matches",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import re
+
+class count_matches:
+    def __init__(self):
+        self.pattern = re.compile(r'\d+')
+        self.calls = 0
+
+    def __call__(self, s):
+        self.calls += 1
+        return len(self.pattern.findall(s)) * 100 + self.calls
+"#,
+        )
+        .unwrap();
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec!["a1b22", "c333"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // the instance persists across calls
+    runtime.call("count_matches", &input).unwrap();
+    let output = runtime.call("count_matches", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +---------------+
+            | count_matches |
+            +---------------+
+            | 203           |
+            | 104           |
+            +---------------+"#]],
+    );
+
+    // the number of arguments is checked against `__call__`
+    let input = RecordBatch::try_new_with_options(
+        Arc::new(Schema::empty()),
+        vec![],
+        &RecordBatchOptions::new().with_row_count(Some(1)),
+    )
+    .unwrap();
+    let err = runtime.call("count_matches", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: function count_matches expects 1 argument, but got 0"
+    );
+}
+
+#[test]
+fn test_call_selected() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "double",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            r#"
+def double(x: int) -> int:
+    if x < 0:
+        raise ValueError("negative")
+    return x * 2
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), Some(-1), Some(2), Some(-2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // rows with negative values are not evaluated
+    let selection = BooleanArray::from(vec![Some(true), Some(false), Some(true), None]);
+    let output = runtime.call_selected("double", &input, &selection).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | double |
+        +--------+
+        | 2      |
+        |        |
+        | 4      |
+        |        |
+        +--------+"#]],
+    );
+}
+
+#[test]
+fn test_call_broadcast() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "repeat",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def repeat(s: str, n: int) -> str:
+    return s * n
+"#,
+        )
+        .unwrap();
+
+    // the count is a constant of all rows
+    let s = StringArray::from(vec![Some("a"), None, Some("bc")]);
+    let args = arrow_udf_core::Arguments::new(3)
+        .with_array(Field::new("s", DataType::Utf8, true), Arc::new(s))
+        .unwrap();
+    let n = Int32Array::from(vec![2]);
+    let output = runtime
+        .call_broadcast(
+            "repeat",
+            &args
+                .clone()
+                .with_scalar(Field::new("n", DataType::Int32, true), Arc::new(n))
+                .unwrap(),
+        )
+        .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | repeat |
+        +--------+
+        | aa     |
+        |        |
+        | bcbc   |
+        +--------+"#]],
+    );
+
+    // a null scalar makes all rows null
+    let n = Int32Array::from(vec![None]);
+    let args = args
+        .with_scalar(Field::new("n", DataType::Int32, true), Arc::new(n))
+        .unwrap();
+    let output = runtime.call_broadcast("repeat", &args).unwrap();
+    assert_eq!(output.column(0).null_count(), 3);
+}
+
 #[test]
 fn test_max_with_custom_handler() {
     let mut runtime = Runtime::new().unwrap();
@@ -173,551 +367,4354 @@ def decimal_add(a, b):
 }
 
 #[test]
-fn test_json_array_access() {
+fn test_decimal128() {
     let mut runtime = Runtime::new().unwrap();
-
     runtime
         .add_function(
-            "json_array_access",
-            json_field("json"),
+            "scale",
+            DataType::Decimal128(10, 2),
             CallMode::ReturnNullOnNullInput,
             r#"
-def json_array_access(array, i):
-    return array[i]
-            "#,
+import decimal
+
+def scale(x, factor):
+    assert isinstance(x, decimal.Decimal)
+    return x * factor
+"#,
         )
         .unwrap();
 
     let schema = Schema::new(vec![
-        json_field("array"),
-        Field::new("i", DataType::Int32, true),
+        Field::new("x", DataType::Decimal128(10, 3), true),
+        Field::new("factor", DataType::Int32, true),
     ]);
-    let arg0 = StringArray::from(vec![r#"[1, null, ""]"#]);
-    let arg1 = Int32Array::from(vec![0]);
+    let arg0 = Decimal128Array::from(vec![Some(1234), Some(-1235), None, Some(1)])
+        .with_precision_and_scale(10, 3)
+        .unwrap();
+    let arg1 = Int32Array::from(vec![1, 1, 1, 5]);
     let input =
         RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
 
-    let output = runtime.call("json_array_access", &input).unwrap();
+    // values are rounded to the scale of the return type, and ties away from zero
+    let output = runtime.call("scale", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +------+
-        | json |
-        +------+
-        | 1    |
-        +------+"#]],
+            +-------+
+            | scale |
+            +-------+
+            | 1.23  |
+            | -1.24 |
+            |       |
+            | 0.01  |
+            +-------+"#]],
+    );
+
+    // values exceeding the precision fail
+    let arg1 = Int32Array::from(vec![100_000_000]);
+    let input = RecordBatch::try_new(
+        input.schema(),
+        vec![input.column(0).slice(0, 1), Arc::new(arg1)],
+    )
+    .unwrap();
+    let err = runtime.call("scale", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ValueError: 123400000.000 is out of range of decimal(10, 2)"
     );
 }
 
 #[test]
-fn test_return_array() {
+fn test_decimal256() {
     let mut runtime = Runtime::new().unwrap();
-
     runtime
         .add_function(
-            "to_array",
-            DataType::new_list(DataType::Int32, true),
-            CallMode::CalledOnNullInput,
+            "parse",
+            DataType::Decimal256(50, 10),
+            CallMode::ReturnNullOnNullInput,
             r#"
-def to_array(x):
-    if x is None:
-        return None
-    else:
-        return [x]
+def parse(s):
+    return s
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
-    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec![
+        "1234567890123456789012345678901234567890.0123456789",
+        "-0.5",
+    ]);
     let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-    let output = runtime.call("to_array", &input).unwrap();
+    let output = runtime.call("parse", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +----------+
-        | to_array |
-        +----------+
-        | [1]      |
-        |          |
-        | [3]      |
-        +----------+"#]],
+            +-----------------------------------------------------+
+            | parse                                               |
+            +-----------------------------------------------------+
+            | 1234567890123456789012345678901234567890.0123456789 |
+            | -0.5000000000                                       |
+            +-----------------------------------------------------+"#]],
     );
 }
 
 #[test]
-fn test_return_large_array() {
-    let mut runtime = Runtime::new().unwrap();
+fn test_temporal() {
+    use arrow_schema::TimeUnit;
 
+    let mut runtime = Runtime::new().unwrap();
+    let types = [
+        ("date32", DataType::Date32),
+        ("date64", DataType::Date64),
+        ("time32", DataType::Time32(TimeUnit::Millisecond)),
+        ("time64", DataType::Time64(TimeUnit::Microsecond)),
+        ("timestamp", DataType::Timestamp(TimeUnit::Second, None)),
+        (
+            "timestamptz",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("+08:00".into())),
+        ),
+    ];
+    for (name, data_type) in &types {
+        runtime
+            .add_function(
+                name,
+                data_type.clone(),
+                CallMode::ReturnNullOnNullInput,
+                &format!("def {name}(x):\n    return x\n"),
+            )
+            .unwrap();
+    }
     runtime
         .add_function(
-            "to_large_array",
-            DataType::new_large_list(DataType::Int32, true),
-            CallMode::CalledOnNullInput,
+            "describe",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
             r#"
-def to_large_array(x):
-    if x is None:
-        return None
-    else:
-        return [x, x+1, x+2]
+import datetime
+
+def describe(date, time, timestamp, timestamptz):
+    assert type(date) is datetime.date and type(time) is datetime.time
+    assert type(timestamp) is datetime.datetime and timestamp.tzinfo is None
+    return f"{date} | {time} | {timestamp} | {timestamptz}"
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
-    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
-    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let arrays: Vec<ArrayRef> = vec![
+        Arc::new(Date32Array::from(vec![Some(19000), Some(-1), None])),
+        Arc::new(Date64Array::from(vec![
+            Some(1_641_600_000_000),
+            Some(-86_400_000),
+            None,
+        ])),
+        Arc::new(Time32MillisecondArray::from(vec![
+            Some(45_296_789),
+            Some(0),
+            None,
+        ])),
+        Arc::new(Time64MicrosecondArray::from(vec![
+            Some(45_296_789_012),
+            Some(86_399_999_999),
+            None,
+        ])),
+        Arc::new(TimestampSecondArray::from(vec![
+            Some(1_700_000_000),
+            Some(-1),
+            None,
+        ])),
+        Arc::new(
+            TimestampMillisecondArray::from(vec![Some(1_700_000_000_123), Some(-1), None])
+                .with_timezone("+08:00"),
+        ),
+    ];
+    let fields: Vec<_> = (types.iter())
+        .map(|(name, data_type)| Field::new(*name, data_type.clone(), true))
+        .collect();
+    let input = RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).unwrap();
 
-    let output = runtime.call("to_large_array", &input).unwrap();
+    // values are converted to Python objects and back
+    for (i, (name, _)) in types.iter().enumerate() {
+        let column = input.project(&[i]).unwrap();
+        let output = runtime.call(name, &column).unwrap();
+        assert_eq!(output.column(0), column.column(0), "{name}");
+    }
+
+    let input = input.project(&[0, 3, 4, 5]).unwrap();
+    let output = runtime.call("describe", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +----------------+
-        | to_large_array |
-        +----------------+
-        | [1, 2, 3]      |
-        |                |
-        | [3, 4, 5]      |
-        +----------------+"#]],
+            +---------------------------------------------------------------------------------------+
+            | describe                                                                              |
+            +---------------------------------------------------------------------------------------+
+            | 2022-01-08 | 12:34:56.789012 | 2023-11-14 22:13:20 | 2023-11-15 06:13:20.123000+08:00 |
+            | 1969-12-31 | 23:59:59.999999 | 1969-12-31 23:59:59 | 1970-01-01 07:59:59.999000+08:00 |
+            |                                                                                       |
+            +---------------------------------------------------------------------------------------+"#]],
     );
 }
 
 #[test]
-fn test_key_value() {
-    let mut runtime = Runtime::new().unwrap();
+fn test_return_temporal() {
+    use arrow_schema::TimeUnit;
 
+    let mut runtime = Runtime::new().unwrap();
     runtime
         .add_function(
-            "key_value",
-            DataType::Struct(
-                vec![
-                    Field::new("key", DataType::Utf8, true),
-                    Field::new("value", DataType::Utf8, true),
-                ]
-                .into(),
-            ),
+            "parse",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into())),
             CallMode::ReturnNullOnNullInput,
             r#"
-class KeyValue:
-    def __init__(self, key, value):
-        self.key = key
-        self.value = value
+import datetime
 
-def key_value(s: str):
-    key, value = s.split('=')
-    return KeyValue(key, value)
+def parse(s):
+    return datetime.datetime.fromisoformat(s)
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new("x", DataType::Utf8, true)]);
-    let arg0 = StringArray::from(vec!["a=b"]);
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec![
+        "2024-01-02T03:04:05.678901+08:00",
+        "2024-01-02T03:04:05",
+        "1969-12-31T23:59:59.999999Z",
+    ]);
     let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-    let output = runtime.call("key_value", &input).unwrap();
+    // values with time zones are converted to UTC, and values without are in UTC
+    let output = runtime.call("parse", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +--------------------+
-        | key_value          |
-        +--------------------+
-        | {key: a, value: b} |
-        +--------------------+"#]],
+            +-----------------------------+
+            | parse                       |
+            +-----------------------------+
+            | 2024-01-01T19:04:05.678901Z |
+            | 2024-01-02T03:04:05Z        |
+            | 1969-12-31T23:59:59.999999Z |
+            +-----------------------------+"#]],
     );
+}
 
+#[test]
+#[ignore = "allocates more than 3 GiB"]
+fn test_promote_large_binary() {
+    let mut runtime = Runtime::new().unwrap();
     runtime
         .add_function(
-            "key_value2",
-            DataType::Struct(
-                vec![
-                    Field::new("key", DataType::Utf8, true),
-                    Field::new("value", DataType::Utf8, true),
-                ]
-                .into(),
-            ),
+            "blob",
+            DataType::Binary,
             CallMode::ReturnNullOnNullInput,
             r#"
-def key_value2(s: str):
-    key, value = s.split('=')
-    return {"key": key, "value": value}
+BLOB = b"x" * 1_100_000_000
+
+def blob(x):
+    return BLOB
 "#,
         )
         .unwrap();
 
-    let output = runtime.call("key_value2", &input).unwrap();
-    check(
-        &[output],
-        expect![[r#"
-        +--------------------+
-        | key_value2         |
-        +--------------------+
-        | {key: a, value: b} |
-        +--------------------+"#]],
-    );
-}
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-#[test]
-fn test_runtime() {
-    let runtime = Runtime::new().unwrap();
-    drop(runtime);
-    let runtime = Runtime::new().unwrap();
-    drop(runtime);
+    // the values exceed `i32` offsets
+    let output = runtime.call("blob", &input).unwrap();
+    assert_eq!(output.schema().field(0).data_type(), &DataType::LargeBinary);
+    let array = output.column(0).as_binary::<i64>();
+    assert_eq!(array.value_length(2), 1_100_000_000);
+    assert!(array.is_null(1));
 }
 
 #[test]
-fn test_struct_to_json() {
+fn test_interval_duration() {
+    use arrow_array::types::IntervalMonthDayNano;
+    use arrow_schema::{IntervalUnit, TimeUnit};
+
     let mut runtime = Runtime::new().unwrap();
+    runtime.set_row_conversion_errors(true);
 
+    let duration = DataType::Duration(TimeUnit::Microsecond);
+    let interval = DataType::Interval(IntervalUnit::MonthDayNano);
     runtime
         .add_function(
-            "to_json",
-            json_field("to_json"),
+            "describe",
+            DataType::Utf8,
             CallMode::ReturnNullOnNullInput,
             r#"
-def to_json(object):
-    return object.__dict__
+def describe(d, i):
+    return f"{d!r} {i!r}"
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "double",
+            duration.clone(),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def double(d, i):
+    return d * 2
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "next_month",
+            interval.clone(),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def next_month(d, i):
+    return (i.months + 1, i.days, i.nanoseconds)
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "to_day_time",
+            DataType::Interval(IntervalUnit::DayTime),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def to_day_time(d, i):
+    return d if i.months == 0 else i
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new(
-        "struct",
-        DataType::Struct(
-            vec![
-                Field::new("key", DataType::Utf8, true),
-                Field::new("value", DataType::Utf8, true),
-            ]
-            .into(),
-        ),
-        true,
-    )]);
-    let arg0 = StructArray::from(vec![
-        (
-            Arc::new(Field::new("key", DataType::Utf8, true)),
-            Arc::new(StringArray::from(vec![Some("a"), None])) as ArrayRef,
-        ),
-        (
-            Arc::new(Field::new("value", DataType::Utf8, true)),
-            Arc::new(StringArray::from(vec![Some("b"), None])),
-        ),
+    let schema = Schema::new(vec![
+        Field::new("d", duration, true),
+        Field::new("i", interval, true),
     ]);
-    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let arg0 = DurationMicrosecondArray::from(vec![Some(1_500_000), Some(-1), None]);
+    let arg1 = IntervalMonthDayNanoArray::from(vec![
+        Some(IntervalMonthDayNano::new(0, 1, 1_000)),
+        Some(IntervalMonthDayNano::new(14, -2, 0)),
+        Some(IntervalMonthDayNano::new(0, 0, 0)),
+    ]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
 
-    let output = runtime.call("to_json", &input).unwrap();
+    let output = runtime.call("describe", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +------------------------------+
-        | to_json                      |
-        +------------------------------+
-        | {"key": "a", "value": "b"}   |
-        | {"key": null, "value": null} |
-        +------------------------------+"#]],
+        +-------------------------------------------------------------------------------------------------------------+
+        | describe                                                                                                    |
+        +-------------------------------------------------------------------------------------------------------------+
+        | datetime.timedelta(seconds=1, microseconds=500000) Interval(months=0, days=1, nanoseconds=1000)             |
+        | datetime.timedelta(days=-1, seconds=86399, microseconds=999999) Interval(months=14, days=-2, nanoseconds=0) |
+        |                                                                                                             |
+        +-------------------------------------------------------------------------------------------------------------+"#]],
+    );
+    let output = runtime.call("double", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------------+
+        | double       |
+        +--------------+
+        | PT3S         |
+        | -PT0.000002S |
+        |              |
+        +--------------+"#]],
+    );
+    let output = runtime.call("next_month", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------------------------------+
+        | next_month                     |
+        +--------------------------------+
+        | 1 mons 1 days 0.000001000 secs |
+        | 15 mons -2 days                |
+        |                                |
+        +--------------------------------+"#]],
+    );
+
+    // timedelta is accepted for intervals, and months are rejected for `DayTime`
+    let output = runtime.call("to_day_time", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------------+---------------------------------------------------------------------------------------------+
+        | to_day_time | error                                                                                       |
+        +-------------+---------------------------------------------------------------------------------------------+
+        | 1.500 secs  |                                                                                             |
+        |             | ValueError: Interval(months=14, days=-2, nanoseconds=0) is out of range of interval DayTime |
+        |             |                                                                                             |
+        +-------------+---------------------------------------------------------------------------------------------+"#]],
     );
 }
 
 #[test]
-fn test_range() {
+fn test_dictionary() {
     let mut runtime = Runtime::new().unwrap();
-
     runtime
         .add_function(
-            "range1",
-            DataType::Int32,
+            "count_same",
+            DataType::Utf8,
             CallMode::ReturnNullOnNullInput,
             r#"
-def range1(n: int):
-    for i in range(n):
-        yield i
+seen = []
+
+def count_same(s):
+    seen.append(s)
+    return f"{s} {sum(x is s for x in seen)}"
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
-    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let arg0: DictionaryArray<types::Int32Type> =
+        vec![Some("apple"), Some("banana"), None, Some("apple")]
+            .into_iter()
+            .collect();
+    let schema = Schema::new(vec![Field::new("s", arg0.data_type().clone(), true)]);
     let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-    let mut outputs = runtime.call_table_function("range1", &input, 2).unwrap();
-
-    assert_eq!(outputs.schema().field(0).name(), "row");
-    assert_eq!(outputs.schema().field(1).name(), "range1");
-    assert_eq!(outputs.schema().field(1).data_type(), &DataType::Int32);
-
-    let o1 = outputs.next().unwrap().unwrap();
-    let o2 = outputs.next().unwrap().unwrap();
-    assert_eq!(o1.num_rows(), 2);
-    assert_eq!(o2.num_rows(), 2);
-    assert!(outputs.next().is_none());
+    // dictionary values are resolved for each row
+    let output = runtime.call("count_same", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------------+
+        | count_same |
+        +------------+
+        | apple 1    |
+        | banana 1   |
+        |            |
+        | apple 1    |
+        +------------+"#]],
+    );
 
+    // rows with the same key share the converted value
+    runtime.set_dictionary_cache(true);
+    let output = runtime.call("count_same", &input).unwrap();
     check(
-        &[o1, o2],
+        &[output],
         expect![[r#"
-        +-----+--------+
-        | row | range1 |
-        +-----+--------+
-        | 0   | 0      |
-        | 2   | 0      |
-        | 2   | 1      |
-        | 2   | 2      |
-        +-----+--------+"#]],
+        +------------+
+        | count_same |
+        +------------+
+        | apple 1    |
+        | banana 1   |
+        |            |
+        | apple 2    |
+        +------------+"#]],
     );
 }
 
 #[test]
-fn test_sum() {
+fn test_json_array_access() {
     let mut runtime = Runtime::new().unwrap();
+
     runtime
-        .add_aggregate(
-            "sum",
-            DataType::Int32,
-            DataType::Int32,
+        .add_function(
+            "json_array_access",
+            json_field("json"),
             CallMode::ReturnNullOnNullInput,
             r#"
-def create_state():
-    return 0
-
-def accumulate(state, value):
-    return state + value
-
-def retract(state, value):
-    return state - value
-
-def merge(state1, state2):
-    return state1 + state2
-"#,
+def json_array_access(array, i):
+    return array[i]
+            "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new("value", DataType::Int32, true)]);
-    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
-    let ops = BooleanArray::from(vec![false, false, true, false]);
-    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let schema = Schema::new(vec![
+        json_field("array"),
+        Field::new("i", DataType::Int32, true),
+    ]);
+    let arg0 = StringArray::from(vec![r#"[1, null, ""]"#]);
+    let arg1 = Int32Array::from(vec![0]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
 
-    let state = runtime.create_state("sum").unwrap();
-    check_array(
-        std::slice::from_ref(&state),
+    let output = runtime.call("json_array_access", &input).unwrap();
+    check(
+        &[output],
         expect![[r#"
-            +-------+
-            | array |
-            +-------+
-            | 0     |
-            +-------+"#]],
+        +------+
+        | json |
+        +------+
+        | 1    |
+        +------+"#]],
     );
+}
 
-    let state = runtime.accumulate("sum", &state, &input).unwrap();
+#[test]
+fn test_json_types() {
+    let mut runtime = Runtime::new().unwrap();
+    let types = [
+        DataType::Utf8,
+        DataType::LargeUtf8,
+        DataType::Utf8View,
+        DataType::Binary,
+        DataType::LargeBinary,
+        DataType::BinaryView,
+    ];
+    let strings = StringArray::from(vec![Some(r#"{"n": 1}"#), Some("{}"), None]);
+    let mut outputs = vec![];
+    for data_type in types {
+        let field = Field::new("json", data_type.clone(), true)
+            .with_metadata([("ARROW:extension:name".into(), "arrowudf.json".into())].into());
+        runtime
+            .add_function(
+                "increment",
+                field.clone(),
+                CallMode::ReturnNullOnNullInput,
+                r#"
+def increment(object):
+    object["n"] = object.get("n", 0) + 1
+    return object
+"#,
+            )
+            .unwrap();
+        let arg0 = arrow_cast::cast(&strings, &data_type).unwrap();
+        let input = RecordBatch::try_new(Arc::new(Schema::new(vec![field])), vec![arg0]).unwrap();
+
+        let output = runtime.call("increment", &input).unwrap();
+        assert_eq!(output.column(0).data_type(), &data_type);
+        outputs.push(arrow_cast::cast(output.column(0), &DataType::Utf8).unwrap());
+        runtime.del_function("increment").unwrap();
+    }
     check_array(
-        std::slice::from_ref(&state),
+        &outputs,
         expect![[r#"
-            +-------+
-            | array |
-            +-------+
-            | 9     |
-            +-------+"#]],
+        +----------+
+        | array    |
+        +----------+
+        | {"n": 2} |
+        | {"n": 1} |
+        |          |
+        | {"n": 2} |
+        | {"n": 1} |
+        |          |
+        | {"n": 2} |
+        | {"n": 1} |
+        |          |
+        | {"n": 2} |
+        | {"n": 1} |
+        |          |
+        | {"n": 2} |
+        | {"n": 1} |
+        |          |
+        | {"n": 2} |
+        | {"n": 1} |
+        |          |
+        +----------+"#]],
     );
+}
 
-    let state = runtime
-        .accumulate_or_retract("sum", &state, &ops, &input)
+#[test]
+fn test_return_array() {
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime
+        .add_function(
+            "to_array",
+            DataType::new_list(DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            r#"
+def to_array(x):
+    if x is None:
+        return None
+    else:
+        return [x]
+"#,
+        )
         .unwrap();
-    check_array(
-        std::slice::from_ref(&state),
-        expect![[r#"
-            +-------+
-            | array |
-            +-------+
-            | 12    |
-            +-------+"#]],
-    );
 
-    let output = runtime.finish("sum", &state).unwrap();
-    check_array(
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("to_array", &input).unwrap();
+    check(
         &[output],
         expect![[r#"
-            +-------+
-            | array |
-            +-------+
-            | 12    |
-            +-------+"#]],
+        +----------+
+        | to_array |
+        +----------+
+        | [1]      |
+        |          |
+        | [3]      |
+        +----------+"#]],
     );
 }
 
 #[test]
-fn test_weighted_avg() {
+fn test_return_large_array() {
     let mut runtime = Runtime::new().unwrap();
+
     runtime
-        .add_aggregate(
-            "weighted_avg",
-            DataType::Struct(
+        .add_function(
+            "to_large_array",
+            DataType::new_large_list(DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            r#"
+def to_large_array(x):
+    if x is None:
+        return None
+    else:
+        return [x, x+1, x+2]
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("to_large_array", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +----------------+
+        | to_large_array |
+        +----------------+
+        | [1, 2, 3]      |
+        |                |
+        | [3, 4, 5]      |
+        +----------------+"#]],
+    );
+}
+
+#[test]
+fn test_nested_list() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime.set_row_conversion_errors(true);
+
+    let int_list = DataType::new_list(DataType::Int32, true);
+    runtime
+        .add_function(
+            "reverse_rows",
+            DataType::new_list(int_list.clone(), true),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def reverse_rows(matrix):
+    return [None if row is None else row[::-1] for row in matrix]
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "upper_tags",
+            DataType::new_large_list(DataType::Utf8, true),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def upper_tags(tags):
+    if len(tags) == 1:
+        return tags[0]
+    return tuple(tag.upper() for tag in tags)
+"#,
+        )
+        .unwrap();
+
+    let mut matrix =
+        builder::ListBuilder::new(builder::ListBuilder::new(builder::Int32Builder::new()));
+    matrix.append_value([Some(vec![Some(1), Some(2)]), None, Some(vec![])]);
+    matrix.append_null();
+    matrix.append_value([Some(vec![Some(3), None, Some(4)])]);
+    let schema = Schema::new(vec![Field::new(
+        "matrix",
+        DataType::new_list(int_list, true),
+        true,
+    )]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(matrix.finish())]).unwrap();
+
+    let output = runtime.call("reverse_rows", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +----------------+
+            | reverse_rows   |
+            +----------------+
+            | [[2, 1], , []] |
+            |                |
+            | [[4, , 3]]     |
+            +----------------+"#]],
+    );
+
+    let mut tags = builder::LargeListBuilder::new(builder::StringBuilder::new());
+    tags.append_value([Some("a"), Some("b")]);
+    tags.append_value([Some("single")]);
+    tags.append_value::<_, &str>([]);
+    let schema = Schema::new(vec![Field::new(
+        "tags",
+        DataType::new_large_list(DataType::Utf8, true),
+        true,
+    )]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(tags.finish())]).unwrap();
+
+    // tuples are accepted as lists, but strings are not split into characters
+    let output = runtime.call("upper_tags", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +------------+-----------------------------------+
+            | upper_tags | error                             |
+            +------------+-----------------------------------+
+            | [A, B]     |                                   |
+            |            | TypeError: expect a list, got str |
+            | []         |                                   |
+            +------------+-----------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_key_value() {
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime
+        .add_function(
+            "key_value",
+            DataType::Struct(
                 vec![
-                    Field::new("sum", DataType::Int32, false),
-                    Field::new("weight", DataType::Int32, false),
+                    Field::new("key", DataType::Utf8, true),
+                    Field::new("value", DataType::Utf8, true),
                 ]
                 .into(),
             ),
-            DataType::Float32,
             CallMode::ReturnNullOnNullInput,
             r#"
-class State:
-    def __init__(self):
-        self.sum = 0
-        self.weight = 0
+class KeyValue:
+    def __init__(self, key, value):
+        self.key = key
+        self.value = value
 
-def create_state():
-    return State()
+def key_value(s: str):
+    key, value = s.split('=')
+    return KeyValue(key, value)
+"#,
+        )
+        .unwrap();
 
-def accumulate(state, value, weight):
-    state.sum += value * weight
-    state.weight += weight
-    return state
+    let schema = Schema::new(vec![Field::new("x", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec!["a=b"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-def retract(state, value, weight):
-    state.sum -= value * weight
-    state.weight -= weight
-    return state
+    let output = runtime.call("key_value", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------------------+
+        | key_value          |
+        +--------------------+
+        | {key: a, value: b} |
+        +--------------------+"#]],
+    );
 
-def merge(state1, state2):
-    state1.sum += state2.sum
-    state1.weight += state2.weight
-    return state1
+    runtime
+        .add_function(
+            "key_value2",
+            DataType::Struct(
+                vec![
+                    Field::new("key", DataType::Utf8, true),
+                    Field::new("value", DataType::Utf8, true),
+                ]
+                .into(),
+            ),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def key_value2(s: str):
+    key, value = s.split('=')
+    return {"key": key, "value": value}
+"#,
+        )
+        .unwrap();
 
-def finish(state):
-    if state.weight == 0:
+    let output = runtime.call("key_value2", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------------------+
+        | key_value2         |
+        +--------------------+
+        | {key: a, value: b} |
+        +--------------------+"#]],
+    );
+}
+
+#[test]
+fn test_expand_struct() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime.set_expand_struct(true);
+    runtime
+        .add_function(
+            "split",
+            DataType::Struct(
+                vec![
+                    Field::new("key", DataType::Utf8, true),
+                    Field::new("value", DataType::Int32, true),
+                ]
+                .into(),
+            ),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def split(s: str):
+    if s == "":
         return None
-    else:
-        return state.sum / state.weight
+    key, value = s.split('=')
+    return (key, int(value))
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![
-        Field::new("value", DataType::Int32, true),
-        Field::new("weight", DataType::Int32, true),
-    ]);
-    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
-    let arg1 = Int32Array::from(vec![Some(2), None, Some(4), Some(6)]);
-    let input =
-        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let schema = Schema::new(vec![Field::new("x", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec![Some("a=1"), None, Some(""), Some("b=x")]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-    let state = runtime.create_state("weighted_avg").unwrap();
-    check_array(
-        std::slice::from_ref(&state),
+    let output = runtime.call("split", &input).unwrap();
+    check(
+        &[output],
         expect![[r#"
-            +---------------------+
-            | array               |
-            +---------------------+
-            | {sum: 0, weight: 0} |
-            +---------------------+"#]],
+            +-----+-------+---------------------------------------------------------+
+            | key | value | error                                                   |
+            +-----+-------+---------------------------------------------------------+
+            | a   | 1     |                                                         |
+            |     |       |                                                         |
+            |     |       |                                                         |
+            |     |       | ValueError: invalid literal for int() with base 10: 'x' |
+            +-----+-------+---------------------------------------------------------+"#]],
     );
 
-    let state = runtime.accumulate("weighted_avg", &state, &input).unwrap();
-    check_array(
-        std::slice::from_ref(&state),
+    // keep the struct array
+    runtime.set_expand_struct(false);
+    let output = runtime.call("split", &input.slice(0, 1)).unwrap();
+    check(
+        &[output],
         expect![[r#"
-            +-----------------------+
-            | array                 |
-            +-----------------------+
-            | {sum: 44, weight: 12} |
-            +-----------------------+"#]],
+            +--------------------+
+            | split              |
+            +--------------------+
+            | {key: a, value: 1} |
+            +--------------------+"#]],
     );
+}
 
-    let states = arrow_select::concat::concat(&[&state, &state]).unwrap();
-    let state = runtime.merge("weighted_avg", &states).unwrap();
-    check_array(
-        std::slice::from_ref(&state),
-        expect![[r#"
-            +-----------------------+
-            | array                 |
-            +-----------------------+
-            | {sum: 88, weight: 24} |
-            +-----------------------+"#]],
+#[test]
+fn test_runtime() {
+    let runtime = Runtime::new().unwrap();
+    drop(runtime);
+    let runtime = Runtime::new().unwrap();
+    drop(runtime);
+}
+
+#[test]
+fn test_struct_argument() {
+    let mut runtime = Runtime::new().unwrap();
+
+    let address = DataType::Struct(
+        vec![
+            Field::new("city", DataType::Utf8, true),
+            Field::new("zip", DataType::Int32, true),
+        ]
+        .into(),
+    );
+    let person = DataType::Struct(
+        vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("address", address.clone(), true),
+        ]
+        .into(),
+    );
+    runtime
+        .add_function(
+            "describe",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def describe(person):
+    assert isinstance(person, dict)
+    address = person["address"]
+    city = "?" if address is None else address.get("city")
+    return f"{list(person)} {person.name} {city}"
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "to_json",
+            json_field("to_json"),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def to_json(person):
+    return person
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "identity",
+            person.clone(),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def identity(person):
+    return person
+"#,
+        )
+        .unwrap();
+
+    let address = StructArray::new(
+        [
+            Arc::new(Field::new("city", DataType::Utf8, true)),
+            Arc::new(Field::new("zip", DataType::Int32, true)),
+        ]
+        .into(),
+        vec![
+            Arc::new(StringArray::from(vec![Some("Paris"), None, None])),
+            Arc::new(Int32Array::from(vec![Some(75001), None, None])),
+        ],
+        Some(vec![true, false, true].into()),
     );
+    let arg0 = StructArray::new(
+        [
+            Arc::new(Field::new("name", DataType::Utf8, true)),
+            Arc::new(Field::new("address", address.data_type().clone(), true)),
+        ]
+        .into(),
+        vec![
+            Arc::new(StringArray::from(vec!["alice", "bob", "carol"])),
+            Arc::new(address),
+        ],
+        None,
+    );
+    let schema = Schema::new(vec![Field::new("person", person, true)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-    let output = runtime.finish("weighted_avg", &state).unwrap();
-    check_array(
+    // structs are dicts keyed by field name, whose fields can also be read as attributes
+    let output = runtime.call("describe", &input).unwrap();
+    check(
         &[output],
         expect![[r#"
-            +-----------+
-            | array     |
-            +-----------+
+            +---------------------------------+
+            | describe                        |
+            +---------------------------------+
+            | ['name', 'address'] alice Paris |
+            | ['name', 'address'] bob ?       |
+            | ['name', 'address'] carol None  |
+            +---------------------------------+"#]],
+    );
+    let output = runtime.call("to_json", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +---------------------------------------------------------------+
+            | to_json                                                       |
+            +---------------------------------------------------------------+
+            | {"name": "alice", "address": {"city": "Paris", "zip": 75001}} |
+            | {"name": "bob", "address": null}                              |
+            | {"name": "carol", "address": {"city": null, "zip": null}}     |
+            +---------------------------------------------------------------+"#]],
+    );
+    let output = runtime.call("identity", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +---------------------------------------------------+
+            | identity                                          |
+            +---------------------------------------------------+
+            | {name: alice, address: {city: Paris, zip: 75001}} |
+            | {name: bob, address: }                            |
+            | {name: carol, address: {city: , zip: }}           |
+            +---------------------------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_map() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime.set_row_conversion_errors(true);
+
+    let mut builder = builder::MapBuilder::new(
+        None,
+        builder::StringBuilder::new(),
+        builder::Int32Builder::new(),
+    );
+    builder.keys().append_value("b");
+    builder.values().append_value(2);
+    builder.keys().append_value("a");
+    builder.values().append_null();
+    builder.append(true).unwrap();
+    builder.append(false).unwrap();
+    builder.append(true).unwrap();
+    builder.keys().append_value("c");
+    builder.values().append_value(3);
+    builder.append(true).unwrap();
+    let map = builder.finish();
+    let map_type = map.data_type().clone();
+
+    runtime
+        .add_function(
+            "upper_keys",
+            map_type.clone(),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def upper_keys(map):
+    assert isinstance(map, dict)
+    if "c" in map:
+        return {None: map["c"]}
+    return {key.upper(): value for key, value in map.items()}
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("map", map_type, true)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(map)]).unwrap();
+
+    // keys keep their order, and null keys are rejected
+    let output = runtime.call("upper_keys", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------------+---------------------------------------+
+        | upper_keys  | error                                 |
+        +-------------+---------------------------------------+
+        | {B: 2, A: } |                                       |
+        |             |                                       |
+        | {}          |                                       |
+        |             | ValueError: map keys must not be None |
+        +-------------+---------------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_struct_to_json() {
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime
+        .add_function(
+            "to_json",
+            json_field("to_json"),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def to_json(object):
+    return object.__dict__
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new(
+        "struct",
+        DataType::Struct(
+            vec![
+                Field::new("key", DataType::Utf8, true),
+                Field::new("value", DataType::Utf8, true),
+            ]
+            .into(),
+        ),
+        true,
+    )]);
+    let arg0 = StructArray::from(vec![
+        (
+            Arc::new(Field::new("key", DataType::Utf8, true)),
+            Arc::new(StringArray::from(vec![Some("a"), None])) as ArrayRef,
+        ),
+        (
+            Arc::new(Field::new("value", DataType::Utf8, true)),
+            Arc::new(StringArray::from(vec![Some("b"), None])),
+        ),
+    ]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("to_json", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------------------------------+
+        | to_json                      |
+        +------------------------------+
+        | {"key": "a", "value": "b"}   |
+        | {"key": null, "value": null} |
+        +------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_range() {
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime
+        .add_function(
+            "range1",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def range1(n: int):
+    for i in range(n):
+        yield i
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let mut outputs = runtime.call_table_function("range1", &input, 2).unwrap();
+
+    assert_eq!(outputs.schema().field(0).name(), "row");
+    assert_eq!(outputs.schema().field(1).name(), "range1");
+    assert_eq!(outputs.schema().field(1).data_type(), &DataType::Int32);
+
+    let o1 = outputs.next().unwrap().unwrap();
+    let o2 = outputs.next().unwrap().unwrap();
+    assert_eq!(o1.num_rows(), 2);
+    assert_eq!(o2.num_rows(), 2);
+    assert!(outputs.next().is_none());
+
+    check(
+        &[o1, o2],
+        expect![[r#"
+        +-----+--------+
+        | row | range1 |
+        +-----+--------+
+        | 0   | 0      |
+        | 2   | 0      |
+        | 2   | 1      |
+        | 2   | 2      |
+        +-----+--------+"#]],
+    );
+}
+
+#[test]
+fn test_sum() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "sum",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return 0
+
+def accumulate(state, value):
+    return state + value
+
+def retract(state, value):
+    return state - value
+
+def merge(state1, state2):
+    return state1 + state2
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("value", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
+    let ops = BooleanArray::from(vec![false, false, true, false]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let state = runtime.create_state("sum").unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+            +-------+
+            | array |
+            +-------+
+            | 0     |
+            +-------+"#]],
+    );
+
+    let state = runtime.accumulate("sum", &state, &input).unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+            +-------+
+            | array |
+            +-------+
+            | 9     |
+            +-------+"#]],
+    );
+
+    let state = runtime
+        .accumulate_or_retract("sum", &state, &ops, &input)
+        .unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+            +-------+
+            | array |
+            +-------+
+            | 12    |
+            +-------+"#]],
+    );
+
+    let output = runtime.finish("sum", &state).unwrap();
+    check_array(
+        &[output],
+        expect![[r#"
+            +-------+
+            | array |
+            +-------+
+            | 12    |
+            +-------+"#]],
+    );
+}
+
+#[test]
+fn test_weighted_avg() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "weighted_avg",
+            DataType::Struct(
+                vec![
+                    Field::new("sum", DataType::Int32, false),
+                    Field::new("weight", DataType::Int32, false),
+                ]
+                .into(),
+            ),
+            DataType::Float32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+class State:
+    def __init__(self):
+        self.sum = 0
+        self.weight = 0
+
+def create_state():
+    return State()
+
+def accumulate(state, value, weight):
+    state.sum += value * weight
+    state.weight += weight
+    return state
+
+def retract(state, value, weight):
+    state.sum -= value * weight
+    state.weight -= weight
+    return state
+
+def merge(state1, state2):
+    state1.sum += state2.sum
+    state1.weight += state2.weight
+    return state1
+
+def finish(state):
+    if state.weight == 0:
+        return None
+    else:
+        return state.sum / state.weight
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("value", DataType::Int32, true),
+        Field::new("weight", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
+    let arg1 = Int32Array::from(vec![Some(2), None, Some(4), Some(6)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let state = runtime.create_state("weighted_avg").unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+            +---------------------+
+            | array               |
+            +---------------------+
+            | {sum: 0, weight: 0} |
+            +---------------------+"#]],
+    );
+
+    let state = runtime.accumulate("weighted_avg", &state, &input).unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+            +-----------------------+
+            | array                 |
+            +-----------------------+
+            | {sum: 44, weight: 12} |
+            +-----------------------+"#]],
+    );
+
+    let states = arrow_select::concat::concat(&[&state, &state]).unwrap();
+    let state = runtime.merge("weighted_avg", &states).unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+            +-----------------------+
+            | array                 |
+            +-----------------------+
+            | {sum: 88, weight: 24} |
+            +-----------------------+"#]],
+    );
+
+    let output = runtime.finish("weighted_avg", &state).unwrap();
+    check_array(
+        &[output],
+        expect![[r#"
+            +-----------+
+            | array     |
+            +-----------+
             | 3.6666667 |
             +-----------+"#]],
     );
 }
 
 #[test]
-fn test_weighted_avg_pickle_state() {
+fn test_weighted_avg_pickle_state() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "weighted_avg",
+            pickle_field("state"),
+            DataType::Float32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+class State:
+    def __init__(self):
+        self.sum = 0
+        self.weight = 0
+
+def create_state():
+    return State()
+
+def accumulate(state, value, weight):
+    state.sum += value * weight
+    state.weight += weight
+    return state
+
+def retract(state, value, weight):
+    state.sum -= value * weight
+    state.weight -= weight
+    return state
+
+def merge(state1, state2):
+    state1.sum += state2.sum
+    state1.weight += state2.weight
+    return state1
+
+def finish(state):
+    if state.weight == 0:
+        return None
+    else:
+        return state.sum / state.weight
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("value", DataType::Int32, true),
+        Field::new("weight", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
+    let arg1 = Int32Array::from(vec![Some(2), None, Some(4), Some(6)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let state = runtime.create_state("weighted_avg").unwrap();
+    let state = runtime.accumulate("weighted_avg", &state, &input).unwrap();
+    // we don't check the state because it is pickled
+    let output = runtime.finish("weighted_avg", &state).unwrap();
+    check_array(
+        &[output],
+        expect![[r#"
+            +-----------+
+            | array     |
+            +-----------+
+            | 3.6666667 |
+            +-----------+"#]],
+    );
+}
+
+#[test]
+fn test_grouped_aggregate() {
+    use arrow_udf_core::UdfRuntime;
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "avg",
+            DataType::Struct(
+                vec![
+                    Field::new("sum", DataType::Int64, false),
+                    Field::new("count", DataType::Int64, false),
+                ]
+                .into(),
+            ),
+            DataType::Float64,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+class State:
+    def __init__(self, sum, count):
+        self.sum = sum
+        self.count = count
+
+def create_state():
+    return State(0, 0)
+
+def accumulate(state, value):
+    return State(state.sum + value, state.count + 1)
+
+def merge(state1, state2):
+    return State(state1.sum + state2.sum, state1.count + state2.count)
+
+def finish(state):
+    return state.sum / state.count if state.count else None
+"#,
+        )
+        .unwrap();
+
+    // two partitions of the same groups are accumulated separately
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "value",
+        DataType::Int32,
+        true,
+    )]));
+    let create_states = |n: usize| {
+        let states: Vec<_> = (0..n)
+            .map(|_| runtime.create_state("avg").unwrap())
+            .collect();
+        arrow_select::concat::concat(&states.iter().map(|s| s.as_ref()).collect::<Vec<_>>())
+            .unwrap()
+    };
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5), Some(7)]);
+    let input = RecordBatch::try_new(schema.clone(), vec![Arc::new(arg0)]).unwrap();
+    let groups = UInt32Array::from(vec![Some(0), Some(1), Some(1), None, Some(0)]);
+    let states0 = runtime
+        .accumulate_batch("avg", &create_states(3), &groups, &input)
+        .unwrap();
+
+    let arg0 = Int32Array::from(vec![2, 4]);
+    let input = RecordBatch::try_new(schema, vec![Arc::new(arg0)]).unwrap();
+    let groups = UInt32Array::from(vec![0, 1]);
+    let states1 = runtime
+        .accumulate_batch("avg", &create_states(3), &groups, &input)
+        .unwrap();
+
+    // the partial states are shipped and merged by group
+    let bytes = runtime.serialize_state("avg", &states1).unwrap();
+    let states1 = runtime.deserialize_state("avg", &bytes).unwrap();
+    let merged: Vec<_> = (0..3)
+        .map(|i| {
+            let states =
+                arrow_select::concat::concat(&[&states0.slice(i, 1), &states1.slice(i, 1)])
+                    .unwrap();
+            runtime.merge("avg", &states).unwrap()
+        })
+        .collect();
+    let merged =
+        arrow_select::concat::concat(&merged.iter().map(|s| s.as_ref()).collect::<Vec<_>>())
+            .unwrap();
+    let output = runtime.finish("avg", &merged).unwrap();
+    check_array(
+        &[output],
+        expect![[r#"
+            +--------------------+
+            | array              |
+            +--------------------+
+            | 3.3333333333333335 |
+            | 3.5                |
+            |                    |
+            +--------------------+"#]],
+    );
+
+    let err = runtime
+        .accumulate_batch("avg", &create_states(1), &groups, &input)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "group 1 out of bounds of 1 states");
+
+    runtime.del_aggregate("avg").unwrap();
+    assert!(runtime.create_state("avg").is_err());
+}
+
+#[test]
+fn test_batched() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function_batched(
+            "concat",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def concat(a: list, b: list) -> list:
+    if len(a) == 0:
+        raise ValueError("empty batch")
+    return [x + y for x, y in zip(a, b)]
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_batched(
+            "first",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            r#"
+def first(a: list) -> list:
+    return a[:1]
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Utf8, true),
+        Field::new("b", DataType::Utf8, true),
+    ]);
+    let arg0 = StringArray::from(vec![Some("a"), None, Some("c"), Some("d")]);
+    let arg1 = StringArray::from(vec![Some("1"), Some("2"), Some("3"), Some("4")]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    // null rows are not passed to the function
+    let output = runtime.call("concat", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | concat |
+        +--------+
+        | a1     |
+        |        |
+        | c3     |
+        | d4     |
+        +--------+"#]],
+    );
+
+    // neither are rows that are not selected
+    let selection = BooleanArray::from(vec![false, true, true, false]);
+    let output = runtime.call_selected("concat", &input, &selection).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | concat |
+        +--------+
+        |        |
+        |        |
+        | c3     |
+        |        |
+        +--------+"#]],
+    );
+
+    // an exception fails the whole call
+    let selection = BooleanArray::from(vec![false, true, false, false]);
+    let err = runtime
+        .call_selected("concat", &input, &selection)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "ValueError: empty batch");
+
+    let schema = Schema::new(vec![Field::new("a", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let err = runtime.call("first", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expect 2 values from batched function, got 1"
+    );
+    let err = runtime
+        .call_table_function("first", &input, 10)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "batched function can not be called as a table function: first"
+    );
+}
+
+#[test]
+fn test_buffer() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function_buffer(
+            "add",
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+from array import array
+
+def add(a, b):
+    assert isinstance(a, memoryview) and a.readonly
+    return array('q', map(sum, zip(a, b)))
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_buffer(
+            "identity",
+            DataType::Float64,
+            CallMode::CalledOnNullInput,
+            "def identity(a):\n    return a",
+        )
+        .unwrap();
+    runtime
+        .add_function_buffer(
+            "wrong",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "from array import array\ndef wrong(a):\n    return array('d', [1.0])",
+        )
+        .unwrap();
+    runtime
+        .add_function_buffer(
+            "first",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "def first(a):\n    return a[:1]",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int64, true),
+        Field::new("b", DataType::Int64, true),
+    ]);
+    // the values of sliced arrays are passed without the parts outside the slice
+    let arg0 = Int64Array::from(vec![Some(0), Some(1), None, Some(3), Some(4)]).slice(1, 4);
+    let arg1 = Int64Array::from(vec![10, 20, 30, 40]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    // rows with null arguments or not selected are null
+    let selection = BooleanArray::from(vec![true, true, true, false]);
+    let output = runtime.call_selected("add", &input, &selection).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----+
+            | add |
+            +-----+
+            | 11  |
+            |     |
+            | 33  |
+            |     |
+            +-----+"#]],
+    );
+
+    // scalar arguments are repeated
+    let arguments = arrow_udf_core::Arguments::new(3)
+        .with_scalar(
+            Field::new("a", DataType::Float64, true),
+            Arc::new(Float64Array::from(vec![1.5])),
+        )
+        .unwrap();
+    let output = runtime.call_broadcast("identity", &arguments).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +----------+
+            | identity |
+            +----------+
+            | 1.5      |
+            | 1.5      |
+            | 1.5      |
+            +----------+"#]],
+    );
+
+    let schema = Schema::new(vec![Field::new("a", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let err = runtime.call("wrong", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "BufferError: buffer contents are not compatible with i32"
+    );
+    let err = runtime.call("first", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expect 2 values from buffer function, got 1"
+    );
+
+    let schema = Schema::new(vec![Field::new("a", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec!["a"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let err = runtime.call("identity", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: unsupported argument type of buffer function identity: Utf8"
+    );
+
+    let err = runtime
+        .add_function_buffer(
+            "upper",
+            DataType::Utf8,
+            CallMode::CalledOnNullInput,
+            "def upper(a):\n    return a",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unsupported return type of buffer function: Utf8"
+    );
+}
+
+#[test]
+#[cfg_attr(Py_3_12, ignore = "NumPy can not be imported in sub-interpreters")]
+fn test_numpy() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function_numpy(
+            "add",
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import numpy as np
+
+def add(a, b):
+    assert isinstance(a, np.ndarray) and not a.flags.writeable
+    return a + b
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_numpy(
+            "mean",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "def mean(a, b):\n    return (a + b) / 2",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int64, true),
+        Field::new("b", DataType::Int64, true),
+    ]);
+    let arg0 = Int64Array::from(vec![Some(1), None, Some(3), Some(4)]);
+    let arg1 = Int64Array::from(vec![10, 20, 30, 40]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    // rows with null arguments or not selected are null
+    let selection = BooleanArray::from(vec![true, true, true, false]);
+    let output = runtime.call_selected("add", &input, &selection).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----+
+            | add |
+            +-----+
+            | 11  |
+            |     |
+            | 33  |
+            |     |
+            +-----+"#]],
+    );
+
+    // the returned array is cast to the return type
+    let input = input.slice(2, 2);
+    let output = runtime.call("mean", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +------+
+            | mean |
+            +------+
+            | 16   |
+            | 22   |
+            +------+"#]],
+    );
+}
+
+#[test]
+#[cfg_attr(
+    not(Py_3_12),
+    ignore = "NumPy functions are supported before Python 3.12"
+)]
+fn test_numpy_unsupported() {
+    let mut runtime = Runtime::new().unwrap();
+    let err = runtime
+        .add_function_numpy(
+            "add",
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            "def add(a, b):\n    return a + b",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "NumPy functions require Python 3.10 or 3.11, since NumPy can not be imported in sub-interpreters"
+    );
+}
+
+#[test]
+#[cfg_attr(Py_3_12, ignore = "pandas can not be imported in sub-interpreters")]
+fn test_pandas() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function_pandas(
+            "add",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import pandas as pd
+
+def add(a, b):
+    assert isinstance(a, pd.Series)
+    return a + b
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_pandas(
+            "half",
+            DataType::Float64,
+            CallMode::CalledOnNullInput,
+            "def half(a):\n    return a / 2",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(0), Some(4)]);
+    let arg1 = Int32Array::from(vec![10, 20, 30, 40]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let output = runtime.call("add", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----+
+            | add |
+            +-----+
+            | 11  |
+            |     |
+            | 30  |
+            | 44  |
+            +-----+"#]],
+    );
+
+    // missing values in the result are null
+    let output = runtime.call("half", &input.project(&[0]).unwrap()).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +------+
+            | half |
+            +------+
+            | 0.5  |
+            |      |
+            | 0.0  |
+            | 2.0  |
+            +------+"#]],
+    );
+}
+
+#[test]
+#[cfg_attr(
+    not(Py_3_12),
+    ignore = "pandas functions are supported before Python 3.12"
+)]
+fn test_pandas_unsupported() {
+    let mut runtime = Runtime::new().unwrap();
+    let err = runtime
+        .add_function_pandas(
+            "add",
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            "def add(a, b):\n    return a + b",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "pandas functions require Python 3.10 or 3.11, since pandas can not be imported in sub-interpreters"
+    );
+}
+
+#[test]
+fn test_arrow() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function_arrow(
+            "first",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def first(a, b):
+    assert len(a) == len(b)
+    # the array can be exported more than once
+    a.__arrow_c_array__()
+    return a
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_arrow(
+            "identity",
+            DataType::Float64,
+            CallMode::CalledOnNullInput,
+            "def identity(a):\n    return a",
+        )
+        .unwrap();
+    runtime
+        .add_function_arrow(
+            "list",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "def list(a):\n    return [1, 2]",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Utf8, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let arg0 = StringArray::from(vec![Some("a"), Some("b"), None, Some("d")]);
+    let arg1 = Int32Array::from(vec![Some(1), None, Some(3), Some(4)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    // rows with null arguments or not selected are null
+    let selection = BooleanArray::from(vec![true, true, true, false]);
+    let output = runtime.call_selected("first", &input, &selection).unwrap();
+    // the buffers of values are shared with the input
+    let values = |batch: &RecordBatch| batch.column(0).to_data().buffers()[1].as_ptr();
+    assert_eq!(values(&output), values(&input));
+    check(
+        &[output],
+        expect![[r#"
+            +-------+
+            | first |
+            +-------+
+            | a     |
+            |       |
+            |       |
+            |       |
+            +-------+"#]],
+    );
+
+    // scalar arguments are repeated
+    let arguments = arrow_udf_core::Arguments::new(3)
+        .with_scalar(
+            Field::new("a", DataType::Float64, true),
+            Arc::new(Float64Array::from(vec![1.5])),
+        )
+        .unwrap();
+    let output = runtime.call_broadcast("identity", &arguments).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +----------+
+            | identity |
+            +----------+
+            | 1.5      |
+            | 1.5      |
+            | 1.5      |
+            +----------+"#]],
+    );
+
+    let schema = Schema::new(vec![Field::new("a", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let err = runtime.call("identity", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: expect Float64 from arrow function identity, got Int32"
+    );
+    let err = runtime.call("list", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "TypeError: expect an object implementing __arrow_c_array__, got list"
+    );
+}
+
+#[test]
+fn test_window() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_window_function(
+            "rank",
+            DataType::Int32,
+            r#"
+def rank(partition, frames):
+    values = partition["score"]
+    ranks = []
+    for value in values:
+        if value is None:
+            ranks.append(None)
+        else:
+            ranks.append(1 + sum(other is not None and other > value for other in values))
+    return ranks
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_window_function(
+            "moving_avg",
+            DataType::Float64,
+            r#"
+def moving_avg(partition, frames):
+    values = partition["score"]
+    results = []
+    for start, end in frames:
+        frame = [v for v in values[start:end] if v is not None]
+        results.append(sum(frame) / len(frame) if frame else None)
+    return results
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_window_function(
+            "wrong",
+            DataType::Int32,
+            "def wrong(partition, frames):\n    return [1]",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("score", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(30), Some(10), None, Some(30), Some(20)]);
+    let partition = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // the frame of every row is the whole partition by default
+    let ranks = runtime.call_window("rank", &partition, None).unwrap();
+    let frames = [0..1, 0..2, 1..3, 2..4, 3..5];
+    let averages = runtime
+        .call_window("moving_avg", &partition, Some(&frames))
+        .unwrap();
+    let output = RecordBatch::try_from_iter([
+        ("score", partition.column(0).clone()),
+        ("rank", ranks.column(0).clone()),
+        ("moving_avg", averages.column(0).clone()),
+    ])
+    .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------+------+------------+
+        | score | rank | moving_avg |
+        +-------+------+------------+
+        | 30    | 1    | 30.0       |
+        | 10    | 4    | 20.0       |
+        |       |      | 10.0       |
+        | 30    | 1    | 30.0       |
+        | 20    | 3    | 25.0       |
+        +-------+------+------------+"#]],
+    );
+
+    let frames = [0..1, 0..2];
+    let err = runtime
+        .call_window("moving_avg", &partition, Some(&frames))
+        .unwrap_err();
+    assert_eq!(err.to_string(), "expect 5 frames, got 2");
+    let frames = [0..1, 0..2, 1..3, 2..4, 3..6];
+    let err = runtime
+        .call_window("moving_avg", &partition, Some(&frames))
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "frame 3..6 of row 4 is out of the partition of 5 rows"
+    );
+    let err = runtime.call_window("wrong", &partition, None).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expect 5 values from window function, got 1"
+    );
+
+    // window functions are not scalar functions
+    let err = runtime.call("rank", &partition).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "window function can not be called as a scalar function: rank"
+    );
+}
+
+#[test]
+fn test_keyword_arguments() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime.set_keyword_arguments(true);
+    runtime
+        .add_function(
+            "describe",
+            DataType::Utf8,
+            CallMode::CalledOnNullInput,
+            r#"
+def describe(name, age=None, **others):
+    return f"{name} {age} {sorted(others)}"
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_batched(
+            "sub",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def sub(a, b, name):
+    return [x - y for x, y in zip(a, b)]
+"#,
+        )
+        .unwrap();
+
+    // columns are passed by name, regardless of their order
+    let schema = Schema::new(vec![
+        Field::new("b", DataType::Int32, true),
+        Field::new("name", DataType::Utf8, true),
+        Field::new("a", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(1), Some(2)]);
+    let arg1 = StringArray::from(vec![Some("alice"), None]);
+    let arg2 = Int32Array::from(vec![Some(10), Some(20)]);
+    let input = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(arg0), Arc::new(arg1), Arc::new(arg2)],
+    )
+    .unwrap();
+
+    let output = runtime.call("describe", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----------------------+
+        | describe              |
+        +-----------------------+
+        | alice None ['a', 'b'] |
+        | None None ['a', 'b']  |
+        +-----------------------+"#]],
+    );
+    let output = runtime.call("sub", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | sub |
+        +-----+
+        | 9   |
+        |     |
+        +-----+"#]],
+    );
+
+    // parameters without a matching column are errors
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(Int32Array::from(vec![1]))]).unwrap();
+    let output = runtime.call("describe", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +----------+----------------------------------------------------------------------+
+        | describe | error                                                                |
+        +----------+----------------------------------------------------------------------+
+        |          | TypeError: describe() missing 1 required positional argument: 'name' |
+        +----------+----------------------------------------------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_variadic() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "concat_ws",
+            DataType::Utf8,
+            CallMode::CalledOnNullInput,
+            r#"
+def concat_ws(sep, *args):
+    return sep.join(arg for arg in args if arg is not None)
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "concat",
+            DataType::Utf8,
+            CallMode::CalledOnNullInput,
+            r#"
+def concat(a, b, c=""):
+    return a + b + c
+"#,
+        )
+        .unwrap();
+
+    // a batch with a separator and `n` strings
+    let input = |n: usize| {
+        let fields = (0..=n).map(|i| Field::new(format!("s{i}"), DataType::Utf8, true));
+        let columns = (0..=n).map(|i| match i {
+            0 => Arc::new(StringArray::from(vec!["-"])) as ArrayRef,
+            i => Arc::new(StringArray::from(vec![format!("{i}")])),
+        });
+        let schema = Schema::new(fields.collect::<Vec<_>>());
+        RecordBatch::try_new(Arc::new(schema), columns.collect()).unwrap()
+    };
+
+    // any number of columns after the separator
+    let outputs = [0, 1, 3].map(|n| {
+        runtime
+            .call("concat_ws", &input(n))
+            .unwrap()
+            .column(0)
+            .clone()
+    });
+    check_array(
+        &outputs,
+        expect![[r#"
+        +-------+
+        | array |
+        +-------+
+        |       |
+        | 1     |
+        | 1-2-3 |
+        +-------+"#]],
+    );
+
+    // the number of columns must be accepted by the signature
+    let output = runtime.call("concat", &input(2)).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | concat |
+        +--------+
+        | -12    |
+        +--------+"#]],
+    );
+    let err = runtime.call("concat", &input(0)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: function concat expects 2 to 3 arguments, but got 1"
+    );
+    let empty = RecordBatch::new_empty(Arc::new(Schema::empty()));
+    let err = runtime.call("concat_ws", &empty).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: function concat_ws expects at least 1 argument, but got 0"
+    );
+}
+
+#[test]
+fn test_output_type_mismatch() {
+    let mut runtime = Runtime::new().unwrap();
+    let err = runtime
+        .add_aggregate(
+            "sum",
+            DataType::Int32,
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return 0
+
+def accumulate(state, value):
+    return state + value
+"#,
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`output_type` must be the same as `state_type` when `finish` is not defined"
+    );
+}
+
+/// Errors after successful rows were placed one row ahead of their rows.
+#[test]
+fn test_error_rows() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "div",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def div(a, b):\n    return a // b",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![1, 2, 3, 4, 5, 6, 7]);
+    let arg1 = Int32Array::from(vec![0, 1, 0, 1, 1, 0, 1]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let output = runtime.call("div", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+-------------------------------------------------------+
+        | div | error                                                 |
+        +-----+-------------------------------------------------------+
+        |     | ZeroDivisionError: integer division or modulo by zero |
+        | 2   |                                                       |
+        |     | ZeroDivisionError: integer division or modulo by zero |
+        | 4   |                                                       |
+        | 5   |                                                       |
+        |     | ZeroDivisionError: integer division or modulo by zero |
+        | 7   |                                                       |
+        +-----+-------------------------------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_error() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "div",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def div(a: int, b: int) -> int:
+    return a // b
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let arg1 = Int32Array::from(vec![0, 1]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let output = runtime.call("div", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+-------------------------------------------------------+
+        | div | error                                                 |
+        +-----+-------------------------------------------------------+
+        |     | ZeroDivisionError: integer division or modulo by zero |
+        | 2   |                                                       |
+        +-----+-------------------------------------------------------+"#]],
+    );
+
+    runtime
+        .add_function(
+            "range1",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def range1(n: int):
+    for i in range(n):
+        if i == 1:
+            raise ValueError("i is 1")
+        yield i
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![0, 2, 1]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let mut outputs = runtime.call_table_function("range1", &input, 10).unwrap();
+    let output = outputs.next().unwrap().unwrap();
+
+    check(
+        &[output],
+        expect![[r#"
+        +-----+--------+--------------------+
+        | row | range1 | error              |
+        +-----+--------+--------------------+
+        | 1   | 0      |                    |
+        | 1   |        | ValueError: i is 1 |
+        | 2   | 0      |                    |
+        +-----+--------+--------------------+"#]],
+    );
+}
+
+#[test]
+fn test_row_conversion_errors() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime.set_row_conversion_errors(true);
+    runtime
+        .add_function(
+            "parse",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def parse(s: str):
+    return int(s) if s.isdigit() else s
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function_batched(
+            "parse_all",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def parse_all(s: list):
+    return [int(x) if x.isdigit() else x for x in s]
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec![Some("1"), Some("x"), None, Some("3")]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // values that can't be converted only fail their rows
+    let output = runtime.call("parse", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-------+-------------------------------------------------------------+
+            | parse | error                                                       |
+            +-------+-------------------------------------------------------------+
+            | 1     |                                                             |
+            |       | TypeError: 'str' object cannot be interpreted as an integer |
+            |       |                                                             |
+            | 3     |                                                             |
+            +-------+-------------------------------------------------------------+"#]],
+    );
+    let output = runtime.call("parse_all", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----------+-------------------------------------------------------------+
+            | parse_all | error                                                       |
+            +-----------+-------------------------------------------------------------+
+            | 1         |                                                             |
+            |           | TypeError: 'str' object cannot be interpreted as an integer |
+            |           |                                                             |
+            | 3         |                                                             |
+            +-----------+-------------------------------------------------------------+"#]],
+    );
+}
+
+#[test]
+fn test_timeout() {
+    use std::time::{Duration, Instant};
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime.set_timeout(Some(Duration::from_millis(100)));
+    runtime
+        .add_function(
+            "spin",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def spin(x):
+    while x > 0:
+        pass
+    return x
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "forever",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def forever(x):
+    while True:
+        yield x
+        while x > 0:
+            pass
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_aggregate(
+            "spin_sum",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return 0
+
+def accumulate(state, x):
+    while x > 0:
+        pass
+    return state + x
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![0, 1, 0]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let start = Instant::now();
+    let err = runtime.call("spin", &input).unwrap_err();
+    assert_eq!(err.to_string(), "resource exceeded: timeout after 100ms");
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    let row = input.slice(1, 1);
+    let mut outputs = runtime.call_table_function("forever", &row, 10).unwrap();
+    let err = outputs.next().unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "resource exceeded: timeout after 100ms");
+    drop(outputs);
+
+    let state = runtime.create_state("spin_sum").unwrap();
+    let err = runtime.accumulate("spin_sum", &state, &input).unwrap_err();
+    assert_eq!(err.to_string(), "resource exceeded: timeout after 100ms");
+
+    // calls within the timeout succeed, and a cancelled token is still reported as cancelled
+    let input = input.slice(0, 1);
+    let output = runtime.call("spin", &input).unwrap();
+    assert_eq!(output.num_rows(), 1);
+    let token = CancelToken::new();
+    token.cancel();
+    let err = runtime
+        .call_cancellable("spin", &input, &token)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "cancelled");
+}
+
+#[test]
+fn test_cancel() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "spin",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def spin(x):
+    while True:
+        pass
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "neg",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def neg(x):
+    return -x
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // call from a thread other than the one that created the runtime
+    let token = CancelToken::new();
+    std::thread::scope(|s| {
+        let handle = s.spawn(|| runtime.call_cancellable("spin", &input, &token));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        token.cancel();
+        let err = handle.join().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "cancelled");
+    });
+
+    // the interrupt does not leak into later calls
+    let output = runtime
+        .call_cancellable("neg", &input, &CancelToken::new())
+        .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | neg |
+        +-----+
+        | -1  |
+        | -2  |
+        +-----+"#]],
+    );
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_restart() {
+    let mut runtime = Runtime::builder().sandboxed(true).build().unwrap();
+    runtime
+        .add_function(
+            "count",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+calls = 0
+
+def count(x):
+    global calls
+    calls += 1
+    return calls
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "has_print",
+            DataType::Boolean,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def has_print(x):
+    b = __builtins__
+    return 'print' in (b if isinstance(b, dict) else vars(b))
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "restore_print",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def restore_print(x):
+    b = __builtins__
+    (b if isinstance(b, dict) else vars(b))['print'] = lambda *args: None
+    return x
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_aggregate(
+            "sum",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def create_state():\n    return 0\ndef accumulate(state, x):\n    return state + x",
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let call = |name: &str| runtime.call(name, &input).unwrap().column(0).clone();
+
+    assert_eq!(&*call("count"), &Int32Array::from(vec![1, 2]));
+    // module-level variables are initialized again
+    runtime.restart().unwrap();
+    assert_eq!(runtime.restarts(), 1);
+    assert_eq!(&*call("count"), &Int32Array::from(vec![1, 2]));
+
+    // the interpreter is restarted before the next call once the sandbox is broken
+    assert_eq!(&*call("has_print"), &BooleanArray::from(vec![false, false]));
+    call("restore_print");
+    assert_eq!(runtime.restarts(), 1);
+    assert_eq!(&*call("has_print"), &BooleanArray::from(vec![false, false]));
+    assert_eq!(runtime.restarts(), 2);
+
+    // aggregate functions are loaded again
+    let state = runtime.create_state("sum").unwrap();
+    let state = runtime.accumulate("sum", &state, &input).unwrap();
+    assert_eq!(&*state, &Int32Array::from(vec![3]));
+}
+
+#[test]
+fn test_pool_partitions() {
+    use arrow_udf_core::{RuntimePool, UdfRuntime};
+
+    let members = (0..2)
+        .map(|_| Box::new(Runtime::new().unwrap()) as Box<dyn UdfRuntime>)
+        .collect();
+    let mut pool = RuntimePool::new(members).unwrap();
+    pool.add_function(
+        "div",
+        Field::new("div", DataType::Int32, true),
+        CallMode::ReturnNullOnNullInput,
+        "def div(a, b):\n    return a // b",
+    )
+    .unwrap();
+    pool.add_function(
+        "series",
+        Field::new("series", DataType::Int32, true),
+        CallMode::ReturnNullOnNullInput,
+        "def series(n):\n    for i in range(n):\n        yield i",
+    )
+    .unwrap();
+
+    // only the output of the second partition has an error column
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![1, 2, 3, 4]);
+    let arg1 = Int32Array::from(vec![1, 1, 1, 0]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let output = pool.call("div", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+-------------------------------------------------------+
+        | div | error                                                 |
+        +-----+-------------------------------------------------------+
+        | 1   |                                                       |
+        | 2   |                                                       |
+        | 3   |                                                       |
+        |     | ZeroDivisionError: integer division or modulo by zero |
+        +-----+-------------------------------------------------------+"#]],
+    );
+
+    // the outputs of table functions are in the order of input rows
+    let input = input.project(&[0]).unwrap();
+    let outputs = pool.call_table_function("series", &input, 2).unwrap();
+    let outputs = outputs.collect::<Result<Vec<_>, _>>().unwrap();
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 1   | 0      |
+        | 1   | 1      |
+        | 2   | 0      |
+        | 2   | 1      |
+        | 2   | 2      |
+        | 3   | 0      |
+        | 3   | 1      |
+        | 3   | 2      |
+        | 3   | 3      |
+        +-----+--------+"#]],
+    );
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_pool() {
+    use arrow_udf_core::UdfRuntime;
+
+    let mut pool = Runtime::builder().sandboxed(true).build_pool(4).unwrap();
+    assert_eq!(pool.members().len(), 4);
+    pool.add_function(
+        "fib",
+        Field::new("fib", DataType::Int32, true),
+        CallMode::ReturnNullOnNullInput,
+        r#"
+def fib(n: int) -> int:
+    if n <= 1:
+        return n
+    else:
+        return fib(n - 1) + fib(n - 2)
+"#,
+    )
+    .unwrap();
+
+    // the options of the builder apply to every runtime
+    let err = pool
+        .add_function(
+            "hostname",
+            Field::new("hostname", DataType::Utf8, true),
+            CallMode::CalledOnNullInput,
+            "import socket\ndef hostname():\n    return socket.gethostname()",
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("socket"), "{err}");
+
+    // the partitions evaluated by runtimes are reassembled in order
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from((0..10).collect::<Vec<_>>());
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let output = pool.call("fib", &input).unwrap();
+    assert_eq!(
+        output
+            .column(0)
+            .as_primitive::<arrow_array::types::Int32Type>(),
+        &Int32Array::from(vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34])
+    );
+
+    // an error in one partition is kept at its row
+    pool.add_function(
+        "div",
+        Field::new("div", DataType::Int32, true),
+        CallMode::ReturnNullOnNullInput,
+        "def div(a, b):\n    return a // b",
+    )
+    .unwrap();
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![1, 2, 3, 4]);
+    let arg1 = Int32Array::from(vec![1, 0, 1, 1]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let output = pool.call("div", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+-------------------------------------------------------+
+        | div | error                                                 |
+        +-----+-------------------------------------------------------+
+        | 1   |                                                       |
+        |     | ZeroDivisionError: integer division or modulo by zero |
+        | 3   |                                                       |
+        | 4   |                                                       |
+        +-----+-------------------------------------------------------+"#]],
+    );
+
+    assert!(Runtime::builder().build_pool(0).is_err());
+}
+
+/// Test there is no GIL contention across threads.
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "runtimes share the GIL before Python 3.12")]
+fn test_no_gil() {
+    use std::time::Duration;
+
+    fn timeit(f: impl FnOnce()) -> Duration {
+        let start = std::time::Instant::now();
+        f();
+        start.elapsed()
+    }
+
+    let t0 = timeit(test_fib);
+    let t1 = timeit(|| {
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(test_fib);
+            }
+        })
+    });
+    assert!(
+        t1 < t0 + Duration::from_millis(10),
+        "multi-threaded execution is slower than single-threaded. there is GIL contention."
+    )
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_import() {
+    let mut runtime = Runtime::builder().sandboxed(true).build().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import decimal
+import json
+import math
+import re
+import numbers
+import datetime
+
+def gcd(a: int, b: int) -> int:
+    while b:
+        a, b = b, a % b
+    return a
+"#,
+        )
+        .unwrap();
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_allow_module() {
+    let mut runtime = Runtime::builder()
+        .sandboxed(true)
+        .allow_module("textwrap")
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "shorten",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import textwrap
+
+def shorten(s: str) -> str:
+    return textwrap.shorten(s, width=10, placeholder="...")
+"#,
+        )
+        .unwrap();
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec!["hello world again"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let output = runtime.call("shorten", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +----------+
+            | shorten  |
+            +----------+
+            | hello... |
+            +----------+"#]],
+    );
+
+    // other modules are still forbidden
+    let err = runtime
+        .add_function(
+            "os",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "import os",
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "ImportError: import os is not allowed");
+
+    let err = Runtime::builder()
+        .sandboxed(true)
+        .allow_module("os'); import os; ('")
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"invalid module name: "os'); import os; ('""#
+    );
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_builder() {
+    let mut runtime = Runtime::builder()
+        .sandboxed(true)
+        .keep_symbol("__builtins__.eval")
+        .preload_module("collections")
+        .keyword_arguments(true)
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "count",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import collections
+
+def count(expr, s):
+    return eval(expr)(collections.Counter(s))
+"#,
+        )
+        .unwrap();
+    let schema = Schema::new(vec![
+        Field::new("s", DataType::Utf8, true),
+        Field::new("expr", DataType::Utf8, true),
+    ]);
+    let arg0 = StringArray::from(vec!["hello"]);
+    let arg1 = StringArray::from(vec!["lambda c: c['l']"]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let output = runtime.call("count", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-------+
+            | count |
+            +-------+
+            | 2     |
+            +-------+"#]],
+    );
+
+    // the modules imported by preloaded modules are still not allowed
+    let err = runtime
+        .add_function(
+            "f",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "import keyword",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ImportError: import keyword is not allowed"
+    );
+
+    let err = Runtime::builder()
+        .preload_module("no_such_module")
+        .build()
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ModuleNotFoundError: No module named 'no_such_module'"
+    );
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_append_path() {
+    let dir = std::env::temp_dir().join(format!("arrow-udf-python-path-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("vendored_greet.py"),
+        "def greet(s):\n    return 'hello ' + s\n",
+    )
+    .unwrap();
+
+    let mut runtime = Runtime::builder()
+        .sandboxed(true)
+        .append_path(&dir)
+        .allow_module("vendored_greet")
+        .build()
+        .unwrap();
+    runtime
+        .add_function(
+            "greet",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import vendored_greet
+
+def greet(s):
+    return vendored_greet.greet(s)
+"#,
+        )
+        .unwrap();
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec!["world"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let output = runtime.call("greet", &input).unwrap();
+    drop(runtime);
+    std::fs::remove_dir_all(&dir).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-------------+
+            | greet       |
+            +-------------+
+            | hello world |
+            +-------------+"#]],
+    );
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_forbid() {
+    assert_err("", "AttributeError: module 'gcd' has no attribute 'gcd'");
+    assert_err("import os", "ImportError: import os is not allowed");
+    assert_err(
+        "breakpoint()",
+        "NameError: name 'breakpoint' is not defined",
+    );
+    assert_err("exit()", "NameError: name 'exit' is not defined");
+    assert_err("eval('exit()')", "NameError: name 'eval' is not defined");
+    assert_err("help()", "NameError: name 'help' is not defined");
+    assert_err("input()", "NameError: name 'input' is not defined");
+    assert_err("open('foo', 'w')", "NameError: name 'open' is not defined");
+    assert_err("print()", "NameError: name 'print' is not defined");
+}
+
+#[track_caller]
+fn assert_err(code: &str, err: &str) {
+    let mut runtime = Runtime::builder().sandboxed(true).build().unwrap();
+    let error = runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            code,
+        )
+        .unwrap_err();
+    assert_eq!(error.to_string(), err);
+}
+
+#[test]
+fn test_type_mismatch() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "neg",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def neg(x):
+    return -x
+"#,
+        )
+        .unwrap();
+
+    // case1: return type mismatch
+    let schema = Schema::new(vec![Field::new("x", DataType::Float32, true)]);
+    let arg0 = Float32Array::from(vec![1.0]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let err = runtime.call("neg", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "TypeError: 'float' object cannot be interpreted as an integer"
+    );
+    // drop the error here and switch to a new runtime
+    // this is to ensure that error does not contain `PyErr`,
+    // otherwise the next call will cause SIGABRT `pointer being freed was not allocated`.
+    drop(err);
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "neg",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def neg(x):
+    return -x
+    "#,
+        )
+        .unwrap();
+
+    // case2: arguments mismatch
+    let input = RecordBatch::try_new_with_options(
+        Arc::new(Schema::empty()),
+        vec![],
+        &RecordBatchOptions::default().with_row_count(Some(1)),
+    )
+    .unwrap();
+
+    let err = runtime.call("neg", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: function neg expects 1 argument, but got 0"
+    );
+
+    // case3: arguments mismatch
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![1]);
+    let arg1 = Int32Array::from(vec![2]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let err = runtime.call("neg", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: function neg expects 1 argument, but got 2"
+    );
+}
+
+#[test]
+fn test_send() {
+    let mut runtime = Runtime::new().unwrap();
+
+    std::thread::spawn(move || {
+        runtime
+            .add_function(
+                "neg",
+                DataType::Int32,
+                CallMode::ReturnNullOnNullInput,
+                r#"
+def neg(x):
+    return -x
+"#,
+            )
+            .unwrap();
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn test_view_array() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "echo",
+            DataType::Utf8View,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def echo(x):
+    return x + "!"
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Utf8View, true)]);
+    let arg0 = StringViewArray::from(vec!["hello", "world"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("echo", &input).unwrap();
+
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | echo   |
+        +--------+
+        | hello! |
+        | world! |
+        +--------+"#]],
+    );
+}
+
+#[test]
+fn test_remove_function() {
+    use arrow_udf_core::UdfRuntime;
+
+    let mut runtime = Runtime::new().unwrap();
+    for code in ["def f(x):\n    return x", "def f(x):\n    return x + 1"] {
+        runtime
+            .add_function("f", DataType::Int32, CallMode::ReturnNullOnNullInput, code)
+            .unwrap();
+    }
+    runtime
+        .add_aggregate(
+            "s",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def create_state():\n    return 0\ndef accumulate(state, x):\n    return state + x",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "loaded",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import sys
+def loaded(x):
+    return ",".join(name for name in ["f", "s", "loaded"] if name in sys.modules)
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // the function is replaced
+    let output = runtime.call("f", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +---+
+            | f |
+            +---+
+            | 2 |
+            +---+"#]],
+    );
+
+    runtime.remove_function("f").unwrap();
+    runtime.remove_function("s").unwrap();
+    let output = runtime.call("loaded", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +--------+
+            | loaded |
+            +--------+
+            | loaded |
+            +--------+"#]],
+    );
+    let err = runtime.remove_function("f").unwrap_err();
+    assert_eq!(err.to_string(), "function not found: f");
+
+    runtime.clear().unwrap();
+    assert!(runtime.list_functions().is_empty());
+    assert!(runtime.call("loaded", &input).is_err());
+}
+
+#[test]
+fn test_functions() {
+    use arrow_udf_core::{functions_to_batch, CheckedRuntime, UdfRuntime};
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def gcd(a, b):\n    return a if b == 0 else gcd(b, a % b)",
+        )
+        .unwrap();
+    runtime
+        .add_aggregate(
+            "sum",
+            DataType::Int64,
+            DataType::Int64,
+            CallMode::CalledOnNullInput,
+            "def create_state():\n    return 0\ndef accumulate(state, x):\n    return state + (x or 0)",
+        )
+        .unwrap();
+    let runtime = CheckedRuntime::new(Box::new(runtime))
+        .with_signature("gcd", vec![DataType::Int32, DataType::Int32]);
+
+    let mut functions = runtime.functions();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    check(
+        &[functions_to_batch(&functions).unwrap()],
+        expect![[r#"
+            +------+-----------+----------------+-------------+---------------------------+------------+
+            | name | kind      | arg_types      | return_type | mode                      | volatility |
+            +------+-----------+----------------+-------------+---------------------------+------------+
+            | gcd  |           | [Int32, Int32] | Int32       | return_null_on_null_input | volatile   |
+            | sum  | aggregate |                | Int64       | called_on_null_input      |            |
+            +------+-----------+----------------+-------------+---------------------------+------------+"#]],
+    );
+}
+
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(Runtime::new().unwrap());
+    runtime
+        .add_function(
+            "neg",
+            Field::new("neg", DataType::Int32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            r#"
+def neg(x):
+    return -x
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            Field::new("series", DataType::Int32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            r#"
+def series(n):
+    for i in range(n):
+        yield i
+"#,
+        )
+        .unwrap();
+
+    let mut functions = runtime.list_functions();
+    functions.sort();
+    assert_eq!(functions, ["neg", "series"]);
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("neg", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | neg |
+        +-----+
+        | -1  |
+        |     |
+        | -2  |
+        +-----+"#]],
+    );
+
+    let outputs = runtime
+        .call_table_function("series", &input, 2)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 2   | 0      |
+        | 2   | 1      |
+        +-----+--------+"#]],
+    );
+}
+
+#[test]
+fn test_nested_null_policy() {
+    use arrow_udf_core::{Error, NestedNullPolicy};
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "sum_xy",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def sum_xy(s):
+    return (s.x or 0) + (s.y or 0)
+"#,
+        )
+        .unwrap();
+
+    let x = Int32Array::from(vec![Some(1), None]);
+    let y = Int32Array::from(vec![Some(2), Some(3)]);
+    let point = StructArray::from(vec![
+        (
+            Arc::new(Field::new("x", DataType::Int32, true)),
+            Arc::new(x) as ArrayRef,
+        ),
+        (
+            Arc::new(Field::new("y", DataType::Int32, true)),
+            Arc::new(y) as ArrayRef,
+        ),
+    ]);
+    let schema = Schema::new(vec![Field::new("s", point.data_type().clone(), true)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(point)]).unwrap();
+
+    let output = runtime.call("sum_xy", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | sum_xy |
+        +--------+
+        | 3      |
+        | 3      |
+        +--------+"#]],
+    );
+
+    runtime.set_nested_null_policy(NestedNullPolicy::Propagate);
+    let output = runtime.call("sum_xy", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------+
+        | sum_xy |
+        +--------+
+        | 3      |
+        |        |
+        +--------+"#]],
+    );
+
+    runtime.set_nested_null_policy(NestedNullPolicy::Error);
+    let err = arrow_udf_core::UdfRuntime::call(&runtime, "sum_xy", &input).unwrap_err();
+    match err {
+        Error::Execution { row, message, .. } => {
+            assert_eq!(row, Some(1));
+            assert_eq!(message, "argument 1 contains a nested null");
+        }
+        e => panic!("unexpected error: {e}"),
+    }
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_log_sink() {
+    use arrow_udf_core::LogLevel;
+    use std::sync::Mutex;
+
+    let mut runtime = Runtime::new().unwrap();
+    let logs = Arc::new(Mutex::new(vec![]));
+    runtime
+        .set_log_sink(Some(Arc::new({
+            let logs = logs.clone();
+            move |level: LogLevel, function: &str, message: &str| {
+                logs.lock()
+                    .unwrap()
+                    .push(format!("[{level}] {function}: {message}"));
+            }
+        })))
+        .unwrap();
+    runtime
+        .add_function(
+            "add",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import sys
+import logging
+
+print("loaded")
+
+def add(a, b):
+    print("add", a, b, end=" ")
+    print("=", a + b)
+    if a < 0:
+        print("negative", a, file=sys.stderr)
+        logging.getLogger("udf").error("failed on %d", a)
+        raise ValueError("negative")
+    logging.debug("ok")
+    return a + b
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(1), Some(-2), None]);
+    let arg1 = Int32Array::from(vec![Some(3), Some(4), Some(5)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    runtime.call("add", &input).unwrap();
+
+    expect![[r#"
+        [info] add: loaded
+        [info] add: add 1 3 = 4
+        [debug] add: ok
+        [info] add: add -2 4 = 2
+        [warn] add: negative -2
+        [error] add: failed on -2"#]]
+    .assert_eq(&logs.lock().unwrap().join("\n"));
+
+    // messages are printed again once the sink is removed
+    runtime.set_log_sink(None).unwrap();
+    runtime.call("add", &input).unwrap();
+    assert_eq!(logs.lock().unwrap().len(), 6);
+
+    // the standard output is captured in the sandbox, where `print` is disabled
+    let mut runtime = Runtime::builder().sandboxed(true).build().unwrap();
+    let sink = logs.clone();
+    runtime
+        .set_log_sink(Some(Arc::new(
+            move |level: LogLevel, function: &str, message: &str| {
+                sink.lock()
+                    .unwrap()
+                    .push(format!("[{level}] {function}: {message}"));
+            },
+        )))
+        .unwrap();
+    runtime
+        .add_function(
+            "hello",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import sys
+
+def hello(a, b):
+    sys.stdout.write(f"hello {a}\n")
+    return a
+"#,
+        )
+        .unwrap();
+    runtime.call("hello", &input).unwrap();
+    assert_eq!(
+        logs.lock().unwrap()[6..],
+        ["[info] hello: hello 1", "[info] hello: hello -2"]
+    );
+}
+
+#[test]
+#[cfg_attr(not(Py_3_12), ignore = "the sandbox requires Python 3.12")]
+fn test_builder_log_sink() {
+    use arrow_udf_core::LogLevel;
+    use std::sync::Mutex;
+
+    let logs = Arc::new(Mutex::new(vec![]));
+    let sink = logs.clone();
+    let mut runtime = Runtime::builder()
+        .sandboxed(true)
+        .log_sink(Arc::new(
+            move |level: LogLevel, function: &str, message: &str| {
+                sink.lock()
+                    .unwrap()
+                    .push(format!("[{level}] {function}: {message}"));
+            },
+        ))
+        .build()
+        .unwrap();
+    // `print` is kept in the sandbox since its output goes to the sink
+    runtime
+        .add_function(
+            "greet",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def greet(a):
+    print("hello", a)
+    return a
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("a", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    runtime.call("greet", &input).unwrap();
+    assert_eq!(
+        *logs.lock().unwrap(),
+        ["[info] greet: hello 1", "[info] greet: hello 2"]
+    );
+
+    // the sink is kept after restarting
+    runtime.restart().unwrap();
+    runtime.call("greet", &input).unwrap();
+    assert_eq!(logs.lock().unwrap().len(), 4);
+
+    // `print` can still be removed explicitly
+    let mut runtime = Runtime::builder()
+        .sandboxed(true)
+        .remove_symbol("__builtins__.print")
+        .log_sink(Arc::new(|_: LogLevel, _: &str, _: &str| {}))
+        .build()
+        .unwrap();
+    let err = runtime
+        .add_function(
+            "greet",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "print('loaded')\ndef greet(a):\n    return a",
+        )
+        .unwrap_err();
+    assert!(
+        format!("{err:?}").contains("NameError: name 'print' is not defined"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn test_metrics() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "inv",
+            DataType::Float64,
+            CallMode::ReturnNullOnNullInput,
+            "def inv(x):\n    return 1 / x",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def series(n):\n    for i in range(n):\n        yield i",
+        )
+        .unwrap();
+    runtime
+        .add_aggregate(
+            "sum",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return 0
+
+def accumulate(state, value):
+    if value < 0:
+        raise ValueError("negative")
+    return state + value
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(0), Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema.clone()), vec![Arc::new(arg0)]).unwrap();
+    let arg0 = Int32Array::from(vec![Some(1), Some(-1)]);
+    let negative = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // errors of rows are returned in the error column and do not fail the call
+    runtime.call("inv", &input).unwrap();
+    runtime.call("inv", &input.slice(0, 2)).unwrap();
+    // calls of unknown functions are not recorded
+    runtime.call("unknown", &input).unwrap_err();
+    let outputs = runtime
+        .call_table_function("series", &input, 2)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(outputs.len(), 2);
+    let state = runtime.create_state("sum").unwrap();
+    let state = runtime.accumulate("sum", &state, &input).unwrap();
+    runtime.accumulate("sum", &state, &negative).unwrap_err();
+
+    let inv = runtime.metrics().get("inv").unwrap();
+    assert!(inv.input_bytes > 0 && inv.output_bytes > 0);
+    // latencies and memory sizes vary, only check the counts
+    let batch = runtime.metrics().to_batch().unwrap();
+    check(
+        &[batch.project(&[0, 1, 2, 3, 4]).unwrap()],
+        expect![[r#"
+            +----------+-------+--------+------------+-------------+
+            | function | calls | errors | input_rows | output_rows |
+            +----------+-------+--------+------------+-------------+
+            | inv      | 2     | 0      | 6          | 6           |
+            | series   | 1     | 0      | 4          | 3           |
+            | sum      | 2     | 1      | 6          | 0           |
+            +----------+-------+--------+------------+-------------+"#]],
+    );
+
+    runtime.metrics().reset();
+    assert_eq!(runtime.metrics().to_batch().unwrap().num_rows(), 0);
+}
+
+#[test]
+#[cfg(feature = "tracing")]
+fn test_tracing() {
+    use std::sync::Mutex;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    /// A writer that collects the formatted events.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = Buffer::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(false)
+        .with_writer({
+            let buffer = buffer.clone();
+            move || buffer.clone()
+        })
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .add_function(
+                "neg",
+                DataType::Int32,
+                CallMode::ReturnNullOnNullInput,
+                "def neg(x):\n    return -x",
+            )
+            .unwrap();
+        let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+        let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+        let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+        runtime.call("neg", &input).unwrap();
+    });
+
+    // spans are reported with their durations when closed
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    for span in [
+        "add_function{function=\"neg\"}: arrow_udf_python: close time.busy",
+        "call{function=\"neg\" rows=3}:build_array{rows=3}: arrow_udf_python: close time.busy",
+    ] {
+        assert!(output.contains(span), "{span} not found in:\n{output}");
+    }
+    // the time of conversion and execution are recorded in the span of the call
+    let call = (output.lines())
+        .find(|line| line.contains("call{function=\"neg\" rows=3 convert_time="))
+        .unwrap_or_else(|| panic!("call span not found in:\n{output}"));
+    assert!(call.contains(" execute_time="), "{call}");
+}
+
+#[test]
+fn test_inferred_type() {
+    use arrow_udf_core::UdfRuntime;
+
+    let mut runtime = Runtime::new().unwrap();
+    for (name, code) in [
+        ("add", "def add(a: int, b: int) -> int:\n    return a + b"),
+        (
+            "name",
+            "from typing import Optional\ndef name(x) -> Optional[str]:\n    return str(x)",
+        ),
+        (
+            "scale",
+            "def scale(x) -> list[float]:\n    return [x * 0.5]",
+        ),
+        (
+            "counts",
+            "def counts(x) -> 'dict[str, int | None]':\n    return {'x': x}",
+        ),
+        (
+            "day",
+            "import datetime\ndef day(x) -> datetime.date:\n    return datetime.date(2024, 1, x)",
+        ),
+        (
+            "price",
+            "from decimal import Decimal\ndef price(x) -> Decimal:\n    return Decimal(x)",
+        ),
+        (
+            "series",
+            "from typing import Iterator\ndef series(n) -> Iterator[int]:\n    yield from range(n)",
+        ),
+        (
+            "pick",
+            "class pick:\n    def __call__(self, x) -> bool:\n        return x > 1",
+        ),
+    ] {
+        runtime
+            .add_function_with_inferred_type(name, CallMode::ReturnNullOnNullInput, code)
+            .unwrap();
+    }
+    let mut functions = runtime.functions();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    let types: Vec<_> = (functions.iter())
+        .map(|f| {
+            let field = f.return_field.as_ref().unwrap();
+            format!("{}: {} {:?}", f.name, field.data_type(), field.metadata())
+        })
+        .collect();
+    expect![[r#"
+        add: Int64 {}
+        counts: Map(Field { name: "entries", data_type: Struct([Field { name: "key", data_type: Utf8, nullable: false, dict_id: 0, dict_is_ordered: false, metadata: {} }, Field { name: "value", data_type: Int64, nullable: true, dict_id: 0, dict_is_ordered: false, metadata: {} }]), nullable: false, dict_id: 0, dict_is_ordered: false, metadata: {} }, false) {}
+        day: Date32 {}
+        name: Utf8 {}
+        pick: Boolean {}
+        price: Utf8 {"ARROW:extension:name": "arrowudf.decimal"}
+        scale: List(Field { name: "item", data_type: Float64, nullable: true, dict_id: 0, dict_is_ordered: false, metadata: {} }) {}
+        series: Int64 {}"#]]
+    .assert_eq(&types.join("\n"));
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let scale = runtime.call("scale", &input).unwrap();
+    check(
+        &[scale],
+        expect![[r#"
+        +-------+
+        | scale |
+        +-------+
+        | [0.5] |
+        |       |
+        | [1.0] |
+        +-------+"#]],
+    );
+    let day = runtime.call("day", &input).unwrap();
+    check(
+        &[day],
+        expect![[r#"
+        +------------+
+        | day        |
+        +------------+
+        | 2024-01-01 |
+        |            |
+        | 2024-01-02 |
+        +------------+"#]],
+    );
+
+    for (code, error) in [
+        (
+            "def f(x):\n    return x",
+            "function f has no return annotation",
+        ),
+        (
+            "def f(x) -> int | str:\n    return x",
+            "can not infer the return type of function f from annotation `int | str`",
+        ),
+        (
+            "def f(x) -> set[int]:\n    return {x}",
+            "can not infer the return type of function f from annotation `set[int]`",
+        ),
+    ] {
+        let err = runtime
+            .add_function_with_inferred_type("f", CallMode::ReturnNullOnNullInput, code)
+            .unwrap_err();
+        assert_eq!(err.to_string(), format!("type mismatch: {error}"));
+    }
+    assert!(!runtime.list_functions().contains(&"f".to_string()));
+}
+
+#[test]
+fn test_return_validation() {
+    use arrow_udf_python::ReturnValidation;
+
+    let mut runtime = Runtime::new().unwrap();
+    let code = r#"
+import decimal
+
+def value(kind):
+    return {"int": 1, "float": 2.5, "bool": True, "str": "3", "decimal": decimal.Decimal("4.5"), "none": None}[kind]
+"#;
+    for (name, return_type) in [
+        ("as_int", DataType::Int32),
+        ("as_float", DataType::Float64),
+        ("as_str", DataType::Utf8),
+    ] {
+        runtime
+            .add_function_with_handler(
+                name,
+                return_type,
+                CallMode::ReturnNullOnNullInput,
+                code,
+                "value",
+            )
+            .unwrap();
+    }
+    let schema = Schema::new(vec![Field::new("kind", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec!["int", "float", "bool", "str", "decimal", "none"]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // values of other types are rejected by their rows
+    runtime.set_row_conversion_errors(true);
+    for name in ["as_int", "as_float", "as_str"] {
+        runtime
+            .set_return_validation(name, ReturnValidation::Strict)
+            .unwrap();
+    }
+    check(
+        &[runtime.call("as_int", &input).unwrap()],
+        expect![[r#"
+            +--------+--------------------------------------------+
+            | as_int | error                                      |
+            +--------+--------------------------------------------+
+            | 1      |                                            |
+            |        | TypeError: expect int, got float           |
+            |        | TypeError: expect int, got bool            |
+            |        | TypeError: expect int, got str             |
+            |        | TypeError: expect int, got decimal.Decimal |
+            |        |                                            |
+            +--------+--------------------------------------------+"#]],
+    );
+    check(
+        &[runtime.call("as_float", &input).unwrap()],
+        expect![[r#"
+            +----------+----------------------------------------------+
+            | as_float | error                                        |
+            +----------+----------------------------------------------+
+            |          | TypeError: expect float, got int             |
+            | 2.5      |                                              |
+            |          | TypeError: expect float, got bool            |
+            |          | TypeError: expect float, got str             |
+            |          | TypeError: expect float, got decimal.Decimal |
+            |          |                                              |
+            +----------+----------------------------------------------+"#]],
+    );
+
+    // values are converted by the types
+    for name in ["as_int", "as_float", "as_str"] {
+        runtime
+            .set_return_validation(name, ReturnValidation::Coerce)
+            .unwrap();
+    }
+    let columns = ["as_int", "as_float", "as_str"]
+        .map(|name| (name, runtime.call(name, &input).unwrap().column(0).clone()));
+    check(
+        &[RecordBatch::try_from_iter(columns).unwrap()],
+        expect![[r#"
+            +--------+----------+--------+
+            | as_int | as_float | as_str |
+            +--------+----------+--------+
+            | 1      | 1.0      | 1      |
+            | 2      | 2.5      | 2.5    |
+            | 1      | 1.0      | True   |
+            | 3      | 3.0      | 3      |
+            | 4      | 4.5      | 4.5    |
+            |        |          |        |
+            +--------+----------+--------+"#]],
+    );
+
+    let err = runtime
+        .set_return_validation("unknown", ReturnValidation::Strict)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "function not found: unknown");
+}
+
+#[test]
+fn test_overload() {
+    use arrow_udf_core::UdfRuntime;
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_overload(
+            "length",
+            &[DataType::Utf8],
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            "def length(s): return len(s)",
+        )
+        .unwrap();
+    runtime
+        .add_overload(
+            "length",
+            &[DataType::Binary],
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            "def length(b): return len(b) * 8",
+        )
+        .unwrap();
+
+    let string_input =
+        RecordBatch::try_from_iter([("s", Arc::new(StringArray::from(vec!["abc"])) as ArrayRef)])
+            .unwrap();
+    let binary_input = RecordBatch::try_from_iter([(
+        "b",
+        Arc::new(BinaryArray::from(vec![b"abc".as_ref()])) as ArrayRef,
+    )])
+    .unwrap();
+    let int_input =
+        RecordBatch::try_from_iter([("i", Arc::new(Int32Array::from(vec![1])) as ArrayRef)])
+            .unwrap();
+    let outputs =
+        [&string_input, &binary_input].map(|input| runtime.call("length", input).unwrap());
+    check(
+        &outputs,
+        expect![[r#"
+            +--------+
+            | length |
+            +--------+
+            | 3      |
+            | 24     |
+            +--------+"#]],
+    );
+    let err = runtime.call("length", &int_input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "type mismatch: no overload of function length takes (Int32)"
+    );
+
+    // functions without a signature are called with other types
+    runtime
+        .add_function(
+            "length",
+            DataType::Int64,
+            CallMode::ReturnNullOnNullInput,
+            "def length(x): return -1",
+        )
+        .unwrap();
+    let outputs = [&string_input, &int_input].map(|input| runtime.call("length", input).unwrap());
+    check(
+        &outputs,
+        expect![[r#"
+            +--------+
+            | length |
+            +--------+
+            | 3      |
+            | -1     |
+            +--------+"#]],
+    );
+
+    let mut names = runtime.list_functions();
+    names.sort();
+    assert_eq!(names, ["length", "length(Binary)", "length(Utf8)"]);
+    let info = runtime.functions();
+    let overloads = info.iter().filter(|f| f.arg_types.is_some()).count();
+    assert!(info.iter().all(|f| f.name == "length"));
+    assert_eq!(overloads, 2);
+
+    runtime.del_function("length(Utf8)").unwrap();
+    let output = runtime.call("length", &string_input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +--------+
+            | length |
+            +--------+
+            | -1     |
+            +--------+"#]],
+    );
+}
+
+#[test]
+fn test_cancellation_token() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "spin",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def spin(x):
+    while True:
+        pass
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def series(n):
+    yield n
+    while True:
+        pass
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_aggregate(
+            "slow_sum",
+            DataType::Int32,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return 0
+
+def accumulate(state, value):
+    while True:
+        pass
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1, 2]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // cancel the calls running in other threads
+    let state = runtime.create_state("slow_sum").unwrap();
+    std::thread::scope(|s| {
+        let scalar = s.spawn(|| runtime.call("spin", &input));
+        let aggregate = s.spawn(|| runtime.accumulate("slow_sum", &state, &input));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        runtime.cancellation_token().cancel();
+        for handle in [
+            scalar.join().unwrap().map(drop),
+            aggregate.join().unwrap().map(drop),
+        ] {
+            assert_eq!(handle.unwrap_err().to_string(), "cancelled");
+        }
+    });
+
+    // table functions fail in the batch once cancelled
+    let mut outputs = runtime.call_table_function("series", &input, 1).unwrap();
+    assert_eq!(outputs.next().unwrap().unwrap().num_rows(), 1);
+    std::thread::scope(|s| {
+        let token = runtime.cancellation_token();
+        s.spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            token.cancel();
+        });
+        let err = outputs.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "cancelled");
+    });
+    drop(outputs);
+
+    // later calls run with a new token
+    assert!(!runtime.cancellation_token().is_cancelled());
+    let output = runtime
+        .call_table_function("series", &input, 1)
+        .unwrap()
+        .next();
+    assert_eq!(output.unwrap().unwrap().num_rows(), 1);
+}
+
+#[test]
+fn test_call_stream() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "div",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def div(x, y): return x // y",
+        )
+        .unwrap();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]));
+    let batches = [vec![1, 0], vec![3, 4]].map(|divisors| {
+        let arg0 = Int32Array::from(vec![6; 2]);
+        let arg1 = Int32Array::from(divisors);
+        RecordBatch::try_new(schema.clone(), vec![Arc::new(arg0), Arc::new(arg1)])
+    });
+    let input = RecordBatchIterator::new(batches, schema.clone());
+
+    let outputs = runtime.call_stream("div", input).unwrap();
+    let output_schema = outputs.schema();
+    let outputs = outputs.collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(outputs.iter().all(|batch| batch.schema() == output_schema));
+    check(
+        &outputs,
+        expect![[r#"
+            +-----+-------------------------------------------------------+
+            | div | error                                                 |
+            +-----+-------------------------------------------------------+
+            | 6   |                                                       |
+            |     | ZeroDivisionError: integer division or modulo by zero |
+            | 2   |                                                       |
+            | 1   |                                                       |
+            +-----+-------------------------------------------------------+"#]],
+    );
+
+    let input = RecordBatchIterator::new([], schema.clone());
+    let err = runtime.call_stream("unknown", input).err().unwrap();
+    assert_eq!(err.to_string(), "function not found: unknown");
+}
+
+#[test]
+fn test_max_output_rows() {
+    let mut runtime = Runtime::builder().max_output_rows(Some(3)).build().unwrap();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def series(n):\n    yield from range(n)",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "neg",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def neg(n):\n    return -n",
+        )
+        .unwrap();
+
+    let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)]));
+    let arg0 = Int32Array::from(vec![2, 5]);
+    let input = RecordBatch::try_new(schema.clone(), vec![Arc::new(arg0)]).unwrap();
+
+    // bounded even with a larger chunk size
+    let outputs = runtime
+        .call_table_function("series", &input, 1024)
+        .unwrap()
+        .map(|batch| batch.unwrap().num_rows())
+        .collect::<Vec<_>>();
+    assert_eq!(outputs, [3, 3, 1]);
+
+    // the input of streams is sliced
+    let reader = RecordBatchIterator::new([Ok(input.clone()), Ok(input.slice(0, 1))], schema);
+    let outputs = runtime
+        .call_stream("neg", reader)
+        .unwrap()
+        .map(|batch| batch.unwrap().num_rows())
+        .collect::<Vec<_>>();
+    assert_eq!(outputs, [2, 1]);
+
+    runtime.set_max_output_rows(Some(1));
+    assert_eq!(runtime.call("neg", &input).unwrap().num_rows(), 2);
+    let reader = RecordBatchIterator::new([Ok(input.clone())], input.schema());
+    let outputs = runtime
+        .call_stream("neg", reader)
+        .unwrap()
+        .collect::<Vec<_>>();
+    assert_eq!(outputs.len(), 2);
+}
+
+#[test]
+fn test_volatility() {
+    use arrow_udf_core::{FunctionProperties, UdfRuntime, Volatility};
+
     let mut runtime = Runtime::new().unwrap();
+    // returns the number of calls so far
+    let code = r#"
+calls = 0
+
+def count(x):
+    global calls
+    calls += 1
+    return calls
+"#;
+    let properties = FunctionProperties::new()
+        .with_mode(CallMode::ReturnNullOnNullInput)
+        .with_volatility(Volatility::Immutable);
     runtime
-        .add_aggregate(
-            "weighted_avg",
-            pickle_field("state"),
-            DataType::Float32,
+        .add_function_with_properties("count", DataType::Int64, properties, code)
+        .unwrap();
+    runtime
+        .add_function_with_handler(
+            "count_volatile",
+            DataType::Int64,
             CallMode::ReturnNullOnNullInput,
-            r#"
-class State:
-    def __init__(self):
-        self.sum = 0
-        self.weight = 0
-
-def create_state():
-    return State()
-
-def accumulate(state, value, weight):
-    state.sum += value * weight
-    state.weight += weight
-    return state
+            code,
+            "count",
+        )
+        .unwrap();
 
-def retract(state, value, weight):
-    state.sum -= value * weight
-    state.weight -= weight
-    return state
+    let info = runtime.functions();
+    let volatility = |name: &str| info.iter().find(|f| f.name == name).unwrap().volatility;
+    assert_eq!(volatility("count"), Some(Volatility::Immutable));
+    assert_eq!(volatility("count_volatile"), Some(Volatility::Volatile));
 
-def merge(state1, state2):
-    state1.sum += state2.sum
-    state1.weight += state2.weight
-    return state1
+    // rows with the same arguments are evaluated once
+    let schema = Schema::new(vec![Field::new("x", DataType::Int64, true)]);
+    let arg0 = Int64Array::from(vec![Some(3), Some(3), None, Some(4), Some(3)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let columns = ["count", "count_volatile"]
+        .map(|name| (name, runtime.call(name, &input).unwrap().column(0).clone()));
+    check(
+        &[RecordBatch::try_from_iter(columns).unwrap()],
+        expect![[r#"
+            +-------+----------------+
+            | count | count_volatile |
+            +-------+----------------+
+            | 1     | 1              |
+            | 1     | 2              |
+            |       |                |
+            | 2     | 3              |
+            | 1     | 4              |
+            +-------+----------------+"#]],
+    );
+}
 
-def finish(state):
-    if state.weight == 0:
-        return None
-    else:
-        return state.sum / state.weight
-"#,
+#[test]
+fn test_row_error_policy() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "inv",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def inv(x): return 100 // x",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "inv_series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def inv_series(x):\n    yield x\n    yield 100 // x",
         )
         .unwrap();
 
-    let schema = Schema::new(vec![
-        Field::new("value", DataType::Int32, true),
-        Field::new("weight", DataType::Int32, true),
-    ]);
-    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(5)]);
-    let arg1 = Int32Array::from(vec![Some(2), None, Some(4), Some(6)]);
-    let input =
-        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(10), Some(0), None, Some(0)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
 
-    let state = runtime.create_state("weighted_avg").unwrap();
-    let state = runtime.accumulate("weighted_avg", &state, &input).unwrap();
-    // we don't check the state because it is pickled
-    let output = runtime.finish("weighted_avg", &state).unwrap();
-    check_array(
+    // errors are suppressed and counted
+    runtime.set_row_error_policy(RowErrorPolicy::Null);
+    let output = runtime.call("inv", &input).unwrap();
+    assert_eq!(output.schema().metadata()[SUPPRESSED_ERRORS_KEY], "2");
+    check(
         &[output],
         expect![[r#"
-            +-----------+
-            | array     |
-            +-----------+
-            | 3.6666667 |
-            +-----------+"#]],
+            +-----+
+            | inv |
+            +-----+
+            | 10  |
+            |     |
+            |     |
+            |     |
+            +-----+"#]],
+    );
+    let output = runtime.call("inv", &input.slice(0, 1)).unwrap();
+    assert!(output.schema().metadata().is_empty());
+
+    // the first error fails the call
+    runtime.set_row_error_policy(RowErrorPolicy::Fail);
+    let err = runtime.call("inv", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "error at row 1: ZeroDivisionError: integer division or modulo by zero"
     );
+    let mut outputs = runtime
+        .call_table_function("inv_series", &input, 10)
+        .unwrap();
+    let err = outputs.next().unwrap().unwrap_err();
+    match err {
+        arrow_udf_core::Error::Execution { row, .. } => assert_eq!(row, Some(1)),
+        _ => panic!("unexpected error: {err}"),
+    }
 }
 
 #[test]
-fn test_output_type_mismatch() {
+fn test_shared_runtime() {
+    use std::sync::RwLock;
+
+    let runtime = Arc::new(RwLock::new(Runtime::new().unwrap()));
+    let add = |name: &str, code: &str| {
+        (runtime.write().unwrap())
+            .add_function(name, DataType::Int32, CallMode::ReturnNullOnNullInput, code)
+            .unwrap()
+    };
+    add("neg", "def neg(x): return -x");
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from((0..100).collect::<Vec<_>>());
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    // calls from multiple threads while functions are added
+    let handles = (0..4)
+        .map(|_| {
+            let runtime = runtime.clone();
+            let input = input.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    let output = runtime.read().unwrap().call("neg", &input).unwrap();
+                    assert_eq!(output.num_rows(), 100);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    add("double", "def double(x): return x * 2");
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let output = runtime.read().unwrap().call("double", &input).unwrap();
+    assert_eq!(output.num_rows(), 100);
+}
+
+#[test]
+fn test_alias() {
+    use arrow_udf_core::UdfRuntime;
+
     let mut runtime = Runtime::new().unwrap();
-    let err = runtime
+    runtime
+        .add_function(
+            "length",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def length(s): return len(s)",
+        )
+        .unwrap();
+    runtime
         .add_aggregate(
             "sum",
             DataType::Int32,
-            DataType::Int64,
+            DataType::Int32,
             CallMode::ReturnNullOnNullInput,
             r#"
 def create_state():
@@ -727,299 +4724,676 @@ def accumulate(state, value):
     return state + value
 "#,
         )
-        .unwrap_err();
+        .unwrap();
+    runtime.add_alias("strlen", "length").unwrap();
+    // aliases of an alias are aliases of the function
+    runtime.add_alias("char_length", "strlen").unwrap();
+    runtime.add_alias("total", "sum").unwrap();
+
+    let strings =
+        RecordBatch::try_from_iter([("s", Arc::new(StringArray::from(vec!["abc"])) as ArrayRef)])
+            .unwrap();
+    let outputs =
+        ["length", "strlen", "char_length"].map(|name| runtime.call(name, &strings).unwrap());
+    check(
+        &outputs,
+        expect![[r#"
+            +--------+
+            | length |
+            +--------+
+            | 3      |
+            | 3      |
+            | 3      |
+            +--------+"#]],
+    );
+
+    let input =
+        RecordBatch::try_from_iter([("v", Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef)])
+            .unwrap();
+    let state = runtime.create_state("total").unwrap();
+    let state = runtime.accumulate("total", &state, &input).unwrap();
+    let output = runtime.finish("total", &state).unwrap();
+    check_array(
+        &[output],
+        expect![[r#"
+            +-------+
+            | array |
+            +-------+
+            | 6     |
+            +-------+"#]],
+    );
+
+    let mut functions = runtime.list_functions();
+    functions.sort();
+    assert_eq!(functions, ["char_length", "length", "strlen"]);
+    let mut aggregates = runtime.list_aggregates();
+    aggregates.sort();
+    assert_eq!(aggregates, ["sum", "total"]);
+
+    let err = runtime.add_alias("len", "size").unwrap_err();
+    assert_eq!(err.to_string(), "function not found: size");
+    let err = runtime.add_alias("sum", "length").unwrap_err();
+    assert_eq!(err.to_string(), "function sum already exists");
+    // an alias is not redirected to another function
+    let err = runtime.add_alias("total", "length").unwrap_err();
+    assert_eq!(err.to_string(), "function total already exists");
+    runtime
+        .add_overload(
+            "size",
+            &[DataType::Utf8],
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "def size(s): return len(s)",
+        )
+        .unwrap();
+    let err = runtime.add_alias("size", "length").unwrap_err();
+    assert_eq!(err.to_string(), "function size already exists");
+
+    // removing an alias keeps the function, and removing a function removes its aliases
+    runtime.remove_function("strlen").unwrap();
+    runtime.call("length", &strings).unwrap();
+    runtime.remove_function("length").unwrap();
+    let err = runtime.call("char_length", &strings).unwrap_err();
+    assert_eq!(err.to_string(), "function not found: char_length");
+}
+
+#[test]
+#[cfg(not(Py_3_12))]
+fn test_main_interpreter() {
+    // runtimes in the main interpreter have separate globals
+    let mut runtime1 = Runtime::new().unwrap();
+    let mut runtime2 = Runtime::new().unwrap();
+    for (runtime, value) in [(&mut runtime1, 1), (&mut runtime2, 2)] {
+        runtime
+            .add_function(
+                "value",
+                DataType::Int32,
+                CallMode::CalledOnNullInput,
+                &format!("VALUE = {value}\ndef value(x): return VALUE"),
+            )
+            .unwrap();
+    }
+    let input =
+        RecordBatch::try_from_iter([("x", Arc::new(Int32Array::from(vec![0])) as ArrayRef)])
+            .unwrap();
+    let outputs = [&runtime1, &runtime2].map(|runtime| runtime.call("value", &input).unwrap());
+    check(
+        &outputs,
+        expect![[r#"
+            +-------+
+            | value |
+            +-------+
+            | 1     |
+            | 2     |
+            +-------+"#]],
+    );
+
+    let err = Runtime::builder().sandboxed(true).build().unwrap_err();
     assert_eq!(
         err.to_string(),
-        "`output_type` must be the same as `state_type` when `finish` is not defined"
+        "the sandbox requires Python 3.12 or later, where functions run in sub-interpreters"
     );
 }
 
 #[test]
-fn test_error() {
+#[cfg(target_os = "linux")]
+fn test_cpu_time_limit() {
+    use std::time::{Duration, Instant};
+
     let mut runtime = Runtime::new().unwrap();
+    runtime
+        .set_cpu_time_limit(Some(Duration::from_millis(100)))
+        .unwrap();
     runtime
         .add_function(
-            "div",
+            "spin",
             DataType::Int32,
             CallMode::ReturnNullOnNullInput,
             r#"
-def div(a: int, b: int) -> int:
-    return a // b
+def spin(x):
+    while x > 0:
+        pass
+    return x
 "#,
         )
         .unwrap();
-
-    let schema = Schema::new(vec![
-        Field::new("x", DataType::Int32, true),
-        Field::new("y", DataType::Int32, true),
-    ]);
-    let arg0 = Int32Array::from(vec![1, 2]);
-    let arg1 = Int32Array::from(vec![0, 1]);
-    let input =
-        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
-
-    let output = runtime.call("div", &input).unwrap();
-    check(
-        &[output],
-        expect![[r#"
-        +-----+-------------------------------------------------------+
-        | div | error                                                 |
-        +-----+-------------------------------------------------------+
-        |     | ZeroDivisionError: integer division or modulo by zero |
-        | 2   |                                                       |
-        +-----+-------------------------------------------------------+"#]],
-    );
-
     runtime
         .add_function(
-            "range1",
+            "sleep",
             DataType::Int32,
             CallMode::ReturnNullOnNullInput,
             r#"
-def range1(n: int):
-    for i in range(n):
-        if i == 1:
-            raise ValueError("i is 1")
-        yield i
+import time
+
+def sleep(x):
+    time.sleep(x / 1000)
+    return x
 "#,
         )
         .unwrap();
 
-    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
-    let arg0 = Int32Array::from(vec![0, 2, 1]);
-    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
-
-    let mut outputs = runtime.call_table_function("range1", &input, 10).unwrap();
-    let output = outputs.next().unwrap().unwrap();
+    let input =
+        RecordBatch::try_from_iter([("x", Arc::new(Int32Array::from(vec![1])) as ArrayRef)])
+            .unwrap();
+    let start = Instant::now();
+    let err = runtime.call("spin", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "resource exceeded: CPU time limit of 100ms exceeded"
+    );
+    assert!(start.elapsed() < Duration::from_secs(10));
 
+    // waiting is not counted
+    let input =
+        RecordBatch::try_from_iter([("x", Arc::new(Int32Array::from(vec![300])) as ArrayRef)])
+            .unwrap();
+    let output = runtime.call("sleep", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +-----+--------+--------------------+
-        | row | range1 | error              |
-        +-----+--------+--------------------+
-        | 1   | 0      |                    |
-        | 1   |        | ValueError: i is 1 |
-        | 2   | 0      |                    |
-        +-----+--------+--------------------+"#]],
+            +-------+
+            | sleep |
+            +-------+
+            | 300   |
+            +-------+"#]],
     );
 }
 
-/// Test there is no GIL contention across threads.
 #[test]
-fn test_no_gil() {
-    use std::time::Duration;
+fn test_type_converter() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_type_converter(
+            "arrow.uuid",
+            r#"
+import uuid
 
-    fn timeit(f: impl FnOnce()) -> Duration {
-        let start = std::time::Instant::now();
-        f();
-        start.elapsed()
-    }
+def to_python(value):
+    return uuid.UUID(bytes=value)
 
-    let t0 = timeit(test_fib);
-    let t1 = timeit(|| {
-        std::thread::scope(|s| {
-            for _ in 0..4 {
-                s.spawn(test_fib);
-            }
-        })
-    });
-    assert!(
-        t1 < t0 + Duration::from_millis(10),
-        "multi-threaded execution is slower than single-threaded. there is GIL contention."
+def from_python(obj):
+    return obj.bytes
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_type_converter(
+            DataType::LargeUtf8,
+            r#"
+def to_python(value):
+    return value.upper()
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "uuid_to_string",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            "def uuid_to_string(u): return str(u)",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "string_to_uuid",
+            uuid_field("string_to_uuid"),
+            CallMode::ReturnNullOnNullInput,
+            "import uuid\ndef string_to_uuid(s): return uuid.UUID(s.lower())",
+        )
+        .unwrap();
+
+    let uuids = FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+        [Some([0x12; 16]), None].into_iter(),
+        16,
     )
+    .unwrap();
+    let schema = Schema::new(vec![uuid_field("u")]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(uuids)]).unwrap();
+    let output = runtime.call("uuid_to_string", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +--------------------------------------+
+            | uuid_to_string                       |
+            +--------------------------------------+
+            | 12121212-1212-1212-1212-121212121212 |
+            |                                      |
+            +--------------------------------------+"#]],
+    );
+
+    // data type converters apply to fields without extension types
+    let strings = LargeStringArray::from(vec!["34343434-3434-3434-3434-343434343434"]);
+    let input = RecordBatch::try_from_iter([("s", Arc::new(strings) as ArrayRef)]).unwrap();
+    let output = runtime.call("string_to_uuid", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +----------------------------------+
+            | string_to_uuid                   |
+            +----------------------------------+
+            | 34343434343434343434343434343434 |
+            +----------------------------------+"#]],
+    );
+
+    let err = runtime
+        .add_type_converter("arrow.opaque", "x = 1")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "TypeError: converter must define `to_python` or `from_python`"
+    );
+}
+
+/// Returns a field with UUID type.
+fn uuid_field(name: &str) -> Field {
+    Field::new(name, DataType::FixedSizeBinary(16), true)
+        .with_metadata([("ARROW:extension:name".into(), "arrow.uuid".into())].into())
 }
 
 #[test]
-fn test_import() {
-    let mut runtime = Runtime::builder().sandboxed(true).build().unwrap();
+fn test_timestamp_time_zone() {
+    use arrow_schema::TimeUnit;
+    use arrow_udf_python::{NaiveTimestampReturn, TimestampArgument};
+
+    let mut runtime = Runtime::new().unwrap();
     runtime
         .add_function(
-            "gcd",
-            DataType::Int32,
+            "show",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullInput,
+            "def show(t): return t.isoformat()",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "parse",
+            DataType::Timestamp(TimeUnit::Second, Some("+08:00".into())),
             CallMode::ReturnNullOnNullInput,
             r#"
-import decimal
-import json
-import math
-import re
-import numbers
 import datetime
 
-def gcd(a: int, b: int) -> int:
-    while b:
-        a, b = b, a % b
-    return a
+def parse(s):
+    return datetime.datetime.fromisoformat(s)
 "#,
         )
         .unwrap();
-}
 
-#[test]
-fn test_forbid() {
-    assert_err("", "AttributeError: module 'gcd' has no attribute 'gcd'");
-    assert_err("import os", "ImportError: import os is not allowed");
-    assert_err(
-        "breakpoint()",
-        "NameError: name 'breakpoint' is not defined",
+    let timestamps = TimestampSecondArray::from(vec![1_700_000_000]).with_timezone("+08:00");
+    let timestamps = RecordBatch::try_from_iter([("t", Arc::new(timestamps) as ArrayRef)]).unwrap();
+    let mut outputs = vec![];
+    for argument in [
+        TimestampArgument::InTimeZone,
+        TimestampArgument::Utc,
+        TimestampArgument::Naive,
+    ] {
+        runtime.set_timestamp_argument(argument);
+        outputs.push(runtime.call("show", &timestamps).unwrap());
+    }
+    check(
+        &outputs,
+        expect![[r#"
+        +---------------------------+
+        | show                      |
+        +---------------------------+
+        | 2023-11-15T06:13:20+08:00 |
+        | 2023-11-14T22:13:20+00:00 |
+        | 2023-11-14T22:13:20       |
+        +---------------------------+"#]],
     );
-    assert_err("exit()", "NameError: name 'exit' is not defined");
-    assert_err("eval('exit()')", "NameError: name 'eval' is not defined");
-    assert_err("help()", "NameError: name 'help' is not defined");
-    assert_err("input()", "NameError: name 'input' is not defined");
-    assert_err("open('foo', 'w')", "NameError: name 'open' is not defined");
-    assert_err("print()", "NameError: name 'print' is not defined");
-}
 
-#[track_caller]
-fn assert_err(code: &str, err: &str) {
-    let mut runtime = Runtime::builder().sandboxed(true).build().unwrap();
-    let error = runtime
-        .add_function(
-            "gcd",
-            DataType::Int32,
-            CallMode::ReturnNullOnNullInput,
-            code,
-        )
-        .unwrap_err();
-    assert_eq!(error.to_string(), err);
+    let strings = StringArray::from(vec!["2024-01-02T03:04:05", "2024-01-02T03:04:05+00:00"]);
+    let strings = RecordBatch::try_from_iter([("s", Arc::new(strings) as ArrayRef)]).unwrap();
+    let mut outputs = vec![];
+    for naive in [NaiveTimestampReturn::Utc, NaiveTimestampReturn::InTimeZone] {
+        runtime.set_naive_timestamp_return(naive);
+        outputs.push(runtime.call("parse", &strings).unwrap());
+    }
+    check(
+        &outputs,
+        expect![[r#"
+        +---------------------------+
+        | parse                     |
+        +---------------------------+
+        | 2024-01-02T11:04:05+08:00 |
+        | 2024-01-02T11:04:05+08:00 |
+        | 2024-01-02T03:04:05+08:00 |
+        | 2024-01-02T11:04:05+08:00 |
+        +---------------------------+"#]],
+    );
+
+    runtime.set_naive_timestamp_return(NaiveTimestampReturn::Reject);
+    let err = runtime.call("parse", &strings).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ValueError: expect an aware datetime for timestamps in time zone +08:00, got a naive datetime"
+    );
 }
 
 #[test]
-fn test_type_mismatch() {
+fn test_strict_arguments() {
+    use arrow_udf_python::StrictArguments;
+
     let mut runtime = Runtime::new().unwrap();
     runtime
         .add_function(
-            "neg",
-            DataType::Int32,
-            CallMode::ReturnNullOnNullInput,
+            "greet",
+            DataType::Utf8,
+            CallMode::ReturnNullOnNullArguments(StrictArguments::from_indices([0])),
             r#"
-def neg(x):
-    return -x
+def greet(name, greeting):
+    return f"{greeting or 'Hello'}, {name}!"
 "#,
         )
         .unwrap();
 
-    // case1: return type mismatch
-    let schema = Schema::new(vec![Field::new("x", DataType::Float32, true)]);
-    let arg0 = Float32Array::from(vec![1.0]);
-    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let name = StringArray::from(vec![Some("Alice"), Some("Bob"), None]);
+    let greeting = StringArray::from(vec![Some("Hi"), None, Some("Hi")]);
+    let input = RecordBatch::try_from_iter([
+        ("name", Arc::new(name) as ArrayRef),
+        ("greeting", Arc::new(greeting) as ArrayRef),
+    ])
+    .unwrap();
 
-    let err = runtime.call("neg", &input).unwrap_err();
-    assert_eq!(
-        err.to_string(),
-        "TypeError: 'float' object cannot be interpreted as an integer"
+    let output = runtime.call("greet", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-------------+
+        | greet       |
+        +-------------+
+        | Hi, Alice!  |
+        | Hello, Bob! |
+        |             |
+        +-------------+"#]],
     );
-    // drop the error here and switch to a new runtime
-    // this is to ensure that error does not contain `PyErr`,
-    // otherwise the next call will cause SIGABRT `pointer being freed was not allocated`.
-    drop(err);
 
-    let mut runtime = Runtime::new().unwrap();
+    // null states are skipped by merge and returned as null by finish, as in strict aggregates
     runtime
-        .add_function(
-            "neg",
+        .add_aggregate(
+            "weighted_sum",
             DataType::Int32,
-            CallMode::ReturnNullOnNullInput,
+            DataType::Int32,
+            CallMode::ReturnNullOnNullArguments(StrictArguments::from_indices([0])),
             r#"
-def neg(x):
-    return -x
-    "#,
+def create_state():
+    return 0
+
+def accumulate(state, value, weight):
+    return state + value * (1 if weight is None else weight)
+
+def merge(state1, state2):
+    return state1 + state2
+
+def finish(state):
+    return state * 10
+"#,
         )
         .unwrap();
+    let states = Int32Array::from(vec![Some(1), None, Some(3)]);
+    let state = runtime.merge("weighted_sum", &states).unwrap();
+    assert_eq!(&*state, &Int32Array::from(vec![4]));
+    let states: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None]));
+    let output = runtime.finish("weighted_sum", &states).unwrap();
+    assert_eq!(&*output, &Int32Array::from(vec![Some(10), None]));
+}
 
-    // case2: arguments mismatch
-    let input = RecordBatch::try_new_with_options(
-        Arc::new(Schema::empty()),
-        vec![],
-        &RecordBatchOptions::default().with_row_count(Some(1)),
-    )
+#[test]
+fn test_add_functions_from_script() {
+    use arrow_udf_core::UdfRuntime;
+
+    let mut runtime = Runtime::new().unwrap();
+    let names = runtime
+        .add_functions_from_script(
+            r#"
+import arrow_udf
+from arrow_udf import udf
+
+@udf(input_types=["INT", "INT"], result_type="INT", call_mode="return_null_on_null_input")
+def gcd(x, y):
+    while y != 0:
+        (x, y) = (y, x % y)
+    return x
+
+@arrow_udf.udf(["VARCHAR"], "STRUCT<word: VARCHAR, length: INT>", name="longest_word")
+def longest(s):
+    word = max(s.split(), key=len)
+    return {"word": word, "length": len(word)}
+
+def helper():
+    pass
+"#,
+        )
+        .unwrap();
+    assert_eq!(names, ["gcd", "longest_word"]);
+    let mut functions = runtime.functions();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    let functions: Vec<_> = (functions.iter())
+        .map(|f| {
+            (
+                f.name.as_str(),
+                f.return_field.as_ref().unwrap().data_type(),
+                f.mode,
+            )
+        })
+        .collect();
+    let word = DataType::Struct(
+        vec![
+            Field::new("word", DataType::Utf8, true),
+            Field::new("length", DataType::Int32, true),
+        ]
+        .into(),
+    );
+    assert_eq!(
+        functions,
+        [
+            (
+                "gcd",
+                &DataType::Int32,
+                Some(CallMode::ReturnNullOnNullInput)
+            ),
+            ("longest_word", &word, Some(CallMode::CalledOnNullInput)),
+        ]
+    );
+
+    let x = Int32Array::from(vec![Some(25), None]);
+    let y = Int32Array::from(vec![Some(15), Some(1)]);
+    let input = RecordBatch::try_from_iter([
+        ("x", Arc::new(x) as ArrayRef),
+        ("y", Arc::new(y) as ArrayRef),
+    ])
     .unwrap();
+    let output = runtime.call("gcd", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        |     |
+        +-----+"#]],
+    );
 
-    let output = runtime.call("neg", &input).unwrap();
+    let s = StringArray::from(vec!["hello arrow udf"]);
+    let input = RecordBatch::try_from_iter([("s", Arc::new(s) as ArrayRef)]).unwrap();
+    let output = runtime.call("longest_word", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +-----+--------------------------------------------------------------+
-        | neg | error                                                        |
-        +-----+--------------------------------------------------------------+
-        |     | TypeError: neg() missing 1 required positional argument: 'x' |
-        +-----+--------------------------------------------------------------+"#]],
+        +--------------------------+
+        | longest_word             |
+        +--------------------------+
+        | {word: hello, length: 5} |
+        +--------------------------+"#]],
     );
 
-    // case3: arguments mismatch
-    let schema = Schema::new(vec![
-        Field::new("x", DataType::Int32, true),
-        Field::new("y", DataType::Int32, true),
-    ]);
-    let arg0 = Int32Array::from(vec![1]);
-    let arg1 = Int32Array::from(vec![2]);
-    let input =
-        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    // the line numbers of the script are kept
+    runtime
+        .add_functions_from_script(
+            r#"
+import sys
+from arrow_udf import udf
 
-    let output = runtime.call("neg", &input).unwrap();
+@udf(result_type="INT")
+def line():
+    return sys._getframe().f_lineno
+"#,
+        )
+        .unwrap();
+    let input = RecordBatch::try_new_with_options(
+        Arc::new(Schema::empty()),
+        vec![],
+        &RecordBatchOptions::new().with_row_count(Some(1)),
+    )
+    .unwrap();
+    let output = runtime.call("line", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +-----+---------------------------------------------------------------+
-        | neg | error                                                         |
-        +-----+---------------------------------------------------------------+
-        |     | TypeError: neg() takes 1 positional argument but 2 were given |
-        +-----+---------------------------------------------------------------+"#]],
+        +------+
+        | line |
+        +------+
+        | 7    |
+        +------+"#]],
+    );
+
+    let err = runtime
+        .add_functions_from_script("@udf(name='f')\ndef f(): pass")
+        .unwrap_err();
+    assert_eq!(err.to_string(), "the @udf of function f has no result_type");
+    let err = runtime
+        .add_functions_from_script("@udf(result_type='TEXT')\ndef f(): pass")
+        .unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "invalid result_type of function f: unsupported type: TEXT"
     );
 }
 
 #[test]
-fn test_send() {
+fn test_float16() {
+    use half::f16;
+
     let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "norm",
+            DataType::Float16,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+import math
 
-    std::thread::spawn(move || {
-        runtime
-            .add_function(
-                "neg",
-                DataType::Int32,
-                CallMode::ReturnNullOnNullInput,
-                r#"
-def neg(x):
-    return -x
+def norm(v):
+    return math.sqrt(sum(x * x for x in v))
 "#,
-            )
-            .unwrap();
-    })
-    .join()
-    .unwrap();
+        )
+        .unwrap();
+
+    let embedding = ListArray::from_iter_primitive::<types::Float16Type, _, _>([
+        Some([Some(f16::from_f32(3.0)), Some(f16::from_f32(4.0))]),
+        Some([Some(f16::from_f32(0.1)), Some(f16::from_f32(0.2))]),
+    ]);
+    let input = RecordBatch::try_from_iter([("v", Arc::new(embedding) as ArrayRef)]).unwrap();
+
+    let output = runtime.call("norm", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------------+
+        | norm       |
+        +------------+
+        | 5          |
+        | 0.22351074 |
+        +------------+"#]],
+    );
 }
 
 #[test]
-fn test_view_array() {
+fn test_uuid() {
     let mut runtime = Runtime::new().unwrap();
     runtime
         .add_function(
-            "echo",
-            DataType::Utf8View,
+            "next_id",
+            uuid_field("next_id"),
             CallMode::ReturnNullOnNullInput,
             r#"
-def echo(x):
-    return x + "!"
+import uuid
+
+def next_id(u):
+    assert isinstance(u, uuid.UUID)
+    return uuid.UUID(int=u.int + 1)
 "#,
         )
         .unwrap();
+    runtime
+        .add_function(
+            "parse_id",
+            uuid_field("parse_id"),
+            CallMode::ReturnNullOnNullInput,
+            "def parse_id(s): return s",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "reverse",
+            DataType::FixedSizeBinary(4),
+            CallMode::ReturnNullOnNullInput,
+            "def reverse(b): return b[::-1]",
+        )
+        .unwrap();
 
-    let schema = Schema::new(vec![Field::new("x", DataType::Utf8View, true)]);
-    let arg0 = StringViewArray::from(vec!["hello", "world"]);
-    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let uuids = FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+        [Some([0x12; 16]), None].into_iter(),
+        16,
+    )
+    .unwrap();
+    let schema = Schema::new(vec![uuid_field("u")]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(uuids)]).unwrap();
+    let output = runtime.call("next_id", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +----------------------------------+
+        | next_id                          |
+        +----------------------------------+
+        | 12121212121212121212121212121213 |
+        |                                  |
+        +----------------------------------+"#]],
+    );
 
-    let output = runtime.call("echo", &input).unwrap();
+    // strings of UUIDs are accepted as well
+    let strings = StringArray::from(vec!["34343434-3434-3434-3434-343434343434"]);
+    let input = RecordBatch::try_from_iter([("s", Arc::new(strings) as ArrayRef)]).unwrap();
+    let output = runtime.call("parse_id", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +----------------------------------+
+        | parse_id                         |
+        +----------------------------------+
+        | 34343434343434343434343434343434 |
+        +----------------------------------+"#]],
+    );
+    let strings = StringArray::from(vec!["short"]);
+    let input = RecordBatch::try_from_iter([("s", Arc::new(strings) as ArrayRef)]).unwrap();
+    let err = runtime.call("parse_id", &input).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ValueError: badly formed hexadecimal UUID string"
+    );
 
+    let bytes = FixedSizeBinaryArray::try_from_iter([b"abcd", b"1234"].into_iter()).unwrap();
+    let input = RecordBatch::try_from_iter([("b", Arc::new(bytes) as ArrayRef)]).unwrap();
+    let output = runtime.call("reverse", &input).unwrap();
     check(
         &[output],
         expect![[r#"
-        +--------+
-        | echo   |
-        +--------+
-        | hello! |
-        | world! |
-        +--------+"#]],
+        +----------+
+        | reverse  |
+        +----------+
+        | 64636261 |
+        | 34333231 |
+        +----------+"#]],
     );
 }
 
@@ -0,0 +1,263 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![doc = include_str!("../README.md")]
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use arrow_schema::Field;
+use arrow_udf_core::{CallMode, UdfRuntime};
+
+/// A runtime shared between the engine and the [`ReloadManager`].
+pub type SharedRuntime = Arc<RwLock<Box<dyn UdfRuntime>>>;
+
+/// Wrap a runtime to be shared with the [`ReloadManager`].
+pub fn shared(runtime: impl UdfRuntime + 'static) -> SharedRuntime {
+    Arc::new(RwLock::new(Box::new(runtime)))
+}
+
+/// A function to build a runtime from the content of a module file.
+type BuildFn = Box<dyn Fn(&[u8]) -> Result<Box<dyn UdfRuntime>> + Send>;
+
+/// An event of reloading a watched file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReloadEvent {
+    /// The file was reloaded.
+    Reloaded {
+        path: PathBuf,
+        /// The names of the functions registered from the file.
+        functions: Vec<String>,
+    },
+    /// The file failed to reload. The runtime keeps the previous version.
+    Failed { path: PathBuf, error: String },
+}
+
+impl fmt::Display for ReloadEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reloaded { path, functions } => {
+                write!(f, "reloaded {}: {}", path.display(), functions.join(", "))
+            }
+            Self::Failed { path, error } => {
+                write!(f, "failed to reload {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+/// Watches the source files of functions and reloads them into their runtimes.
+#[derive(Default)]
+pub struct ReloadManager {
+    sources: Vec<Source>,
+}
+
+/// A watched file.
+struct Source {
+    path: PathBuf,
+    runtime: SharedRuntime,
+    kind: SourceKind,
+    /// The modification time and size of the file when it was last loaded.
+    version: Option<(SystemTime, u64)>,
+}
+
+enum SourceKind {
+    /// The source code of a function added by `UdfRuntime::add_function`.
+    Function {
+        name: String,
+        return_field: Field,
+        mode: CallMode,
+    },
+    /// A module that replaces the whole runtime.
+    Module { build: BuildFn },
+}
+
+impl ReloadManager {
+    /// Create a manager without watched files.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a function from the source file to the runtime, and reload it when the file changes.
+    pub fn watch_function(
+        &mut self,
+        runtime: &SharedRuntime,
+        path: impl AsRef<Path>,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+    ) -> Result<()> {
+        self.watch(
+            runtime,
+            path.as_ref(),
+            SourceKind::Function {
+                name: name.to_string(),
+                return_field,
+                mode,
+            },
+        )
+    }
+
+    /// Build the runtime from the module file, and rebuild it when the file changes.
+    ///
+    /// The new runtime replaces the shared one, so the module should define all functions of the runtime.
+    pub fn watch_module(
+        &mut self,
+        runtime: &SharedRuntime,
+        path: impl AsRef<Path>,
+        build: impl Fn(&[u8]) -> Result<Box<dyn UdfRuntime>> + Send + 'static,
+    ) -> Result<()> {
+        self.watch(
+            runtime,
+            path.as_ref(),
+            SourceKind::Module {
+                build: Box::new(build),
+            },
+        )
+    }
+
+    fn watch(&mut self, runtime: &SharedRuntime, path: &Path, kind: SourceKind) -> Result<()> {
+        let mut source = Source {
+            path: path.to_path_buf(),
+            runtime: runtime.clone(),
+            kind,
+            version: None,
+        };
+        source.version = Some(version(path)?);
+        source
+            .load()
+            .with_context(|| format!("failed to load {}", path.display()))?;
+        self.sources.push(source);
+        Ok(())
+    }
+
+    /// Returns the paths of watched files.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.sources.iter().map(|s| s.path.as_path())
+    }
+
+    /// Reload the files changed since the last check and return the events.
+    pub fn poll(&mut self) -> Vec<ReloadEvent> {
+        let mut events = vec![];
+        for source in &mut self.sources {
+            let current = match version(&source.path) {
+                Ok(current) => Some(current),
+                // report a missing file once
+                Err(e) if source.version.is_some() => {
+                    source.version = None;
+                    events.push(ReloadEvent::Failed {
+                        path: source.path.clone(),
+                        error: format!("{e:#}"),
+                    });
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            if current == source.version {
+                continue;
+            }
+            source.version = current;
+            let event = match source.load() {
+                Ok(functions) => ReloadEvent::Reloaded {
+                    path: source.path.clone(),
+                    functions,
+                },
+                Err(e) => ReloadEvent::Failed {
+                    path: source.path.clone(),
+                    error: format!("{e:#}"),
+                },
+            };
+            tracing::info!(%event);
+            events.push(event);
+        }
+        events
+    }
+
+    /// Check the files periodically in a background thread and pass the events to the callback.
+    ///
+    /// The thread stops when the returned handle is dropped.
+    pub fn spawn(
+        mut self,
+        interval: Duration,
+        mut on_event: impl FnMut(ReloadEvent) + Send + 'static,
+    ) -> ReloadHandle {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => self.poll().into_iter().for_each(&mut on_event),
+                _ => return,
+            }
+        });
+        ReloadHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Source {
+    /// Load the file into the runtime and return the names of registered functions.
+    fn load(&self) -> Result<Vec<String>> {
+        let content = std::fs::read(&self.path)?;
+        match &self.kind {
+            SourceKind::Function {
+                name,
+                return_field,
+                mode,
+            } => {
+                let code = String::from_utf8(content).context("source is not valid UTF-8")?;
+                // the runtime keeps the previous function if the new code fails to compile
+                let mut runtime = self.runtime.write().unwrap();
+                runtime.add_function(name, return_field.clone(), *mode, &code)?;
+                Ok(vec![name.clone()])
+            }
+            SourceKind::Module { build } => {
+                // build outside the lock so that calls are not blocked meanwhile
+                let new = build(&content)?;
+                let mut functions = new.list_functions();
+                functions.sort();
+                *self.runtime.write().unwrap() = new;
+                Ok(functions)
+            }
+        }
+    }
+}
+
+/// Returns the modification time and size of the file.
+fn version(path: &Path) -> Result<(SystemTime, u64)> {
+    let metadata =
+        std::fs::metadata(path).with_context(|| format!("failed to stat {}", path.display()))?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// The handle of a background thread started by [`ReloadManager::spawn`].
+///
+/// Dropping the handle stops the thread.
+pub struct ReloadHandle {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for ReloadHandle {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::{Array, Int32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::CallMode;
+use arrow_udf_reload::{shared, ReloadEvent, ReloadManager, SharedRuntime};
+
+#[test]
+fn test_reload_function() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("f.js");
+    write(&path, "export function f(x) { return x + 1; }");
+
+    let runtime = shared(arrow_udf_js::Runtime::new().unwrap());
+    let mut manager = ReloadManager::new();
+    manager
+        .watch_function(
+            &runtime,
+            &path,
+            "f",
+            int32_field(),
+            CallMode::CalledOnNullInput,
+        )
+        .unwrap();
+    assert_eq!(call(&runtime), [2, 3]);
+    assert_eq!(manager.poll(), []);
+
+    write(&path, "export function f(x) { return x * 10; }");
+    assert_eq!(
+        manager.poll(),
+        [ReloadEvent::Reloaded {
+            path: path.clone(),
+            functions: vec!["f".into()],
+        }]
+    );
+    assert_eq!(call(&runtime), [10, 20]);
+
+    // the previous version is kept if the new code fails to compile
+    write(&path, "export function f(x) {");
+    let events = manager.poll();
+    assert!(
+        matches!(&events[..], [ReloadEvent::Failed { .. }]),
+        "{events:?}"
+    );
+    assert!(events[0]
+        .to_string()
+        .starts_with(&format!("failed to reload {}", path.display())));
+    assert_eq!(call(&runtime), [10, 20]);
+    assert_eq!(manager.poll(), []);
+
+    // a removed file is reported once
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(manager.poll().len(), 1);
+    assert_eq!(manager.poll(), []);
+    assert_eq!(call(&runtime), [10, 20]);
+}
+
+#[test]
+fn test_reload_module() {
+    let binary = std::fs::read("../target/wasm32-wasip1/release/arrow_udf_example.wasm")
+        .expect("failed to load wasm binary");
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("example.wasm");
+    write(&path, &binary);
+
+    let runtime = shared(arrow_udf_js::Runtime::new().unwrap());
+    let mut manager = ReloadManager::new();
+    manager
+        .watch_module(&runtime, &path, |binary| {
+            Ok(Box::new(arrow_udf_wasm::Runtime::new(binary)?))
+        })
+        .unwrap();
+    assert_eq!(manager.paths().collect::<Vec<_>>(), [path.as_path()]);
+    let functions = runtime.read().unwrap().list_functions();
+    assert!(functions.contains(&"gcd(int32,int32)->int32".to_string()));
+
+    write(&path, b"not a module");
+    let events = manager.poll();
+    assert!(
+        matches!(&events[..], [ReloadEvent::Failed { .. }]),
+        "{events:?}"
+    );
+    assert_eq!(runtime.read().unwrap().list_functions(), functions);
+
+    write(&path, &binary);
+    match &manager.poll()[..] {
+        [ReloadEvent::Reloaded { functions: f, .. }] => assert_eq!(f.len(), functions.len()),
+        events => panic!("unexpected events: {events:?}"),
+    }
+}
+
+#[test]
+fn test_spawn() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("f.js");
+    write(&path, "export function f(x) { return x + 1; }");
+
+    let runtime = shared(arrow_udf_js::Runtime::new().unwrap());
+    let mut manager = ReloadManager::new();
+    manager
+        .watch_function(
+            &runtime,
+            &path,
+            "f",
+            int32_field(),
+            CallMode::CalledOnNullInput,
+        )
+        .unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let handle = manager.spawn(Duration::from_millis(10), move |event| {
+        _ = tx.send(event);
+    });
+
+    write(&path, "export function f(x) { return x - 1; }");
+    let event = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(matches!(event, ReloadEvent::Reloaded { .. }), "{event}");
+    assert_eq!(call(&runtime), [0, 1]);
+
+    drop(handle);
+    // the callback is dropped with the thread
+    rx.recv().unwrap_err();
+}
+
+fn int32_field() -> Field {
+    Field::new("f", DataType::Int32, true)
+}
+
+fn call(runtime: &SharedRuntime) -> Vec<i32> {
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let input = RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(Int32Array::from(vec![1, 2]))],
+    )
+    .unwrap();
+    let output = runtime.read().unwrap().call("f", &input).unwrap();
+    let output = output.column(0).as_any().downcast_ref::<Int32Array>();
+    output.unwrap().values().to_vec()
+}
+
+/// Write the file and advance its modification time, so that the change is detected
+/// even if the file system has a coarse timestamp resolution.
+fn write(path: &Path, content: impl AsRef<[u8]>) {
+    let previous = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    std::fs::write(path, content).unwrap();
+    if let Some(previous) = previous {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(previous + Duration::from_secs(1))
+            .unwrap();
+    }
+}
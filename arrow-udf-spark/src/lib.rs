@@ -0,0 +1,213 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use arrow_array::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, Field, Schema};
+use arrow_udf_core::UdfRuntime;
+
+mod pickle;
+pub mod protocol;
+
+use self::protocol::*;
+
+/// A server speaking the Spark Python worker protocol,
+/// running pandas UDFs with functions in a runtime.
+///
+/// It takes the place of the `pyspark.daemon` process,
+/// so existing Spark executors can be pointed at it.
+#[derive(Clone)]
+pub struct Server {
+    runtime: Arc<dyn UdfRuntime>,
+    secret: Option<String>,
+}
+
+impl Server {
+    /// Create a server for the functions in the runtime.
+    pub fn new(runtime: Arc<dyn UdfRuntime>) -> Self {
+        Self {
+            runtime,
+            secret: None,
+        }
+    }
+
+    /// Require connections to authenticate with the secret,
+    /// which Spark passes to its workers in `PYTHON_WORKER_FACTORY_SECRET`.
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Serve connections on the listener, one thread for each connection.
+    pub fn serve(&self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = server.serve_connection(stream) {
+                    tracing::warn!(error = %e, "connection failed");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Serve as `pyspark.daemon`: listen on a local port and write it to stdout,
+    /// with the secret from the `PYTHON_WORKER_FACTORY_SECRET` environment variable.
+    pub fn serve_daemon(mut self) -> Result<()> {
+        if let Ok(secret) = std::env::var("PYTHON_WORKER_FACTORY_SECRET") {
+            self.secret = Some(secret);
+        }
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let mut stdout = std::io::stdout();
+        write_i32(&mut stdout, listener.local_addr()?.port() as i32)?;
+        stdout.flush()?;
+        self.serve(listener)
+    }
+
+    /// Serve tasks on a connection until the JVM stops reusing it.
+    pub fn serve_connection(&self, stream: TcpStream) -> Result<()> {
+        let mut input = BufReader::new(stream.try_clone()?);
+        let mut output = BufWriter::new(stream);
+        // acknowledge the connection with a process id, as a forked daemon worker does
+        write_i32(&mut output, std::process::id() as i32)?;
+        output.flush()?;
+        if let Some(secret) = &self.secret {
+            let client_secret = read_string(&mut input)?;
+            if client_secret != *secret {
+                write_bytes(&mut output, b"err")?;
+                output.flush()?;
+                bail!("authentication failed");
+            }
+            write_bytes(&mut output, b"ok")?;
+            output.flush()?;
+        }
+        while self.run_task(&mut input, &mut output)? {}
+        Ok(())
+    }
+
+    /// Run a task read from the input, writing its results to the output.
+    ///
+    /// Returns whether the JVM will send another task on the same connection.
+    /// Errors in functions are reported to the JVM and also returned.
+    pub fn run_task(&self, input: &mut impl Read, output: &mut impl Write) -> Result<bool> {
+        let boot_time = now_millis();
+        let result = self.run_task_inner(input, output, boot_time);
+        if let Err(e) = &result {
+            write_i32(output, PYTHON_EXCEPTION_THROWN)?;
+            write_bytes(output, format!("{e:#}").as_bytes())?;
+        }
+        output.flush()?;
+        result
+    }
+
+    fn run_task_inner(
+        &self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        boot_time: i64,
+    ) -> Result<bool> {
+        let Some(task) = read_task(input)? else {
+            return Ok(false);
+        };
+        let init_time = now_millis();
+        let mut reader = StreamReader::try_new(&mut *input, None)?;
+        // the output stream is only started if there is any input batch
+        if let Some(batch) = reader.next() {
+            let batch = self.call(&task, &batch?)?;
+            write_i32(output, START_ARROW_STREAM)?;
+            let mut writer = StreamWriter::try_new(&mut *output, &batch.schema())?;
+            writer.write(&batch)?;
+            let result = self.write_outputs(&task, reader, &mut writer);
+            // end the stream before reporting any error, as PySpark does
+            writer.finish()?;
+            result?;
+        }
+        write_i32(output, TIMING_DATA)?;
+        write_i64(output, boot_time)?;
+        write_i64(output, init_time)?;
+        write_i64(output, now_millis())?;
+        // memory and disk bytes spilled
+        write_i64(output, 0)?;
+        write_i64(output, 0)?;
+        write_i32(output, END_OF_DATA_SECTION)?;
+        // no accumulator updates
+        write_i32(output, 0)?;
+        if read_i32(input)? == END_OF_STREAM {
+            write_i32(output, END_OF_STREAM)?;
+            Ok(true)
+        } else {
+            // tell the JVM not to reuse this worker
+            write_i32(output, END_OF_DATA_SECTION)?;
+            Ok(false)
+        }
+    }
+
+    fn write_outputs(
+        &self,
+        task: &Task,
+        reader: impl Iterator<Item = Result<RecordBatch, ArrowError>>,
+        writer: &mut StreamWriter<impl Write>,
+    ) -> Result<()> {
+        for batch in reader {
+            writer.write(&self.call(task, &batch?)?)?;
+        }
+        Ok(())
+    }
+
+    /// Call the UDFs of a task on an input batch.
+    ///
+    /// The output has a column `_i` for the i-th UDF.
+    fn call(&self, task: &Task, input: &RecordBatch) -> Result<RecordBatch> {
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for (i, udf) in task.udfs.iter().enumerate() {
+            let mut batch = input
+                .project(&udf.arg_offsets)
+                .context("invalid argument offsets")?;
+            for name in &udf.functions {
+                batch = self.runtime.call(name, &batch)?;
+            }
+            if batch.num_columns() != 1 || batch.num_rows() != input.num_rows() {
+                bail!("function must return one column with a value for each row");
+            }
+            let field = batch.schema().field(0).clone();
+            fields.push(Field::new(
+                format!("_{i}"),
+                field.data_type().clone(),
+                field.is_nullable(),
+            ));
+            columns.push(batch.column(0).clone());
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
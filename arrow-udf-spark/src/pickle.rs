@@ -0,0 +1,103 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resolve function names from UDF commands.
+//!
+//! PySpark sends each UDF as a pickled `(func, return_type)` tuple.
+//! Python functions are never run here: a function pickled by reference,
+//! i.e. defined in an importable module rather than `__main__`,
+//! is resolved by its name to the function of the same name in the runtime.
+//! A command that is not a pickle is taken as a UTF-8 function name.
+
+use anyhow::{bail, Context, Result};
+
+const PROTO: u8 = 0x80;
+const FRAME: u8 = 0x95;
+const MARK: u8 = b'(';
+const MEMOIZE: u8 = 0x94;
+const BINPUT: u8 = b'q';
+const LONG_BINPUT: u8 = b'r';
+const SHORT_BINUNICODE: u8 = 0x8c;
+const BINUNICODE: u8 = b'X';
+const BINUNICODE8: u8 = 0x8d;
+const STACK_GLOBAL: u8 = 0x93;
+const GLOBAL: u8 = b'c';
+
+/// Returns the function name of a UDF command.
+pub fn function_name(command: &[u8]) -> Result<String> {
+    if command.first() != Some(&PROTO) {
+        return String::from_utf8(command.to_vec()).context("invalid function name");
+    }
+    let (module, qualname) = first_global(command)?;
+    if module.starts_with("cloudpickle") || module.starts_with("pyspark.cloudpickle") {
+        bail!("function is pickled by value, it must be defined in an importable module");
+    }
+    match qualname.rsplit_once('.') {
+        Some((_, name)) => Ok(name.to_string()),
+        None => Ok(qualname),
+    }
+}
+
+/// Returns the module and name of the first global referenced by a pickle,
+/// which is the function for a `(func, return_type)` tuple.
+fn first_global(mut bytes: &[u8]) -> Result<(String, String)> {
+    let mut strings = vec![];
+    loop {
+        let (&op, rest) = bytes.split_first().context("unexpected end of pickle")?;
+        bytes = rest;
+        match op {
+            PROTO => bytes = skip(bytes, 1)?,
+            FRAME => bytes = skip(bytes, 8)?,
+            MARK | MEMOIZE => {}
+            BINPUT => bytes = skip(bytes, 1)?,
+            LONG_BINPUT => bytes = skip(bytes, 4)?,
+            SHORT_BINUNICODE | BINUNICODE | BINUNICODE8 => {
+                let width = match op {
+                    SHORT_BINUNICODE => 1,
+                    BINUNICODE => 4,
+                    _ => 8,
+                };
+                let mut len = [0; 8];
+                len[..width].copy_from_slice(bytes.get(..width).context("truncated pickle")?);
+                let len = u64::from_le_bytes(len) as usize;
+                let s = bytes.get(width..width + len).context("truncated pickle")?;
+                strings.push(String::from_utf8(s.to_vec()).context("invalid pickle string")?);
+                bytes = &bytes[width + len..];
+            }
+            STACK_GLOBAL => {
+                let (Some(qualname), Some(module)) = (strings.pop(), strings.pop()) else {
+                    bail!("invalid pickle: STACK_GLOBAL without module and name");
+                };
+                return Ok((module, qualname));
+            }
+            GLOBAL => {
+                let mut lines = bytes.splitn(3, |&b| b == b'\n');
+                let (Some(module), Some(qualname), Some(_)) =
+                    (lines.next(), lines.next(), lines.next())
+                else {
+                    bail!("truncated pickle");
+                };
+                let module = String::from_utf8(module.to_vec()).context("invalid pickle string")?;
+                let qualname =
+                    String::from_utf8(qualname.to_vec()).context("invalid pickle string")?;
+                return Ok((module, qualname));
+            }
+            _ => bail!("unsupported pickle opcode before the function: {op:#04x}"),
+        }
+    }
+}
+
+fn skip(bytes: &[u8], n: usize) -> Result<&[u8]> {
+    bytes.get(n..).context("truncated pickle")
+}
@@ -0,0 +1,177 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Framing of the Spark Python worker protocol.
+//!
+//! Integers are big-endian, and strings and byte arrays are prefixed with their length as an `i32`.
+//! The layout follows `pyspark/worker.py` of Spark 3.4 and 3.5.
+
+use std::io::{Read, Write};
+
+use anyhow::{bail, Context, Result};
+
+/// Marks the end of the data section, followed by accumulator updates.
+pub const END_OF_DATA_SECTION: i32 = -1;
+/// Followed by the error message of a failed task.
+pub const PYTHON_EXCEPTION_THROWN: i32 = -2;
+/// Followed by the boot, init and finish time of the worker in milliseconds.
+pub const TIMING_DATA: i32 = -3;
+/// Sent by the JVM after a task if the worker can be reused.
+pub const END_OF_STREAM: i32 = -4;
+/// Precedes the Arrow stream of task outputs.
+pub const START_ARROW_STREAM: i32 = -6;
+
+/// `PythonEvalType.SQL_SCALAR_PANDAS_UDF`
+pub const SQL_SCALAR_PANDAS_UDF: i32 = 200;
+/// `PythonEvalType.SQL_SCALAR_PANDAS_ITER_UDF`
+pub const SQL_SCALAR_PANDAS_ITER_UDF: i32 = 204;
+
+pub fn read_i32(r: &mut impl Read) -> Result<i32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_be_bytes(buf))
+}
+
+pub fn read_i64(r: &mut impl Read) -> Result<i64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(i64::from_be_bytes(buf))
+}
+
+pub fn read_bool(r: &mut impl Read) -> Result<bool> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0] != 0)
+}
+
+pub fn read_bytes(r: &mut impl Read) -> Result<Vec<u8>> {
+    let len = read_i32(r)?;
+    if len < 0 {
+        bail!("invalid length: {len}");
+    }
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+pub fn read_string(r: &mut impl Read) -> Result<String> {
+    String::from_utf8(read_bytes(r)?).context("invalid UTF-8 string")
+}
+
+pub fn write_i32(w: &mut impl Write, value: i32) -> Result<()> {
+    w.write_all(&value.to_be_bytes())?;
+    Ok(())
+}
+
+pub fn write_i64(w: &mut impl Write, value: i64) -> Result<()> {
+    w.write_all(&value.to_be_bytes())?;
+    Ok(())
+}
+
+pub fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    write_i32(w, bytes.len() as i32)?;
+    w.write_all(bytes)?;
+    Ok(())
+}
+
+/// A UDF in a task, possibly chained with others, e.g. `f(g(x))`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Udf {
+    /// Indexes of the argument columns in the input batches.
+    pub arg_offsets: Vec<usize>,
+    /// The names of chained functions, with the innermost first.
+    pub functions: Vec<String>,
+}
+
+/// The header of a task sent by the JVM before the input Arrow stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub partition_id: i32,
+    pub eval_type: i32,
+    pub udfs: Vec<Udf>,
+}
+
+/// Read a task header, or `None` if the JVM asks the worker to exit.
+pub fn read_task(r: &mut impl Read) -> Result<Option<Task>> {
+    let split_index = read_i32(r)?;
+    if split_index == -1 {
+        return Ok(None);
+    }
+    let _python_version = read_string(r)?;
+    // barrier tasks are not supported, but the fields are always sent
+    let _is_barrier = read_bool(r)?;
+    let _bound_port = read_i32(r)?;
+    let _secret = read_string(r)?;
+    // task context
+    let _stage_id = read_i32(r)?;
+    let partition_id = read_i32(r)?;
+    let _attempt_number = read_i32(r)?;
+    let _task_attempt_id = read_i64(r)?;
+    let _cpus = read_i32(r)?;
+    for _ in 0..read_i32(r)? {
+        let _key = read_string(r)?;
+        let _name = read_string(r)?;
+        for _ in 0..read_i32(r)? {
+            let _address = read_string(r)?;
+        }
+    }
+    for _ in 0..read_i32(r)? {
+        let _key = read_string(r)?;
+        let _value = read_string(r)?;
+    }
+    let _spark_files_dir = read_string(r)?;
+    for _ in 0..read_i32(r)? {
+        let _python_include = read_string(r)?;
+    }
+    // broadcast variables are not used by the runtimes
+    if read_bool(r)? {
+        bail!("encrypted broadcast variables are not supported");
+    }
+    for _ in 0..read_i32(r)? {
+        if read_i64(r)? >= 0 {
+            let _path = read_string(r)?;
+        }
+    }
+    let eval_type = read_i32(r)?;
+    if eval_type != SQL_SCALAR_PANDAS_UDF && eval_type != SQL_SCALAR_PANDAS_ITER_UDF {
+        bail!("unsupported eval type: {eval_type}");
+    }
+    for _ in 0..read_i32(r)? {
+        let _key = read_string(r)?;
+        let _value = read_string(r)?;
+    }
+    let mut udfs = vec![];
+    for _ in 0..read_i32(r)? {
+        let mut arg_offsets = vec![];
+        for _ in 0..read_i32(r)? {
+            arg_offsets.push(read_i32(r)? as usize);
+        }
+        let mut functions = vec![];
+        for _ in 0..read_i32(r)? {
+            functions.push(crate::pickle::function_name(&read_bytes(r)?)?);
+        }
+        if functions.is_empty() {
+            bail!("UDF without function");
+        }
+        udfs.push(Udf {
+            arg_offsets,
+            functions,
+        });
+    }
+    Ok(Some(Task {
+        partition_id,
+        eval_type,
+        udfs,
+    }))
+}
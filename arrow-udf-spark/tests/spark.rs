@@ -0,0 +1,231 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+use arrow_array::{Int32Array, RecordBatch};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_js::{CallMode, Runtime};
+use arrow_udf_spark::protocol::*;
+use arrow_udf_spark::Server;
+use expect_test::{expect, Expect};
+
+/// A pickled `(udfs.gcd, udfs.IntegerType())` tuple.
+const PICKLED_GCD: &[u8] = b"\x80\x05\x95'\x00\x00\x00\x00\x00\x00\x00\x8c\x04udfs\x94\x8c\x03gcd\x94\x93\x94h\x00\x8c\x0bIntegerType\x94\x93\x94)\x81\x94\x86\x94.";
+
+#[test]
+fn test_scalar_udf() {
+    let server = server();
+    let mut input = vec![];
+    // gcd(_0, _1) and neg(gcd(_1, _0))
+    write_task(
+        &mut input,
+        &[(&[0, 1], &[PICKLED_GCD]), (&[1, 0], &[b"gcd", b"neg"])],
+    );
+    write_input(
+        &mut input,
+        &[
+            batch(vec![Some(25), None], vec![Some(15), Some(1)]),
+            batch(vec![Some(6)], vec![Some(4)]),
+        ],
+    );
+    write_i32(&mut input, END_OF_STREAM).unwrap();
+
+    let mut output = vec![];
+    let reuse = server
+        .run_task(&mut Cursor::new(input), &mut output)
+        .unwrap();
+    assert!(reuse);
+
+    let mut output = Cursor::new(output);
+    assert_eq!(read_i32(&mut output).unwrap(), START_ARROW_STREAM);
+    check(
+        &read_output(&mut output),
+        expect![[r#"
+        +----+----+
+        | _0 | _1 |
+        +----+----+
+        | 5  | -5 |
+        |    |    |
+        | 2  | -2 |
+        +----+----+"#]],
+    );
+    assert_eq!(read_i32(&mut output).unwrap(), TIMING_DATA);
+    for _ in 0..5 {
+        read_i64(&mut output).unwrap();
+    }
+    assert_eq!(read_i32(&mut output).unwrap(), END_OF_DATA_SECTION);
+    assert_eq!(read_i32(&mut output).unwrap(), 0);
+    assert_eq!(read_i32(&mut output).unwrap(), END_OF_STREAM);
+    assert_eq!(output.position() as usize, output.get_ref().len());
+}
+
+#[test]
+fn test_error() {
+    let server = server();
+    let mut input = vec![];
+    write_task(&mut input, &[(&[0], &[b"unknown"])]);
+    write_input(&mut input, &[batch(vec![Some(1)], vec![Some(1)])]);
+
+    let mut output = vec![];
+    let err = server
+        .run_task(&mut Cursor::new(input), &mut output)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "function not found: unknown");
+
+    let mut output = Cursor::new(output);
+    assert_eq!(read_i32(&mut output).unwrap(), PYTHON_EXCEPTION_THROWN);
+    assert_eq!(
+        read_string(&mut output).unwrap(),
+        "function not found: unknown"
+    );
+
+    // functions pickled by value can not be resolved
+    let mut input = vec![];
+    let pickled = b"\x80\x05\x8c\x17cloudpickle.cloudpickle\x94\x8c\x0e_make_function\x94\x93\x94";
+    write_task(&mut input, &[(&[0], &[pickled])]);
+    let err = server
+        .run_task(&mut Cursor::new(input), &mut vec![])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "function is pickled by value, it must be defined in an importable module"
+    );
+}
+
+#[test]
+fn test_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = server().with_secret("secret");
+    std::thread::spawn(move || server.serve(listener));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    read_i32(&mut stream).unwrap();
+    write_bytes(&mut stream, b"secret").unwrap();
+    assert_eq!(read_bytes(&mut stream).unwrap(), b"ok");
+    // ask the worker to exit
+    write_i32(&mut stream, -1).unwrap();
+    let mut rest = vec![];
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    read_i32(&mut stream).unwrap();
+    write_bytes(&mut stream, b"wrong").unwrap();
+    assert_eq!(read_bytes(&mut stream).unwrap(), b"err");
+}
+
+fn server() -> Server {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "neg",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            "export function neg(a) { return -a; }",
+        )
+        .unwrap();
+    Server::new(Arc::new(runtime))
+}
+
+/// Write a task header as the JVM does, with the argument offsets and commands of each UDF.
+fn write_task(w: &mut impl Write, udfs: &[(&[i32], &[&[u8]])]) {
+    write_i32(w, 0).unwrap(); // split index
+    write_bytes(w, b"3.11").unwrap();
+    w.write_all(&[0]).unwrap(); // is barrier
+    write_i32(w, 0).unwrap(); // bound port
+    write_bytes(w, b"").unwrap(); // secret
+    write_i32(w, 1).unwrap(); // stage id
+    write_i32(w, 2).unwrap(); // partition id
+    write_i32(w, 0).unwrap(); // attempt number
+    write_i64(w, 3).unwrap(); // task attempt id
+    write_i32(w, 1).unwrap(); // cpus
+    write_i32(w, 0).unwrap(); // resources
+    write_i32(w, 1).unwrap(); // local properties
+    write_bytes(w, b"spark.job.description").unwrap();
+    write_bytes(w, b"test").unwrap();
+    write_bytes(w, b"/tmp").unwrap(); // spark files dir
+    write_i32(w, 0).unwrap(); // python includes
+    w.write_all(&[0]).unwrap(); // needs broadcast decryption server
+    write_i32(w, 0).unwrap(); // broadcast variables
+    write_i32(w, SQL_SCALAR_PANDAS_UDF).unwrap();
+    write_i32(w, 1).unwrap(); // runner conf
+    write_bytes(w, b"spark.sql.session.timeZone").unwrap();
+    write_bytes(w, b"UTC").unwrap();
+    write_i32(w, udfs.len() as i32).unwrap();
+    for (offsets, commands) in udfs {
+        write_i32(w, offsets.len() as i32).unwrap();
+        for offset in *offsets {
+            write_i32(w, *offset).unwrap();
+        }
+        write_i32(w, commands.len() as i32).unwrap();
+        for command in *commands {
+            write_bytes(w, command).unwrap();
+        }
+    }
+}
+
+fn write_input(w: &mut impl Write, batches: &[RecordBatch]) {
+    let mut writer = StreamWriter::try_new(w, &batches[0].schema()).unwrap();
+    for batch in batches {
+        writer.write(batch).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn read_output(r: &mut impl Read) -> Vec<RecordBatch> {
+    let reader = StreamReader::try_new(r, None).unwrap();
+    reader.collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+fn batch(x: Vec<Option<i32>>, y: Vec<Option<i32>>) -> RecordBatch {
+    let schema = Schema::new(vec![
+        Field::new("_0", DataType::Int32, true),
+        Field::new("_1", DataType::Int32, true),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(Int32Array::from(x)), Arc::new(Int32Array::from(y))],
+    )
+    .unwrap()
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
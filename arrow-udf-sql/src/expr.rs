@@ -0,0 +1,658 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compile SQL expressions against a schema and evaluate them on batches.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use arrow_arith::boolean::{and_kleene, is_not_null, is_null, not, or_kleene};
+use arrow_arith::numeric;
+use arrow_array::cast::AsArray;
+use arrow_array::{
+    new_null_array, Array, ArrayRef, BooleanArray, Float64Array, Int32Array, Int64Array,
+    RecordBatch, StringArray, UInt32Array,
+};
+use arrow_cast::{cast_with_options, CastOptions};
+use arrow_ord::cmp;
+use arrow_schema::{DataType, Schema};
+use arrow_select::filter::prep_null_mask_filter;
+use sqlparser::ast::{self, BinaryOperator, UnaryOperator};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+
+/// A SQL scalar expression compiled against an input schema.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    node: Node,
+    data_type: DataType,
+}
+
+/// A compiled expression node.
+#[derive(Debug, Clone)]
+enum Node {
+    Column(usize),
+    /// A literal value as an array of length 1.
+    Literal(ArrayRef),
+    Binary(Op, Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Neg(Box<Node>),
+    IsNull(Box<Node>),
+    IsNotNull(Box<Node>),
+    Cast {
+        expr: Box<Node>,
+        to: DataType,
+        safe: bool,
+    },
+    /// `CASE WHEN c1 THEN r1 ... ELSE e END`
+    Case {
+        branches: Vec<(Node, Node)>,
+        else_result: Box<Node>,
+    },
+    Function(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Concat,
+    Like,
+    NotLike,
+    ILike,
+    NotILike,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Coalesce,
+    NullIf,
+    Abs,
+    Lower,
+    Upper,
+    Length,
+}
+
+/// Parse a SQL scalar expression.
+pub(crate) fn parse(sql: &str) -> Result<ast::Expr> {
+    let dialect = GenericDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    let expr = parser.parse_expr()?;
+    let next = parser.peek_token();
+    if next.token != sqlparser::tokenizer::Token::EOF {
+        bail!("unexpected token after expression: {next}");
+    }
+    Ok(expr)
+}
+
+impl Expression {
+    /// Parse and compile a SQL scalar expression against the schema.
+    ///
+    /// Columns are referenced by name. Unquoted names are matched case-insensitively
+    /// if there is no exact match.
+    pub fn new(sql: &str, schema: &Schema) -> Result<Self> {
+        Self::compile(&parse(sql)?, schema)
+    }
+
+    /// Compile a parsed expression against the schema.
+    pub(crate) fn compile(expr: &ast::Expr, schema: &Schema) -> Result<Self> {
+        let (node, data_type) = Compiler { schema }.compile(expr)?;
+        Ok(Self { node, data_type })
+    }
+
+    /// Returns the type of the result.
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    /// Evaluate the expression on a batch with the compiled schema.
+    pub fn evaluate(&self, input: &RecordBatch) -> Result<ArrayRef> {
+        self.node.evaluate(input)
+    }
+}
+
+struct Compiler<'a> {
+    schema: &'a Schema,
+}
+
+impl Compiler<'_> {
+    fn compile(&self, expr: &ast::Expr) -> Result<(Node, DataType)> {
+        use ast::Expr;
+        Ok(match expr {
+            Expr::Identifier(ident) => self.column(ident)?,
+            Expr::CompoundIdentifier(idents) if idents.len() == 1 => self.column(&idents[0])?,
+            Expr::Nested(expr) => self.compile(expr)?,
+            Expr::Value(value) => literal(value)?,
+            Expr::BinaryOp { left, op, right } => {
+                let op = match op {
+                    BinaryOperator::Plus => Op::Add,
+                    BinaryOperator::Minus => Op::Sub,
+                    BinaryOperator::Multiply => Op::Mul,
+                    BinaryOperator::Divide => Op::Div,
+                    BinaryOperator::Modulo => Op::Rem,
+                    BinaryOperator::Eq => Op::Eq,
+                    BinaryOperator::NotEq => Op::NotEq,
+                    BinaryOperator::Lt => Op::Lt,
+                    BinaryOperator::LtEq => Op::LtEq,
+                    BinaryOperator::Gt => Op::Gt,
+                    BinaryOperator::GtEq => Op::GtEq,
+                    BinaryOperator::And => Op::And,
+                    BinaryOperator::Or => Op::Or,
+                    BinaryOperator::StringConcat => Op::Concat,
+                    op => bail!("operator not supported: {op}"),
+                };
+                self.binary(op, self.compile(left)?, self.compile(right)?)?
+            }
+            Expr::UnaryOp { op, expr } => {
+                let (node, ty) = self.compile(expr)?;
+                match op {
+                    UnaryOperator::Not => {
+                        let node = coerce(node, &ty, &DataType::Boolean, "NOT")?;
+                        (Node::Not(Box::new(node)), DataType::Boolean)
+                    }
+                    UnaryOperator::Minus if is_numeric(&ty) => (Node::Neg(Box::new(node)), ty),
+                    UnaryOperator::Plus if is_numeric(&ty) => (node, ty),
+                    op => bail!("operator {op} not supported for type {ty}"),
+                }
+            }
+            Expr::IsNull(expr) => (
+                Node::IsNull(Box::new(self.compile(expr)?.0)),
+                DataType::Boolean,
+            ),
+            Expr::IsNotNull(expr) => (
+                Node::IsNotNull(Box::new(self.compile(expr)?.0)),
+                DataType::Boolean,
+            ),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let value = self.compile(expr)?;
+                let ge = self.binary(Op::GtEq, value.clone(), self.compile(low)?)?;
+                let le = self.binary(Op::LtEq, value, self.compile(high)?)?;
+                negate(self.binary(Op::And, ge, le)?, *negated)
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let value = self.compile(expr)?;
+                let mut result: Option<(Node, DataType)> = None;
+                for item in list {
+                    let eq = self.binary(Op::Eq, value.clone(), self.compile(item)?)?;
+                    result = Some(match result {
+                        Some(result) => self.binary(Op::Or, result, eq)?,
+                        None => eq,
+                    });
+                }
+                negate(result.context("empty IN list")?, *negated)
+            }
+            Expr::Like {
+                negated,
+                any: false,
+                expr,
+                pattern,
+                escape_char: None,
+            } => {
+                let op = if *negated { Op::NotLike } else { Op::Like };
+                self.binary(op, self.compile(expr)?, self.compile(pattern)?)?
+            }
+            Expr::ILike {
+                negated,
+                any: false,
+                expr,
+                pattern,
+                escape_char: None,
+            } => {
+                let op = if *negated { Op::NotILike } else { Op::ILike };
+                self.binary(op, self.compile(expr)?, self.compile(pattern)?)?
+            }
+            Expr::Cast {
+                kind,
+                expr,
+                data_type,
+                format: None,
+            } => {
+                let (node, _) = self.compile(expr)?;
+                let to = sql_type(data_type)?;
+                let safe = matches!(kind, ast::CastKind::TryCast | ast::CastKind::SafeCast);
+                let node = Node::Cast {
+                    expr: Box::new(node),
+                    to: to.clone(),
+                    safe,
+                };
+                (node, to)
+            }
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                let operand = operand.as_ref().map(|e| self.compile(e)).transpose()?;
+                let mut branches = Vec::with_capacity(conditions.len());
+                for condition in conditions {
+                    let condition = self.compile(condition)?;
+                    let condition = match &operand {
+                        Some(operand) => self.binary(Op::Eq, operand.clone(), condition)?,
+                        None => condition,
+                    };
+                    branches.push(coerce(
+                        condition.0,
+                        &condition.1,
+                        &DataType::Boolean,
+                        "CASE",
+                    )?);
+                }
+                let mut values = results
+                    .iter()
+                    .map(|e| self.compile(e))
+                    .collect::<Result<Vec<_>>>()?;
+                values.push(match else_result {
+                    Some(e) => self.compile(e)?,
+                    None => null_literal(),
+                });
+                let (mut values, ty) = unify(values, "CASE")?;
+                let else_result = values.pop().unwrap();
+                let node = Node::Case {
+                    branches: branches.into_iter().zip(values).collect(),
+                    else_result: Box::new(else_result),
+                };
+                (node, ty)
+            }
+            Expr::Function(function) => self.function(function)?,
+            _ => bail!("expression not supported: {expr}"),
+        })
+    }
+
+    fn column(&self, ident: &ast::Ident) -> Result<(Node, DataType)> {
+        let fields = self.schema.fields();
+        let index = match fields.iter().position(|f| f.name() == &ident.value) {
+            Some(index) => index,
+            None if ident.quote_style.is_none() => fields
+                .iter()
+                .position(|f| f.name().eq_ignore_ascii_case(&ident.value))
+                .with_context(|| format!("column not found: {}", ident.value))?,
+            None => bail!("column not found: {}", ident.value),
+        };
+        Ok((Node::Column(index), fields[index].data_type().clone()))
+    }
+
+    fn binary(
+        &self,
+        op: Op,
+        (left, lty): (Node, DataType),
+        (right, rty): (Node, DataType),
+    ) -> Result<(Node, DataType)> {
+        let (operand_type, return_type) = match op {
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem => {
+                let ty = numeric_common_type(&lty, &rty).with_context(|| {
+                    format!("arithmetic operator not supported for {lty} and {rty}")
+                })?;
+                (ty.clone(), ty)
+            }
+            Op::Eq | Op::NotEq | Op::Lt | Op::LtEq | Op::Gt | Op::GtEq => {
+                let ty = common_type(&lty, &rty)
+                    .with_context(|| format!("cannot compare {lty} with {rty}"))?;
+                (ty, DataType::Boolean)
+            }
+            Op::And | Op::Or => (DataType::Boolean, DataType::Boolean),
+            Op::Concat => (DataType::Utf8, DataType::Utf8),
+            Op::Like | Op::NotLike | Op::ILike | Op::NotILike => {
+                (DataType::Utf8, DataType::Boolean)
+            }
+        };
+        let name = format!("{op:?}");
+        let left = coerce(left, &lty, &operand_type, &name)?;
+        let right = coerce(right, &rty, &operand_type, &name)?;
+        Ok((
+            Node::Binary(op, Box::new(left), Box::new(right)),
+            return_type,
+        ))
+    }
+
+    fn function(&self, function: &ast::Function) -> Result<(Node, DataType)> {
+        let name = function.name.to_string().to_lowercase();
+        let ast::FunctionArguments::List(list) = &function.args else {
+            bail!("function {name} must be called with arguments");
+        };
+        if list.duplicate_treatment.is_some() || !list.clauses.is_empty() || function.over.is_some()
+        {
+            bail!("function {name} is not a scalar function");
+        }
+        let mut args = Vec::with_capacity(list.args.len());
+        for arg in &list.args {
+            let ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(arg)) = arg else {
+                bail!("only positional arguments are supported: {arg}");
+            };
+            args.push(self.compile(arg)?);
+        }
+        let check_args = |n: usize| {
+            if args.len() != n {
+                bail!(
+                    "function {name} expects {n} arguments, but got {}",
+                    args.len()
+                );
+            }
+            Ok(())
+        };
+        let (function, args, ty) = match name.as_str() {
+            "coalesce" => {
+                if args.is_empty() {
+                    bail!("function coalesce expects at least 1 argument");
+                }
+                let (args, ty) = unify(args, "coalesce")?;
+                (Function::Coalesce, args, ty)
+            }
+            "nullif" => {
+                check_args(2)?;
+                // compare in the common type, but return the type of the first argument
+                let ty = args[0].1.clone();
+                let (args, common) = unify(args, "nullif")?;
+                let node = Node::Function(Function::NullIf, args);
+                return Ok((coerce(node, &common, &ty, "nullif")?, ty));
+            }
+            "abs" => {
+                check_args(1)?;
+                let (node, ty) = args.pop().unwrap();
+                if !is_numeric(&ty) {
+                    bail!("function abs not supported for type {ty}");
+                }
+                (Function::Abs, vec![node], ty)
+            }
+            "lower" | "upper" | "length" | "char_length" => {
+                check_args(1)?;
+                let (node, ty) = args.pop().unwrap();
+                let node = coerce(node, &ty, &DataType::Utf8, &name)?;
+                match name.as_str() {
+                    "lower" => (Function::Lower, vec![node], DataType::Utf8),
+                    "upper" => (Function::Upper, vec![node], DataType::Utf8),
+                    _ => (Function::Length, vec![node], DataType::Int32),
+                }
+            }
+            _ => bail!("function not supported: {name}"),
+        };
+        Ok((Node::Function(function, args), ty))
+    }
+}
+
+fn negate((node, ty): (Node, DataType), negated: bool) -> (Node, DataType) {
+    match negated {
+        true => (Node::Not(Box::new(node)), ty),
+        false => (node, ty),
+    }
+}
+
+fn literal(value: &ast::Value) -> Result<(Node, DataType)> {
+    let array: ArrayRef = match value {
+        ast::Value::Number(n, _) => match n.parse::<i64>() {
+            Ok(v) => Arc::new(Int64Array::from(vec![v])),
+            Err(_) => Arc::new(Float64Array::from(vec![n
+                .parse::<f64>()
+                .with_context(|| format!("invalid number: {n}"))?])),
+        },
+        ast::Value::SingleQuotedString(s) => Arc::new(StringArray::from(vec![s.as_str()])),
+        ast::Value::Boolean(b) => Arc::new(BooleanArray::from(vec![*b])),
+        ast::Value::Null => return Ok(null_literal()),
+        _ => bail!("literal not supported: {value}"),
+    };
+    let ty = array.data_type().clone();
+    Ok((Node::Literal(array), ty))
+}
+
+fn null_literal() -> (Node, DataType) {
+    (
+        Node::Literal(new_null_array(&DataType::Null, 1)),
+        DataType::Null,
+    )
+}
+
+/// Convert a SQL type to an Arrow type.
+fn sql_type(ty: &ast::DataType) -> Result<DataType> {
+    use ast::DataType as T;
+    Ok(match ty {
+        T::Boolean | T::Bool => DataType::Boolean,
+        T::TinyInt(_) => DataType::Int8,
+        T::SmallInt(_) | T::Int2(_) | T::Int16 => DataType::Int16,
+        T::Int(_) | T::Integer(_) | T::Int4(_) | T::Int32 => DataType::Int32,
+        T::BigInt(_) | T::Int8(_) | T::Int64 => DataType::Int64,
+        T::Real | T::Float4 | T::Float32 => DataType::Float32,
+        T::Double | T::DoublePrecision | T::Float8 | T::Float64 | T::Float(_) => DataType::Float64,
+        T::Varchar(_) | T::Char(_) | T::Text | T::String(_) => DataType::Utf8,
+        T::Date => DataType::Date32,
+        _ => bail!("type not supported: {ty}"),
+    })
+}
+
+fn is_numeric(ty: &DataType) -> bool {
+    ty.is_numeric() && !matches!(ty, DataType::Decimal128(..) | DataType::Decimal256(..))
+}
+
+/// Returns the type that both numeric types can be converted to.
+fn numeric_common_type(a: &DataType, b: &DataType) -> Option<DataType> {
+    use DataType::*;
+    match (a, b) {
+        (Null, Null) => Some(Int64),
+        (Null, t) | (t, Null) if is_numeric(t) => Some(t.clone()),
+        (a, b) if a == b && is_numeric(a) => Some(a.clone()),
+        (a, b) if (a.is_floating() && is_numeric(b)) || (b.is_floating() && is_numeric(a)) => {
+            Some(Float64)
+        }
+        (a, b) if a.is_integer() && b.is_integer() => {
+            let width = |t: &DataType| t.primitive_width().unwrap();
+            let wider = if width(a) >= width(b) { a } else { b };
+            match a.is_signed_integer() == b.is_signed_integer() {
+                true => Some(wider.clone()),
+                false => Some(Int64),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the type that both types can be converted to.
+fn common_type(a: &DataType, b: &DataType) -> Option<DataType> {
+    if let Some(ty) = numeric_common_type(a, b) {
+        return Some(ty);
+    }
+    match (a, b) {
+        (DataType::Null, t) | (t, DataType::Null) => Some(t.clone()),
+        (a, b) if a == b => Some(a.clone()),
+        (
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View,
+        ) => Some(DataType::Utf8),
+        _ => None,
+    }
+}
+
+/// Convert all values to their common type.
+fn unify(values: Vec<(Node, DataType)>, context: &str) -> Result<(Vec<Node>, DataType)> {
+    let mut ty = DataType::Null;
+    for (_, t) in &values {
+        ty = common_type(&ty, t)
+            .with_context(|| format!("incompatible types in {context}: {ty} and {t}"))?;
+    }
+    let nodes = values
+        .into_iter()
+        .map(|(node, t)| coerce(node, &t, &ty, context))
+        .collect::<Result<_>>()?;
+    Ok((nodes, ty))
+}
+
+/// Cast the node to the type if needed.
+fn coerce(node: Node, from: &DataType, to: &DataType, context: &str) -> Result<Node> {
+    if from == to {
+        return Ok(node);
+    }
+    let allowed = match to {
+        DataType::Utf8 => {
+            matches!(
+                from,
+                DataType::Null | DataType::LargeUtf8 | DataType::Utf8View
+            ) || context == "Concat"
+        }
+        _ => *from == DataType::Null || is_numeric(from) && is_numeric(to),
+    };
+    if !allowed {
+        bail!("{context} expects {to}, but got {from}");
+    }
+    Ok(Node::Cast {
+        expr: Box::new(node),
+        to: to.clone(),
+        safe: false,
+    })
+}
+
+impl Node {
+    fn evaluate(&self, input: &RecordBatch) -> Result<ArrayRef> {
+        let num_rows = input.num_rows();
+        Ok(match self {
+            Node::Column(i) => input.column(*i).clone(),
+            Node::Literal(value) => {
+                let indices = UInt32Array::from(vec![0; num_rows]);
+                arrow_select::take::take(value, &indices, None)?
+            }
+            Node::Binary(op, left, right) => {
+                let left = left.evaluate(input)?;
+                let right = right.evaluate(input)?;
+                let (l, r) = (&left, &right);
+                match op {
+                    Op::Add => numeric::add(l, r)?,
+                    Op::Sub => numeric::sub(l, r)?,
+                    Op::Mul => numeric::mul(l, r)?,
+                    Op::Div => numeric::div(l, r)?,
+                    Op::Rem => numeric::rem(l, r)?,
+                    Op::Eq => Arc::new(cmp::eq(l, r)?),
+                    Op::NotEq => Arc::new(cmp::neq(l, r)?),
+                    Op::Lt => Arc::new(cmp::lt(l, r)?),
+                    Op::LtEq => Arc::new(cmp::lt_eq(l, r)?),
+                    Op::Gt => Arc::new(cmp::gt(l, r)?),
+                    Op::GtEq => Arc::new(cmp::gt_eq(l, r)?),
+                    Op::And => Arc::new(and_kleene(l.as_boolean(), r.as_boolean())?),
+                    Op::Or => Arc::new(or_kleene(l.as_boolean(), r.as_boolean())?),
+                    Op::Concat => Arc::new(arrow_string::concat_elements::concat_elements_utf8(
+                        l.as_string::<i32>(),
+                        r.as_string::<i32>(),
+                    )?),
+                    Op::Like => Arc::new(arrow_string::like::like(l, r)?),
+                    Op::NotLike => Arc::new(arrow_string::like::nlike(l, r)?),
+                    Op::ILike => Arc::new(arrow_string::like::ilike(l, r)?),
+                    Op::NotILike => Arc::new(arrow_string::like::nilike(l, r)?),
+                }
+            }
+            Node::Not(expr) => Arc::new(not(expr.evaluate(input)?.as_boolean())?),
+            Node::Neg(expr) => numeric::neg(&expr.evaluate(input)?)?,
+            Node::IsNull(expr) => Arc::new(is_null(&expr.evaluate(input)?)?),
+            Node::IsNotNull(expr) => Arc::new(is_not_null(&expr.evaluate(input)?)?),
+            Node::Cast { expr, to, safe } => {
+                let options = CastOptions {
+                    safe: *safe,
+                    ..Default::default()
+                };
+                cast_with_options(&expr.evaluate(input)?, to, &options)?
+            }
+            Node::Case {
+                branches,
+                else_result,
+            } => {
+                let mut result = else_result.evaluate(input)?;
+                // the first matching branch wins
+                for (condition, value) in branches.iter().rev() {
+                    let mask = prep_null_mask_filter(condition.evaluate(input)?.as_boolean());
+                    result = arrow_select::zip::zip(&mask, &value.evaluate(input)?, &result)?;
+                }
+                result
+            }
+            Node::Function(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(input))
+                    .collect::<Result<Vec<_>>>()?;
+                function.evaluate(args)?
+            }
+        })
+    }
+}
+
+impl Function {
+    fn evaluate(self, mut args: Vec<ArrayRef>) -> Result<ArrayRef> {
+        Ok(match self {
+            Function::Coalesce => {
+                let mut result = args.pop().ok_or_else(|| anyhow!("no argument"))?;
+                for arg in args.iter().rev() {
+                    result = arrow_select::zip::zip(&is_not_null(arg)?, arg, &result)?;
+                }
+                result
+            }
+            Function::NullIf => {
+                let equal = prep_null_mask_filter(&cmp::eq(&args[0], &args[1])?);
+                arrow_select::nullif::nullif(&args[0], &equal)?
+            }
+            Function::Abs if args[0].data_type().is_unsigned_integer() => args.remove(0),
+            Function::Abs => {
+                let value = &args[0];
+                let zero = cast_with_options(
+                    &Int64Array::from(vec![0]),
+                    value.data_type(),
+                    &CastOptions::default(),
+                )?;
+                let zero = arrow_array::Scalar::new(zero);
+                let negative = prep_null_mask_filter(&cmp::lt(value, &zero)?);
+                arrow_select::zip::zip(&negative, &numeric::neg(value)?, value)?
+            }
+            Function::Lower => {
+                let strings = args[0].as_string::<i32>();
+                Arc::new(
+                    strings
+                        .iter()
+                        .map(|s| s.map(str::to_lowercase))
+                        .collect::<StringArray>(),
+                )
+            }
+            Function::Upper => {
+                let strings = args[0].as_string::<i32>();
+                Arc::new(
+                    strings
+                        .iter()
+                        .map(|s| s.map(str::to_uppercase))
+                        .collect::<StringArray>(),
+                )
+            }
+            Function::Length => {
+                let strings = args[0].as_string::<i32>();
+                Arc::new(
+                    strings
+                        .iter()
+                        .map(|s| s.map(|s| s.chars().count() as i32))
+                        .collect::<Int32Array>(),
+                )
+            }
+        })
+    }
+}
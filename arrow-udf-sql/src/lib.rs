@@ -0,0 +1,153 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use arrow_arith::boolean::{is_null, or};
+use arrow_array::{RecordBatch, RecordBatchOptions};
+use arrow_cast::{cast_with_options, CastOptions};
+use arrow_schema::{Field, Schema};
+use arrow_udf_core::{Capabilities, Error, FunctionKind};
+
+mod expr;
+
+pub use self::expr::Expression;
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
+
+/// A runtime to evaluate SQL scalar expressions as user defined functions.
+#[derive(Debug, Default)]
+pub struct Runtime {
+    functions: HashMap<String, Function>,
+}
+
+#[derive(Debug)]
+struct Function {
+    expr: sqlparser::ast::Expr,
+    return_field: Field,
+    mode: CallMode,
+}
+
+impl Runtime {
+    /// Create a new runtime.
+    pub fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    /// Add a new function defined by a SQL scalar expression.
+    ///
+    /// The expression references the columns of input batches by name, e.g. `a * 2 + coalesce(b, 0)`.
+    /// It is parsed here, and compiled against the schema of each input batch when called.
+    /// The result is cast to the type of `return_field`.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name))]
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let expr = expr::parse(code).context("failed to parse SQL expression")?;
+        let function = Function {
+            expr,
+            return_field,
+            mode,
+        };
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    /// Remove a function.
+    pub fn del_function(&mut self, name: &str) -> Result<()> {
+        self.functions
+            .remove(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Ok(())
+    }
+
+    /// Call a function.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    pub fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let expr = Expression::compile(&function.expr, &input.schema())?;
+        let output = expr.evaluate(input)?;
+        let options = CastOptions {
+            safe: false,
+            ..Default::default()
+        };
+        let mut output = cast_with_options(&output, function.return_field.data_type(), &options)?;
+        let mut any_null = None;
+        for (i, column) in input.columns().iter().enumerate() {
+            if !function.mode.is_strict_argument(i) || column.null_count() == 0 {
+                continue;
+            }
+            let null = is_null(column)?;
+            any_null = Some(match any_null {
+                Some(any_null) => or(&any_null, &null)?,
+                None => null,
+            });
+        }
+        if let Some(any_null) = any_null {
+            output = arrow_select::nullif::nullif(&output, &any_null)?;
+        }
+        let schema = Schema::new(vec![function.return_field.clone()]);
+        Ok(RecordBatch::try_new_with_options(
+            Arc::new(schema),
+            vec![output],
+            &RecordBatchOptions::default().with_row_count(Some(input.num_rows())),
+        )?)
+    }
+}
+
+impl arrow_udf_core::UdfRuntime for Runtime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        Ok(self.add_function(name, return_field, mode, code)?)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        Ok(self.call(name, input)?)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        _input: &'a RecordBatch,
+        _chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        Err(anyhow::anyhow!("table function not supported: {name}").into())
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    /// Expressions are evaluated by Arrow kernels, so the data types are not restricted here.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new().with_function_kinds([FunctionKind::Scalar])
+    }
+}
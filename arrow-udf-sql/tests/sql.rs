@@ -0,0 +1,260 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::*;
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_cast::pretty::pretty_format_batches;
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_core::CallMode;
+use arrow_udf_sql::{Expression, Runtime};
+use expect_test::{expect, Expect};
+
+fn input() -> RecordBatch {
+    let schema = Schema::new(vec![
+        Field::new("a", DataType::Int32, true),
+        Field::new("b", DataType::Float64, true),
+        Field::new("s", DataType::Utf8, true),
+    ]);
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![
+            Arc::new(Int32Array::from(vec![Some(1), Some(-2), None, Some(4)])),
+            Arc::new(Float64Array::from(vec![
+                Some(0.5),
+                None,
+                Some(2.0),
+                Some(3.0),
+            ])),
+            Arc::new(StringArray::from(vec![
+                Some("Hello"),
+                Some("world"),
+                None,
+                Some("Arrow"),
+            ])),
+        ],
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_arithmetic() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Float64, true),
+            CallMode::CalledOnNullInput,
+            "a * 2 + coalesce(b, 0)",
+        )
+        .unwrap();
+    let output = runtime.call("f", &input()).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +------+
+            | f    |
+            +------+
+            | 2.5  |
+            | -4.0 |
+            |      |
+            | 11.0 |
+            +------+"#]],
+    );
+}
+
+#[test]
+fn test_return_null_on_null_input() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Int64, true),
+            CallMode::ReturnNullOnNullInput,
+            "coalesce(a, 0) + 1",
+        )
+        .unwrap();
+    // rows with any null column return null
+    let output = runtime.call("f", &input()).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +---+
+            | f |
+            +---+
+            | 2 |
+            |   |
+            |   |
+            | 5 |
+            +---+"#]],
+    );
+}
+
+#[test]
+fn test_expressions() {
+    let input = input();
+    let cases = [
+        "-a",
+        "abs(a)",
+        "a % 3 = 1",
+        "a BETWEEN 0 AND 2",
+        "a NOT IN (1, 4)",
+        "a IS NULL OR b IS NULL",
+        "NOT (a > 0 AND b < 1)",
+        "CASE WHEN a > 2 THEN 'big' WHEN a > 0 THEN 'small' ELSE 'other' END",
+        "CASE a WHEN 1 THEN b END",
+        "nullif(a, 1)",
+        "upper(s) || '!'",
+        "lower(S)",
+        "length(s)",
+        "s LIKE '%o%'",
+        "s ILIKE 'h%'",
+        "CAST(b AS INT)",
+        "TRY_CAST(s AS INT)",
+        "a || '-' || b",
+    ];
+    let mut actual = String::new();
+    for sql in cases {
+        let expr = Expression::new(sql, &input.schema()).unwrap();
+        let output = expr.evaluate(&input).unwrap();
+        assert_eq!(output.data_type(), expr.data_type(), "{sql}");
+        actual += &format!("{sql}: {}\n", format_array(&output));
+    }
+    expect![[r#"
+        -a: Int32 [-1, 2, null, -4]
+        abs(a): Int32 [1, 2, null, 4]
+        a % 3 = 1: Boolean [true, false, null, true]
+        a BETWEEN 0 AND 2: Boolean [true, false, null, false]
+        a NOT IN (1, 4): Boolean [false, true, null, false]
+        a IS NULL OR b IS NULL: Boolean [false, true, true, false]
+        NOT (a > 0 AND b < 1): Boolean [false, true, true, true]
+        CASE WHEN a > 2 THEN 'big' WHEN a > 0 THEN 'small' ELSE 'other' END: Utf8 [small, other, other, big]
+        CASE a WHEN 1 THEN b END: Float64 [0.5, null, null, null]
+        nullif(a, 1): Int32 [null, -2, null, 4]
+        upper(s) || '!': Utf8 [HELLO!, WORLD!, null, ARROW!]
+        lower(S): Utf8 [hello, world, null, arrow]
+        length(s): Int32 [5, 5, null, 5]
+        s LIKE '%o%': Boolean [true, true, null, true]
+        s ILIKE 'h%': Boolean [true, false, null, false]
+        CAST(b AS INT): Int32 [0, null, 2, 3]
+        TRY_CAST(s AS INT): Int32 [null, null, null, null]
+        a || '-' || b: Utf8 [1-0.5, null, null, 4-3.0]
+    "#]].assert_eq(&actual);
+}
+
+#[test]
+fn test_compile_error() {
+    let schema = input().schema();
+    let cases = [
+        "x + 1",
+        "\"A\" + 1",
+        "s + 1",
+        "a = 'x'",
+        "NOT a",
+        "unknown(a)",
+        "nullif(a)",
+        "a +",
+        "a b",
+    ];
+    let mut actual = String::new();
+    for sql in cases {
+        let err = Expression::new(sql, &schema).unwrap_err();
+        actual += &format!("{sql}: {err}\n");
+    }
+    expect![[r#"
+        x + 1: column not found: x
+        "A" + 1: column not found: A
+        s + 1: arithmetic operator not supported for Utf8 and Int64
+        a = 'x': cannot compare Int32 with Utf8
+        NOT a: NOT expects Boolean, but got Int32
+        unknown(a): function not supported: unknown
+        nullif(a): function nullif expects 2 arguments, but got 1
+        a +: sql parser error: Expected: an expression, found: EOF
+        a b: unexpected token after expression: b
+    "#]]
+    .assert_eq(&actual);
+}
+
+#[test]
+fn test_runtime_error() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "div",
+            Field::new("div", DataType::Int32, true),
+            CallMode::ReturnNullOnNullInput,
+            "a / (a - 1)",
+        )
+        .unwrap();
+    let err = runtime.call("div", &input()).unwrap_err();
+    assert_eq!(err.to_string(), "Divide by zero error");
+
+    let err = runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Int32, true),
+            CallMode::ReturnNullOnNullInput,
+            "a +",
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "failed to parse SQL expression");
+}
+
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(Runtime::new().unwrap());
+    runtime
+        .add_function(
+            "neg",
+            Field::new("neg", DataType::Int32, true),
+            CallMode::ReturnNullOnNullInput,
+            "-a",
+        )
+        .unwrap();
+    assert_eq!(runtime.list_functions(), ["neg"]);
+    let output = runtime.call("neg", &input()).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+            +-----+
+            | neg |
+            +-----+
+            | -1  |
+            |     |
+            |     |
+            | -4  |
+            +-----+"#]],
+    );
+    runtime
+        .call_table_function("neg", &input(), 1)
+        .err()
+        .unwrap();
+}
+
+/// Format the values of an array in one line.
+fn format_array(array: &dyn Array) -> String {
+    let options = FormatOptions::default().with_null("null");
+    let formatter = ArrayFormatter::try_new(array, &options).unwrap();
+    let values: Vec<_> = (0..array.len())
+        .map(|i| formatter.value(i).to_string())
+        .collect();
+    format!("{} [{}]", array.data_type(), values.join(", "))
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
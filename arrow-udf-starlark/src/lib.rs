@@ -0,0 +1,766 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use arrow_array::builder::{ArrayBuilder, Int32Builder};
+use arrow_array::{Array, ArrayRef, Int32Array, RecordBatch};
+use arrow_schema::{DataType, Field, FieldRef, Schema, SchemaRef};
+use arrow_udf_core::{Capabilities, Error, FunctionKind, NestedNullPolicy, ResourceLimit};
+use starlark::environment::{FrozenModule, Globals, GlobalsBuilder, LibraryExtension, Module};
+use starlark::eval::Evaluator;
+use starlark::syntax::{AstModule, Dialect};
+use starlark::values::list::ListRef;
+use starlark::values::{OwnedFrozenValue, Value};
+
+pub use self::into_field::IntoField;
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
+
+mod into_field;
+mod starlarkarrow;
+
+/// A runtime to execute user defined functions in Starlark.
+///
+/// Starlark code is deterministic and hermetic: it has no access to the file system,
+/// network or clock, and `load` statements are disabled.
+///
+/// # Usages
+///
+/// - Create a new runtime with [`Runtime::new`].
+/// - For scalar functions, use [`add_function`] and [`call`].
+/// - For table functions, use [`add_function`] and [`call_table_function`].
+/// - For aggregate functions, create the function with [`add_aggregate`], and then
+///     - create a new state with [`create_state`],
+///     - update the state with [`accumulate`],
+///     - merge states with [`merge`],
+///     - finally get the result with [`finish`].
+///
+/// [`add_function`]: Runtime::add_function
+/// [`add_aggregate`]: Runtime::add_aggregate
+/// [`call`]: Runtime::call
+/// [`call_table_function`]: Runtime::call_table_function
+/// [`create_state`]: Runtime::create_state
+/// [`accumulate`]: Runtime::accumulate
+/// [`merge`]: Runtime::merge
+/// [`finish`]: Runtime::finish
+pub struct Runtime {
+    functions: HashMap<String, Function>,
+    aggregates: HashMap<String, Aggregate>,
+    globals: Globals,
+    /// The maximum number of ticks of each function call.
+    max_ticks: Option<u64>,
+    /// Timeout of each function call.
+    timeout: Option<Duration>,
+    /// How nulls inside nested arguments are handled.
+    nested_nulls: NestedNullPolicy,
+}
+
+impl Debug for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("functions", &self.functions.keys())
+            .field("aggregates", &self.aggregates.keys())
+            .field("max_ticks", &self.max_ticks)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// A user defined scalar function or table function.
+struct Function {
+    function: OwnedFrozenValue,
+    return_field: FieldRef,
+    mode: CallMode,
+}
+
+/// A user defined aggregate function.
+struct Aggregate {
+    state_field: FieldRef,
+    output_field: FieldRef,
+    mode: CallMode,
+    create_state: OwnedFrozenValue,
+    accumulate: OwnedFrozenValue,
+    finish: Option<OwnedFrozenValue>,
+    merge: Option<OwnedFrozenValue>,
+}
+
+impl arrow_udf_core::UdfRuntime for Runtime {
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        Ok(self.add_function(name, return_field, mode, code)?)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        Ok(self.call(name, input)?)
+    }
+
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        Ok(Box::new(
+            self.call_table_function(name, input, chunk_size)?
+                .map(|batch| batch.map_err(Into::into)),
+        ))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn list_aggregates(&self) -> Vec<String> {
+        self.aggregates.keys().cloned().collect()
+    }
+
+    fn create_state(&self, name: &str) -> arrow_udf_core::Result<ArrayRef> {
+        Ok(self.create_state(name)?)
+    }
+
+    fn accumulate(
+        &self,
+        name: &str,
+        state: &dyn Array,
+        input: &RecordBatch,
+    ) -> arrow_udf_core::Result<ArrayRef> {
+        Ok(self.accumulate(name, state, input)?)
+    }
+
+    fn merge(&self, name: &str, states: &dyn Array) -> arrow_udf_core::Result<ArrayRef> {
+        Ok(self.merge(name, states)?)
+    }
+
+    fn finish(&self, name: &str, states: &ArrayRef) -> arrow_udf_core::Result<ArrayRef> {
+        Ok(self.finish(name, states)?)
+    }
+
+    fn state_field(&self, name: &str) -> arrow_udf_core::Result<FieldRef> {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| arrow_udf_core::Error::FunctionNotFound(name.to_string()))?;
+        Ok(aggregate.state_field.clone())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new()
+            .with_data_types(DATA_TYPES.iter().copied())
+            .with_function_kinds([
+                FunctionKind::Scalar,
+                FunctionKind::Table,
+                FunctionKind::Aggregate,
+            ])
+            .with_resource_limits([ResourceLimit::Timeout, ResourceLimit::Instructions])
+    }
+}
+
+/// The names of data types that can be converted between Arrow and Starlark.
+const DATA_TYPES: &[&str] = &[
+    "Boolean",
+    "Float32",
+    "Float64",
+    "Int8",
+    "Int16",
+    "Int32",
+    "Int64",
+    "LargeList",
+    "LargeUtf8",
+    "List",
+    "Null",
+    "Struct",
+    "UInt8",
+    "UInt16",
+    "UInt32",
+    "UInt64",
+    "Utf8",
+];
+
+impl Runtime {
+    /// Create a new `Runtime`.
+    ///
+    /// The `struct` type and the `json` module are available to functions
+    /// in addition to the standard library.
+    pub fn new() -> Result<Self> {
+        let globals =
+            GlobalsBuilder::extended_by(&[LibraryExtension::StructType, LibraryExtension::Json])
+                .build();
+        Ok(Self {
+            functions: HashMap::new(),
+            aggregates: HashMap::new(),
+            globals,
+            max_ticks: None,
+            timeout: None,
+            nested_nulls: NestedNullPolicy::default(),
+        })
+    }
+
+    /// Set the maximum number of ticks of each function call.
+    ///
+    /// A tick is one function call or one loop iteration, so the limit is deterministic.
+    /// Calls exceeding the limit fail with an error.
+    pub fn set_max_ticks(&mut self, ticks: Option<u64>) {
+        self.max_ticks = ticks.filter(|&t| t > 0);
+    }
+
+    /// Set the timeout of each function call.
+    ///
+    /// The timeout is checked periodically during evaluation.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Set how nulls inside nested arguments are handled for functions that return null on null input.
+    pub fn set_nested_null_policy(&mut self, policy: NestedNullPolicy) {
+        self.nested_nulls = policy;
+    }
+
+    /// Add a new scalar function or table function.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the function.
+    /// - `return_type`: The data type of the return value.
+    /// - `mode`: Whether the function will be called when some of its arguments are null.
+    /// - `code`: The Starlark code of the function.
+    ///
+    /// The code should define a function with the same name as the function.
+    /// A table function returns a list, each element of which is an output row.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_starlark::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// // add a scalar function
+    /// runtime
+    ///     .add_function(
+    ///         "gcd",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def gcd(a, b):
+    ///     for _ in range(a + b):
+    ///         if b == 0:
+    ///             return a
+    ///         a, b = b, a % b
+    ///     return a
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// // add a table function
+    /// runtime
+    ///     .add_function(
+    ///         "series",
+    ///         DataType::Int32,
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def series(n):
+    ///     return list(range(n))
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        self.add_function_with_handler(name, return_type, mode, code, name)
+    }
+
+    /// Add a new scalar function or table function with custom handler name.
+    ///
+    /// # Arguments
+    ///
+    /// - `handler`: The name of function in Starlark code to be called.
+    /// - others: Same as [`add_function`].
+    ///
+    /// [`add_function`]: Runtime::add_function
+    #[tracing::instrument(name = "add_function", level = "debug", skip_all, fields(function = name))]
+    pub fn add_function_with_handler(
+        &mut self,
+        name: &str,
+        return_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+        handler: &str,
+    ) -> Result<()> {
+        let module = self.load_module(name, code)?;
+        let function = Function {
+            function: get_function(&module, handler)?,
+            return_field: return_type.into_field(name).into(),
+            mode,
+        };
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    /// Remove a scalar or table function.
+    pub fn del_function(&mut self, name: &str) -> Result<()> {
+        self.functions
+            .remove(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Ok(())
+    }
+
+    /// Add a new aggregate function.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the function.
+    /// - `state_type`: The data type of the internal state.
+    /// - `output_type`: The data type of the aggregate value.
+    /// - `mode`: Whether the function will be called when some of its arguments are null.
+    /// - `code`: The Starlark code of the aggregate function.
+    ///
+    /// The code should define at least two functions:
+    ///
+    /// - `create_state() -> state`: Create a new state.
+    /// - `accumulate(state, *args) -> state`: Accumulate a new value into the state, returning the updated state.
+    ///
+    /// optionally, the code can define:
+    ///
+    /// - `finish(state) -> value`: Get the result of the aggregate function.
+    ///   If not defined, the state is returned as the result.
+    ///   In this case, `output_type` must be the same as `state_type`.
+    /// - `merge(state, state) -> state`: Merge two states, returning the merged state.
+    ///
+    /// States are converted to `state_type` between calls.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use arrow_udf_starlark::{Runtime, CallMode};
+    /// # use arrow_schema::DataType;
+    /// let mut runtime = Runtime::new().unwrap();
+    /// runtime
+    ///     .add_aggregate(
+    ///         "sum",
+    ///         DataType::Int32, // state_type
+    ///         DataType::Int32, // output_type
+    ///         CallMode::ReturnNullOnNullInput,
+    ///         r#"
+    /// def create_state():
+    ///     return 0
+    ///
+    /// def accumulate(state, value):
+    ///     return state + value
+    ///
+    /// def merge(state1, state2):
+    ///     return state1 + state2
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name))]
+    pub fn add_aggregate(
+        &mut self,
+        name: &str,
+        state_type: impl IntoField,
+        output_type: impl IntoField,
+        mode: CallMode,
+        code: &str,
+    ) -> Result<()> {
+        let module = self.load_module(name, code)?;
+        let aggregate = Aggregate {
+            state_field: state_type.into_field(name).into(),
+            output_field: output_type.into_field(name).into(),
+            mode,
+            create_state: get_function(&module, "create_state")?,
+            accumulate: get_function(&module, "accumulate")?,
+            finish: get_function(&module, "finish").ok(),
+            merge: get_function(&module, "merge").ok(),
+        };
+        if aggregate.finish.is_none() && aggregate.state_field != aggregate.output_field {
+            bail!("`output_type` must be the same as `state_type` when `finish` is not defined");
+        }
+        self.aggregates.insert(name.to_string(), aggregate);
+        Ok(())
+    }
+
+    /// Remove an aggregate function.
+    pub fn del_aggregate(&mut self, name: &str) -> Result<()> {
+        self.aggregates
+            .remove(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Ok(())
+    }
+
+    /// Parse and evaluate the code into a frozen module.
+    fn load_module(&self, name: &str, code: &str) -> Result<FrozenModule> {
+        let mut dialect = Dialect::Standard;
+        dialect.enable_load = false;
+        let ast = AstModule::parse(&format!("{name}.star"), code.to_string(), &dialect)
+            .map_err(|e| e.into_anyhow())
+            .context("failed to parse code")?;
+        Module::with_temp_heap(|module| {
+            let mut eval = self.evaluator(&module)?;
+            eval.eval_module(ast, &self.globals)
+                .map_err(|e| e.into_anyhow())
+                .context("failed to evaluate module")?;
+            drop(eval);
+            Ok(module.freeze()?)
+        })
+    }
+
+    /// Create an evaluator with the limits of the runtime.
+    fn evaluator<'v, 'a>(&self, module: &'a Module<'v>) -> Result<Evaluator<'v, 'a, 'static>> {
+        let mut eval = Evaluator::new(module);
+        if let Some(ticks) = self.max_ticks {
+            eval.set_max_tick_count(ticks)?;
+        }
+        if let Some(timeout) = self.timeout {
+            let deadline = Instant::now() + timeout;
+            eval.set_check_cancelled(Box::new(move || Instant::now() >= deadline));
+        }
+        Ok(eval)
+    }
+
+    /// Check the strict arguments of a row and their nested nulls against the policy.
+    /// Returns true if the row should be skipped.
+    fn skips_row(&self, mode: CallMode, input: &RecordBatch, row: usize) -> Result<bool> {
+        let values = input.columns().iter().map(|c| (c.as_ref(), row));
+        Ok(mode
+            .skips_row(self.nested_nulls, values)
+            .map_err(|e| e.with_row(row))?)
+    }
+
+    /// Call a Starlark function with a new evaluator.
+    fn call_user_fn<'v>(
+        &self,
+        module: &Module<'v>,
+        function: Value<'v>,
+        args: &[Value<'v>],
+    ) -> Result<Value<'v>> {
+        let mut eval = self.evaluator(module)?;
+        eval.eval_function(function, args, &[])
+            .map_err(|e| e.into_anyhow())
+    }
+
+    /// Call a scalar function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    #[doc = include_str!("doc_create_function.txt")]
+    /// let schema = Schema::new(vec![
+    ///     Field::new("x", DataType::Int32, true),
+    ///     Field::new("y", DataType::Int32, true),
+    /// ]);
+    /// let arg0 = Int32Array::from(vec![Some(25), None]);
+    /// let arg1 = Int32Array::from(vec![Some(15), None]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+    ///
+    /// let output = runtime.call("gcd", &input).unwrap();
+    /// assert_eq!(&**output.column(0), &Int32Array::from(vec![Some(5), None]));
+    /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    pub fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let array = Module::with_temp_heap(|module| {
+            let heap = module.heap();
+            let handler = heap.access_owned_frozen_value(&function.function);
+            let mut results = Vec::with_capacity(input.num_rows());
+            for i in 0..input.num_rows() {
+                let row = get_row(&module, input, i)?;
+                if self.skips_row(function.mode, input, i)? {
+                    results.push(Value::new_none());
+                    continue;
+                }
+                let result = self
+                    .call_user_fn(&module, handler, &row)
+                    .context("failed to call function")?;
+                results.push(result);
+            }
+            tracing::trace_span!("build_array", rows = results.len())
+                .in_scope(|| starlarkarrow::build_array(&function.return_field, &results))
+                .context("failed to build arrow array from return values")
+        })?;
+        let schema = Schema::new(vec![function.return_field.clone()]);
+        Ok(RecordBatch::try_new(Arc::new(schema), vec![array])?)
+    }
+
+    /// Call a table function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    #[doc = include_str!("doc_create_function.txt")]
+    /// let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    /// let arg0 = Int32Array::from(vec![Some(1), None, Some(3)]);
+    /// let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    ///
+    /// let mut outputs = runtime.call_table_function("series", &input, 10).unwrap();
+    /// let output = outputs.next().unwrap().unwrap();
+    /// let pretty = arrow_cast::pretty::pretty_format_batches(&[output]).unwrap().to_string();
+    /// assert_eq!(pretty, r#"
+    /// +-----+--------+
+    /// | row | series |
+    /// +-----+--------+
+    /// | 0   | 0      |
+    /// | 2   | 0      |
+    /// | 2   | 1      |
+    /// | 2   | 2      |
+    /// +-----+--------+"#.trim());
+    /// ```
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    pub fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        chunk_size: usize,
+    ) -> Result<RecordBatchIter<'a>> {
+        assert!(chunk_size > 0);
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Ok(RecordBatchIter {
+            rt: self,
+            input,
+            function,
+            schema: Arc::new(Schema::new(vec![
+                Arc::new(Field::new("row", DataType::Int32, false)),
+                function.return_field.clone(),
+            ])),
+            chunk_size,
+            row: 0,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Create a new state for an aggregate function.
+    pub fn create_state(&self, name: &str) -> Result<ArrayRef> {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Module::with_temp_heap(|module| {
+            let create_state = module
+                .heap()
+                .access_owned_frozen_value(&aggregate.create_state);
+            let state = self
+                .call_user_fn(&module, create_state, &[])
+                .context("failed to call function \"create_state\"")?;
+            starlarkarrow::build_array(&aggregate.state_field, &[state])
+                .context("failed to build arrow array from state")
+        })
+    }
+
+    /// Call accumulate of an aggregate function.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    pub fn accumulate(
+        &self,
+        name: &str,
+        state: &dyn Array,
+        input: &RecordBatch,
+    ) -> Result<ArrayRef> {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        Module::with_temp_heap(|module| {
+            let heap = module.heap();
+            let accumulate = heap.access_owned_frozen_value(&aggregate.accumulate);
+            let mut state = starlarkarrow::get_value(heap, &aggregate.state_field, state, 0)?;
+            for i in 0..input.num_rows() {
+                let mut row = get_row(&module, input, i)?;
+                if self.skips_row(aggregate.mode, input, i)? {
+                    continue;
+                }
+                row.insert(0, state);
+                state = self
+                    .call_user_fn(&module, accumulate, &row)
+                    .context("failed to call function \"accumulate\"")?;
+            }
+            starlarkarrow::build_array(&aggregate.state_field, &[state])
+                .context("failed to build arrow array from state")
+        })
+    }
+
+    /// Merge states of an aggregate function into one state.
+    pub fn merge(&self, name: &str, states: &dyn Array) -> Result<ArrayRef> {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let merge = aggregate.merge.as_ref().context("merge not found")?;
+        Module::with_temp_heap(|module| {
+            let heap = module.heap();
+            let merge = heap.access_owned_frozen_value(merge);
+            let mut state = starlarkarrow::get_value(heap, &aggregate.state_field, states, 0)?;
+            for i in 1..states.len() {
+                let other = starlarkarrow::get_value(heap, &aggregate.state_field, states, i)?;
+                state = self
+                    .call_user_fn(&module, merge, &[state, other])
+                    .context("failed to call function \"merge\"")?;
+            }
+            starlarkarrow::build_array(&aggregate.state_field, &[state])
+                .context("failed to build arrow array from state")
+        })
+    }
+
+    /// Get the results of states of an aggregate function.
+    pub fn finish(&self, name: &str, states: &ArrayRef) -> Result<ArrayRef> {
+        let aggregate = self
+            .aggregates
+            .get(name)
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let Some(finish) = &aggregate.finish else {
+            return Ok(states.clone());
+        };
+        Module::with_temp_heap(|module| {
+            let heap = module.heap();
+            let finish = heap.access_owned_frozen_value(finish);
+            let mut results = Vec::with_capacity(states.len());
+            for i in 0..states.len() {
+                let state = starlarkarrow::get_value(heap, &aggregate.state_field, states, i)?;
+                let result = self
+                    .call_user_fn(&module, finish, &[state])
+                    .context("failed to call function \"finish\"")?;
+                results.push(result);
+            }
+            starlarkarrow::build_array(&aggregate.output_field, &results)
+                .context("failed to build arrow array from return values")
+        })
+    }
+}
+
+/// Get a function from a module.
+fn get_function(module: &FrozenModule, name: &str) -> Result<OwnedFrozenValue> {
+    module
+        .get(name)
+        .with_context(|| format!("function \"{name}\" not found"))
+}
+
+/// Get the values of a row as Starlark values.
+fn get_row<'v>(module: &Module<'v>, input: &RecordBatch, i: usize) -> Result<Vec<Value<'v>>> {
+    let mut row = Vec::with_capacity(input.num_columns());
+    for (column, field) in input.columns().iter().zip(input.schema().fields()) {
+        let value = starlarkarrow::get_value(module.heap(), field, column, i)
+            .context("failed to get starlark value from arrow array")?;
+        row.push(value);
+    }
+    Ok(row)
+}
+
+/// An iterator over the result of a table function.
+pub struct RecordBatchIter<'a> {
+    rt: &'a Runtime,
+    input: &'a RecordBatch,
+    function: &'a Function,
+    schema: SchemaRef,
+    chunk_size: usize,
+    // mutable states
+    /// Current row index.
+    row: usize,
+    /// Outputs of previous rows that have not been returned.
+    pending: VecDeque<(i32, ArrayRef)>,
+}
+
+impl RecordBatchIter<'_> {
+    /// Get the schema of the output.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Call the function on the next row, returning its output list as an array.
+    fn eval_next_row(&mut self) -> Result<Option<ArrayRef>> {
+        let i = self.row;
+        self.row += 1;
+        let function = self.function;
+        Module::with_temp_heap(|module| {
+            let row = get_row(&module, self.input, i)?;
+            if self.rt.skips_row(function.mode, self.input, i)? {
+                return Ok(None);
+            }
+            let handler = module.heap().access_owned_frozen_value(&function.function);
+            let result = self
+                .rt
+                .call_user_fn(&module, handler, &row)
+                .context("failed to call function")?;
+            if result.is_none() {
+                return Ok(None);
+            }
+            let list = ListRef::from_value(result)
+                .with_context(|| format!("expected list, got {}", result.get_type()))?;
+            let values: Vec<_> = list.iter().collect();
+            let array = starlarkarrow::build_array(&function.return_field, &values)
+                .context("failed to build arrow array from return values")?;
+            Ok(Some(array))
+        })
+    }
+
+    fn next(&mut self) -> Result<Option<RecordBatch>> {
+        let mut indexes = Int32Builder::with_capacity(self.chunk_size);
+        let mut arrays = vec![];
+        while indexes.len() < self.chunk_size {
+            let (row, array) = match self.pending.pop_front() {
+                Some(pending) => pending,
+                None if self.row < self.input.num_rows() => {
+                    let row = self.row as i32;
+                    match self.eval_next_row()? {
+                        Some(array) => (row, array),
+                        None => continue,
+                    }
+                }
+                None => break,
+            };
+            let len = array.len().min(self.chunk_size - indexes.len());
+            if len < array.len() {
+                self.pending
+                    .push_front((row, array.slice(len, array.len() - len)));
+            }
+            indexes.append_slice(&vec![row; len]);
+            arrays.push(array.slice(0, len));
+        }
+        if arrays.is_empty() {
+            return Ok(None);
+        }
+        let arrays: Vec<&dyn Array> = arrays.iter().map(|a| a.as_ref()).collect();
+        let array = arrow_select::concat::concat(&arrays)?;
+        let indexes: Int32Array = indexes.finish();
+        Ok(Some(RecordBatch::try_new(
+            self.schema.clone(),
+            vec![Arc::new(indexes), array],
+        )?))
+    }
+}
+
+impl Iterator for RecordBatchIter<'_> {
+    type Item = Result<RecordBatch>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next().transpose()
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion between Arrow arrays and Starlark values.
+
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use arrow_array::{array::*, builder::*, cast::AsArray, types::*};
+use arrow_buffer::{NullBuffer, OffsetBuffer};
+use arrow_schema::{DataType, Field};
+use starlark::values::dict::{AllocDict, DictRef};
+use starlark::values::float::UnpackFloat;
+use starlark::values::list::{AllocList, ListRef};
+use starlark::values::{Heap, UnpackValue, Value};
+
+/// Get the value at `i` of an array as a Starlark value.
+pub(crate) fn get_value<'v>(
+    heap: Heap<'v>,
+    field: &Field,
+    array: &dyn Array,
+    i: usize,
+) -> Result<Value<'v>> {
+    if array.is_null(i) {
+        return Ok(Value::new_none());
+    }
+    Ok(match array.data_type() {
+        DataType::Boolean => heap.alloc(array.as_boolean().value(i)),
+        DataType::Int8 => heap.alloc(array.as_primitive::<Int8Type>().value(i) as i32),
+        DataType::Int16 => heap.alloc(array.as_primitive::<Int16Type>().value(i) as i32),
+        DataType::Int32 => heap.alloc(array.as_primitive::<Int32Type>().value(i)),
+        DataType::Int64 => heap.alloc(array.as_primitive::<Int64Type>().value(i)),
+        DataType::UInt8 => heap.alloc(array.as_primitive::<UInt8Type>().value(i) as i32),
+        DataType::UInt16 => heap.alloc(array.as_primitive::<UInt16Type>().value(i) as i32),
+        DataType::UInt32 => heap.alloc(array.as_primitive::<UInt32Type>().value(i) as i64),
+        DataType::UInt64 => heap.alloc(array.as_primitive::<UInt64Type>().value(i)),
+        DataType::Float32 => heap.alloc(array.as_primitive::<Float32Type>().value(i) as f64),
+        DataType::Float64 => heap.alloc(array.as_primitive::<Float64Type>().value(i)),
+        DataType::Utf8 => heap.alloc(array.as_string::<i32>().value(i)),
+        DataType::LargeUtf8 => heap.alloc(array.as_string::<i64>().value(i)),
+        DataType::List(inner) => {
+            let list = array.as_list::<i32>().value(i);
+            heap.alloc(AllocList(list_values(heap, inner, &list)?))
+        }
+        DataType::LargeList(inner) => {
+            let list = array.as_list::<i64>().value(i);
+            heap.alloc(AllocList(list_values(heap, inner, &list)?))
+        }
+        DataType::Struct(fields) => {
+            let array = array.as_struct();
+            let mut entries = Vec::with_capacity(fields.len());
+            for (field, column) in fields.iter().zip(array.columns()) {
+                entries.push((field.name().as_str(), get_value(heap, field, column, i)?));
+            }
+            heap.alloc(AllocDict(entries))
+        }
+        _ => bail!("unsupported type: {}", field.data_type()),
+    })
+}
+
+fn list_values<'v>(heap: Heap<'v>, field: &Field, array: &dyn Array) -> Result<Vec<Value<'v>>> {
+    (0..array.len())
+        .map(|i| get_value(heap, field, array, i))
+        .collect()
+}
+
+/// Build an array from Starlark values. `None` is converted to null.
+pub(crate) fn build_array(field: &Field, values: &[Value<'_>]) -> Result<ArrayRef> {
+    macro_rules! build {
+        ($builder:ty, $unpack:expr) => {{
+            let mut builder = <$builder>::default();
+            for &value in values {
+                if value.is_none() {
+                    builder.append_null();
+                } else {
+                    builder.append_value($unpack(value)?);
+                }
+            }
+            Arc::new(builder.finish())
+        }};
+    }
+    macro_rules! build_int {
+        ($builder:ty) => {
+            build!($builder, |value| {
+                let int: i64 = unpack(value, "int")?;
+                int.try_into()
+                    .with_context(|| format!("integer out of range: {int}"))
+            })
+        };
+    }
+    Ok(match field.data_type() {
+        DataType::Null => Arc::new(NullArray::new(values.len())),
+        DataType::Boolean => build!(BooleanBuilder, |value| unpack::<bool>(value, "bool")),
+        DataType::Int8 => build_int!(Int8Builder),
+        DataType::Int16 => build_int!(Int16Builder),
+        DataType::Int32 => build_int!(Int32Builder),
+        DataType::Int64 => build_int!(Int64Builder),
+        DataType::UInt8 => build_int!(UInt8Builder),
+        DataType::UInt16 => build_int!(UInt16Builder),
+        DataType::UInt32 => build_int!(UInt32Builder),
+        DataType::UInt64 => build!(UInt64Builder, |value| unpack::<u64>(value, "int")),
+        DataType::Float32 => build!(Float32Builder, |value| {
+            unpack::<UnpackFloat>(value, "float").map(|f| f.0 as f32)
+        }),
+        DataType::Float64 => build!(Float64Builder, |value| {
+            unpack::<UnpackFloat>(value, "float").map(|f| f.0)
+        }),
+        DataType::Utf8 => build!(StringBuilder, |value| unpack::<&str>(value, "string")),
+        DataType::LargeUtf8 => {
+            build!(LargeStringBuilder, |value| unpack::<&str>(value, "string"))
+        }
+        DataType::List(inner) => {
+            let (offsets, child, nulls) = build_list(inner, values)?;
+            Arc::new(ListArray::try_new(
+                inner.clone(),
+                OffsetBuffer::new(offsets.into_iter().map(|o| o as i32).collect()),
+                child,
+                nulls,
+            )?)
+        }
+        DataType::LargeList(inner) => {
+            let (offsets, child, nulls) = build_list(inner, values)?;
+            Arc::new(LargeListArray::try_new(
+                inner.clone(),
+                OffsetBuffer::new(offsets.into_iter().map(|o| o as i64).collect()),
+                child,
+                nulls,
+            )?)
+        }
+        DataType::Struct(fields) => {
+            let mut dicts = Vec::with_capacity(values.len());
+            for &value in values {
+                if value.is_none() {
+                    dicts.push(None);
+                } else {
+                    let dict = DictRef::from_value(value)
+                        .with_context(|| format!("expected dict, got {}", value.get_type()))?;
+                    dicts.push(Some(dict));
+                }
+            }
+            let mut columns = Vec::with_capacity(fields.len());
+            for field in fields {
+                let values: Vec<_> = dicts
+                    .iter()
+                    .map(|dict| {
+                        dict.as_ref()
+                            .and_then(|d| d.get_str(field.name()))
+                            .unwrap_or_else(Value::new_none)
+                    })
+                    .collect();
+                columns.push(build_array(field, &values)?);
+            }
+            let nulls = NullBuffer::from_iter(dicts.iter().map(|d| d.is_some()));
+            Arc::new(StructArray::try_new(
+                fields.clone(),
+                columns,
+                Some(nulls).filter(|n| n.null_count() > 0),
+            )?)
+        }
+        _ => bail!("unsupported type: {}", field.data_type()),
+    })
+}
+
+/// Flatten lists into offsets, child array and validity.
+fn build_list(
+    field: &Field,
+    values: &[Value<'_>],
+) -> Result<(Vec<usize>, ArrayRef, Option<NullBuffer>)> {
+    let mut offsets = Vec::with_capacity(values.len() + 1);
+    offsets.push(0);
+    let mut elements = vec![];
+    let mut valid = Vec::with_capacity(values.len());
+    for &value in values {
+        if value.is_none() {
+            valid.push(false);
+        } else {
+            let list = ListRef::from_value(value)
+                .with_context(|| format!("expected list, got {}", value.get_type()))?;
+            elements.extend(list.iter());
+            valid.push(true);
+        }
+        offsets.push(elements.len());
+    }
+    let child = build_array(field, &elements)?;
+    let nulls = NullBuffer::from(valid);
+    Ok((offsets, child, Some(nulls).filter(|n| n.null_count() > 0)))
+}
+
+/// Unpack a Starlark value into a Rust value.
+fn unpack<'v, T: UnpackValue<'v>>(value: Value<'v>, expected: &str) -> Result<T> {
+    T::unpack_value(value)
+        .map_err(|e| e.into_anyhow())?
+        .with_context(|| format!("expected {expected}, got {}", value.get_type()))
+}
@@ -0,0 +1,424 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::{
+    Array, ArrayRef, Float64Array, Int32Array, ListArray, RecordBatch, StringArray, StructArray,
+};
+use arrow_cast::pretty::{pretty_format_batches, pretty_format_columns};
+use arrow_schema::{DataType, Field, Fields, Schema};
+use arrow_udf_starlark::{CallMode, Runtime};
+use expect_test::{expect, Expect};
+
+#[test]
+fn test_gcd() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def gcd(a, b):
+    for _ in range(a + b):
+        if b == 0:
+            return a
+        a, b = b, a % b
+    return a
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![
+        Field::new("x", DataType::Int32, true),
+        Field::new("y", DataType::Int32, true),
+    ]);
+    let arg0 = Int32Array::from(vec![Some(25), None]);
+    let arg1 = Int32Array::from(vec![Some(15), Some(1)]);
+    let input =
+        RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0), Arc::new(arg1)]).unwrap();
+
+    let output = runtime.call("gcd", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        |     |
+        +-----+"#]],
+    );
+
+    runtime.del_function("gcd").unwrap();
+    runtime.call("gcd", &input).unwrap_err();
+}
+
+#[test]
+fn test_called_on_null_input() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "describe",
+            DataType::Utf8,
+            CallMode::CalledOnNullInput,
+            r#"
+def describe(s):
+    if s == None:
+        return "null"
+    return s.upper() + "!"
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+    let arg0 = StringArray::from(vec![Some("hello"), None]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let output = runtime.call("describe", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +----------+
+        | describe |
+        +----------+
+        | HELLO!   |
+        | null     |
+        +----------+"#]],
+    );
+}
+
+#[test]
+fn test_nested_types() {
+    let mut runtime = Runtime::new().unwrap();
+    let point = DataType::Struct(Fields::from(vec![
+        Field::new("x", DataType::Float64, true),
+        Field::new("y", DataType::Float64, true),
+    ]));
+    runtime
+        .add_function(
+            "centroid",
+            point.clone(),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def centroid(xs):
+    total = 0.0
+    for x in xs:
+        total += x
+    return {"x": total / len(xs), "y": len(xs)}
+"#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "split",
+            DataType::new_list(DataType::Utf8, true),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def split(p):
+    return [str(p["x"]), None, str(p["y"])]
+"#,
+        )
+        .unwrap();
+
+    let list = ListArray::from_iter_primitive::<arrow_array::types::Float64Type, _, _>(vec![
+        Some(vec![Some(1.0), Some(2.0)]),
+        None,
+        Some(vec![Some(0.5)]),
+    ]);
+    let schema = Schema::new(vec![Field::new("xs", list.data_type().clone(), true)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(list)]).unwrap();
+
+    let output = runtime.call("centroid", &input).unwrap();
+    check(
+        std::slice::from_ref(&output),
+        expect![[r#"
+        +------------------+
+        | centroid         |
+        +------------------+
+        | {x: 1.5, y: 2.0} |
+        |                  |
+        | {x: 0.5, y: 1.0} |
+        +------------------+"#]],
+    );
+
+    let output = runtime.call("split", &output).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +--------------+
+        | split        |
+        +--------------+
+        | [1.5, , 2.0] |
+        |              |
+        | [0.5, , 1.0] |
+        +--------------+"#]],
+    );
+}
+
+#[test]
+fn test_struct_input() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "norm",
+            DataType::Float64,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def norm(p):
+    return p["x"] * p["x"] + p["y"] * p["y"]
+"#,
+        )
+        .unwrap();
+    let point = StructArray::from(vec![
+        (
+            Arc::new(Field::new("x", DataType::Float64, true)),
+            Arc::new(Float64Array::from(vec![3.0, 1.0])) as ArrayRef,
+        ),
+        (
+            Arc::new(Field::new("y", DataType::Float64, true)),
+            Arc::new(Float64Array::from(vec![4.0, 0.0])) as ArrayRef,
+        ),
+    ]);
+    let schema = Schema::new(vec![Field::new("p", point.data_type().clone(), true)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(point)]).unwrap();
+
+    let output = runtime.call("norm", &input).unwrap();
+    check_array(
+        output.columns(),
+        expect![[r#"
+        +-------+
+        | array |
+        +-------+
+        | 25.0  |
+        | 1.0   |
+        +-------+"#]],
+    );
+}
+
+#[test]
+fn test_table_function() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "series",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def series(n):
+    return list(range(n))
+"#,
+        )
+        .unwrap();
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(3), Some(0), Some(2)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    let outputs = runtime
+        .call_table_function("series", &input, 2)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(outputs.len(), 3);
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | 0      |
+        | 2   | 0      |
+        | 2   | 1      |
+        | 2   | 2      |
+        | 4   | 0      |
+        | 4   | 1      |
+        +-----+--------+"#]],
+    );
+}
+
+#[test]
+fn test_aggregate() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_aggregate(
+            "avg",
+            DataType::Struct(Fields::from(vec![
+                Field::new("sum", DataType::Int64, true),
+                Field::new("count", DataType::Int64, true),
+            ])),
+            DataType::Float64,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+def create_state():
+    return {"sum": 0, "count": 0}
+
+def accumulate(state, value):
+    return {"sum": state["sum"] + value, "count": state["count"] + 1}
+
+def merge(a, b):
+    return {"sum": a["sum"] + b["sum"], "count": a["count"] + b["count"]}
+
+def finish(state):
+    if state["count"] == 0:
+        return None
+    return state["sum"] / state["count"]
+"#,
+        )
+        .unwrap();
+
+    let state = runtime.create_state("avg").unwrap();
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None, Some(4)]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let state = runtime.accumulate("avg", &state, &input).unwrap();
+    let states = arrow_select::concat::concat(&[&state, &state]).unwrap();
+    let state = runtime.merge("avg", &states).unwrap();
+    check_array(
+        std::slice::from_ref(&state),
+        expect![[r#"
+        +---------------------+
+        | array               |
+        +---------------------+
+        | {sum: 10, count: 4} |
+        +---------------------+"#]],
+    );
+    let output = runtime.finish("avg", &state).unwrap();
+    check_array(
+        &[output],
+        expect![[r#"
+        +-------+
+        | array |
+        +-------+
+        | 2.5   |
+        +-------+"#]],
+    );
+}
+
+#[test]
+fn test_errors() {
+    let mut runtime = Runtime::new().unwrap();
+    let err = runtime
+        .add_function(
+            "f",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "def g(): pass",
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "function \"f\" not found");
+
+    // modules can not be loaded
+    let err = runtime
+        .add_function(
+            "f",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "load('os.star', 'system')\ndef f(): pass",
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "failed to parse code");
+
+    runtime
+        .add_function(
+            "f",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            "def f(x): return str(x)",
+        )
+        .unwrap();
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![1]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let err = runtime.call("f", &input).unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "failed to build arrow array from return values: expected int, got string"
+    );
+}
+
+#[test]
+fn test_limits() {
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "spin",
+            DataType::Int32,
+            CallMode::CalledOnNullInput,
+            r#"
+def spin(n):
+    for _ in range(n):
+        pass
+    return n
+"#,
+        )
+        .unwrap();
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![100, 1_000_000_000]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+
+    runtime.set_max_ticks(Some(1000));
+    let err = runtime.call("spin", &input).unwrap_err();
+    assert!(format!("{err:#}").contains("1000 ticks has been exceeded"));
+    let input_small = input.slice(0, 1);
+    runtime.call("spin", &input_small).unwrap();
+
+    runtime.set_max_ticks(None);
+    runtime.set_timeout(Some(Duration::from_millis(10)));
+    let err = runtime.call("spin", &input).unwrap_err();
+    assert!(format!("{err:#}").contains("cancelled"), "{err:#}");
+}
+
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(Runtime::new().unwrap());
+    runtime
+        .add_function(
+            "neg",
+            Field::new("neg", DataType::Int32, true),
+            arrow_udf_core::CallMode::ReturnNullOnNullInput,
+            "def neg(x): return -x",
+        )
+        .unwrap();
+    assert_eq!(runtime.list_functions(), ["neg"]);
+
+    let schema = Schema::new(vec![Field::new("x", DataType::Int32, true)]);
+    let arg0 = Int32Array::from(vec![Some(1), None]);
+    let input = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(arg0)]).unwrap();
+    let output = runtime.call("neg", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | neg |
+        +-----+
+        | -1  |
+        |     |
+        +-----+"#]],
+    );
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check(actual: &[RecordBatch], expect: Expect) {
+    expect.assert_eq(&pretty_format_batches(actual).unwrap().to_string());
+}
+
+/// Compare the actual output with the expected output.
+#[track_caller]
+fn check_array(actual: &[ArrayRef], expect: Expect) {
+    expect.assert_eq(&pretty_format_columns("array", actual).unwrap().to_string());
+}
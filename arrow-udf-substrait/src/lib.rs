@@ -0,0 +1,234 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![doc = include_str!("../README.md")]
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, IntervalUnit};
+use arrow_udf_core::{check_arguments, UdfRuntime};
+use prost::Message;
+
+pub mod proto;
+
+use self::proto::{
+    simple_extension_declaration::MappingType, ExtensionFunction, Plan, SimpleExtensionDeclaration,
+    SimpleExtensionUri,
+};
+
+/// The signature of a scalar function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSignature {
+    /// The name of function in the runtime.
+    pub name: String,
+    /// The types of arguments.
+    pub arg_types: Vec<DataType>,
+    /// The type of return value.
+    pub return_type: DataType,
+}
+
+impl FunctionSignature {
+    /// Create a new signature.
+    pub fn new(name: &str, arg_types: Vec<DataType>, return_type: DataType) -> Self {
+        Self {
+            name: name.to_string(),
+            arg_types,
+            return_type,
+        }
+    }
+
+    /// Returns the compound name used in Substrait, e.g. `gcd:i32_i32`.
+    pub fn compound_name(&self) -> Result<String> {
+        if self.arg_types.is_empty() {
+            return Ok(self.name.clone());
+        }
+        let args = self
+            .arg_types
+            .iter()
+            .map(type_short_name)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("{}:{}", self.name, args.join("_")))
+    }
+}
+
+/// Returns the short name of a type used in Substrait compound function names.
+fn type_short_name(ty: &DataType) -> Result<&'static str> {
+    Ok(match ty {
+        DataType::Boolean => "bool",
+        DataType::Int8 => "i8",
+        DataType::Int16 => "i16",
+        DataType::Int32 => "i32",
+        DataType::Int64 => "i64",
+        DataType::Float32 => "fp32",
+        DataType::Float64 => "fp64",
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => "str",
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => "vbin",
+        DataType::FixedSizeBinary(_) => "fbin",
+        DataType::Date32 | DataType::Date64 => "date",
+        DataType::Time32(_) | DataType::Time64(_) => "time",
+        DataType::Timestamp(_, None) => "ts",
+        DataType::Timestamp(_, Some(_)) => "tstz",
+        DataType::Interval(IntervalUnit::YearMonth) => "iyear",
+        DataType::Interval(IntervalUnit::DayTime) => "iday",
+        DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => "dec",
+        DataType::List(_) | DataType::LargeList(_) => "list",
+        DataType::Struct(_) => "struct",
+        DataType::Map(_, _) => "map",
+        _ => bail!("type not supported by Substrait: {ty}"),
+    })
+}
+
+/// A set of functions declared under one Substrait extension URI.
+#[derive(Debug, Clone)]
+pub struct ExtensionRegistry {
+    uri: String,
+    functions: Vec<FunctionSignature>,
+}
+
+impl ExtensionRegistry {
+    /// Create an empty registry for the extension URI.
+    pub fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            functions: vec![],
+        }
+    }
+
+    /// Add a function to the registry.
+    pub fn with_function(mut self, signature: FunctionSignature) -> Self {
+        self.functions.push(signature);
+        self
+    }
+
+    /// Returns the extension URI.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Build the extension URI and function declarations to be included in a plan.
+    ///
+    /// Functions are anchored sequentially from `first_function_anchor` in the order they were added.
+    pub fn declarations(
+        &self,
+        uri_anchor: u32,
+        first_function_anchor: u32,
+    ) -> Result<(SimpleExtensionUri, Vec<SimpleExtensionDeclaration>)> {
+        let uri = SimpleExtensionUri {
+            extension_uri_anchor: uri_anchor,
+            uri: self.uri.clone(),
+        };
+        let mut declarations = Vec::with_capacity(self.functions.len());
+        for (i, function) in self.functions.iter().enumerate() {
+            declarations.push(SimpleExtensionDeclaration {
+                mapping_type: Some(MappingType::ExtensionFunction(ExtensionFunction {
+                    extension_uri_reference: uri_anchor,
+                    function_anchor: first_function_anchor + i as u32,
+                    name: function.compound_name()?,
+                })),
+            });
+        }
+        Ok((uri, declarations))
+    }
+
+    /// Resolve the functions declared in a serialized Substrait plan.
+    pub fn resolve_plan(&self, plan: &[u8]) -> Result<ResolvedFunctions> {
+        let plan = Plan::decode(plan).context("failed to decode Substrait plan")?;
+        self.resolve(&plan.extension_uris, &plan.extensions)
+    }
+
+    /// Resolve the function declarations under this extension URI.
+    ///
+    /// Declarations of other extensions are ignored.
+    pub fn resolve(
+        &self,
+        uris: &[SimpleExtensionUri],
+        declarations: &[SimpleExtensionDeclaration],
+    ) -> Result<ResolvedFunctions> {
+        let anchors: Vec<u32> = uris
+            .iter()
+            .filter(|u| u.uri == self.uri)
+            .map(|u| u.extension_uri_anchor)
+            .collect();
+        let mut functions = BTreeMap::new();
+        for declaration in declarations {
+            let Some(MappingType::ExtensionFunction(function)) = &declaration.mapping_type else {
+                continue;
+            };
+            if !anchors.contains(&function.extension_uri_reference) {
+                continue;
+            }
+            let signature = self
+                .find(&function.name)?
+                .with_context(|| format!("function not found: {}", function.name))?;
+            functions.insert(function.function_anchor, signature.clone());
+        }
+        Ok(ResolvedFunctions { functions })
+    }
+
+    /// Find a function by compound name, or by simple name if it is not overloaded.
+    fn find(&self, name: &str) -> Result<Option<&FunctionSignature>> {
+        if !name.contains(':') {
+            let mut candidates = self.functions.iter().filter(|f| f.name == name);
+            let first = candidates.next();
+            if candidates.next().is_some() {
+                bail!("ambiguous function name: {name}");
+            }
+            return Ok(first);
+        }
+        for function in &self.functions {
+            if function.compound_name()? == name {
+                return Ok(Some(function));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Function anchors in a plan resolved to functions in the runtime.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedFunctions {
+    functions: BTreeMap<u32, FunctionSignature>,
+}
+
+impl ResolvedFunctions {
+    /// Get the signature of a function by its anchor.
+    pub fn get(&self, anchor: u32) -> Option<&FunctionSignature> {
+        self.functions.get(&anchor)
+    }
+
+    /// Returns an iterator over the anchors and signatures.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &FunctionSignature)> {
+        self.functions.iter().map(|(k, v)| (*k, v))
+    }
+
+    /// Call the function referenced by the anchor in the runtime.
+    pub fn call(
+        &self,
+        runtime: &dyn UdfRuntime,
+        anchor: u32,
+        input: &RecordBatch,
+    ) -> Result<RecordBatch> {
+        let signature = self
+            .get(anchor)
+            .with_context(|| format!("function anchor not found: {anchor}"))?;
+        check_arguments(
+            &signature.name,
+            &signature.arg_types,
+            input.schema().fields(),
+        )?;
+        Ok(runtime.call(&signature.name, input)?)
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The subset of Substrait protobuf messages for extension declarations.
+//!
+//! Field numbers follow `substrait/plan.proto` and `substrait/extensions/extensions.proto`.
+//! Other fields of a plan, such as relations, are skipped when decoding.
+
+/// A Substrait plan with only the extension declarations.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Plan {
+    #[prost(message, repeated, tag = "1")]
+    pub extension_uris: Vec<SimpleExtensionUri>,
+    #[prost(message, repeated, tag = "2")]
+    pub extensions: Vec<SimpleExtensionDeclaration>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SimpleExtensionUri {
+    /// A surrogate key used in the context of a single plan to reference the URI.
+    #[prost(uint32, tag = "1")]
+    pub extension_uri_anchor: u32,
+    #[prost(string, tag = "2")]
+    pub uri: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SimpleExtensionDeclaration {
+    #[prost(oneof = "simple_extension_declaration::MappingType", tags = "1, 2, 3")]
+    pub mapping_type: Option<simple_extension_declaration::MappingType>,
+}
+
+pub mod simple_extension_declaration {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum MappingType {
+        #[prost(message, tag = "1")]
+        ExtensionType(super::ExtensionType),
+        #[prost(message, tag = "2")]
+        ExtensionTypeVariation(super::ExtensionTypeVariation),
+        #[prost(message, tag = "3")]
+        ExtensionFunction(super::ExtensionFunction),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExtensionType {
+    #[prost(uint32, tag = "1")]
+    pub extension_uri_reference: u32,
+    #[prost(uint32, tag = "2")]
+    pub type_anchor: u32,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExtensionTypeVariation {
+    #[prost(uint32, tag = "1")]
+    pub extension_uri_reference: u32,
+    #[prost(uint32, tag = "2")]
+    pub type_variation_anchor: u32,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExtensionFunction {
+    #[prost(uint32, tag = "1")]
+    pub extension_uri_reference: u32,
+    /// A surrogate key used in the context of a single plan to reference the function.
+    #[prost(uint32, tag = "2")]
+    pub function_anchor: u32,
+    /// The compound name of the function, e.g. `gcd:i32_i32`.
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
@@ -0,0 +1,167 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::{Array, Int32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use arrow_udf_js::{CallMode, Runtime};
+use arrow_udf_substrait::proto::*;
+use arrow_udf_substrait::*;
+use prost::Message;
+
+const URI: &str = "https://example.com/functions.yaml";
+
+fn registry() -> ExtensionRegistry {
+    ExtensionRegistry::new(URI)
+        .with_function(FunctionSignature::new(
+            "gcd",
+            vec![DataType::Int32, DataType::Int32],
+            DataType::Int32,
+        ))
+        .with_function(FunctionSignature::new(
+            "gcd",
+            vec![DataType::Int64, DataType::Int64],
+            DataType::Int64,
+        ))
+        .with_function(FunctionSignature::new(
+            "length",
+            vec![DataType::Utf8],
+            DataType::Int32,
+        ))
+}
+
+#[test]
+fn test_declarations() {
+    let (uri, declarations) = registry().declarations(1, 10).unwrap();
+    assert_eq!(uri.uri, URI);
+    let names: Vec<_> = declarations
+        .iter()
+        .map(|d| match &d.mapping_type {
+            Some(simple_extension_declaration::MappingType::ExtensionFunction(f)) => {
+                (f.function_anchor, f.name.as_str())
+            }
+            _ => panic!("expect extension function"),
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [(10, "gcd:i32_i32"), (11, "gcd:i64_i64"), (12, "length:str")]
+    );
+}
+
+#[test]
+fn test_resolve_plan() {
+    // a plan produced by another system, which also uses standard functions
+    let plan = Plan {
+        extension_uris: vec![
+            SimpleExtensionUri {
+                extension_uri_anchor: 1,
+                uri: "https://github.com/substrait-io/substrait/blob/main/extensions/functions_arithmetic.yaml".into(),
+            },
+            SimpleExtensionUri {
+                extension_uri_anchor: 2,
+                uri: URI.into(),
+            },
+        ],
+        extensions: vec![
+            function(1, 1, "add:i32_i32"),
+            function(2, 2, "gcd:i32_i32"),
+            function(2, 3, "length"),
+        ],
+    };
+    let mut bytes = plan.encode_to_vec();
+    // append a `relations` field, which should be skipped
+    bytes.extend_from_slice(&[0x1a, 0x02, 0x08, 0x01]);
+
+    let resolved = registry().resolve_plan(&bytes).unwrap();
+    assert!(resolved.get(1).is_none());
+    assert_eq!(
+        resolved.get(2).unwrap().arg_types,
+        [DataType::Int32, DataType::Int32]
+    );
+    assert_eq!(resolved.get(3).unwrap().name, "length");
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .add_function(
+            "gcd",
+            DataType::Int32,
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Int32, true),
+            Field::new("y", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![25, 12])),
+            Arc::new(Int32Array::from(vec![15, 9])),
+        ],
+    )
+    .unwrap();
+    let output = resolved.call(&runtime, 2, &input).unwrap();
+    let output = output.column(0).as_any().downcast_ref::<Int32Array>();
+    assert_eq!(output.unwrap(), &Int32Array::from(vec![5, 3]));
+
+    // the length function expects a string
+    resolved.call(&runtime, 3, &input).unwrap_err();
+    resolved.call(&runtime, 4, &input).unwrap_err();
+}
+
+#[test]
+fn test_resolve_error() {
+    let uris = [SimpleExtensionUri {
+        extension_uri_anchor: 1,
+        uri: URI.into(),
+    }];
+    // overloaded function must be referenced by compound name
+    let err = registry()
+        .resolve(&uris, &[function(1, 1, "gcd")])
+        .unwrap_err();
+    assert_eq!(err.to_string(), "ambiguous function name: gcd");
+
+    let err = registry()
+        .resolve(&uris, &[function(1, 1, "gcd:fp64_fp64")])
+        .unwrap_err();
+    assert_eq!(err.to_string(), "function not found: gcd:fp64_fp64");
+
+    let err = FunctionSignature::new("f", vec![DataType::UInt8], DataType::UInt8)
+        .compound_name()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "type not supported by Substrait: UInt8");
+}
+
+fn function(uri: u32, anchor: u32, name: &str) -> SimpleExtensionDeclaration {
+    SimpleExtensionDeclaration {
+        mapping_type: Some(
+            simple_extension_declaration::MappingType::ExtensionFunction(ExtensionFunction {
+                extension_uri_reference: uri,
+                function_anchor: anchor,
+                name: name.into(),
+            }),
+        ),
+    }
+}
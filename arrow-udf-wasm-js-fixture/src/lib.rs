@@ -0,0 +1,365 @@
+// Copyright 2024 RisingWave Labs
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A JavaScript engine for the WASM runtime of arrow-udf, used in its tests.
+//!
+//! It implements the `arrowudf_js_*` exports described in the README of `arrow-udf-wasm`
+//! by evaluating functions in QuickJS.
+//! The standard exports are provided by `arrow_udf::ffi`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::builder::{BooleanBuilder, Float64Builder, Int32Builder, StringBuilder};
+use arrow_array::cast::AsArray;
+use arrow_array::types::*;
+use arrow_array::{Array, ArrayRef, Int32Array, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, FieldRef, Schema};
+use arrow_udf::ffi::{scalar_wrapper, table_wrapper, CSlice};
+use rquickjs::function::{Args, This};
+use rquickjs::{Coerced, Context, Ctx, FromJs, Module, Object, Persistent, Value};
+
+/// The schema metadata key of the function name in inputs.
+const FUNCTION_KEY: &str = "arrowudf.function";
+
+struct Function {
+    js: Persistent<rquickjs::Function<'static>>,
+    return_field: FieldRef,
+    /// Whether rows with null arguments return null without calling the function.
+    strict: bool,
+    /// Whether the function is a generator, which is called as a table function.
+    generator: bool,
+}
+
+/// The functions and the context where they are defined.
+struct Engine {
+    // functions are dropped before the context
+    functions: HashMap<String, Function>,
+    context: Context,
+}
+
+impl Engine {
+    fn new() -> Self {
+        let runtime = rquickjs::Runtime::new().expect("failed to create QuickJS runtime");
+        let context = Context::full(&runtime).expect("failed to create QuickJS context");
+        Self {
+            functions: HashMap::new(),
+            context,
+        }
+    }
+}
+
+thread_local! {
+    static ENGINE: RefCell<Engine> = RefCell::new(Engine::new());
+}
+
+/// Evaluate the code of a function.
+///
+/// # Safety
+///
+/// See [`scalar_wrapper`].
+#[no_mangle]
+pub unsafe extern "C" fn arrowudf_js_add_function(
+    ptr: *const u8,
+    len: usize,
+    out: *mut CSlice,
+) -> i32 {
+    scalar_wrapper(add_function, ptr, len, out)
+}
+
+/// Call a scalar function.
+///
+/// # Safety
+///
+/// See [`scalar_wrapper`].
+#[no_mangle]
+pub unsafe extern "C" fn arrowudf_js_call(ptr: *const u8, len: usize, out: *mut CSlice) -> i32 {
+    scalar_wrapper(call, ptr, len, out)
+}
+
+/// Call a table function.
+///
+/// # Safety
+///
+/// See [`table_wrapper`].
+#[no_mangle]
+pub unsafe extern "C" fn arrowudf_js_call_table(
+    ptr: *const u8,
+    len: usize,
+    out: *mut CSlice,
+) -> i32 {
+    table_wrapper(call_table, ptr, len, out)
+}
+
+fn add_function(input: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let metadata = input.schema().metadata().clone();
+    let get = |key: &str| {
+        metadata
+            .get(key)
+            .ok_or_else(|| ArrowError::InvalidArgumentError(format!("missing {key}")))
+    };
+    let name = get("name")?;
+    let code = get("code")?;
+    let strict = match get("mode")?.as_str() {
+        "CalledOnNullInput" => false,
+        "ReturnNullOnNullInput" => true,
+        mode => {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "invalid mode: {mode}"
+            )))
+        }
+    };
+    ENGINE.with_borrow_mut(|engine| {
+        let (js, generator) = engine.context.with(|ctx| {
+            let (module, promise) = Module::declare(ctx.clone(), name.as_str(), code.as_str())
+                .and_then(|module| module.eval())
+                .map_err(|e| js_error(&ctx, e))?;
+            promise.finish::<()>().map_err(|e| js_error(&ctx, e))?;
+            let function: rquickjs::Function = module.get(name.as_str()).map_err(|_| {
+                ArrowError::ComputeError(format!("function {name} is not exported"))
+            })?;
+            let constructor: Object = function.get("constructor").map_err(|e| js_error(&ctx, e))?;
+            let kind: String = constructor.get("name").map_err(|e| js_error(&ctx, e))?;
+            Ok::<_, ArrowError>((
+                Persistent::save(&ctx, function),
+                kind == "GeneratorFunction",
+            ))
+        })?;
+        let function = Function {
+            js,
+            return_field: input.schema().field(0).clone().into(),
+            strict,
+            generator,
+        };
+        engine.functions.insert(name.clone(), function);
+        Ok::<_, ArrowError>(())
+    })?;
+    Ok(RecordBatch::new_empty(Arc::new(Schema::empty())))
+}
+
+fn call(input: &RecordBatch) -> Result<RecordBatch, ArrowError> {
+    with_function(input, |ctx, name, function| {
+        if function.generator {
+            return Err(ArrowError::ComputeError(format!(
+                "{name} is a generator, call it as a table function"
+            )));
+        }
+        let js = function
+            .js
+            .clone()
+            .restore(ctx)
+            .map_err(|e| js_error(ctx, e))?;
+        let mut values = Vec::with_capacity(input.num_rows());
+        for row in 0..input.num_rows() {
+            values.push(match arguments(ctx, function, input, row)? {
+                Some(args) => Some(call_function(ctx, &js, args)?),
+                None => None,
+            });
+        }
+        let array = build_array(function.return_field.data_type(), &values)?;
+        let schema = Schema::new(vec![function.return_field.clone()]);
+        RecordBatch::try_new(Arc::new(schema), vec![array])
+    })
+}
+
+fn call_table(
+    input: &RecordBatch,
+) -> Result<Box<dyn Iterator<Item = RecordBatch> + '_>, ArrowError> {
+    let batch = with_function(input, |ctx, name, function| {
+        if !function.generator {
+            return Err(ArrowError::ComputeError(format!(
+                "{name} is not a generator, call it as a scalar function"
+            )));
+        }
+        let js = function
+            .js
+            .clone()
+            .restore(ctx)
+            .map_err(|e| js_error(ctx, e))?;
+        let mut rows = vec![];
+        let mut values = vec![];
+        for row in 0..input.num_rows() {
+            let Some(args) = arguments(ctx, function, input, row)? else {
+                continue;
+            };
+            let generator: Object = call_function(ctx, &js, args)?;
+            let next: rquickjs::Function = generator.get("next").map_err(|e| js_error(ctx, e))?;
+            loop {
+                let result: Object =
+                    (next.call((This(generator.clone()),))).map_err(|e| js_error(ctx, e))?;
+                if result.get("done").map_err(|e| js_error(ctx, e))? {
+                    break;
+                }
+                rows.push(row as i32);
+                values.push(Some(result.get("value").map_err(|e| js_error(ctx, e))?));
+            }
+        }
+        let array = build_array(function.return_field.data_type(), &values)?;
+        let schema = Schema::new(vec![
+            Arc::new(Field::new("row", DataType::Int32, true)),
+            function.return_field.clone(),
+        ]);
+        let rows: ArrayRef = Arc::new(Int32Array::from(rows));
+        RecordBatch::try_new(Arc::new(schema), vec![rows, array])
+    })?;
+    Ok(Box::new(std::iter::once(batch)))
+}
+
+/// Run a closure with the function named in the schema metadata of the input.
+fn with_function<T>(
+    input: &RecordBatch,
+    f: impl for<'js> FnOnce(&Ctx<'js>, &str, &Function) -> Result<T, ArrowError>,
+) -> Result<T, ArrowError> {
+    let schema = input.schema();
+    let name = (schema.metadata().get(FUNCTION_KEY))
+        .ok_or_else(|| ArrowError::InvalidArgumentError(format!("missing {FUNCTION_KEY}")))?;
+    ENGINE.with_borrow(|engine| {
+        let function = (engine.functions.get(name))
+            .ok_or_else(|| ArrowError::ComputeError(format!("function not found: {name}")))?;
+        engine.context.with(|ctx| f(&ctx, name, function))
+    })
+}
+
+/// Call a function with the arguments of a row.
+fn call_function<'js, T: FromJs<'js>>(
+    ctx: &Ctx<'js>,
+    function: &rquickjs::Function<'js>,
+    args: Vec<Value<'js>>,
+) -> Result<T, ArrowError> {
+    let mut call_args = Args::new(ctx.clone(), args.len());
+    (call_args.push_args(args))
+        .and_then(|_| function.call_arg(call_args))
+        .map_err(|e| js_error(ctx, e))
+}
+
+/// Returns the arguments of a row, or `None` if the row returns null without calling the function.
+fn arguments<'js>(
+    ctx: &Ctx<'js>,
+    function: &Function,
+    input: &RecordBatch,
+    row: usize,
+) -> Result<Option<Vec<Value<'js>>>, ArrowError> {
+    let mut args = Vec::with_capacity(input.num_columns());
+    for column in input.columns() {
+        if column.is_null(row) {
+            if function.strict {
+                return Ok(None);
+            }
+            args.push(Value::new_null(ctx.clone()));
+            continue;
+        }
+        args.push(match column.data_type() {
+            DataType::Boolean => Value::new_bool(ctx.clone(), column.as_boolean().value(row)),
+            DataType::Int32 => {
+                Value::new_int(ctx.clone(), column.as_primitive::<Int32Type>().value(row))
+            }
+            DataType::Int64 => Value::new_number(
+                ctx.clone(),
+                column.as_primitive::<Int64Type>().value(row) as f64,
+            ),
+            DataType::Float64 => {
+                Value::new_float(ctx.clone(), column.as_primitive::<Float64Type>().value(row))
+            }
+            DataType::Utf8 => {
+                rquickjs::String::from_str(ctx.clone(), column.as_string::<i32>().value(row))
+                    .map_err(|e| js_error(ctx, e))?
+                    .into_value()
+            }
+            t => return Err(unsupported(t)),
+        });
+    }
+    Ok(Some(args))
+}
+
+/// Build an array from the values returned by a function, where `None` is a null row.
+fn build_array(data_type: &DataType, values: &[Option<Value>]) -> Result<ArrayRef, ArrowError> {
+    let values = values
+        .iter()
+        .map(|v| v.as_ref().filter(|v| !v.is_null() && !v.is_undefined()));
+    Ok(match data_type {
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Some(v) => {
+                        builder.append_value(v.as_bool().ok_or_else(|| mismatch(data_type, v))?)
+                    }
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int32 => {
+            let mut builder = Int32Builder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Some(v) => {
+                        let n = v.as_number().ok_or_else(|| mismatch(data_type, v))?;
+                        builder.append_value(n as i32)
+                    }
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Some(v) => {
+                        builder.append_value(v.as_number().ok_or_else(|| mismatch(data_type, v))?)
+                    }
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::with_capacity(values.len(), 1024);
+            for value in values {
+                match value {
+                    Some(v) => {
+                        let Coerced(s) =
+                            (v.get::<Coerced<String>>()).map_err(|e| js_error(v.ctx(), e))?;
+                        builder.append_value(s)
+                    }
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        t => return Err(unsupported(t)),
+    })
+}
+
+/// Convert an error of QuickJS, with the exception converted to a string if one is thrown.
+fn js_error(ctx: &Ctx, e: rquickjs::Error) -> ArrowError {
+    let message = match e {
+        rquickjs::Error::Exception => match ctx.catch().get::<Coerced<String>>() {
+            Ok(Coerced(message)) => message,
+            Err(e) => e.to_string(),
+        },
+        e => e.to_string(),
+    };
+    ArrowError::ComputeError(message)
+}
+
+fn unsupported(data_type: &DataType) -> ArrowError {
+    ArrowError::NotYetImplemented(format!("unsupported type: {data_type}"))
+}
+
+fn mismatch(data_type: &DataType, value: &Value) -> ArrowError {
+    ArrowError::CastError(format!("can not convert {value:?} to {data_type}"))
+}
@@ -14,12 +14,19 @@
 
 #![doc = include_str!("../README.md")]
 
-use anyhow::{anyhow, bail, ensure, Context};
+use anyhow::{anyhow, ensure, Context};
 use arrow_array::RecordBatch;
+use arrow_schema::{Field, Schema};
+use arrow_udf_core::{
+    BatchMode, CancelToken, Capabilities, Error, FunctionKind, LogLevel, ResourceLimit, UdfLogSink,
+};
+pub use arrow_udf_core::{
+    CallMode, FunctionProperties, ParallelSafety, StrictArguments, Volatility,
+};
 use ram_file::{RamFile, RamFileRef};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use wasi_common::{sync::WasiCtxBuilder, WasiCtx};
 use wasmtime::*;
 
@@ -42,8 +49,17 @@ pub struct Runtime {
     instances: Mutex<Vec<Instance>>,
     /// ABI version. (major, minor)
     abi_version: (u8, u8),
+    /// Whether the binary is a JavaScript engine that accepts functions at runtime.
+    js_engine: bool,
+    /// Encoded definitions of JavaScript functions, replayed on each new instance.
+    js_functions: Vec<(String, Vec<u8>)>,
+    /// The sink of logs, shared with instances.
+    log_sink: LogSinkRef,
 }
 
+/// A log sink shared by the runtime and its instances.
+type LogSinkRef = Arc<Mutex<Option<Arc<dyn UdfLogSink>>>>;
+
 /// Configurations.
 #[derive(Debug, Default, PartialEq, Eq)]
 #[non_exhaustive]
@@ -52,6 +68,12 @@ pub struct Config {
     pub memory_size_limit: Option<usize>,
     /// File size limit in bytes.
     pub file_size_limit: Option<usize>,
+    /// Fuel limit of each call.
+    ///
+    /// Every WebAssembly instruction consumes some fuel,
+    /// and a call fails once the fuel is exhausted.
+    /// For table functions, the limit applies to the whole iteration.
+    pub fuel: Option<u64>,
 }
 
 struct Instance {
@@ -63,14 +85,36 @@ struct Instance {
     record_batch_iterator_next: TypedFunc<(u32, u32), ()>,
     // extern "C" fn(iter: *mut RecordBatchIter)
     record_batch_iterator_drop: TypedFunc<u32, ()>,
-    // extern "C" fn(ptr: *const u8, len: usize, out: *mut CSlice) -> i32
-    functions: HashMap<String, TypedFunc<(u32, u32, u32), i32>>,
+    functions: HashMap<String, UdfFunc>,
+    // entry points of a JavaScript engine, with the same signature as functions
+    js: Option<JsEntries>,
     memory: Memory,
     store: Store<(WasiCtx, StoreLimits)>,
     stdout: RamFileRef,
     stderr: RamFileRef,
+    log_sink: LogSinkRef,
+    /// The function being called, attached to the logs.
+    function: Arc<Mutex<String>>,
+    fuel: Option<u64>,
+    /// Cancel token of the current call, checked when the epoch of the engine is incremented.
+    cancel: Arc<Mutex<Option<CancelToken>>>,
+}
+
+/// extern "C" fn(ptr: *const u8, len: usize, out: *mut CSlice) -> i32
+type UdfFunc = TypedFunc<(u32, u32, u32), i32>;
+
+struct JsEntries {
+    add_function: UdfFunc,
+    call: UdfFunc,
+    call_table: UdfFunc,
 }
 
+/// The schema metadata key of the function name in inputs of a JavaScript engine.
+const JS_FUNCTION_KEY: &str = "arrowudf.function";
+
+/// The prefix of the entry points of a JavaScript engine, which are not functions themselves.
+const JS_ENTRY_PREFIX: &str = "arrowudf_js_";
+
 impl Debug for Runtime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Runtime")
@@ -84,15 +128,31 @@ impl Debug for Runtime {
 
 impl Runtime {
     /// Create a new UDF runtime from a WASM binary.
-    pub fn new(binary: &[u8]) -> Result<Self> {
+    pub fn new(binary: &[u8]) -> arrow_udf_core::Result<Self> {
         Self::with_config(binary, Config::default())
     }
 
     /// Create a new UDF runtime from a WASM binary with configuration.
-    pub fn with_config(binary: &[u8], config: Config) -> Result<Self> {
+    pub fn with_config(binary: &[u8], config: Config) -> arrow_udf_core::Result<Self> {
         // use a global engine by default
-        static ENGINE: once_cell::sync::Lazy<Engine> = once_cell::sync::Lazy::new(Engine::default);
-        Self::with_config_engine(binary, config, &ENGINE)
+        // epoch interruption is enabled to cancel running calls
+        static ENGINE: once_cell::sync::Lazy<Engine> = once_cell::sync::Lazy::new(|| {
+            let mut config = wasmtime::Config::new();
+            config.epoch_interruption(true);
+            Engine::new(&config).expect("failed to create engine")
+        });
+        // fuel metering must be enabled on the engine
+        static FUEL_ENGINE: once_cell::sync::Lazy<Engine> = once_cell::sync::Lazy::new(|| {
+            let mut config = wasmtime::Config::new();
+            config.consume_fuel(true);
+            config.epoch_interruption(true);
+            Engine::new(&config).expect("failed to create engine")
+        });
+        let engine = match config.fuel {
+            Some(_) => &FUEL_ENGINE,
+            None => &ENGINE,
+        };
+        Ok(Self::with_config_engine(binary, config, engine)?)
     }
 
     /// Create a new UDF runtime from a WASM binary with a customized engine.
@@ -111,6 +171,9 @@ impl Runtime {
         let mut functions = HashSet::new();
         let mut types = HashMap::new();
         for export in module.exports() {
+            if export.name().starts_with(JS_ENTRY_PREFIX) {
+                continue;
+            }
             if let Some(encoded) = export.name().strip_prefix("arrowudf_") {
                 let name = base64_decode(encoded).context("invalid symbol")?;
                 functions.insert(name);
@@ -121,6 +184,10 @@ impl Runtime {
             }
         }
 
+        let js_engine = module
+            .exports()
+            .any(|e| e.name() == "arrowudf_js_add_function");
+
         Ok(Self {
             module,
             config,
@@ -128,9 +195,96 @@ impl Runtime {
             types,
             instances: Mutex::new(vec![]),
             abi_version: (major, minor),
+            js_engine,
+            js_functions: vec![],
+            log_sink: Default::default(),
         })
     }
 
+    /// Returns true if the binary is a JavaScript engine.
+    ///
+    /// Functions of a JavaScript engine are added at runtime by [`Runtime::add_function`].
+    pub fn is_js_engine(&self) -> bool {
+        self.js_engine
+    }
+
+    /// Add a JavaScript function to the engine.
+    ///
+    /// The code is evaluated in every instance of the engine,
+    /// so that the function gets the memory isolation and fuel metering of WebAssembly.
+    ///
+    /// The engine only receives `CalledOnNullInput` or `ReturnNullOnNullInput`,
+    /// so [`CallMode::ReturnNullOnNullArguments`] is rejected.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name))]
+    pub fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        if !self.js_engine {
+            return Err(anyhow!(
+                "failed to add function {name}: functions of WASM runtime are defined by the binary"
+            )
+            .into());
+        }
+        let mode = match mode {
+            CallMode::CalledOnNullInput => "CalledOnNullInput",
+            CallMode::ReturnNullOnNullInput => "ReturnNullOnNullInput",
+            CallMode::ReturnNullOnNullArguments(_) => return Err(anyhow!(
+                "failed to add function {name}: strict arguments are not supported by JavaScript functions in WASM runtime"
+            )
+            .into()),
+        };
+        let metadata = [
+            ("name".to_string(), name.to_string()),
+            ("mode".to_string(), mode.to_string()),
+            ("code".to_string(), code.to_string()),
+        ];
+        let schema = Schema::new(vec![return_field]).with_metadata(metadata.into());
+        let definition = encode_record_batch(&RecordBatch::new_empty(Arc::new(schema)))?;
+
+        // replace the definition in place, keeping the old one to restore on failure
+        let old = match self.js_functions.iter_mut().find(|(n, _)| n == name) {
+            Some((_, old)) => Some(std::mem::replace(old, definition)),
+            None => {
+                self.js_functions.push((name.to_string(), definition));
+                None
+            }
+        };
+        let instance = match Instance::new(self) {
+            Ok(instance) => instance,
+            Err(e) => {
+                match old {
+                    Some(old) => {
+                        let entry = self.js_functions.iter_mut().find(|(n, _)| n == name);
+                        entry.unwrap().1 = old;
+                    }
+                    None => _ = self.js_functions.pop(),
+                }
+                return Err(e.context(format!("failed to add function {name}")).into());
+            }
+        };
+        // existing instances do not have the function, replace them
+        let instances = self.instances.get_mut().unwrap();
+        instances.clear();
+        instances.push(instance);
+        self.functions.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Set the sink of messages logged by functions.
+    ///
+    /// Functions log messages through the `arrowudf.log` import, see [`arrow_udf::ffi::log`].
+    /// Once set, lines written to the standard output are also logged as [`LogLevel::Info`],
+    /// and lines written to the standard error as [`LogLevel::Warn`], after each call.
+    ///
+    /// [`arrow_udf::ffi::log`]: https://docs.rs/arrow-udf/latest/arrow_udf/ffi/fn.log.html
+    pub fn set_log_sink(&mut self, sink: Option<Arc<dyn UdfLogSink>>) {
+        *self.log_sink.lock().unwrap() = sink;
+    }
+
     /// Return available functions.
     pub fn functions(&self) -> impl Iterator<Item = &str> {
         self.functions.iter().map(|s| s.as_str())
@@ -146,6 +300,17 @@ impl Runtime {
         self.abi_version
     }
 
+    /// Return the total size of linear memories of instances in bytes.
+    ///
+    /// Instances in the middle of a call are not counted.
+    pub fn memory_size(&self) -> usize {
+        let instances = self.instances.lock().unwrap();
+        instances
+            .iter()
+            .map(|instance| instance.memory.data_size(&instance.store))
+            .sum()
+    }
+
     /// Given a function signature that inlines struct types, find the function name.
     ///
     /// # Example
@@ -185,9 +350,35 @@ impl Runtime {
     }
 
     /// Call a function.
-    pub fn call(&self, name: &str, input: &RecordBatch) -> Result<RecordBatch> {
+    pub fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        Ok(self.call_with_token(name, input, None)?)
+    }
+
+    /// Call a function, interrupting it once the token is cancelled.
+    ///
+    /// Returns [`Error::Cancelled`] if the call is cancelled.
+    pub fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> arrow_udf_core::Result<RecordBatch> {
+        token.check()?;
+        match self.call_with_token(name, input, Some(token)) {
+            Err(_) if token.is_cancelled() => Err(Error::Cancelled),
+            result => Ok(result?),
+        }
+    }
+
+    #[tracing::instrument(name = "call", level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
+    fn call_with_token(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: Option<&CancelToken>,
+    ) -> Result<RecordBatch> {
         if !self.functions.contains(name) {
-            bail!("function not found: {name}");
+            return Err(Error::FunctionNotFound(name.to_string()).into());
         }
 
         // get an instance from the pool, or create a new one if the pool is empty
@@ -197,8 +388,17 @@ impl Runtime {
             Instance::new(self)?
         };
 
+        // incrementing the epoch makes running instances check their tokens
+        let guard = token.map(|token| {
+            *instance.cancel.lock().unwrap() = Some(token.clone());
+            let engine = self.module.engine().clone();
+            token.on_cancel(move || engine.increment_epoch())
+        });
+
         // call the function
         let output = instance.call_scalar_function(name, input);
+        drop(guard);
+        instance.cancel.lock().unwrap().take();
 
         // put the instance back to the pool
         if output.is_ok() {
@@ -209,14 +409,16 @@ impl Runtime {
     }
 
     /// Call a table function.
+    #[tracing::instrument(level = "debug", skip_all, fields(function = name, rows = input.num_rows()))]
     pub fn call_table_function<'a>(
         &'a self,
         name: &'a str,
         input: &'a RecordBatch,
-    ) -> Result<impl Iterator<Item = Result<RecordBatch>> + 'a> {
+    ) -> arrow_udf_core::Result<impl Iterator<Item = arrow_udf_core::Result<RecordBatch>> + 'a>
+    {
         use genawaiter2::{sync::gen, yield_};
         if !self.functions.contains(name) {
-            bail!("function not found: {name}");
+            return Err(Error::FunctionNotFound(name.to_string()));
         }
 
         // get an instance from the pool, or create a new one if the pool is empty
@@ -231,12 +433,12 @@ impl Runtime {
             let iter = match instance.call_table_function(name, input) {
                 Ok(iter) => iter,
                 Err(e) => {
-                    yield_!(Err(e));
+                    yield_!(Err(e.into()));
                     return;
                 }
             };
             for output in iter {
-                yield_!(output);
+                yield_!(output.map_err(Into::into));
             }
             // put the instance back to the pool
             // FIXME: if the iterator is not consumed, the instance will be dropped
@@ -246,6 +448,59 @@ impl Runtime {
     }
 }
 
+impl arrow_udf_core::UdfRuntime for Runtime {
+    /// Functions are defined by the WASM binary, so adding a function is only supported
+    /// by JavaScript engines.
+    fn add_function(
+        &mut self,
+        name: &str,
+        return_field: Field,
+        mode: CallMode,
+        code: &str,
+    ) -> arrow_udf_core::Result<()> {
+        self.add_function(name, return_field, mode, code)
+    }
+
+    fn call(&self, name: &str, input: &RecordBatch) -> arrow_udf_core::Result<RecordBatch> {
+        self.call(name, input)
+    }
+
+    fn call_cancellable(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        token: &CancelToken,
+    ) -> arrow_udf_core::Result<RecordBatch> {
+        self.call_cancellable(name, input, token)
+    }
+
+    /// The output batch size is decided by the function itself, so `chunk_size` is ignored.
+    fn call_table_function<'a>(
+        &'a self,
+        name: &'a str,
+        input: &'a RecordBatch,
+        _chunk_size: usize,
+    ) -> arrow_udf_core::Result<arrow_udf_core::RecordBatchIter<'a>> {
+        Ok(Box::new(self.call_table_function(name, input)?))
+    }
+
+    fn list_functions(&self) -> Vec<String> {
+        self.functions.iter().cloned().collect()
+    }
+
+    fn memory_usage(&self) -> Option<usize> {
+        Some(self.memory_size())
+    }
+
+    /// Batches are passed to functions in the IPC format, so the data types are decided by the binary.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new()
+            .with_function_kinds([FunctionKind::Scalar, FunctionKind::Table])
+            .with_batch_modes([BatchMode::Cancellable])
+            .with_resource_limits([ResourceLimit::Memory, ResourceLimit::Instructions])
+    }
+}
+
 impl Instance {
     /// Create a new instance.
     fn new(rt: &Runtime) -> Result<Self> {
@@ -254,6 +509,40 @@ impl Instance {
         let mut linker = Linker::new(engine);
         wasi_common::sync::add_to_linker(&mut linker, |(wasi, _)| wasi)?;
 
+        // extern "C" fn(level: u32, ptr: *const u8, len: usize)
+        let log_sink = rt.log_sink.clone();
+        let function: Arc<Mutex<String>> = Default::default();
+        linker.func_wrap("arrowudf", "log", {
+            let log_sink = log_sink.clone();
+            let function = function.clone();
+            move |mut caller: Caller<'_, (WasiCtx, StoreLimits)>,
+                  level: u32,
+                  ptr: u32,
+                  len: u32|
+                  -> Result<()> {
+                let Some(sink) = log_sink.lock().unwrap().clone() else {
+                    return Ok(());
+                };
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(|export| export.into_memory())
+                    .context("no memory")?;
+                let message = memory
+                    .data(&caller)
+                    .get(ptr as usize..ptr as usize + len as usize)
+                    .context("log message out of bounds")?;
+                let level = match level {
+                    0 => LogLevel::Debug,
+                    1 => LogLevel::Info,
+                    2 => LogLevel::Warn,
+                    _ => LogLevel::Error,
+                };
+                let message = String::from_utf8_lossy(message);
+                sink.log(level, &function.lock().unwrap(), &message);
+                Ok(())
+            }
+        })?;
+
         // Create a WASI context and put it in a Store; all instances in the store
         // share this context. `WasiCtxBuilder` provides a number of ways to
         // configure what the target program will have access to.
@@ -273,10 +562,28 @@ impl Instance {
         };
         let mut store = Store::new(engine, (wasi, limits));
         store.limiter(|(_, limiter)| limiter);
+        if let Some(fuel) = rt.config.fuel {
+            store.set_fuel(fuel)?;
+        }
+        let cancel: Arc<Mutex<Option<CancelToken>>> = Default::default();
+        store.epoch_deadline_callback({
+            let cancel = cancel.clone();
+            move |_| {
+                let cancel = cancel.lock().unwrap();
+                if cancel.as_ref().is_some_and(|token| token.is_cancelled()) {
+                    return Err(Error::Cancelled.into());
+                }
+                Ok(UpdateDeadline::Continue(1))
+            }
+        });
+        store.set_epoch_deadline(1);
 
         let instance = linker.instantiate(&mut store, module)?;
         let mut functions = HashMap::new();
         for export in module.exports() {
+            if export.name().starts_with(JS_ENTRY_PREFIX) {
+                continue;
+            }
             let Some(encoded) = export.name().strip_prefix("arrowudf_") else {
                 continue;
             };
@@ -293,8 +600,16 @@ impl Instance {
         let memory = instance
             .get_memory(&mut store, "memory")
             .context("no memory")?;
+        let js = match rt.js_engine {
+            true => Some(JsEntries {
+                add_function: instance.get_typed_func(&mut store, "arrowudf_js_add_function")?,
+                call: instance.get_typed_func(&mut store, "arrowudf_js_call")?,
+                call_table: instance.get_typed_func(&mut store, "arrowudf_js_call_table")?,
+            }),
+            false => None,
+        };
 
-        Ok(Instance {
+        let mut instance = Instance {
             alloc,
             dealloc,
             record_batch_iterator_next,
@@ -302,9 +617,100 @@ impl Instance {
             memory,
             store,
             functions,
+            js,
             stdout,
             stderr,
-        })
+            log_sink,
+            function,
+            fuel: rt.config.fuel,
+            cancel,
+        };
+        for (name, definition) in &rt.js_functions {
+            instance
+                .add_js_function(name, definition)
+                .with_context(|| format!("failed to evaluate function {name}"))?;
+        }
+        Ok(instance)
+    }
+
+    /// Set the function being called, whose name is attached to the logs.
+    fn enter(&self, function: &str) {
+        let mut current = self.function.lock().unwrap();
+        current.clear();
+        current.push_str(function);
+    }
+
+    /// Reset the fuel before a call.
+    fn refuel(&mut self) -> Result<()> {
+        if let Some(fuel) = self.fuel {
+            self.store.set_fuel(fuel)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluate the definition of a JavaScript function in the engine.
+    fn add_js_function(&mut self, name: &str, definition: &[u8]) -> Result<()> {
+        let func = self
+            .js
+            .as_ref()
+            .context("not a JavaScript engine")?
+            .add_function
+            .clone();
+        self.enter(name);
+        self.refuel()?;
+
+        let alloc_len = u32::try_from(definition.len() + 4 * 2).context("code too large")?;
+        let alloc_ptr = self.alloc.call(&mut self.store, (alloc_len, 4))?;
+        ensure!(alloc_ptr != 0, "failed to allocate for code");
+        let in_ptr = alloc_ptr + 4 * 2;
+        self.memory
+            .write(&mut self.store, in_ptr as usize, definition)?;
+
+        let result = func.call(
+            &mut self.store,
+            (in_ptr, definition.len() as u32, alloc_ptr),
+        );
+        let errno = self.append_stdio(result)?;
+
+        let out_ptr = self.read_u32(alloc_ptr)?;
+        let out_len = self.read_u32(alloc_ptr + 4)?;
+        let out_bytes = self
+            .memory
+            .data(&self.store)
+            .get(out_ptr as usize..(out_ptr + out_len) as usize)
+            .context("output slice out of bounds")?;
+        let result = match errno {
+            0 => Ok(()),
+            _ => Err(Error::execution(std::str::from_utf8(out_bytes)?).into()),
+        };
+
+        self.dealloc
+            .call(&mut self.store, (alloc_ptr, alloc_len, 4))?;
+        self.dealloc.call(&mut self.store, (out_ptr, out_len, 1))?;
+
+        result
+    }
+
+    /// Find the entry point of a function and tag the input with the function name if needed.
+    fn entry_point(
+        &self,
+        name: &str,
+        input: &RecordBatch,
+        table: bool,
+    ) -> Result<(UdfFunc, Vec<u8>)> {
+        if let Some(func) = self.functions.get(name) {
+            return Ok((func.clone(), encode_record_batch(input)?));
+        }
+        let js = self
+            .js
+            .as_ref()
+            .ok_or_else(|| Error::FunctionNotFound(name.to_string()))?;
+        let mut metadata = input.schema().metadata().clone();
+        metadata.insert(JS_FUNCTION_KEY.to_string(), name.to_string());
+        let schema = input.schema().as_ref().clone().with_metadata(metadata);
+        let input = input.clone().with_schema(Arc::new(schema))?;
+        let func = if table { &js.call_table } else { &js.call };
+        Ok((func.clone(), encode_record_batch(&input)?))
     }
 
     /// Call a scalar function.
@@ -315,14 +721,10 @@ impl Instance {
         // and 2 copies in output path:
         //      wasm record batch -> wasm memory -> host record batch
 
-        // get function
-        let func = self
-            .functions
-            .get(name)
-            .with_context(|| format!("function not found: {name}"))?;
-
-        // encode input batch
-        let input = encode_record_batch(input)?;
+        // get function and encode input batch
+        let (func, input) = self.entry_point(name, input, false)?;
+        self.enter(name);
+        self.refuel()?;
 
         // allocate memory for input buffer and output struct
         let alloc_len = u32::try_from(input.len() + 4 * 2).context("input too large")?;
@@ -350,7 +752,7 @@ impl Instance {
             .context("output slice out of bounds")?;
         let result = match errno {
             0 => Ok(decode_record_batch(out_bytes)?),
-            _ => Err(anyhow!("{}", std::str::from_utf8(out_bytes)?)),
+            _ => Err(Error::execution(std::str::from_utf8(out_bytes)?).into()),
         };
 
         // deallocate memory
@@ -373,14 +775,10 @@ impl Instance {
         // and 2 copies in output path:
         //      wasm record batch -> wasm memory -> host record batch
 
-        // get function
-        let func = self
-            .functions
-            .get(name)
-            .with_context(|| format!("function not found: {name}"))?;
-
-        // encode input batch
-        let input = encode_record_batch(input)?;
+        // get function and encode input batch
+        let (func, input) = self.entry_point(name, input, true)?;
+        self.enter(name);
+        self.refuel()?;
 
         // allocate memory for input buffer and output struct
         let alloc_len = u32::try_from(input.len() + 4 * 2).context("input too large")?;
@@ -410,7 +808,7 @@ impl Instance {
         let ptr = match errno {
             0 => out_ptr,
             _ => {
-                let err = anyhow!("{}", std::str::from_utf8(out_bytes)?);
+                let err = Error::execution(std::str::from_utf8(out_bytes)?).into();
                 // deallocate memory
                 self.dealloc
                     .call(&mut self.store, (alloc_ptr, alloc_len, 4))?;
@@ -499,17 +897,45 @@ impl Instance {
         ))
     }
 
-    /// Take stdout and stderr, append to the error context.
+    /// Take stdout and stderr, log their lines to the sink and append them to the error context.
     fn append_stdio<T>(&self, result: Result<T>) -> Result<T> {
         let stdout = self.stdout.take();
         let stderr = self.stderr.take();
+        if let Some(sink) = self.log_sink.lock().unwrap().clone() {
+            let function = self.function.lock().unwrap();
+            for (level, output) in [(LogLevel::Info, &stdout), (LogLevel::Warn, &stderr)] {
+                for line in String::from_utf8_lossy(output).lines() {
+                    sink.log(level, &function, line);
+                }
+            }
+        }
         match result {
             Ok(v) => Ok(v),
-            Err(e) => Err(e.context(format!(
-                "--- stdout\n{}\n--- stderr\n{}",
-                String::from_utf8_lossy(&stdout),
-                String::from_utf8_lossy(&stderr),
-            ))),
+            Err(e) => {
+                let stdio = format!(
+                    "--- stdout\n{}\n--- stderr\n{}",
+                    String::from_utf8_lossy(&stdout),
+                    String::from_utf8_lossy(&stderr),
+                );
+                // keep the structured error under the context of stdio
+                let e = match e.downcast_ref::<Trap>() {
+                    Some(Trap::OutOfFuel) => {
+                        Error::ResourceExceeded(Trap::OutOfFuel.to_string()).into()
+                    }
+                    // the panic message of the function is written to stderr
+                    Some(trap) => Error::Execution {
+                        row: None,
+                        message: match String::from_utf8_lossy(&stderr).trim_end() {
+                            "" => trap.to_string(),
+                            stderr => format!("{trap}\n{stderr}"),
+                        },
+                        traceback: Some(format!("{e}\n{stdio}")),
+                    }
+                    .into(),
+                    None => e,
+                };
+                Err(e.context(stdio))
+            }
         }
     }
 }
@@ -530,6 +956,7 @@ fn base64_decode(input: &str) -> Result<String> {
     String::from_utf8(bytes).context("invalid utf8")
 }
 
+#[tracing::instrument(level = "trace", skip_all, fields(rows = batch.num_rows()))]
 fn encode_record_batch(batch: &RecordBatch) -> Result<Vec<u8>> {
     let mut buf = vec![];
     let mut writer = arrow_ipc::writer::FileWriter::try_new(&mut buf, &batch.schema())?;
@@ -539,6 +966,7 @@ fn encode_record_batch(batch: &RecordBatch) -> Result<Vec<u8>> {
     Ok(buf)
 }
 
+#[tracing::instrument(level = "trace", skip_all, fields(bytes = bytes.len()))]
 fn decode_record_batch(bytes: &[u8]) -> Result<RecordBatch> {
     let mut reader = arrow_ipc::reader::FileReader::try_new(std::io::Cursor::new(bytes), None)?;
     let batch = reader.next().unwrap()?;
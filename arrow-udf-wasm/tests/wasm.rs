@@ -14,13 +14,15 @@
 
 use std::sync::Arc;
 
-use arrow_array::{Int32Array, RecordBatch, RecordBatchOptions, StringArray};
+use arrow_array::{BooleanArray, Int32Array, RecordBatch, RecordBatchOptions, StringArray};
 use arrow_cast::pretty::pretty_format_batches;
 use arrow_schema::{DataType, Field, Schema};
-use arrow_udf_wasm::Runtime;
+use arrow_udf_core::CancelToken;
+use arrow_udf_wasm::{CallMode, Config, Runtime, StrictArguments};
 use expect_test::{expect, Expect};
 
 const BINARY_PATH: &str = "../target/wasm32-wasip1/release/arrow_udf_example.wasm";
+const JS_ENGINE_PATH: &str = "../target/wasm32-wasip1/release/arrow_udf_wasm_js_fixture.wasm";
 
 static RUNTIME: once_cell::sync::Lazy<Runtime> = once_cell::sync::Lazy::new(|| {
     Runtime::new(&std::fs::read(BINARY_PATH).expect("failed to load wasm binary"))
@@ -113,6 +115,42 @@ fn test_division_by_zero() {
     );
 }
 
+#[test]
+fn test_call_selected() {
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![Some(15), Some(5), Some(4)])),
+            Arc::new(Int32Array::from(vec![25, 0, 2])),
+        ],
+    )
+    .unwrap();
+
+    // the division by zero is not evaluated
+    let selection = BooleanArray::from(vec![true, false, true]);
+    let output = arrow_udf_core::UdfRuntime::call_selected(
+        &*RUNTIME,
+        "div(int32,int32)->int32",
+        &input,
+        &selection,
+    )
+    .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+-------+
+        | div | error |
+        +-----+-------+
+        | 0   |       |
+        |     |       |
+        | 2   |       |
+        +-----+-------+"#]],
+    );
+}
+
 #[test]
 fn test_length() {
     let input = RecordBatch::try_new(
@@ -183,6 +221,444 @@ fn test_range() {
     );
 }
 
+#[test]
+fn test_fuel() {
+    let binary = std::fs::read(BINARY_PATH).expect("failed to load wasm binary");
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Int32, true),
+            Field::new("y", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![25])),
+            Arc::new(Int32Array::from(vec![15])),
+        ],
+    )
+    .unwrap();
+
+    let mut config = Config::default();
+    config.fuel = Some(100_000_000);
+    let runtime = Runtime::with_config(&binary, config).unwrap();
+    runtime.call("gcd(int32,int32)->int32", &input).unwrap();
+
+    let mut config = Config::default();
+    config.fuel = Some(1000);
+    let runtime = Runtime::with_config(&binary, config).unwrap();
+    let err = runtime.call("gcd(int32,int32)->int32", &input).unwrap_err();
+    assert!(format!("{err:?}").contains("all fuel consumed"), "{err:?}");
+
+    let err =
+        arrow_udf_core::UdfRuntime::call(&runtime, "gcd(int32,int32)->int32", &input).unwrap_err();
+    assert!(
+        matches!(err, arrow_udf_core::Error::ResourceExceeded(_)),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn test_cancel() {
+    let input = RecordBatch::try_new_with_options(
+        Arc::new(Schema::empty()),
+        vec![],
+        &RecordBatchOptions::default().with_row_count(Some(1)),
+    )
+    .unwrap();
+
+    let token = CancelToken::new();
+    let canceller = std::thread::spawn({
+        let token = token.clone();
+        move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            token.cancel();
+        }
+    });
+    let err = RUNTIME
+        .call_cancellable("spin()->null", &input, &token)
+        .unwrap_err();
+    canceller.join().unwrap();
+    assert_eq!(err.to_string(), "cancelled");
+
+    // instances in the pool keep running after the epoch is incremented
+    test_gcd();
+}
+
+#[test]
+fn test_call_broadcast() {
+    use arrow_udf_core::{Arguments, UdfRuntime};
+
+    // scalars are materialized by default
+    let args = Arguments::new(3)
+        .with_array(
+            Field::new("x", DataType::Int32, true),
+            Arc::new(Int32Array::from(vec![Some(10), None, Some(4)])),
+        )
+        .unwrap()
+        .with_scalar(
+            Field::new("y", DataType::Int32, true),
+            Arc::new(Int32Array::from(vec![6])),
+        )
+        .unwrap();
+    let output = (&*RUNTIME as &dyn UdfRuntime)
+        .call_broadcast("gcd(int32,int32)->int32", &args)
+        .unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 2   |
+        |     |
+        | 2   |
+        +-----+"#]],
+    );
+}
+
+#[test]
+fn test_udf_runtime_trait() {
+    let mut runtime: Box<dyn arrow_udf_core::UdfRuntime> = Box::new(
+        Runtime::new(&std::fs::read(BINARY_PATH).expect("failed to load wasm binary")).unwrap(),
+    );
+    assert!(runtime
+        .list_functions()
+        .contains(&"gcd(int32,int32)->int32".to_string()));
+    runtime
+        .add_function(
+            "neg",
+            Field::new("neg", DataType::Int32, true),
+            arrow_udf_core::CallMode::default(),
+            "",
+        )
+        .unwrap_err();
+    assert!(!RUNTIME.is_js_engine());
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Int32, true),
+            Field::new("y", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![Some(25), None])),
+            Arc::new(Int32Array::from(vec![Some(15), None])),
+        ],
+    )
+    .unwrap();
+
+    let output = runtime.call("gcd(int32,int32)->int32", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        |     |
+        +-----+"#]],
+    );
+    // the linear memory of the instance is reported after the call
+    assert!(runtime.memory_usage().unwrap() > 0);
+}
+
+fn js_engine(config: Config) -> Runtime {
+    let binary = std::fs::read(JS_ENGINE_PATH).expect("failed to load js engine");
+    let runtime = Runtime::with_config(&binary, config).unwrap();
+    assert!(runtime.is_js_engine());
+    runtime
+}
+
+#[test]
+fn test_js_engine_call() {
+    let mut runtime = js_engine(Config::default());
+    assert_eq!(runtime.functions().count(), 0);
+    runtime
+        .add_function(
+            "gcd",
+            Field::new("gcd", DataType::Int32, true),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function gcd(a, b) {
+                while (b != 0) {
+                    let t = b;
+                    b = a % b;
+                    a = t;
+                }
+                return a;
+            }
+            "#,
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "div",
+            Field::new("div", DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            r#"
+            export function div(a, b) {
+                if (a === null || b === null) {
+                    return -1;
+                }
+                if (b == 0) {
+                    throw new Error("division by zero");
+                }
+                return a / b;
+            }
+            "#,
+        )
+        .unwrap();
+    let mut functions = runtime.functions().collect::<Vec<_>>();
+    functions.sort();
+    assert_eq!(functions, ["div", "gcd"]);
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![Some(15), Some(5), None])),
+            Arc::new(Int32Array::from(vec![25, 5, 1])),
+        ],
+    )
+    .unwrap();
+    let gcd = runtime.call("gcd", &input).unwrap();
+    let div = runtime.call("div", &input).unwrap();
+    check(
+        &[gcd, div],
+        expect![[r#"
+        +-----+
+        | gcd |
+        +-----+
+        | 5   |
+        | 5   |
+        |     |
+        | 0   |
+        | 1   |
+        | -1  |
+        +-----+"#]],
+    );
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![1])),
+            Arc::new(Int32Array::from(vec![0])),
+        ],
+    )
+    .unwrap();
+    let err = runtime.call("div", &input).unwrap_err();
+    assert!(err.to_string().contains("Error: division by zero"), "{err}");
+
+    let err = runtime.call("nope", &input).unwrap_err();
+    assert_eq!(err.to_string(), "function not found: nope");
+}
+
+#[test]
+fn test_js_engine_invalid_code() {
+    let mut runtime = js_engine(Config::default());
+    let err = runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            "export function f( {",
+        )
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("SyntaxError"), "{err:#}");
+    assert_eq!(runtime.functions().count(), 0);
+
+    let err = runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Int32, true),
+            CallMode::ReturnNullOnNullArguments(StrictArguments::from_indices([0])),
+            "export function f(x) { return x; }",
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("strict arguments"), "{err}");
+
+    // a failed replacement keeps the old definition
+    runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            "export function f(x) { return x + 1; }",
+        )
+        .unwrap();
+    runtime
+        .add_function(
+            "f",
+            Field::new("f", DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            "export function f( {",
+        )
+        .unwrap_err();
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![1]))],
+    )
+    .unwrap();
+    let output = runtime.call("f", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +---+
+        | f |
+        +---+
+        | 2 |
+        +---+"#]],
+    );
+}
+
+#[test]
+fn test_js_engine_table_function() {
+    let mut runtime = js_engine(Config::default());
+    runtime
+        .add_function(
+            "series",
+            Field::new("series", DataType::Utf8, true),
+            CallMode::ReturnNullOnNullInput,
+            r#"
+            export function* series(n) {
+                for (let i = 0; i < n; i++) {
+                    yield "s" + i;
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("x", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![Some(1), None, Some(3)]))],
+    )
+    .unwrap();
+    let outputs = runtime
+        .call_table_function("series", &input)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    check(
+        &outputs,
+        expect![[r#"
+        +-----+--------+
+        | row | series |
+        +-----+--------+
+        | 0   | s0     |
+        | 2   | s0     |
+        | 2   | s1     |
+        | 2   | s2     |
+        +-----+--------+"#]],
+    );
+
+    // a table function is not a scalar function
+    let err = runtime.call("series", &input).unwrap_err();
+    assert!(err.to_string().contains("is a generator"), "{err}");
+}
+
+#[test]
+fn test_js_engine_fuel() {
+    let mut config = Config::default();
+    config.fuel = Some(10_000_000);
+    let mut runtime = js_engine(config);
+    runtime
+        .add_function(
+            "spin",
+            Field::new("spin", DataType::Int32, true),
+            CallMode::CalledOnNullInput,
+            r#"
+            export function spin(n) {
+                let i = 0;
+                while (n < 0 || i < n) {
+                    i += 1;
+                }
+                return i;
+            }
+            "#,
+        )
+        .unwrap();
+
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![10]))],
+    )
+    .unwrap();
+    let output = runtime.call("spin", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+
+        | spin |
+        +------+
+        | 10   |
+        +------+"#]],
+    );
+
+    // an endless loop is stopped once the fuel is consumed
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![-1]))],
+    )
+    .unwrap();
+    let err = arrow_udf_core::UdfRuntime::call(&runtime, "spin", &input).unwrap_err();
+    assert!(
+        matches!(err, arrow_udf_core::Error::ResourceExceeded(_)),
+        "{err:?}"
+    );
+
+    // the instance is dropped, and the function is evaluated again in a new one
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, true)])),
+        vec![Arc::new(Int32Array::from(vec![3]))],
+    )
+    .unwrap();
+    let output = runtime.call("spin", &input).unwrap();
+    check(
+        &[output],
+        expect![[r#"
+        +------+
+        | spin |
+        +------+
+        | 3    |
+        +------+"#]],
+    );
+}
+
+#[test]
+fn test_log_sink() {
+    use arrow_udf_core::LogLevel;
+    use std::sync::Mutex;
+
+    let mut runtime = Runtime::new(&std::fs::read(BINARY_PATH).unwrap()).unwrap();
+    let logs = Arc::new(Mutex::new(vec![]));
+    runtime.set_log_sink(Some(Arc::new({
+        let logs = logs.clone();
+        move |level: LogLevel, function: &str, message: &str| {
+            logs.lock()
+                .unwrap()
+                .push(format!("[{level}] {function}: {message}"));
+        }
+    })));
+    let input = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)])),
+        vec![Arc::new(StringArray::from(vec!["a", "bc"]))],
+    )
+    .unwrap();
+    runtime.call("log(string)->int32", &input).unwrap();
+
+    expect![[r#"
+        [warn] log(string)->int32: a
+        [warn] log(string)->int32: bc
+        [info] log(string)->int32: printed a
+        [info] log(string)->int32: printed bc
+        [warn] log(string)->int32: error a
+        [warn] log(string)->int32: error bc"#]]
+    .assert_eq(&logs.lock().unwrap().join("\n"));
+}
+
 /// Compare the actual output with the expected output.
 #[track_caller]
 fn check(actual: &[RecordBatch], expect: Expect) {
@@ -56,6 +56,38 @@ pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize, align: usize) {
     );
 }
 
+/// Log a message through the `arrowudf.log` import of the host.
+///
+/// The level is one of [`LOG_DEBUG`], [`LOG_INFO`], [`LOG_WARN`] and [`LOG_ERROR`].
+/// The host delivers the message to its log sink with the name of the function being called.
+/// Outside WebAssembly, the message is written to the standard error.
+pub fn log(level: u32, message: &str) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        #[link(wasm_import_module = "arrowudf")]
+        extern "C" {
+            #[link_name = "log"]
+            fn host_log(level: u32, ptr: *const u8, len: usize);
+        }
+        // SAFETY: the host only reads the message during the call
+        unsafe { host_log(level, message.as_ptr(), message.len()) };
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        _ = level;
+        eprintln!("{message}");
+    }
+}
+
+/// The level of debug messages passed to [`log`].
+pub const LOG_DEBUG: u32 = 0;
+/// The level of informational messages passed to [`log`].
+pub const LOG_INFO: u32 = 1;
+/// The level of warnings passed to [`log`].
+pub const LOG_WARN: u32 = 2;
+/// The level of errors passed to [`log`].
+pub const LOG_ERROR: u32 = 3;
+
 /// A FFI-safe slice.
 #[repr(C)]
 #[derive(Debug)]